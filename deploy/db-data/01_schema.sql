@@ -34,9 +34,20 @@ CREATE TABLE "systems"
     "cpu_count"    integer,
     "cpu_usage"    double precision,
     "uptime"       integer,
-    "memory_used"  bigint,
-    "memory_total" bigint,
+    "memory_used_bytes"  bigint,
+    "memory_total_bytes" bigint,
     "admin"        integer,
+    "cert_fingerprint" text,
+    "agent_version" text,
+    "arch"         text,
+    "virtualization" text,
+    "tags"         jsonb              NOT NULL DEFAULT '{}'::jsonb, -- [tags] from the agent's config
+    "site"         text, -- site, datacenter and rack from [tags] or the hub's location API
+    "datacenter"   text,
+    "rack"         text,
+    "clock_skew_ms" bigint, -- of the latest report that measured it
+    "decommissioned" timestamp with time zone, -- set through the hub's decommission API
+    "decommission_archive" text, -- archive of the samples once the key was revoked
     CONSTRAINT "systems_hostname_key" UNIQUE ("hostname")
 );
 
@@ -61,7 +72,10 @@ CREATE TABLE "services"
     "state"       text,
     "pid"         integer,
     "cpu"         text,
-    "memory"      text
+    "memory"      text,
+    "requires"    text[]  NOT NULL DEFAULT '{}', -- systemd Requires=, Wants= and After=
+    "wants"       text[]  NOT NULL DEFAULT '{}',
+    "after"       text[]  NOT NULL DEFAULT '{}'
 );
 
 CREATE TABLE "sessions"
@@ -76,18 +90,21 @@ CREATE TABLE "disks"
 (
     "system"      integer       NOT NULL,
     "name"        text          NOT NULL,
-    "space"       integer,
-    "used"        integer,
-    "read"        double precision,
+    "space_bytes" bigint,
+    "used_bytes"  bigint,
+    "read"        double precision, -- bytes/sec
     "write"       double precision,
-    "unit"        text,
+    "read_iops"   double precision,
+    "write_iops"  double precision,
+    "inodes_total" bigint,
+    "inodes_used" bigint,
     "time"        timestamp
                       with
                       time zone NOT NULL,
     "mount_point" text
 );
 
-SELECT create_hypertable('disks', 'time', if_not_exists => true);
+SELECT create_hypertable('disks', 'time', chunk_time_interval => INTERVAL '7 days', if_not_exists => true);
 
 CREATE TABLE "metrics"
 (
@@ -96,18 +113,88 @@ CREATE TABLE "metrics"
                                     time zone NOT NULL,
     "system_id"                 integer       NOT NULL,
     "cpu_usage"                 double precision,
-    "memory_used_kb"            bigint,
-    "memory_total_kb"           bigint,
+    "cpu_user"                  double precision,
+    "cpu_system"                double precision,
+    "cpu_iowait"                double precision,
+    "cpu_irq"                   double precision,
+    "cpu_steal"                 double precision,
+    "memory_used_bytes"         bigint,
+    "memory_total_bytes"        bigint,
+    "memory_available_bytes"    bigint,
+    "swap_used_bytes"           bigint,
     "docker_containers_running" integer,
     "components"                text,
+    "processes"                 integer,
+    "threads"                   integer,
+    "procs_running"             integer,
+    "procs_zombie"              integer,
+    "procs_blocked"             integer,
+    "ctxt_per_sec"              double precision,
+    "intr_per_sec"              double precision,
+    "entropy_avail"             integer,
     "uptime"                    integer,
-    "net_in"                    integer,
-    "net_out"                   integer,
+    "net_in_bytes_per_sec"      double precision,
+    "net_out_bytes_per_sec"     double precision,
     "load_one"                  double precision,
     "load_five"                 double precision,
-    "load_fifteen"              double precision
+    "load_fifteen"              double precision,
+    "clock_skew_ms"             bigint -- agent clock minus hub clock on arrival, positive when ahead
 );
-SELECT create_hypertable('metrics', 'time', if_not_exists => true);
+SELECT create_hypertable('metrics', 'time', chunk_time_interval => INTERVAL '7 days', if_not_exists => true);
+
+-- Hourly averages of pruned metrics and disks rows, written with RETENTION_ROLLUP_DAYS
+CREATE TABLE "metrics_hourly"
+(
+    "time"                timestamp with time zone NOT NULL,
+    "system_id"           integer                  NOT NULL,
+    "samples"             integer                  NOT NULL,
+    "cpu_usage"           double precision,
+    "cpu_usage_max"       double precision,
+    "cpu_user"            double precision,
+    "cpu_system"          double precision,
+    "cpu_iowait"          double precision,
+    "cpu_irq"             double precision,
+    "cpu_steal"           double precision,
+    "memory_used_bytes"   double precision,
+    "memory_total_bytes"  bigint,
+    "memory_available_bytes" double precision,
+    "swap_used_bytes"     double precision,
+    "processes"           double precision,
+    "threads"             double precision,
+    "procs_running"       double precision,
+    "procs_zombie"        double precision,
+    "procs_blocked"       double precision,
+    "ctxt_per_sec"        double precision,
+    "intr_per_sec"        double precision,
+    "net_in_bytes_per_sec" double precision,
+    "net_out_bytes_per_sec" double precision,
+    "load_one"            double precision,
+    "load_five"           double precision,
+    "load_fifteen"        double precision,
+    CONSTRAINT metrics_hourly_system_time_key UNIQUE ("system_id", "time"),
+    CONSTRAINT metrics_hourly_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+SELECT create_hypertable('metrics_hourly', 'time', chunk_time_interval => INTERVAL '30 days', if_not_exists => true);
+
+CREATE TABLE "disks_hourly"
+(
+    "time"         timestamp with time zone NOT NULL,
+    "system"       integer                  NOT NULL,
+    "name"         text                     NOT NULL,
+    "mount_point"  text,
+    "samples"      integer                  NOT NULL,
+    "space_bytes"  double precision,
+    "used_bytes"   double precision,
+    "read"         double precision,
+    "write"        double precision,
+    "read_iops"    double precision,
+    "write_iops"   double precision,
+    "inodes_total" bigint,
+    "inodes_used"  double precision,
+    CONSTRAINT disks_hourly_system_name_time_key UNIQUE ("system", "name", "time"),
+    CONSTRAINT disks_hourly_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+SELECT create_hypertable('disks_hourly', 'time', chunk_time_interval => INTERVAL '30 days', if_not_exists => true);
 
 CREATE TABLE "gpus"
 (
@@ -136,6 +223,23 @@ CREATE TABLE "gpu_metrics"
 
 SELECT create_hypertable('gpu_metrics', 'time', if_not_exists => true);
 
+-- Hourly averages of pruned gpu_metrics rows, written with RETENTION_ROLLUP_DAYS
+CREATE TABLE "gpu_metrics_hourly"
+(
+    "time"            timestamp        NOT NULL,
+    "gpu_id"          integer          NOT NULL,
+    "samples"         integer          NOT NULL,
+    "utilization"     double precision,
+    "utilization_max" double precision,
+    "memory_used_mb"  double precision,
+    "temperature"     double precision,
+    "temperature_max" double precision,
+    "power"           double precision,
+    CONSTRAINT gpu_metrics_hourly_gpu_time_key UNIQUE ("gpu_id", "time"),
+    CONSTRAINT gpu_metrics_hourly_gpu_fk FOREIGN KEY ("gpu_id") REFERENCES "public"."gpus" ("id") ON DELETE CASCADE
+);
+SELECT create_hypertable('gpu_metrics_hourly', 'time', chunk_time_interval => INTERVAL '30 days', if_not_exists => true);
+
 CREATE TABLE "containers"
 (
     "id"         integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
@@ -159,6 +263,184 @@ CREATE TABLE "container_metrics"
 
 SELECT create_hypertable('container_metrics', 'time', if_not_exists => true);
 
+-- Ad-hoc metrics posted by scripts and third-party tools through the hub's HTTP API
+CREATE TABLE "custom_metrics"
+(
+    "time"      timestamp with time zone NOT NULL,
+    "system_id" integer                  NOT NULL,
+    "name"      text                     NOT NULL,
+    "value"     double precision         NOT NULL,
+    "labels"    jsonb                    NOT NULL DEFAULT '{}'::jsonb,
+    CONSTRAINT custom_metrics_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+SELECT create_hypertable('custom_metrics', 'time', if_not_exists => true);
+
+-- TCP probes run by agents against the targets pushed in agent_config
+CREATE TABLE "probe_results"
+(
+    "time"       timestamp with time zone NOT NULL,
+    "system_id"  integer                  NOT NULL,
+    "name"       text                     NOT NULL,
+    "address"    text                     NOT NULL,
+    "up"         boolean                  NOT NULL,
+    "latency_ms" double precision,
+    CONSTRAINT probe_results_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+SELECT create_hypertable('probe_results', 'time', if_not_exists => true);
+
+-- The busiest processes of every metrics report, by CPU and by memory, for post-incident lookups
+CREATE TABLE "process_samples"
+(
+    "time"      timestamp with time zone NOT NULL,
+    "system_id" integer                  NOT NULL,
+    "pid"       integer                  NOT NULL,
+    "name"      text                     NOT NULL,
+    "cpu_usage" real                     NOT NULL,
+    "memory_kb" bigint                   NOT NULL,
+    CONSTRAINT process_samples_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+SELECT create_hypertable('process_samples', 'time', chunk_time_interval => INTERVAL '1 day', if_not_exists => true);
+
+-- Hourly SMART readings per drive, the `smart` rule component compares them over days
+CREATE TABLE "smart_attributes"
+(
+    "time"                  timestamp with time zone NOT NULL,
+    "system_id"             integer                  NOT NULL,
+    "device"                text                     NOT NULL,
+    "model"                 text                     NOT NULL,
+    "serial"                text                     NOT NULL,
+    "passed"                boolean                  NOT NULL,
+    "reallocated_sectors"   bigint,
+    "pending_sectors"       bigint,
+    "uncorrectable_sectors" bigint,
+    "media_errors"          bigint,
+    "percentage_used"       integer,
+    "temperature"           double precision,
+    "power_on_hours"        bigint,
+    CONSTRAINT smart_attributes_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+SELECT create_hypertable('smart_attributes', 'time', chunk_time_interval => INTERVAL '30 days', if_not_exists => true);
+
+-- Collector intervals, probe targets and feature toggles pushed to agents over WatchConfig.
+-- The row without a system_id applies to every agent, system rows override it key by key.
+CREATE TABLE "agent_config"
+(
+    "id"        serial PRIMARY KEY       NOT NULL,
+    "system_id" integer,
+    "config"    jsonb                    NOT NULL DEFAULT '{}'::jsonb,
+    "updated"   timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT agent_config_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS "agent_config_system_idx" ON "agent_config" (COALESCE("system_id", 0));
+
+-- Hub feature flags, on for the canary systems and a stable rollout_percent of the others.
+-- Resolved per system into the features of its agent config, agent_config rows override them.
+CREATE TABLE "feature_flags"
+(
+    "name"            text PRIMARY KEY         NOT NULL,
+    "rollout_percent" smallint                 NOT NULL DEFAULT 0 CHECK ("rollout_percent" BETWEEN 0 AND 100),
+    "canary_systems"  integer[]                NOT NULL DEFAULT '{}',
+    "updated"         timestamp with time zone NOT NULL DEFAULT now()
+);
+
+-- Failed agent authentications, rate limit hits and lockouts recorded by the hub
+CREATE TABLE "auth_events"
+(
+    "id"        integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "time"      timestamp with time zone NOT NULL DEFAULT now(),
+    "event"     text                     NOT NULL, -- invalid_key, cert_rejected, cert_mismatch, rate_limited, locked_out
+    "source_ip" text,
+    "key_id"    text                     NOT NULL, -- truncated sha256 of the presented key, never the key itself
+    "system"    integer,
+    CONSTRAINT auth_events_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE SET NULL
+);
+
+-- Agent reports the hub refused because they failed validation
+CREATE TABLE "rejected_reports"
+(
+    "id"     integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "time"   timestamp with time zone NOT NULL DEFAULT now(),
+    "system" integer                  NOT NULL,
+    "report" text                     NOT NULL, -- metrics, system_info, gpus, gpu_metrics, services, containers, container_metrics, health, agent_event, command_audit
+    "field"  text                     NOT NULL,
+    "reason" text                     NOT NULL,
+    CONSTRAINT rejected_reports_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+-- Collector panics and hangs reported by the agents' supervisors
+CREATE TABLE "agent_health_events"
+(
+    "id"        integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "time"      timestamp with time zone NOT NULL DEFAULT now(),
+    "system"    integer                  NOT NULL,
+    "collector" text                     NOT NULL,
+    "kind"      text                     NOT NULL, -- panic, timeout
+    "detail"    text                     NOT NULL,
+    "restarts"  integer                  NOT NULL,
+    CONSTRAINT agent_health_events_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+-- Agent panics, kept on the host and reported when the agent starts again
+CREATE TABLE "agent_events"
+(
+    "id"        integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "time"      timestamp with time zone NOT NULL,
+    "received"  timestamp with time zone NOT NULL DEFAULT now(),
+    "system"    integer                  NOT NULL,
+    "kind"      text                     NOT NULL, -- panic
+    "version"   text                     NOT NULL,
+    "thread"    text                     NOT NULL,
+    "location"  text                     NOT NULL,
+    "message"   text                     NOT NULL,
+    "backtrace" text                     NOT NULL,
+    CONSTRAINT agent_events_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+CREATE TABLE "snmp_devices"
+(
+    "id"            integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "system_id"     integer NOT NULL,
+    "host"          text    NOT NULL,
+    "port"          integer NOT NULL DEFAULT 161,
+    "community"     text    NOT NULL DEFAULT 'public', -- may be a secret reference
+    "interval_secs" integer NOT NULL DEFAULT 60,
+    "oids"          jsonb   NOT NULL DEFAULT '{}'::jsonb, -- metric name -> OID
+    "active"        boolean NOT NULL DEFAULT true,
+    CONSTRAINT snmp_devices_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+CREATE TABLE "prometheus_targets"
+(
+    "id"            integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "system_id"     integer NOT NULL,
+    "url"           text    NOT NULL, -- e.g. http://host:9100/metrics
+    "bearer_token"  text,             -- may be a secret reference
+    "interval_secs" integer NOT NULL DEFAULT 30,
+    "active"        boolean NOT NULL DEFAULT true,
+    CONSTRAINT prometheus_targets_system_fk FOREIGN KEY ("system_id") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+-- Agent builds offered to agents polling their update channel, the newest published row wins
+CREATE TABLE "agent_releases"
+(
+    "id"        integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "channel"   text                     NOT NULL, -- stable or beta
+    "version"   text                     NOT NULL,
+    "os"        text                     NOT NULL DEFAULT 'linux', -- linux or windows
+    "arch"      text                     NOT NULL DEFAULT 'x86_64', -- Rust target arch, e.g. aarch64
+    "url"       text                     NOT NULL,
+    "sha256"    text                     NOT NULL, -- lowercase hex of the binary at url
+    "published" timestamp with time zone NOT NULL DEFAULT now(),
+    "manifest"  text, -- JSON signed with the release key when published, see sign-release.sh
+    "signature" text, -- base64 Ed25519 signature over manifest
+    CONSTRAINT agent_releases_channel_version_os_arch_key UNIQUE ("channel", "version", "os", "arch")
+);
+
 CREATE TABLE "alert_rules"
 (
     "id"          integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY (
@@ -171,6 +453,9 @@ CREATE TABLE "alert_rules"
     "user_id"     integer NOT NULL,
     "expression"  text    NOT NULL,
     "severity"    text    NOT NULL,
+    "target"      text, -- tag selector such as role=db,dc=eu-1 or * for all, matched systems need no alert_systems row
+    "for_secs"    integer NOT NULL DEFAULT 0, -- the conditions have to hold this long before the rule fires
+    "annotations" jsonb   NOT NULL DEFAULT '{}'::jsonb, -- runbook_url, owner, ... sent with every notification
     "active"      boolean   DEFAULT false,
     "created"     timestamp DEFAULT now(),
     "updated"     timestamp DEFAULT now()
@@ -196,12 +481,64 @@ CREATE TABLE "alert_history"
             1 CACHE 1
         ),
     "system" integer       NOT NULL,
-    "alert"  integer       NOT NULL,
+    "alert"  integer,                -- alert_rules.id, NULL for built-in rules
+    "rule"   text,                   -- name of the built-in rule
     "date"   timestamp
                  with
                  time zone NOT NULL
 );
 
+-- Hub-wide maintenance, notifications are paused while "until" is in the future
+-- One row per scope, '' for hub-wide maintenance or a location group such as 'site=eu-1'
+CREATE TABLE "hub_maintenance"
+(
+    "scope"   text PRIMARY KEY         NOT NULL DEFAULT '',
+    "started" timestamp with time zone NOT NULL,
+    "until"   timestamp with time zone NOT NULL,
+    "reason"  text                     NOT NULL DEFAULT ''
+);
+
+-- Attempts to hand a triggered rule to a notifier, alert is NULL for built-in rules
+CREATE TABLE "notification_deliveries"
+(
+    "id"       integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "time"     timestamp with time zone NOT NULL DEFAULT now(),
+    "alert"    integer,                           -- alert_history.id
+    "system"   integer                  NOT NULL,
+    "rule"     text                     NOT NULL,
+    "notifier" integer,
+    "channel"  text                     NOT NULL, -- discord, email, webhook or unknown
+    "success"  boolean                  NOT NULL,
+    "error"    text,
+    CONSTRAINT notification_deliveries_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE CASCADE,
+    CONSTRAINT notification_deliveries_notifier_fk FOREIGN KEY ("notifier") REFERENCES "public"."notifiers" ("id") ON DELETE SET NULL
+);
+
+-- Actions taken on hosts over the agent websocket, reported by the agents once they ended
+CREATE TABLE "command_audit"
+(
+    "id"        integer PRIMARY KEY GENERATED ALWAYS AS IDENTITY,
+    "time"      timestamp with time zone NOT NULL,
+    "received"  timestamp with time zone NOT NULL DEFAULT now(),
+    "system"    integer                  NOT NULL,
+    "actor"     text                     NOT NULL, -- websocket peer, hub:<session> for relayed sessions
+    "action"    text                     NOT NULL, -- execute, stop, update, delete, upload, tunnel, grant_tunnel, authorize, apply_updates, start_service, stop_service, restart_service
+    "target"    text                     NOT NULL,
+    "args"      text[]                   NOT NULL DEFAULT '{}',
+    "exit_code" integer,
+    "error"     text,
+    CONSTRAINT command_audit_system_fk FOREIGN KEY ("system") REFERENCES "public"."systems" ("id") ON DELETE CASCADE
+);
+
+-- What a command read and wrote, for the command_audit entries agents sent a transcript with
+CREATE TABLE "command_transcripts"
+(
+    "audit_id"  integer PRIMARY KEY,
+    "lines"     jsonb   NOT NULL, -- [{"time", "stream", "text"}], time null for input without timestamps
+    "truncated" boolean NOT NULL DEFAULT false,
+    CONSTRAINT command_transcripts_audit_fk FOREIGN KEY ("audit_id") REFERENCES "public"."command_audit" ("id") ON DELETE CASCADE
+);
+
 ALTER TABLE "alert_history"
     ADD CONSTRAINT "alert_history_alert_rules_id_fk" FOREIGN KEY ("alert") REFERENCES "public"."alert_rules" ("id") ON DELETE no action ON UPDATE no action;
 
@@ -242,6 +579,7 @@ ALTER TABLE "systems"
     ADD CONSTRAINT "systems_users_id_fk" FOREIGN KEY ("admin") REFERENCES "public"."users" ("id") ON DELETE cascade ON UPDATE cascade;
 
 CREATE INDEX "alert_systems_system_id_index" ON "alert_systems" USING btree ("system_id" int4_ops);
+CREATE INDEX IF NOT EXISTS "systems_tags_idx" ON "systems" USING gin ("tags");
 
 
 CREATE INDEX IF NOT EXISTS "gpus_system_id_idx" ON "gpus" ("system_id");
@@ -254,8 +592,25 @@ CREATE INDEX IF NOT EXISTS "container_metrics_time_gpu_idx" ON "container_metric
 ALTER TABLE "container_metrics"
     ADD CONSTRAINT container_metrics_gpu_id_fk FOREIGN KEY ("container_id") REFERENCES "public"."containers" ("id") ON DELETE CASCADE;
 
+CREATE INDEX IF NOT EXISTS "auth_events_time_idx" ON "auth_events" USING btree ("time");
+CREATE INDEX IF NOT EXISTS "rejected_reports_system_time_idx" ON "rejected_reports" USING btree ("system", "time");
+CREATE INDEX IF NOT EXISTS "agent_health_events_system_time_idx" ON "agent_health_events" USING btree ("system", "time");
+CREATE INDEX IF NOT EXISTS "agent_events_system_time_idx" ON "agent_events" USING btree ("system", "time");
+CREATE INDEX IF NOT EXISTS "process_samples_system_time_idx" ON "process_samples" USING btree ("system_id", "time" DESC);
+CREATE INDEX IF NOT EXISTS "smart_attributes_system_time_idx" ON "smart_attributes" USING btree ("system_id", "time");
+CREATE INDEX IF NOT EXISTS "notification_deliveries_system_time_idx" ON "notification_deliveries" USING btree ("system", "time");
+CREATE INDEX IF NOT EXISTS "command_audit_system_time_idx" ON "command_audit" USING btree ("system", "time");
+CREATE INDEX IF NOT EXISTS "notification_deliveries_alert_idx" ON "notification_deliveries" USING btree ("alert");
+
+CREATE INDEX IF NOT EXISTS "custom_metrics_system_name_time_idx"
+    ON "custom_metrics" USING btree ("system_id", "name", "time" DESC);
+
 CREATE INDEX IF NOT EXISTS "metrics_time_idx"
     ON "metrics" USING btree ("time" timestamptz_ops);
+CREATE INDEX IF NOT EXISTS "metrics_system_time_idx"
+    ON "metrics" USING btree ("system_id", "time" DESC);
+CREATE INDEX IF NOT EXISTS "disks_system_time_idx"
+    ON "disks" USING btree ("system", "time" DESC);
 
 
 ALTER TABLE gpus
@@ -264,6 +619,8 @@ ALTER TABLE containers
     ADD CONSTRAINT containers_system_docker_key UNIQUE (system_id, docker_id);
 ALTER TABLE disks
     ADD CONSTRAINT disks_system_name_time_key UNIQUE (system, name, time);
+ALTER TABLE services
+    ADD CONSTRAINT services_system_name_key UNIQUE (system, name);
 
 CREATE FUNCTION public.update_latest_cpu_usage() RETURNS trigger
     LANGUAGE plpgsql
@@ -313,8 +670,8 @@ AS
 $$
 BEGIN
     UPDATE systems
-    SET memory_used  = NEW.memory_used_kb,
-        memory_total = NEW.memory_total_kb
+    SET memory_used_bytes  = NEW.memory_used_bytes,
+        memory_total_bytes = NEW.memory_total_bytes
     WHERE id = NEW.system_id;
     RETURN NEW;
 END;
@@ -322,6 +679,20 @@ $$;
 ALTER FUNCTION public.update_memory() OWNER TO postgres;
 
 
+CREATE FUNCTION public.update_clock_skew() RETURNS trigger
+    LANGUAGE plpgsql
+AS
+$$
+BEGIN
+    UPDATE systems
+    SET clock_skew_ms = NEW.clock_skew_ms
+    WHERE id = NEW.system_id;
+    RETURN NEW;
+END;
+$$;
+ALTER FUNCTION public.update_clock_skew() OWNER TO postgres;
+
+
 CREATE TRIGGER "metrics_cpu_usage_trigger"
     AFTER INSERT
     ON "metrics"
@@ -347,5 +718,54 @@ CREATE TRIGGER "metrics_memory_trigger"
     AFTER INSERT
     ON "metrics"
     FOR EACH ROW
-    WHEN ((new.memory_used_kb IS NOT NULL) AND (new.memory_total_kb IS NOT NULL))
+    WHEN ((new.memory_used_bytes IS NOT NULL) AND (new.memory_total_bytes IS NOT NULL))
 EXECUTE FUNCTION public.update_memory();
+
+CREATE TRIGGER "metrics_clock_skew_trigger"
+    AFTER INSERT
+    ON "metrics"
+    FOR EACH ROW
+    WHEN ((new.clock_skew_ms IS NOT NULL))
+EXECUTE FUNCTION public.update_clock_skew();
+
+-- Hubs cache alert rules per system and drop them on this notification
+CREATE FUNCTION public.notify_rules_changed() RETURNS trigger
+    LANGUAGE plpgsql
+AS
+$$
+BEGIN
+    PERFORM pg_notify('lynx_rules', TG_TABLE_NAME);
+    RETURN NULL;
+END;
+$$;
+ALTER FUNCTION public.notify_rules_changed() OWNER TO postgres;
+
+CREATE TRIGGER "alert_rules_changed_trigger"
+    AFTER INSERT OR UPDATE OR DELETE
+    ON "alert_rules"
+    FOR EACH STATEMENT
+EXECUTE FUNCTION public.notify_rules_changed();
+
+CREATE TRIGGER "alert_systems_changed_trigger"
+    AFTER INSERT OR UPDATE OR DELETE
+    ON "alert_systems"
+    FOR EACH STATEMENT
+EXECUTE FUNCTION public.notify_rules_changed();
+
+CREATE TRIGGER "alert_notifiers_changed_trigger"
+    AFTER INSERT OR UPDATE OR DELETE
+    ON "alert_notifiers"
+    FOR EACH STATEMENT
+EXECUTE FUNCTION public.notify_rules_changed();
+
+CREATE TRIGGER "notifiers_changed_trigger"
+    AFTER INSERT OR UPDATE OR DELETE
+    ON "notifiers"
+    FOR EACH STATEMENT
+EXECUTE FUNCTION public.notify_rules_changed();
+
+CREATE TRIGGER "systems_tags_changed_trigger"
+    AFTER UPDATE OF "tags", "admin"
+    ON "systems"
+    FOR EACH STATEMENT
+EXECUTE FUNCTION public.notify_rules_changed();
@@ -4,7 +4,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .out_dir("src/proto")
         .protoc_arg("-I=../lynx-proto")
         .compile_protos(
-            &["monitor.proto"],
+            &[
+                "metrics.proto",
+                "inventory.proto",
+                "control.proto",
+                "monitor.proto",
+            ],
             &["."],
         )?;
     Ok(())
@@ -0,0 +1,42 @@
+use std::time::Duration;
+use tonic::Status;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+
+/// Sends the agent key with every request, the hub looks the system up by it.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    pub agent_key: String,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
+        request.metadata_mut().insert(
+            "x-agent-key",
+            MetadataValue::try_from(&self.agent_key).unwrap(),
+        );
+        Ok(request)
+    }
+}
+
+/*
+ * endpoint
+ * The hub endpoint with the keepalives the hub expects from agents, over mTLS with the given
+ * config and in plaintext without one. The client certificate has to be signed by the hub's CA.
+ */
+pub fn endpoint(
+    server_url: &str,
+    tls: Option<ClientTlsConfig>,
+) -> Result<Endpoint, tonic::transport::Error> {
+    let mut endpoint = Endpoint::from_shared(server_url.to_string())?;
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls)?;
+    }
+    Ok(endpoint
+        .tcp_keepalive(Some(Duration::from_secs(30)))
+        .http2_keep_alive_interval(Duration::from_secs(15))
+        .keep_alive_timeout(Duration::from_secs(5))
+        .keep_alive_while_idle(true)
+        .connect_timeout(Duration::from_secs(10)))
+}
@@ -0,0 +1,233 @@
+use crate::health::{self, HealthSender};
+use crate::observer::{NoObserver, Observer};
+use crate::proto::monitor::{
+    ContainerMetricsRequest, ContainerRequest, GpuMetricsRequest, GpuRequest, MetricsRequest,
+    SystemInfoRequest, SystemctlRequest,
+};
+use crate::remote_config::{self, ConfigReceiver};
+use async_trait::async_trait;
+use log::{error, info, warn};
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, timeout};
+
+/*
+ * Collectors
+ * A collector gathers one kind of report every `interval` seconds and sends it as a
+ * CollectorRequest, the agent hands those to HubConnection::report. The CollectorManager runs
+ * every registered collector on its own task, so an agent is its collectors plus the loop
+ * draining their channel.
+ */
+
+#[derive(Debug, thiserror::Error)]
+pub enum CollectorError {
+    #[error("Failed to collect metrics: {0}")]
+    MetricsCollectionError(String),
+    #[error("Failed to collect system info: {0}")]
+    SystemInfoCollectionError(String),
+    #[error("Failed to collect systemctl info: {0}")]
+    SystemctlCollectionError(String),
+
+    #[error("Channel send error: {0}")]
+    Channel(#[from] tokio::sync::mpsc::error::TrySendError<CollectorRequest>),
+}
+/// The two large reports are boxed, so the other kinds don't move their size around.
+#[derive(Debug)]
+pub enum CollectorRequest {
+    Metrics(Box<MetricsRequest>),
+    SystemInfo(Box<SystemInfoRequest>),
+    Systemctl(SystemctlRequest),
+    GpuInfo(GpuRequest),
+    GpuMetrics(GpuMetricsRequest),
+    ContainerInfo(ContainerRequest),
+    ContainerMetrics(ContainerMetricsRequest),
+}
+
+/// Runs taking longer than this are abandoned and reported, see `Collector::timeout`.
+pub const DEFAULT_RUN_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[async_trait]
+pub trait Collector: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn interval(&self) -> u64;
+
+    fn timeout(&self) -> Duration {
+        DEFAULT_RUN_TIMEOUT
+    }
+
+    async fn collect(
+        &self,
+        tx: mpsc::Sender<CollectorRequest>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+}
+
+pub struct CollectorManager {
+    collectors: Vec<Arc<dyn Collector>>,
+    observer: Arc<dyn Observer>,
+}
+
+impl Default for CollectorManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CollectorManager {
+    pub fn new() -> Self {
+        Self {
+            collectors: Vec::new(),
+            observer: Arc::new(NoObserver),
+        }
+    }
+
+    /// Passes the outcome of every collector run to `observer`.
+    pub fn with_observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    pub fn register<C: Collector + 'static>(&mut self, collector: C) {
+        self.collectors.push(Arc::new(collector));
+    }
+
+    /*
+     * start_all
+     * Runs every collector on its own schedule. Intervals pushed by the hub replace the
+     * built-in ones as soon as they arrive, collectors the hub disabled by name are skipped.
+     * Each run is supervised: a run that panics or outlives the collector's timeout is
+     * abandoned, reported as a health event and the collector starts fresh on its next tick.
+     * A run stuck in blocking code (e.g. systemctl on a broken D-Bus) can't be cancelled, the
+     * collector skips its ticks until that run returns instead of piling up more of them.
+     */
+    pub async fn start_all(
+        &self,
+        tx: mpsc::Sender<CollectorRequest>,
+        config: ConfigReceiver,
+        health: HealthSender,
+    ) {
+        for collector in &self.collectors {
+            let tx = tx.clone();
+            let collector = Arc::clone(collector);
+            let mut config = config.clone();
+            let health = health.clone();
+            let observer = Arc::clone(&self.observer);
+
+            tokio::spawn(async move {
+                info!("[collector] Starting {} collector", collector.name());
+                let mut restarts: u32 = 0;
+                let mut abandoned: Option<JoinHandle<CollectResult>> = None;
+                let mut period = remote_config::interval_secs(
+                    &config.borrow(),
+                    collector.name(),
+                    collector.interval(),
+                );
+                let mut interval = tokio::time::interval(Duration::from_secs(period));
+                let mut watching = true;
+
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        changed = config.changed(), if watching => {
+                            if changed.is_err() {
+                                // the hub doesn't push config, keep the current schedule
+                                watching = false;
+                                continue;
+                            }
+                            let pushed = remote_config::interval_secs(
+                                &config.borrow_and_update(),
+                                collector.name(),
+                                collector.interval(),
+                            );
+                            if pushed != period {
+                                info!("[collector] {} now runs every {}s", collector.name(), pushed);
+                                period = pushed;
+                                let every = Duration::from_secs(period);
+                                interval = tokio::time::interval_at(Instant::now() + every, every);
+                            }
+                            continue;
+                        }
+                    }
+                    if !remote_config::feature_enabled(&config.borrow(), collector.name()) {
+                        continue;
+                    }
+                    if let Some(run) = &abandoned {
+                        if !run.is_finished() {
+                            warn!(
+                                "[collector] {} is still stuck in an abandoned run, skipping",
+                                collector.name()
+                            );
+                            continue;
+                        }
+                        abandoned = None;
+                    }
+
+                    let start = Instant::now();
+                    let mut run = tokio::spawn({
+                        let collector = Arc::clone(&collector);
+                        let tx = tx.clone();
+                        async move { collector.collect(tx).await }
+                    });
+                    let (kind, detail) = match timeout(collector.timeout(), &mut run).await {
+                        Ok(Ok(Ok(()))) => {
+                            let elapsed = start.elapsed();
+                            observer.collector_succeeded(collector.name(), elapsed);
+                            info!(
+                                "[{}][{}s] collection completed",
+                                collector.name(),
+                                elapsed.as_secs_f32().round()
+                            );
+                            continue;
+                        }
+                        Ok(Ok(Err(e))) => {
+                            error!("[collector] {} collection failed: {}", collector.name(), e);
+                            observer.collector_failed(collector.name(), e.to_string(), false);
+                            continue;
+                        }
+                        Ok(Err(e)) if e.is_panic() => ("panic", panic_message(e.into_panic())),
+                        Ok(Err(e)) => {
+                            error!("[collector] {} run was cancelled: {}", collector.name(), e);
+                            continue;
+                        }
+                        Err(_) => {
+                            run.abort();
+                            abandoned = Some(run);
+                            (
+                                "timeout",
+                                format!("no result after {}s", collector.timeout().as_secs()),
+                            )
+                        }
+                    };
+                    restarts += 1;
+                    error!(
+                        "[collector] {} {}: {}, restarting ({} restarts)",
+                        collector.name(),
+                        kind,
+                        detail,
+                        restarts
+                    );
+                    observer.collector_failed(collector.name(), format!("{kind}: {detail}"), true);
+                    let event = health::event(collector.name(), kind, detail, restarts);
+                    if health.try_send(event).is_err() {
+                        warn!("[collector] Health event queue is full, dropping event");
+                    }
+                }
+            });
+        }
+    }
+}
+
+type CollectResult = Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panicked without a message".to_string()
+    }
+}
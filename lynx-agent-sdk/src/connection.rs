@@ -0,0 +1,576 @@
+use crate::client::AuthInterceptor;
+use crate::collector::CollectorRequest;
+use crate::observer::{NoObserver, Observer};
+use crate::proto::monitor::inventory_client::InventoryClient;
+use crate::proto::monitor::metrics_ingest_client::MetricsIngestClient;
+use crate::proto::monitor::system_monitor_client::SystemMonitorClient;
+use crate::proto::monitor::{
+    ContainerMetricsRequest, ContainerRequest, GpuMetricsRequest, GpuRequest, MetricsAck,
+    MetricsRequest, Response, SystemInfoRequest, SystemctlRequest,
+};
+use futures_util::stream;
+use log::{info, warn};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::time::{Instant, timeout};
+use tonic::codegen::InterceptedService;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::MetadataValue;
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Status, Streaming};
+use tonic_health::pb::HealthCheckRequest;
+use tonic_health::pb::health_check_response::ServingStatus;
+use tonic_health::pb::health_client::HealthClient;
+use tracing::Instrument;
+
+/*
+ * Hub connection
+ * Owns the gRPC channel the collectors' reports go out on. A report that fails because the hub
+ * is unreachable or too slow drops the channel; the next report reconnects, checks the hub's
+ * grpc.health.v1 service and only then goes out. Failed reconnects back off from 1 second up
+ * to a minute, reports in between fail without being sent; the caller can keep the ones whose
+ * error `is_transient` and send them again once the hub is back. The main loop also checks the
+ * hub's health every HEALTH_INTERVAL, so a hub that went away is noticed while collectors are
+ * quiet.
+ *
+ * Every report goes out with a fresh `x-request-id`, logged with its outcome here and by the
+ * hub with everything it does for the report, so both sides can be matched up. Deliveries and
+ * failures are passed on to the connection's Observer.
+ *
+ * Metrics go out on one long-lived MetricsChannel stream instead of a call per sample. The hub
+ * acks every sample in order, a rejected one is reported as failed and the stream stays open;
+ * a broken stream is reopened by the next sample and only drops the channel when the hub is
+ * unreachable. An ack with `retry_after_secs` holds samples back while the hub catches up.
+ */
+
+pub const HEALTH_INTERVAL: Duration = Duration::from_secs(30);
+const REQUEST_ID_HEADER: &str = "x-request-id";
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(5);
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Units per StreamSystemctl message, larger systemctl reports are streamed in chunks
+pub const SYSTEMCTL_CHUNK: usize = 500;
+
+pub type MonitorClient = SystemMonitorClient<InterceptedService<Channel, AuthInterceptor>>;
+type IngestClient = MetricsIngestClient<InterceptedService<Channel, AuthInterceptor>>;
+type RpcFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<tonic::Response<Response>, tonic::Status>> + Send + 'a>>;
+
+#[derive(Error, Debug)]
+pub enum ConnectionError {
+    #[error("Failed to connect to the hub: {0}")]
+    Connect(#[from] tonic::transport::Error),
+    #[error("Hub is not serving: {0}")]
+    Unhealthy(String),
+    #[error("Hub unreachable, retrying in {0}s")]
+    Backoff(u64),
+    #[error("Request failed: {0}")]
+    Rpc(#[from] tonic::Status),
+    #[error("Request timed out after {0}s")]
+    Timeout(u64),
+    #[error("Metrics channel closed by the hub")]
+    ChannelClosed,
+    #[error("Hub acked sample {0} out of order")]
+    UnexpectedAck(u64),
+    #[error("Hub asked to hold metrics back, retrying in {0}s")]
+    Throttled(u64),
+    #[error("Hub rejected the report ({status}): {message}")]
+    Rejected { status: String, message: String },
+}
+
+impl ConnectionError {
+    /// The hub couldn't take the report right now, sending it again later can succeed. Reports
+    /// the hub rejected, also with a non-200 Response, fail the same way every time.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            ConnectionError::Rpc(status) => matches!(
+                status.code(),
+                Code::Unavailable | Code::DeadlineExceeded | Code::ResourceExhausted
+            ),
+            ConnectionError::Connect(_)
+            | ConnectionError::Unhealthy(_)
+            | ConnectionError::Backoff(_)
+            | ConnectionError::Timeout(_)
+            | ConnectionError::ChannelClosed
+            | ConnectionError::Throttled(_) => true,
+            ConnectionError::UnexpectedAck(_) | ConnectionError::Rejected { .. } => false,
+        }
+    }
+}
+
+/// The open MetricsChannel: samples go in through `tx`, their acks come back in order.
+struct MetricsStream {
+    tx: mpsc::Sender<MetricsRequest>,
+    acks: Streaming<MetricsAck>,
+    /// `x-request-id` of the stream, the hub logs each sample as `<id>.<sequence>`
+    request_id: String,
+    sequence: u64,
+}
+
+impl MetricsStream {
+    /// Sends one sample and waits for its ack.
+    async fn exchange(&mut self, metrics: MetricsRequest) -> Result<MetricsAck, ConnectionError> {
+        self.tx
+            .send(metrics)
+            .await
+            .map_err(|_| ConnectionError::ChannelClosed)?;
+        let ack = self
+            .acks
+            .message()
+            .await?
+            .ok_or(ConnectionError::ChannelClosed)?;
+        if ack.sequence != self.sequence {
+            return Err(ConnectionError::UnexpectedAck(ack.sequence));
+        }
+        self.sequence += 1;
+        Ok(ack)
+    }
+}
+
+pub struct HubConnection {
+    endpoint: Endpoint,
+    auth: AuthInterceptor,
+    channel: Channel,
+    client: MonitorClient,
+    /// Set once the channel failed, None while it is up
+    retry_at: Option<Instant>,
+    backoff: Duration,
+    /// Cleared when the hub answers StreamSystemctl with UNIMPLEMENTED
+    stream_systemctl: bool,
+    /// Open until it breaks, the next sample opens a new one
+    metrics_stream: Option<MetricsStream>,
+    /// Cleared when the hub answers MetricsChannel with UNIMPLEMENTED
+    metrics_channel: bool,
+    /// Set while the hub asked to hold samples back
+    metrics_held_until: Option<Instant>,
+    observer: Arc<dyn Observer>,
+}
+
+impl HubConnection {
+    pub async fn connect(
+        endpoint: Endpoint,
+        auth: AuthInterceptor,
+    ) -> Result<Self, ConnectionError> {
+        let channel = endpoint.connect().await?;
+        let client = SystemMonitorClient::with_interceptor(channel.clone(), auth.clone());
+        Ok(Self {
+            endpoint,
+            auth,
+            channel,
+            client,
+            retry_at: None,
+            backoff: MIN_BACKOFF,
+            stream_systemctl: true,
+            metrics_stream: None,
+            metrics_channel: true,
+            metrics_held_until: None,
+            observer: Arc::new(NoObserver),
+        })
+    }
+
+    /// Passes the outcome of every report and health check to `observer`.
+    pub fn with_observer(mut self, observer: Arc<dyn Observer>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// The channel other clients of the hub (control, config) share.
+    pub fn channel(&self) -> Channel {
+        self.channel.clone()
+    }
+
+    /// A `SystemMonitor` client on the current channel.
+    pub fn client(&self) -> MonitorClient {
+        self.client.clone()
+    }
+
+    /// Asks the hub's health service, hubs without one count as serving.
+    async fn health(channel: Channel) -> Result<(), ConnectionError> {
+        let request = HealthCheckRequest {
+            service: String::new(),
+        };
+        match timeout(HEALTH_TIMEOUT, HealthClient::new(channel).check(request)).await {
+            Ok(Ok(response)) if response.get_ref().status == ServingStatus::Serving as i32 => {
+                Ok(())
+            }
+            Ok(Ok(response)) => Err(ConnectionError::Unhealthy(format!(
+                "status {}",
+                response.get_ref().status
+            ))),
+            Ok(Err(status)) if status.code() == Code::Unimplemented => Ok(()),
+            Ok(Err(status)) => Err(ConnectionError::Rpc(status)),
+            Err(_) => Err(ConnectionError::Timeout(HEALTH_TIMEOUT.as_secs())),
+        }
+    }
+
+    /// Drops the channel, the next report reconnects.
+    fn fail(&mut self) {
+        self.metrics_stream = None;
+        if self.retry_at.is_none() {
+            self.retry_at = Some(Instant::now());
+        }
+    }
+
+    /// Reconnects if the channel failed and the backoff ran out.
+    async fn ensure_connected(&mut self) -> Result<(), ConnectionError> {
+        let Some(retry_at) = self.retry_at else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        if now < retry_at {
+            return Err(ConnectionError::Backoff((retry_at - now).as_secs().max(1)));
+        }
+        let connected = match self.endpoint.connect().await {
+            Ok(channel) => Self::health(channel.clone()).await.map(|()| channel),
+            Err(e) => Err(e.into()),
+        };
+        match connected {
+            Ok(channel) => {
+                info!("[agent] Reconnected to the hub");
+                self.client =
+                    SystemMonitorClient::with_interceptor(channel.clone(), self.auth.clone());
+                self.channel = channel;
+                self.retry_at = None;
+                self.backoff = MIN_BACKOFF;
+                Ok(())
+            }
+            Err(e) => {
+                warn!(
+                    "[agent] Reconnecting to the hub failed, retrying in {}s: {}",
+                    self.backoff.as_secs(),
+                    e
+                );
+                self.retry_at = Some(now + self.backoff);
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+                Err(e)
+            }
+        }
+    }
+
+    /// Checks the hub's health on the current channel, a failure reconnects.
+    pub async fn check_health(&mut self) {
+        if self.retry_at.is_some() {
+            let _ = self.ensure_connected().await;
+            return;
+        }
+        if let Err(e) = Self::health(self.channel.clone()).await {
+            warn!("[agent] Hub health check failed: {}", e);
+            self.observer.report_failed(e.to_string());
+            self.fail();
+        }
+    }
+
+    async fn send<T, F>(&mut self, request: T, operation: F) -> Result<(), ConnectionError>
+    where
+        F: for<'a> FnOnce(&'a mut MonitorClient, tonic::Request<T>) -> RpcFuture<'a>,
+    {
+        if let Err(e) = self.ensure_connected().await {
+            self.observer.report_failed(e.to_string());
+            return Err(e);
+        }
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let span = tracing::info_span!("request", request_id = %request_id);
+        let mut request = tonic::Request::new(request);
+        if let Ok(value) = MetadataValue::try_from(request_id.as_str()) {
+            request.metadata_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        let result = timeout(RPC_TIMEOUT, operation(&mut self.client, request))
+            .instrument(span.clone())
+            .await;
+        let _entered = span.enter();
+        let error = match result {
+            Ok(Ok(response)) => {
+                let response = response.into_inner();
+                if response.status == "200" {
+                    info!("[agent] Request successful");
+                    self.observer.report_sent();
+                    return Ok(());
+                }
+                info!("[agent] Request failed: {:?}", response.message);
+                ConnectionError::Rejected {
+                    status: response.status,
+                    message: response.message,
+                }
+            }
+            Ok(Err(status)) => {
+                if matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded) {
+                    self.fail();
+                }
+                ConnectionError::Rpc(status)
+            }
+            Err(_) => {
+                self.fail();
+                ConnectionError::Timeout(RPC_TIMEOUT.as_secs())
+            }
+        };
+        self.observer.report_failed(error.to_string());
+        Err(error)
+    }
+
+    pub async fn send_sysinfo(&mut self, info: SystemInfoRequest) -> Result<(), ConnectionError> {
+        self.send(info, |client, req| Box::pin(client.get_system_info(req)))
+            .await
+    }
+
+    /*
+     * send_metrics
+     * Sends a sample on the metrics channel, hubs without one get a ReportMetrics call per sample
+     * from then on. The sample is stamped right before it goes out, after any reconnect, so the
+     * hub can tell how far our clock is off from its own.
+     */
+    pub async fn send_metrics(&mut self, metrics: MetricsRequest) -> Result<(), ConnectionError> {
+        if let Some(until) = self.metrics_held_until {
+            let now = Instant::now();
+            if now < until {
+                return Err(ConnectionError::Throttled((until - now).as_secs().max(1)));
+            }
+            self.metrics_held_until = None;
+        }
+        if self.metrics_channel {
+            if let Err(e) = self.ensure_connected().await {
+                self.observer.report_failed(e.to_string());
+                return Err(e);
+            }
+            let stream = match self.metrics_stream.take() {
+                Some(stream) => Ok(stream),
+                None => self.open_metrics_stream().await,
+            };
+            match stream {
+                Ok(stream) => return self.send_on_stream(stream, metrics).await,
+                Err(ConnectionError::Rpc(status)) if status.code() == Code::Unimplemented => {
+                    warn!("[agent] Hub has no metrics channel, reporting samples one by one");
+                    self.metrics_channel = false;
+                }
+                Err(e) => {
+                    self.fail_on(&e);
+                    self.observer.report_failed(e.to_string());
+                    return Err(e);
+                }
+            }
+        }
+        self.send(metrics, |client, mut req| {
+            req.get_mut().sent_at_ms = Some(chrono::Utc::now().timestamp_millis());
+            Box::pin(client.report_metrics(req))
+        })
+        .await
+    }
+
+    async fn open_metrics_stream(&mut self) -> Result<MetricsStream, ConnectionError> {
+        let (tx, rx) = mpsc::channel(1);
+        let request_id = uuid::Uuid::new_v4().to_string();
+        let mut request = tonic::Request::new(ReceiverStream::new(rx));
+        if let Ok(value) = MetadataValue::try_from(request_id.as_str()) {
+            request.metadata_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        let mut ingest: IngestClient =
+            MetricsIngestClient::with_interceptor(self.channel(), self.auth.clone());
+        let acks = timeout(RPC_TIMEOUT, ingest.metrics_channel(request))
+            .await
+            .map_err(|_| ConnectionError::Timeout(RPC_TIMEOUT.as_secs()))??
+            .into_inner();
+        info!("[agent] Opened the metrics channel");
+        Ok(MetricsStream {
+            tx,
+            acks,
+            request_id,
+            sequence: 0,
+        })
+    }
+
+    /// Sends a sample on `stream` and keeps the stream for the next one unless it broke.
+    async fn send_on_stream(
+        &mut self,
+        mut stream: MetricsStream,
+        mut metrics: MetricsRequest,
+    ) -> Result<(), ConnectionError> {
+        let message_id = format!("{}.{}", stream.request_id, stream.sequence);
+        let span = tracing::info_span!("request", request_id = %message_id);
+        metrics.sent_at_ms = Some(chrono::Utc::now().timestamp_millis());
+        let result = timeout(RPC_TIMEOUT, stream.exchange(metrics))
+            .instrument(span.clone())
+            .await
+            .unwrap_or(Err(ConnectionError::Timeout(RPC_TIMEOUT.as_secs())));
+        let _entered = span.enter();
+        let ack = match result {
+            Ok(ack) => ack,
+            Err(e) => {
+                warn!("[agent] Metrics channel broke: {}", e);
+                self.fail_on(&e);
+                self.observer.report_failed(e.to_string());
+                return Err(e);
+            }
+        };
+        self.metrics_stream = Some(stream);
+        if ack.retry_after_secs > 0 {
+            warn!(
+                "[agent] Hub is backed up, holding metrics back for {}s",
+                ack.retry_after_secs
+            );
+            self.metrics_held_until =
+                Some(Instant::now() + Duration::from_secs(ack.retry_after_secs.into()));
+        }
+        if ack.code != Code::Ok as i32 {
+            let status = Status::new(Code::from(ack.code), ack.message);
+            info!("[agent] Request failed: {:?}", status.message());
+            self.observer.report_failed(status.to_string());
+            return Err(ConnectionError::Rpc(status));
+        }
+        info!("[agent] Request successful");
+        self.observer.report_sent();
+        Ok(())
+    }
+
+    /// Drops the channel after errors that say the hub is unreachable, a stream the hub merely
+    /// closed is reopened on the same channel.
+    fn fail_on(&mut self, error: &ConnectionError) {
+        let unreachable = match error {
+            ConnectionError::Rpc(status) => {
+                matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded)
+            }
+            ConnectionError::Timeout(_) => true,
+            _ => false,
+        };
+        if unreachable {
+            self.fail();
+        }
+    }
+
+    /*
+     * send_systemctl
+     * Reports with more than SYSTEMCTL_CHUNK units go out as a StreamSystemctl of chunks, the
+     * units are moved into them rather than copied. Hubs without the RPC get whole reports
+     * from then on; the streamed report is lost, the next full sync sends every unit again.
+     */
+    pub async fn send_systemctl(
+        &mut self,
+        systemctl: SystemctlRequest,
+    ) -> Result<(), ConnectionError> {
+        if systemctl.services.len() <= SYSTEMCTL_CHUNK || !self.stream_systemctl {
+            return self
+                .send(systemctl, |client, req| {
+                    Box::pin(client.report_systemctl(req))
+                })
+                .await;
+        }
+        if let Err(e) = self.ensure_connected().await {
+            self.observer.report_failed(e.to_string());
+            return Err(e);
+        }
+        let mut inventory = InventoryClient::with_interceptor(self.channel(), self.auth.clone());
+        let chunks = stream::iter(systemctl_chunks(systemctl, SYSTEMCTL_CHUNK));
+        let result = self
+            .send(chunks, move |_, req| {
+                Box::pin(async move { inventory.stream_systemctl(req).await })
+            })
+            .await;
+        let unimplemented = matches!(
+            &result,
+            Err(ConnectionError::Rpc(status)) if status.code() == Code::Unimplemented
+        );
+        if unimplemented {
+            warn!("[agent] Hub does not take streamed systemctl reports, sending them whole");
+            self.stream_systemctl = false;
+        }
+        result
+    }
+
+    pub async fn send_gpu_info(&mut self, gpus: GpuRequest) -> Result<(), ConnectionError> {
+        self.send(gpus, |client, req| Box::pin(client.register_gp_us(req)))
+            .await
+    }
+
+    pub async fn send_gpu_metrics(
+        &mut self,
+        metrics: GpuMetricsRequest,
+    ) -> Result<(), ConnectionError> {
+        self.send(metrics, |client, req| {
+            Box::pin(client.report_gpu_metrics(req))
+        })
+        .await
+    }
+
+    pub async fn send_containers(
+        &mut self,
+        containers: ContainerRequest,
+    ) -> Result<(), ConnectionError> {
+        self.send(containers, |client, req| {
+            Box::pin(client.register_containers(req))
+        })
+        .await
+    }
+
+    pub async fn send_container_metrics(
+        &mut self,
+        metrics: ContainerMetricsRequest,
+    ) -> Result<(), ConnectionError> {
+        self.send(metrics, |client, req| {
+            Box::pin(client.report_container_metrics(req))
+        })
+        .await
+    }
+
+    /// Sends what a collector produced.
+    pub async fn report(&mut self, request: CollectorRequest) -> Result<(), ConnectionError> {
+        self.observer.report_collected(&request);
+        match request {
+            CollectorRequest::SystemInfo(info) => {
+                info!("[agent] Sending system info to hub...");
+                self.send_sysinfo(*info).await
+            }
+            CollectorRequest::Metrics(metrics) => {
+                info!("[agent] Sending metrics to hub...");
+                self.send_metrics(*metrics).await
+            }
+            CollectorRequest::Systemctl(systemctl) => {
+                info!("[agent] Sending systemctl services to hub...");
+                self.send_systemctl(systemctl).await
+            }
+            CollectorRequest::GpuInfo(gpus) => {
+                info!("[agent] Sending GPU info to hub...");
+                self.send_gpu_info(gpus).await
+            }
+            CollectorRequest::GpuMetrics(metrics) => {
+                info!("[agent] Sending GPU metrics to hub...");
+                self.send_gpu_metrics(metrics).await
+            }
+            CollectorRequest::ContainerInfo(containers) => {
+                info!("[agent] Sending container info to hub...");
+                self.send_containers(containers).await
+            }
+            CollectorRequest::ContainerMetrics(metrics) => {
+                info!("[agent] Sending container metrics to hub...");
+                self.send_container_metrics(metrics).await
+            }
+        }
+    }
+}
+
+/// Splits a systemctl report into messages of at most `size` units, removals go with the first.
+pub fn systemctl_chunks(
+    systemctl: SystemctlRequest,
+    size: usize,
+) -> impl Iterator<Item = SystemctlRequest> + Send + 'static {
+    let SystemctlRequest {
+        services,
+        mut removed,
+        full_sync,
+    } = systemctl;
+    let mut services = services.into_iter().peekable();
+    let mut first = true;
+    std::iter::from_fn(move || {
+        if services.peek().is_none() && !first {
+            return None;
+        }
+        first = false;
+        Some(SystemctlRequest {
+            services: services.by_ref().take(size.max(1)).collect(),
+            removed: std::mem::take(&mut removed),
+            full_sync,
+        })
+    })
+}
@@ -0,0 +1,49 @@
+use crate::client::AuthInterceptor;
+use crate::proto::monitor::AgentHealthEvent;
+use crate::proto::monitor::control_client::ControlClient;
+use log::{info, warn};
+use tokio::sync::mpsc;
+use tonic::Code;
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+
+/*
+ * Health events
+ * The collector supervisor reports every run it gave up on (a panic or a timeout) to the hub
+ * over ReportHealth. Reporting is best effort: events that can't be delivered are logged and
+ * dropped, the supervisor never waits for the hub.
+ */
+
+pub type HealthSender = mpsc::Sender<AgentHealthEvent>;
+
+/// Events waiting for the hub, further ones are dropped while it is full.
+pub const HEALTH_QUEUE: usize = 64;
+
+pub fn event(collector: &str, kind: &str, detail: String, restarts: u32) -> AgentHealthEvent {
+    AgentHealthEvent {
+        collector: collector.to_string(),
+        kind: kind.to_string(),
+        detail,
+        restarts,
+    }
+}
+
+pub async fn report_health(
+    mut client: ControlClient<InterceptedService<Channel, AuthInterceptor>>,
+    mut rx: mpsc::Receiver<AgentHealthEvent>,
+) {
+    let mut supported = true;
+    while let Some(event) = rx.recv().await {
+        if !supported {
+            continue;
+        }
+        match client.report_health(event).await {
+            Ok(_) => {}
+            Err(status) if status.code() == Code::Unimplemented => {
+                info!("[health] Hub does not take health events, keeping them in the log");
+                supported = false;
+            }
+            Err(status) => warn!("[health] Failed to report health event: {}", status),
+        }
+    }
+}
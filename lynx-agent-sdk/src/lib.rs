@@ -0,0 +1,16 @@
+/*
+ * Lynx agent SDK
+ * What an agent needs to report to a Lynx hub: the protocol types and clients, the Collector
+ * trait with the supervisor running collectors on their schedule, the hub connection their
+ * reports go out on, collector health events and the config the hub pushes. lynx-agent is
+ * built on it; a specialized agent (e.g. appliance firmware) registers its own collectors and
+ * speaks the same protocol without forking the binary. See the lynx-agent section of docs.md.
+ */
+
+pub mod client;
+pub mod collector;
+pub mod connection;
+pub mod health;
+pub mod observer;
+pub mod proto;
+pub mod remote_config;
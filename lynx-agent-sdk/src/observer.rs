@@ -0,0 +1,30 @@
+use crate::collector::CollectorRequest;
+use std::time::Duration;
+
+/*
+ * Observer
+ * Hooks into collection and reporting for an agent that wants to show how they go, lynx-agent
+ * feeds its diagnostics page from them. Every hook does nothing unless implemented, they are
+ * called inline and must not block.
+ */
+pub trait Observer: Send + Sync {
+    /// A collector run finished without error.
+    fn collector_succeeded(&self, _collector: &'static str, _duration: Duration) {}
+
+    /// A collector run failed, `restarted` when it panicked or timed out and was abandoned.
+    fn collector_failed(&self, _collector: &'static str, _error: String, _restarted: bool) {}
+
+    /// A report is about to go out to the hub.
+    fn report_collected(&self, _request: &CollectorRequest) {}
+
+    /// The hub took a report.
+    fn report_sent(&self) {}
+
+    /// A report or health check failed.
+    fn report_failed(&self, _error: String) {}
+}
+
+/// The observer of agents that don't set one.
+pub struct NoObserver;
+
+impl Observer for NoObserver {}
@@ -0,0 +1,102 @@
+use crate::client::AuthInterceptor;
+use crate::proto::monitor::system_monitor_client::SystemMonitorClient;
+use crate::proto::monitor::{AgentConfig, WatchConfigRequest};
+use log::{info, warn};
+use std::time::Duration;
+use tokio::sync::watch;
+use tonic::Code;
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+
+/*
+ * Remote configuration
+ * The hub pushes collector intervals, probe targets and feature toggles over a WatchConfig
+ * stream. The latest config is shared through a watch channel, collectors read it on every
+ * pass. Until the hub sent anything the default (empty) config applies, which keeps the
+ * built-in intervals and every feature but the experimental ones enabled. Those wait for the
+ * hub to turn them on, usually through a feature flag rolled out to a few systems first. A
+ * config with `decommission` set is handed to the agent's decommission hook instead, lynx-agent
+ * uninstalls itself.
+ */
+
+pub type ConfigReceiver = watch::Receiver<AgentConfig>;
+
+const RETRY_MIN: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+
+/// Features that are off unless the hub enables them.
+pub const EXPERIMENTAL_FEATURES: &[&str] = &["smart"];
+
+/// The pushed interval for a collector, its built-in one otherwise.
+pub fn interval_secs(config: &AgentConfig, collector: &str, default: u64) -> u64 {
+    config
+        .collector_intervals
+        .get(collector)
+        .copied()
+        .filter(|secs| *secs > 0)
+        .unwrap_or(default)
+}
+
+/// Features stay enabled unless the hub turned them off explicitly, experimental ones the
+/// other way around.
+pub fn feature_enabled(config: &AgentConfig, feature: &str) -> bool {
+    config
+        .features
+        .get(feature)
+        .copied()
+        .unwrap_or(!EXPERIMENTAL_FEATURES.contains(&feature))
+}
+
+/*
+ * watch_config
+ * Keeps a WatchConfig stream open and publishes every config the hub sends. Reconnects with
+ * backoff when the stream breaks, gives up for good on hubs that don't implement the RPC. Stops
+ * after calling `decommission` once the hub decommissioned the system.
+ */
+pub async fn watch_config(
+    mut client: SystemMonitorClient<InterceptedService<Channel, AuthInterceptor>>,
+    tx: watch::Sender<AgentConfig>,
+    decommission: impl FnOnce() + Send,
+) {
+    let mut backoff = RETRY_MIN;
+    loop {
+        let version = tx.borrow().version;
+        match client.watch_config(WatchConfigRequest { version }).await {
+            Ok(response) => {
+                backoff = RETRY_MIN;
+                let mut stream = response.into_inner();
+                loop {
+                    match stream.message().await {
+                        Ok(Some(config)) if config.decommission => {
+                            warn!("[config] The hub decommissioned this system");
+                            decommission();
+                            return;
+                        }
+                        Ok(Some(config)) => {
+                            info!(
+                                "[config] Applying config version {} from hub",
+                                config.version
+                            );
+                            tx.send_replace(config);
+                        }
+                        Ok(None) => {
+                            warn!("[config] Hub closed the config stream");
+                            break;
+                        }
+                        Err(status) => {
+                            warn!("[config] Config stream failed: {}", status);
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(status) if status.code() == Code::Unimplemented => {
+                info!("[config] Hub does not push config, keeping local settings");
+                return;
+            }
+            Err(status) => warn!("[config] Failed to watch config: {}", status),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(RETRY_MAX);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::lib::client::AuthInterceptor;
+use crate::proto::monitor::control_client::ControlClient;
+use crate::proto::monitor::CommandAudit;
+use log::{info, warn};
+use std::sync::Mutex;
+use tokio::sync::mpsc;
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/*
+ * Command audit
+ * Every action taken on the host over the websocket (commands, service control, updates and
+ * uninstalls) is logged and reported to the hub over ReportCommand once it ended: who asked
+ * for it, what, when and how it ended. Like health events reporting is best effort, entries
+ * the hub can't take stay in the log only. Commands carry their transcript, see lib::websocket.
+ */
+
+/// Entries waiting for the hub, further ones are dropped while it is full.
+pub const AUDIT_QUEUE: usize = 256;
+
+type Queue = (
+    mpsc::Sender<CommandAudit>,
+    Mutex<Option<mpsc::Receiver<CommandAudit>>>,
+);
+
+lazy_static::lazy_static! {
+    static ref QUEUE: Queue = {
+        let (tx, rx) = mpsc::channel(AUDIT_QUEUE);
+        (tx, Mutex::new(Some(rx)))
+    };
+}
+
+/// An action `actor` asked for just now, handed to `finish` once it ended.
+pub fn started(actor: &str, action: &str, target: &str, args: Vec<String>) -> CommandAudit {
+    CommandAudit {
+        time: chrono::Utc::now().timestamp(),
+        actor: actor.to_string(),
+        action: action.to_string(),
+        target: target.to_string(),
+        args,
+        exit_code: None,
+        error: String::new(),
+        transcript: Vec::new(),
+    }
+}
+
+/// Queues the entry for the hub, `error` None when the action succeeded.
+pub fn finish(mut entry: CommandAudit, exit_code: Option<i32>, error: Option<String>) {
+    entry.exit_code = exit_code;
+    entry.error = error.unwrap_or_default();
+    if entry.error.is_empty() {
+        info!(
+            "[audit] {} {} {:?} for {}, exit code {:?}",
+            entry.action, entry.target, entry.args, entry.actor, entry.exit_code
+        );
+    } else {
+        warn!(
+            "[audit] {} {} {:?} for {} failed: {}",
+            entry.action, entry.target, entry.args, entry.actor, entry.error
+        );
+    }
+    if QUEUE.0.try_send(entry).is_err() {
+        warn!("[audit] Queue is full, the entry is only logged");
+    }
+}
+
+/// An action that ended right away.
+pub fn record(actor: &str, action: &str, target: &str, args: Vec<String>, error: Option<String>) {
+    finish(started(actor, action, target, args), None, error);
+}
+
+pub async fn report_commands(
+    mut client: ControlClient<InterceptedService<Channel, AuthInterceptor>>,
+) {
+    let Some(mut rx) = QUEUE.1.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+        return;
+    };
+    let mut supported = true;
+    while let Some(entry) = rx.recv().await {
+        if !supported {
+            continue;
+        }
+        match client.report_command(entry).await {
+            Ok(_) => {}
+            Err(status) if status.code() == Code::Unimplemented => {
+                info!("[audit] Hub does not take command audits, keeping them in the log");
+                supported = false;
+            }
+            Err(status) => warn!("[audit] Failed to report command: {}", status),
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use base64::Engine;
+use log::{info, warn};
+use ring::signature::{self, UnparsedPublicKey, VerificationAlgorithm};
+use serde::Deserialize;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Row;
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+use x509_parser::oid_registry::{
+    OID_KEY_TYPE_EC_PUBLIC_KEY, OID_PKCS1_RSAENCRYPTION, OID_SIG_ED25519,
+};
+
+/*
+ * Command authorizations
+ * Websocket messages listed in the `[authorization]` section of config.toml (by default every
+ * one that changes the host, see DEFAULT_ACTIONS) are only carried out with a `token` the hub
+ * issued for them, so reaching the websocket port alone can't destroy the host. The token names
+ * the action, optionally its target, this agent (by its key) and an expiry, and is signed with
+ * the hub's CA key; the agent checks it against certs/ca.crt and takes each token once. Used
+ * tokens are known by the digest of their claims, which carry a nonce, not by the signature,
+ * since an ECDSA signature can be rewritten into another valid one. They are kept in the
+ * `used_tokens` table of the cache database until they expire, so restarting the agent doesn't
+ * make them usable again. Without the CA, e.g. in `--insecure` mode, the listed messages are
+ * refused.
+ */
+
+/// Messages that need a token unless config.toml says otherwise.
+pub const DEFAULT_ACTIONS: [&str; 8] = [
+    "delete",
+    "execute",
+    "applyupdates",
+    "upload",
+    "startservice",
+    "stopservice",
+    "restartservice",
+    "update",
+];
+
+/// Optional `[authorization]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct AuthorizationConfig {
+    /// Websocket message types that need a token from the hub
+    pub actions: Vec<String>,
+}
+
+impl Default for AuthorizationConfig {
+    fn default() -> Self {
+        Self {
+            actions: DEFAULT_ACTIONS.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+}
+
+struct Policy {
+    actions: Vec<String>,
+    /// key_id of the agent key, as the hub computes it
+    agent: String,
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// The CA's verification algorithm and public key, loaded by the first check that finds it
+static CA: OnceLock<(&'static dyn VerificationAlgorithm, Vec<u8>)> = OnceLock::new();
+
+lazy_static::lazy_static! {
+    /// Ids of the tokens used so far, with their expiry
+    static ref USED: Mutex<HashMap<String, i64>> = Mutex::new(HashMap::new());
+}
+
+/// Where used tokens are persisted, set by remember_used
+static USED_STORE: OnceLock<SqlitePool> = OnceLock::new();
+
+/*
+ * remember_used
+ * Loads the tokens used before the agent last stopped from the cache database, and keeps the
+ * ones used from now on there until they expire. Called before the websocket server starts.
+ */
+pub async fn remember_used(pool: SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS used_tokens (
+            id TEXT PRIMARY KEY,
+            expires INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(&pool)
+    .await?;
+    sqlx::query("DELETE FROM used_tokens WHERE expires < ?")
+        .bind(chrono::Utc::now().timestamp())
+        .execute(&pool)
+        .await?;
+    let rows = sqlx::query("SELECT id, expires FROM used_tokens")
+        .fetch_all(&pool)
+        .await?;
+    if !rows.is_empty() {
+        info!("[authorization] {} tokens were already used", rows.len());
+    }
+    USED.lock().unwrap_or_else(|e| e.into_inner()).extend(
+        rows.iter()
+            .map(|row| (row.get::<String, _>("id"), row.get::<i64, _>("expires"))),
+    );
+    if USED_STORE.set(pool).is_err() {
+        warn!("[authorization] Used tokens already loaded, ignoring");
+    }
+    Ok(())
+}
+
+/// Writes a used token to the cache database in the background, check itself stays synchronous.
+fn persist_used(id: String, expires: i64, now: i64) {
+    let (Some(pool), Ok(runtime)) = (USED_STORE.get(), tokio::runtime::Handle::try_current())
+    else {
+        return;
+    };
+    let pool = pool.clone();
+    runtime.spawn(async move {
+        let result = async {
+            sqlx::query("DELETE FROM used_tokens WHERE expires < ?")
+                .bind(now)
+                .execute(&pool)
+                .await?;
+            sqlx::query("INSERT OR IGNORE INTO used_tokens (id, expires) VALUES (?, ?)")
+                .bind(&id)
+                .bind(expires)
+                .execute(&pool)
+                .await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("[authorization] Failed to persist a used token: {}", e);
+        }
+    });
+}
+
+/// Sets which messages need a token, once the agent key is known.
+pub fn set_policy(config: AuthorizationConfig, agent_key: &str) {
+    let policy = Policy {
+        actions: config.actions,
+        agent: key_id(agent_key),
+    };
+    if POLICY.set(policy).is_err() {
+        warn!("[authorization] Authorization policy already set, ignoring");
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum AuthorizationError {
+    #[error("{0} needs a token from the hub, see POST /systems/{{id}}/authorizations")]
+    Missing(String),
+    #[error("Malformed token")]
+    Malformed,
+    #[error("Token signature does not verify against the hub CA")]
+    BadSignature,
+    #[error("Token is for {0:?}")]
+    WrongAction(String),
+    #[error("Token is for another target")]
+    WrongTarget,
+    #[error("Token is for another system")]
+    WrongSystem,
+    #[error("Token expired")]
+    Expired,
+    #[error("Token was already used")]
+    Reused,
+    #[error("Hub CA not available: {0}")]
+    NoCa(String),
+}
+
+#[derive(Deserialize, Debug)]
+struct Claims {
+    action: String,
+    target: Option<String>,
+    agent: String,
+    expires: i64,
+    /// Random per token, so two tokens never have the same claims
+    nonce: String,
+}
+
+/// Same as the hub's auth_limit::key_id: the first 8 bytes of the key's sha256, in hex.
+pub fn key_id(agent_key: &str) -> String {
+    ring::digest::digest(&ring::digest::SHA256, agent_key.as_bytes()).as_ref()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Upload chunks past the first continue an upload its first chunk was authorized for, the
+/// agent only takes them from the connection that started it.
+fn continues_upload(action: &str, message: &serde_json::Value) -> bool {
+    action == "upload" && message.get("offset").and_then(|o| o.as_u64()).unwrap_or(0) > 0
+}
+
+/// What a message acts on, the first of the fields messages name their target with.
+fn message_target(message: &serde_json::Value) -> Option<&str> {
+    ["service_name", "command", "path", "url"]
+        .iter()
+        .find_map(|field| message.get(field).and_then(|v| v.as_str()))
+}
+
+/// The algorithm the CA key signs with, see the hub's CertificateAuthority::sign.
+fn algorithm(
+    spki: &x509_parser::x509::SubjectPublicKeyInfo,
+) -> Option<&'static dyn VerificationAlgorithm> {
+    let oid = &spki.algorithm.algorithm;
+    if *oid == OID_PKCS1_RSAENCRYPTION {
+        Some(&signature::RSA_PKCS1_2048_8192_SHA256)
+    } else if *oid == OID_SIG_ED25519 {
+        Some(&signature::ED25519)
+    } else if *oid == OID_KEY_TYPE_EC_PUBLIC_KEY {
+        // uncompressed points, 65 bytes on P-256 and 97 on P-384
+        match spki.subject_public_key.data.len() {
+            65 => Some(&signature::ECDSA_P256_SHA256_ASN1),
+            97 => Some(&signature::ECDSA_P384_SHA384_ASN1),
+            _ => None,
+        }
+    } else {
+        None
+    }
+}
+
+fn verify_with_ca(payload: &[u8], sig: &[u8]) -> Result<(), AuthorizationError> {
+    let (algorithm, key) = match CA.get() {
+        Some(ca) => ca,
+        None => {
+            let ca = load_ca()?;
+            CA.get_or_init(|| ca)
+        }
+    };
+    UnparsedPublicKey::new(*algorithm, key)
+        .verify(payload, sig)
+        .map_err(|_| AuthorizationError::BadSignature)
+}
+
+/// Reads the CA certificate; a missing one isn't cached, so a later enrollment still counts.
+fn load_ca() -> Result<(&'static dyn VerificationAlgorithm, Vec<u8>), AuthorizationError> {
+    let path = env::var("LYNX_CA_PATH").unwrap_or_else(|_| "certs/ca.crt".to_string());
+    let no_ca = AuthorizationError::NoCa;
+    let pem = std::fs::read(&path).map_err(|e| no_ca(format!("{path}: {e}")))?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&pem).map_err(|e| no_ca(e.to_string()))?;
+    let cert = pem.parse_x509().map_err(|e| no_ca(e.to_string()))?;
+    let spki = cert.public_key();
+    let algorithm = algorithm(spki).ok_or_else(|| no_ca("unsupported key type".to_string()))?;
+    Ok((algorithm, spki.subject_public_key.data.to_vec()))
+}
+
+/*
+ * check
+ * Lets a websocket message of type `action` through if it needs no token, or carries a valid
+ * one. Tokens are verified before anything in them is trusted.
+ */
+pub fn check(action: &str, message: &serde_json::Value) -> Result<(), AuthorizationError> {
+    let Some(policy) = POLICY.get() else {
+        // the websocket server starts after the policy is set
+        return Err(AuthorizationError::Missing(action.to_string()));
+    };
+    if !policy.actions.iter().any(|a| a == action) || continues_upload(action, message) {
+        return Ok(());
+    }
+    let token = message
+        .get("token")
+        .and_then(|t| t.as_str())
+        .ok_or_else(|| AuthorizationError::Missing(action.to_string()))?;
+    let (payload, sig) = token.split_once('.').ok_or(AuthorizationError::Malformed)?;
+    let engine = base64::engine::general_purpose::STANDARD;
+    let payload = engine
+        .decode(payload)
+        .map_err(|_| AuthorizationError::Malformed)?;
+    let sig = engine
+        .decode(sig)
+        .map_err(|_| AuthorizationError::Malformed)?;
+    verify_with_ca(&payload, &sig)?;
+
+    let claims: Claims =
+        serde_json::from_slice(&payload).map_err(|_| AuthorizationError::Malformed)?;
+    if claims.nonce.is_empty() {
+        return Err(AuthorizationError::Malformed);
+    }
+    if claims.action != action {
+        return Err(AuthorizationError::WrongAction(claims.action));
+    }
+    if claims.target.is_some() && claims.target.as_deref() != message_target(message) {
+        return Err(AuthorizationError::WrongTarget);
+    }
+    if claims.agent != policy.agent {
+        return Err(AuthorizationError::WrongSystem);
+    }
+    let now = chrono::Utc::now().timestamp();
+    if claims.expires < now {
+        return Err(AuthorizationError::Expired);
+    }
+    // the claims name the token: they are what the CA signed, a signature has other valid forms
+    let id: String = ring::digest::digest(&ring::digest::SHA256, &payload)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    let mut used = USED.lock().unwrap_or_else(|e| e.into_inner());
+    used.retain(|_, expires| *expires >= now);
+    if used.insert(id.clone(), claims.expires).is_some() {
+        return Err(AuthorizationError::Reused);
+    }
+    drop(used);
+    persist_used(id, claims.expires, now);
+    Ok(())
+}
@@ -5,6 +5,7 @@ use dashmap::DashMap;
 use log::info;
 use serde::{Deserialize, Serialize};
 use sqlx::{sqlite::SqlitePool, Row};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use thiserror::Error;
@@ -65,6 +66,19 @@ pub struct LogEntry {
 
 pub type CacheResult<T> = Result<T, CacheError>;
 
+/// Upper bounds for the cache. When either limit is exceeded the least recently written entries
+/// are evicted until the cache is a tenth under both. `None` means unbounded.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CacheLimits {
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+}
+
+/// Eviction frees a tenth below a limit, so a full cache doesn't evict on every write.
+fn low_water(limit: usize) -> usize {
+    limit - limit / 10
+}
+
 pub struct FastCache {
     // In-memory cache for ultra-fast access
     memory_cache: Arc<DashMap<String, Vec<u8>>>,
@@ -74,17 +88,30 @@ pub struct FastCache {
     db_pool: SqlitePool,
     // Write-through vs write-back mode
     write_through: bool,
+    // Size limits enforced on every write
+    limits: CacheLimits,
+    // Running total of the entries' sizes, so writes don't have to sum them
+    total_bytes: AtomicUsize,
 }
 
 #[derive(Debug, Clone)]
 struct CacheMetadata {
     expires_at: Option<DateTime<Utc>>,
+    updated_at: DateTime<Utc>,
     tags: Vec<String>,
     size: usize,
 }
 
 impl FastCache {
     pub async fn new(database_url: &str, write_through: bool) -> CacheResult<Self> {
+        Self::new_with_limits(database_url, write_through, CacheLimits::default()).await
+    }
+
+    pub async fn new_with_limits(
+        database_url: &str,
+        write_through: bool,
+        limits: CacheLimits,
+    ) -> CacheResult<Self> {
         let db_pool = SqlitePool::connect(database_url).await?;
 
         // Create tables
@@ -118,25 +145,33 @@ impl FastCache {
             metadata_cache: Arc::new(DashMap::new()),
             db_pool,
             write_through,
+            limits,
+            total_bytes: AtomicUsize::new(0),
         };
 
         // Load existing data into memory cache on startup
         cache.load_from_disk().await?;
+        cache.enforce_limits(None).await?;
 
         Ok(cache)
     }
 
     async fn load_from_disk(&self) -> CacheResult<()> {
-        let rows = sqlx::query("SELECT key, value, expires_at, tags FROM cache_entries")
-            .fetch_all(&self.db_pool)
-            .await?;
+        let rows =
+            sqlx::query("SELECT key, value, updated_at, expires_at, tags FROM cache_entries")
+                .fetch_all(&self.db_pool)
+                .await?;
 
         for row in rows {
             let key: String = row.get("key");
             let value: Vec<u8> = row.get("value");
             let expires_at: Option<String> = row.get("expires_at");
+            let updated_at: String = row.get("updated_at");
             let tags: Option<String> = row.get("tags");
 
+            let updated_at = DateTime::parse_from_rfc3339(&updated_at)
+                .map_err(|_| CacheError::InvalidKey("Invalid updated_at format".to_string()))?
+                .with_timezone(&Utc);
             let expires_at = expires_at
                 .map(|s| DateTime::parse_from_rfc3339(&s))
                 .transpose()
@@ -155,10 +190,11 @@ impl FastCache {
                 .unwrap_or_default();
 
             self.memory_cache.insert(key.clone(), value.clone());
-            self.metadata_cache.insert(
+            self.insert_metadata(
                 key,
                 CacheMetadata {
                     expires_at,
+                    updated_at,
                     tags: parsed_tags,
                     size: value.len(),
                 },
@@ -186,10 +222,11 @@ impl FastCache {
         // Store in memory cache
         self.memory_cache
             .insert(key.to_string(), serialized.clone());
-        self.metadata_cache.insert(
+        self.insert_metadata(
             key.to_string(),
             CacheMetadata {
                 expires_at,
+                updated_at: now,
                 tags: tags.clone(),
                 size: serialized.len(),
             },
@@ -201,6 +238,78 @@ impl FastCache {
                 .await?;
         }
 
+        self.enforce_limits(Some(key)).await?;
+
+        Ok(())
+    }
+
+    /// Inserts or replaces an entry's metadata and keeps `total_bytes` in step.
+    fn insert_metadata(&self, key: String, metadata: CacheMetadata) {
+        self.total_bytes.fetch_add(metadata.size, Ordering::Relaxed);
+        if let Some(old) = self.metadata_cache.insert(key, metadata) {
+            self.total_bytes.fetch_sub(old.size, Ordering::Relaxed);
+        }
+    }
+
+    /*
+     * enforce_limits
+     * Once the cache is over a configured limit, evicts entries until it is back under the low
+     * water mark. Expired entries go first, then the least recently written ones. `keep` protects
+     * the entry that was just written.
+     */
+    async fn enforce_limits(&self, keep: Option<&str>) -> CacheResult<usize> {
+        let max_entries = self.limits.max_entries.unwrap_or(usize::MAX);
+        let max_bytes = self.limits.max_bytes.unwrap_or(usize::MAX);
+
+        let mut entries = self.metadata_cache.len();
+        let mut bytes = self.total_bytes.load(Ordering::Relaxed);
+        if entries <= max_entries && bytes <= max_bytes {
+            return Ok(0);
+        }
+
+        let (max_entries, max_bytes) = (low_water(max_entries), low_water(max_bytes));
+        let now = Utc::now();
+        let mut candidates: Vec<(bool, DateTime<Utc>, String, usize)> = self
+            .metadata_cache
+            .iter()
+            .filter(|e| Some(e.key().as_str()) != keep)
+            .map(|e| {
+                let meta = e.value();
+                let live = meta.expires_at.is_none_or(|exp| exp >= now);
+                (live, meta.updated_at, e.key().clone(), meta.size)
+            })
+            .collect();
+        // expired (live = false) first, then oldest first
+        candidates.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+
+        let mut evicted = 0;
+        for (_, _, key, size) in candidates {
+            if entries <= max_entries && bytes <= max_bytes {
+                break;
+            }
+            self.delete(&key).await?;
+            entries = entries.saturating_sub(1);
+            bytes = bytes.saturating_sub(size);
+            evicted += 1;
+        }
+
+        if evicted > 0 {
+            info!("[cache] Evicted {} entries to stay within limits", evicted);
+        }
+        Ok(evicted)
+    }
+
+    /*
+     * compact
+     * Checkpoints the SQLite WAL back into the main database file and rebuilds the file so
+     * deleted entries actually give their space back.
+     */
+    pub async fn compact(&self) -> CacheResult<()> {
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.db_pool)
+            .await?;
+        sqlx::query("VACUUM").execute(&self.db_pool).await?;
+        info!("[cache] Compacted cache database");
         Ok(())
     }
 
@@ -230,7 +339,9 @@ impl FastCache {
 
     pub async fn delete(&self, key: &str) -> CacheResult<bool> {
         let existed = self.memory_cache.remove(key).is_some();
-        self.metadata_cache.remove(key);
+        if let Some((_, old)) = self.metadata_cache.remove(key) {
+            self.total_bytes.fetch_sub(old.size, Ordering::Relaxed);
+        }
 
         if self.write_through {
             sqlx::query("DELETE FROM cache_entries WHERE key = ?")
@@ -289,11 +400,7 @@ impl FastCache {
 
     pub fn cache_stats(&self) -> CacheStats {
         let total_entries = self.memory_cache.len();
-        let total_size: usize = self
-            .metadata_cache
-            .iter()
-            .map(|entry| entry.value().size)
-            .sum();
+        let total_size = self.total_bytes.load(Ordering::Relaxed);
 
         CacheStats {
             total_entries,
@@ -302,6 +409,11 @@ impl FastCache {
         }
     }
 
+    /// The database behind the cache, lib::spool keeps its queue in it.
+    pub fn pool(&self) -> &SqlitePool {
+        &self.db_pool
+    }
+
     async fn persist_to_disk(
         &self,
         key: &str,
@@ -462,14 +574,38 @@ impl FastCache {
         // Clear current in-memory state
         self.memory_cache.clear();
         self.metadata_cache.clear();
+        self.total_bytes.store(0, Ordering::Relaxed);
         // Reload from disk
         self.load_from_disk().await
     }
 }
 
-// Background cleanup task
+// Background compaction task, an interval of 0 turns it off
+pub async fn start_compaction_task(cache: Arc<FastCache>, interval: Duration) {
+    if interval.is_zero() {
+        info!("[cache] Compaction disabled");
+        return;
+    }
+    let mut interval_timer = tokio::time::interval(interval);
+    // the first tick completes immediately, skip it so we don't vacuum right at startup
+    interval_timer.tick().await;
+
+    loop {
+        interval_timer.tick().await;
+
+        if let Err(e) = cache.compact().await {
+            log::error!("Error during cache compaction: {}", e);
+        }
+    }
+}
+
+// Background cleanup task, an interval of 0 turns it off
 pub async fn start_cleanup_task(cache: Arc<FastCache>, interval: Duration) {
-    let mut interval_timer = tokio::time::interval(Duration::from_secs(interval.as_secs()));
+    if interval.is_zero() {
+        info!("[cache] Cleanup of expired entries disabled");
+        return;
+    }
+    let mut interval_timer = tokio::time::interval(interval);
 
     loop {
         interval_timer.tick().await;
@@ -1,16 +1,12 @@
-use crate::lib::collectors::CollectorRequest;
-use crate::proto;
-use crate::proto::monitor::system_monitor_client::SystemMonitorClient;
-use log::{error, info};
+use log::info;
 use serde::Deserialize;
 use std::fs;
-use std::time::Duration;
-use tokio::time::timeout;
-use tonic::codegen::InterceptedService;
-use tonic::metadata::MetadataValue;
-use tonic::service::Interceptor;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
 use tonic::transport::{Certificate, ClientTlsConfig, Identity};
-use tonic::{Code, Status};
+
+pub use lynx_agent_sdk::client::AuthInterceptor;
 
 pub async fn tls_config() -> Result<ClientTlsConfig, Box<dyn std::error::Error>> {
     let current_dir = std::env::current_dir()?;
@@ -50,185 +46,229 @@ pub async fn tls_config() -> Result<ClientTlsConfig, Box<dyn std::error::Error>>
     Ok(client_tls_config)
 }
 
+/*
+ * install_crypto_policy
+ * tonic and the websocket server both build their rustls configs from the process wide crypto
+ * provider, so the minimum version and cipher suites are enforced by installing a provider that
+ * only offers the allowed suites. Must run before any TLS config is built.
+ */
+pub fn install_crypto_policy(tls: &TlsConfig) -> Result<(), Box<dyn std::error::Error>> {
+    use rustls::crypto::{aws_lc_rs, CryptoProvider};
+
+    let tls13_only = match tls.min_version.as_str() {
+        "1.2" => false,
+        "1.3" => true,
+        other => return Err(format!("Invalid min_version {other}, expected 1.2 or 1.3").into()),
+    };
+
+    let defaults = aws_lc_rs::default_provider().cipher_suites;
+    for name in &tls.cipher_suites {
+        if !defaults.iter().any(|s| format!("{:?}", s.suite()) == *name) {
+            return Err(format!("Unknown cipher suite {name}").into());
+        }
+    }
+    let cipher_suites: Vec<_> = defaults
+        .into_iter()
+        .filter(|s| !tls13_only || s.version() == &rustls::version::TLS13)
+        .filter(|s| {
+            tls.cipher_suites.is_empty()
+                || tls
+                    .cipher_suites
+                    .iter()
+                    .any(|name| format!("{:?}", s.suite()) == *name)
+        })
+        .collect();
+    if cipher_suites.is_empty() {
+        return Err("No cipher suite left that satisfies the TLS policy".into());
+    }
+
+    info!(
+        "[agent] Minimum TLS {}, {} cipher suite(s) allowed",
+        tls.min_version,
+        cipher_suites.len()
+    );
+    CryptoProvider {
+        cipher_suites,
+        ..aws_lc_rs::default_provider()
+    }
+    .install_default()
+    .map_err(|_| "A TLS crypto provider was already installed".into())
+}
+
+/*
+ * ensure_local_plaintext
+ * `--insecure` is meant for running the stack on a dev machine, refuse anything that would send
+ * the agent key over the network unencrypted.
+ */
+pub fn ensure_local_plaintext(server_url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let url = url::Url::parse(server_url)?;
+    if url.scheme() != "http" {
+        return Err("--insecure requires an http:// server_url".into());
+    }
+    match url.host() {
+        Some(url::Host::Domain("localhost")) => Ok(()),
+        Some(url::Host::Ipv4(ip)) if ip.is_loopback() => Ok(()),
+        Some(url::Host::Ipv6(ip)) if ip.is_loopback() => Ok(()),
+        _ => Err("--insecure only allows connecting to a hub on localhost".into()),
+    }
+}
+
+/// Builds the hub endpoint, without TLS when running with `--insecure`.
+pub fn make_endpoint(
+    config: &LynxConfig,
+    tls: Option<ClientTlsConfig>,
+) -> Result<tonic::transport::Endpoint, Box<dyn std::error::Error>> {
+    let tls = tls.map(|tls| match &config.core.tls_name {
+        Some(name) => tls.domain_name(name),
+        None => tls,
+    });
+    Ok(lynx_agent_sdk::client::endpoint(
+        &config.core.server_url,
+        tls,
+    )?)
+}
+
+const EXPIRY_CERTS: [&str; 2] = ["docker-agent.crt", "ca.crt"];
+
+/// Modification times of EXPIRY_CERTS and the earliest expiry found in them, as unix seconds.
+type ExpiryCache = (Vec<Option<SystemTime>>, Option<i64>);
+
+static CERT_EXPIRY: Mutex<Option<ExpiryCache>> = Mutex::new(None);
+
+/*
+ * cert_expiry_days
+ * Returns the days left until the agent's client certificate (or the CA it chains to) expires,
+ * whichever comes first. Negative once expired, None if the certificates can't be read. The
+ * certificates are only parsed again once one of them changed on disk, e.g. after a renewal.
+ */
+pub fn cert_expiry_days() -> Option<f64> {
+    let certs_dir = std::env::current_dir().ok()?.join("certs");
+    let modified: Vec<Option<SystemTime>> = EXPIRY_CERTS
+        .iter()
+        .map(|file| {
+            fs::metadata(certs_dir.join(file))
+                .and_then(|m| m.modified())
+                .ok()
+        })
+        .collect();
+
+    let mut cached = CERT_EXPIRY.lock().unwrap_or_else(|e| e.into_inner());
+    let not_after = match cached.as_ref() {
+        Some((seen, not_after)) if *seen == modified => *not_after,
+        _ => {
+            let not_after = earliest_not_after(&certs_dir);
+            *cached = Some((modified, not_after));
+            not_after
+        }
+    };
+    not_after.map(|not_after| (not_after - chrono::Utc::now().timestamp()) as f64 / 86_400.0)
+}
+
+fn earliest_not_after(certs_dir: &Path) -> Option<i64> {
+    EXPIRY_CERTS
+        .iter()
+        .filter_map(|file| fs::read(certs_dir.join(file)).ok())
+        .flat_map(|pem| {
+            x509_parser::pem::Pem::iter_from_buffer(&pem)
+                .filter_map(|p| p.ok())
+                .filter_map(|p| {
+                    p.parse_x509()
+                        .ok()
+                        .map(|cert| cert.validity().not_after.timestamp())
+                })
+                .collect::<Vec<_>>()
+        })
+        .min()
+}
+
 #[derive(Deserialize, Debug)]
 pub struct CoreConfig {
+    /// Found on the local network when left empty, see [discovery]
+    #[serde(default)]
     pub server_url: String,
+    /// Name the hub certificate is checked against when it differs from the server_url host,
+    /// set by discovery
+    #[serde(skip)]
+    pub tls_name: Option<String>,
+    /// Filled in by enrollment when left empty
+    #[serde(default)]
     pub agent_key: String,
+    /// Reads the key from a root-only file, systemd credential or the OS keyring instead
+    pub agent_key_source: Option<crate::lib::credentials::KeySource>,
 }
 
 #[derive(Deserialize, Debug)]
-pub struct LynxConfig {
-    pub core: CoreConfig,
+#[serde(default)]
+pub struct CacheConfig {
+    pub database_url: String,
+    pub max_entries: Option<usize>,
+    pub max_bytes: Option<usize>,
+    pub cleanup_interval_secs: u64,
+    pub compact_interval_secs: u64,
 }
 
-pub struct AuthInterceptor {
-    pub agent_key: String,
-}
-
-impl Interceptor for AuthInterceptor {
-    fn call(&mut self, mut request: tonic::Request<()>) -> Result<tonic::Request<()>, Status> {
-        request.metadata_mut().insert(
-            "x-agent-key",
-            MetadataValue::try_from(&self.agent_key).unwrap(),
-        );
-        Ok(request)
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            database_url: "sqlite://lynx-cache.db?mode=rwc".to_string(),
+            max_entries: Some(10_000),
+            max_bytes: Some(16 * 1024 * 1024),
+            cleanup_interval_secs: 300,
+            compact_interval_secs: 3600,
+        }
     }
 }
 
-pub struct GrpcClient {
-    client: SystemMonitorClient<InterceptedService<tonic::transport::Channel, AuthInterceptor>>,
-    config: LynxConfig,
-    client_tls_config: tonic::transport::ClientTlsConfig,
+/// Optional `[tls]` section. The CRLs are only checked for websocket clients, tonic has no hook
+/// to check the hub's certificate against them.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub min_version: String,
+    /// rustls suite names, e.g. "TLS13_AES_256_GCM_SHA384". Empty allows all of them.
+    pub cipher_suites: Vec<String>,
+    pub crl_files: Vec<std::path::PathBuf>,
 }
 
-impl GrpcClient {
-    pub fn new(
-        client: SystemMonitorClient<InterceptedService<tonic::transport::Channel, AuthInterceptor>>,
-        config: LynxConfig,
-        client_tls_config: tonic::transport::ClientTlsConfig,
-    ) -> Self {
+impl Default for TlsConfig {
+    fn default() -> Self {
         Self {
-            client,
-            config,
-            client_tls_config,
-        }
-    }
-
-    pub async fn send_request<T, F>(
-        &mut self,
-        request: T,
-        operation: F,
-    ) -> Result<(), Box<dyn std::error::Error>>
-    where
-        F: for<'a> FnOnce(
-            &'a mut SystemMonitorClient<
-                InterceptedService<tonic::transport::Channel, AuthInterceptor>,
-            >,
-            tonic::Request<T>,
-        ) -> std::pin::Pin<
-            Box<
-                dyn std::future::Future<
-                        Output = Result<tonic::Response<proto::monitor::Response>, tonic::Status>,
-                    > + Send
-                    + 'a,
-            >,
-        >,
-    {
-        let rpc_timeout = Duration::from_secs(10);
-        let request = tonic::Request::new(request);
-
-        match timeout(rpc_timeout, operation(&mut self.client, request)).await {
-            Ok(Ok(response)) => {
-                let resp = response.into_inner();
-                if resp.status == "200" {
-                    info!("[agent] Request successful");
-                } else {
-                    info!("[agent] Request failed: {:?}", resp.message);
-                }
-                Ok(())
-            }
-            Ok(Err(e)) => {
-                error!("[agent] Error sending request: {}", e);
-                if e.code() == Code::Unavailable || e.code() == Code::DeadlineExceeded {
-                    self.reconnect().await?;
-                }
-                Ok(())
-            }
-            Err(_) => {
-                error!("[agent] Request timeout; reconnecting");
-                self.reconnect().await?;
-                Ok(())
-            }
+            min_version: "1.2".to_string(),
+            cipher_suites: Vec::new(),
+            crl_files: Vec::new(),
         }
     }
-
-    async fn reconnect(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let make_client =
-            |config: &LynxConfig,
-             tls: tonic::transport::ClientTlsConfig|
-             -> Result<tonic::transport::Endpoint, Box<dyn std::error::Error>> {
-                let endpoint =
-                    tonic::transport::Endpoint::from_shared(config.core.server_url.clone())?
-                        .tls_config(tls)?
-                        .tcp_keepalive(Some(Duration::from_secs(30)))
-                        .http2_keep_alive_interval(Duration::from_secs(15))
-                        .keep_alive_timeout(Duration::from_secs(5))
-                        .keep_alive_while_idle(true)
-                        .connect_timeout(Duration::from_secs(10));
-                Ok(endpoint)
-            };
-        let endpoint = make_client(&self.config, self.client_tls_config.clone())?;
-        let channel = endpoint.connect().await?;
-        self.client = SystemMonitorClient::with_interceptor(
-            channel,
-            AuthInterceptor {
-                agent_key: self.config.core.agent_key.clone(),
-            },
-        );
-        Ok(())
-    }
 }
 
-pub async fn handle_collector_requests(
-    grpc_client: &mut GrpcClient,
-    request: CollectorRequest,
-) -> Result<(), Box<dyn std::error::Error>> {
-    match request {
-        CollectorRequest::SystemInfo(info) => {
-            info!("[agent] Sending system info to hub...");
-            grpc_client
-                .send_request(info, move |client, req| {
-                    Box::pin(client.get_system_info(req))
-                })
-                .await
-        }
-        CollectorRequest::Metrics(metrics) => {
-            info!("[agent] Sending metrics to hub...");
-            grpc_client
-                .send_request(metrics, move |client, req| {
-                    Box::pin(client.report_metrics(req))
-                })
-                .await
-        }
-        CollectorRequest::Systemctl(systemctl) => {
-            info!("[agent] Sending systemctl services to hub...");
-            grpc_client
-                .send_request(systemctl, move |client, req| {
-                    Box::pin(client.report_systemctl(req))
-                })
-                .await
-        }
-        CollectorRequest::GpuInfo(gpu_info) => {
-            info!("[agent] Sending GPU info to hub...");
-            grpc_client
-                .send_request(gpu_info, move |client, req| {
-                    Box::pin(client.register_gp_us(req))
-                })
-                .await
-        }
-        CollectorRequest::GpuMetrics(gpu_metrics) => {
-            info!("[agent] Sending GPU metrics to hub...");
-            grpc_client
-                .send_request(gpu_metrics, move |client, req| {
-                    Box::pin(client.report_gpu_metrics(req))
-                })
-                .await
-        }
-        CollectorRequest::ContainerInfo(container_info) => {
-            info!("[agent] Sending container info to hub...");
-            grpc_client
-                .send_request(container_info, move |client, req| {
-                    Box::pin(client.register_containers(req))
-                })
-                .await
-        }
-        CollectorRequest::ContainerMetrics(container_metrics) => {
-            info!("[agent] Sending container metrics to hub...");
-            grpc_client
-                .send_request(container_metrics, move |client, req| {
-                    Box::pin(client.report_container_metrics(req))
-                })
-                .await
-        }
-    }
+#[derive(Deserialize, Debug)]
+pub struct LynxConfig {
+    pub core: CoreConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub spool: crate::lib::spool::SpoolConfig,
+    #[serde(default)]
+    pub tls: TlsConfig,
+    #[serde(default)]
+    pub disks: crate::lib::system_info::DiskConfig,
+    /// Free-form `[tags]` reported with the system info, e.g. role = "db"
+    #[serde(default)]
+    pub tags: std::collections::HashMap<String, String>,
+    pub enroll: Option<crate::lib::enroll::EnrollConfig>,
+    #[serde(default)]
+    pub update: crate::lib::update::UpdateConfig,
+    #[serde(default)]
+    pub diagnostics: crate::lib::diagnostics::DiagnosticsConfig,
+    #[serde(default)]
+    pub scripts: crate::lib::scripts::ScriptsConfig,
+    #[serde(default)]
+    pub commands: crate::lib::websocket::CommandLimits,
+    #[serde(default)]
+    pub upload: crate::lib::upload::UploadConfig,
+    #[serde(default)]
+    pub tunnels: crate::lib::tunnel::TunnelConfig,
+    #[serde(default)]
+    pub authorization: crate::lib::authorization::AuthorizationConfig,
+    #[serde(default)]
+    pub discovery: crate::lib::discovery::DiscoveryConfig,
 }
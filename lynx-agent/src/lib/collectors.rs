@@ -1,101 +1,47 @@
 use crate::lib;
 use crate::lib::cache::FastCache;
+use crate::lib::diagnostics::Diagnostics;
+use crate::lib::scripts::Scripts;
 use crate::proto::monitor::{
     ContainerInfo, ContainerMetrics, ContainerMetricsRequest, ContainerRequest, GpuMetricsRequest,
-    GpuRequest, GpuResponse, MetricsRequest, SystemInfoRequest, SystemctlRequest,
+    GpuRequest, GpuResponse,
 };
 use async_trait::async_trait;
 use bollard::query_parameters::ListContainersOptions;
+use chrono::Utc;
 use log::{error, info};
+use lynx_agent_sdk::health::HealthSender;
+use lynx_agent_sdk::remote_config::{self, ConfigReceiver};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
 use sysinfo::{System, MINIMUM_CPU_UPDATE_INTERVAL};
 use tokio::sync::mpsc;
-use tokio::time::{timeout, Instant};
-
-#[derive(Debug, thiserror::Error)]
-pub enum CollectorError {
-    #[error("Failed to collect metrics: {0}")]
-    MetricsCollectionError(String),
-    #[error("Failed to collect system info: {0}")]
-    SystemInfoCollectionError(String),
-    #[error("Failed to collect systemctl info: {0}")]
-    SystemctlCollectionError(String),
-
-    #[error("Channel send error: {0}")]
-    Channel(#[from] tokio::sync::mpsc::error::TrySendError<CollectorRequest>),
-}
-#[derive(Debug)]
-pub enum CollectorRequest {
-    Metrics(MetricsRequest),
-    SystemInfo(SystemInfoRequest),
-    Systemctl(SystemctlRequest),
-    GpuInfo(GpuRequest),
-    GpuMetrics(GpuMetricsRequest),
-    ContainerInfo(ContainerRequest),
-    ContainerMetrics(ContainerMetricsRequest),
-}
-
-#[async_trait]
-pub trait Collector: Send + Sync {
-    fn name(&self) -> &'static str;
 
-    fn interval(&self) -> u64;
-
-    async fn collect(
-        &self,
-        tx: mpsc::Sender<CollectorRequest>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>>;
-}
+pub use lynx_agent_sdk::collector::{
+    Collector, CollectorError, CollectorManager, CollectorRequest,
+};
 
-pub struct CollectorManager {
-    collectors: Vec<Arc<dyn Collector>>,
+pub struct MetricsCollector {
+    /// Kept across collections, see system_info::new_system
+    system: tokio::sync::Mutex<System>,
+    rates: tokio::sync::Mutex<lib::system_info::Rates>,
+    smart: tokio::sync::Mutex<lib::smart::SmartReader>,
+    config: ConfigReceiver,
+    scripts: Scripts,
 }
 
-impl CollectorManager {
-    pub fn new() -> Self {
+impl MetricsCollector {
+    pub fn new(config: ConfigReceiver, scripts: Scripts) -> Self {
         Self {
-            collectors: Vec::new(),
-        }
-    }
-
-    pub fn register<C: Collector + 'static>(&mut self, collector: C) {
-        self.collectors.push(Arc::new(collector));
-    }
-
-    pub async fn start_all(&self, tx: mpsc::Sender<CollectorRequest>) {
-        for collector in &self.collectors {
-            let tx = tx.clone();
-            let collector = Arc::clone(collector);
-
-            tokio::spawn(async move {
-                info!("[collector] Starting {} collector", collector.name());
-                let mut interval = tokio::time::interval(Duration::from_secs(collector.interval()));
-
-                loop {
-                    interval.tick().await;
-                    let start = Instant::now();
-                    match collector.collect(tx.clone()).await {
-                        Ok(_) => {
-                            let elapsed = start.elapsed();
-                            info!(
-                                "[{}][{}s] collection completed",
-                                collector.name(),
-                                elapsed.as_secs_f32().round()
-                            );
-                        }
-                        Err(e) => {
-                            error!("[collector] {} collection failed: {}", collector.name(), e);
-                        }
-                    }
-                }
-            });
+            system: tokio::sync::Mutex::new(lib::system_info::new_system()),
+            rates: Default::default(),
+            smart: Default::default(),
+            config,
+            scripts,
         }
     }
 }
 
-pub struct MetricsCollector;
 #[async_trait]
 impl Collector for MetricsCollector {
     fn name(&self) -> &'static str {
@@ -111,36 +57,68 @@ impl Collector for MetricsCollector {
         tx: mpsc::Sender<CollectorRequest>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
         // collect system metrics and send
-        let mut sys = System::new_all();
+        let mut system = self.system.lock().await;
+        let mut rates = self.rates.lock().await;
+        // only matters on the first run, later ones are an interval apart
         tokio::time::sleep(MINIMUM_CPU_UPDATE_INTERVAL).await;
-        let metrics = lib::system_info::collect_metrics(&mut sys).await;
-        tx.send(CollectorRequest::Metrics(metrics))
+        let mut metrics = lib::system_info::collect_metrics(&mut system, &mut rates).await;
+        drop((system, rates));
+        let (targets, gpu, containers, smart) = {
+            let config = self.config.borrow();
+            (
+                if remote_config::feature_enabled(&config, "probes") {
+                    config.probe_targets.clone()
+                } else {
+                    Vec::new()
+                },
+                remote_config::feature_enabled(&config, "gpu"),
+                remote_config::feature_enabled(&config, "containers"),
+                remote_config::feature_enabled(&config, "smart"),
+            )
+        };
+        metrics.probe_results = lib::probes::run_probes(&targets).await;
+        if smart {
+            metrics.smart_devices = self.smart.lock().await.read_if_due().await;
+        }
+        metrics.custom_metrics = self.scripts.derive(&metrics);
+        tx.send(CollectorRequest::Metrics(Box::new(metrics)))
             .await
             .map_err(|e| CollectorError::Channel(e.into()))?;
 
         // collect GPU inventory + metrics and send if present
-        let gpu_manager = lib::gpu::GPUManager::new();
-        match gpu_manager.start_collection().await {
-            Ok((gpu_info_opt, gpu_metrics)) => {
-                if let Some(info) = gpu_info_opt {
-                    tx.send(CollectorRequest::GpuInfo(GpuRequest { gpus: info }))
+        if gpu {
+            let gpu_manager = lib::gpu::GPUManager::new();
+            match gpu_manager.start_collection().await {
+                Ok((gpu_info_opt, gpu_metrics)) => {
+                    // stamped here so spooled samples keep their time when replayed
+                    let collected_at_ms = Some(Utc::now().timestamp_millis());
+                    if let Some(info) = gpu_info_opt {
+                        tx.send(CollectorRequest::GpuInfo(GpuRequest { gpus: info }))
+                            .await
+                            .map_err(|e| CollectorError::Channel(e.into()))
+                            .unwrap_or_else(|e| {
+                                error!("[collector] failed to send GpuInfo: {}", e)
+                            });
+                    }
+
+                    if !gpu_metrics.is_empty() {
+                        tx.send(CollectorRequest::GpuMetrics(GpuMetricsRequest {
+                            gpu_metrics,
+                            collected_at_ms,
+                        }))
                         .await
                         .map_err(|e| CollectorError::Channel(e.into()))
-                        .unwrap_or_else(|e| error!("[collector] failed to send GpuInfo: {}", e));
+                        .unwrap_or_else(|e| error!("[collector] failed to send GpuMetrics: {}", e));
+                    }
                 }
-
-                if !gpu_metrics.is_empty() {
-                    tx.send(CollectorRequest::GpuMetrics(GpuMetricsRequest {
-                        gpu_metrics,
-                    }))
-                    .await
-                    .map_err(|e| CollectorError::Channel(e.into()))
-                    .unwrap_or_else(|e| error!("[collector] failed to send GpuMetrics: {}", e));
+                Err(e) => {
+                    error!("Failed to collect GPU metrics: {}", e);
                 }
             }
-            Err(e) => {
-                error!("Failed to collect GPU metrics: {}", e);
-            }
+        }
+
+        if !containers {
+            return Ok(());
         }
 
         // collect Docker metrics for running containers
@@ -179,7 +157,10 @@ impl Collector for MetricsCollector {
                 })?;
             if !container_metrics.is_empty() {
                 tx.send(CollectorRequest::ContainerMetrics(
-                    ContainerMetricsRequest { container_metrics },
+                    ContainerMetricsRequest {
+                        container_metrics,
+                        collected_at_ms: Some(Utc::now().timestamp_millis()),
+                    },
                 ))
                 .await
                 .map_err(|e| CollectorError::Channel(e.into()))
@@ -191,7 +172,11 @@ impl Collector for MetricsCollector {
     }
 }
 
-pub struct SystemInfoCollector;
+pub struct SystemInfoCollector {
+    system: tokio::sync::Mutex<System>,
+    tags: HashMap<String, String>,
+    config: ConfigReceiver,
+}
 #[async_trait]
 impl Collector for SystemInfoCollector {
     fn name(&self) -> &'static str {
@@ -206,13 +191,17 @@ impl Collector for SystemInfoCollector {
         &self,
         tx: mpsc::Sender<CollectorRequest>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let mut sys = System::new_all();
-        let system_info = lib::system_info::collect_system_info(&mut sys).await;
-        let request = CollectorRequest::SystemInfo(system_info);
+        let mut system = self.system.lock().await;
+        let system_info = lib::system_info::collect_system_info(&mut system, &self.tags).await;
+        drop(system);
+        let request = CollectorRequest::SystemInfo(Box::new(system_info));
         tx.send(request)
             .await
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync + 'static>)?;
 
+        if !remote_config::feature_enabled(&self.config.borrow(), "containers") {
+            return Ok(());
+        }
         let docker_manager = lib::docker::DockerManager::new().map_err(|e| {
             CollectorError::SystemInfoCollectionError(format!(
                 "Failed to build docker manager: {}",
@@ -237,8 +226,17 @@ impl Collector for SystemInfoCollector {
     }
 }
 
+/// Every this many runs the collector lists all units, hourly at the default interval.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const SYSTEMCTL_FULL_SYNC_RUNS: u64 = 12;
+
 #[cfg(target_os = "linux")]
-pub struct SystemctlCollector;
+pub struct SystemctlCollector {
+    cache: Arc<FastCache>,
+    /// Units listed by the previous run, the ones missing from the next are reported removed
+    known: tokio::sync::Mutex<std::collections::HashSet<String>>,
+    runs: std::sync::atomic::AtomicU64,
+}
 #[cfg(target_os = "linux")]
 #[async_trait]
 impl Collector for SystemctlCollector {
@@ -254,7 +252,20 @@ impl Collector for SystemctlCollector {
         &self,
         tx: mpsc::Sender<CollectorRequest>,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-        let systemctl_info = lib::system_info::collect_systemctl_services().await;
+        // the first run is a full sync, so the hub drops units removed while the agent was down
+        let run = self.runs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let full_sync = run.is_multiple_of(SYSTEMCTL_FULL_SYNC_RUNS);
+        let mut known = self.known.lock().await;
+        let systemctl_info =
+            lib::system_info::collect_systemctl_services(&self.cache, &mut known, full_sync).await;
+        drop(known);
+        if systemctl_info.services.is_empty()
+            && systemctl_info.removed.is_empty()
+            && !systemctl_info.full_sync
+        {
+            info!("[collector] No systemctl changes since last collection");
+            return Ok(());
+        }
         let request = CollectorRequest::Systemctl(systemctl_info);
         tx.send(request)
             .await
@@ -262,14 +273,81 @@ impl Collector for SystemctlCollector {
     }
 }
 
-pub async fn start_collectors(tx: mpsc::Sender<CollectorRequest>) {
-    let mut manager = CollectorManager::new();
+/// launchd jobs on macOS, reported to the hub like systemd units.
+#[cfg(target_os = "macos")]
+pub struct LaunchdCollector {
+    cache: Arc<FastCache>,
+    /// Jobs listed by the previous run, the ones missing from the next are reported removed
+    known: tokio::sync::Mutex<std::collections::HashSet<String>>,
+    runs: std::sync::atomic::AtomicU64,
+}
+#[cfg(target_os = "macos")]
+#[async_trait]
+impl Collector for LaunchdCollector {
+    fn name(&self) -> &'static str {
+        "LaunchdCollector"
+    }
 
-    manager.register(MetricsCollector);
-    manager.register(SystemInfoCollector);
+    fn interval(&self) -> u64 {
+        300
+    }
 
-    #[cfg(target_os = "linux")]
-    manager.register(SystemctlCollector);
+    async fn collect(
+        &self,
+        tx: mpsc::Sender<CollectorRequest>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let run = self.runs.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let full_sync = run.is_multiple_of(SYSTEMCTL_FULL_SYNC_RUNS);
+        let mut known = self.known.lock().await;
+        let launchd_info =
+            lib::launchd::collect_launchd_services(&self.cache, &mut known, full_sync).await;
+        drop(known);
+        if launchd_info.services.is_empty()
+            && launchd_info.removed.is_empty()
+            && !launchd_info.full_sync
+        {
+            info!("[collector] No launchd changes since last collection");
+            return Ok(());
+        }
+        let request = CollectorRequest::Systemctl(launchd_info);
+        tx.send(request)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync + 'static>)
+    }
+}
+
+pub async fn start_collectors(
+    tx: mpsc::Sender<CollectorRequest>,
+    cache: Arc<FastCache>,
+    tags: HashMap<String, String>,
+    config: ConfigReceiver,
+    health: HealthSender,
+    scripts: Scripts,
+) {
+    let mut manager = CollectorManager::new().with_observer(Arc::new(Diagnostics));
+
+    manager.register(MetricsCollector::new(config.clone(), scripts));
+    manager.register(SystemInfoCollector {
+        system: tokio::sync::Mutex::new(lib::system_info::new_system()),
+        tags,
+        config: config.clone(),
+    });
 
-    manager.start_all(tx).await;
+    #[cfg(target_os = "linux")]
+    manager.register(SystemctlCollector {
+        cache,
+        known: Default::default(),
+        runs: Default::default(),
+    });
+    #[cfg(target_os = "macos")]
+    manager.register(LaunchdCollector {
+        cache,
+        known: Default::default(),
+        runs: Default::default(),
+    });
+    // no service manager the hub can list elsewhere
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    drop(cache);
+
+    manager.start_all(tx, config, health).await;
 }
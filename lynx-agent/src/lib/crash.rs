@@ -0,0 +1,173 @@
+use crate::lib::client::AuthInterceptor;
+use crate::proto::monitor::control_client::ControlClient;
+use crate::proto::monitor::AgentEvent;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/*
+ * Crash reports
+ * A panic hook writes every panic, with a captured backtrace, to crashes/ in the working
+ * directory before the default hook runs. The process may not survive it (or the service
+ * manager restarts it), so nothing is sent from the hook: once connected again the agent
+ * sends each file over ReportAgentEvent and deletes it when the hub accepted it. Panics the
+ * collector supervisor recovers from are written as well, they are sent on the next start.
+ */
+
+pub const CRASH_DIR: &str = "crashes";
+
+/// Reports kept on disk, further panics are only logged until they were sent.
+const MAX_PENDING: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug)]
+struct CrashReport {
+    time: i64,
+    version: String,
+    thread: String,
+    location: String,
+    message: String,
+    backtrace: String,
+}
+
+impl From<CrashReport> for AgentEvent {
+    fn from(report: CrashReport) -> Self {
+        AgentEvent {
+            kind: "panic".to_string(),
+            time: report.time,
+            version: report.version,
+            thread: report.thread,
+            location: report.location,
+            message: report.message,
+            backtrace: report.backtrace,
+        }
+    }
+}
+
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        persist(info);
+        default_hook(info);
+    }));
+}
+
+fn panic_message(info: &PanicHookInfo<'_>) -> String {
+    if let Some(message) = info.payload().downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = info.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panic with a non-string payload".to_string()
+    }
+}
+
+/// Runs inside the panic hook, so every failure is logged and swallowed.
+fn persist(info: &PanicHookInfo<'_>) {
+    let dir = Path::new(CRASH_DIR);
+    if pending(dir).len() >= MAX_PENDING {
+        error!(
+            "[crash] {} crash reports waiting already, not keeping this one",
+            MAX_PENDING
+        );
+        return;
+    }
+    let now = chrono::Utc::now();
+    let report = CrashReport {
+        time: now.timestamp(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        thread: std::thread::current()
+            .name()
+            .unwrap_or("unnamed")
+            .to_string(),
+        location: info
+            .location()
+            .map(|l| format!("{}:{}", l.file(), l.line()))
+            .unwrap_or_default(),
+        message: panic_message(info),
+        backtrace: Backtrace::force_capture().to_string(),
+    };
+    let path = dir.join(format!(
+        "panic-{}-{}.json",
+        now.timestamp_millis(),
+        std::process::id()
+    ));
+    let result = fs::create_dir_all(dir).and_then(|_| {
+        let json = serde_json::to_vec(&report).map_err(std::io::Error::other)?;
+        fs::write(&path, json)
+    });
+    match result {
+        Ok(()) => error!(
+            "[crash] Panic at {}, report kept in {}",
+            report.location,
+            path.display()
+        ),
+        Err(e) => error!("[crash] Failed to keep the crash report: {}", e),
+    }
+}
+
+/// Crash reports waiting to be sent, oldest first.
+fn pending(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    files
+}
+
+/// Sends the crash reports left by earlier runs, stops at the first one the hub didn't take.
+pub async fn report_pending(
+    mut client: ControlClient<InterceptedService<Channel, AuthInterceptor>>,
+) {
+    let files = pending(Path::new(CRASH_DIR));
+    if files.is_empty() {
+        return;
+    }
+    info!("[crash] Reporting {} crash reports to the hub", files.len());
+    for path in files {
+        let report = match fs::read(&path).map_err(|e| e.to_string()).and_then(|data| {
+            serde_json::from_slice::<CrashReport>(&data).map_err(|e| e.to_string())
+        }) {
+            Ok(report) => report,
+            Err(e) => {
+                warn!(
+                    "[crash] Dropping unreadable crash report {}: {}",
+                    path.display(),
+                    e
+                );
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+        };
+        match client.report_agent_event(AgentEvent::from(report)).await {
+            Ok(_) => {
+                if let Err(e) = fs::remove_file(&path) {
+                    warn!("[crash] Failed to remove {}: {}", path.display(), e);
+                }
+            }
+            Err(status) if status.code() == Code::Unimplemented => {
+                info!(
+                    "[crash] Hub does not take crash reports, keeping them in {}",
+                    CRASH_DIR
+                );
+                return;
+            }
+            Err(status) => {
+                warn!(
+                    "[crash] Failed to report crash, retrying on the next start: {}",
+                    status
+                );
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use crate::lib::client::CoreConfig;
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CredentialError {
+    #[error("Invalid agent_key_source {0:?}, expected file:<path>, systemd:<name> or keyring:<service>[/<user>]")]
+    InvalidSource(String),
+    #[error("Agent key not found in {0}")]
+    NotFound(String),
+    #[error("{0:?} must only be readable by its owner (chmod 600)")]
+    InsecurePermissions(PathBuf),
+    #[error("CREDENTIALS_DIRECTORY is not set, is LoadCredential= configured for this unit?")]
+    NoCredentialsDirectory,
+    #[error("systemd credentials are read-only, provision {0:?} with LoadCredential=")]
+    ReadOnly(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Keyring error: {0}")]
+    Keyring(#[from] keyring::Error),
+}
+
+/// Where the agent key lives when it shouldn't sit in plaintext in config.toml.
+///
+/// ```toml
+/// [core]
+/// agent_key_source = "file:/etc/lynx/agent-key"   # root-only file
+/// agent_key_source = "systemd:lynx-agent-key"     # LoadCredential=lynx-agent-key:...
+/// agent_key_source = "keyring:lynx-agent"         # OS keyring, user defaults to "agent_key"
+/// ```
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "String")]
+pub enum KeySource {
+    File(PathBuf),
+    Systemd(String),
+    Keyring { service: String, user: String },
+}
+
+impl TryFrom<String> for KeySource {
+    type Error = CredentialError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || CredentialError::InvalidSource(value.clone());
+        let (scheme, rest) = value.split_once(':').ok_or_else(invalid)?;
+        if rest.is_empty() {
+            return Err(invalid());
+        }
+        match scheme {
+            "file" => Ok(KeySource::File(PathBuf::from(rest))),
+            "systemd" => Ok(KeySource::Systemd(rest.to_string())),
+            "keyring" => {
+                let (service, user) = rest.split_once('/').unwrap_or((rest, "agent_key"));
+                Ok(KeySource::Keyring {
+                    service: service.to_string(),
+                    user: user.to_string(),
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl std::fmt::Display for KeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeySource::File(path) => write!(f, "file:{}", path.display()),
+            KeySource::Systemd(name) => write!(f, "systemd:{name}"),
+            KeySource::Keyring { service, user } => write!(f, "keyring:{service}/{user}"),
+        }
+    }
+}
+
+/// Refuses key files that group or other can read, mirroring what ssh does for private keys.
+fn check_permissions(path: &Path) -> Result<(), CredentialError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(path)?.permissions().mode() & 0o077 != 0 {
+            return Err(CredentialError::InsecurePermissions(path.to_path_buf()));
+        }
+    }
+    Ok(())
+}
+
+fn read_key_file(path: &Path, source: &KeySource) -> Result<String, CredentialError> {
+    if !path.exists() {
+        return Err(CredentialError::NotFound(source.to_string()));
+    }
+    let key = fs::read_to_string(path)?.trim().to_string();
+    if key.is_empty() {
+        return Err(CredentialError::NotFound(source.to_string()));
+    }
+    Ok(key)
+}
+
+/*
+ * load_key
+ * Reads the agent key from the configured source.
+ */
+pub fn load_key(source: &KeySource) -> Result<String, CredentialError> {
+    match source {
+        KeySource::File(path) => {
+            if path.exists() {
+                check_permissions(path)?;
+            }
+            read_key_file(path, source)
+        }
+        KeySource::Systemd(name) => {
+            // systemd already restricts the credentials directory to the unit
+            let dir = std::env::var_os("CREDENTIALS_DIRECTORY")
+                .ok_or(CredentialError::NoCredentialsDirectory)?;
+            read_key_file(&Path::new(&dir).join(name), source)
+        }
+        KeySource::Keyring { service, user } => {
+            match keyring::Entry::new(service, user)?.get_password() {
+                Ok(key) => Ok(key),
+                Err(keyring::Error::NoEntry) => Err(CredentialError::NotFound(source.to_string())),
+                Err(e) => Err(e.into()),
+            }
+        }
+    }
+}
+
+/*
+ * store_key
+ * Saves a key handed out during enrollment into the configured source.
+ */
+pub fn store_key(source: &KeySource, key: &str) -> Result<(), CredentialError> {
+    match source {
+        KeySource::File(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(path, key)?;
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+            }
+            Ok(())
+        }
+        KeySource::Systemd(name) => Err(CredentialError::ReadOnly(name.clone())),
+        KeySource::Keyring { service, user } => {
+            keyring::Entry::new(service, user)?.set_password(key)?;
+            Ok(())
+        }
+    }
+}
+
+/*
+ * resolve_agent_key
+ * Fills `core.agent_key` from `agent_key_source` when one is configured. A missing key is only
+ * a warning since enrollment may be about to provision it.
+ */
+pub fn resolve_agent_key(core: &mut CoreConfig) -> Result<(), CredentialError> {
+    let Some(source) = &core.agent_key_source else {
+        return Ok(());
+    };
+    if !core.agent_key.is_empty() {
+        warn!("[agent] Both agent_key and agent_key_source are set, using {source}");
+    }
+    match load_key(source) {
+        Ok(key) => {
+            info!("[agent] Loaded agent key from {source}");
+            core.agent_key = key;
+            Ok(())
+        }
+        Err(CredentialError::NotFound(_)) => {
+            warn!("[agent] No agent key found in {source} yet");
+            core.agent_key.clear();
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,326 @@
+use crate::lib::collectors::CollectorRequest;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use lynx_agent_sdk::observer::Observer;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/*
+ * Diagnostics page
+ * With [diagnostics] enabled the agent serves a plain HTML page on 127.0.0.1 showing the last
+ * report of each kind, how every collector's last run went, the state of the hub connection and
+ * config.toml with its secrets redacted. It is meant for `curl localhost:9101` on the host,
+ * there is no authentication and it never listens on another address.
+ */
+
+/// Reports longer than this are cut on the page, systemctl lists can be large.
+const MAX_REPORT_CHARS: usize = 64 * 1024;
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Config keys whose values never show up on the page.
+const SECRET_KEYS: &[&str] = &["key", "token", "password", "secret", "community"];
+
+/// Optional `[diagnostics]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 9101,
+        }
+    }
+}
+
+#[derive(Default)]
+struct CollectorStatus {
+    last_run: Option<DateTime<Utc>>,
+    last_duration: Option<Duration>,
+    last_error: Option<(DateTime<Utc>, String)>,
+    restarts: u32,
+}
+
+#[derive(Default)]
+struct Connection {
+    server_url: String,
+    last_sent: Option<DateTime<Utc>>,
+    last_error: Option<(DateTime<Utc>, String)>,
+}
+
+#[derive(Default)]
+struct State {
+    started: Option<DateTime<Utc>>,
+    config: String,
+    collectors: BTreeMap<&'static str, CollectorStatus>,
+    reports: BTreeMap<&'static str, (DateTime<Utc>, String)>,
+    connection: Connection,
+}
+
+lazy_static::lazy_static! {
+    static ref STATE: Mutex<State> = Mutex::new(State::default());
+}
+
+fn with_state(f: impl FnOnce(&mut State)) {
+    // a panic elsewhere must not take the page down with it
+    let mut state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(&mut state);
+}
+
+/// Keeps config.toml for the page, with every secret value replaced.
+pub fn set_config(config_str: &str, server_url: &str) {
+    let config = match toml::from_str::<toml::Value>(config_str) {
+        Ok(mut value) => {
+            redact(&mut value);
+            toml::to_string_pretty(&value).unwrap_or_default()
+        }
+        Err(e) => format!("config.toml could not be parsed: {e}"),
+    };
+    with_state(|state| {
+        state.started = Some(Utc::now());
+        state.config = config;
+        state.connection.server_url = server_url.to_string();
+    });
+}
+
+fn redact(value: &mut toml::Value) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if SECRET_KEYS.iter().any(|secret| key.contains(secret)) && !value.is_table() {
+                    *value = toml::Value::String("<redacted>".to_string());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        toml::Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+pub fn collector_succeeded(collector: &'static str, duration: Duration) {
+    with_state(|state| {
+        let status = state.collectors.entry(collector).or_default();
+        status.last_run = Some(Utc::now());
+        status.last_duration = Some(duration);
+    });
+}
+
+pub fn collector_failed(collector: &'static str, error: String, restarted: bool) {
+    with_state(|state| {
+        let status = state.collectors.entry(collector).or_default();
+        let now = Utc::now();
+        status.last_run = Some(now);
+        status.last_error = Some((now, error));
+        if restarted {
+            status.restarts += 1;
+        }
+    });
+}
+
+pub fn report_collected(request: &CollectorRequest) {
+    let kind = match request {
+        CollectorRequest::Metrics(_) => "metrics",
+        CollectorRequest::SystemInfo(_) => "system_info",
+        CollectorRequest::Systemctl(_) => "systemctl",
+        CollectorRequest::GpuInfo(_) => "gpu_info",
+        CollectorRequest::GpuMetrics(_) => "gpu_metrics",
+        CollectorRequest::ContainerInfo(_) => "container_info",
+        CollectorRequest::ContainerMetrics(_) => "container_metrics",
+    };
+    let mut report = format!("{request:#?}");
+    if let Some((cut, _)) = report.char_indices().nth(MAX_REPORT_CHARS) {
+        report.truncate(cut);
+        report.push_str("\n...");
+    }
+    with_state(|state| {
+        state.reports.insert(kind, (Utc::now(), report));
+    });
+}
+
+pub fn report_sent() {
+    with_state(|state| state.connection.last_sent = Some(Utc::now()));
+}
+
+pub fn report_failed(error: String) {
+    with_state(|state| state.connection.last_error = Some((Utc::now(), error)));
+}
+
+/// Feeds the page from the collector manager and the hub connection.
+pub struct Diagnostics;
+
+impl Observer for Diagnostics {
+    fn collector_succeeded(&self, collector: &'static str, duration: Duration) {
+        collector_succeeded(collector, duration);
+    }
+
+    fn collector_failed(&self, collector: &'static str, error: String, restarted: bool) {
+        collector_failed(collector, error, restarted);
+    }
+
+    fn report_collected(&self, request: &CollectorRequest) {
+        report_collected(request);
+    }
+
+    fn report_sent(&self) {
+        report_sent();
+    }
+
+    fn report_failed(&self, error: String) {
+        report_failed(error);
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn time(time: &Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.to_rfc3339())
+        .unwrap_or_else(|| "never".to_string())
+}
+
+fn render() -> String {
+    let state = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    let mut page = String::from(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\">\
+         <meta http-equiv=\"refresh\" content=\"10\"><title>Lynx agent</title></head><body>",
+    );
+    let _ = write!(
+        page,
+        "<h1>Lynx agent {}</h1><p>Running since {}</p>",
+        env!("CARGO_PKG_VERSION"),
+        time(&state.started)
+    );
+
+    let connection = &state.connection;
+    let _ = write!(
+        page,
+        "<h2>Hub connection</h2><p>{}<br>Last report delivered: {}<br>Last error: {}</p>",
+        escape(&connection.server_url),
+        time(&connection.last_sent),
+        connection
+            .last_error
+            .as_ref()
+            .map(|(at, e)| format!("{} at {}", escape(e), at.to_rfc3339()))
+            .unwrap_or_else(|| "none".to_string())
+    );
+
+    page.push_str(
+        "<h2>Collectors</h2><table border=\"1\"><tr><th>Collector</th><th>Last run</th>\
+         <th>Duration</th><th>Last error</th><th>Restarts</th></tr>",
+    );
+    for (name, status) in &state.collectors {
+        let _ = write!(
+            page,
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            name,
+            time(&status.last_run),
+            status
+                .last_duration
+                .map(|d| format!("{:.1}s", d.as_secs_f32()))
+                .unwrap_or_default(),
+            status
+                .last_error
+                .as_ref()
+                .map(|(at, e)| format!("{} at {}", escape(e), at.to_rfc3339()))
+                .unwrap_or_default(),
+            status.restarts
+        );
+    }
+    page.push_str("</table><h2>Last reports</h2>");
+    for (kind, (at, report)) in &state.reports {
+        let _ = write!(
+            page,
+            "<h3>{} ({})</h3><pre>{}</pre>",
+            kind,
+            at.to_rfc3339(),
+            escape(report)
+        );
+    }
+    let _ = write!(
+        page,
+        "<h2>Configuration</h2><pre>{}</pre></body></html>",
+        escape(&state.config)
+    );
+    page
+}
+
+pub async fn serve(config: DiagnosticsConfig) {
+    if !config.enabled {
+        return;
+    }
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, config.port));
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("[diagnostics] Failed to listen on {}: {}", addr, e);
+            return;
+        }
+    };
+    info!(
+        "[diagnostics] Serving the diagnostics page on http://{}",
+        addr
+    );
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = handle(stream).await {
+                        warn!("[diagnostics] Failed to answer request: {}", e);
+                    }
+                });
+            }
+            Err(e) => warn!("[diagnostics] Failed to accept connection: {}", e),
+        }
+    }
+}
+
+async fn handle(mut stream: TcpStream) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let read = match tokio::time::timeout(READ_TIMEOUT, stream.read(&mut buf)).await {
+            Ok(read) => read?,
+            Err(_) => return Ok(()),
+        };
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/")) => ("200 OK", render()),
+        (Some("GET"), _) => ("404 Not Found", "Not found".to_string()),
+        _ => ("405 Method Not Allowed", "Method not allowed".to_string()),
+    };
+    let content_type = if status.starts_with("200") {
+        "text/html; charset=utf-8"
+    } else {
+        "text/plain"
+    };
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
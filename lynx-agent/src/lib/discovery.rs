@@ -0,0 +1,119 @@
+use crate::lib::client::LynxConfig;
+use log::{info, warn};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+/*
+ * Hub discovery
+ * Agents without a server_url look for a hub advertising `_lynx-hub._tcp` on the LAN (see the
+ * hub's MDNS_ADVERTISE) and connect to the first one that answers. The connection goes to the
+ * resolved address while TLS checks the certificate against the `tls_name` the hub announces,
+ * so the hub still has to present a certificate from our CA: announcing a service on the
+ * network is not enough to receive the agent key. A configured server_url always wins.
+ */
+
+const SERVICE_TYPE: &str = "_lynx-hub._tcp.local.";
+
+#[derive(Error, Debug)]
+pub enum DiscoveryError {
+    #[error("mDNS error: {0}")]
+    Mdns(#[from] mdns_sd::Error),
+    #[error("No server_url configured and hub discovery is disabled")]
+    Disabled,
+    #[error("No hub answered on the local network within {0}s")]
+    NotFound(u64),
+}
+
+/// Optional `[discovery]` section.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    /// Look for the hub when server_url is empty
+    pub enabled: bool,
+    pub timeout_secs: u64,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_secs: 10,
+        }
+    }
+}
+
+/// Where to reach a hub found on the network.
+#[derive(Debug, Clone)]
+pub struct DiscoveredHub {
+    pub server_url: String,
+    /// Name the hub certificate is checked against
+    pub tls_name: Option<String>,
+}
+
+/// IPv4 preferred, link-local IPv6 addresses would need a scope the url can't carry.
+fn hub_from(info: &ServiceInfo) -> Option<DiscoveredHub> {
+    let host = match info.get_addresses_v4().into_iter().next() {
+        Some(ip) => ip.to_string(),
+        None => info
+            .get_addresses()
+            .iter()
+            .find(|ip| !ip.is_loopback())
+            .map(|ip| format!("[{ip}]"))?,
+    };
+    Some(DiscoveredHub {
+        server_url: format!("https://{host}:{}", info.get_port()),
+        tls_name: info
+            .get_property_val_str("tls_name")
+            .filter(|name| !name.is_empty())
+            .map(str::to_string),
+    })
+}
+
+/// Browses for the hub until one resolves or the timeout passes.
+pub async fn discover(config: &DiscoveryConfig) -> Result<DiscoveredHub, DiscoveryError> {
+    let daemon = ServiceDaemon::new()?;
+    let events = daemon.browse(SERVICE_TYPE)?;
+    let search = async {
+        while let Ok(event) = events.recv_async().await {
+            if let ServiceEvent::ServiceResolved(info) = event {
+                match hub_from(&info) {
+                    Some(hub) => return Some(hub),
+                    None => warn!("[discovery] {} has no usable address", info.get_fullname()),
+                }
+            }
+        }
+        None
+    };
+    let found = tokio::time::timeout(Duration::from_secs(config.timeout_secs), search)
+        .await
+        .ok()
+        .flatten();
+    let _ = daemon.shutdown();
+    found.ok_or(DiscoveryError::NotFound(config.timeout_secs))
+}
+
+/*
+ * resolve_server_url
+ * Fills in server_url (and the TLS name to check) from discovery when the config leaves it
+ * empty. Runs on every start, so an agent follows a hub that moved to another address.
+ */
+pub async fn resolve_server_url(config: &mut LynxConfig) -> Result<(), DiscoveryError> {
+    if !config.core.server_url.is_empty() {
+        return Ok(());
+    }
+    if !config.discovery.enabled {
+        return Err(DiscoveryError::Disabled);
+    }
+    info!("[discovery] No server_url configured, looking for a hub on the local network");
+    let hub = discover(&config.discovery).await?;
+    info!(
+        "[discovery] Found hub at {} ({})",
+        hub.server_url,
+        hub.tls_name.as_deref().unwrap_or("no tls_name")
+    );
+    config.core.server_url = hub.server_url;
+    config.core.tls_name = hub.tls_name;
+    Ok(())
+}
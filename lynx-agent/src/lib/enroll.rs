@@ -0,0 +1,164 @@
+use crate::lib::client::LynxConfig;
+use crate::proto::monitor::enrollment_client::EnrollmentClient;
+use crate::proto::monitor::EnrollRequest;
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml_edit::DocumentMut;
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint};
+
+#[derive(Deserialize, Debug)]
+pub struct EnrollConfig {
+    pub token: String,
+    /// Defaults to the machine hostname, must match the hostname registered in the portal
+    pub hostname: Option<String>,
+}
+
+/// Creates the file readable by the agent's user only, before anything is written to it.
+fn write_private(path: &Path, contents: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // the mode only applies to new files, one left from before is narrowed first
+    #[cfg(unix)]
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+/*
+ * persist_agent_key
+ * Writes the key handed out by the hub back into config.toml so restarts keep the identity.
+ * With an agent_key_source the key goes there instead and config.toml only drops the token.
+ */
+fn persist_agent_key(
+    config: &LynxConfig,
+    config_path: &Path,
+    agent_key: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let raw = fs::read_to_string(config_path)?;
+    // edited in place, so the comments and layout of config.toml survive
+    let mut doc: DocumentMut = raw.parse()?;
+    match &config.core.agent_key_source {
+        Some(source) => {
+            crate::lib::credentials::store_key(source, agent_key)?;
+            info!("[enroll] Agent key stored in {source}");
+        }
+        None => {
+            if let Some(core) = doc.get_mut("core").and_then(|c| c.as_table_like_mut()) {
+                core.insert("agent_key", toml_edit::value(agent_key));
+            }
+        }
+    }
+    // the token is single use, keep it out of the config once consumed
+    doc.remove("enroll");
+    fs::write(config_path, doc.to_string())?;
+    Ok(())
+}
+
+/*
+ * enroll_if_needed
+ * When no client certificate exists yet and an enrollment token is configured, generate a key
+ * pair locally, have the hub sign the CSR and store the resulting certificate in ./certs.
+ * Only ca.crt has to be shipped to the agent ahead of time.
+ */
+pub async fn enroll_if_needed(
+    config: &mut LynxConfig,
+    config_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let certs_dir = std::env::current_dir()?.join("certs");
+    let cert_path = certs_dir.join("docker-agent.crt");
+    let key_path = certs_dir.join("docker-agent.key");
+    let ca_path = certs_dir.join("ca.crt");
+    if cert_path.exists() && key_path.exists() {
+        return Ok(());
+    }
+
+    let Some(enroll) = config.enroll.as_ref() else {
+        warn!("[enroll] No client certificate found and no [enroll] token configured");
+        return Ok(());
+    };
+    if !ca_path.exists() {
+        return Err(format!("CA certificate required for enrollment: {:?}", ca_path).into());
+    }
+
+    let hostname = enroll
+        .hostname
+        .clone()
+        .or_else(sysinfo::System::host_name)
+        .ok_or("Unable to determine hostname for enrollment")?;
+
+    info!(
+        "[enroll] Enrolling {} with {}",
+        hostname, config.core.server_url
+    );
+    let key_pair = rcgen::KeyPair::generate()?;
+    let csr_pem = rcgen::CertificateParams::new(vec![hostname.clone()])?
+        .serialize_request(&key_pair)?
+        .pem()?;
+
+    // no client identity yet, the hub only verifies the token
+    let ca_cert = fs::read_to_string(&ca_path)?;
+    let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca_cert.as_bytes()));
+    if let Some(name) = &config.core.tls_name {
+        tls = tls.domain_name(name);
+    }
+    let channel = Endpoint::from_shared(config.core.server_url.clone())?
+        .tls_config(tls)?
+        .connect_timeout(Duration::from_secs(10))
+        .connect()
+        .await?;
+    let response = EnrollmentClient::new(channel)
+        .enroll(EnrollRequest {
+            hostname,
+            token: enroll.token.clone(),
+            csr_pem,
+        })
+        .await?
+        .into_inner();
+
+    // the certificate's presence is what skips enrollment on the next start, so the files are
+    // staged first and only moved into place once the agent key was persisted
+    fs::create_dir_all(&certs_dir)?;
+    let staged = |path: &Path| {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".enroll");
+        PathBuf::from(name)
+    };
+    let files = [
+        (&key_path, key_pair.serialize_pem()),
+        (&ca_path, response.ca_certificate_pem.clone()),
+        (&cert_path, response.certificate_pem.clone()),
+    ];
+    let staging = files
+        .iter()
+        .try_for_each(|(path, contents)| write_private(&staged(path), contents))
+        .and_then(|()| {
+            persist_agent_key(config, config_path, &response.agent_key)
+                .map_err(|e| std::io::Error::other(e.to_string()))
+        });
+    if let Err(e) = staging {
+        for (path, _) in &files {
+            let _ = fs::remove_file(staged(path));
+        }
+        return Err(format!("Failed to store the enrollment: {}", e).into());
+    }
+    // the certificate goes last
+    for (path, _) in &files {
+        fs::rename(staged(path), path)?;
+    }
+
+    config.core.agent_key = response.agent_key;
+    config.enroll = None;
+    info!(
+        "[enroll] Enrollment complete, certificate stored in {:?}",
+        certs_dir
+    );
+    Ok(())
+}
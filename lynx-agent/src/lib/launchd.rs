@@ -0,0 +1,198 @@
+use crate::lib::cache::{FastCache, SystemService};
+use crate::lib::service_control::{ServiceAction, ServiceControlError};
+use crate::proto::monitor::SystemctlRequest;
+use log::warn;
+use std::collections::{HashMap, HashSet};
+
+/*
+ * launchd
+ * The macOS counterpart of the systemctl collector and service control. `launchctl list` lists
+ * the jobs of the agent's domain (the system daemons when it runs as root) with their pid and
+ * last exit status. Jobs are reported like systemd units so the hub counts and filters them the
+ * same way: Active while running, Failed when the last run exited non-zero, Inactive otherwise.
+ * The description says whether a job is a LaunchDaemon or a LaunchAgent, from the directory
+ * its plist is in. Start and stop are `launchctl start` / `stop` in the same domain, a restart
+ * is both; jobs with KeepAlive are started again by launchd right after a stop.
+ */
+
+/// Where plists are installed, with the kind of job they define.
+const PLIST_DIRS: &[(&str, &str)] = &[
+    ("/Library/LaunchDaemons", "LaunchDaemon"),
+    ("/System/Library/LaunchDaemons", "LaunchDaemon"),
+    ("/Library/LaunchAgents", "LaunchAgent"),
+    ("/System/Library/LaunchAgents", "LaunchAgent"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchdJob {
+    pub label: String,
+    pub pid: Option<u64>,
+    /// Exit status of the last run, negative for the signal that ended it
+    pub status: i32,
+}
+
+impl LaunchdJob {
+    /// The job's state in systemd's terms.
+    pub fn state(&self) -> &'static str {
+        match (self.pid, self.status) {
+            (Some(_), _) => "Active",
+            (None, 0) => "Inactive",
+            (None, _) => "Failed",
+        }
+    }
+}
+
+/// `launchctl list` output: a `PID Status Label` header, then a tab separated line per job.
+pub fn parse_list(output: &str) -> Vec<LaunchdJob> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let (pid, status, label) = (fields.next()?, fields.next()?, fields.next()?.trim());
+            // `-` for jobs that never exited, the header doesn't parse
+            let status = match status.trim() {
+                "-" => 0,
+                status => status.parse().ok()?,
+            };
+            if label.is_empty() {
+                return None;
+            }
+            Some(LaunchdJob {
+                label: label.to_string(),
+                pid: pid.trim().parse().ok(),
+                status,
+            })
+        })
+        .collect()
+}
+
+/// LaunchDaemon or LaunchAgent per label, from the plists named after their label.
+fn job_kinds() -> HashMap<String, &'static str> {
+    let user_agents = std::env::var("HOME")
+        .ok()
+        .map(|home| (format!("{home}/Library/LaunchAgents"), "LaunchAgent"));
+    let mut kinds = HashMap::new();
+    for (dir, kind) in PLIST_DIRS
+        .iter()
+        .map(|(dir, kind)| (dir.to_string(), *kind))
+        .chain(user_agents)
+    {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().is_none_or(|ext| ext != "plist") {
+                continue;
+            }
+            if let Some(label) = path.file_stem().and_then(|stem| stem.to_str()) {
+                kinds.entry(label.to_string()).or_insert(kind);
+            }
+        }
+    }
+    kinds
+}
+
+/*
+ * collect_launchd_services
+ * The jobs that changed since they were cached, every job on a full sync, and the labels from
+ * `known` no longer listed as removed, like collect_systemctl_services. A failed listing
+ * reports nothing.
+ */
+pub async fn collect_launchd_services(
+    cache: &FastCache,
+    known: &mut HashSet<String>,
+    full_sync: bool,
+) -> SystemctlRequest {
+    let output = match tokio::process::Command::new("launchctl")
+        .arg("list")
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            warn!(
+                "[agent] launchctl list failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return SystemctlRequest::default();
+        }
+        Err(e) => {
+            warn!("[agent] Failed to run launchctl list: {e}");
+            return SystemctlRequest::default();
+        }
+    };
+    let kinds = job_kinds();
+    let mut services = vec![];
+    let mut listed = HashSet::new();
+
+    for job in parse_list(&String::from_utf8_lossy(&output.stdout)) {
+        listed.insert(job.label.clone());
+        let service = SystemService {
+            name: job.label.clone(),
+            status: job.state().to_string(),
+            enabled: job.pid.is_some(),
+            description: kinds.get(&job.label).map(|kind| kind.to_string()),
+            pid: job.pid,
+            cpu_usage: None,
+            memory_usage: None,
+        };
+        let cached = cache.get_system_service(&job.label).await.unwrap_or(None);
+        if cached.as_ref() != Some(&service) {
+            let _ = cache
+                .set_system_service(&service, Some(chrono::Duration::minutes(10)))
+                .await;
+        } else if !full_sync {
+            continue;
+        }
+        services.push(crate::proto::monitor::SystemService {
+            service_name: service.name,
+            description: service.description.unwrap_or_default(),
+            state: service.status,
+            pid: service.pid.unwrap_or(0),
+            cpu: "unknown".to_string(),
+            memory: "unknown".to_string(),
+            ..Default::default()
+        });
+    }
+    let mut removed: Vec<String> = known.difference(&listed).cloned().collect();
+    removed.sort();
+    *known = listed;
+
+    SystemctlRequest {
+        services,
+        removed,
+        full_sync,
+    }
+}
+
+/// Starts, stops or restarts the job with this label in the agent's domain.
+pub async fn control(label: &str, action: ServiceAction) -> Result<(), ServiceControlError> {
+    let verbs: &[&str] = match action {
+        ServiceAction::Start => &["start"],
+        ServiceAction::Stop => &["stop"],
+        ServiceAction::Restart => &["stop", "start"],
+    };
+    for verb in verbs {
+        let output = tokio::process::Command::new("launchctl")
+            .args([*verb, label])
+            .output()
+            .await
+            .map_err(ServiceControlError::Launchctl)?;
+        if output.status.success() {
+            continue;
+        }
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        if message.contains("Operation not permitted") || message.contains("Permission denied") {
+            return Err(ServiceControlError::NotPermitted {
+                action,
+                unit: label.to_string(),
+            });
+        }
+        return Err(ServiceControlError::Launchd {
+            action,
+            unit: label.to_string(),
+            message,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,34 @@
+use log::info;
+use tracing_subscriber::EnvFilter;
+
+/*
+ * Logging
+ * Same setup as the hub: tracing with one JSON object per line unless LOG_FORMAT=text, and the
+ * log macros used throughout the agent forwarded into it.
+ */
+
+/// MY_LOG_LEVEL takes EnvFilter directives, e.g. `info,bollard=warn`.
+fn filter() -> EnvFilter {
+    EnvFilter::try_from_env("MY_LOG_LEVEL").unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+pub fn init() {
+    let builder = tracing_subscriber::fmt().with_env_filter(filter());
+    let text = matches!(
+        std::env::var("LOG_FORMAT").as_deref(),
+        Ok("text") | Ok("pretty")
+    );
+    if text {
+        // MY_LOG_STYLE=never turns colors off
+        let ansi = !matches!(std::env::var("MY_LOG_STYLE").as_deref(), Ok("never"));
+        builder.with_ansi(ansi).init();
+    } else {
+        builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init();
+    }
+    info!("[agent] Logging initialized");
+}
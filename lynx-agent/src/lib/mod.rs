@@ -1,7 +1,31 @@
+pub mod audit;
+pub mod authorization;
 pub mod cache;
 pub mod client;
 pub mod collectors;
+pub mod crash;
+pub mod credentials;
+pub mod diagnostics;
+pub mod discovery;
 pub mod docker;
+pub mod enroll;
 pub mod gpu;
+#[cfg(target_os = "macos")]
+pub mod launchd;
+pub mod logging;
+pub mod network;
+pub mod packages;
+pub mod probes;
+pub mod protocol;
+pub mod scripts;
+pub mod service_control;
+pub mod service_logs;
+pub mod sessions;
+pub mod smart;
+pub mod spool;
 pub mod system_info;
+pub mod tunnel;
+pub mod uninstall;
+pub mod update;
+pub mod upload;
 pub mod websocket;
@@ -0,0 +1,113 @@
+use std::collections::VecDeque;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use sysinfo::Networks;
+
+/*
+ * Network sampler
+ * One task reads the interface counters every SAMPLE_INTERVAL and keeps HISTORY worth of them.
+ * The metrics collector asks for the rate since its previous pass and the live stream for the
+ * rate over the last sample, both from the same counters, so a report and the live view never
+ * disagree because one of them just started its own deltas over. A counter going backwards
+ * (interface replaced or reset) counts as no traffic. Started by the first caller.
+ */
+
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+/// Samples older than this are dropped, longer windows get the rate over all of it
+const HISTORY: Duration = Duration::from_secs(15 * 60);
+
+static SAMPLER: OnceLock<NetworkSampler> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug)]
+struct Sample {
+    received: u64,
+    transmitted: u64,
+    at: Instant,
+}
+
+/// Bytes per second across all interfaces.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkRate {
+    pub received: f64,
+    pub transmitted: f64,
+}
+
+pub struct NetworkSampler {
+    samples: Mutex<VecDeque<Sample>>,
+}
+
+/// The shared sampler, its task starts on first use.
+pub fn sampler() -> &'static NetworkSampler {
+    SAMPLER.get_or_init(|| {
+        let sampler = NetworkSampler {
+            samples: Mutex::new(VecDeque::new()),
+        };
+        tokio::spawn(run_sampler());
+        sampler
+    })
+}
+
+async fn run_sampler() {
+    let mut networks = Networks::new_with_refreshed_list();
+    let mut tick = tokio::time::interval(SAMPLE_INTERVAL);
+    loop {
+        tick.tick().await;
+        networks.refresh(true);
+        let (received, transmitted) = networks.values().fold((0, 0), |(in_acc, out_acc), net| {
+            (
+                in_acc + net.total_received(),
+                out_acc + net.total_transmitted(),
+            )
+        });
+        sampler().push(Sample {
+            received,
+            transmitted,
+            at: Instant::now(),
+        });
+    }
+}
+
+/// Per second increase of a counter, zero when it went backwards.
+fn per_sec(current: u64, previous: u64, elapsed: f64) -> f64 {
+    if current < previous || elapsed <= 0.0 {
+        return 0.0;
+    }
+    (current - previous) as f64 / elapsed
+}
+
+impl NetworkSampler {
+    fn push(&self, sample: Sample) {
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back(sample);
+        while samples
+            .front()
+            .is_some_and(|oldest| sample.at.duration_since(oldest.at) > HISTORY)
+        {
+            samples.pop_front();
+        }
+    }
+
+    /*
+     * rate
+     * From the newest sample at least `window` older than the latest one to the latest, or the
+     * oldest kept when the history is shorter. Zero until two samples were taken.
+     */
+    pub fn rate(&self, window: Duration) -> NetworkRate {
+        let samples = self.samples.lock().unwrap();
+        let Some(latest) = samples.back().copied() else {
+            return NetworkRate::default();
+        };
+        let base = samples
+            .iter()
+            .rev()
+            .find(|s| latest.at.duration_since(s.at) >= window)
+            .or(samples.front())
+            .copied()
+            .unwrap_or(latest);
+        let elapsed = latest.at.duration_since(base.at).as_secs_f64();
+        NetworkRate {
+            received: per_sec(latest.received, base.received, elapsed),
+            transmitted: per_sec(latest.transmitted, base.transmitted, elapsed),
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use crate::lib;
+use log::{info, warn};
+use serde::Serialize;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc::Sender;
+use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+
+/*
+ * Package updates
+ * `listupdates` and `applyupdates` websocket messages let the hub drive patching: the agent
+ * lists the updates apt or dnf has pending and installs all or some of them, relaying the
+ * package manager's output while it runs. One upgrade runs at a time, and unlike `execute`
+ * commands upgrades can't be stopped, killing a package manager halfway leaves a broken system.
+ */
+
+/// Upgrades taking longer than this are reported as failed, the package manager keeps running.
+const APPLY_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const LIST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+static APPLYING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Manager {
+    Apt,
+    Dnf,
+}
+
+#[derive(Error, Debug)]
+pub enum PackageError {
+    #[error("No supported package manager (apt or dnf) found")]
+    Unsupported,
+    #[error("Invalid package name {0:?}")]
+    InvalidPackage(String),
+    #[error("Another upgrade is still running")]
+    Busy,
+    #[error("Failed to run {0:?}: {1}")]
+    Io(&'static str, std::io::Error),
+    #[error("{0} did not finish within {1} seconds")]
+    Timeout(&'static str, u64),
+    #[error("{0} failed: {1}")]
+    Failed(&'static str, String),
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct PendingUpdate {
+    pub name: String,
+    /// Installed version, dnf doesn't tell
+    pub current: Option<String>,
+    pub available: String,
+    /// The apt suite or dnf repository, e.g. `jammy-security`
+    pub origin: String,
+}
+
+/// Answers to `listupdates` and `applyupdates` messages.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum PackageFrame {
+    #[serde(rename = "pending_updates")]
+    Pending {
+        manager: Manager,
+        updates: Vec<PendingUpdate>,
+    },
+    /// One line of the package manager's output
+    #[serde(rename = "packages_progress")]
+    Progress { line: String },
+    #[serde(rename = "packages_done")]
+    Done {
+        packages: Vec<String>,
+        exit_code: Option<i32>,
+    },
+    #[serde(rename = "packages_error")]
+    Error { error: String },
+}
+
+impl Manager {
+    /// The program upgrades run with
+    fn program(self) -> &'static str {
+        match self {
+            Manager::Apt => "apt-get",
+            Manager::Dnf => "dnf",
+        }
+    }
+}
+
+pub fn detect() -> Option<Manager> {
+    if Path::new("/usr/bin/apt-get").exists() {
+        Some(Manager::Apt)
+    } else if Path::new("/usr/bin/dnf").exists() {
+        Some(Manager::Dnf)
+    } else {
+        None
+    }
+}
+
+/// Package names apt and dnf accept, also keeps anything that could pass for an option out.
+fn valid_package(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 256
+        && !name.starts_with('-')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-._:".contains(c))
+}
+
+/// `apt list --upgradable`, e.g. `curl/jammy-security 7.81.0-2 amd64 [upgradable from: 7.81.0-1]`.
+pub fn parse_apt(output: &str) -> Vec<PendingUpdate> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (name, origin) = fields.next()?.split_once('/')?;
+            let available = fields.next()?;
+            let current = line
+                .split_once("[upgradable from: ")
+                .and_then(|(_, rest)| rest.strip_suffix(']'))
+                .map(str::to_string);
+            Some(PendingUpdate {
+                name: name.to_string(),
+                current,
+                available: available.to_string(),
+                origin: origin.split(',').next().unwrap_or(origin).to_string(),
+            })
+        })
+        .collect()
+}
+
+/// `dnf -q check-update`, e.g. `curl.x86_64  7.76.1-29.el9_4  baseos`.
+pub fn parse_dnf(output: &str) -> Vec<PendingUpdate> {
+    output
+        .lines()
+        // "Obsoleting Packages" and the packages under it are no updates
+        .take_while(|line| !line.starts_with("Obsoleting"))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [package, available, origin] = fields[..] else {
+                return None;
+            };
+            let (name, _arch) = package.rsplit_once('.')?;
+            Some(PendingUpdate {
+                name: name.to_string(),
+                current: None,
+                available: available.to_string(),
+                origin: origin.to_string(),
+            })
+        })
+        .collect()
+}
+
+async fn run(
+    program: &'static str,
+    args: &[&str],
+    ok_codes: &[i32],
+) -> Result<String, PackageError> {
+    let output = Command::new(program)
+        .args(args)
+        .env("LC_ALL", "C")
+        .kill_on_drop(true)
+        .output();
+    let output = tokio::time::timeout(LIST_TIMEOUT, output)
+        .await
+        .map_err(|_| PackageError::Timeout(program, LIST_TIMEOUT.as_secs()))?
+        .map_err(|e| PackageError::Io(program, e))?;
+    match output.status.code() {
+        Some(code) if ok_codes.contains(&code) => {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        }
+        _ => Err(PackageError::Failed(
+            program,
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        )),
+    }
+}
+
+/// Pending updates, with `refresh` apt downloads the package lists first (dnf always does).
+pub async fn list(refresh: bool) -> Result<(Manager, Vec<PendingUpdate>), PackageError> {
+    let manager = detect().ok_or(PackageError::Unsupported)?;
+    let updates = match manager {
+        Manager::Apt => {
+            if refresh {
+                run("apt-get", &["update", "-q"], &[0]).await?;
+            }
+            parse_apt(&run("apt", &["list", "--upgradable"], &[0]).await?)
+        }
+        // 100 means there are updates
+        Manager::Dnf => parse_dnf(&run("dnf", &["-q", "check-update"], &[0, 100]).await?),
+    };
+    Ok((manager, updates))
+}
+
+fn upgrade_command(manager: Manager, packages: &[String]) -> Command {
+    let mut command = match manager {
+        Manager::Apt => {
+            let mut command = Command::new("apt-get");
+            command.env("DEBIAN_FRONTEND", "noninteractive").args([
+                "-y",
+                "-q",
+                "-o",
+                "Dpkg::Options::=--force-confold",
+            ]);
+            if packages.is_empty() {
+                command.arg("upgrade");
+            } else {
+                command.args(["install", "--only-upgrade"]).args(packages);
+            }
+            command
+        }
+        Manager::Dnf => {
+            let mut command = Command::new("dnf");
+            command.args(["-y", "upgrade"]).args(packages);
+            command
+        }
+    };
+    command
+        .env("LC_ALL", "C")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    command
+}
+
+/// Waits for room, a client that went away doesn't stop an upgrade.
+async fn send(tx: &Sender<Message>, frame: &PackageFrame) {
+    if let Ok(text) = serde_json::to_string(frame) {
+        let _ = tx.send(Message::Text(Utf8Bytes::from(text))).await;
+    }
+}
+
+/// Runs the upgrade, relaying its output, and returns its exit code.
+async fn upgrade(
+    manager: Manager,
+    packages: &[String],
+    tx: &Sender<Message>,
+) -> Result<Option<i32>, PackageError> {
+    let program = manager.program();
+    let mut child = upgrade_command(manager, packages)
+        .spawn()
+        .map_err(|e| PackageError::Io(program, e))?;
+    let mut stdout = child.stdout.take().map(|out| BufReader::new(out).lines());
+    let mut stderr = child.stderr.take().map(|err| BufReader::new(err).lines());
+    let relay = async {
+        loop {
+            let line = tokio::select! {
+                Some(line) = async { stdout.as_mut()?.next_line().await.ok().flatten() } => line,
+                Some(line) = async { stderr.as_mut()?.next_line().await.ok().flatten() } => line,
+                else => break,
+            };
+            send(tx, &PackageFrame::Progress { line }).await;
+        }
+        child.wait().await
+    };
+    let status = tokio::time::timeout(APPLY_TIMEOUT, relay)
+        .await
+        .map_err(|_| PackageError::Timeout(program, APPLY_TIMEOUT.as_secs()))?
+        .map_err(|e| PackageError::Io(program, e))?;
+    Ok(status.code())
+}
+
+/// Answers a `listupdates` message.
+pub async fn send_pending(tx: Sender<Message>, refresh: bool) {
+    let frame = match list(refresh).await {
+        Ok((manager, updates)) => PackageFrame::Pending { manager, updates },
+        Err(e) => {
+            warn!("[packages] Failed to list pending updates: {}", e);
+            PackageFrame::Error {
+                error: e.to_string(),
+            }
+        }
+    };
+    send(&tx, &frame).await;
+}
+
+/// Answers an `applyupdates` message, an empty `packages` upgrades everything.
+pub async fn apply(peer: String, tx: Sender<Message>, packages: Vec<String>) {
+    let manager = detect();
+    let target = manager.map(Manager::program).unwrap_or_default();
+    let audit = lib::audit::started(&peer, "apply_updates", target, packages.clone());
+    let result = match (manager, packages.iter().find(|p| !valid_package(p))) {
+        (None, _) => Err(PackageError::Unsupported),
+        (_, Some(invalid)) => Err(PackageError::InvalidPackage(invalid.clone())),
+        (Some(manager), None) => {
+            if APPLYING.swap(true, Ordering::SeqCst) {
+                Err(PackageError::Busy)
+            } else {
+                info!(
+                    "[packages] Upgrading {} for {}",
+                    if packages.is_empty() {
+                        "all packages".to_string()
+                    } else {
+                        packages.join(" ")
+                    },
+                    peer
+                );
+                let result = upgrade(manager, &packages, &tx).await;
+                APPLYING.store(false, Ordering::SeqCst);
+                result
+            }
+        }
+    };
+    let frame = match result {
+        Ok(exit_code) => {
+            let error = (exit_code != Some(0)).then(|| "upgrade failed".to_string());
+            lib::audit::finish(audit, exit_code, error);
+            PackageFrame::Done {
+                packages,
+                exit_code,
+            }
+        }
+        Err(e) => {
+            warn!("[packages] Upgrade for {} failed: {}", peer, e);
+            lib::audit::finish(audit, None, Some(e.to_string()));
+            PackageFrame::Error {
+                error: e.to_string(),
+            }
+        }
+    };
+    send(&tx, &frame).await;
+}
@@ -0,0 +1,28 @@
+use crate::proto::monitor::{ProbeResult, ProbeTarget};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+/// Runs every probe target pushed by the hub concurrently.
+pub async fn run_probes(targets: &[ProbeTarget]) -> Vec<ProbeResult> {
+    futures_util::future::join_all(targets.iter().map(probe)).await
+}
+
+/*
+ * probe
+ * A TCP connect to the target's host:port. Up when the connection is established within the
+ * target's timeout, the latency is the time the connect took.
+ */
+async fn probe(target: &ProbeTarget) -> ProbeResult {
+    let timeout = Duration::from_millis(target.timeout_ms.max(1) as u64);
+    let start = Instant::now();
+    let up = matches!(
+        tokio::time::timeout(timeout, TcpStream::connect(&target.address)).await,
+        Ok(Ok(_))
+    );
+    ProbeResult {
+        name: target.name.clone(),
+        address: target.address.clone(),
+        up,
+        latency_ms: up.then(|| start.elapsed().as_secs_f64() * 1000.0),
+    }
+}
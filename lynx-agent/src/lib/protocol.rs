@@ -0,0 +1,145 @@
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+
+/*
+ * Websocket protocol
+ * Clients may open with `{"type": "hello", "version": 1}`, the agent answers with the version it
+ * speaks and the message types it knows, so dashboards can tell what an agent supports before
+ * sending anything. A message the agent can't handle is answered with an `error` frame carrying
+ * a code, a message and the `id` the client put on the message, if any, and the connection stays
+ * open. The version only grows when messages change incompatibly, clients that don't say hello
+ * get version 1.
+ */
+
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest version the agent still speaks
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Message types the agent handles, as listed in its hello.
+pub const MESSAGE_TYPES: &[&str] = &[
+    "hello",
+    "execute",
+    "stop",
+    "update",
+    "delete",
+    "live",
+    "startservice",
+    "stopservice",
+    "restartservice",
+    "servicelogs",
+    "listupdates",
+    "applyupdates",
+    "upload",
+    "EOF",
+];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Not JSON, or not a text message
+    InvalidJson,
+    /// A `type` the agent doesn't know, or none
+    UnknownMessage,
+    /// A known type with missing or mistyped fields
+    InvalidMessage,
+    /// Refused by the `[authorization]` policy, see lib::authorization
+    Unauthorized,
+    /// The client's version is older than the agent still speaks
+    UnsupportedVersion,
+}
+
+/// Frames about the protocol itself, the answers to messages have their own.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum ProtocolFrame {
+    #[serde(rename = "hello")]
+    Hello {
+        version: u32,
+        min_version: u32,
+        agent_version: &'static str,
+        messages: &'static [&'static str],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<serde_json::Value>,
+    },
+    #[serde(rename = "error")]
+    Error {
+        code: ErrorCode,
+        message: String,
+        /// The `id` of the message this answers
+        #[serde(skip_serializing_if = "Option::is_none")]
+        id: Option<serde_json::Value>,
+    },
+}
+
+/// A client message, with the `id` and `type` answers refer to.
+pub struct Incoming {
+    pub value: serde_json::Value,
+    pub id: Option<serde_json::Value>,
+    pub action: String,
+}
+
+/// Reads a client message, None for pings and closes, the error frame if it can't be handled.
+pub fn parse(msg: &Message) -> Result<Option<Incoming>, ProtocolFrame> {
+    let text = match msg {
+        Message::Text(text) => text.as_str(),
+        Message::Binary(_) => {
+            return Err(error(
+                ErrorCode::InvalidJson,
+                "Expected a text message",
+                None,
+            ))
+        }
+        _ => return Ok(None),
+    };
+    let value: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| error(ErrorCode::InvalidJson, e.to_string(), None))?;
+    let id = value.get("id").cloned();
+    let action = value
+        .get("type")
+        .and_then(|t| t.as_str())
+        .unwrap_or_default()
+        .to_string();
+    if !MESSAGE_TYPES.contains(&action.as_str()) {
+        let message = format!("Unknown message type {action:?}");
+        return Err(error(ErrorCode::UnknownMessage, message, id));
+    }
+    Ok(Some(Incoming { value, id, action }))
+}
+
+pub fn error(
+    code: ErrorCode,
+    message: impl Into<String>,
+    id: Option<serde_json::Value>,
+) -> ProtocolFrame {
+    ProtocolFrame::Error {
+        code,
+        message: message.into(),
+        id,
+    }
+}
+
+/// Answers a `hello`, the agent speaks the older of both versions.
+pub fn hello(version: Option<u32>, id: Option<serde_json::Value>) -> ProtocolFrame {
+    let version = version.unwrap_or(MIN_PROTOCOL_VERSION);
+    if version < MIN_PROTOCOL_VERSION {
+        return error(
+            ErrorCode::UnsupportedVersion,
+            format!("Protocol version {version} is no longer supported, the oldest is {MIN_PROTOCOL_VERSION}"),
+            id,
+        );
+    }
+    ProtocolFrame::Hello {
+        version: version.min(PROTOCOL_VERSION),
+        min_version: MIN_PROTOCOL_VERSION,
+        agent_version: env!("CARGO_PKG_VERSION"),
+        messages: MESSAGE_TYPES,
+        id,
+    }
+}
+
+pub fn send(tx: &Sender<Message>, frame: &ProtocolFrame) {
+    if let Ok(text) = serde_json::to_string(frame) {
+        let _ = tx.try_send(Message::Text(Utf8Bytes::from(text)));
+    }
+}
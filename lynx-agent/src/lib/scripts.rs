@@ -0,0 +1,286 @@
+use crate::proto::monitor::{CustomMetric, MetricsRequest};
+use log::{error, info, warn};
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table, Value, VmState};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
+
+/*
+ * Scripts
+ * Lua scripts in the [scripts] directory derive custom metrics from each metrics collection
+ * without spawning a process. Every *.lua file runs in its own Lua state with only the math,
+ * string and table libraries (no io, os, require or the dofile/loadfile/load loaders), an
+ * instruction budget per call and a memory limit. A script defines `derive(metrics)` and returns a table of name = value, or
+ * name = { value = ..., labels = { ... } }; the values go out with the metrics report as
+ * custom metrics, so they show up as `custom.<name>` on the hub like posted ones.
+ */
+
+const HOOK_EVERY: u32 = 1000;
+
+/// Optional `[scripts]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScriptsConfig {
+    pub dir: PathBuf,
+    /// Lua instructions one `derive` call may run before it is stopped
+    pub max_instructions: u64,
+    pub max_memory_bytes: usize,
+}
+
+impl Default for ScriptsConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("scripts"),
+            max_instructions: 1_000_000,
+            max_memory_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ScriptError {
+    #[error("Failed to read script: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Lua error: {0}")]
+    Lua(#[from] mlua::Error),
+    #[error("Script does not define derive(metrics)")]
+    MissingDerive,
+    #[error("Script ran over its budget of {0} instructions")]
+    Budget(u64),
+}
+
+struct Script {
+    name: String,
+    lua: Lua,
+    instructions: Arc<AtomicU64>,
+}
+
+pub struct Scripts {
+    scripts: Mutex<Vec<Script>>,
+    max_instructions: u64,
+}
+
+impl Scripts {
+    /// Loads every script in the directory, broken ones are logged and left out.
+    pub fn load(config: &ScriptsConfig) -> Self {
+        let mut scripts = Vec::new();
+        let mut files: Vec<PathBuf> = fs::read_dir(&config.dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "lua"))
+                    .collect()
+            })
+            .unwrap_or_default();
+        files.sort();
+        for path in files {
+            match load_script(&path, config) {
+                Ok(script) => {
+                    info!("[scripts] Loaded {}", script.name);
+                    scripts.push(script);
+                }
+                Err(e) => error!("[scripts] Skipping {}: {}", path.display(), e),
+            }
+        }
+        Self {
+            scripts: Mutex::new(scripts),
+            max_instructions: config.max_instructions,
+        }
+    }
+
+    /// Runs every script on a collection, a failing script only loses its own values.
+    pub fn derive(&self, metrics: &MetricsRequest) -> Vec<CustomMetric> {
+        let scripts = self.scripts.lock().unwrap_or_else(|e| e.into_inner());
+        let mut derived = Vec::new();
+        for script in scripts.iter() {
+            script.instructions.store(0, Ordering::Relaxed);
+            match run(script, metrics) {
+                Ok(values) => derived.extend(values),
+                Err(ScriptError::Lua(_))
+                    if script.instructions.load(Ordering::Relaxed) > self.max_instructions =>
+                {
+                    warn!(
+                        "[scripts] {}: {}",
+                        script.name,
+                        ScriptError::Budget(self.max_instructions)
+                    )
+                }
+                Err(e) => warn!("[scripts] {} failed: {}", script.name, e),
+            }
+        }
+        derived
+    }
+}
+
+fn load_script(path: &Path, config: &ScriptsConfig) -> Result<Script, ScriptError> {
+    let source = fs::read_to_string(path)?;
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lua = Lua::new_with(
+        StdLib::MATH | StdLib::STRING | StdLib::TABLE,
+        LuaOptions::new(),
+    )?;
+    // the base library is always loaded, and its loaders read any file the agent can
+    for loader in ["dofile", "loadfile", "load"] {
+        lua.globals().set(loader, Value::Nil)?;
+    }
+    lua.set_memory_limit(config.max_memory_bytes)?;
+    let instructions = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&instructions);
+    let budget = config.max_instructions;
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(HOOK_EVERY),
+        move |_, _| {
+            let used = counter.fetch_add(HOOK_EVERY as u64, Ordering::Relaxed) + HOOK_EVERY as u64;
+            if used > budget {
+                return Err(mlua::Error::runtime("instruction budget exceeded"));
+            }
+            Ok(VmState::Continue)
+        },
+    );
+    lua.load(source).set_name(name.as_str()).exec()?;
+    if !matches!(lua.globals().get::<Value>("derive")?, Value::Function(_)) {
+        return Err(ScriptError::MissingDerive);
+    }
+    Ok(Script {
+        name,
+        lua,
+        instructions,
+    })
+}
+
+fn run(script: &Script, metrics: &MetricsRequest) -> Result<Vec<CustomMetric>, ScriptError> {
+    let lua = &script.lua;
+    let derive: mlua::Function = lua.globals().get("derive")?;
+    let result: Value = derive.call(metrics_table(lua, metrics)?)?;
+    let Value::Table(result) = result else {
+        return Ok(Vec::new());
+    };
+    let mut derived = Vec::new();
+    for pair in result.pairs::<String, Value>() {
+        let (name, value) = pair?;
+        let (value, labels) = match value {
+            Value::Integer(i) => (i as f64, HashMap::new()),
+            Value::Number(n) => (n, HashMap::new()),
+            Value::Table(t) => {
+                let labels = match t.get::<Option<Table>>("labels")? {
+                    Some(labels) => labels.pairs::<String, String>().collect::<Result<_, _>>()?,
+                    None => HashMap::new(),
+                };
+                (t.get::<f64>("value")?, labels)
+            }
+            // nil and other values mean the script has nothing for this name right now
+            _ => continue,
+        };
+        derived.push(CustomMetric {
+            name,
+            value,
+            labels,
+        });
+    }
+    Ok(derived)
+}
+
+/// The collection as scripts see it, names follow the alert rule metrics.
+fn metrics_table(lua: &Lua, metrics: &MetricsRequest) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    if let Some(cpu) = &metrics.cpu_stats {
+        let t = lua.create_table()?;
+        t.set("usage", cpu.usage_percent)?;
+        t.set("user", cpu.user_percent)?;
+        t.set("system", cpu.system_percent)?;
+        t.set("iowait", cpu.iowait_percent)?;
+        t.set("irq", cpu.irq_percent)?;
+        t.set("steal", cpu.steal_percent)?;
+        table.set("cpu", t)?;
+    }
+    if let Some(memory) = &metrics.memory_stats {
+        let t = lua.create_table()?;
+        t.set("total", memory.total_kb)?;
+        t.set("used", memory.used_kb)?;
+        t.set("free", memory.free_kb)?;
+        t.set("available", memory.available_kb)?;
+        t.set("cached", memory.cached_kb)?;
+        t.set("buffers", memory.buffers_kb)?;
+        t.set("dirty", memory.dirty_kb)?;
+        t.set("swap_total", memory.swap_total_kb)?;
+        t.set("swap_used", memory.swap_used_kb)?;
+        t.set("swap_in", memory.swap_in_per_sec)?;
+        t.set("swap_out", memory.swap_out_per_sec)?;
+        table.set("memory", t)?;
+    }
+    if let Some(load) = &metrics.load_average {
+        let t = lua.create_table()?;
+        t.set("one", load.one_minute)?;
+        t.set("five", load.five_minutes)?;
+        t.set("fifteen", load.fifteen_minutes)?;
+        table.set("load", t)?;
+    }
+    if let Some(network) = &metrics.network_stats {
+        let t = lua.create_table()?;
+        t.set("in", network.r#in)?;
+        t.set("out", network.out)?;
+        table.set("network", t)?;
+    }
+    if let Some(processes) = &metrics.process_stats {
+        let t = lua.create_table()?;
+        t.set("total", processes.total)?;
+        t.set("threads", processes.threads)?;
+        t.set("running", processes.running)?;
+        t.set("zombie", processes.zombie)?;
+        table.set("processes", t)?;
+    }
+    if let Some(kernel) = &metrics.kernel_stats {
+        let t = lua.create_table()?;
+        t.set("context_switches", kernel.context_switches_per_sec)?;
+        t.set("interrupts", kernel.interrupts_per_sec)?;
+        t.set("procs_blocked", kernel.procs_blocked)?;
+        t.set("entropy", kernel.entropy_avail)?;
+        table.set("kernel", t)?;
+    }
+
+    let disks = lua.create_table()?;
+    for disk in &metrics.disk_stats {
+        let t = lua.create_table()?;
+        t.set("name", disk.name.as_str())?;
+        t.set("mount_point", disk.mount_point.as_str())?;
+        t.set("total", disk.total_space)?;
+        t.set("used", disk.used_space)?;
+        t.set("unit", disk.unit.as_str())?;
+        t.set("read_bytes", disk.read_bytes)?;
+        t.set("write_bytes", disk.write_bytes)?;
+        t.set("read_iops", disk.read_iops)?;
+        t.set("write_iops", disk.write_iops)?;
+        t.set("inodes_total", disk.inodes_total)?;
+        t.set("inodes_used", disk.inodes_used)?;
+        disks.push(t)?;
+    }
+    table.set("disks", disks)?;
+
+    let components = lua.create_table()?;
+    for component in &metrics.components {
+        let t = lua.create_table()?;
+        t.set("label", component.label.as_str())?;
+        t.set("temperature", component.temperature)?;
+        components.push(t)?;
+    }
+    table.set("components", components)?;
+
+    let probes = lua.create_table()?;
+    for probe in &metrics.probe_results {
+        let t = lua.create_table()?;
+        t.set("name", probe.name.as_str())?;
+        t.set("address", probe.address.as_str())?;
+        t.set("up", probe.up)?;
+        t.set("latency_ms", probe.latency_ms)?;
+        probes.push(t)?;
+    }
+    table.set("probes", probes)?;
+    Ok(table)
+}
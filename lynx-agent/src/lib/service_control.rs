@@ -0,0 +1,210 @@
+#[cfg(target_os = "linux")]
+use log::{info, warn};
+use std::fmt;
+use thiserror::Error;
+#[cfg(target_os = "linux")]
+use zbus::Connection;
+
+/*
+ * Service control
+ * start, stop and restart of systemd units requested over the websocket. The agent asks systemd
+ * over the system D-Bus, so it does not need to run as root: polkit decides, and the install
+ * script for unprivileged agents adds a rule granting its user org.freedesktop.systemd1
+ * manage-units for these verbs. Without such a rule the request is denied and reported as
+ * read-only instead of prompting anyone. When the bus itself is unreachable (e.g. inside a
+ * container) the agent falls back to the systemctl binary. On macOS the services are launchd
+ * jobs, see lib::launchd, on FreeBSD rc.d scripts run through service(8).
+ */
+
+#[cfg(target_os = "linux")]
+const SYSTEMD_DEST: &str = "org.freedesktop.systemd1";
+#[cfg(target_os = "linux")]
+const SYSTEMD_PATH: &str = "/org/freedesktop/systemd1";
+#[cfg(target_os = "linux")]
+const SYSTEMD_MANAGER: &str = "org.freedesktop.systemd1.Manager";
+
+/// Errors polkit answers with when the caller is not allowed to manage the unit.
+#[cfg(target_os = "linux")]
+const DENIED_ERRORS: &[&str] = &[
+    "org.freedesktop.DBus.Error.AccessDenied",
+    "org.freedesktop.DBus.Error.InteractiveAuthorizationRequired",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceAction {
+    Start,
+    Stop,
+    Restart,
+}
+
+impl ServiceAction {
+    #[cfg(target_os = "linux")]
+    fn method(self) -> &'static str {
+        match self {
+            ServiceAction::Start => "StartUnit",
+            ServiceAction::Stop => "StopUnit",
+            ServiceAction::Restart => "RestartUnit",
+        }
+    }
+}
+
+impl fmt::Display for ServiceAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ServiceAction::Start => "start",
+            ServiceAction::Stop => "stop",
+            ServiceAction::Restart => "restart",
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ServiceControlError {
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "freebsd"))]
+    #[error("Not permitted to {action} {unit}, service control is read-only for this agent")]
+    NotPermitted { action: ServiceAction, unit: String },
+    #[cfg(target_os = "linux")]
+    #[error("systemd refused to {action} {unit}: {message}")]
+    Failed {
+        action: ServiceAction,
+        unit: String,
+        message: String,
+    },
+    #[cfg(target_os = "linux")]
+    #[error("D-Bus error: {0}")]
+    DBus(#[from] zbus::Error),
+    #[cfg(target_os = "linux")]
+    #[error("systemctl failed: {0}")]
+    Systemctl(#[from] std::io::Error),
+    #[cfg(target_os = "macos")]
+    #[error("launchd refused to {action} {unit}: {message}")]
+    Launchd {
+        action: ServiceAction,
+        unit: String,
+        message: String,
+    },
+    #[cfg(target_os = "macos")]
+    #[error("launchctl failed: {0}")]
+    Launchctl(std::io::Error),
+    #[cfg(target_os = "freebsd")]
+    #[error("rc.d refused to {action} {unit}: {message}")]
+    Rc {
+        action: ServiceAction,
+        unit: String,
+        message: String,
+    },
+    #[cfg(target_os = "freebsd")]
+    #[error("service failed: {0}")]
+    Service(#[from] std::io::Error),
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+    #[error("Service control is not supported on {0}")]
+    Unsupported(&'static str),
+}
+
+/// Unit names as systemd expects them, plain service names get `.service` appended.
+#[cfg(target_os = "linux")]
+pub fn unit_name(service: &str) -> String {
+    if service.contains('.') {
+        service.to_string()
+    } else {
+        format!("{service}.service")
+    }
+}
+
+/// Starts, stops or restarts a systemd unit.
+#[cfg(target_os = "linux")]
+pub async fn control(service: &str, action: ServiceAction) -> Result<(), ServiceControlError> {
+    let unit = unit_name(service);
+    let conn = match Connection::system().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            info!("[services] System bus unavailable ({}), using systemctl", e);
+            return systemctl(&unit, action).await;
+        }
+    };
+    let reply = conn
+        .call_method(
+            Some(SYSTEMD_DEST),
+            SYSTEMD_PATH,
+            Some(SYSTEMD_MANAGER),
+            action.method(),
+            &(unit.as_str(), "replace"),
+        )
+        .await;
+    match reply {
+        Ok(_) => Ok(()),
+        Err(zbus::Error::MethodError(name, message, _)) => {
+            if DENIED_ERRORS.contains(&name.as_str()) {
+                warn!("[services] Not permitted to {} {}", action, unit);
+                Err(ServiceControlError::NotPermitted { action, unit })
+            } else {
+                Err(ServiceControlError::Failed {
+                    action,
+                    unit,
+                    message: message.unwrap_or_else(|| name.to_string()),
+                })
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Starts, stops or restarts a launchd job.
+#[cfg(target_os = "macos")]
+pub async fn control(service: &str, action: ServiceAction) -> Result<(), ServiceControlError> {
+    crate::lib::launchd::control(service, action).await
+}
+
+/// Starts, stops or restarts an rc.d service, which has to be enabled in rc.conf.
+#[cfg(target_os = "freebsd")]
+pub async fn control(service: &str, action: ServiceAction) -> Result<(), ServiceControlError> {
+    let output = tokio::process::Command::new("service")
+        .arg(service)
+        .arg(action.to_string())
+        .output()
+        .await?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if message.contains("Permission denied") || message.contains("Operation not permitted") {
+        return Err(ServiceControlError::NotPermitted {
+            action,
+            unit: service.to_string(),
+        });
+    }
+    Err(ServiceControlError::Rc {
+        action,
+        unit: service.to_string(),
+        message,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "freebsd")))]
+pub async fn control(_service: &str, _action: ServiceAction) -> Result<(), ServiceControlError> {
+    Err(ServiceControlError::Unsupported(std::env::consts::OS))
+}
+
+#[cfg(target_os = "linux")]
+async fn systemctl(unit: &str, action: ServiceAction) -> Result<(), ServiceControlError> {
+    let output = tokio::process::Command::new("systemctl")
+        .arg(action.to_string())
+        .arg(unit)
+        .output()
+        .await?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    if message.contains("Access denied") || message.contains("Interactive authentication") {
+        return Err(ServiceControlError::NotPermitted {
+            action,
+            unit: unit.to_string(),
+        });
+    }
+    Err(ServiceControlError::Failed {
+        action,
+        unit: unit.to_string(),
+        message,
+    })
+}
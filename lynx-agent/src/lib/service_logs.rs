@@ -0,0 +1,136 @@
+use crate::lib::service_control::unit_name;
+use serde::Serialize;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::process::Command;
+
+/*
+ * Service logs
+ * `servicelogs` websocket messages return the recent journal of a systemd unit, so a failed
+ * unit can be looked into from the dashboard. The agent runs journalctl with JSON output and
+ * answers with one frame per entry, then a frame with the count. Reading the journal of system
+ * units needs the agent's user in the systemd-journal (or adm) group.
+ */
+
+pub const DEFAULT_LINES: u32 = 100;
+pub const MAX_LINES: u32 = 2000;
+/// Messages are cut to this many characters.
+pub const MAX_MESSAGE_CHARS: usize = 4096;
+const JOURNALCTL_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum ServiceLogError {
+    #[error("Invalid unit name {0:?}")]
+    InvalidUnit(String),
+    #[error("Invalid since {0:?}, use e.g. \"1h ago\" or \"2025-01-31 08:00\"")]
+    InvalidSince(String),
+    #[error("Failed to run journalctl: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("journalctl did not answer within {} seconds", JOURNALCTL_TIMEOUT.as_secs())]
+    Timeout,
+    #[error("journalctl failed: {0}")]
+    Failed(String),
+}
+
+/// Frames sent back for a `servicelogs` message.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum LogFrame {
+    #[serde(rename = "servicelog")]
+    Entry {
+        service_name: String,
+        /// Unix milliseconds
+        time: i64,
+        /// syslog priority, 0 (emerg) to 7 (debug)
+        priority: u8,
+        message: String,
+    },
+    #[serde(rename = "servicelogs_end")]
+    End { service_name: String, count: usize },
+    #[serde(rename = "servicelogs_error")]
+    Error { service_name: String, error: String },
+}
+
+/// Unit names systemd accepts, also keeps anything that could pass for an option out.
+fn valid_unit(unit: &str) -> bool {
+    unit.len() <= 256
+        && !unit.starts_with('-')
+        && unit
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ":-_.@\\".contains(c))
+}
+
+/// journalctl's time specs, e.g. `1h ago`, `-30m`, `today` or `2025-01-31 08:00:00`.
+fn valid_since(since: &str) -> bool {
+    !since.trim().is_empty()
+        && since.len() <= 64
+        && since
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || " :-+.".contains(c))
+}
+
+/// One line of `journalctl -o json`, None for lines that aren't a journal entry.
+pub fn parse_entry(service_name: &str, line: &str) -> Option<LogFrame> {
+    let entry: serde_json::Value = serde_json::from_str(line).ok()?;
+    let micros: i64 = entry.get("__REALTIME_TIMESTAMP")?.as_str()?.parse().ok()?;
+    let priority = entry
+        .get("PRIORITY")
+        .and_then(|p| p.as_str())
+        .and_then(|p| p.parse().ok())
+        .unwrap_or(6);
+    // binary messages come as arrays of bytes
+    let message = match entry.get("MESSAGE")? {
+        serde_json::Value::String(message) => message.clone(),
+        serde_json::Value::Array(bytes) => {
+            let bytes: Vec<u8> = bytes
+                .iter()
+                .filter_map(|b| b.as_u64().map(|b| b as u8))
+                .collect();
+            String::from_utf8_lossy(&bytes).into_owned()
+        }
+        _ => return None,
+    };
+    Some(LogFrame::Entry {
+        service_name: service_name.to_string(),
+        time: micros / 1000,
+        priority,
+        message: message.chars().take(MAX_MESSAGE_CHARS).collect(),
+    })
+}
+
+/// The last `lines` journal entries of a unit, oldest first, optionally only those after `since`.
+pub async fn read(
+    service_name: &str,
+    lines: Option<u32>,
+    since: Option<&str>,
+) -> Result<Vec<LogFrame>, ServiceLogError> {
+    let unit = unit_name(service_name);
+    if !valid_unit(&unit) {
+        return Err(ServiceLogError::InvalidUnit(service_name.to_string()));
+    }
+    let lines = lines.unwrap_or(DEFAULT_LINES).clamp(1, MAX_LINES);
+    let mut command = Command::new("journalctl");
+    command
+        .arg(format!("--unit={unit}"))
+        .arg(format!("--lines={lines}"))
+        .args(["--output=json", "--no-pager", "--quiet"])
+        .kill_on_drop(true);
+    if let Some(since) = since {
+        if !valid_since(since) {
+            return Err(ServiceLogError::InvalidSince(since.to_string()));
+        }
+        command.arg(format!("--since={since}"));
+    }
+    let output = tokio::time::timeout(JOURNALCTL_TIMEOUT, command.output())
+        .await
+        .map_err(|_| ServiceLogError::Timeout)??;
+    if !output.status.success() {
+        return Err(ServiceLogError::Failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| parse_entry(service_name, line))
+        .collect())
+}
@@ -0,0 +1,170 @@
+use crate::lib;
+use crate::lib::client::AuthInterceptor;
+use crate::lib::websocket::{self, PeerMap};
+use crate::proto::monitor::control_client::ControlClient;
+use crate::proto::monitor::session_frame::Frame;
+use crate::proto::monitor::SessionFrame;
+use futures_util::{sink, stream};
+use log::{info, warn};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/*
+ * Relayed sessions
+ * Dashboards reach the agent through the hub instead of connecting to its websocket port: the
+ * agent keeps an OpenSessions stream to the hub and serves every session the hub opens on it
+ * like a client of the local websocket server. Only the hub has to reach the agent, and it has
+ * authenticated the dashboard already. The local server keeps running for direct connections.
+ */
+
+const RETRY_MIN: Duration = Duration::from_secs(5);
+const RETRY_MAX: Duration = Duration::from_secs(300);
+/// Frames waiting for the hub, and messages waiting for a session's handler.
+const SESSION_QUEUE: usize = 64;
+
+struct Route {
+    tx: mpsc::Sender<Message>,
+    /// Tunnels are closed rather than losing bytes
+    tunnel: bool,
+}
+
+/*
+ * relay_sessions
+ * Keeps the session stream open, reconnecting with backoff when it breaks. Sessions don't
+ * survive a broken stream, the dashboard reconnects. Gives up for good on hubs that don't
+ * implement the RPC.
+ */
+pub async fn relay_sessions(
+    mut client: ControlClient<InterceptedService<Channel, AuthInterceptor>>,
+    peers: PeerMap,
+) {
+    let mut backoff = RETRY_MIN;
+    loop {
+        let (to_hub, rx) = mpsc::channel::<SessionFrame>(SESSION_QUEUE);
+        let outbound = stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|frame| (frame, rx))
+        });
+        match client.open_sessions(outbound).await {
+            Ok(response) => {
+                backoff = RETRY_MIN;
+                info!("[sessions] Relaying dashboard sessions through the hub");
+                let mut inbound = response.into_inner();
+                let mut sessions: HashMap<String, Route> = HashMap::new();
+                loop {
+                    match inbound.message().await {
+                        Ok(Some(frame)) => route(frame, &mut sessions, &to_hub, &peers),
+                        Ok(None) => {
+                            warn!("[sessions] Hub closed the session stream");
+                            break;
+                        }
+                        Err(status) => {
+                            warn!("[sessions] Session stream failed: {}", status);
+                            break;
+                        }
+                    }
+                }
+                // dropping the senders ends every relayed session
+            }
+            Err(status) if status.code() == Code::Unimplemented => {
+                info!("[sessions] Hub does not relay sessions, dashboards connect directly");
+                return;
+            }
+            Err(status) => warn!("[sessions] Failed to open session stream: {}", status),
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(RETRY_MAX);
+    }
+}
+
+fn route(
+    frame: SessionFrame,
+    sessions: &mut HashMap<String, Route>,
+    to_hub: &mpsc::Sender<SessionFrame>,
+    peers: &PeerMap,
+) {
+    let id = frame.session_id;
+    let message = match frame.frame {
+        Some(Frame::Open(_)) => {
+            let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+            sessions.insert(id.clone(), Route { tx, tunnel: false });
+            tokio::spawn(serve(id, rx, to_hub.clone(), peers.clone()));
+            return;
+        }
+        Some(Frame::Tunnel(open)) => {
+            let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+            sessions.insert(id.clone(), Route { tx, tunnel: true });
+            tokio::spawn(lib::tunnel::serve(id, open, rx, to_hub.clone()));
+            return;
+        }
+        Some(Frame::Close(reason)) => {
+            info!("[sessions] Session {} closed by the hub: {}", id, reason);
+            sessions.remove(&id);
+            return;
+        }
+        Some(Frame::Text(text)) => Message::Text(text.into()),
+        Some(Frame::Binary(data)) => Message::Binary(data.into()),
+        None => return,
+    };
+    let Some(session) = sessions.get(&id) else {
+        return;
+    };
+    if let Err(e) = session.tx.try_send(message) {
+        warn!("[sessions] Dropping message for session {}: {}", id, e);
+        // a tunnel's connection can't skip bytes, dropping the sender ends it
+        if session.tunnel || session.tx.is_closed() {
+            sessions.remove(&id);
+        }
+    }
+}
+
+/// Serves one relayed session until either side closes it.
+async fn serve(
+    id: String,
+    rx: mpsc::Receiver<Message>,
+    to_hub: mpsc::Sender<SessionFrame>,
+    peers: PeerMap,
+) {
+    info!("[sessions] Session {} opened by the hub", id);
+    let incoming = stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|msg| (Ok::<_, WsError>(msg), rx))
+    });
+    let session_id = id.clone();
+    let outgoing = sink::unfold(to_hub.clone(), move |to_hub, msg: Message| {
+        let frame = frame_for(&session_id, msg);
+        async move {
+            if let Some(frame) = frame {
+                to_hub
+                    .send(frame)
+                    .await
+                    .map_err(|_| WsError::ConnectionClosed)?;
+            }
+            Ok::<_, WsError>(to_hub)
+        }
+    });
+    websocket::serve_connection(format!("hub:{id}"), incoming, Box::pin(outgoing), peers).await;
+    let _ = to_hub
+        .send(SessionFrame {
+            session_id: id,
+            frame: Some(Frame::Close("session ended".to_string())),
+        })
+        .await;
+}
+
+/// The frame relaying a websocket message, None for pings and pongs.
+pub fn frame_for(session_id: &str, msg: Message) -> Option<SessionFrame> {
+    let frame = match msg {
+        Message::Text(text) => Frame::Text(text.to_string()),
+        Message::Binary(data) => Frame::Binary(data.to_vec()),
+        Message::Close(_) => Frame::Close("closed by the agent".to_string()),
+        Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => return None,
+    };
+    Some(SessionFrame {
+        session_id: session_id.to_string(),
+        frame: Some(frame),
+    })
+}
@@ -0,0 +1,132 @@
+use crate::proto::monitor::SmartDevice;
+use log::warn;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+/*
+ * SMART
+ * Drive health from `smartctl --json`, read once an hour since attributes move slowly and
+ * waking idle drives for them every minute would do more harm than good. The hub keeps every
+ * reading so rules can compare them over days (`smart.reallocated_sectors.delta_7d > 0`).
+ * Hosts without smartmontools, or an agent without the rights to query the drives, report none.
+ */
+
+pub const SMART_INTERVAL: Duration = Duration::from_secs(3600);
+const SMARTCTL_COMMAND: &str = "smartctl";
+const SMARTCTL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// ATA attribute ids
+const REALLOCATED_SECTOR_COUNT: u64 = 5;
+const CURRENT_PENDING_SECTOR: u64 = 197;
+const OFFLINE_UNCORRECTABLE: u64 = 198;
+
+#[derive(Default)]
+pub struct SmartReader {
+    last_read: Option<Instant>,
+    /// Logged once, smartctl missing stays missing
+    unavailable: bool,
+}
+
+impl SmartReader {
+    /// Every drive's health when SMART_INTERVAL passed since the last read, nothing otherwise.
+    pub async fn read_if_due(&mut self) -> Vec<SmartDevice> {
+        if self
+            .last_read
+            .is_some_and(|at| at.elapsed() < SMART_INTERVAL)
+        {
+            return Vec::new();
+        }
+        self.last_read = Some(Instant::now());
+
+        let Some(scan) = smartctl(&["--scan", "--json"]).await else {
+            if !self.unavailable {
+                warn!("[agent] smartctl is not available, drive health is not reported");
+                self.unavailable = true;
+            }
+            return Vec::new();
+        };
+        self.unavailable = false;
+
+        let mut devices = Vec::new();
+        for (name, kind) in scanned_devices(&scan) {
+            let Some(output) = smartctl(&["--json", "-a", "-d", &kind, &name]).await else {
+                continue;
+            };
+            match parse_device(&name, &output) {
+                Some(device) => devices.push(device),
+                None => warn!("[agent] No SMART data for {name}"),
+            }
+        }
+        devices
+    }
+}
+
+/// smartctl's JSON output, None when it can't run. The exit status is a bit mask that is
+/// set for failing drives too, so it is left to the caller to make sense of the output.
+async fn smartctl(args: &[&str]) -> Option<Value> {
+    let output = tokio::time::timeout(
+        SMARTCTL_TIMEOUT,
+        Command::new(SMARTCTL_COMMAND)
+            .args(args)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .ok()?
+    .ok()?;
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// Device names and types from `smartctl --scan --json`.
+pub fn scanned_devices(scan: &Value) -> Vec<(String, String)> {
+    scan["devices"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|d| {
+            let name = d["name"].as_str()?;
+            let kind = d["type"].as_str().unwrap_or("auto");
+            Some((name.to_string(), kind.to_string()))
+        })
+        .collect()
+}
+
+/*
+ * parse_device
+ * One drive from `smartctl --json -a`. ATA drives report sector counts as attributes, NVMe
+ * drives a health log with media errors and wear instead. Output without a SMART status (a
+ * drive that doesn't support it, or smartctl failing to open it) yields None.
+ */
+pub fn parse_device(name: &str, output: &Value) -> Option<SmartDevice> {
+    let passed = output["smart_status"]["passed"].as_bool()?;
+    let attribute = |id: u64| {
+        output["ata_smart_attributes"]["table"]
+            .as_array()?
+            .iter()
+            .find(|a| a["id"].as_u64() == Some(id))?["raw"]["value"]
+            .as_u64()
+    };
+    let nvme = &output["nvme_smart_health_information_log"];
+    Some(SmartDevice {
+        device: name.to_string(),
+        model: output["model_name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        serial: output["serial_number"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        passed,
+        reallocated_sectors: attribute(REALLOCATED_SECTOR_COUNT),
+        pending_sectors: attribute(CURRENT_PENDING_SECTOR),
+        uncorrectable_sectors: attribute(OFFLINE_UNCORRECTABLE),
+        media_errors: nvme["media_errors"].as_u64(),
+        percentage_used: nvme["percentage_used"]
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok()),
+        temperature: output["temperature"]["current"].as_f64(),
+        power_on_hours: output["power_on_time"]["hours"].as_u64(),
+    })
+}
@@ -0,0 +1,289 @@
+use crate::lib::collectors::CollectorRequest;
+use crate::proto::monitor::{
+    ContainerMetricsRequest, ContainerRequest, GpuMetricsRequest, GpuRequest, MetricsRequest,
+    SystemInfoRequest, SystemctlRequest,
+};
+use chrono::Utc;
+use log::{error, info, warn};
+use lynx_agent_sdk::connection::HubConnection;
+use prost::Message;
+use serde::Deserialize;
+use sqlx::Row;
+use sqlx::sqlite::SqlitePool;
+use std::time::Duration;
+use thiserror::Error;
+
+/*
+ * Spool
+ * Reports the hub can't take right now (it is unreachable, too slow or asked to hold back) are
+ * queued in the cache database and sent again in order once it is back, instead of being
+ * dropped. While anything is queued new reports go in behind it, so the hub gets them in the
+ * order they were collected. The queue survives restarts and is bounded by the `[spool]`
+ * section: reports older than max_age_secs go first, then the oldest ones until it fits in
+ * max_bytes. Metrics, GPU and container samples keep their collected_at_ms, so replayed ones
+ * are filed under the time they were collected.
+ */
+
+/// How often a non-empty queue is replayed.
+pub const REPLAY_INTERVAL: Duration = Duration::from_secs(5);
+/// Reports sent per replay, so new ones aren't held up behind a long backlog.
+const REPLAY_BATCH: i64 = 50;
+
+#[derive(Error, Debug)]
+pub enum SpoolError {
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("Failed to decode queued report: {0}")]
+    Decode(#[from] prost::DecodeError),
+    #[error("Unknown report kind: {0}")]
+    UnknownKind(String),
+}
+
+/// Optional `[spool]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SpoolConfig {
+    /// false drops reports while the hub is away
+    pub enabled: bool,
+    pub max_bytes: u64,
+    pub max_age_secs: u64,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_bytes: 64 * 1024 * 1024,
+            max_age_secs: 86400,
+        }
+    }
+}
+
+pub struct Spool {
+    pool: SqlitePool,
+    config: SpoolConfig,
+    entries: u64,
+    bytes: u64,
+}
+
+impl Spool {
+    pub async fn open(pool: SqlitePool, config: SpoolConfig) -> Result<Self, SpoolError> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS spool (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                kind TEXT NOT NULL,
+                payload BLOB NOT NULL,
+                queued_at_ms INTEGER NOT NULL
+            )
+            "#,
+        )
+        .execute(&pool)
+        .await?;
+
+        let mut spool = Self {
+            pool,
+            config,
+            entries: 0,
+            bytes: 0,
+        };
+        spool.prune().await?;
+        if spool.entries > 0 {
+            info!(
+                "[spool] {} reports ({} bytes) queued before the restart",
+                spool.entries, spool.bytes
+            );
+        }
+        Ok(spool)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /*
+     * report
+     * Sends the report unless older ones are still queued, and queues it when the hub can't take
+     * it right now. It is encoded before it goes out since sending consumes it.
+     */
+    pub async fn report(&mut self, hub: &mut HubConnection, request: CollectorRequest) {
+        if !self.config.enabled {
+            if let Err(e) = hub.report(request).await {
+                error!("[agent] Error handling collector request: {}", e);
+            }
+            return;
+        }
+        let (kind, payload) = encode(&request);
+        if self.is_empty() {
+            match hub.report(request).await {
+                Ok(()) => return,
+                Err(e) if e.is_transient() => {
+                    warn!("[spool] Hub can't take reports, queueing them: {}", e)
+                }
+                Err(e) => {
+                    error!("[agent] Error handling collector request: {}", e);
+                    return;
+                }
+            }
+        }
+        if let Err(e) = self.push(kind, payload).await {
+            error!("[spool] Failed to queue {} report: {}", kind, e);
+        }
+    }
+
+    /*
+     * replay
+     * Sends up to REPLAY_BATCH queued reports oldest first and stops at the first one the hub
+     * can't take yet. Reports the hub rejects are dropped, they would fail the same way again.
+     */
+    pub async fn replay(&mut self, hub: &mut HubConnection) {
+        if let Err(e) = self.try_replay(hub).await {
+            error!("[spool] Failed to replay queued reports: {}", e);
+        }
+    }
+
+    async fn try_replay(&mut self, hub: &mut HubConnection) -> Result<(), SpoolError> {
+        self.prune().await?;
+        let rows = sqlx::query("SELECT id, kind, payload FROM spool ORDER BY id LIMIT ?")
+            .bind(REPLAY_BATCH)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut sent = 0;
+        for row in rows {
+            let id: i64 = row.get("id");
+            let kind: String = row.get("kind");
+            let payload: Vec<u8> = row.get("payload");
+            match decode(&kind, &payload) {
+                Ok(request) => match hub.report(request).await {
+                    Ok(()) => sent += 1,
+                    Err(e) if e.is_transient() => break,
+                    Err(e) => warn!("[spool] Hub rejected a queued {} report: {}", kind, e),
+                },
+                Err(e) => warn!("[spool] Dropping queued report {}: {}", id, e),
+            }
+            self.remove(id, payload.len() as u64).await?;
+        }
+
+        if sent > 0 {
+            info!(
+                "[spool] Replayed {} reports, {} still queued",
+                sent, self.entries
+            );
+        }
+        Ok(())
+    }
+
+    async fn push(&mut self, kind: &str, payload: Vec<u8>) -> Result<(), SpoolError> {
+        let size = payload.len() as u64;
+        sqlx::query("INSERT INTO spool (kind, payload, queued_at_ms) VALUES (?, ?, ?)")
+            .bind(kind)
+            .bind(payload)
+            .bind(Utc::now().timestamp_millis())
+            .execute(&self.pool)
+            .await?;
+        self.entries += 1;
+        self.bytes += size;
+        if self.bytes > self.config.max_bytes {
+            self.prune().await?;
+        }
+        Ok(())
+    }
+
+    async fn remove(&mut self, id: i64, size: u64) -> Result<(), SpoolError> {
+        sqlx::query("DELETE FROM spool WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        self.entries = self.entries.saturating_sub(1);
+        self.bytes = self.bytes.saturating_sub(size);
+        Ok(())
+    }
+
+    /// Drops reports older than max_age_secs, then the oldest ones until the rest fit in
+    /// max_bytes.
+    async fn prune(&mut self) -> Result<(), SpoolError> {
+        let cutoff = Utc::now().timestamp_millis()
+            - i64::try_from(self.config.max_age_secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+        let expired = sqlx::query("DELETE FROM spool WHERE queued_at_ms < ?")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        self.count().await?;
+
+        let mut over = 0;
+        if self.bytes > self.config.max_bytes {
+            let excess = i64::try_from(self.bytes - self.config.max_bytes).unwrap_or(i64::MAX);
+            // everything up to the first report at which the running total covers the excess
+            over = sqlx::query(
+                r#"
+                DELETE FROM spool WHERE id <= (
+                    SELECT id FROM (
+                        SELECT id, SUM(LENGTH(payload)) OVER (ORDER BY id) AS total FROM spool
+                    )
+                    WHERE total >= ? ORDER BY id LIMIT 1
+                )
+                "#,
+            )
+            .bind(excess)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+            self.count().await?;
+        }
+
+        if expired > 0 || over > 0 {
+            warn!(
+                "[spool] Dropped {} reports older than {}s and {} over the {} byte limit",
+                expired, self.config.max_age_secs, over, self.config.max_bytes
+            );
+        }
+        Ok(())
+    }
+
+    async fn count(&mut self) -> Result<(), SpoolError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) AS entries, COALESCE(SUM(LENGTH(payload)), 0) AS bytes FROM spool",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        self.entries = row.get::<i64, _>("entries") as u64;
+        self.bytes = row.get::<i64, _>("bytes") as u64;
+        Ok(())
+    }
+}
+
+/// The kind a report is queued under and its protobuf encoding.
+fn encode(request: &CollectorRequest) -> (&'static str, Vec<u8>) {
+    match request {
+        CollectorRequest::Metrics(metrics) => ("metrics", metrics.encode_to_vec()),
+        CollectorRequest::SystemInfo(info) => ("system_info", info.encode_to_vec()),
+        CollectorRequest::Systemctl(systemctl) => ("systemctl", systemctl.encode_to_vec()),
+        CollectorRequest::GpuInfo(gpus) => ("gpu_info", gpus.encode_to_vec()),
+        CollectorRequest::GpuMetrics(metrics) => ("gpu_metrics", metrics.encode_to_vec()),
+        CollectorRequest::ContainerInfo(containers) => {
+            ("container_info", containers.encode_to_vec())
+        }
+        CollectorRequest::ContainerMetrics(metrics) => {
+            ("container_metrics", metrics.encode_to_vec())
+        }
+    }
+}
+
+fn decode(kind: &str, payload: &[u8]) -> Result<CollectorRequest, SpoolError> {
+    Ok(match kind {
+        "metrics" => CollectorRequest::Metrics(Box::new(MetricsRequest::decode(payload)?)),
+        "system_info" => {
+            CollectorRequest::SystemInfo(Box::new(SystemInfoRequest::decode(payload)?))
+        }
+        "systemctl" => CollectorRequest::Systemctl(SystemctlRequest::decode(payload)?),
+        "gpu_info" => CollectorRequest::GpuInfo(GpuRequest::decode(payload)?),
+        "gpu_metrics" => CollectorRequest::GpuMetrics(GpuMetricsRequest::decode(payload)?),
+        "container_info" => CollectorRequest::ContainerInfo(ContainerRequest::decode(payload)?),
+        "container_metrics" => {
+            CollectorRequest::ContainerMetrics(ContainerMetricsRequest::decode(payload)?)
+        }
+        _ => return Err(SpoolError::UnknownKind(kind.to_string())),
+    })
+}
@@ -1,13 +1,24 @@
 use crate::proto::monitor::{
-    Component, CpuStats, DiskStats, LoadAverage, MemoryStats, MetricsRequest, NetworkStats,
-    SystemInfoRequest, SystemctlRequest,
+    Component, CpuStats, DiskStats, KernelStats, LoadAverage, MemoryStats, MetricsRequest,
+    NetworkStats, ProcessStats, SystemInfoRequest, TopProcess,
 };
+#[cfg(target_os = "linux")]
+use crate::proto::monitor::SystemctlRequest;
+#[cfg(target_os = "linux")]
+use crate::lib::cache::FastCache;
+use crate::lib::network;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 #[cfg(target_os = "linux")]
 use std::str::FromStr;
-use sysinfo::{Components, Networks, System};
-use systemctl::{ActiveState, UnitService};
-#[cfg(not(target_os = "windows"))]
+use std::sync::OnceLock;
+use std::time::Instant;
+use sysinfo::{
+    Components, CpuRefreshKind, MemoryRefreshKind, Process, ProcessRefreshKind, ProcessStatus,
+    ProcessesToUpdate, RefreshKind, System,
+};
+#[cfg(target_os = "linux")]
+use systemctl::ActiveState;
 use systemstat::Platform;
 
 macro_rules! to_kb {
@@ -15,11 +26,6 @@ macro_rules! to_kb {
         $x / 1024
     };
 }
-macro_rules! to_mb {
-    ($x:expr) => {
-        $x / 1024 / 1024
-    };
-}
 macro_rules! to_gb {
     ($x:expr) => {
         $x / 1024 / 1024 / 1024
@@ -54,7 +60,161 @@ pub struct Metrics {
     pub load_average: LoadAverage,
 }
 
-pub async fn collect_system_info(system: &mut System) -> SystemInfoRequest {
+/// Optional `[disks]` section. Mount points ending in `*` match as a prefix.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DiskConfig {
+    pub exclude_mount_points: Vec<String>,
+    pub exclude_fs_types: Vec<String>,
+    /// Bind mounts repeat a filesystem that is already reported at its first mount point
+    pub exclude_bind_mounts: bool,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            exclude_mount_points: vec!["/var/lib/docker/*".to_string(), "/snap/*".to_string()],
+            exclude_fs_types: [
+                "tmpfs",
+                "devtmpfs",
+                "overlay",
+                "squashfs",
+                "nsfs",
+                "ramfs",
+                "devfs",
+                "fdescfs",
+                "procfs",
+                "linprocfs",
+                "linsysfs",
+            ]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+            exclude_bind_mounts: true,
+        }
+    }
+}
+
+impl DiskConfig {
+    fn excludes(&self, mount_point: &str, fs_type: &str) -> bool {
+        self.exclude_fs_types.iter().any(|t| t == fs_type)
+            || self
+                .exclude_mount_points
+                .iter()
+                .any(|m| match m.strip_suffix('*') {
+                    Some(prefix) => mount_point.starts_with(prefix),
+                    None => m == mount_point,
+                })
+    }
+}
+
+static DISK_FILTER: OnceLock<DiskConfig> = OnceLock::new();
+
+/// Sets the disk filter from config.toml, collections before this use the defaults.
+pub fn set_disk_filter(config: DiskConfig) {
+    if DISK_FILTER.set(config).is_err() {
+        log::warn!("[agent] Disk filter already set, ignoring");
+    }
+}
+
+/// Counters from the previous metrics pass, so the next one can report rates over the time
+/// that actually elapsed.
+#[derive(Default, Debug)]
+pub struct Rates {
+    disks: HashMap<String, DiskCounters>,
+    /// When the previous pass read the network rate
+    network: Option<Instant>,
+    /// Pages swapped (in, out) and when they were read
+    swap: Option<(u64, u64, Instant)>,
+    cpu: Option<CpuTimes>,
+    /// Context switches and interrupts since boot and when they were read
+    kernel: Option<(u64, u64, Instant)>,
+}
+
+/// Aggregate jiffies from the `cpu` line of /proc/stat.
+#[derive(Clone, Copy, Debug, Default)]
+struct CpuTimes {
+    user: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    steal: u64,
+}
+
+impl CpuTimes {
+    fn total(&self) -> u64 {
+        self.user + self.system + self.idle + self.iowait + self.irq + self.steal
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct DiskCounters {
+    read_bytes: u64,
+    written_bytes: u64,
+    /// Completed (reads, writes), only where the OS exposes them
+    ops: Option<(u64, u64)>,
+    at: Instant,
+}
+
+/// Per second increase of a counter, zero when it went backwards (device reset or replaced).
+fn per_sec(current: u64, previous: u64, elapsed: f64) -> f64 {
+    if current < previous || elapsed <= 0.0 {
+        return 0.0;
+    }
+    (current - previous) as f64 / elapsed
+}
+
+/*
+ * detect_virtualization
+ * Asks systemd-detect-virt first, which knows most hypervisors and container runtimes. Without
+ * systemd only docker and the cpuinfo hypervisor flag are recognised.
+ */
+#[cfg(target_os = "linux")]
+async fn detect_virtualization() -> String {
+    if let Ok(output) = tokio::process::Command::new("systemd-detect-virt")
+        .output()
+        .await
+    {
+        // exits non-zero when printing "none"
+        let virt = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !virt.is_empty() {
+            return virt;
+        }
+    }
+    if std::path::Path::new("/.dockerenv").exists() {
+        return "docker".to_string();
+    }
+    match std::fs::read_to_string("/proc/cpuinfo") {
+        Ok(cpuinfo) if cpuinfo.contains(" hypervisor") => "vm".to_string(),
+        Ok(_) => "none".to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+async fn detect_virtualization() -> String {
+    String::new()
+}
+
+/*
+ * new_system
+ * A System kept across collections, so CPU usage is the average since the previous one and
+ * each pass only refreshes what it reads instead of rescanning everything like System::new_all.
+ */
+pub fn new_system() -> System {
+    System::new_with_specifics(
+        RefreshKind::nothing()
+            .with_cpu(CpuRefreshKind::nothing().with_cpu_usage())
+            .with_memory(MemoryRefreshKind::everything()),
+    )
+}
+
+pub async fn collect_system_info(
+    system: &mut System,
+    tags: &HashMap<String, String>,
+) -> SystemInfoRequest {
+    system.refresh_memory();
     let hostname = sysinfo::System::host_name().unwrap_or(String::from(""));
     let os_info = sysinfo::System::long_os_version().unwrap_or(String::from(""));
     let kernal_version = System::kernel_version().unwrap_or(String::from(""));
@@ -77,17 +237,34 @@ pub async fn collect_system_info(system: &mut System) -> SystemInfoRequest {
         uptime_seconds: uptime,
         cpu_model: build_specs.cpu_model,
         cpu_count: build_specs.cpu_cores as u32,
+        agent_version: env!("CARGO_PKG_VERSION").to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        virtualization: detect_virtualization().await,
+        tags: tags.clone(),
     }
 }
 
-pub async fn collect_systemctl_services() -> SystemctlRequest {
+/*
+ * collect_systemctl_services
+ * The services that changed since they were cached, every service on a full sync, and the
+ * units from `known` that are no longer listed as removed. `known` is replaced by the units
+ * listed now. A failed listing reports nothing, so it is never taken for all units being gone.
+ */
+#[cfg(target_os = "linux")]
+pub async fn collect_systemctl_services(
+    cache: &FastCache,
+    known: &mut HashSet<String>,
+    full_sync: bool,
+) -> SystemctlRequest {
     let systemctl = systemctl::SystemCtl::default();
     let units = systemctl.list_units_full(Some("service"), None, None);
     let mut changed_services = vec![];
+    let mut listed = HashSet::new();
 
     match units {
         Ok(units) => {
             for unit in units {
+                listed.insert(unit.unit_name.clone());
                 // Get current active state and other info
                 let active_state = systemctl
                     .get_active_state(&unit.unit_name)
@@ -109,7 +286,7 @@ pub async fn collect_systemctl_services() -> SystemctlRequest {
                     memory_usage: memory,
                 };
                 // Check cache
-                /* let cached = cache
+                let cached = cache
                     .get_system_service(&unit.unit_name)
                     .await
                     .unwrap_or(None);
@@ -118,54 +295,251 @@ pub async fn collect_systemctl_services() -> SystemctlRequest {
                     let _ = cache
                         .set_system_service(&service, Some(chrono::Duration::minutes(10)))
                         .await;
-                    changed_services.push(unit.clone());
-                }*/
+                } else if !full_sync {
+                    continue;
+                }
+                // built from what was just queried, units are neither copied nor queried twice
+                changed_services.push(crate::proto::monitor::SystemService {
+                    service_name: service.name,
+                    description: unit.description,
+                    state: format!("{:?}", unit.active),
+                    pid: service.pid.unwrap_or(0),
+                    cpu: service.cpu_usage.unwrap_or_else(|| "unknown".to_string()),
+                    memory: service
+                        .memory_usage
+                        .unwrap_or_else(|| "unknown".to_string()),
+                    ..Default::default()
+                });
             }
         }
         Err(e) => {
             println!("Failed to list systemctl units: {}", e);
+            return SystemctlRequest::default();
+        }
+    }
+    let mut removed: Vec<String> = known.difference(&listed).cloned().collect();
+    removed.sort();
+    *known = listed;
+
+    let names: Vec<&str> = changed_services
+        .iter()
+        .map(|s| s.service_name.as_str())
+        .collect();
+    let mut dependencies = unit_dependencies(&names).await;
+    for service in &mut changed_services {
+        if let Some(deps) = dependencies.remove(&service.service_name) {
+            service.requires = deps.requires;
+            service.wants = deps.wants;
+            service.after = deps.after;
+        }
+    }
+
+    SystemctlRequest {
+        services: changed_services,
+        removed,
+        full_sync,
+    }
+}
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct UnitDependencies {
+    pub requires: Vec<String>,
+    pub wants: Vec<String>,
+    pub after: Vec<String>,
+}
+
+/*
+ * unit_dependencies
+ * Requires, Wants and After of the given units from a single `systemctl show`. Only units sent
+ * to the hub are asked for, so dependencies changed by a daemon-reload alone reach the hub with
+ * the next full sync. Without systemctl every unit simply has none.
+ */
+#[cfg(target_os = "linux")]
+async fn unit_dependencies(units: &[&str]) -> HashMap<String, UnitDependencies> {
+    if units.is_empty() {
+        return HashMap::new();
+    }
+    let output = tokio::process::Command::new("systemctl")
+        .args([
+            "show", "-p", "Id", "-p", "Requires", "-p", "Wants", "-p", "After", "--",
+        ])
+        .args(units)
+        .output()
+        .await;
+    match output {
+        Ok(output) if output.status.success() => {
+            parse_unit_dependencies(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => {
+            log::warn!(
+                "[agent] systemctl show failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            HashMap::new()
+        }
+        Err(e) => {
+            log::warn!("[agent] Failed to run systemctl show: {e}");
+            HashMap::new()
         }
     }
+}
 
-    let services = changed_services
-        .into_iter()
-        .map(|unit: UnitService| {
-            let unit_props = systemctl.create_unit(&unit.unit_name).ok();
-            crate::proto::monitor::SystemService {
-                service_name: unit.unit_name.clone(),
-                description: unit.description,
-                state: format!("{:?}", unit.active),
-                pid: unit_props.as_ref().and_then(|p| p.pid).unwrap_or(0),
-                cpu: unit_props
-                    .as_ref()
-                    .and_then(|p| p.cpu.clone())
-                    .unwrap_or_else(|| "unknown".to_string()),
-                memory: unit_props
-                    .as_ref()
-                    .and_then(|p| p.memory.clone())
-                    .unwrap_or_else(|| "unknown".to_string()),
+/// `systemctl show` output: one block of `Key=value` lines per unit, separated by blank lines.
+#[cfg(target_os = "linux")]
+pub fn parse_unit_dependencies(output: &str) -> HashMap<String, UnitDependencies> {
+    let mut units = HashMap::new();
+    for block in output.split("\n\n") {
+        let mut id = None;
+        let mut deps = UnitDependencies::default();
+        for line in block.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let names = || value.split_whitespace().map(str::to_string).collect();
+            match key {
+                "Id" => id = Some(value.trim().to_string()),
+                "Requires" => deps.requires = names(),
+                "Wants" => deps.wants = names(),
+                "After" => deps.after = names(),
+                _ => {}
             }
-        })
+        }
+        if let Some(id) = id.filter(|id| !id.is_empty()) {
+            units.insert(id, deps);
+        }
+    }
+    units
+}
+
+#[cfg(target_os = "linux")]
+fn read_cpu_times() -> Option<CpuTimes> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    let line = stat.lines().find(|l| l.starts_with("cpu "))?;
+    let fields: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|f| u64::from_str(f).unwrap_or(0))
         .collect();
-    SystemctlRequest { services }
+    let field = |i: usize| fields.get(i).copied().unwrap_or(0);
+    // user nice system idle iowait irq softirq steal, guest time is already part of user
+    Some(CpuTimes {
+        user: field(0) + field(1),
+        system: field(2),
+        idle: field(3),
+        iowait: field(4),
+        irq: field(5) + field(6),
+        steal: field(7),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_cpu_times() -> Option<CpuTimes> {
+    None
 }
-fn collect_cpu_stats(system: &System) -> CpuStats {
+
+/*
+ * collect_cpu_stats
+ * Overall usage comes from sysinfo, the user/system/iowait/irq/steal breakdown from /proc/stat
+ * deltas since the previous pass where available.
+ */
+fn collect_cpu_stats(system: &System, rates: &mut Rates) -> CpuStats {
     let cpu_usage = system
         .cpus()
         .iter()
         .fold(0.0, |acc, cpu| acc + cpu.cpu_usage())
         / system.cpus().len() as f32;
-    CpuStats {
+    let mut stats = CpuStats {
         usage_percent: cpu_usage as f64,
+        ..Default::default()
+    };
+
+    let Some(current) = read_cpu_times() else {
+        return stats;
+    };
+    if let Some(previous) = rates.cpu.replace(current) {
+        let total = current.total().saturating_sub(previous.total());
+        if total > 0 {
+            let share = |now: u64, before: u64| {
+                Some(now.saturating_sub(before) as f64 / total as f64 * 100.0)
+            };
+            stats.user_percent = share(current.user, previous.user);
+            stats.system_percent = share(current.system, previous.system);
+            stats.iowait_percent = share(current.iowait, previous.iowait);
+            stats.irq_percent = share(current.irq, previous.irq);
+            stats.steal_percent = share(current.steal, previous.steal);
+        }
     }
+    stats
 }
 
-fn collect_memory_stats(system: &System) -> MemoryStats {
-    MemoryStats {
+/// Fields of a `key: value [kB]` file like /proc/meminfo or `key value` like /proc/vmstat.
+#[cfg(target_os = "linux")]
+fn read_proc_fields(path: &str) -> HashMap<String, u64> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let key = parts.next()?.trim_end_matches(':');
+            let value = u64::from_str(parts.next()?).ok()?;
+            Some((key.to_string(), value))
+        })
+        .collect()
+}
+
+/// Pages swapped in and out since boot.
+#[cfg(target_os = "linux")]
+fn read_swap_pages() -> Option<(u64, u64)> {
+    let vmstat = read_proc_fields("/proc/vmstat");
+    Some((*vmstat.get("pswpin")?, *vmstat.get("pswpout")?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_swap_pages() -> Option<(u64, u64)> {
+    None
+}
+
+/*
+ * collect_memory_stats
+ * Reads /proc/meminfo on Linux for the cache/buffer breakdown, everything else falls back to
+ * what sysinfo offers. Swap activity is a rate over the time since the previous pass.
+ */
+fn collect_memory_stats(system: &System, rates: &mut Rates) -> MemoryStats {
+    let mut stats = MemoryStats {
         total_kb: to_kb!(system.total_memory()),
         used_kb: to_kb!(system.used_memory()),
         free_kb: to_kb!(system.free_memory()),
+        available_kb: Some(to_kb!(system.available_memory())),
+        swap_total_kb: Some(to_kb!(system.total_swap())),
+        swap_used_kb: Some(to_kb!(system.used_swap())),
+        ..Default::default()
+    };
+
+    #[cfg(target_os = "linux")]
+    {
+        let meminfo = read_proc_fields("/proc/meminfo");
+        let field = |key: &str| meminfo.get(key).copied();
+        stats.available_kb = field("MemAvailable").or(stats.available_kb);
+        stats.cached_kb = field("Cached");
+        stats.buffers_kb = field("Buffers");
+        stats.dirty_kb = field("Dirty");
+        if let (Some(total), Some(free)) = (field("SwapTotal"), field("SwapFree")) {
+            stats.swap_total_kb = Some(total);
+            stats.swap_used_kb = Some(total.saturating_sub(free));
+        }
     }
+
+    if let Some((swapped_in, swapped_out)) = read_swap_pages() {
+        let now = Instant::now();
+        if let Some((prev_in, prev_out, at)) = rates.swap.replace((swapped_in, swapped_out, now)) {
+            let elapsed = now.duration_since(at).as_secs_f64();
+            stats.swap_in_per_sec = Some(per_sec(swapped_in, prev_in, elapsed));
+            stats.swap_out_per_sec = Some(per_sec(swapped_out, prev_out, elapsed));
+        }
+    }
+    stats
 }
 
 #[cfg(target_os = "windows")]
@@ -174,7 +548,7 @@ fn collect_component_stats() -> Vec<Component> {
     Vec::new()
 }
 
-#[cfg(not(target_os = "windows"))]
+#[cfg(not(any(target_os = "windows", target_os = "freebsd")))]
 fn collect_component_stats() -> Vec<Component> {
     let components = Components::new_with_refreshed_list();
     components
@@ -189,27 +563,275 @@ fn collect_component_stats() -> Vec<Component> {
         .collect()
 }
 
-async fn collect_disk_stats() -> Vec<DiskStats> {
+/*
+ * collect_component_stats
+ * FreeBSD exposes temperatures as sysctls: dev.cpu.N.temperature with coretemp(4) or amdtemp(4)
+ * loaded, hw.acpi.thermal.tzN.temperature for ACPI thermal zones. Missing ones are skipped.
+ */
+#[cfg(target_os = "freebsd")]
+fn collect_component_stats() -> Vec<Component> {
+    // -q leaves out the trees that don't exist, the rest is printed regardless
+    match std::process::Command::new("sysctl")
+        .args(["-q", "-e", "dev.cpu", "hw.acpi.thermal"])
+        .output()
+    {
+        Ok(output) => parse_sysctl_temperatures(&String::from_utf8_lossy(&output.stdout)),
+        Err(e) => {
+            log::warn!("[agent] Failed to run sysctl: {e}");
+            Vec::new()
+        }
+    }
+}
+
+/// `sysctl -e` lines such as `dev.cpu.0.temperature=45.0C`, other sysctls are ignored.
+#[cfg(target_os = "freebsd")]
+pub fn parse_sysctl_temperatures(output: &str) -> Vec<Component> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once('=')?;
+            let sensor = name.strip_suffix(".temperature")?;
+            let temperature = value.trim().trim_end_matches('C').parse::<f32>().ok()?;
+            let label = if let Some(cpu) = sensor.strip_prefix("dev.cpu.") {
+                format!("CPU {cpu}")
+            } else {
+                format!(
+                    "ACPI {}",
+                    sensor.strip_prefix("hw.acpi.thermal.").unwrap_or(sensor)
+                )
+            };
+            Some(Component { label, temperature })
+        })
+        .collect()
+}
+
+/// Completed reads and writes per block device from /proc/diskstats.
+#[cfg(target_os = "linux")]
+fn read_disk_ops() -> HashMap<String, (u64, u64)> {
+    let Ok(stats) = std::fs::read_to_string("/proc/diskstats") else {
+        return HashMap::new();
+    };
+    stats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let reads = u64::from_str(fields.get(3)?).ok()?;
+            let writes = u64::from_str(fields.get(7)?).ok()?;
+            Some((fields[2].to_string(), (reads, writes)))
+        })
+        .collect()
+}
+
+/// Completed reads and writes per device since boot from devstat(9), as `iostat -x -I` reports them.
+#[cfg(target_os = "freebsd")]
+fn read_disk_ops() -> HashMap<String, (u64, u64)> {
+    match std::process::Command::new("iostat")
+        .args(["-x", "-I", "-d", "-c", "1"])
+        .output()
+    {
+        Ok(output) if output.status.success() => {
+            parse_iostat_totals(&String::from_utf8_lossy(&output.stdout))
+        }
+        Ok(output) => {
+            log::warn!(
+                "[agent] iostat failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            HashMap::new()
+        }
+        Err(e) => {
+            log::warn!("[agent] Failed to run iostat: {e}");
+            HashMap::new()
+        }
+    }
+}
+
+/// `iostat -x -I` output: the `device r/i w/i ...` header, then a line of totals per device.
+#[cfg(target_os = "freebsd")]
+pub fn parse_iostat_totals(output: &str) -> HashMap<String, (u64, u64)> {
+    let mut columns = None;
+    let mut ops = HashMap::new();
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.first() == Some(&"device") {
+            let column = |name: &str| fields.iter().position(|f| *f == name);
+            columns = column("r/i").zip(column("w/i"));
+            continue;
+        }
+        let Some((reads, writes)) = columns else {
+            continue;
+        };
+        let count = |i: usize| fields.get(i)?.parse::<f64>().ok().map(|v| v as u64);
+        if let (Some(name), Some(reads), Some(writes)) =
+            (fields.first(), count(reads), count(writes))
+        {
+            ops.insert(name.to_string(), (reads, writes));
+        }
+    }
+    ops
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+fn read_disk_ops() -> HashMap<String, (u64, u64)> {
+    HashMap::new()
+}
+
+/// Reads and writes of a disk, those of its whole device where partitions aren't counted.
+fn disk_ops(ops: &HashMap<String, (u64, u64)>, device: &str) -> Option<(u64, u64)> {
+    ops.get(device)
+        .or_else(|| ops.get(whole_disk(device)))
+        .copied()
+}
+
+/*
+ * whole_disk
+ * The device a FreeBSD partition is on, devstat only counts whole devices: ada0p2 (GPT), ada0s1
+ * (MBR slice) and ada0s1a (partition in a slice) are all on ada0. Other names come back as
+ * they are.
+ */
+fn whole_disk(device: &str) -> &str {
+    // a partition letter follows its slice number
+    let slice = match device.as_bytes() {
+        [.., b'0'..=b'9', b'a'..=b'h'] => &device[..device.len() - 1],
+        _ => device,
+    };
+    let number = slice.trim_end_matches(|c: char| c.is_ascii_digit());
+    if number.len() == slice.len() {
+        return device;
+    }
+    let disk = if slice.len() < device.len() {
+        number.strip_suffix('s')
+    } else {
+        number
+            .strip_suffix('p')
+            .or_else(|| number.strip_suffix('s'))
+    };
+    match disk {
+        Some(disk) if disk.ends_with(|c: char| c.is_ascii_digit()) => disk,
+        _ => device,
+    }
+}
+
+/*
+ * read_bind_mounts
+ * Mount points of a device that is already mounted earlier in /proc/self/mountinfo. Covers bind
+ * mounts as well as further btrfs subvolumes, which report the same space as the first mount.
+ */
+#[cfg(target_os = "linux")]
+fn read_bind_mounts() -> HashSet<String> {
+    let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+        return HashSet::new();
+    };
+    let mut devices = HashSet::new();
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (device, mount_point) = (fields.get(2)?, fields.get(4)?);
+            (!devices.insert(device.to_string())).then(|| mount_point.replace("\\040", " "))
+        })
+        .collect()
+}
+
+/// nullfs mounts, FreeBSD's bind mounts, from the fstab formatted `mount -p`.
+#[cfg(target_os = "freebsd")]
+fn read_bind_mounts() -> HashSet<String> {
+    let Ok(output) = std::process::Command::new("mount")
+        .args(["-p", "-t", "nullfs"])
+        .output()
+    else {
+        return HashSet::new();
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .map(|mount_point| mount_point.replace("\\040", " "))
+        .collect()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+fn read_bind_mounts() -> HashSet<String> {
+    HashSet::new()
+}
+
+/*
+ * collect_disk_stats
+ * Reports read/write throughput (and IOPS where available) per disk as rates over the time since
+ * the `previous` pass, and returns the counters for the next one. A disk seen for the first time
+ * reports zero until the next pass. Mounts excluded by the `[disks]` config are skipped entirely.
+ * Blocking, statvfs on a dead network mount can take a while.
+ */
+fn collect_disk_stats(
+    previous: &HashMap<String, DiskCounters>,
+) -> (Vec<DiskStats>, HashMap<String, DiskCounters>) {
     let sys_disks = sysinfo::Disks::new_with_refreshed_list();
+    let filter = DISK_FILTER.get_or_init(DiskConfig::default);
+    let bind_mounts = if filter.exclude_bind_mounts {
+        read_bind_mounts()
+    } else {
+        HashSet::new()
+    };
+    let ops = read_disk_ops();
+    let statvfs = systemstat::System::new();
+    let now = Instant::now();
+    let mut current = HashMap::new();
     let disks = sys_disks
         .iter()
+        .filter(|d| {
+            let mount_point = d.mount_point().to_string_lossy();
+            !bind_mounts.contains(mount_point.as_ref())
+                && !filter.excludes(&mount_point, &d.file_system().to_string_lossy())
+        })
         .map(|d| {
             let name = d.name().to_string_lossy().into_owned();
             let mount_point = d.mount_point().to_str().unwrap_or("").to_string();
             let total_space = d.total_space();
             let available_space = d.available_space();
+            let counters = DiskCounters {
+                read_bytes: d.usage().total_read_bytes,
+                written_bytes: d.usage().total_written_bytes,
+                ops: disk_ops(&ops, name.trim_start_matches("/dev/")),
+                at: now,
+            };
+            // Filesystems without a fixed inode table (and Windows) report zero files
+            let inodes = statvfs
+                .mount_at(&mount_point)
+                .ok()
+                .filter(|fs| fs.files_total > 0)
+                .map(|fs| (fs.files_total as u64, fs.files as u64));
+            let previous = previous.get(&name).copied();
+            current.insert(name.clone(), counters);
+
+            let elapsed = previous.map_or(0.0, |p| now.duration_since(p.at).as_secs_f64());
+            let rate = |current: u64, previous: u64| per_sec(current, previous, elapsed);
+            let iops = match (counters.ops, previous.and_then(|p| p.ops)) {
+                (Some((reads, writes)), Some((prev_reads, prev_writes))) => (
+                    Some(rate(reads, prev_reads)),
+                    Some(rate(writes, prev_writes)),
+                ),
+                (Some(_), None) => (Some(0.0), Some(0.0)),
+                (None, _) => (None, None),
+            };
             DiskStats {
                 name,
                 used_space: to_gb!(total_space - available_space) as i32,
                 total_space: to_gb!(total_space) as i32,
-                read_bytes: d.usage().total_read_bytes as f64,
-                write_bytes: d.usage().total_written_bytes as f64,
+                read_bytes: previous.map_or(0.0, |p| rate(counters.read_bytes, p.read_bytes)),
+                write_bytes: previous
+                    .map_or(0.0, |p| rate(counters.written_bytes, p.written_bytes)),
                 unit: "gb".to_string(),
                 mount_point,
+                read_iops: iops.0,
+                write_iops: iops.1,
+                inodes_total: inodes.map(|(total, _)| total),
+                inodes_used: inodes.map(|(_, used)| used),
+                total_bytes: Some(total_space),
+                used_bytes: Some(total_space.saturating_sub(available_space)),
             }
         })
         .collect();
-    disks
+    // Disks that disappeared are dropped instead of accumulating
+    (disks, current)
 }
 
 #[cfg(target_os = "windows")]
@@ -222,7 +844,8 @@ fn collect_load_average(_system: &System) -> LoadAverage {
     }
 }
 
-fn collect_load_average(system: &System) -> LoadAverage {
+#[cfg(not(target_os = "windows"))]
+fn collect_load_average(_system: &System) -> LoadAverage {
     let load = System::load_average();
     LoadAverage {
         one_minute: load.one,
@@ -231,36 +854,151 @@ fn collect_load_average(system: &System) -> LoadAverage {
     }
 }
 
-async fn collect_network_stats() -> NetworkStats {
-    let get_network_totals = |networks: &sysinfo::Networks| {
-        networks
-            .values()
-            .fold((0, 0), |(mut in_acc, mut out_acc), net| {
-                in_acc += net.total_received();
-                out_acc += net.total_transmitted();
-                (in_acc, out_acc)
-            })
-    };
-    let (net_in, net_out) = get_network_totals(&Networks::new_with_refreshed_list());
-    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-    let (net_in2, net_out2) = get_network_totals(&Networks::new_with_refreshed_list());
+/*
+ * collect_network_stats
+ * Throughput in bytes/s across all interfaces since the previous pass, from the shared sampler
+ * (see lib::network), with whole MB/s for older hubs. The first pass gets the rate over the
+ * last sample.
+ */
+fn collect_network_stats(rates: &mut Rates) -> NetworkStats {
+    let now = Instant::now();
+    let window = rates
+        .network
+        .replace(now)
+        .map_or(network::SAMPLE_INTERVAL, |previous| {
+            now.duration_since(previous)
+        });
+    let rate = network::sampler().rate(window);
     NetworkStats {
-        r#in: to_mb!(net_in2 - net_in),
-        out: to_mb!(net_out2 - net_out),
+        r#in: (rate.received / 1024.0 / 1024.0) as u64,
+        out: (rate.transmitted / 1024.0 / 1024.0) as u64,
+        in_bytes_per_sec: Some(rate.received),
+        out_bytes_per_sec: Some(rate.transmitted),
     }
 }
 
-pub async fn collect_metrics(system: &mut System) -> MetricsRequest {
-    system.refresh_cpu_all();
-    system.refresh_memory();
-    tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+/// Processes reported by CPU and by memory each pass, a process in both lists is sent once
+const TOP_PROCESSES: usize = 10;
+
+/*
+ * collect_process_stats
+ * sysinfo lists threads (tasks) next to processes on Linux, they are counted separately so
+ * `total` only covers processes and `threads` every schedulable task. The busiest processes
+ * are reported too, CPU usage is measured since the previous pass so the first reports 0.
+ */
+fn collect_process_stats(system: &mut System) -> (ProcessStats, Vec<TopProcess>) {
+    // disk usage, command lines and environments aren't reported
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing()
+            .with_tasks()
+            .with_cpu()
+            .with_memory(),
+    );
+    let mut stats = ProcessStats::default();
+    let mut processes = Vec::new();
+    for process in system.processes().values() {
+        stats.threads += 1;
+        if process.thread_kind().is_some() {
+            continue;
+        }
+        stats.total += 1;
+        match process.status() {
+            ProcessStatus::Run => stats.running += 1,
+            ProcessStatus::Zombie => stats.zombie += 1,
+            _ => {}
+        }
+        processes.push(process);
+    }
+    (stats, top_processes(processes))
+}
 
-    let cpu_stats = collect_cpu_stats(system);
-    let memory_stats = collect_memory_stats(system);
-    let components = collect_component_stats();
+fn top_processes(mut processes: Vec<&Process>) -> Vec<TopProcess> {
+    processes.sort_by(|a, b| b.cpu_usage().total_cmp(&a.cpu_usage()));
+    let mut top: Vec<&Process> = processes.iter().take(TOP_PROCESSES).copied().collect();
+    processes.sort_by_key(|p| std::cmp::Reverse(p.memory()));
+    for process in processes.into_iter().take(TOP_PROCESSES) {
+        if !top.iter().any(|p| p.pid() == process.pid()) {
+            top.push(process);
+        }
+    }
+    top.into_iter()
+        .map(|p| TopProcess {
+            pid: p.pid().as_u32(),
+            name: p.name().to_string_lossy().into_owned(),
+            cpu_usage: p.cpu_usage(),
+            memory_kb: to_kb!(p.memory()),
+        })
+        .collect()
+}
+
+/*
+ * collect_kernel_stats
+ * Context switch and interrupt rates, tasks blocked on I/O and the entropy pool from /proc.
+ * Linux only, other platforms report nothing.
+ */
+#[cfg(target_os = "linux")]
+fn collect_kernel_stats(rates: &mut Rates) -> Option<KernelStats> {
+    let stat = read_proc_fields("/proc/stat");
+    let context_switches = *stat.get("ctxt")?;
+    let interrupts = *stat.get("intr")?;
+    let now = Instant::now();
+    let (context_switches_per_sec, interrupts_per_sec) =
+        match rates.kernel.replace((context_switches, interrupts, now)) {
+            Some((prev_ctxt, prev_intr, at)) => {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                (
+                    per_sec(context_switches, prev_ctxt, elapsed),
+                    per_sec(interrupts, prev_intr, elapsed),
+                )
+            }
+            None => (0.0, 0.0),
+        };
+    let entropy_avail = std::fs::read_to_string("/proc/sys/kernel/random/entropy_avail")
+        .ok()
+        .and_then(|v| u32::from_str(v.trim()).ok());
+    Some(KernelStats {
+        context_switches_per_sec,
+        interrupts_per_sec,
+        procs_blocked: stat.get("procs_blocked").copied().unwrap_or(0) as u32,
+        entropy_avail,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn collect_kernel_stats(_rates: &mut Rates) -> Option<KernelStats> {
+    None
+}
+
+/// Metrics since the previous call with `system`, callers keep calls MINIMUM_CPU_UPDATE_INTERVAL
+/// apart for a meaningful CPU usage.
+/*
+ * collect_metrics
+ * Disks and sensors are read on the blocking pool while CPU, memory, processes, network and
+ * kernel stats are read here, so a pass takes as long as its slowest part and every sample is
+ * stamped with the time the pass started.
+ */
+pub async fn collect_metrics(system: &mut System, rates: &mut Rates) -> MetricsRequest {
+    let collected_at = chrono::Utc::now();
+    let previous_disks = std::mem::take(&mut rates.disks);
+    let disks = tokio::task::spawn_blocking(move || collect_disk_stats(&previous_disks));
+    let components = tokio::task::spawn_blocking(collect_component_stats);
+
+    system.refresh_cpu_usage();
+    system.refresh_memory();
+    let cpu_stats = collect_cpu_stats(system, rates);
+    let memory_stats = collect_memory_stats(system, rates);
     let load_average = collect_load_average(system);
-    let disk_stats = collect_disk_stats().await;
-    let network_stats = collect_network_stats().await;
+    let network_stats = collect_network_stats(rates);
+    let (process_stats, top_processes) = collect_process_stats(system);
+    let kernel_stats = collect_kernel_stats(rates);
+
+    let (disks, components) = tokio::join!(disks, components);
+    // a panicked task reports nothing this pass, disks start over on the next
+    let (disk_stats, disk_counters) = disks.unwrap_or_default();
+    rates.disks = disk_counters;
+    let components = components.unwrap_or_default();
 
     MetricsRequest {
         cpu_stats: Some(cpu_stats),
@@ -269,5 +1007,15 @@ pub async fn collect_metrics(system: &mut System) -> MetricsRequest {
         components,
         network_stats: Some(network_stats),
         load_average: Some(load_average),
+        cert_expiry_days: crate::lib::client::cert_expiry_days(),
+        process_stats: Some(process_stats),
+        kernel_stats,
+        collected_at_ms: Some(collected_at.timestamp_millis()),
+        probe_results: Vec::new(),
+        custom_metrics: Vec::new(),
+        top_processes,
+        smart_devices: Vec::new(),
+        // stamped by the connection when it goes out
+        sent_at_ms: None,
     }
 }
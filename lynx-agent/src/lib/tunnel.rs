@@ -0,0 +1,184 @@
+use crate::lib;
+use crate::proto::monitor::session_frame::Frame;
+use crate::proto::monitor::{SessionFrame, TunnelOpen};
+use log::{info, warn};
+use serde::Deserialize;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::OnceLock;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+/*
+ * Tunnels
+ * The hub can open a TCP tunnel on the session stream to reach a port on this host, e.g. a
+ * local admin UI, after an admin granted it for a limited time. The agent connects to the port
+ * on its loopback and copies bytes between the connection and the session's binary frames.
+ * Only ports listed in the `[tunnels]` section of config.toml can be reached, without any
+ * tunnels are refused. Every tunnel ends up in the command audit with the bytes it carried.
+ */
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_BUFFER: usize = 16 * 1024;
+
+/// Optional `[tunnels]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TunnelConfig {
+    /// Loopback ports the hub may open tunnels to, empty disables tunnels
+    pub allowed_ports: Vec<u16>,
+    /// Tunnels are closed after this long, whatever the hub granted
+    pub max_secs: u64,
+}
+
+impl Default for TunnelConfig {
+    fn default() -> Self {
+        Self {
+            allowed_ports: Vec::new(),
+            max_secs: 3600,
+        }
+    }
+}
+
+static TUNNEL_CONFIG: OnceLock<TunnelConfig> = OnceLock::new();
+
+/// Sets the tunnel policy from config.toml, tunnels before this are refused.
+pub fn set_tunnel_config(config: TunnelConfig) {
+    if TUNNEL_CONFIG.set(config).is_err() {
+        warn!("[tunnel] Tunnel policy already set, ignoring");
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TunnelError {
+    #[error("Tunnels are disabled on this agent, see [tunnels] in config.toml")]
+    Disabled,
+    #[error("Port {0} is not in the agent's allowed tunnel ports")]
+    NotAllowed(u32),
+    #[error("Failed to connect to port {port}: {error}")]
+    Connect { port: u32, error: std::io::Error },
+    #[error("Tunnel failed: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The loopback address and how long a tunnel may stay open, if the policy allows it.
+pub fn permit(
+    open: &TunnelOpen,
+    config: &TunnelConfig,
+) -> Result<(SocketAddr, Duration), TunnelError> {
+    if config.allowed_ports.is_empty() {
+        return Err(TunnelError::Disabled);
+    }
+    let port = u16::try_from(open.port)
+        .ok()
+        .filter(|port| config.allowed_ports.contains(port))
+        .ok_or(TunnelError::NotAllowed(open.port))?;
+    let ttl = Duration::from_secs(u64::from(open.ttl_secs).min(config.max_secs));
+    Ok((SocketAddr::from((Ipv4Addr::LOCALHOST, port)), ttl))
+}
+
+async fn connect(open: &TunnelOpen) -> Result<(TcpStream, Duration), TunnelError> {
+    let config = TUNNEL_CONFIG.get().ok_or(TunnelError::Disabled)?;
+    let (addr, ttl) = permit(open, config)?;
+    let connect_error = |error| TunnelError::Connect {
+        port: open.port,
+        error,
+    };
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+        .await
+        .map_err(|_| connect_error(std::io::ErrorKind::TimedOut.into()))?
+        .map_err(connect_error)?;
+    Ok((stream, ttl))
+}
+
+/// Copies bytes both ways until either side closes or `ttl` ran out, returns the bytes each way.
+async fn copy(
+    id: &str,
+    mut stream: TcpStream,
+    ttl: Duration,
+    rx: &mut mpsc::Receiver<Message>,
+    to_hub: &mpsc::Sender<SessionFrame>,
+) -> Result<(u64, u64), TunnelError> {
+    let (mut reader, mut writer) = stream.split();
+    let expired = tokio::time::sleep(ttl);
+    tokio::pin!(expired);
+    let mut buf = vec![0; READ_BUFFER];
+    let (mut to_host, mut from_host) = (0, 0);
+    loop {
+        tokio::select! {
+            _ = &mut expired => {
+                info!("[tunnel] Tunnel {} reached its TTL", id);
+                break;
+            }
+            message = rx.recv() => match message {
+                Some(Message::Binary(data)) => {
+                    writer.write_all(&data).await?;
+                    to_host += data.len() as u64;
+                }
+                Some(Message::Close(_)) | None => break,
+                Some(_) => {}
+            },
+            read = reader.read(&mut buf) => {
+                let n = read?;
+                if n == 0 {
+                    break;
+                }
+                from_host += n as u64;
+                let frame = SessionFrame {
+                    session_id: id.to_string(),
+                    frame: Some(Frame::Binary(buf[..n].to_vec())),
+                };
+                if to_hub.send(frame).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = writer.shutdown().await;
+    Ok((to_host, from_host))
+}
+
+/// Serves one tunnel the hub opened until either side closes it.
+pub async fn serve(
+    id: String,
+    open: TunnelOpen,
+    mut rx: mpsc::Receiver<Message>,
+    to_hub: mpsc::Sender<SessionFrame>,
+) {
+    let mut entry = lib::audit::started(
+        &format!("hub:{id}"),
+        "tunnel",
+        &open.port.to_string(),
+        vec![format!("ttl={}s", open.ttl_secs)],
+    );
+    let result = match connect(&open).await {
+        Ok((stream, ttl)) => {
+            info!(
+                "[tunnel] Tunnel {} opened to port {} for {}s",
+                id,
+                open.port,
+                ttl.as_secs()
+            );
+            copy(&id, stream, ttl, &mut rx, &to_hub).await
+        }
+        Err(e) => Err(e),
+    };
+    let reason = match &result {
+        Ok((to_host, from_host)) => {
+            entry.args.push(format!("sent={to_host}"));
+            entry.args.push(format!("received={from_host}"));
+            "tunnel closed".to_string()
+        }
+        Err(e) => e.to_string(),
+    };
+    let _ = to_hub
+        .send(SessionFrame {
+            session_id: id,
+            frame: Some(Frame::Close(reason)),
+        })
+        .await;
+    lib::audit::finish(entry, None, result.err().map(|e| e.to_string()));
+}
@@ -0,0 +1,170 @@
+use log::{error, info, warn};
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/*
+ * Uninstall
+ * The hub decommissions a system by pushing a config with `decommission` set, operators can
+ * also send a `delete` websocket message. Either way the agent removes what the install script
+ * put on the host: its service, certs/, config.toml, crashes/, the cache database and its own binary,
+ * then exits without the service manager bringing it back. The hub revokes the agent key
+ * on its side, so a copy left behind could not report anymore.
+ */
+
+pub const SERVICE_NAME: &str = "lynx-view-agent";
+
+#[cfg(unix)]
+const SYSTEMD_UNIT: &str = "/etc/systemd/system/lynx-view-agent.service";
+#[cfg(unix)]
+const OPENRC_SCRIPT: &str = "/etc/init.d/lynx-view-agent";
+#[cfg(unix)]
+const RC_D_SCRIPT: &str = "/usr/local/etc/rc.d/lynx_view_agent";
+
+lazy_static::lazy_static! {
+    static ref UNINSTALL: Notify = Notify::new();
+}
+
+/// Asks the task started by `run_on_request` to uninstall the agent.
+pub fn request() {
+    UNINSTALL.notify_one();
+}
+
+/// Waits for an uninstall request, then removes the agent and exits.
+pub async fn run_on_request(cache_database_url: String) {
+    UNINSTALL.notified().await;
+    warn!("[uninstall] Decommissioned, removing the agent from this host");
+    // let the websocket reply and the last log lines go out
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    remove_files(&cache_database_url);
+    remove_service();
+    info!("[uninstall] Agent removed");
+    std::process::exit(0);
+}
+
+/// The file behind a `sqlite://path?options` url, None for in-memory databases.
+fn sqlite_path(database_url: &str) -> Option<&str> {
+    let path = database_url.strip_prefix("sqlite://")?;
+    let path = path.split('?').next().unwrap_or(path);
+    (!path.is_empty() && path != ":memory:").then_some(path)
+}
+
+fn remove(path: &Path) {
+    let result = if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    match result {
+        Ok(()) => info!("[uninstall] Removed {}", path.display()),
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => error!("[uninstall] Failed to remove {}: {}", path.display(), e),
+    }
+}
+
+/*
+ * remove_files
+ * Everything the agent keeps lives in its working directory (the install script's config
+ * directory), which is removed as well once nothing else is left in it.
+ */
+fn remove_files(cache_database_url: &str) {
+    let Ok(dir) = env::current_dir() else {
+        error!("[uninstall] Working directory is gone, leaving files in place");
+        return;
+    };
+    remove(&dir.join("certs"));
+    remove(&dir.join("config.toml"));
+    remove(&dir.join(crate::lib::crash::CRASH_DIR));
+    if let Some(cache) = sqlite_path(cache_database_url) {
+        let cache = dir.join(cache);
+        remove(&cache);
+        for suffix in ["-wal", "-shm"] {
+            let mut journal = cache.clone().into_os_string();
+            journal.push(suffix);
+            remove(Path::new(&journal));
+        }
+    }
+    // only succeeds when empty, anything the operator put there stays
+    let _ = fs::remove_dir(&dir);
+
+    // Windows can't delete a running executable, remove_service does it after the stop
+    #[cfg(unix)]
+    match env::current_exe() {
+        Ok(exe) => remove(&exe),
+        Err(e) => error!("[uninstall] Failed to locate the agent binary: {}", e),
+    }
+}
+
+#[cfg(unix)]
+fn run(program: &str, args: &[&str]) {
+    match Command::new(program).args(args).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("[uninstall] {} {:?} exited with {}", program, args, status),
+        Err(e) => warn!("[uninstall] Failed to run {}: {}", program, e),
+    }
+}
+
+/*
+ * remove_service
+ * Unregisters the service so it is neither restarted now nor started at boot. The final stop
+ * is left to the service manager in the background, it ends this process.
+ */
+#[cfg(unix)]
+fn remove_service() {
+    if Path::new(SYSTEMD_UNIT).exists() {
+        run("systemctl", &["disable", SERVICE_NAME]);
+        remove(Path::new(SYSTEMD_UNIT));
+        run("systemctl", &["daemon-reload"]);
+        run("systemctl", &["stop", "--no-block", SERVICE_NAME]);
+    } else if Path::new(OPENRC_SCRIPT).exists() {
+        run("rc-update", &["del", SERVICE_NAME, "default"]);
+        // supervise-daemon would respawn the agent, the stop has to outlive it
+        use std::os::unix::process::CommandExt;
+        let script = format!("rc-service {SERVICE_NAME} stop; rm -f {OPENRC_SCRIPT}");
+        if let Err(e) = Command::new("sh")
+            .args(["-c", script.as_str()])
+            .process_group(0)
+            .spawn()
+        {
+            error!("[uninstall] Failed to stop the OpenRC service: {}", e);
+        }
+    } else if Path::new(RC_D_SCRIPT).exists() {
+        // FreeBSD, daemon(8) supervises the agent like supervise-daemon does
+        run("sysrc", &["-x", "lynx_view_agent_enable"]);
+        use std::os::unix::process::CommandExt;
+        let script = format!("service lynx_view_agent stop; rm -f {RC_D_SCRIPT}");
+        if let Err(e) = Command::new("sh")
+            .args(["-c", script.as_str()])
+            .process_group(0)
+            .spawn()
+        {
+            error!("[uninstall] Failed to stop the rc.d service: {}", e);
+        }
+    } else {
+        info!("[uninstall] No service installed, exiting");
+    }
+}
+
+#[cfg(windows)]
+fn remove_service() {
+    use std::os::windows::process::CommandExt;
+    const DETACHED_PROCESS: u32 = 0x0000_0008;
+    let exe = env::current_exe()
+        .map(|exe| exe.display().to_string())
+        .unwrap_or_default();
+    // nssm stop ends this process, the detached shell finishes the job
+    let script = format!(
+        "nssm stop {SERVICE_NAME} & nssm remove {SERVICE_NAME} confirm & del /f /q \"{exe}\""
+    );
+    if let Err(e) = Command::new("cmd")
+        .args(["/C", script.as_str()])
+        .creation_flags(DETACHED_PROCESS)
+        .spawn()
+    {
+        error!("[uninstall] Failed to remove the NSSM service: {}", e);
+    }
+}
@@ -0,0 +1,308 @@
+use crate::lib::client::AuthInterceptor;
+use crate::proto::monitor::control_client::ControlClient;
+use crate::proto::monitor::{ReleaseManifest, ReleaseRequest};
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Notify;
+use tonic::codegen::InterceptedService;
+use tonic::transport::Channel;
+use tonic::Code;
+use x509_parser::prelude::FromDer;
+use x509_parser::x509::SubjectPublicKeyInfo;
+
+/*
+ * Self-update
+ * With an [update] channel set, the agent asks the hub for the newest build on it every
+ * check_interval_secs (and when an `update` websocket message without url arrives). The hub
+ * answers with a manifest signed by the release key; the agent checks the signature against
+ * certs/release.pub, downloads the binary, compares it to the manifest's sha256 and replaces
+ * itself, then exits so systemd restarts it on the new version.
+ */
+
+lazy_static::lazy_static! {
+    static ref CHECK_NOW: Notify = Notify::new();
+}
+
+/// Optional `[update]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UpdateConfig {
+    /// stable, beta, or off to only update on an explicit websocket message
+    pub channel: String,
+    pub check_interval_secs: u64,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            channel: "stable".to_string(),
+            check_interval_secs: 3600,
+        }
+    }
+}
+
+/// The signed manifest of a release, see ReleaseManifest in types.proto.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Release {
+    pub channel: String,
+    pub version: String,
+    pub os: String,
+    pub arch: String,
+    pub url: String,
+    pub sha256: String,
+    pub published: String,
+}
+
+#[derive(Error, Debug)]
+pub enum UpdateError {
+    #[error("Release public key {0:?} not found, updates are disabled without it")]
+    NoPublicKey(PathBuf),
+    #[error("Invalid release public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("Signature of {0} does not match the release public key")]
+    BadSignature(String),
+    #[error("Invalid release manifest: {0}")]
+    InvalidManifest(String),
+    #[error("Checksum of {0} does not match the release manifest")]
+    ChecksumMismatch(String),
+    #[error("Download failed: {0}")]
+    Download(#[from] reqwest::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Raw Ed25519 key from the PEM encoded SubjectPublicKeyInfo written by the install script.
+fn load_release_key() -> Result<Vec<u8>, UpdateError> {
+    let path = PathBuf::from(
+        env::var("LYNX_RELEASE_KEY_PATH").unwrap_or_else(|_| "certs/release.pub".to_string()),
+    );
+    let pem = fs::read(&path).map_err(|_| UpdateError::NoPublicKey(path.clone()))?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&pem)
+        .map_err(|e| UpdateError::InvalidPublicKey(e.to_string()))?;
+    let (_, spki) = SubjectPublicKeyInfo::from_der(&pem.contents)
+        .map_err(|e| UpdateError::InvalidPublicKey(e.to_string()))?;
+    Ok(spki.subject_public_key.data.to_vec())
+}
+
+/*
+ * verify_release
+ * Checks a raw Ed25519 signature, as produced by
+ *   openssl pkeyutl -sign -rawin -inkey release.key -in lynx-agent -out lynx-agent.sig
+ */
+pub fn verify_release(public_key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, public_key)
+        .verify(data, signature)
+        .is_ok()
+}
+
+/*
+ * verify_manifest
+ * Parses a manifest from the hub, but only after its signature checked out, and makes sure it
+ * is meant for this channel, OS and architecture.
+ */
+pub fn verify_manifest(
+    public_key: &[u8],
+    manifest: &ReleaseManifest,
+    channel: &str,
+    os: &str,
+    arch: &str,
+) -> Result<Release, UpdateError> {
+    if !verify_release(public_key, &manifest.manifest, &manifest.signature) {
+        return Err(UpdateError::BadSignature("release manifest".to_string()));
+    }
+    let release: Release = serde_json::from_slice(&manifest.manifest)
+        .map_err(|e| UpdateError::InvalidManifest(e.to_string()))?;
+    if release.channel != channel || release.os != os || release.arch != arch {
+        return Err(UpdateError::InvalidManifest(format!(
+            "got {} for {} {}, asked for {channel} for {os} {arch}",
+            release.channel, release.os, release.arch
+        )));
+    }
+    Ok(release)
+}
+
+/// Numeric dot separated parts, then a pre-release suffix sorts before the plain version.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    fn split(version: &str) -> (Vec<u64>, Option<&str>) {
+        let version = version.trim().trim_start_matches('v');
+        let (core, pre) = match version.split_once('-') {
+            Some((core, pre)) => (core, Some(pre)),
+            None => (version, None),
+        };
+        let parts = core.split('.').map(|p| p.parse().unwrap_or(0)).collect();
+        (parts, pre)
+    }
+    let (a_parts, a_pre) = split(a);
+    let (b_parts, b_pre) = split(b);
+    let len = a_parts.len().max(b_parts.len());
+    for i in 0..len {
+        let order = a_parts
+            .get(i)
+            .unwrap_or(&0)
+            .cmp(b_parts.get(i).unwrap_or(&0));
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    match (a_pre, b_pre) {
+        (None, None) => Ordering::Equal,
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (Some(a), Some(b)) => a.cmp(b),
+    }
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Makes the update loop check its channel right away.
+pub fn request_check() {
+    CHECK_NOW.notify_one();
+}
+
+/*
+ * run_update_checks
+ * Polls the hub for the newest release on the configured channel and installs it when it is
+ * newer than the running agent. Stops for good when the channel is off or the hub has no
+ * GetRelease RPC.
+ */
+pub async fn run_update_checks(
+    mut client: ControlClient<InterceptedService<Channel, AuthInterceptor>>,
+    config: UpdateConfig,
+) {
+    if config.channel == "off" {
+        info!("[update] Update channel is off, only websocket updates are applied");
+        return;
+    }
+    let mut tick = tokio::time::interval(Duration::from_secs(config.check_interval_secs.max(60)));
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = CHECK_NOW.notified() => {}
+        }
+        let request = ReleaseRequest {
+            channel: config.channel.clone(),
+            arch: env::consts::ARCH.to_string(),
+            os: env::consts::OS.to_string(),
+        };
+        let manifest = match client.get_release(request).await {
+            Ok(response) => response.into_inner(),
+            Err(status) if status.code() == Code::Unimplemented => {
+                info!("[update] Hub does not offer release channels");
+                return;
+            }
+            Err(status) if status.code() == Code::NotFound => {
+                info!("[update] No {} release published yet", config.channel);
+                continue;
+            }
+            Err(status) => {
+                warn!("[update] Release check failed: {}", status);
+                continue;
+            }
+        };
+        match install_release(&manifest, &config.channel).await {
+            Ok(Some(version)) => {
+                info!("[update] Installed {version}, restarting");
+                // systemd restarts the agent on the new binary
+                std::process::exit(0);
+            }
+            Ok(None) => {}
+            Err(e) => error!("[update] Update failed: {}", e),
+        }
+    }
+}
+
+/// The version installed, None when the agent already runs the newest one.
+async fn install_release(
+    manifest: &ReleaseManifest,
+    channel: &str,
+) -> Result<Option<String>, UpdateError> {
+    let public_key = load_release_key()?;
+    let release = verify_manifest(
+        &public_key,
+        manifest,
+        channel,
+        env::consts::OS,
+        env::consts::ARCH,
+    )?;
+    let current = env!("CARGO_PKG_VERSION");
+    if compare_versions(&release.version, current) != Ordering::Greater {
+        return Ok(None);
+    }
+    info!(
+        "[update] {} {} is available (running {current})",
+        release.channel, release.version
+    );
+    let binary = reqwest::Client::new()
+        .get(&release.url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    if sha256_hex(&binary) != release.sha256.to_lowercase() {
+        return Err(UpdateError::ChecksumMismatch(release.url));
+    }
+    install_binary(&binary)?;
+    Ok(Some(release.version))
+}
+
+/*
+ * apply_update
+ * Downloads the binary at `url` together with `<url>.sig` and replaces the running executable,
+ * but only when the signature verifies against the pinned release key. The caller exits
+ * afterwards so systemd restarts the agent on the new binary.
+ */
+pub async fn apply_update(url: &str) -> Result<(), UpdateError> {
+    let public_key = load_release_key()?;
+    let client = reqwest::Client::new();
+    let binary = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+    let signature = client
+        .get(format!("{url}.sig"))
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    if !verify_release(&public_key, &binary, &signature) {
+        warn!("[agent] Refusing update from {url}, signature mismatch");
+        return Err(UpdateError::BadSignature(url.to_string()));
+    }
+
+    install_binary(&binary)?;
+    info!("[agent] Installed signed update from {url}");
+    Ok(())
+}
+
+/// Replaces the running executable, the new one takes effect on the next start.
+fn install_binary(binary: &[u8]) -> Result<(), UpdateError> {
+    // write next to the current binary so the rename stays on one filesystem
+    let current = env::current_exe()?;
+    let staged = current.with_extension("new");
+    fs::write(&staged, binary)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&staged, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::rename(&staged, &current)?;
+    Ok(())
+}
@@ -0,0 +1,399 @@
+use crate::lib;
+use base64::Engine;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use thiserror::Error;
+use tokio::sync::mpsc::{self, Sender};
+use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
+
+/*
+ * File uploads
+ * `upload` websocket messages write a file on the host, for config pushes and small artifacts.
+ * A file arrives in chunks at increasing offsets, the first one carries the size, mode and
+ * sha256 of the whole file. Chunks are written to a temporary file next to the target, which
+ * replaces the target only once the checksum matched. Only directories listed in the `[upload]`
+ * section of config.toml (and below) can be written to, without any uploads are refused.
+ * Each connection has a writer task taking its chunks in order, the writes run on a blocking
+ * thread rather than the websocket task and outside the lock on the uploads in progress.
+ */
+
+/// Chunks waiting for a connection's writer, further ones are refused while it is full.
+pub const UPLOAD_QUEUE: usize = 16;
+
+/// Optional `[upload]` section of config.toml.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// Directories uploads may write into, empty disables uploads
+    pub allowed_dirs: Vec<PathBuf>,
+    pub max_bytes: u64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            allowed_dirs: Vec::new(),
+            max_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+static UPLOAD_CONFIG: OnceLock<UploadConfig> = OnceLock::new();
+
+/// Sets the upload policy from config.toml, uploads before this are refused.
+pub fn set_upload_config(config: UploadConfig) {
+    if UPLOAD_CONFIG.set(config).is_err() {
+        warn!("[upload] Upload policy already set, ignoring");
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UploadError {
+    #[error("Uploads are disabled on this agent, see [upload] in config.toml")]
+    Disabled,
+    #[error("Invalid path {0:?}, it has to be absolute and name a file")]
+    InvalidPath(String),
+    #[error("{0:?} is not in an allowed upload directory")]
+    NotAllowed(String),
+    #[error("File of {size} bytes is larger than the limit of {max}")]
+    TooLarge { size: u64, max: u64 },
+    #[error("Invalid mode {0:?}, use octal permissions such as \"0644\"")]
+    InvalidMode(String),
+    #[error("The first chunk has to carry the file's sha256")]
+    MissingChecksum,
+    #[error("Chunk is not valid base64: {0}")]
+    InvalidData(#[from] base64::DecodeError),
+    #[error("No upload in progress for this path")]
+    NotStarted,
+    #[error("Expected the chunk at offset {expected}, got {got}")]
+    UnexpectedOffset { expected: u64, got: u64 },
+    #[error("Received more than the announced {0} bytes")]
+    Overrun(u64),
+    #[error("Checksum mismatch, the file was not written")]
+    ChecksumMismatch,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// One `upload` message.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Chunk {
+    pub path: String,
+    pub offset: u64,
+    /// Size of the whole file
+    pub size: u64,
+    /// base64 of this chunk's bytes
+    #[serde(default)]
+    pub data: String,
+    /// Octal, first chunk only, 0644 when left out
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// Hex sha256 of the whole file, first chunk only
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// Answers to `upload` messages.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum UploadFrame {
+    #[serde(rename = "upload_progress")]
+    Progress {
+        path: String,
+        received: u64,
+        size: u64,
+    },
+    #[serde(rename = "upload_done")]
+    Done {
+        path: String,
+        size: u64,
+        sha256: String,
+    },
+    #[serde(rename = "upload_error")]
+    Error { path: String, error: String },
+}
+
+struct Upload {
+    target: PathBuf,
+    staged: PathBuf,
+    file: File,
+    received: u64,
+    size: u64,
+    mode: u32,
+    sha256: String,
+    digest: ring::digest::Context,
+}
+
+lazy_static::lazy_static! {
+    /// Uploads in progress by peer and path
+    static ref UPLOADS: Mutex<HashMap<(String, String), Upload>> = Mutex::new(HashMap::new());
+}
+
+pub fn parse_mode(mode: Option<&str>) -> Result<u32, UploadError> {
+    let Some(text) = mode else {
+        return Ok(0o644);
+    };
+    match u32::from_str_radix(text.trim_start_matches("0o"), 8) {
+        // no setuid, setgid or sticky bits
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err(UploadError::InvalidMode(text.to_string())),
+    }
+}
+
+/*
+ * resolve
+ * The file `path` names, with its directory resolved through symlinks, if that directory is one
+ * of `allowed` or below one. The directory has to exist, uploads don't create any.
+ */
+pub fn resolve(path: &str, allowed: &[PathBuf]) -> Result<PathBuf, UploadError> {
+    let invalid = || UploadError::InvalidPath(path.to_string());
+    let requested = Path::new(path);
+    if !requested.is_absolute()
+        || requested
+            .components()
+            .any(|c| matches!(c, Component::ParentDir | Component::CurDir))
+    {
+        return Err(invalid());
+    }
+    let name = requested.file_name().ok_or_else(invalid)?;
+    let dir = fs::canonicalize(requested.parent().ok_or_else(invalid)?)
+        .map_err(|_| UploadError::NotAllowed(path.to_string()))?;
+    let permitted = allowed
+        .iter()
+        .filter_map(|allowed| fs::canonicalize(allowed).ok())
+        .any(|allowed| dir.starts_with(allowed));
+    if !permitted {
+        return Err(UploadError::NotAllowed(path.to_string()));
+    }
+    Ok(dir.join(name))
+}
+
+fn start(chunk: &Chunk, config: &UploadConfig) -> Result<Upload, UploadError> {
+    if config.allowed_dirs.is_empty() {
+        return Err(UploadError::Disabled);
+    }
+    if chunk.size > config.max_bytes {
+        return Err(UploadError::TooLarge {
+            size: chunk.size,
+            max: config.max_bytes,
+        });
+    }
+    let sha256 = chunk
+        .sha256
+        .as_deref()
+        .filter(|s| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit()))
+        .ok_or(UploadError::MissingChecksum)?
+        .to_lowercase();
+    let mode = parse_mode(chunk.mode.as_deref())?;
+    let target = resolve(&chunk.path, &config.allowed_dirs)?;
+    // next to the target so the rename stays on one filesystem
+    let name = target.file_name().unwrap_or_default().to_string_lossy();
+    let staged = target.with_file_name(format!(".{name}.lynx-upload"));
+    // left over from an upload that died halfway, or planted there; removing a link leaves its
+    // target alone
+    let _ = fs::remove_file(&staged);
+    let mut options = OpenOptions::new();
+    // a file or link that shows up in between fails the upload instead of being written through
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+    }
+    let file = options.open(&staged)?;
+    Ok(Upload {
+        target,
+        staged,
+        file,
+        received: 0,
+        size: chunk.size,
+        mode,
+        sha256,
+        digest: ring::digest::Context::new(&ring::digest::SHA256),
+    })
+}
+
+/// Moves a complete upload into place, after checking its checksum.
+fn finish(mut upload: Upload) -> Result<(), UploadError> {
+    let digest: String = upload
+        .digest
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    if digest != upload.sha256 {
+        return Err(UploadError::ChecksumMismatch);
+    }
+    upload.file.flush()?;
+    upload.file.sync_all()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&upload.staged, fs::Permissions::from_mode(upload.mode))?;
+    }
+    fs::rename(&upload.staged, &upload.target)?;
+    Ok(())
+}
+
+fn append(upload: &mut Upload, offset: u64, data: &[u8]) -> Result<(), UploadError> {
+    if offset != upload.received {
+        return Err(UploadError::UnexpectedOffset {
+            expected: upload.received,
+            got: offset,
+        });
+    }
+    if upload.received + data.len() as u64 > upload.size {
+        return Err(UploadError::Overrun(upload.size));
+    }
+    upload.file.write_all(data)?;
+    upload.digest.update(data);
+    upload.received += data.len() as u64;
+    Ok(())
+}
+
+fn write_chunk(peer: &str, chunk: &Chunk) -> Result<UploadFrame, UploadError> {
+    let data = base64::engine::general_purpose::STANDARD.decode(&chunk.data)?;
+    let key = (peer.to_string(), chunk.path.clone());
+    // the lock is only held to take the upload out and put it back, so one peer's disk I/O
+    // doesn't hold up everyone else's uploads; the peer's writer is the only one to touch it
+    let previous = UPLOADS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(&key);
+    let mut upload = if chunk.offset == 0 {
+        // a new first chunk starts the upload over
+        if let Some(previous) = previous {
+            let _ = fs::remove_file(&previous.staged);
+        }
+        start(chunk, UPLOAD_CONFIG.get().ok_or(UploadError::Disabled)?)?
+    } else {
+        previous.ok_or(UploadError::NotStarted)?
+    };
+    if let Err(e) = append(&mut upload, chunk.offset, &data) {
+        let _ = fs::remove_file(&upload.staged);
+        return Err(e);
+    }
+    if upload.received < upload.size {
+        let frame = UploadFrame::Progress {
+            path: chunk.path.clone(),
+            received: upload.received,
+            size: upload.size,
+        };
+        UPLOADS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, upload);
+        return Ok(frame);
+    }
+    let (size, mode, sha256) = (upload.size, upload.mode, upload.sha256.clone());
+    let (target, staged) = (upload.target.clone(), upload.staged.clone());
+    if let Err(e) = finish(upload) {
+        let _ = fs::remove_file(&staged);
+        return Err(e);
+    }
+    info!(
+        "[upload] Wrote {} ({} bytes, mode {:o}) for {}",
+        target.display(),
+        size,
+        mode,
+        peer
+    );
+    lib::audit::record(
+        peer,
+        "upload",
+        &target.to_string_lossy(),
+        vec![format!("{mode:o}"), size.to_string()],
+        None,
+    );
+    Ok(UploadFrame::Done {
+        path: chunk.path.clone(),
+        size,
+        sha256,
+    })
+}
+
+/// Handles one `upload` message from `peer`, a failed chunk ends its upload.
+fn receive(peer: &str, chunk: Chunk) -> UploadFrame {
+    write_chunk(peer, &chunk).unwrap_or_else(|e| {
+        warn!(
+            "[upload] Upload of {} for {} failed: {}",
+            chunk.path, peer, e
+        );
+        lib::audit::record(
+            peer,
+            "upload",
+            &chunk.path,
+            vec![chunk.size.to_string()],
+            Some(e.to_string()),
+        );
+        UploadFrame::Error {
+            path: chunk.path,
+            error: e.to_string(),
+        }
+    })
+}
+
+/// Queues a chunk for the connection's writer, a full queue fails the chunk right away.
+pub fn queue(chunks: &Sender<Chunk>, tx: &Sender<Message>, chunk: Chunk) {
+    if let Err(e) = chunks.try_send(chunk) {
+        let chunk = e.into_inner();
+        warn!(
+            "[upload] Too many chunks of {} in flight, refusing one",
+            chunk.path
+        );
+        send(
+            tx,
+            &UploadFrame::Error {
+                path: chunk.path,
+                error: "Too many chunks in flight, wait for their progress".to_string(),
+            },
+        );
+    }
+}
+
+/*
+ * serve
+ * The writer of one connection: writes its chunks in the order they arrived, each on a blocking
+ * thread, and answers every one on `tx`. Ends once the connection dropped its sender.
+ */
+pub async fn serve(peer: String, mut chunks: mpsc::Receiver<Chunk>, tx: Sender<Message>) {
+    while let Some(chunk) = chunks.recv().await {
+        let path = chunk.path.clone();
+        let owner = peer.clone();
+        let frame = tokio::task::spawn_blocking(move || receive(&owner, chunk))
+            .await
+            .unwrap_or_else(|e| UploadFrame::Error {
+                path,
+                error: e.to_string(),
+            });
+        send(&tx, &frame);
+    }
+}
+
+fn send(tx: &Sender<Message>, frame: &UploadFrame) {
+    if let Ok(text) = serde_json::to_string(frame) {
+        let _ = tx.try_send(Message::Text(Utf8Bytes::from(text)));
+    }
+}
+
+/// Drops the unfinished uploads of a peer that disconnected.
+pub fn abort(peer: &str) {
+    let mut uploads = UPLOADS.lock().unwrap_or_else(|e| e.into_inner());
+    uploads.retain(|(owner, path), upload| {
+        if owner != peer {
+            return true;
+        }
+        warn!(
+            "[upload] {} disconnected during the upload of {}",
+            peer, path
+        );
+        let _ = fs::remove_file(&upload.staged);
+        false
+    });
+}
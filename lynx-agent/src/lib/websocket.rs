@@ -1,7 +1,11 @@
 use crate::lib;
-use futures_util::{future, pin_mut, SinkExt, StreamExt, TryStreamExt};
+use crate::lib::protocol::{self, ErrorCode, Incoming};
+use crate::lib::service_control::ServiceAction;
+use crate::lib::service_logs::LogFrame;
+use crate::proto::monitor::{CommandAudit, TranscriptLine};
+use futures_util::{future, pin_mut, Sink, SinkExt, Stream, StreamExt, TryStreamExt};
 use log::{error, info, warn};
-use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::pki_types::{CertificateDer, CertificateRevocationListDer, PrivateKeyDer};
 use rustls::server::WebPkiClientVerifier;
 use rustls::{RootCertStore, ServerConfig};
 use serde::{Deserialize, Serialize};
@@ -9,17 +13,16 @@ use std::collections::HashMap;
 use std::env;
 use std::fs::File;
 use std::net::SocketAddr;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use std::time::Duration;
-use sysinfo::System;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader};
 use tokio::net::TcpListener;
 use tokio::process::{Child, Command};
 use tokio::sync::mpsc::{self, channel, Receiver, Sender};
 use tokio::sync::{Mutex, Notify};
 use tokio::time::interval;
 use tokio_rustls::TlsAcceptor;
-use tokio_tungstenite::tungstenite::error::ProtocolError::{HandshakeIncomplete, WrongHttpMethod};
 use tokio_tungstenite::tungstenite::{Message, Utf8Bytes};
 use uuid::Uuid;
 
@@ -28,23 +31,38 @@ type ProcessInfo = (ChildHandle, Arc<Notify>);
 lazy_static::lazy_static! {
     static ref RUNNING_PROCESSES: Arc<Mutex<HashMap<Uuid, ProcessInfo>>> =
         Arc::new(Mutex::new(HashMap::new()));
-    static ref LIVE_METRICS: Arc<Mutex<HashMap<SocketAddr, ProcessInfo>>> =
+    static ref LIVE_METRICS: Arc<Mutex<HashMap<String, ProcessInfo>>> =
         Arc::new(Mutex::new(HashMap::new()));
 }
 
+/// Either a TLS or (in `--insecure` mode) a plain TCP stream
+trait WsIo: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> WsIo for T {}
+
 type Tx = Sender<Message>;
 type Rx = Receiver<Message>;
-pub type PeerMap = Arc<Mutex<HashMap<SocketAddr, Tx>>>;
+/// Open sessions by peer, the client's address or `hub:<session id>` for relayed ones
+pub type PeerMap = Arc<Mutex<HashMap<String, Tx>>>;
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")] // This is crucial for enum deserialization
 enum WsMessage {
+    /// Version exchange, see lib::protocol
+    #[serde(rename = "hello")]
+    Hello {
+        #[serde(default)]
+        version: Option<u32>,
+    },
     #[serde(rename = "execute")]
     Execute { command: String, args: Vec<String> },
     #[serde(rename = "stop")]
     Stop,
+    /// Without a url the agent checks its update channel right away
     #[serde(rename = "update")]
-    Update,
+    Update {
+        #[serde(default)]
+        url: Option<String>,
+    },
     #[serde(rename = "delete")]
     Delete,
     #[serde(rename = "live")]
@@ -64,42 +82,236 @@ enum WsMessage {
         service_name: String,
         origin: String,
     },
-    #[serde(rename = "output")]
-    Output(String),
+    /// Recent journal of a unit, answered with LogFrame messages
+    #[serde(rename = "servicelogs")]
+    ServiceLogs {
+        service_name: String,
+        #[serde(default)]
+        lines: Option<u32>,
+        #[serde(default)]
+        since: Option<String>,
+    },
+    /// Pending package updates, answered with a PackageFrame
+    #[serde(rename = "listupdates")]
+    ListUpdates {
+        #[serde(default)]
+        refresh: bool,
+    },
+    /// Installs the given updates, all of them when empty, see lib::packages
+    #[serde(rename = "applyupdates")]
+    ApplyUpdates {
+        #[serde(default)]
+        packages: Vec<String>,
+    },
+    /// One chunk of a file to write, see lib::upload
+    #[serde(rename = "upload")]
+    Upload(lib::upload::Chunk),
     #[serde(rename = "EOF")]
     EOF,
 }
 
-fn load_certs(path: &str) -> Vec<CertificateDer<'static>> {
-    let certfile = std::fs::File::open(path).unwrap();
-    let mut reader = std::io::BufReader::new(certfile);
-    rustls_pemfile::certs(&mut reader)
+fn open_pem(path: &str) -> Result<std::io::BufReader<File>, String> {
+    File::open(path)
+        .map(std::io::BufReader::new)
+        .map_err(|e| format!("Failed to open {}: {}", path, e))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    rustls_pemfile::certs(&mut open_pem(path)?)
         .collect::<Result<Vec<_>, _>>()
-        .unwrap()
+        .map_err(|e| format!("Failed to read certificates from {}: {}", path, e))
 }
 
-fn load_private_key(path: &str) -> PrivateKeyDer<'static> {
-    let keyfile = std::fs::File::open(path).unwrap();
-    let mut reader = std::io::BufReader::new(keyfile);
-    rustls_pemfile::pkcs8_private_keys(&mut reader)
+fn load_private_key(path: &str) -> Result<PrivateKeyDer<'static>, String> {
+    rustls_pemfile::pkcs8_private_keys(&mut open_pem(path)?)
         .next()
-        .unwrap()
-        .unwrap()
-        .into()
+        .ok_or_else(|| format!("No PKCS#8 private key in {}", path))?
+        .map(Into::into)
+        .map_err(|e| format!("Failed to read the private key from {}: {}", path, e))
 }
 
-fn load_ca(path: &str) -> RootCertStore {
+fn load_ca(path: &str) -> Result<RootCertStore, String> {
     let mut ca = RootCertStore::empty();
-    let cafile = std::fs::File::open(path).unwrap();
-    let mut reader = std::io::BufReader::new(cafile);
-    for cert in rustls_pemfile::certs(&mut reader) {
-        ca.add(cert.unwrap()).unwrap();
+    for cert in rustls_pemfile::certs(&mut open_pem(path)?) {
+        let cert = cert.map_err(|e| format!("Failed to read {}: {}", path, e))?;
+        ca.add(cert)
+            .map_err(|e| format!("Invalid CA certificate in {}: {}", path, e))?;
+    }
+    Ok(ca)
+}
+
+/// certs/agent.crt when one was installed for the websocket server, otherwise the pair
+/// enrollment wrote, which the CA issued for server use too.
+fn server_identity() -> (String, String) {
+    let installed = ("certs/agent.crt", "certs/agent.key");
+    let enrolled = ("certs/docker-agent.crt", "certs/docker-agent.key");
+    let (cert, key) = if Path::new(installed.0).exists() {
+        installed
+    } else {
+        enrolled
+    };
+    (
+        env::var("LYNX_CERT_PATH").unwrap_or_else(|_| cert.to_string()),
+        env::var("LYNX_KEY_PATH").unwrap_or_else(|_| key.to_string()),
+    )
+}
+
+fn load_crls(paths: &[PathBuf]) -> std::io::Result<Vec<CertificateRevocationListDer<'static>>> {
+    let mut crls = Vec::new();
+    for path in paths {
+        let mut reader = std::io::BufReader::new(File::open(path)?);
+        for crl in rustls_pemfile::crls(&mut reader) {
+            crls.push(crl?);
+        }
+    }
+    Ok(crls)
+}
+
+/// Answers a `servicelogs` message, see lib::service_logs.
+async fn send_service_logs(
+    tx: &Tx,
+    service_name: String,
+    lines: Option<u32>,
+    since: Option<String>,
+) {
+    let frames = match lib::service_logs::read(&service_name, lines, since.as_deref()).await {
+        Ok(entries) => {
+            let end = LogFrame::End {
+                service_name: service_name.clone(),
+                count: entries.len(),
+            };
+            entries.into_iter().chain(std::iter::once(end)).collect()
+        }
+        Err(e) => {
+            warn!("[ws] Failed to read logs of {}: {}", service_name, e);
+            vec![LogFrame::Error {
+                service_name,
+                error: e.to_string(),
+            }]
+        }
+    };
+    for frame in frames {
+        let Ok(text) = serde_json::to_string(&frame) else {
+            continue;
+        };
+        // waits for room, a journal can be larger than the queue
+        if tx.send(Message::Text(Utf8Bytes::from(text))).await.is_err() {
+            break;
+        }
     }
-    ca
 }
 
-pub async fn stream_output(recp: Tx, child: ChildHandle, terminate_signal: Arc<Notify>) {
+/// Optional `[commands]` section of config.toml, limits and transcripts for `execute` messages.
+/// 0 disables a limit.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CommandLimits {
+    pub max_runtime_secs: u64,
+    /// stdout and stderr together
+    pub max_output_bytes: u64,
+    /// Sends the command line and its output with the audit entry
+    pub record_transcripts: bool,
+    /// Timestamps recorded input too, output always is
+    pub input_timestamps: bool,
+}
+
+impl Default for CommandLimits {
+    fn default() -> Self {
+        Self {
+            max_runtime_secs: 600,
+            max_output_bytes: 1024 * 1024,
+            record_transcripts: true,
+            input_timestamps: false,
+        }
+    }
+}
+
+static COMMAND_LIMITS: OnceLock<CommandLimits> = OnceLock::new();
+
+/// Transcript lines past this are not recorded, the hub keeps no more either.
+const MAX_TRANSCRIPT_LINES: usize = 20_000;
+
+/// Sets the command limits from config.toml, commands before this use the defaults.
+pub fn set_command_limits(limits: CommandLimits) {
+    if COMMAND_LIMITS.set(limits).is_err() {
+        warn!("[ws] Command limits already set, ignoring");
+    }
+}
+
+fn command_limits() -> &'static CommandLimits {
+    COMMAND_LIMITS.get_or_init(CommandLimits::default)
+}
+
+/// Resolves at the deadline, never without one.
+async fn until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Kills a command that ran over a limit and tells the client why.
+async fn kill_runaway(child: &mut Child, recp: &Tx, reason: String) -> Option<String> {
+    warn!("[command] {}, killing it", reason);
+    if let Err(e) = child.kill().await {
+        error!("[command] Failed to kill command: {}", e);
+    }
+    let _ = recp.try_send(Message::Text(Utf8Bytes::from(format!(
+        "[ERROR] {}, killed",
+        reason
+    ))));
+    Some(reason)
+}
+
+/// Adds a line to the transcript of an audited command, if transcripts are recorded.
+fn transcribe(audit: Option<&mut CommandAudit>, stream: &str, text: &str) {
+    let limits = command_limits();
+    // one line past the limit, so the hub marks the transcript truncated
+    let Some(audit) =
+        audit.filter(|a| limits.record_transcripts && a.transcript.len() <= MAX_TRANSCRIPT_LINES)
+    else {
+        return;
+    };
+    let time_ms = if stream != "input" || limits.input_timestamps {
+        chrono::Utc::now().timestamp_millis()
+    } else {
+        0
+    };
+    audit.transcript.push(TranscriptLine {
+        time_ms,
+        stream: stream.to_string(),
+        text: text.to_string(),
+    });
+}
+
+/// A line read from a command, without its line break.
+fn output_line(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_end_matches(['\n', '\r'])
+        .to_string()
+}
+
+/*
+ * stream_output
+ * Relays a command's output until it exits, is stopped or runs over its limits, then finishes
+ * its audit entry. Output is read in bytes, so binary output counts against the limit too, and
+ * each stream is read to at most the limit so a line without end can't fill the memory.
+ */
+pub async fn stream_output(
+    recp: Tx,
+    child: ChildHandle,
+    terminate_signal: Arc<Notify>,
+    mut audit: Option<CommandAudit>,
+) {
+    let limits = command_limits();
+    let deadline = (limits.max_runtime_secs > 0)
+        .then(|| tokio::time::Instant::now() + Duration::from_secs(limits.max_runtime_secs));
+    let max_output = match limits.max_output_bytes {
+        0 => u64::MAX,
+        max => max,
+    };
     let mut child_opt = child.lock().await;
+    let mut outcome = (None, None);
     if let Some(child) = child_opt.as_mut() {
         let stdout = child
             .stdout
@@ -110,43 +322,72 @@ pub async fn stream_output(recp: Tx, child: ChildHandle, terminate_signal: Arc<N
             .take()
             .expect("Child did not have a handle to stderr");
 
-        let mut stdout_reader = BufReader::new(stdout).lines();
-        let mut stderr_reader = BufReader::new(stderr).lines();
+        let mut stdout_reader = BufReader::new(stdout.take(max_output.saturating_add(1)));
+        let mut stderr_reader = BufReader::new(stderr.take(max_output.saturating_add(1)));
+        let (mut stdout_line, mut stderr_line) = (Vec::new(), Vec::new());
+        let (mut stdout_open, mut stderr_open) = (true, true);
+        let mut output_bytes: u64 = 0;
         loop {
             tokio::select! {
-                Ok(Some(line)) = stdout_reader.next_line() => {
-                    //info!("[command:output] {}", line);
-                    // Use try_send to avoid blocking and handle full channel
-                    if let Err(e) = recp.try_send(Message::Text(Utf8Bytes::from(line))) {
-                        info!("[ERROR] Failed to send output: {}", e);
-                        break;
+                read = stdout_reader.read_until(b'\n', &mut stdout_line), if stdout_open => {
+                    match read {
+                        Ok(0) | Err(_) => stdout_open = false,
+                        Ok(n) => {
+                            output_bytes += n as u64;
+                            let line = output_line(&stdout_line);
+                            stdout_line.clear();
+                            transcribe(audit.as_mut(), "stdout", &line);
+                            // Use try_send to avoid blocking and handle full channel
+                            if let Err(e) = recp.try_send(Message::Text(Utf8Bytes::from(line))) {
+                                info!("[ERROR] Failed to send output: {}", e);
+                                outcome.1 = Some(format!("output could not be relayed: {}", e));
+                                break;
+                            }
+                            // delay for a short period to avoid overwhelming the WebSocket
+                            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+                        }
                     }
-                    // delay for a short period to avoid overwhelming the WebSocket
-                    tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                 }
-                Ok(Some(line)) = stderr_reader.next_line() => {
-                    info!("[command:error] {}", line);
-                    if let Err(e) = recp.try_send(Message::Text(Utf8Bytes::from(format!("[ERROR] {}", line)))) {
-                        info!("[ERROR] Failed to send error output: {}", e);
-                        break;
+                read = stderr_reader.read_until(b'\n', &mut stderr_line), if stderr_open => {
+                    match read {
+                        Ok(0) | Err(_) => stderr_open = false,
+                        Ok(n) => {
+                            output_bytes += n as u64;
+                            let line = output_line(&stderr_line);
+                            stderr_line.clear();
+                            transcribe(audit.as_mut(), "stderr", &line);
+                            info!("[command:error] {}", line);
+                            if let Err(e) = recp.try_send(Message::Text(Utf8Bytes::from(format!("[ERROR] {}", line)))) {
+                                info!("[ERROR] Failed to send error output: {}", e);
+                                outcome.1 = Some(format!("output could not be relayed: {}", e));
+                                break;
+                            }
+                        }
                     }
                 },
                 _ = terminate_signal.notified() => {
                     info!("[command] Termination signal received, stopping command");
+                    outcome.1 = Some("stopped".to_string());
                     if let Err(e) = child.kill().await {
                         error!("[command] Failed to kill command: {}", e);
                     } else {
                         info!("[command] Command killed successfully");
                     }
                     break;
+                },
+                _ = until(deadline) => {
+                    let reason = format!("Command ran longer than {} seconds", limits.max_runtime_secs);
+                    outcome.1 = kill_runaway(child, &recp, reason).await;
+                    break;
                 },
                  _ = async {
                     tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
                     false
                 } => {
                     // This is a timeout to avoid blocking indefinitely
-                    if child.try_wait().unwrap().is_some() {
+                    if let Some(status) = child.try_wait().unwrap() {
                         info!("[command] Command has exited");
+                        outcome.0 = status.code();
                         if let Err(e) = recp.try_send(Message::Text(Utf8Bytes::from("EOF"))) {
                             info!("[ERROR] Failed to send EOF: {}", e);
                         }
@@ -154,26 +395,46 @@ pub async fn stream_output(recp: Tx, child: ChildHandle, terminate_signal: Arc<N
                     }
                 }
             }
+            if output_bytes > max_output {
+                let reason = format!("Command wrote more than {} bytes", max_output);
+                outcome.1 = kill_runaway(child, &recp, reason).await;
+                break;
+            }
         }
     }
+    if let Some(audit) = audit {
+        lib::audit::finish(audit, outcome.0, outcome.1);
+    }
 }
 
-pub async fn start_command(command: String, args: Vec<String>, ws_sender: Tx) -> Uuid {
+/// Spawns `command` and streams its output to `ws_sender`. `None` when it couldn't be spawned,
+/// the error has then been sent to the client and recorded in the audit log.
+pub async fn start_command(
+    command: String,
+    args: Vec<String>,
+    ws_sender: Tx,
+    mut audit: CommandAudit,
+) -> Option<Uuid> {
     let process_id = Uuid::new_v4();
-    let child = Command::new(&command)
+    let input = [&[command.clone()], &args[..]].concat().join(" ");
+    transcribe(Some(&mut audit), "input", &input);
+    let child = match Command::new(&command)
         .args(&args)
         .stdout(std::process::Stdio::piped())
         .stderr(std::process::Stdio::piped())
         .spawn()
-        .map_err(|e| {
+    {
+        Ok(child) => child,
+        Err(e) => {
             let _ = ws_sender.try_send(Message::Text(Utf8Bytes::from(format!(
                 "[ERROR] Failed to spawn command: {}",
                 e
             ))));
             error!("[ERROR] Failed to spawn command: {}", e);
-            e
-        })
-        .expect("Failed to spawn command");
+            lib::audit::finish(audit, None, Some(e.to_string()));
+            return None;
+        }
+    };
     let child_handle = Arc::new(Mutex::new(Some(child)));
     let terminate_signal = Arc::new(Notify::new());
     // Store the process information in the global map
@@ -182,29 +443,37 @@ pub async fn start_command(command: String, args: Vec<String>, ws_sender: Tx) ->
         .await
         .insert(process_id, (child_handle.clone(), terminate_signal.clone()));
 
-    tokio::spawn(stream_output(ws_sender, child_handle, terminate_signal));
+    tokio::spawn(stream_output(
+        ws_sender,
+        child_handle,
+        terminate_signal,
+        Some(audit),
+    ));
 
-    process_id
+    Some(process_id)
 }
 
-pub async fn start_metrics_command(addr: SocketAddr, ws_sender: Tx) -> Uuid {
+pub async fn start_metrics_command(peer: String, ws_sender: Tx) -> Uuid {
     let process_id = Uuid::new_v4();
     let terminate_signal = Arc::new(Notify::new());
     {
         let terminate_signal = terminate_signal.clone();
-        let mut sys = System::new_all();
+        let mut sys = lib::system_info::new_system();
+        let mut rates = lib::system_info::Rates::default();
         let ws_sender = ws_sender.clone();
+        let peer = peer.clone();
         tokio::spawn(async move {
             loop {
                 tokio::select! {
                     _ = terminate_signal.notified() => {
-                        info!("[metrics] Termination signal received, stopping live metrics for {}", addr);
+                        info!("[metrics] Termination signal received, stopping live metrics for {}", peer);
                         break;
                     }
                     _ = async {
-                        let metrics = lib::system_info::collect_metrics(&mut sys).await;
+                        tokio::time::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL).await;
+                        let metrics = lib::system_info::collect_metrics(&mut sys, &mut rates).await;
                         info!("[metrics] Sending live metrics to {}: CPU: {}%, Memory: {}KB used of {}KB ({}%), Load Avg (1m): {}",
-                            addr,
+                            peer,
                             metrics.cpu_stats.unwrap().usage_percent,
                             metrics.memory_stats.unwrap().used_kb,
                             metrics.memory_stats.unwrap().total_kb,
@@ -219,7 +488,7 @@ pub async fn start_metrics_command(addr: SocketAddr, ws_sender: Tx) -> Uuid {
                             metrics.memory_stats.unwrap().used_kb / metrics.memory_stats.unwrap().total_kb * 100,
                             metrics.load_average.unwrap().one_minute
                         )))) {
-                            warn!("[metrics] Failed to send live metrics to {}: {}", addr, e);
+                            warn!("[metrics] Failed to send live metrics to {}: {}", peer, e);
                         }
                     } => {}
                 }
@@ -233,33 +502,69 @@ pub async fn start_metrics_command(addr: SocketAddr, ws_sender: Tx) -> Uuid {
     LIVE_METRICS
         .lock()
         .await
-        .insert(addr, (child_handle.clone(), terminate_signal.clone()));
+        .insert(peer, (child_handle.clone(), terminate_signal.clone()));
 
-    tokio::spawn(stream_output(ws_sender, child_handle, terminate_signal));
+    tokio::spawn(stream_output(
+        ws_sender,
+        child_handle,
+        terminate_signal,
+        None,
+    ));
 
     process_id
 }
 
-pub async fn start_websocket_server(peers: PeerMap) -> Result<(), Box<dyn std::error::Error>> {
-    let addr = env::var("LYNX_AGENT_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
-    let cert_path = env::var("LYNX_CERT_PATH").unwrap_or_else(|_| "certs/agent.crt".to_string());
-    let key_path = env::var("LYNX_KEY_PATH").unwrap_or_else(|_| "certs/agent.key".to_string());
+/*
+ * tls_acceptor
+ * mTLS acceptor for the websocket server, only clients with a cert signed by our CA (and not
+ * revoked by one of the configured CRLs) may connect.
+ */
+fn tls_acceptor(crl_files: &[PathBuf]) -> Result<TlsAcceptor, Box<dyn std::error::Error>> {
+    let (cert_path, key_path) = server_identity();
     let ca_path = env::var("LYNX_CA_PATH").unwrap_or_else(|_| "certs/ca.crt".to_string());
-    let certs = load_certs(&cert_path);
-    let key = load_private_key(&key_path);
-    let ca_store = load_ca(&ca_path);
+    let certs = load_certs(&cert_path)?;
+    let key = load_private_key(&key_path)?;
+    let ca_store = load_ca(&ca_path)?;
+    let crls =
+        load_crls(crl_files).map_err(|e| format!("Failed to load CRLs {:?}: {}", crl_files, e))?;
 
     let config = ServerConfig::builder_with_protocol_versions(&[&rustls::version::TLS13])
         .with_client_cert_verifier(
             WebPkiClientVerifier::builder(Arc::new(ca_store))
+                .with_crls(crls)
                 .build()
                 .map_err(|e| format!("Failed to build client cert verifier: {}", e))?,
         )
         .with_single_cert(certs, key)?;
 
-    let acceptor = TlsAcceptor::from(Arc::new(config));
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+pub async fn start_websocket_server(
+    peers: PeerMap,
+    insecure: bool,
+    crl_files: Vec<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = env::var("LYNX_AGENT_ADDR").unwrap_or_else(|_| "127.0.0.1:8080".to_string());
 
-    info!("[agent] Started mTLS websocket server at {}", addr);
+    let acceptor = if insecure {
+        if !addr.parse::<SocketAddr>()?.ip().is_loopback() {
+            return Err(format!(
+                "--insecure only serves websockets on localhost, not {}",
+                addr
+            )
+            .into());
+        }
+        warn!(
+            "[agent] Started plaintext websocket server at {} (--insecure)",
+            addr
+        );
+        None
+    } else {
+        let acceptor = tls_acceptor(&crl_files)?;
+        info!("[agent] Started mTLS websocket server at {}", addr);
+        Some(acceptor)
+    };
 
     let try_socket = TcpListener::bind(&addr).await;
     let listener = try_socket.expect("Failed to bind");
@@ -270,18 +575,21 @@ pub async fn start_websocket_server(peers: PeerMap) -> Result<(), Box<dyn std::e
             let acceptor = acceptor.clone();
             let peers_clone = peers_clone.clone();
             tokio::spawn(async move {
-                let tls_stream = match acceptor.accept(stream).await {
-                    Ok(tls_stream) => tls_stream,
-                    Err(e) => {
-                        error!(
-                            "[mTLS] Failed to establish TLS connection with {}: {}",
-                            addr, e
-                        );
-                        return;
-                    }
+                let stream: Box<dyn WsIo> = match acceptor {
+                    Some(acceptor) => match acceptor.accept(stream).await {
+                        Ok(tls_stream) => Box::new(tls_stream),
+                        Err(e) => {
+                            error!(
+                                "[mTLS] Failed to establish TLS connection with {}: {}",
+                                addr, e
+                            );
+                            return;
+                        }
+                    },
+                    None => Box::new(stream),
                 };
 
-                let ws_stream = match tokio_tungstenite::accept_async(tls_stream).await {
+                let ws_stream = match tokio_tungstenite::accept_async(stream).await {
                     Ok(ws_stream) => ws_stream,
                     Err(e) => {
                         error!(
@@ -292,313 +600,409 @@ pub async fn start_websocket_server(peers: PeerMap) -> Result<(), Box<dyn std::e
                     }
                 };
 
-                info!("[ws] mTLS connection established: {}", addr);
-                let (tx, mut rx) = channel(64);
-                peers_clone.lock().await.insert(addr, tx.clone());
-
-                let (mut outgoing, incoming) = ws_stream.split();
-                // Process incoming messages
-                let incoming_messages = incoming.try_for_each(|msg| {
-                    if let Ok(text) = msg.to_text() {
-                        info!("[ws] Received message from {}: {}", addr, text);
-                        match serde_json::from_str::<WsMessage>(text) {
-                            Ok(WsMessage::Execute { command, args }) => {
-                                info!("[ws] Executing command: {} {:?}", command, args);
-                                let tx_clone = tx.clone();
-                                tokio::spawn(async move {
-                                    let process_id =
-                                        start_command(command, args, tx_clone.clone()).await;
+                info!("[ws] Connection established: {}", addr);
+                let (outgoing, incoming) = ws_stream.split();
+                serve_connection(addr.to_string(), incoming, outgoing, peers_clone).await;
+            });
+        }
+    });
+    Ok(())
+}
+
+/*
+ * serve_connection
+ * Handles one dashboard session, a client of the local websocket server or a session the hub
+ * relays over the agent's session stream (see lib::sessions). `peer` names the session in logs
+ * and keys its live metrics.
+ */
+pub async fn serve_connection<S, K>(peer: String, incoming: S, mut outgoing: K, peers: PeerMap)
+where
+    S: Stream<Item = Result<Message, tokio_tungstenite::tungstenite::Error>>,
+    K: Sink<Message> + Unpin,
+    K::Error: std::fmt::Display,
+{
+    let (tx, mut rx) = channel(64);
+    peers.lock().await.insert(peer.clone(), tx.clone());
+    let (upload_tx, upload_rx) = channel(lib::upload::UPLOAD_QUEUE);
+    let uploads = tokio::spawn(lib::upload::serve(peer.clone(), upload_rx, tx.clone()));
+
+    // Process incoming messages
+    let incoming_messages = incoming.try_for_each(|msg| {
+        let incoming = protocol::parse(&msg).unwrap_or_else(|frame| {
+            warn!("[ws] Unusable message from {}: {:?}", peer, frame);
+            protocol::send(&tx, &frame);
+            None
+        });
+        if let Some(Incoming { value, id, action }) = incoming {
+            // only the type and id, payloads carry command tokens and upload data
+            let shown_id = id.as_ref().map_or_else(|| "none".to_string(), |id| id.to_string());
+            info!("[ws] Received {} from {} (id {})", action, peer, shown_id);
+            if let Err(e) = lib::authorization::check(&action, &value) {
+                warn!("[ws] Refused {} from {}: {}", action, peer, e);
+                lib::audit::record(&peer, &action, "", Vec::new(), Some(e.to_string()));
+                let frame = protocol::error(ErrorCode::Unauthorized, e.to_string(), id);
+                protocol::send(&tx, &frame);
+                return future::ok(());
+            }
+            match serde_json::from_value::<WsMessage>(value) {
+                Ok(WsMessage::Hello { version }) => {
+                    protocol::send(&tx, &protocol::hello(version, id));
+                }
+                Ok(WsMessage::Execute { command, args }) => {
+                    info!("[ws] Executing command: {} {:?}", command, args);
+                    let audit = lib::audit::started(&peer, "execute", &command, args.clone());
+                    let tx_clone = tx.clone();
+                    tokio::spawn(async move {
+                        let Some(process_id) =
+                            start_command(command, args, tx_clone.clone(), audit).await
+                        else {
+                            return;
+                        };
+                        let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(format!(
+                            "Started command with ID: {}",
+                            process_id
+                        ))));
+                    });
+                }
+                Ok(WsMessage::Stop) => {
+                    let mut audit = lib::audit::started(&peer, "stop", "", Vec::new());
+                    let tx_clone = tx.clone();
+                    tokio::spawn(async move {
+                        let mut processes = RUNNING_PROCESSES.lock().await;
+                        let mut error = None;
+                        for (pid, (child_handle, terminate_signal)) in processes.clone().iter() {
+                            terminate_signal.notify_one();
+                            if let Some(child) = child_handle.lock().await.as_mut() {
+                                audit.args.push(pid.to_string());
+                                if let Err(e) = child.kill().await {
+                                    info!("[ws] Failed to stop command {}: {}", pid, e);
+                                    error = Some(format!("{}: {}", pid, e));
                                     let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
-                                        format!("Started command with ID: {}", process_id),
+                                        format!("Failed to stop command {}: {}", pid, e),
                                     )));
-                                });
-                            }
-                            Ok(WsMessage::Stop) => {
-                                let tx_clone = tx.clone();
-                                tokio::spawn(async move {
-                                    let mut processes = RUNNING_PROCESSES.lock().await;
-                                    for (pid, (child_handle, terminate_signal)) in
-                                        processes.clone().iter()
-                                    {
-                                        terminate_signal.notify_one();
-                                        if let Some(child) = child_handle.lock().await.as_mut() {
-                                            if let Err(e) = child.kill().await {
-                                                info!("[ws] Failed to stop command {}: {}", pid, e);
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to stop command {}: {}",
-                                                        pid, e
-                                                    )),
-                                                ));
-                                            } else {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Stopped command {}",
-                                                        pid
-                                                    )),
-                                                ));
-                                            }
-                                        } else {
-                                            continue;
-                                        }
-                                        processes.remove(pid);
-                                    }
-                                });
+                                } else {
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Stopped command {}", pid),
+                                    )));
+                                }
+                            } else {
+                                continue;
                             }
-                            Ok(WsMessage::Update) => {
-                                // todo: Make update script
+                            processes.remove(pid);
+                        }
+                        lib::audit::finish(audit, None, error);
+                    });
+                }
+                Ok(WsMessage::Update { url: None }) => {
+                    lib::audit::record(&peer, "update", "", Vec::new(), None);
+                    lib::update::request_check();
+                    let _ = tx.try_send(Message::Text(Utf8Bytes::from_static(
+                        "Checking the update channel",
+                    )));
+                }
+                Ok(WsMessage::Update { url: Some(url) }) => {
+                    let audit = lib::audit::started(&peer, "update", &url, Vec::new());
+                    let tx_clone = tx.clone();
+                    tokio::spawn(async move {
+                        match lib::update::apply_update(&url).await {
+                            Ok(()) => {
+                                lib::audit::finish(audit, None, None);
+                                let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from_static(
+                                    "Update installed, restarting",
+                                )));
+                                // give the reply a moment to flush, systemd restarts us
+                                tokio::time::sleep(Duration::from_secs(1)).await;
+                                std::process::exit(0);
                             }
-                            Ok(WsMessage::Delete) => {
-                                // todo: Uninstall self
+                            Err(e) => {
+                                error!("[ws] Update failed: {}", e);
+                                lib::audit::finish(audit, None, Some(e.to_string()));
+                                let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(format!(
+                                    "Update failed: {}",
+                                    e
+                                ))));
                             }
-                            Ok(WsMessage::Live) => {
-                                info!(
-                                    "[ws] Starting live relay of system metrics to agent: {}",
-                                    addr
-                                );
-                                let tx_clone = tx.clone();
-                                tokio::spawn(async move {
-                                    let process_id =
-                                        start_metrics_command(addr, tx_clone.clone()).await;
-                                    let _ =
-                                        tx_clone.try_send(Message::Text(Utf8Bytes::from(format!(
-                                            "Started live metrics with thread ID: {}",
-                                            process_id
-                                        ))));
-                                });
-                            }
-                            Ok(WsMessage::StartService {
-                                service_name,
-                                origin,
-                            }) => {
-                                let systemctl = systemctl::SystemCtl::default();
-                                let tx_clone = tx.clone();
-                                tokio::spawn(async move {
-                                    if origin == "systemctl" {
-                                        match systemctl.start(&service_name) {
-                                            Ok(_) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Started service: {}",
-                                                        service_name
-                                                    )),
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to start service {}: {}",
-                                                        service_name, e
-                                                    )),
-                                                ));
-                                            }
-                                        }
-                                    } else if origin == "docker" {
-                                        let docker_manager = lib::docker::DockerManager::new()
-                                            .map_err(|e| {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to start docker manager: {}",
-                                                        e
-                                                    )),
-                                                ));
-                                            })
-                                            .unwrap();
-
-                                        match docker_manager.start_container(&service_name).await {
-                                            Ok(_) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Started docker container: {}",
-                                                        service_name
-                                                    )),
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to start docker container: {}",
-                                                        e
-                                                    )),
-                                                ));
-                                            }
-                                        }
-                                    }
-                                });
+                        }
+                    });
+                }
+                Ok(WsMessage::Delete) => {
+                    lib::audit::record(&peer, "delete", "", Vec::new(), None);
+                    lib::uninstall::request();
+                    let _ = tx.try_send(Message::Text(Utf8Bytes::from_static(
+                        "Uninstalling the agent",
+                    )));
+                }
+                Ok(WsMessage::Live) => {
+                    info!(
+                        "[ws] Starting live relay of system metrics to agent: {}",
+                        peer
+                    );
+                    let tx_clone = tx.clone();
+                    let peer = peer.clone();
+                    tokio::spawn(async move {
+                        let process_id = start_metrics_command(peer, tx_clone.clone()).await;
+                        let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(format!(
+                            "Started live metrics with thread ID: {}",
+                            process_id
+                        ))));
+                    });
+                }
+                Ok(WsMessage::StartService {
+                    service_name,
+                    origin,
+                }) => {
+                    let tx_clone = tx.clone();
+                    let audit = lib::audit::started(
+                        &peer,
+                        "start_service",
+                        &service_name,
+                        vec![origin.clone()],
+                    );
+                    tokio::spawn(async move {
+                        if origin == "systemctl" || origin == "launchd" {
+                            match lib::service_control::control(&service_name, ServiceAction::Start)
+                                .await
+                            {
+                                Ok(()) => {
+                                    lib::audit::finish(audit, None, None);
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Started service: {}", service_name),
+                                    )));
+                                }
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to start service {}: {}", service_name, e),
+                                    )));
+                                }
                             }
-                            Ok(WsMessage::StopService {
-                                service_name,
-                                origin,
-                            }) => {
-                                let systemctl = systemctl::SystemCtl::default();
-                                let tx_clone = tx.clone();
-                                tokio::spawn(async move {
-                                    if origin == "systemctl" {
-                                        match systemctl.stop(&service_name) {
-                                            Ok(_) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Stopped service: {}",
-                                                        service_name
-                                                    )),
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to stop service {}: {}",
-                                                        service_name, e
-                                                    )),
-                                                ));
-                                            }
-                                        }
-                                    } else if origin == "docker" {
-                                        let docker_manager = lib::docker::DockerManager::new()
-                                            .map_err(|e| {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to start docker manager: {}",
-                                                        e
-                                                    )),
-                                                ));
-                                            })
-                                            .unwrap();
-
-                                        match docker_manager.stop_container(&service_name).await {
-                                            Ok(_) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Stopped docker container: {}",
-                                                        service_name
-                                                    )),
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to stop docker container: {}",
-                                                        e
-                                                    )),
-                                                ));
-                                            }
-                                        }
-                                    }
-                                });
+                        } else if origin == "docker" {
+                            let docker_manager = match lib::docker::DockerManager::new() {
+                                Ok(docker_manager) => docker_manager,
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to start docker manager: {}", e),
+                                    )));
+                                    return;
+                                }
+                            };
+
+                            match docker_manager.start_container(&service_name).await {
+                                Ok(_) => {
+                                    lib::audit::finish(audit, None, None);
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Started docker container: {}", service_name),
+                                    )));
+                                }
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to start docker container: {}", e),
+                                    )));
+                                }
                             }
-                            Ok(WsMessage::RestartService {
-                                service_name,
-                                origin,
-                            }) => {
-                                let systemctl = systemctl::SystemCtl::default();
-                                let tx_clone = tx.clone();
-                                tokio::spawn(async move {
-                                    if origin == "systemctl" {
-                                        match systemctl.restart(&service_name) {
-                                            Ok(status) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Restarted service: {}",
-                                                        status
-                                                    )),
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to restart service {}: {}",
-                                                        service_name, e
-                                                    )),
-                                                ));
-                                            }
-                                        }
-                                    } else if origin == "docker" {
-                                        let docker_manager = lib::docker::DockerManager::new()
-                                            .map_err(|e| {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to start docker manager: {}",
-                                                        e
-                                                    )),
-                                                ));
-                                            })
-                                            .unwrap();
-
-                                        match docker_manager.restart_container(&service_name).await
-                                        {
-                                            Ok(_) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Restarted docker container: {}",
-                                                        service_name
-                                                    )),
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                let _ = tx_clone.try_send(Message::Text(
-                                                    Utf8Bytes::from(format!(
-                                                        "Failed to restart docker container: {}",
-                                                        e
-                                                    )),
-                                                ));
-                                            }
-                                        }
-                                    } else {
-                                        let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
-                                            format!("Invalid origin for service command"),
-                                        )));
-                                    }
-                                });
+                        } else {
+                            lib::audit::finish(audit, None, Some("invalid origin".to_string()));
+                        }
+                    });
+                }
+                Ok(WsMessage::StopService {
+                    service_name,
+                    origin,
+                }) => {
+                    let tx_clone = tx.clone();
+                    let audit = lib::audit::started(
+                        &peer,
+                        "stop_service",
+                        &service_name,
+                        vec![origin.clone()],
+                    );
+                    tokio::spawn(async move {
+                        if origin == "systemctl" || origin == "launchd" {
+                            match lib::service_control::control(&service_name, ServiceAction::Stop)
+                                .await
+                            {
+                                Ok(()) => {
+                                    lib::audit::finish(audit, None, None);
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Stopped service: {}", service_name),
+                                    )));
+                                }
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to stop service {}: {}", service_name, e),
+                                    )));
+                                }
                             }
-                            Ok(WsMessage::EOF) | Err(_) | _ => {
-                                let peers_thread = peers_clone.clone();
-                                tokio::spawn(async move {
-                                    peers_thread.lock().await.remove(&addr);
-                                });
-                                return future::err(
-                                    tokio_tungstenite::tungstenite::Error::Protocol(
-                                        HandshakeIncomplete,
-                                    ),
-                                );
+                        } else if origin == "docker" {
+                            let docker_manager = match lib::docker::DockerManager::new() {
+                                Ok(docker_manager) => docker_manager,
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to start docker manager: {}", e),
+                                    )));
+                                    return;
+                                }
+                            };
+
+                            match docker_manager.stop_container(&service_name).await {
+                                Ok(_) => {
+                                    lib::audit::finish(audit, None, None);
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Stopped docker container: {}", service_name),
+                                    )));
+                                }
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to stop docker container: {}", e),
+                                    )));
+                                }
                             }
+                        } else {
+                            lib::audit::finish(audit, None, Some("invalid origin".to_string()));
                         }
-                    }
-                    future::ok(())
-                });
-
-                // Forward messages from rx thread to outgoing websocket stream
-                let outgoing_messages = async move {
-                    while let Some(msg) = rx.recv().await {
-                        if let Err(e) = outgoing.send(msg).await {
-                            error!("[ws] Failed to send message to {}: {}", addr, e);
-                            break;
-                        }
-                    }
-                };
-
-                // Run both tasks concurrently
-                tokio::select! {
-                    _ = incoming_messages => {},
-                    _ = outgoing_messages => {},
+                    });
                 }
+                Ok(WsMessage::RestartService {
+                    service_name,
+                    origin,
+                }) => {
+                    let tx_clone = tx.clone();
+                    let audit = lib::audit::started(
+                        &peer,
+                        "restart_service",
+                        &service_name,
+                        vec![origin.clone()],
+                    );
+                    tokio::spawn(async move {
+                        if origin == "systemctl" || origin == "launchd" {
+                            match lib::service_control::control(
+                                &service_name,
+                                ServiceAction::Restart,
+                            )
+                            .await
+                            {
+                                Ok(()) => {
+                                    lib::audit::finish(audit, None, None);
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Restarted service: {}", service_name),
+                                    )));
+                                }
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ =
+                                        tx_clone.try_send(Message::Text(Utf8Bytes::from(format!(
+                                            "Failed to restart service {}: {}",
+                                            service_name, e
+                                        ))));
+                                }
+                            }
+                        } else if origin == "docker" {
+                            let docker_manager = match lib::docker::DockerManager::new() {
+                                Ok(docker_manager) => docker_manager,
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to start docker manager: {}", e),
+                                    )));
+                                    return;
+                                }
+                            };
 
-                info!("{} disconnected", &addr);
-                peers_clone.lock().await.remove(&addr);
-                tokio::spawn(async move {
-                    let mut live_metrics = LIVE_METRICS.lock().await;
-                    if let Some((child_handle, terminate_signal)) = live_metrics.remove(&addr) {
-                        info!("[ws] Stopping live metrics for {}", addr);
-                        terminate_signal.notify_one();
-                        if let Some(child) = child_handle.lock().await.as_mut() {
-                            if let Err(e) = child.kill().await {
-                                info!("[ws] Failed to stop live metrics for {}: {}", addr, e);
-                            } else {
-                                info!("[ws] Stopped live metrics for {}", addr);
+                            match docker_manager.restart_container(&service_name).await {
+                                Ok(_) => {
+                                    lib::audit::finish(audit, None, None);
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Restarted docker container: {}", service_name),
+                                    )));
+                                }
+                                Err(e) => {
+                                    lib::audit::finish(audit, None, Some(e.to_string()));
+                                    let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(
+                                        format!("Failed to restart docker container: {}", e),
+                                    )));
+                                }
                             }
+                        } else {
+                            lib::audit::finish(audit, None, Some("invalid origin".to_string()));
+                            let _ = tx_clone.try_send(Message::Text(Utf8Bytes::from(format!(
+                                "Invalid origin for service command"
+                            ))));
                         }
-                    }
-                });
-            });
+                    });
+                }
+                Ok(WsMessage::ServiceLogs {
+                    service_name,
+                    lines,
+                    since,
+                }) => {
+                    let tx_clone = tx.clone();
+                    tokio::spawn(async move {
+                        send_service_logs(&tx_clone, service_name, lines, since).await;
+                    });
+                }
+                Ok(WsMessage::ListUpdates { refresh }) => {
+                    tokio::spawn(lib::packages::send_pending(tx.clone(), refresh));
+                }
+                Ok(WsMessage::ApplyUpdates { packages }) => {
+                    tokio::spawn(lib::packages::apply(peer.clone(), tx.clone(), packages));
+                }
+                Ok(WsMessage::Upload(chunk)) => {
+                    lib::upload::queue(&upload_tx, &tx, chunk);
+                }
+                Ok(WsMessage::EOF) => {
+                    // the client is done, serve_connection cleans up after it
+                    return future::err(tokio_tungstenite::tungstenite::Error::ConnectionClosed);
+                }
+                Err(e) => {
+                    warn!("[ws] Invalid {} message from {}: {}", action, peer, e);
+                    let frame = protocol::error(ErrorCode::InvalidMessage, e.to_string(), id);
+                    protocol::send(&tx, &frame);
+                }
+            }
+        }
+        future::ok(())
+    });
+
+    // Forward messages from rx thread to outgoing websocket stream
+    let out_peer = peer.clone();
+    let outgoing_messages = async move {
+        while let Some(msg) = rx.recv().await {
+            if let Err(e) = outgoing.send(msg).await {
+                error!("[ws] Failed to send message to {}: {}", out_peer, e);
+                break;
+            }
+        }
+    };
+
+    // Run both tasks concurrently
+    tokio::select! {
+        _ = incoming_messages => {},
+        _ = outgoing_messages => {},
+    }
+
+    info!("{} disconnected", &peer);
+    peers.lock().await.remove(&peer);
+    // the writer finishes the chunks it took before the unfinished uploads are dropped
+    drop(upload_tx);
+    let _ = uploads.await;
+    lib::upload::abort(&peer);
+    tokio::spawn(async move {
+        let mut live_metrics = LIVE_METRICS.lock().await;
+        if let Some((child_handle, terminate_signal)) = live_metrics.remove(&peer) {
+            info!("[ws] Stopping live metrics for {}", peer);
+            terminate_signal.notify_one();
+            if let Some(child) = child_handle.lock().await.as_mut() {
+                if let Err(e) = child.kill().await {
+                    info!("[ws] Failed to stop live metrics for {}: {}", peer, e);
+                } else {
+                    info!("[ws] Stopped live metrics for {}", peer);
+                }
+            }
         }
     });
-    Ok(())
 }
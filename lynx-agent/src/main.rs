@@ -1,14 +1,17 @@
 mod lib;
 mod proto;
-use crate::lib::client::{handle_collector_requests, AuthInterceptor, GrpcClient, LynxConfig};
+use crate::lib::cache::{CacheLimits, FastCache};
+use crate::lib::client::{AuthInterceptor, LynxConfig};
 use crate::lib::collectors::CollectorRequest;
+use crate::lib::diagnostics::Diagnostics;
 use crate::lib::websocket::PeerMap;
 use bollard::query_parameters::ListContainersOptions;
 use dotenv::dotenv;
-use env_logger::Env;
 use futures_channel::mpsc::UnboundedSender;
-use log::{error, info};
-use proto::monitor::system_monitor_client::SystemMonitorClient;
+use log::{error, info, warn};
+use lynx_agent_sdk::connection::{self, HubConnection};
+use lynx_agent_sdk::{health, remote_config};
+use proto::monitor::control_client::ControlClient;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fmt::Debug;
@@ -21,64 +24,172 @@ use tokio_tungstenite::tungstenite::protocol::Message;
 use tonic::codegen::InterceptedService;
 use tonic::metadata::MetadataValue;
 use tonic::service::Interceptor;
-use tonic::transport::ClientTlsConfig;
 use tonic::{Code, Status};
 
 type Tx = UnboundedSender<Message>;
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
-    let env = Env::default()
-        .filter("MY_LOG_LEVEL")
-        .write_style("MY_LOG_STYLE");
-    env_logger::Builder::from_env(env)
-        .format_timestamp_secs()
-        .init();
+    lib::logging::init();
+    lib::crash::install_panic_hook();
 
     info!("[agent] Starting Lynx Agent...");
 
-    let client_tls_config = lib::client::tls_config().await.map_err(|e| {
-        error!("[agent] Failed to load TLS configuration: {}", e);
+    let config_path = std::path::Path::new("config.toml");
+    let config_str = std::fs::read_to_string(config_path).map_err(|e| {
+        error!("[agent] No config.toml found, please create one.");
         e
     })?;
 
-    let config_str = std::fs::read_to_string("config.toml").map_err(|e| {
-        error!("[agent] No config.toml found, please create one.");
+    let mut config: LynxConfig = toml::from_str(&config_str)?;
+    lib::credentials::resolve_agent_key(&mut config.core).map_err(|e| {
+        error!("[agent] Failed to load agent key: {}", e);
         e
     })?;
+    lib::discovery::resolve_server_url(&mut config)
+        .await
+        .map_err(|e| {
+            error!("[agent] {}", e);
+            e
+        })?;
 
-    let config: LynxConfig = toml::from_str(&config_str)?;
+    lib::system_info::set_disk_filter(config.disks.clone());
+    lib::websocket::set_command_limits(config.commands.clone());
+    lib::upload::set_upload_config(config.upload.clone());
+    lib::tunnel::set_tunnel_config(config.tunnels.clone());
+    lib::diagnostics::set_config(&config_str, &config.core.server_url);
+    tokio::spawn(lib::diagnostics::serve(config.diagnostics.clone()));
 
-    info!("Connecting to lynx-hub at {}", config.core.server_url);
+    // --insecure: plaintext gRPC/WS on localhost only, for local development without a CA
+    let insecure = std::env::args().any(|arg| arg == "--insecure");
+    let client_tls_config = if insecure {
+        lib::client::ensure_local_plaintext(&config.core.server_url).map_err(|e| {
+            error!("[agent] {}", e);
+            e
+        })?;
+        warn!("[agent] Running with --insecure: mTLS disabled, do not use in production");
+        None
+    } else {
+        lib::client::install_crypto_policy(&config.tls).map_err(|e| {
+            error!("[agent] Invalid TLS policy: {}", e);
+            e
+        })?;
+        lib::enroll::enroll_if_needed(&mut config, config_path)
+            .await
+            .map_err(|e| {
+                error!("[agent] Enrollment failed: {}", e);
+                e
+            })?;
 
-    let make_client = |config: &LynxConfig,
-                       tls: tonic::transport::ClientTlsConfig|
-     -> Result<tonic::transport::Endpoint, Box<dyn std::error::Error>> {
-        let endpoint = tonic::transport::Endpoint::from_shared(config.core.server_url.clone())?
-            .tls_config(tls)?
-            .tcp_keepalive(Some(Duration::from_secs(30)))
-            .http2_keep_alive_interval(Duration::from_secs(15))
-            .keep_alive_timeout(Duration::from_secs(5))
-            .keep_alive_while_idle(true)
-            .connect_timeout(Duration::from_secs(10));
-        Ok(endpoint)
+        let client_tls_config = lib::client::tls_config().await.map_err(|e| {
+            error!("[agent] Failed to load TLS configuration: {}", e);
+            e
+        })?;
+        match lib::client::cert_expiry_days() {
+            Some(days) if days < 0.0 => error!("[agent] Client certificate has expired"),
+            Some(days) if days < 14.0 => {
+                warn!("[agent] Client certificate expires in {:.1} days", days)
+            }
+            _ => {}
+        }
+        Some(client_tls_config)
     };
+    // tokens are bound to the agent key, which enrollment may have just written
+    lib::authorization::set_policy(config.authorization.clone(), &config.core.agent_key);
 
-    // Connect to gRPC server with mTLS
-    let endpoint = make_client(&config, client_tls_config.clone())?;
-    let channel = endpoint.connect().await?;
-    let mut client = SystemMonitorClient::with_interceptor(
-        channel,
-        AuthInterceptor {
-            agent_key: config.core.agent_key.clone(),
-        },
+    // Local cache used to only report what changed between collections
+    let cache = Arc::new(
+        FastCache::new_with_limits(
+            &config.cache.database_url,
+            true,
+            CacheLimits {
+                max_entries: config.cache.max_entries,
+                max_bytes: config.cache.max_bytes,
+            },
+        )
+        .await
+        .map_err(|e| {
+            error!("[agent] Failed to open cache database: {}", e);
+            e
+        })?,
     );
-    let mut grpc_client = GrpcClient::new(client, config, client_tls_config);
+    // tokens used before a restart stay used
+    lib::authorization::remember_used(cache.pool().clone())
+        .await
+        .map_err(|e| {
+            error!("[agent] Failed to load used command tokens: {}", e);
+            e
+        })?;
+    tokio::spawn(lib::cache::start_cleanup_task(
+        cache.clone(),
+        Duration::from_secs(config.cache.cleanup_interval_secs),
+    ));
+    tokio::spawn(lib::cache::start_compaction_task(
+        cache.clone(),
+        Duration::from_secs(config.cache.compact_interval_secs),
+    ));
+    // Reports the hub can't take are queued next to the cache and sent again once it is back
+    let mut spool = lib::spool::Spool::open(cache.pool().clone(), config.spool.clone())
+        .await
+        .map_err(|e| {
+            error!("[agent] Failed to open report spool: {}", e);
+            e
+        })?;
+
+    info!("Connecting to lynx-hub at {}", config.core.server_url);
+
+    // Connect to gRPC server with mTLS
+    let endpoint = lib::client::make_endpoint(&config, client_tls_config)?;
+    let auth = AuthInterceptor {
+        agent_key: config.core.agent_key.clone(),
+    };
+    let mut hub = HubConnection::connect(endpoint, auth.clone())
+        .await?
+        .with_observer(Arc::new(Diagnostics));
+    let channel = hub.channel();
+    let client = hub.client();
+    let crl_files = config.tls.crl_files.clone();
+    let tags = config.tags.clone();
+    let scripts = lib::scripts::Scripts::load(&config.scripts);
+
+    // Config pushed by the hub, collectors pick up changes live
+    let (config_tx, config_rx) = tokio::sync::watch::channel(Default::default());
+    tokio::spawn(remote_config::watch_config(
+        client.clone(),
+        config_tx,
+        lib::uninstall::request,
+    ));
+    let control = ControlClient::with_interceptor(channel, auth);
+    tokio::spawn(lib::crash::report_pending(control.clone()));
+    tokio::spawn(lib::update::run_update_checks(
+        control.clone(),
+        config.update.clone(),
+    ));
+    // actions taken over the websocket, see lib::audit
+    tokio::spawn(lib::audit::report_commands(control.clone()));
+    // collector panics and hangs reported by the supervisor
+    let (health_tx, health_rx) = mpsc::channel(health::HEALTH_QUEUE);
+    let session_control = control.clone();
+    tokio::spawn(health::report_health(control, health_rx));
+    tokio::spawn(lib::uninstall::run_on_request(
+        config.cache.database_url.clone(),
+    ));
+
+    // network rates for reports and live metrics come from one sampler
+    lib::network::sampler();
 
     // Start collectors with async mpsc
     let (tx, mut rx) = mpsc::channel::<lib::collectors::CollectorRequest>(1024);
 
-    lib::collectors::start_collectors(tx.clone()).await;
+    lib::collectors::start_collectors(
+        tx.clone(),
+        cache.clone(),
+        tags,
+        config_rx,
+        health_tx,
+        scripts,
+    )
+    .await;
 
     let mut handles = vec![];
 
@@ -87,10 +198,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // WebSocket server for real-time updates
     let peers = state.clone();
     let websocket_handle = tokio::spawn(async move {
-        let _ = lib::websocket::start_websocket_server(peers).await;
+        if let Err(e) = lib::websocket::start_websocket_server(peers, insecure, crl_files).await {
+            error!("[ws] Failed to start websocket server: {}", e);
+        }
     });
     handles.push(websocket_handle);
 
+    // Dashboard sessions relayed by the hub, served like websocket clients
+    tokio::spawn(lib::sessions::relay_sessions(
+        session_control,
+        state.clone(),
+    ));
+
+    let mut health_checks = tokio::time::interval(connection::HEALTH_INTERVAL);
+    let mut replay = tokio::time::interval(lib::spool::REPLAY_INTERVAL);
     loop {
         // Check if any tasks have finished or panicked
         handles.retain(|handle| {
@@ -103,16 +224,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
 
         tokio::select! {
-            Some(request) = rx.recv() => {
-                if let Err(e) = handle_collector_requests(&mut grpc_client, request).await {
-                    error!("[agent] Error handling collector request: {}", e);
+            request = rx.recv() => match request {
+                Some(request) => spool.report(&mut hub, request).await,
+                None => {
+                    // Channel closed
+                    error!("[agent] All collectors have shut down, exiting main loop.");
+                    break;
                 }
-            }
-            else => {
-                // Channel closed
-                error!("[agent] All collectors have shut down, exiting main loop.");
-                break;
-            }
+            },
+            // notices a hub that went away while collectors are quiet
+            _ = health_checks.tick() => hub.check_health().await,
+            // a batch of queued reports at a time, new ones wait behind them in the spool
+            _ = replay.tick(), if !spool.is_empty() => spool.replay(&mut hub).await,
         }
     }
     Ok(())
@@ -1 +1 @@
-pub mod monitor;
\ No newline at end of file
+pub use lynx_agent_sdk::proto::monitor;
@@ -0,0 +1,120 @@
+use lynx_core::cache::Cache;
+use lynx_core::proto::monitor::{SystemService, SystemctlRequest};
+use prost::Message;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+/*
+ * Systemctl report benchmark
+ * A host with 5000 units: the report encoded as one message and as the 500-unit chunks agents
+ * stream, and the hub's cache path for all of it. Run with `cargo bench --bench systemctl`.
+ */
+
+const UNITS: usize = 5_000;
+const CHUNK: usize = 500;
+const ROUNDS: u32 = 20;
+
+fn units(count: usize) -> Vec<SystemService> {
+    (0..count)
+        .map(|i| SystemService {
+            service_name: format!("unit-{i:05}.service"),
+            description: format!("Benchmark unit number {i} with a typical description"),
+            pid: 1_000 + i as u64,
+            state: if i % 10 == 0 { "Inactive" } else { "Active" }.to_string(),
+            cpu: format!("{}ms", i * 3),
+            memory: format!("{}.{}M", i % 512, i % 10),
+            ..Default::default()
+        })
+        .collect()
+}
+
+fn chunked(services: Vec<SystemService>) -> Vec<SystemctlRequest> {
+    let mut services = services.into_iter().peekable();
+    let mut chunks = Vec::new();
+    while services.peek().is_some() {
+        chunks.push(SystemctlRequest {
+            services: services.by_ref().take(CHUNK).collect(),
+            removed: Vec::new(),
+            full_sync: true,
+        });
+    }
+    chunks
+}
+
+fn report(name: &str, total: Duration, detail: String) {
+    println!(
+        "{name:<28} {:>10.3} ms/round  {detail}",
+        total.as_secs_f64() * 1e3 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let whole = SystemctlRequest {
+        services: units(UNITS),
+        removed: Vec::new(),
+        full_sync: true,
+    };
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        black_box(whole.encode_to_vec());
+        elapsed += started.elapsed();
+    }
+    report(
+        "encode, one message",
+        elapsed,
+        format!("largest message {} bytes", whole.encoded_len()),
+    );
+
+    let chunks = chunked(units(UNITS));
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let started = Instant::now();
+        for chunk in &chunks {
+            black_box(chunk.encode_to_vec());
+        }
+        elapsed += started.elapsed();
+    }
+    let largest = chunks.iter().map(Message::encoded_len).max().unwrap_or(0);
+    report(
+        "encode, chunked",
+        elapsed,
+        format!("{} messages, largest {largest} bytes", chunks.len()),
+    );
+
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let chunks = chunked(units(UNITS));
+        let started = Instant::now();
+        for chunk in chunks {
+            black_box(SystemctlRequest::decode(chunk.encode_to_vec().as_slice()).unwrap());
+        }
+        elapsed += started.elapsed();
+    }
+    report("round trip, chunked", elapsed, String::new());
+
+    let cache = Cache::new(100, 100);
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        let services = units(UNITS);
+        let started = Instant::now();
+        cache.record_services(1, &services);
+        for service in services {
+            cache.upsert_service(service);
+        }
+        elapsed += started.elapsed();
+    }
+    let stats = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(cache.stats());
+    report(
+        "cache, record and upsert",
+        elapsed,
+        format!(
+            "{} services, ~{} KiB",
+            cache.service_count(),
+            stats.approx_memory_bytes / 1024
+        ),
+    );
+}
@@ -1,8 +1,20 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Served by the reflection service, stays in OUT_DIR
+    let descriptor_path =
+        std::path::PathBuf::from(std::env::var("OUT_DIR")?).join("monitor_descriptor.bin");
     tonic_build::configure()
         .build_server(true)
+        .file_descriptor_set_path(descriptor_path)
         .out_dir("src/proto")
         .protoc_arg("-I=../lynx-proto")
-        .compile_protos(&["monitor.proto"], &["."])?;
+        .compile_protos(
+            &[
+                "metrics.proto",
+                "inventory.proto",
+                "control.proto",
+                "monitor.proto",
+            ],
+            &["."],
+        )?;
     Ok(())
 }
@@ -0,0 +1,202 @@
+use dashmap::DashMap;
+use log::error;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct AuthLimitOptions {
+    /// Authentication attempts per source IP and minute, 0 disables the limit
+    pub per_ip_per_min: u32,
+    /// Authentication attempts per agent key and minute, 0 disables the limit
+    pub per_key_per_min: u32,
+    /// Failed attempts within `lockout_secs` before the IP / key is locked out, 0 disables lockout
+    pub max_failures: u32,
+    pub lockout_secs: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthDenied {
+    /// `first` is only set for the attempt that crossed the limit, so it is recorded once
+    RateLimited { subject: String, first: bool },
+    LockedOut {
+        subject: String,
+        retry_after: Duration,
+    },
+}
+
+struct Window {
+    started: Instant,
+    attempts: u32,
+}
+
+struct Failures {
+    started: Instant,
+    count: u32,
+    locked_until: Option<Instant>,
+}
+
+/*
+ * AuthLimiter
+ * Throttles agent authentication per source IP and per key, and locks either out after repeated
+ * failures so agent keys can't be brute-forced. Keys are tracked by `key_id`, never in plaintext.
+ */
+pub struct AuthLimiter {
+    opts: AuthLimitOptions,
+    windows: DashMap<String, Window>,
+    failures: DashMap<String, Failures>,
+}
+
+/// Short, non-reversible identifier of an agent key for counters and auth events.
+pub fn key_id(agent_key: &str) -> String {
+    openssl::sha::sha256(agent_key.as_bytes())[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn subjects(ip: Option<IpAddr>, key_id: &str) -> Vec<(String, bool)> {
+    let mut subjects = Vec::with_capacity(2);
+    if let Some(ip) = ip {
+        subjects.push((format!("ip {ip}"), true));
+    }
+    subjects.push((format!("key {key_id}"), false));
+    subjects
+}
+
+impl AuthLimiter {
+    pub fn new(opts: AuthLimitOptions) -> Self {
+        Self {
+            opts,
+            windows: DashMap::new(),
+            failures: DashMap::new(),
+        }
+    }
+
+    fn lockout(&self) -> Duration {
+        Duration::from_secs(self.opts.lockout_secs)
+    }
+
+    /// Counts an authentication attempt, failing while the IP or key is locked out or over its rate.
+    pub fn check(&self, ip: Option<IpAddr>, key_id: &str) -> Result<(), AuthDenied> {
+        let now = Instant::now();
+        let subjects = subjects(ip, key_id);
+
+        for (subject, _) in &subjects {
+            let locked_until = self.failures.get(subject).and_then(|f| f.locked_until);
+            if let Some(until) = locked_until.filter(|until| *until > now) {
+                return Err(AuthDenied::LockedOut {
+                    subject: subject.clone(),
+                    retry_after: until - now,
+                });
+            }
+        }
+
+        for (subject, is_ip) in subjects {
+            let limit = match is_ip {
+                true => self.opts.per_ip_per_min,
+                false => self.opts.per_key_per_min,
+            };
+            if limit == 0 {
+                continue;
+            }
+            let mut window = self.windows.entry(subject.clone()).or_insert(Window {
+                started: now,
+                attempts: 0,
+            });
+            if now.duration_since(window.started) >= RATE_WINDOW {
+                window.started = now;
+                window.attempts = 0;
+            }
+            window.attempts = window.attempts.saturating_add(1);
+            if window.attempts > limit {
+                return Err(AuthDenied::RateLimited {
+                    first: window.attempts == limit + 1,
+                    subject,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a failed attempt and returns the subjects that just got locked out.
+    pub fn record_failure(&self, ip: Option<IpAddr>, key_id: &str) -> Vec<String> {
+        if self.opts.max_failures == 0 {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        let mut locked = Vec::new();
+
+        for (subject, _) in subjects(ip, key_id) {
+            let mut failures = self.failures.entry(subject.clone()).or_insert(Failures {
+                started: now,
+                count: 0,
+                locked_until: None,
+            });
+            let expired = match failures.locked_until {
+                Some(until) => until <= now,
+                None => now.duration_since(failures.started) >= self.lockout(),
+            };
+            if expired {
+                failures.started = now;
+                failures.count = 0;
+                failures.locked_until = None;
+            }
+            failures.count += 1;
+            if failures.count >= self.opts.max_failures && failures.locked_until.is_none() {
+                failures.locked_until = Some(now + self.lockout());
+                locked.push(subject);
+            }
+        }
+        locked
+    }
+
+    /// A successful authentication clears the failures counted against the key.
+    pub fn record_success(&self, key_id: &str) {
+        self.failures.remove(&format!("key {key_id}"));
+    }
+
+    /// Drops expired windows and lockouts so the maps don't grow with every scanning IP.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.windows
+            .retain(|_, w| now.duration_since(w.started) < RATE_WINDOW);
+        self.failures.retain(|_, f| match f.locked_until {
+            Some(until) => until > now,
+            None => now.duration_since(f.started) < self.lockout(),
+        });
+    }
+}
+
+/// Best effort, a failed insert must not turn into an authentication error.
+pub async fn record_auth_event(
+    pool: &PgPool,
+    event: &str,
+    remote_ip: Option<IpAddr>,
+    key_id: &str,
+    system_id: Option<i32>,
+) {
+    let result = sqlx::query!(
+        r#"INSERT INTO auth_events (time, event, source_ip, key_id, system) VALUES (NOW(), $1, $2, $3, $4)"#,
+        event,
+        remote_ip.map(|ip| ip.to_string()),
+        key_id,
+        system_id
+    )
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        error!("[hub] Failed to record auth event {event}: {e}");
+    }
+}
+
+pub async fn run_prune(limiter: Arc<AuthLimiter>) {
+    let mut tick = tokio::time::interval(RATE_WINDOW);
+    loop {
+        tick.tick().await;
+        limiter.prune();
+    }
+}
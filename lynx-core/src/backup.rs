@@ -0,0 +1,411 @@
+use crate::units;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter};
+use tonic::codegen::tokio_stream::StreamExt;
+
+/*
+ * Backup and restore
+ * `lynx-core backup <file>` writes systems, users, rules, notifiers, alert history and the
+ * recent metrics to a JSON lines archive: a header line, then one {"table", "row"} line per
+ * row in the order of TABLES. `lynx-core restore <file>` loads it into a hub database whose
+ * schema is in place but holds no systems yet, in one transaction, keeping the original ids.
+ * Rows are exported with row_to_json and loaded with json_populate_recordset, so the archive
+ * follows the schema of the hub that wrote it. Rows of older archive versions are upgraded to
+ * the current schema while restoring (see upgrade_row).
+ */
+
+pub const ARCHIVE_FORMAT: &str = "lynx-backup";
+pub const ARCHIVE_VERSION: u32 = 2;
+/// Days of metrics kept in an archive unless `--metrics-days` says otherwise.
+pub const DEFAULT_METRICS_DAYS: i64 = 7;
+
+/// Rows sent to the database per insert during a restore.
+const RESTORE_BATCH: usize = 1_000;
+
+pub struct BackupTable {
+    pub name: &'static str,
+    /// Only rows newer than the metrics window are exported when set
+    pub time_column: Option<&'static str>,
+    /// Identity or serial `id` whose sequence is moved past the restored rows
+    pub has_id: bool,
+}
+
+const fn table(name: &'static str, has_id: bool) -> BackupTable {
+    BackupTable {
+        name,
+        time_column: None,
+        has_id,
+    }
+}
+
+const fn series(name: &'static str, time_column: &'static str) -> BackupTable {
+    BackupTable {
+        name,
+        time_column: Some(time_column),
+        has_id: false,
+    }
+}
+
+/// Archived tables, parents before the rows referencing them.
+pub const TABLES: &[BackupTable] = &[
+    table("users", true),
+    table("systems", true),
+    table("notifiers", true),
+    table("alert_rules", true),
+    table("alert_notifiers", false),
+    table("alert_systems", false),
+    table("alert_history", true),
+    table("agent_config", true),
+    table("feature_flags", false),
+    table("snmp_devices", true),
+    table("prometheus_targets", true),
+    series("metrics", "time"),
+    series("disks", "time"),
+    series("custom_metrics", "time"),
+];
+
+/// Columns of systems that the metrics triggers overwrite while restoring.
+const SYSTEM_STATE_COLUMNS: &[&str] = &[
+    "last_seen",
+    "cpu_usage",
+    "uptime",
+    "memory_used_bytes",
+    "memory_total_bytes",
+    "clock_skew_ms",
+];
+
+#[derive(Error, Debug)]
+pub enum BackupError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid archive line {line}: {reason}")]
+    Format { line: usize, reason: String },
+    #[error("The target database already has systems, restore into an empty hub")]
+    NotEmpty,
+    #[error("{0}")]
+    Usage(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ArchiveHeader {
+    pub format: String,
+    pub version: u32,
+    pub created: chrono::DateTime<chrono::Utc>,
+    pub metrics_days: i64,
+}
+
+#[derive(Deserialize)]
+struct ArchiveRow {
+    table: String,
+    row: serde_json::Value,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Backup { path: PathBuf, metrics_days: i64 },
+    Restore { path: PathBuf },
+}
+
+impl Command {
+    /// The subcommand in the hub's arguments (without the program name), None to run the hub.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, BackupError> {
+        let usage = || {
+            BackupError::Usage(
+                "usage: lynx-core backup <file> [--metrics-days N] | lynx-core restore <file>"
+                    .to_string(),
+            )
+        };
+        let Some(command) = args.first() else {
+            return Ok(None);
+        };
+        match command.as_str() {
+            "backup" => {
+                let mut path = None;
+                let mut metrics_days = DEFAULT_METRICS_DAYS;
+                let mut rest = args[1..].iter();
+                while let Some(arg) = rest.next() {
+                    if arg == "--metrics-days" {
+                        metrics_days = rest
+                            .next()
+                            .and_then(|days| days.parse::<i64>().ok())
+                            .filter(|days| *days >= 0)
+                            .ok_or_else(usage)?;
+                    } else if path.is_none() {
+                        path = Some(PathBuf::from(arg));
+                    } else {
+                        return Err(usage());
+                    }
+                }
+                Ok(Some(Command::Backup {
+                    path: path.ok_or_else(usage)?,
+                    metrics_days,
+                }))
+            }
+            "restore" => match &args[1..] {
+                [path] => Ok(Some(Command::Restore {
+                    path: PathBuf::from(path),
+                })),
+                _ => Err(usage()),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Rows written or restored per table.
+pub type Summary = Vec<(&'static str, u64)>;
+
+pub fn parse_header(line: &str) -> Result<ArchiveHeader, BackupError> {
+    let invalid = |reason: String| BackupError::Format { line: 1, reason };
+    let header: ArchiveHeader =
+        serde_json::from_str(line).map_err(|e| invalid(format!("not a header: {e}")))?;
+    if header.format != ARCHIVE_FORMAT {
+        return Err(invalid(format!("unknown format {}", header.format)));
+    }
+    if header.version > ARCHIVE_VERSION {
+        return Err(invalid(format!(
+            "version {} is newer than this hub supports ({ARCHIVE_VERSION})",
+            header.version
+        )));
+    }
+    Ok(header)
+}
+
+/*
+ * backup
+ * Streams every table to the archive. Configuration tables are small, the metrics tables are
+ * limited to the last `metrics_days` days (0 leaves them out).
+ */
+pub async fn backup(pool: &PgPool, path: &Path, metrics_days: i64) -> Result<Summary, BackupError> {
+    let mut out = BufWriter::new(File::create(path).await?);
+    let header = ArchiveHeader {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        created: chrono::Utc::now(),
+        metrics_days,
+    };
+    let header = serde_json::to_string(&header).expect("header serializes");
+    write_line(&mut out, &header).await?;
+
+    let mut summary = Summary::new();
+    for table in TABLES {
+        let sql = match table.time_column {
+            Some(_) if metrics_days == 0 => continue,
+            Some(col) => format!(
+                "SELECT row_to_json(t)::text FROM {name} t WHERE t.{col} >= NOW() - ($1 * INTERVAL '1 day')",
+                name = table.name
+            ),
+            None => format!("SELECT row_to_json(t)::text FROM {} t", table.name),
+        };
+        let mut query = sqlx::query_scalar::<_, String>(&sql);
+        if table.time_column.is_some() {
+            query = query.bind(metrics_days);
+        }
+        let mut rows = query.fetch(pool);
+        let mut count = 0;
+        while let Some(row) = rows.next().await {
+            // row_to_json already produced the JSON, no need to parse it again
+            let line = format!("{{\"table\":\"{}\",\"row\":{}}}", table.name, row?);
+            write_line(&mut out, &line).await?;
+            count += 1;
+        }
+        info!("[backup] {}: {count} rows", table.name);
+        summary.push((table.name, count));
+    }
+    out.flush().await?;
+    Ok(summary)
+}
+
+async fn write_line(out: &mut BufWriter<File>, line: &str) -> Result<(), std::io::Error> {
+    out.write_all(line.as_bytes()).await?;
+    out.write_all(b"\n").await
+}
+
+/*
+ * restore
+ * Loads an archive into an empty hub in a single transaction, nothing is kept when a row fails.
+ * The metrics triggers refresh last_seen and friends on systems as rows go in, so those columns
+ * are put back from the archive at the end, and every id sequence continues after the
+ * restored rows.
+ */
+pub async fn restore(pool: &PgPool, path: &Path) -> Result<Summary, BackupError> {
+    let mut lines = BufReader::new(File::open(path).await?).lines();
+    let header = match lines.next_line().await? {
+        Some(line) => parse_header(&line)?,
+        None => {
+            return Err(BackupError::Format {
+                line: 1,
+                reason: "empty archive".to_string(),
+            })
+        }
+    };
+    info!(
+        "[backup] Restoring archive from {} with {} days of metrics",
+        header.created, header.metrics_days
+    );
+
+    let mut tx = pool.begin().await?;
+    let has_systems: bool = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM systems)")
+        .fetch_one(&mut *tx)
+        .await?;
+    if has_systems {
+        return Err(BackupError::NotEmpty);
+    }
+
+    let mut summary = Summary::new();
+    let mut current: Option<usize> = None;
+    let mut batch: Vec<String> = Vec::new();
+    let mut systems: Vec<String> = Vec::new();
+    let mut line_no = 1;
+    while let Some(line) = lines.next_line().await? {
+        line_no += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: ArchiveRow = serde_json::from_str(&line).map_err(|e| BackupError::Format {
+            line: line_no,
+            reason: e.to_string(),
+        })?;
+        let index = TABLES
+            .iter()
+            .position(|t| t.name == row.table)
+            .ok_or_else(|| BackupError::Format {
+                line: line_no,
+                reason: format!("unknown table {}", row.table),
+            })?;
+        // tables arrive in TABLES order, going back would break the foreign keys
+        if current.is_some_and(|c| index < c) {
+            return Err(BackupError::Format {
+                line: line_no,
+                reason: format!("{} out of order", row.table),
+            });
+        }
+        if current != Some(index) {
+            if let Some(c) = current {
+                insert_batch(&mut tx, TABLES[c].name, &mut batch).await?;
+            }
+            summary.push((TABLES[index].name, 0));
+            current = Some(index);
+        }
+        let mut value = row.row;
+        upgrade_row(header.version, &row.table, &mut value);
+        let json = value.to_string();
+        if row.table == "systems" {
+            systems.push(json.clone());
+        }
+        batch.push(json);
+        if let Some((_, count)) = summary.last_mut() {
+            *count += 1;
+        }
+        if batch.len() >= RESTORE_BATCH {
+            insert_batch(&mut tx, TABLES[index].name, &mut batch).await?;
+        }
+    }
+    if let Some(c) = current {
+        insert_batch(&mut tx, TABLES[c].name, &mut batch).await?;
+    }
+
+    if !systems.is_empty() {
+        let set = SYSTEM_STATE_COLUMNS
+            .iter()
+            .map(|col| format!("{col} = r.{col}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let sql = format!(
+            "UPDATE systems s SET {set} FROM json_populate_recordset(NULL::systems, $1::json) r WHERE s.id = r.id"
+        );
+        sqlx::query(&sql)
+            .bind(format!("[{}]", systems.join(",")))
+            .execute(&mut *tx)
+            .await?;
+    }
+    for table in TABLES.iter().filter(|t| t.has_id) {
+        let sql = format!(
+            "SELECT setval(pg_get_serial_sequence('{name}', 'id'), MAX(id)) FROM {name} HAVING MAX(id) IS NOT NULL",
+            name = table.name
+        );
+        sqlx::query(&sql).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+
+    for (table, count) in &summary {
+        info!("[backup] {table}: {count} rows restored");
+    }
+    Ok(summary)
+}
+
+/// Moves `from` to `to`, multiplied by `factor`.
+fn rename_scaled(
+    row: &mut serde_json::Map<String, serde_json::Value>,
+    from: &str,
+    to: &str,
+    factor: u64,
+) {
+    let Some(value) = row.remove(from) else {
+        return;
+    };
+    let scaled = match value.as_i64() {
+        Some(n) => n.saturating_mul(factor as i64).into(),
+        None => value
+            .as_f64()
+            .map_or(serde_json::Value::Null, |v| (v * factor as f64).into()),
+    };
+    row.insert(to.to_string(), scaled);
+}
+
+/*
+ * upgrade_row
+ * Version 1 archives predate sizes in bytes: memory in KB, network in whole MB/s and disk space
+ * in the disk's `unit`. Their rows are converted the way units::migrate converts a database.
+ */
+pub fn upgrade_row(version: u32, table: &str, row: &mut serde_json::Value) {
+    let Some(row) = row.as_object_mut().filter(|_| version < 2) else {
+        return;
+    };
+    match table {
+        "systems" => {
+            rename_scaled(row, "memory_used", "memory_used_bytes", 1 << 10);
+            rename_scaled(row, "memory_total", "memory_total_bytes", 1 << 10);
+        }
+        "metrics" => {
+            for (from, to, factor) in units::METRIC_COLUMNS {
+                rename_scaled(row, from, to, *factor);
+            }
+        }
+        "disks" => {
+            let unit = row.remove("unit");
+            let factor =
+                units::disk_unit_bytes(unit.as_ref().and_then(|u| u.as_str()).unwrap_or(""));
+            rename_scaled(row, "space", "space_bytes", factor);
+            rename_scaled(row, "used", "used_bytes", factor);
+        }
+        _ => {}
+    }
+}
+
+async fn insert_batch(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    table: &str,
+    batch: &mut Vec<String>,
+) -> Result<(), sqlx::Error> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+    // keeps the archived ids, identity columns would otherwise refuse them
+    let sql = format!(
+        "INSERT INTO {table} OVERRIDING SYSTEM VALUE SELECT * FROM json_populate_recordset(NULL::{table}, $1::json)"
+    );
+    sqlx::query(&sql)
+        .bind(format!("[{}]", batch.join(",")))
+        .execute(&mut **tx)
+        .await?;
+    batch.clear();
+    Ok(())
+}
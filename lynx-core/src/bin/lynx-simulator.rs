@@ -0,0 +1,311 @@
+use dotenv::dotenv;
+use log::{error, info, warn};
+use lynx_core::proto::monitor::metrics_ingest_client::MetricsIngestClient;
+use lynx_core::simulator::SimulatedSystem;
+use lynx_core::tls::pem_fingerprint;
+use sqlx::PgPool;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval, sleep, Instant, MissedTickBehavior};
+use tonic::codegen::InterceptedService;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
+use tonic::{Code, Request, Status};
+
+/*
+ * lynx-simulator
+ * Load generator for the hub: registers SIM_SYSTEMS fake systems in the hub's database and
+ * reports metrics for each of them every SIM_INTERVAL_SECS, like that many agents would. Used to
+ * measure ingest throughput, alert evaluation and retention without a real fleet.
+ *
+ *   HUB_URL              hub gRPC address (default http://localhost:50051)
+ *   DATABASE_URL         the hub's database, the systems are created or re-keyed there
+ *   SIM_SYSTEMS          fake systems (default 100), named SIM_PREFIX + number
+ *   SIM_PREFIX           hostname prefix (default lynx-sim-)
+ *   SIM_INTERVAL_SECS    seconds between reports of a system (default 10)
+ *   SIM_CONNECTIONS      gRPC connections the systems are spread over (default 4)
+ *   SIM_DURATION_SECS    stop after this long, 0 (default) runs until Ctrl-C
+ *   SIM_BACKFILL_HOURS   first report this many hours of history per system, at most 23
+ *   LYNX_CERT_PATH, LYNX_KEY_PATH, LYNX_CA_PATH   client certificate for https:// hubs
+ */
+
+type IngestClient = MetricsIngestClient<InterceptedService<Channel, AgentKey>>;
+
+const STATS_INTERVAL: Duration = Duration::from_secs(10);
+const RPC_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
+struct Options {
+    hub_url: String,
+    database_url: String,
+    systems: u32,
+    prefix: String,
+    interval: Duration,
+    connections: usize,
+    duration: Option<Duration>,
+    backfill: Duration,
+}
+
+impl Options {
+    fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let database_url = std::env::var("DATABASE_URL")
+            .map_err(|_| "DATABASE_URL environment variable is not set")?;
+        let backfill_hours: u64 = env_or("SIM_BACKFILL_HOURS", 0);
+        if backfill_hours > 23 {
+            return Err(
+                "SIM_BACKFILL_HOURS can be at most 23, the hub rejects older samples".into(),
+            );
+        }
+        let duration = env_or("SIM_DURATION_SECS", 0);
+        Ok(Self {
+            hub_url: env_or("HUB_URL", "http://localhost:50051".to_string()),
+            database_url,
+            systems: env_or("SIM_SYSTEMS", 100),
+            prefix: env_or("SIM_PREFIX", "lynx-sim-".to_string()),
+            interval: Duration::from_secs(env_or("SIM_INTERVAL_SECS", 10).max(1)),
+            connections: env_or("SIM_CONNECTIONS", 4).max(1),
+            duration: (duration > 0).then(|| Duration::from_secs(duration)),
+            backfill: Duration::from_secs(backfill_hours * 3600),
+        })
+    }
+}
+
+#[derive(Clone)]
+struct AgentKey(MetadataValue<Ascii>);
+
+impl Interceptor for AgentKey {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        request.metadata_mut().insert("x-agent-key", self.0.clone());
+        Ok(request)
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    sent: AtomicU64,
+    rejected: AtomicU64,
+    failed: AtomicU64,
+    latency_us: AtomicU64,
+    incidents: AtomicU64,
+}
+
+impl Stats {
+    fn record(&self, result: &Result<(), Status>, latency: Duration) {
+        self.latency_us
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        let counter = match result {
+            Ok(()) => &self.sent,
+            Err(status) if status.code() == Code::ResourceExhausted => &self.rejected,
+            Err(_) => &self.failed,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Totals since the previous call.
+    fn take(&self) -> (u64, u64, u64, u64, u64) {
+        (
+            self.sent.swap(0, Ordering::Relaxed),
+            self.rejected.swap(0, Ordering::Relaxed),
+            self.failed.swap(0, Ordering::Relaxed),
+            self.latency_us.swap(0, Ordering::Relaxed),
+            self.incidents.swap(0, Ordering::Relaxed),
+        )
+    }
+}
+
+/// A client certificate when the hub is reached over https, pinned for every fake system.
+fn tls_config() -> Result<(ClientTlsConfig, String), Box<dyn std::error::Error>> {
+    let cert_path = env_or("LYNX_CERT_PATH", "certs/agent.crt".to_string());
+    let key_path = env_or("LYNX_KEY_PATH", "certs/agent.key".to_string());
+    let ca_path = env_or("LYNX_CA_PATH", "certs/ca.crt".to_string());
+    let cert = std::fs::read_to_string(&cert_path)
+        .map_err(|e| format!("Failed to read {cert_path}: {e}"))?;
+    let key = std::fs::read_to_string(&key_path)
+        .map_err(|e| format!("Failed to read {key_path}: {e}"))?;
+    let ca =
+        std::fs::read_to_string(&ca_path).map_err(|e| format!("Failed to read {ca_path}: {e}"))?;
+    let fingerprint = pem_fingerprint(&cert)?;
+    let tls = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(ca))
+        .identity(Identity::from_pem(cert, key));
+    Ok((tls, fingerprint))
+}
+
+/*
+ * provision
+ * Creates the fake systems, or takes over the ones a previous run left, with a fresh key each.
+ * With a client certificate its fingerprint is stored too, so hubs pinning certificates accept
+ * every fake system on the same one.
+ */
+async fn provision(
+    pool: &PgPool,
+    options: &Options,
+    fingerprint: Option<&str>,
+) -> Result<Vec<(u32, String)>, sqlx::Error> {
+    let mut systems = Vec::with_capacity(options.systems as usize);
+    for n in 1..=options.systems {
+        let hostname = format!("{}{n:05}", options.prefix);
+        let key = uuid::Uuid::new_v4().to_string();
+        let updated = sqlx::query(
+            "UPDATE systems SET key = $2, active = true, cert_fingerprint = $3 \
+             WHERE hostname = $1",
+        )
+        .bind(&hostname)
+        .bind(&key)
+        .bind(fingerprint)
+        .execute(pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            sqlx::query(
+                "INSERT INTO systems (hostname, address, label, key, active, cert_fingerprint) \
+                 VALUES ($1, '127.0.0.1', $1, $2, true, $3)",
+            )
+            .bind(&hostname)
+            .bind(&key)
+            .bind(fingerprint)
+            .execute(pool)
+            .await?;
+        }
+        systems.push((n, key));
+    }
+    Ok(systems)
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+async fn report(
+    client: &mut IngestClient,
+    system: &mut SimulatedSystem,
+    collected_at_ms: i64,
+    stats: &Stats,
+) {
+    let sample = system.sample(collected_at_ms);
+    if system.in_incident() {
+        stats.incidents.fetch_add(1, Ordering::Relaxed);
+    }
+    let started = Instant::now();
+    let result = match tokio::time::timeout(RPC_TIMEOUT, client.report_metrics(sample)).await {
+        Ok(result) => result.map(|_| ()),
+        Err(_) => Err(Status::deadline_exceeded("Timed out")),
+    };
+    match &result {
+        Err(status) if status.code() != Code::ResourceExhausted => {
+            warn!("[simulator] Report failed: {}", status.message())
+        }
+        _ => {}
+    }
+    stats.record(&result, started.elapsed());
+}
+
+/*
+ * simulate
+ * One fake system. The first report is delayed by the system's share of the interval so the
+ * fleet reports evenly spread out instead of in bursts; backfilled history is sent as fast as
+ * the hub takes it.
+ */
+async fn simulate(
+    n: u32,
+    key: String,
+    channel: Channel,
+    options: Arc<Options>,
+    stats: Arc<Stats>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let auth = AgentKey(MetadataValue::try_from(key)?);
+    let mut client = MetricsIngestClient::with_interceptor(channel, auth);
+    let mut system = SimulatedSystem::new(n as u64);
+
+    let step = options.interval.as_millis() as i64;
+    let mut collected_at = now_ms() - options.backfill.as_millis() as i64;
+    while collected_at < now_ms() - step {
+        report(&mut client, &mut system, collected_at, &stats).await;
+        collected_at += step;
+    }
+
+    sleep(options.interval * (n % options.systems) / options.systems).await;
+    let mut ticks = interval(options.interval);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        report(&mut client, &mut system, now_ms(), &stats).await;
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    lynx_core::logging::init();
+    let options = Arc::new(Options::from_env()?);
+
+    let tls = if options.hub_url.starts_with("https://") {
+        Some(tls_config()?)
+    } else {
+        None
+    };
+    let fingerprint = tls.as_ref().map(|(_, fingerprint)| fingerprint.as_str());
+
+    let pool = PgPool::connect(&options.database_url).await?;
+    let systems = provision(&pool, &options, fingerprint).await?;
+    pool.close().await;
+    info!(
+        "[simulator] {} systems ready, reporting every {}s to {}",
+        systems.len(),
+        options.interval.as_secs(),
+        options.hub_url
+    );
+
+    let mut channels = Vec::with_capacity(options.connections);
+    for _ in 0..options.connections {
+        let mut endpoint = Endpoint::from_shared(options.hub_url.clone())?;
+        if let Some((tls, _)) = &tls {
+            endpoint = endpoint.tls_config(tls.clone())?;
+        }
+        channels.push(endpoint.connect().await?);
+    }
+
+    let stats = Arc::new(Stats::default());
+    for (n, key) in systems {
+        let channel = channels[n as usize % channels.len()].clone();
+        let (options, stats) = (options.clone(), stats.clone());
+        tokio::spawn(async move {
+            if let Err(e) = simulate(n, key, channel, options, stats).await {
+                error!("[simulator] System {n} stopped: {e}");
+            }
+        });
+    }
+
+    let started = Instant::now();
+    let mut ticks = interval(STATS_INTERVAL);
+    ticks.tick().await;
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        let (sent, rejected, failed, latency_us, incidents) = stats.take();
+        let calls = (sent + rejected + failed).max(1);
+        info!(
+            "[simulator] {:.1} reports/s, {} rejected, {} failed, {:.1} ms avg, {} in incidents",
+            sent as f64 / STATS_INTERVAL.as_secs_f64(),
+            rejected,
+            failed,
+            latency_us as f64 / calls as f64 / 1e3,
+            incidents
+        );
+        if options.duration.is_some_and(|d| started.elapsed() >= d) {
+            break;
+        }
+    }
+    info!("[simulator] Stopped after {}s", started.elapsed().as_secs());
+    Ok(())
+}
@@ -1,13 +1,27 @@
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use std::collections::HashMap;
+use std::future::Future;
 use std::path::Path;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{self, error::TrySendError};
 use tokio::sync::RwLock;
 
-use crate::proto::monitor::SystemService;
+use crate::proto::monitor::{GpuMetrics, MetricsRequest, SystemService};
+use crate::shared::{SharedError, SharedState};
+use log::warn;
+use prost::Message;
 use serde::{Deserialize, Serialize};
 
+/// A Redis write waiting for the cache's writer.
+type SharedWrite = Pin<Box<dyn Future<Output = Result<(), SharedError>> + Send>>;
+
+/// Redis writes queued behind the writer, further ones are dropped while it is behind.
+const SHARED_WRITE_QUEUE: usize = 10_000;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ConfigChange {
     pub key: String,
@@ -31,6 +45,12 @@ struct SerializableSystemService {
     pub state: String,
     pub cpu: String,
     pub memory: String,
+    #[serde(default)]
+    pub requires: Vec<String>,
+    #[serde(default)]
+    pub wants: Vec<String>,
+    #[serde(default)]
+    pub after: Vec<String>,
 }
 
 impl From<&SystemService> for SerializableSystemService {
@@ -42,6 +62,9 @@ impl From<&SystemService> for SerializableSystemService {
             state: s.state.clone(),
             cpu: s.cpu.clone(),
             memory: s.memory.clone(),
+            requires: s.requires.clone(),
+            wants: s.wants.clone(),
+            after: s.after.clone(),
         }
     }
 }
@@ -55,6 +78,9 @@ impl From<SerializableSystemService> for SystemService {
             state: s.state,
             cpu: s.cpu,
             memory: s.memory,
+            requires: s.requires,
+            wants: s.wants,
+            after: s.after,
         }
     }
 }
@@ -66,6 +92,29 @@ struct CacheSnapshot {
     logs: Vec<LogEntry>,
 }
 
+/// What a system last reported, served by GetSystemStatus.
+#[derive(Clone, Debug, Default)]
+pub struct SystemSnapshot {
+    pub metrics: Option<MetricsRequest>,
+    pub last_seen: Option<DateTime<Utc>>,
+    pub gpu_metrics: Vec<GpuMetrics>,
+    /// Keyed by service name, agents send the services that changed and the ones removed
+    pub services: HashMap<String, SystemService>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CacheStats {
+    pub services: usize,
+    pub system_ids: usize,
+    pub systems: usize,
+    pub logs: usize,
+    pub config_changes: usize,
+    pub approx_memory_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub hit_ratio: f64,
+}
+
 #[derive(Debug, Clone)]
 struct SystemIdEntry {
     id: i32,
@@ -77,10 +126,17 @@ pub struct Cache {
     services: Arc<DashMap<String, SystemService>>,
     config_changes: Arc<RwLock<Vec<ConfigChange>>>,
     logs: Arc<RwLock<Vec<LogEntry>>>,
-    system_ids: DashMap<String, SystemIdEntry>,
+    system_ids: Arc<DashMap<String, SystemIdEntry>>,
+    systems: Arc<DashMap<i32, SystemSnapshot>>,
     system_id_ttl: Duration,
     max_logs: usize,
     max_config_changes: usize,
+    hits: Arc<AtomicU64>,
+    misses: Arc<AtomicU64>,
+    /// Redis behind the per-system views when several hubs share the load
+    shared: Option<SharedState>,
+    /// Feeds the task writing through to Redis
+    writes: Option<mpsc::Sender<SharedWrite>>,
 }
 
 impl Cache {
@@ -89,19 +145,73 @@ impl Cache {
             services: Arc::new(DashMap::new()),
             config_changes: Arc::new(RwLock::new(Vec::new())),
             logs: Arc::new(RwLock::new(Vec::new())),
-            system_ids: DashMap::new(),
+            system_ids: Arc::new(DashMap::new()),
+            systems: Arc::new(DashMap::new()),
             system_id_ttl: Duration::from_secs(300),
             max_logs,
             max_config_changes,
+            hits: Arc::new(AtomicU64::new(0)),
+            misses: Arc::new(AtomicU64::new(0)),
+            shared: None,
+            writes: None,
         }
     }
-    pub fn get_system_id(&self, key: &str) -> Option<i32> {
-        if let Some(e) = self.system_ids.get(key) {
-            if e.inserted.elapsed() <= self.system_id_ttl {
-                return Some(e.id);
+
+    /*
+     * with_shared
+     * Writes reports through to Redis and reads per-system views from it. The writes go through
+     * one task that runs them in the order they were made, so a slow write can't let an older
+     * report overwrite a newer one.
+     */
+    pub fn with_shared(mut self, shared: SharedState) -> Self {
+        let (tx, mut rx) = mpsc::channel::<SharedWrite>(SHARED_WRITE_QUEUE);
+        tokio::spawn(async move {
+            while let Some(write) = rx.recv().await {
+                if let Err(e) = write.await {
+                    warn!("[cache] Shared state write failed: {e}");
+                }
             }
+        });
+        self.shared = Some(shared);
+        self.writes = Some(tx);
+        self
+    }
+
+    /// Queues a Redis write behind the earlier ones, reports must not wait on it.
+    fn write_through<F, Fut>(&self, write: F)
+    where
+        F: FnOnce(SharedState) -> Fut,
+        Fut: Future<Output = Result<(), SharedError>> + Send + 'static,
+    {
+        let (Some(shared), Some(writes)) = (&self.shared, &self.writes) else {
+            return;
+        };
+        match writes.try_send(Box::pin(write(shared.clone()))) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                warn!("[cache] Shared state writer behind, dropped a write")
+            }
+            Err(TrySendError::Closed(_)) => {}
         }
-        None
+    }
+
+    fn record_lookup<T>(&self, found: Option<T>) -> Option<T> {
+        let counter = if found.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    pub fn get_system_id(&self, key: &str) -> Option<i32> {
+        let found = self
+            .system_ids
+            .get(key)
+            .filter(|e| e.inserted.elapsed() <= self.system_id_ttl)
+            .map(|e| e.id);
+        self.record_lookup(found)
     }
     pub fn evict_expired_system_ids(&self) {
         let ttl = self.system_id_ttl;
@@ -125,13 +235,93 @@ impl Cache {
     }
 
     pub fn get_service(&self, name: &str) -> Option<SystemService> {
-        self.services.get(name).map(|s| s.clone())
+        let found = self.services.get(name).map(|s| s.clone());
+        self.record_lookup(found)
     }
 
     pub fn list_services(&self) -> Vec<SystemService> {
         self.services.iter().map(|r| r.clone()).collect()
     }
 
+    pub fn record_metrics(&self, system_id: i32, metrics: &MetricsRequest) {
+        let now = Utc::now();
+        {
+            let mut entry = self.systems.entry(system_id).or_default();
+            entry.metrics = Some(metrics.clone());
+            entry.last_seen = Some(now);
+        }
+        let metrics = metrics.clone();
+        self.write_through(move |shared| async move {
+            shared.put_metrics(system_id, &metrics, now).await
+        });
+    }
+
+    pub fn record_gpu_metrics(&self, system_id: i32, metrics: &[GpuMetrics]) {
+        let now = Utc::now();
+        {
+            let mut entry = self.systems.entry(system_id).or_default();
+            entry.gpu_metrics = metrics.to_vec();
+            entry.last_seen = Some(now);
+        }
+        let metrics = metrics.to_vec();
+        self.write_through(move |shared| async move {
+            shared.put_gpu_metrics(system_id, metrics, now).await
+        });
+    }
+
+    pub fn record_services(&self, system_id: i32, services: &[SystemService]) {
+        {
+            let mut entry = self.systems.entry(system_id).or_default();
+            for svc in services {
+                entry.services.insert(svc.service_name.clone(), svc.clone());
+            }
+        }
+        // copied for Redis only when there is one
+        self.write_through(|shared| {
+            let services = services.to_vec();
+            async move { shared.put_services(system_id, &services).await }
+        });
+    }
+
+    pub fn remove_services(&self, system_id: i32, names: &[String]) {
+        if names.is_empty() {
+            return;
+        }
+        if let Some(mut entry) = self.systems.get_mut(&system_id) {
+            for name in names {
+                entry.services.remove(name);
+            }
+        }
+        let names = names.to_vec();
+        self.write_through(
+            move |shared| async move { shared.remove_services(system_id, &names).await },
+        );
+    }
+
+    pub fn system_snapshot(&self, system_id: i32) -> Option<SystemSnapshot> {
+        let found = self.systems.get(&system_id).map(|s| s.clone());
+        self.record_lookup(found)
+    }
+
+    /*
+     * load_system
+     * The system as all hubs see it: read from Redis when the state is shared, so a system that
+     * reports to another instance is still current here. Falls back to this hub's own view
+     * while Redis is unreachable.
+     */
+    pub async fn load_system(&self, system_id: i32) -> Option<SystemSnapshot> {
+        let Some(shared) = &self.shared else {
+            return self.system_snapshot(system_id);
+        };
+        match shared.system_snapshot(system_id).await {
+            Ok(found) => self.record_lookup(found),
+            Err(e) => {
+                warn!("[cache] Shared state read failed (system {system_id}): {e}");
+                self.system_snapshot(system_id)
+            }
+        }
+    }
+
     pub async fn record_config_change(
         &self,
         key: String,
@@ -211,4 +401,82 @@ impl Cache {
     pub async fn config_change_count(&self) -> usize {
         self.config_changes.read().await.len()
     }
+
+    pub async fn stats(&self) -> CacheStats {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let hit_ratio = if hits + misses == 0 {
+            0.0
+        } else {
+            hits as f64 / (hits + misses) as f64
+        };
+
+        // Rough estimate: struct sizes plus heap allocated strings
+        let services_bytes: usize = self
+            .services
+            .iter()
+            .map(|r| {
+                let s = r.value();
+                r.key().len()
+                    + std::mem::size_of::<SystemService>()
+                    + s.service_name.len()
+                    + s.description.len()
+                    + s.state.len()
+                    + s.cpu.len()
+                    + s.memory.len()
+            })
+            .sum();
+        let system_ids_bytes: usize = self
+            .system_ids
+            .iter()
+            .map(|r| r.key().len() + std::mem::size_of::<SystemIdEntry>())
+            .sum();
+        let systems_bytes: usize = self
+            .systems
+            .iter()
+            .map(|r| {
+                let s = r.value();
+                std::mem::size_of::<SystemSnapshot>()
+                    + s.metrics.as_ref().map_or(0, |m| m.encoded_len())
+                    + s.gpu_metrics.iter().map(|g| g.encoded_len()).sum::<usize>()
+                    + s.services
+                        .values()
+                        .map(|svc| svc.encoded_len() + svc.service_name.len())
+                        .sum::<usize>()
+            })
+            .sum();
+        let logs = self.logs.read().await;
+        let logs_bytes: usize = logs
+            .iter()
+            .map(|l| std::mem::size_of::<LogEntry>() + l.level.len() + l.message.len())
+            .sum();
+        let log_count = logs.len();
+        drop(logs);
+        let changes = self.config_changes.read().await;
+        let changes_bytes: usize = changes
+            .iter()
+            .map(|c| {
+                std::mem::size_of::<ConfigChange>()
+                    + c.key.len()
+                    + c.old_value.as_ref().map_or(0, |v| v.len())
+                    + c.new_value.len()
+            })
+            .sum();
+
+        CacheStats {
+            services: self.services.len(),
+            system_ids: self.system_ids.len(),
+            systems: self.systems.len(),
+            logs: log_count,
+            config_changes: changes.len(),
+            approx_memory_bytes: services_bytes
+                + system_ids_bytes
+                + systems_bytes
+                + logs_bytes
+                + changes_bytes,
+            hits,
+            misses,
+            hit_ratio,
+        }
+    }
 }
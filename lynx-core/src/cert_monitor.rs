@@ -0,0 +1,132 @@
+use crate::config::Secrets;
+use crate::leader::Leadership;
+use crate::notify::{NotificationService, NotificationServiceType};
+use crate::services::maintenance;
+use crate::tls::{inspect_certs, CertExpiry, CERT_EXPIRY_WARN_DAYS};
+use log::{error, info, warn};
+use sqlx::{PgPool, Row};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::{interval, Instant};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(6 * 3600);
+const NOTIFY_COOLDOWN: Duration = Duration::from_secs(24 * 3600);
+
+/// Latest expiry information for the hub's own certificates, shared with the HTTP API.
+#[derive(Clone, Default)]
+pub struct CertStatus {
+    certs: Arc<RwLock<Vec<CertExpiry>>>,
+}
+
+impl CertStatus {
+    pub async fn get(&self) -> Vec<CertExpiry> {
+        self.certs.read().await.clone()
+    }
+
+    async fn set(&self, certs: Vec<CertExpiry>) {
+        *self.certs.write().await = certs;
+    }
+}
+
+/// False during maintenance, the next check tries again.
+async fn notify_admins(
+    pool: &PgPool,
+    secrets: &Secrets,
+    message: &str,
+) -> Result<bool, sqlx::Error> {
+    if let Some(m) = maintenance::current(pool, None).await? {
+        info!("[tls] Maintenance until {}, not notifying admins", m.until);
+        return Ok(false);
+    }
+    let rows = sqlx::query(crate::queries::alert_queries::GET_ADMIN_NOTIFIERS)
+        .fetch_all(pool)
+        .await?;
+    for row in rows {
+        let url: String = row.get("value");
+        let url = match secrets.resolve(&url).await {
+            Ok(url) => url,
+            Err(e) => {
+                error!("[tls] Failed to resolve notifier secret: {e}");
+                continue;
+            }
+        };
+        match NotificationServiceType::from_url(&url) {
+            Ok(service) => {
+                if let Err(e) = service.send(message).await {
+                    error!("[tls] Failed to send expiry notification: {e}");
+                }
+            }
+            Err(e) => error!("[tls] Invalid notifier for expiry alert: {e}"),
+        }
+    }
+    Ok(true)
+}
+
+/*
+ * run_cert_monitor
+ * Checks the hub certificates at startup and then periodically. When one of them is about to
+ * expire every admin notifier is alerted, at most once a day, since an expired CA or server
+ * certificate silently breaks mTLS for the whole fleet.
+ */
+pub async fn run_cert_monitor(
+    certs_dir: PathBuf,
+    status: CertStatus,
+    pool: PgPool,
+    secrets: Secrets,
+    leadership: Leadership,
+) {
+    let mut tick = interval(CHECK_INTERVAL);
+    let mut last_notified: Option<Instant> = None;
+    loop {
+        tick.tick().await;
+        let certs = check_certs(&certs_dir);
+        let expiring: Vec<&CertExpiry> = certs
+            .iter()
+            .filter(|c| c.days_remaining < CERT_EXPIRY_WARN_DAYS)
+            .collect();
+
+        // every hub checks its own certificates, only the leader notifies
+        if !expiring.is_empty()
+            && leadership.is_leader()
+            && last_notified.is_none_or(|t| t.elapsed() >= NOTIFY_COOLDOWN)
+        {
+            let lines = expiring
+                .iter()
+                .map(|c| format!("{} ({}): {:.1} days", c.file, c.subject, c.days_remaining))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let message = format!(
+                "Alert: Hub certificate expiry\nDescription: certificates expire within {} days\n{}\nSeverity: critical",
+                CERT_EXPIRY_WARN_DAYS, lines
+            );
+            match notify_admins(&pool, &secrets, &message).await {
+                Ok(true) => last_notified = Some(Instant::now()),
+                Ok(false) => {}
+                Err(e) => error!("[tls] Failed to load admin notifiers: {e}"),
+            }
+        }
+        status.set(certs).await;
+    }
+}
+
+fn check_certs(certs_dir: &Path) -> Vec<CertExpiry> {
+    let certs = inspect_certs(certs_dir);
+    for c in &certs {
+        if c.days_remaining < 0.0 {
+            error!("[tls] {} ({}) expired {}", c.file, c.subject, c.not_after);
+        } else if c.days_remaining < CERT_EXPIRY_WARN_DAYS {
+            warn!(
+                "[tls] {} ({}) expires in {:.1} days",
+                c.file, c.subject, c.days_remaining
+            );
+        } else {
+            info!(
+                "[tls] {} ({}) valid for {:.0} days",
+                c.file, c.subject, c.days_remaining
+            );
+        }
+    }
+    certs
+}
@@ -1,23 +1,674 @@
-use env_logger::Env;
+use crate::auth_limit::AuthLimitOptions;
+use crate::discovery::{self, DiscoveryOptions};
+use crate::events::EventsConfig;
+use crate::notify::flapping::FlapOptions;
+use crate::notify::worker::EvaluationOptions;
+use crate::partitions::PartitionOptions;
+use crate::retention::RetentionOptions;
+use crate::services::decommission::DecommissionOptions;
+use crate::services::ingest::{IngestOptions, OverflowPolicy};
+use crate::sinks::influx::InfluxConfig;
+use async_trait::async_trait;
 use log::info;
+use openssl::hash::{hash, MessageDigest};
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
 
 #[derive(Clone, Debug)]
-pub struct Config {
+pub struct DbConfig {
     pub database_url: String,
-    pub retention_days: i64,
+    pub read_database_url: Option<String>,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout_secs: u64,
+    pub idle_timeout_secs: u64,
+    /// Server side statement timeout, 0 disables it
+    pub statement_timeout_ms: u64,
+    /// Statements slower than this are logged as warnings, 0 disables it
+    pub slow_query_ms: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// Per-table retention, RETENTION_DAYS and RETENTION_<TABLE>_DAYS
+    pub retention: RetentionOptions,
+    pub partitions: PartitionOptions,
+    /// Where the gRPC server listens, GRPC_ADDR / GRPC_PORT or GRPC_SOCKET
+    pub grpc_bind: GrpcBind,
+    /// Operator HTTP API, HTTP_ADDR; plain HTTP, so loopback unless set
+    pub http_addr: SocketAddr,
+    pub db: DbConfig,
+    /// Validity of certificates issued to enrolling agents
+    pub enroll_cert_days: u32,
+    /// Require agents to present the client certificate pinned to their system
+    pub pin_client_certs: bool,
+    /// Resolves `secret:` references in config values and notifier URLs
+    pub secrets: Secrets,
+    pub tls: TlsOptions,
+    pub auth_limit: AuthLimitOptions,
+    pub agent_release: AgentRelease,
+    /// Bearer token for the operator endpoints that change systems, they are refused when unset
+    pub admin_token: Option<String>,
+    /// Base url of the portal, notifications link to the alerting system when set
+    pub portal_url: Option<String>,
+    pub flapping: FlapOptions,
+    pub decommission: DecommissionOptions,
+    /// Copy every MetricsRequest to InfluxDB v2 when set
+    pub influx: Option<InfluxConfig>,
+    /// Publish alerts (and optionally metric samples) to NATS or a Kafka REST proxy when set
+    pub events: Option<EventsConfig>,
+    /// Share per-system state and alert cooldowns with other hubs through Redis when set
+    pub redis_url: Option<String>,
+    /// Serve grpc.reflection so grpcurl can list and describe the API without the proto files
+    pub grpc_reflection: bool,
+    /// How long alert rules stay cached per system, changes announced by NOTIFY drop them sooner
+    pub rule_cache_ttl: Duration,
+    /// How long a graceful shutdown may take before the hub exits anyway
+    pub shutdown_timeout: Duration,
+    /// Ingest queue and the writer tasks draining it
+    pub ingest: IngestOptions,
+    /// Alert evaluation queue and the notification workers draining it
+    pub evaluation: EvaluationOptions,
+    /// /readyz fails once the ingest queue is fuller than this, in percent of its capacity
+    pub ready_queue_percent: u8,
+    /// `--insecure`: plaintext gRPC on localhost without mTLS, for local development only
+    pub insecure: bool,
+    /// Announce the hub to agents on the LAN over mDNS, MDNS_ADVERTISE
+    pub discovery: Option<DiscoveryOptions>,
+}
+
+pub const DEFAULT_GRPC_PORT: u16 = 50051;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GrpcBind {
+    Tcp(SocketAddr),
+    /// Unix domain socket, e.g. for a reverse proxy on the same host
+    Unix(PathBuf),
+}
+
+impl fmt::Display for GrpcBind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrpcBind::Tcp(addr) => write!(f, "{addr}"),
+            GrpcBind::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl GrpcBind {
+    /*
+     * parse
+     * GRPC_SOCKET wins over GRPC_ADDR, GRPC_PORT only changes the port of the default address.
+     * The default is 0.0.0.0:50051, or loopback for `--insecure`, which refuses to listen on
+     * anything but loopback since it turns mTLS off.
+     */
+    pub fn parse(
+        addr: Option<&str>,
+        port: Option<&str>,
+        socket: Option<&str>,
+        insecure: bool,
+    ) -> Result<Self, String> {
+        if let Some(path) = socket.filter(|s| !s.is_empty()) {
+            return Ok(GrpcBind::Unix(PathBuf::from(path)));
+        }
+        let addr = match addr.filter(|a| !a.is_empty()) {
+            Some(addr) => addr
+                .parse::<SocketAddr>()
+                .map_err(|e| format!("Invalid GRPC_ADDR {addr:?}: {e}"))?,
+            None => {
+                let port = match port.filter(|p| !p.is_empty()) {
+                    Some(port) => port
+                        .parse::<u16>()
+                        .map_err(|e| format!("Invalid GRPC_PORT {port:?}: {e}"))?,
+                    None => DEFAULT_GRPC_PORT,
+                };
+                let ip = if insecure {
+                    Ipv4Addr::LOCALHOST
+                } else {
+                    Ipv4Addr::UNSPECIFIED
+                };
+                SocketAddr::from((ip, port))
+            }
+        };
+        if insecure && !addr.ip().is_loopback() {
+            return Err(format!(
+                "--insecure only listens on loopback, GRPC_ADDR {addr} is not"
+            ));
+        }
+        Ok(GrpcBind::Tcp(addr))
+    }
+}
+
+/*
+ * Secrets
+ * Any config value or notifier URL can reference a secret instead of holding it in plaintext:
+ *   DATABASE_URL=secret:lynx/database_url            whole value
+ *   smtp://lynx:${secret:lynx/smtp#password}@mail... inline placeholder
+ * The part after `#` selects a field when the secret is a JSON object / Vault KV entry.
+ * SECRETS_BACKEND picks the provider: env (default), env-file, vault or aws.
+ */
+#[derive(Error, Debug)]
+pub enum SecretsError {
+    #[error("Secret not found: {0}")]
+    NotFound(String),
+    #[error("Secrets backend misconfigured: {0}")]
+    Config(String),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("HTTP request error: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Signing error: {0}")]
+    Signing(#[from] openssl::error::ErrorStack),
+}
+
+#[async_trait]
+pub trait SecretsBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String, SecretsError>;
+}
+
+#[derive(Clone)]
+pub struct Secrets {
+    backend: Arc<dyn SecretsBackend>,
+}
+
+impl std::fmt::Debug for Secrets {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Secrets")
+            .field("backend", &self.backend.name())
+            .finish()
+    }
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self::new(EnvBackend::default())
+    }
+}
+
+impl Secrets {
+    pub fn new(backend: impl SecretsBackend + 'static) -> Self {
+        Self {
+            backend: Arc::new(backend),
+        }
+    }
+
+    pub fn from_env() -> Result<Self, SecretsError> {
+        let backend = std::env::var("SECRETS_BACKEND").unwrap_or_else(|_| "env".to_string());
+        let secrets = match backend.as_str() {
+            "env" => Self::new(EnvBackend::default()),
+            "env-file" => {
+                let path = std::env::var("SECRETS_FILE").map_err(|_| {
+                    SecretsError::Config("SECRETS_FILE is required for env-file".to_string())
+                })?;
+                Self::new(EnvBackend::from_file(&path)?)
+            }
+            "vault" => Self::new(VaultBackend::from_env()?),
+            "aws" => Self::new(AwsSecretsBackend::from_env()?),
+            other => {
+                return Err(SecretsError::Config(format!(
+                    "Unknown SECRETS_BACKEND {other}"
+                )))
+            }
+        };
+        info!("[hub] Using {} secrets backend", secrets.backend.name());
+        Ok(secrets)
+    }
+
+    async fn lookup(&self, reference: &str) -> Result<String, SecretsError> {
+        let (name, field) = match reference.split_once('#') {
+            Some((name, field)) => (name, Some(field)),
+            None => (reference, None),
+        };
+        self.backend.get(name, field).await
+    }
+
+    /// Returns `value` with every secret reference replaced; plain values pass through.
+    pub async fn resolve(&self, value: &str) -> Result<String, SecretsError> {
+        if let Some(reference) = value.strip_prefix("secret:") {
+            return self.lookup(reference).await;
+        }
+
+        let mut out = String::with_capacity(value.len());
+        let mut rest = value;
+        while let Some(start) = rest.find("${secret:") {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| SecretsError::Config(format!("Unterminated secret in {rest}")))?;
+            out.push_str(&rest[..start]);
+            out.push_str(&self.lookup(&rest[start + 9..start + end]).await?);
+            rest = &rest[start + end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+}
+
+/// Picks `field` out of a JSON secret, or returns the raw secret when no field was asked for.
+fn select_field(name: &str, raw: String, field: Option<&str>) -> Result<String, SecretsError> {
+    let Some(field) = field else {
+        return Ok(raw);
+    };
+    let json: Value = serde_json::from_str(&raw)
+        .map_err(|_| SecretsError::Config(format!("Secret {name} is not a JSON object")))?;
+    match json.get(field) {
+        Some(Value::String(s)) => Ok(s.clone()),
+        Some(v) => Ok(v.to_string()),
+        None => Err(SecretsError::NotFound(format!("{name}#{field}"))),
+    }
+}
+
+/// Process environment, optionally overlaid with a dotenv style file (e.g. a mounted
+/// Docker/Kubernetes secret) so values don't have to live in the compose file.
+#[derive(Default)]
+pub struct EnvBackend {
+    values: HashMap<String, String>,
+}
+
+impl EnvBackend {
+    pub fn from_file(path: &str) -> Result<Self, SecretsError> {
+        let values = std::fs::read_to_string(path)?
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty() && !l.starts_with('#'))
+            .filter_map(|l| l.split_once('='))
+            .map(|(k, v)| {
+                let v = v.trim().trim_matches('"');
+                (k.trim().to_string(), v.to_string())
+            })
+            .collect();
+        Ok(Self { values })
+    }
+}
+
+#[async_trait]
+impl SecretsBackend for EnvBackend {
+    fn name(&self) -> &'static str {
+        if self.values.is_empty() {
+            "env"
+        } else {
+            "env-file"
+        }
+    }
+
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String, SecretsError> {
+        let raw = match self.values.get(name) {
+            Some(v) => v.clone(),
+            None => std::env::var(name).map_err(|_| SecretsError::NotFound(name.to_string()))?,
+        };
+        select_field(name, raw, field)
+    }
+}
+
+/// HashiCorp Vault KV v2, authenticated with VAULT_TOKEN.
+pub struct VaultBackend {
+    client: reqwest::Client,
+    addr: String,
+    mount: String,
+    token: String,
+}
+
+impl VaultBackend {
+    pub fn from_env() -> Result<Self, SecretsError> {
+        let var = |key: &str| {
+            std::env::var(key).map_err(|_| SecretsError::Config(format!("{key} is required")))
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            addr: var("VAULT_ADDR")?.trim_end_matches('/').to_string(),
+            mount: std::env::var("VAULT_MOUNT").unwrap_or_else(|_| "secret".to_string()),
+            token: var("VAULT_TOKEN")?,
+        })
+    }
+}
+
+#[async_trait]
+impl SecretsBackend for VaultBackend {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String, SecretsError> {
+        let url = format!("{}/v1/{}/data/{}", self.addr, self.mount, name);
+        let response = self
+            .client
+            .get(&url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(SecretsError::NotFound(name.to_string()));
+        }
+        let body: Value = response.error_for_status()?.json().await?;
+        let data = &body["data"]["data"];
+        let field = field.unwrap_or("value");
+        match data.get(field) {
+            Some(Value::String(s)) => Ok(s.clone()),
+            Some(v) => Ok(v.to_string()),
+            None => Err(SecretsError::NotFound(format!("{name}#{field}"))),
+        }
+    }
+}
+
+/// AWS Secrets Manager, using the standard AWS_* credential variables and SigV4 signing.
+pub struct AwsSecretsBackend {
+    client: reqwest::Client,
+    region: String,
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Result<Vec<u8>, SecretsError> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(data.as_bytes())?;
+    Ok(signer.sign_to_vec()?)
+}
+
+impl AwsSecretsBackend {
+    pub fn from_env() -> Result<Self, SecretsError> {
+        let var = |key: &str| {
+            std::env::var(key).map_err(|_| SecretsError::Config(format!("{key} is required")))
+        };
+        Ok(Self {
+            client: reqwest::Client::new(),
+            region: std::env::var("AWS_REGION")
+                .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+                .map_err(|_| SecretsError::Config("AWS_REGION is required".to_string()))?,
+            access_key_id: var("AWS_ACCESS_KEY_ID")?,
+            secret_access_key: var("AWS_SECRET_ACCESS_KEY")?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    /*
+     * authorization
+     * Builds the SigV4 Authorization header for a GetSecretValue call. Headers are signed in
+     * sorted order as SigV4 requires.
+     */
+    fn authorization(
+        &self,
+        host: &str,
+        amz_date: &str,
+        body: &str,
+    ) -> Result<String, SecretsError> {
+        let date = &amz_date[..8];
+        let mut headers = vec![
+            ("content-type", "application/x-amz-json-1.1".to_string()),
+            ("host", host.to_string()),
+            ("x-amz-date", amz_date.to_string()),
+            ("x-amz-target", "secretsmanager.GetSecretValue".to_string()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        headers.sort();
+        let canonical_headers: String = headers.iter().map(|(k, v)| format!("{k}:{v}\n")).collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+            hex(&hash(MessageDigest::sha256(), body.as_bytes())?)
+        );
+
+        let scope = format!("{date}/{}/secretsmanager/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex(&hash(
+                MessageDigest::sha256(),
+                canonical_request.as_bytes()
+            )?)
+        );
+
+        let k_date = hmac_sha256(format!("AWS4{}", self.secret_access_key).as_bytes(), date)?;
+        let k_region = hmac_sha256(&k_date, &self.region)?;
+        let k_service = hmac_sha256(&k_region, "secretsmanager")?;
+        let k_signing = hmac_sha256(&k_service, "aws4_request")?;
+        let signature = hex(&hmac_sha256(&k_signing, &string_to_sign)?);
+
+        Ok(format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key_id
+        ))
+    }
+}
+
+#[async_trait]
+impl SecretsBackend for AwsSecretsBackend {
+    fn name(&self) -> &'static str {
+        "aws"
+    }
+
+    async fn get(&self, name: &str, field: Option<&str>) -> Result<String, SecretsError> {
+        let host = format!("secretsmanager.{}.amazonaws.com", self.region);
+        let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let body = serde_json::json!({ "SecretId": name }).to_string();
+        let authorization = self.authorization(&host, &amz_date, &body)?;
+
+        let mut request = self
+            .client
+            .post(format!("https://{host}/"))
+            .header("Content-Type", "application/x-amz-json-1.1")
+            .header("X-Amz-Date", &amz_date)
+            .header("X-Amz-Target", "secretsmanager.GetSecretValue")
+            .header("Authorization", authorization)
+            .body(body);
+        if let Some(token) = &self.session_token {
+            request = request.header("X-Amz-Security-Token", token);
+        }
+
+        let response = request.send().await?;
+        if response.status() == reqwest::StatusCode::BAD_REQUEST {
+            // Secrets Manager reports ResourceNotFoundException as a 400
+            return Err(SecretsError::NotFound(name.to_string()));
+        }
+        let body: Value = response.error_for_status()?.json().await?;
+        let raw = body["SecretString"]
+            .as_str()
+            .ok_or_else(|| SecretsError::NotFound(name.to_string()))?
+            .to_string();
+        select_field(name, raw, field)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsOptions {
+    /// Lowest accepted protocol version, "1.2" or "1.3"
+    pub min_version: String,
+    /// rustls suite names (e.g. TLS13_AES_256_GCM_SHA384), empty keeps the defaults
+    pub cipher_suites: Vec<String>,
+    /// PEM CRLs issued by our CA, checked against agent client certificates
+    pub crl_files: Vec<PathBuf>,
+    /// Ask the OCSP responder listed in agent certificates for their status
+    pub ocsp: bool,
+}
+
+/// Where install scripts download the agent from and how they are pinned and signed.
+#[derive(Clone, Debug)]
+pub struct AgentRelease {
+    pub bin_url: Option<String>,
+    /// Lowercase hex sha256 of the binary at `bin_url`, checked before it is installed
+    pub bin_sha256: Option<String>,
+    /// Ed25519 key signing install scripts, see [`crate::signing::ReleaseSigner`]
+    pub signing_key: Option<PathBuf>,
+    /// Hub address written into generated agent configs
+    pub server_url: String,
+}
+
+/// Splits a comma separated env var, empty when unset.
+fn env_list(key: &str) -> Vec<String> {
+    std::env::var(key)
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Reads an optional env var, falling back to `default` when unset or unparsable.
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<T>().ok())
+        .unwrap_or(default)
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+    pub async fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let secrets = Secrets::from_env()?;
         let database_url = std::env::var("DATABASE_URL")
             .map_err(|_| "DATABASE_URL environment variable is not set")?;
-        let retention_days = std::env::var("RETENTION_DAYS")
-            .unwrap_or_else(|_| "30".to_string())
-            .parse::<i64>()
-            .unwrap_or(30);
-        Ok(Self {
+        let database_url = secrets.resolve(&database_url).await?;
+        let read_database_url = match std::env::var("DATABASE_READ_URL") {
+            Ok(v) if !v.is_empty() => Some(secrets.resolve(&v).await?),
+            _ => None,
+        };
+        let redis_url = match std::env::var("REDIS_URL") {
+            Ok(v) if !v.is_empty() => Some(secrets.resolve(&v).await?),
+            _ => None,
+        };
+        let insecure = std::env::args().any(|arg| arg == "--insecure");
+        let grpc_bind = GrpcBind::parse(
+            std::env::var("GRPC_ADDR").ok().as_deref(),
+            std::env::var("GRPC_PORT").ok().as_deref(),
+            std::env::var("GRPC_SOCKET").ok().as_deref(),
+            insecure,
+        )?;
+        let http_addr = std::env::var("HTTP_ADDR")
+            .unwrap_or_else(|_| "127.0.0.1:50052".to_string())
+            .parse::<SocketAddr>()
+            .map_err(|e| format!("Invalid HTTP_ADDR: {e}"))?;
+        let db = DbConfig {
             database_url,
-            retention_days,
+            read_database_url,
+            max_connections: env_or("DB_MAX_CONNECTIONS", 20),
+            min_connections: env_or("DB_MIN_CONNECTIONS", 5),
+            acquire_timeout_secs: env_or("DB_ACQUIRE_TIMEOUT_SECS", 5),
+            idle_timeout_secs: env_or("DB_IDLE_TIMEOUT_SECS", 300),
+            statement_timeout_ms: env_or("DB_STATEMENT_TIMEOUT_MS", 0),
+            slow_query_ms: env_or("DB_SLOW_QUERY_MS", 1000),
+        };
+        let influx = match std::env::var("INFLUX_URL") {
+            Ok(url) if !url.is_empty() => {
+                let var = |key: &str| {
+                    std::env::var(key).map_err(|_| format!("{key} is required with INFLUX_URL"))
+                };
+                Some(InfluxConfig {
+                    url,
+                    org: var("INFLUX_ORG")?,
+                    bucket: var("INFLUX_BUCKET")?,
+                    token: secrets.resolve(&var("INFLUX_TOKEN")?).await?,
+                })
+            }
+            _ => None,
+        };
+        let events = match std::env::var("EVENTS_URL") {
+            Ok(url) if !url.is_empty() => Some(EventsConfig {
+                url: secrets.resolve(&url).await?,
+                alerts_topic: env_or("EVENTS_ALERTS_TOPIC", "lynx.alerts".to_string()),
+                metrics_topic: std::env::var("EVENTS_METRICS_TOPIC")
+                    .ok()
+                    .filter(|t| !t.is_empty()),
+            }),
+            _ => None,
+        };
+        let agent_server_url = env_or("AGENT_SERVER_URL", "https://localhost:50051".to_string());
+        let discovery = env_or("MDNS_ADVERTISE", false).then(|| DiscoveryOptions {
+            instance: env_or("MDNS_INSTANCE", "lynx-hub".to_string()),
+            tls_name: std::env::var("MDNS_TLS_NAME")
+                .ok()
+                .filter(|n| !n.is_empty())
+                .or_else(|| discovery::tls_name_from_url(&agent_server_url))
+                .unwrap_or_else(|| "localhost".to_string()),
+        });
+        let admin_token = match std::env::var("ADMIN_TOKEN") {
+            Ok(v) if !v.is_empty() => Some(secrets.resolve(&v).await?),
+            _ => None,
+        };
+        Ok(Self {
+            retention: RetentionOptions::from_lookup(|key| std::env::var(key).ok()),
+            partitions: PartitionOptions {
+                interval_days: env_or("PARTITION_INTERVAL_DAYS", 7).max(1),
+                migrate: env_or("PARTITION_MIGRATE", false),
+            },
+            grpc_bind,
+            http_addr,
+            db,
+            enroll_cert_days: env_or("ENROLL_CERT_DAYS", 365),
+            pin_client_certs: env_or("PIN_CLIENT_CERTS", false),
+            secrets,
+            tls: TlsOptions {
+                min_version: env_or("TLS_MIN_VERSION", "1.2".to_string()),
+                cipher_suites: env_list("TLS_CIPHER_SUITES"),
+                crl_files: env_list("TLS_CRL_FILES")
+                    .into_iter()
+                    .map(PathBuf::from)
+                    .collect(),
+                ocsp: env_or("TLS_OCSP", false),
+            },
+            auth_limit: AuthLimitOptions {
+                per_ip_per_min: env_or("AUTH_RATE_PER_IP", 600),
+                per_key_per_min: env_or("AUTH_RATE_PER_KEY", 300),
+                max_failures: env_or("AUTH_MAX_FAILURES", 10),
+                lockout_secs: env_or("AUTH_LOCKOUT_SECS", 900),
+            },
+            agent_release: AgentRelease {
+                bin_url: std::env::var("AGENT_BIN_URL").ok(),
+                bin_sha256: std::env::var("AGENT_BIN_SHA256")
+                    .ok()
+                    .map(|v| v.trim().to_lowercase()),
+                signing_key: std::env::var("AGENT_SIGNING_KEY").ok().map(PathBuf::from),
+                server_url: agent_server_url,
+            },
+            admin_token,
+            portal_url: std::env::var("PORTAL_URL")
+                .ok()
+                .filter(|u| !u.trim().is_empty()),
+            flapping: FlapOptions {
+                max_transitions: env_or("FLAP_MAX_TRANSITIONS", 6),
+                window: Duration::from_secs(env_or("FLAP_WINDOW_MINS", 30) * 60),
+            },
+            decommission: DecommissionOptions {
+                grace: Duration::from_secs(env_or("DECOMMISSION_GRACE_SECS", 300)),
+                archive_dir: PathBuf::from(env_or("ARCHIVE_DIR", "archive".to_string())),
+            },
+            influx,
+            events,
+            redis_url,
+            grpc_reflection: env_or("GRPC_REFLECTION", true),
+            rule_cache_ttl: Duration::from_secs(env_or("RULE_CACHE_SECS", 60)),
+            shutdown_timeout: Duration::from_secs(env_or("SHUTDOWN_TIMEOUT_SECS", 25)),
+            ingest: IngestOptions {
+                queue_size: env_or("INGEST_QUEUE_SIZE", 10_000).max(1),
+                workers: env_or("INGEST_WORKERS", 1).max(1),
+                wait: Duration::from_millis(env_or("INGEST_WAIT_MS", 1000)),
+                overflow: env_or("INGEST_OVERFLOW", OverflowPolicy::Reject),
+            },
+            evaluation: EvaluationOptions {
+                queue_size: env_or("ALERT_QUEUE_SIZE", 10_000).max(1),
+                workers: env_or("ALERT_WORKERS", 1).max(1),
+            },
+            ready_queue_percent: env_or("READY_QUEUE_PERCENT", 90).min(100),
+            insecure,
+            discovery,
         })
     }
 }
@@ -25,13 +676,3 @@ impl Config {
 pub fn load_env() {
     dotenv::dotenv().ok();
 }
-
-pub fn init_logging() {
-    let env = Env::default()
-        .filter("MY_LOG_LEVEL")
-        .write_style("MY_LOG_STYLE");
-    env_logger::Builder::from_env(env)
-        .format_timestamp_secs()
-        .init();
-    info!("[hub] Logging initialized");
-}
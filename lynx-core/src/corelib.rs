@@ -1,9 +1,32 @@
+pub mod auth_limit;
+pub mod backup;
 pub mod cache;
+pub mod cert_monitor;
 pub mod config;
+pub mod counters;
 pub mod db;
+pub mod discovery;
+pub mod events;
+pub mod health;
+pub mod http;
+pub mod leader;
+pub mod listener;
+pub mod logging;
 pub mod proto;
 
 pub mod notify;
+pub mod partitions;
+pub mod prometheus;
 mod queries;
+pub mod retention;
+pub mod revocation;
 pub mod services;
+pub mod shared;
+pub mod shutdown;
+pub mod signing;
+pub mod simulator;
+pub mod sinks;
+pub mod snmp;
+pub mod telemetry;
 pub mod tls;
+pub mod units;
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+/*
+ * CounterRates
+ * Turns monotonically increasing counters scraped by the hub's pollers (interface octets, CPU
+ * seconds) into per-second rates, the shape agents report. The first reading of a counter and a
+ * counter that went backwards (wrap or device reboot) produce no rate.
+ */
+#[derive(Debug, Default)]
+pub struct CounterRates {
+    last: HashMap<String, (f64, Instant)>,
+}
+
+impl CounterRates {
+    pub fn rate(&mut self, name: &str, value: f64, now: Instant) -> Option<f64> {
+        let (previous, at) = self.last.insert(name.to_string(), (value, now))?;
+        let elapsed = now.duration_since(at).as_secs_f64();
+        if value < previous || elapsed <= 0.0 {
+            return None;
+        }
+        Some((value - previous) / elapsed)
+    }
+
+    /// Increase since the previous reading, for counters compared against each other.
+    pub fn delta(&mut self, name: &str, value: f64, now: Instant) -> Option<f64> {
+        let (previous, _) = self.last.insert(name.to_string(), (value, now))?;
+        (value >= previous).then_some(value - previous)
+    }
+}
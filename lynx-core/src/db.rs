@@ -1,11 +1,43 @@
-use sqlx::postgres::PgPoolOptions;
+use crate::config::DbConfig;
+use log::LevelFilter;
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::ConnectOptions;
+use std::str::FromStr;
+use std::time::Duration;
 
-pub async fn setup_db(database_url: &str) -> Result<sqlx::PgPool, sqlx::Error> {
+fn connect_options(url: &str, cfg: &DbConfig) -> Result<PgConnectOptions, sqlx::Error> {
+    let mut opts = PgConnectOptions::from_str(url)?;
+    if cfg.statement_timeout_ms > 0 {
+        opts = opts.options([("statement_timeout", cfg.statement_timeout_ms.to_string())]);
+    }
+    if cfg.slow_query_ms > 0 {
+        opts =
+            opts.log_slow_statements(LevelFilter::Warn, Duration::from_millis(cfg.slow_query_ms));
+    }
+    Ok(opts)
+}
+
+async fn connect(url: &str, cfg: &DbConfig) -> Result<sqlx::PgPool, sqlx::Error> {
     PgPoolOptions::new()
-        .max_connections(20)
-        .min_connections(5)
-        .acquire_timeout(std::time::Duration::from_secs(5))
-        .idle_timeout(std::time::Duration::from_secs(300))
-        .connect(database_url)
+        .max_connections(cfg.max_connections)
+        .min_connections(cfg.min_connections.min(cfg.max_connections))
+        .acquire_timeout(Duration::from_secs(cfg.acquire_timeout_secs))
+        .idle_timeout(Duration::from_secs(cfg.idle_timeout_secs))
+        .connect_with(connect_options(url, cfg)?)
         .await
 }
+
+pub async fn setup_db(cfg: &DbConfig) -> Result<sqlx::PgPool, sqlx::Error> {
+    connect(&cfg.database_url, cfg).await
+}
+
+/// Pool for read-only query endpoints. Falls back to the primary pool when no replica is set.
+pub async fn setup_read_db(
+    cfg: &DbConfig,
+    primary: &sqlx::PgPool,
+) -> Result<sqlx::PgPool, sqlx::Error> {
+    match &cfg.read_database_url {
+        Some(url) => connect(url, cfg).await,
+        None => Ok(primary.clone()),
+    }
+}
@@ -0,0 +1,80 @@
+use crate::config::GrpcBind;
+use log::{info, warn};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use thiserror::Error;
+
+/*
+ * LAN discovery
+ * With MDNS_ADVERTISE the hub announces its gRPC port as a `_lynx-hub._tcp` DNS-SD service on
+ * every interface, so agents on the same network can leave server_url out of their config.
+ * Agents connect to the address they resolved and check the hub certificate against the
+ * `tls_name` TXT entry, so the name only has to be in the certificate, not in any DNS.
+ */
+
+pub const SERVICE_TYPE: &str = "_lynx-hub._tcp.local.";
+
+#[derive(Error, Debug)]
+pub enum DiscoveryError {
+    #[error("mDNS error: {0}")]
+    Mdns(#[from] mdns_sd::Error),
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscoveryOptions {
+    /// Service instance and mDNS host name, MDNS_INSTANCE
+    pub instance: String,
+    /// Name in the hub certificate, MDNS_TLS_NAME, by default the host of AGENT_SERVER_URL
+    pub tls_name: String,
+}
+
+/// The port to announce, None for a hub behind a unix socket that agents can't reach directly.
+pub fn advertised_port(bind: &GrpcBind) -> Option<u16> {
+    match bind {
+        GrpcBind::Tcp(addr) if !addr.ip().is_loopback() => Some(addr.port()),
+        _ => None,
+    }
+}
+
+/// The host of a server url, `hub.lan` for `https://hub.lan:50051`.
+pub fn tls_name_from_url(server_url: &str) -> Option<String> {
+    url::Url::parse(server_url)
+        .ok()?
+        .host_str()
+        .filter(|host| !host.is_empty())
+        .map(str::to_string)
+}
+
+/*
+ * advertise
+ * Registers the service and returns the daemon answering queries for it, which has to be kept
+ * until shutdown. Hubs that agents can't reach over the network announce nothing.
+ */
+pub fn advertise(
+    options: &DiscoveryOptions,
+    bind: &GrpcBind,
+) -> Result<Option<ServiceDaemon>, DiscoveryError> {
+    let Some(port) = advertised_port(bind) else {
+        warn!("[discovery] gRPC listens on {bind}, which agents can't reach, not advertising");
+        return Ok(None);
+    };
+    let daemon = ServiceDaemon::new()?;
+    let properties = [
+        ("tls_name", options.tls_name.as_str()),
+        ("version", env!("CARGO_PKG_VERSION")),
+    ];
+    let service = ServiceInfo::new(
+        SERVICE_TYPE,
+        &options.instance,
+        &format!("{}.local.", options.instance),
+        "",
+        port,
+        &properties[..],
+    )?
+    .enable_addr_auto();
+    daemon.register(service)?;
+    info!(
+        "[discovery] Advertising {}.{SERVICE_TYPE} on port {port} as {}",
+        options.instance, options.tls_name
+    );
+    Ok(Some(daemon))
+}
@@ -0,0 +1,218 @@
+use crate::notify::Rule;
+use crate::services::ingest::MetricIngestItem;
+use crate::sinks::{MetricSink, SinkError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::error;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+pub mod kafka_rest;
+pub mod nats;
+
+pub use kafka_rest::KafkaRestPublisher;
+pub use nats::NatsPublisher;
+
+/*
+ * Event publishing
+ * Pushes alert events and, when a metrics topic is configured, every stored metric sample to a
+ * Kafka topic (through a Kafka REST proxy) or a NATS subject, so downstream data platforms don't
+ * have to poll Postgres. Like sinks, publishing is best effort and never blocks ingest.
+ */
+
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum EventError {
+    #[error("HTTP request error: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Broker rejected the publish ({status}): {body}")]
+    Rejected { status: u16, body: String },
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Protocol error: {0}")]
+    Protocol(String),
+    #[error("Invalid events URL: {0}")]
+    Config(String),
+}
+
+/// A message for the broker, `key` picks the Kafka partition and is ignored by NATS.
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub key: String,
+    pub payload: Value,
+}
+
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn publish(&self, topic: &str, events: &[Event]) -> Result<(), EventError>;
+}
+
+#[derive(Clone, Debug)]
+pub struct EventsConfig {
+    /// nats://[user:pass@]host:4222 or the http(s) URL of a Kafka REST proxy
+    pub url: String,
+    pub alerts_topic: String,
+    /// Raw metric samples are only published when set
+    pub metrics_topic: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Events {
+    publisher: Arc<dyn EventPublisher>,
+    alerts_topic: String,
+    metrics_topic: Option<String>,
+}
+
+impl Events {
+    pub fn new(config: &EventsConfig) -> Result<Self, EventError> {
+        let publisher: Arc<dyn EventPublisher> = if config.url.starts_with("nats://") {
+            Arc::new(NatsPublisher::new(&config.url)?)
+        } else if config.url.starts_with("http://") || config.url.starts_with("https://") {
+            Arc::new(KafkaRestPublisher::new(&config.url)?)
+        } else {
+            return Err(EventError::Config(
+                "expected nats:// or the http(s) URL of a Kafka REST proxy".to_string(),
+            ));
+        };
+        Ok(Self {
+            publisher,
+            alerts_topic: config.alerts_topic.clone(),
+            metrics_topic: config.metrics_topic.clone(),
+        })
+    }
+
+    pub fn publisher_name(&self) -> &'static str {
+        self.publisher.name()
+    }
+
+    /// Publishes a triggered rule without waiting for the broker.
+    pub fn alert(&self, rule: &Rule, system_id: i32) {
+        let events = vec![alert_event(rule, system_id, Utc::now())];
+        let publisher = self.publisher.clone();
+        let topic = self.alerts_topic.clone();
+        tokio::spawn(async move {
+            if let Err(e) = publish_with_timeout(publisher.as_ref(), &topic, &events).await {
+                error!(
+                    "[events] {} dropped alert event for {topic}: {e}",
+                    publisher.name()
+                );
+            }
+        });
+    }
+
+    /// A metric sink publishing samples to the metrics topic, None when it isn't configured.
+    pub fn metrics_sink(&self) -> Option<EventSink> {
+        self.metrics_topic.as_ref().map(|topic| EventSink {
+            publisher: self.publisher.clone(),
+            topic: topic.clone(),
+        })
+    }
+}
+
+async fn publish_with_timeout(
+    publisher: &dyn EventPublisher,
+    topic: &str,
+    events: &[Event],
+) -> Result<(), EventError> {
+    match tokio::time::timeout(PUBLISH_TIMEOUT, publisher.publish(topic, events)).await {
+        Ok(result) => result,
+        Err(_) => Err(EventError::Protocol(format!(
+            "timed out after {PUBLISH_TIMEOUT:?}"
+        ))),
+    }
+}
+
+pub fn alert_event(rule: &Rule, system_id: i32, time: DateTime<Utc>) -> Event {
+    Event {
+        key: system_id.to_string(),
+        payload: json!({
+            "type": "alert",
+            "time": time.to_rfc3339(),
+            "system_id": system_id,
+            "rule_id": if rule.builtin { None } else { Some(rule.id) },
+            "rule": rule.name,
+            "description": rule.description,
+            "severity": rule.severity,
+            "annotations": rule.annotations,
+        }),
+    }
+}
+
+pub fn metric_event(m: &MetricIngestItem) -> Event {
+    Event {
+        key: m.system_id.to_string(),
+        payload: json!({
+            "type": "metrics",
+            "time": m.time.to_rfc3339(),
+            "system_id": m.system_id,
+            "cpu_usage": m.cpu_usage,
+            "cpu_user": m.cpu_user,
+            "cpu_system": m.cpu_system,
+            "cpu_iowait": m.cpu_iowait,
+            "cpu_irq": m.cpu_irq,
+            "cpu_steal": m.cpu_steal,
+            "memory_used_bytes": m.memory_used_bytes,
+            "memory_total_bytes": m.memory_total_bytes,
+            "memory_available_bytes": m.memory_available_bytes,
+            "swap_used_bytes": m.swap_used_bytes,
+            "processes": m.processes,
+            "threads": m.threads,
+            "procs_running": m.procs_running,
+            "procs_zombie": m.procs_zombie,
+            "procs_blocked": m.procs_blocked,
+            "ctxt_per_sec": m.ctxt_per_sec,
+            "intr_per_sec": m.intr_per_sec,
+            "entropy_avail": m.entropy_avail,
+            "net_in_bytes_per_sec": m.net_in_bytes_per_sec,
+            "net_out_bytes_per_sec": m.net_out_bytes_per_sec,
+            "load_one": m.load_one,
+            "load_five": m.load_five,
+            "load_fifteen": m.load_fifteen,
+            "disks": m.disks.iter().map(|d| json!({
+                "name": d.name,
+                "mount_point": d.mount_point,
+                "total_bytes": d.total_bytes,
+                "used_bytes": d.used_bytes,
+                "read_bytes": d.read_bytes,
+                "write_bytes": d.write_bytes,
+                "read_iops": d.read_iops,
+                "write_iops": d.write_iops,
+                "inodes_total": d.inodes_total,
+                "inodes_used": d.inodes_used,
+            })).collect::<Vec<_>>(),
+            "components": m.original.components.iter().map(|c| json!({
+                "label": c.label,
+                "temperature": c.temperature,
+            })).collect::<Vec<_>>(),
+            "probes": m.original.probe_results.iter().map(|p| json!({
+                "name": p.name,
+                "address": p.address,
+                "up": p.up,
+                "latency_ms": p.latency_ms,
+            })).collect::<Vec<_>>(),
+        }),
+    }
+}
+
+/// Feeds stored metric batches to the metrics topic through the regular sink dispatch.
+pub struct EventSink {
+    publisher: Arc<dyn EventPublisher>,
+    topic: String,
+}
+
+#[async_trait]
+impl MetricSink for EventSink {
+    fn name(&self) -> &'static str {
+        self.publisher.name()
+    }
+
+    async fn write(&self, metrics: &[MetricIngestItem]) -> Result<(), SinkError> {
+        let events: Vec<Event> = metrics.iter().map(metric_event).collect();
+        self.publisher.publish(&self.topic, &events).await?;
+        Ok(())
+    }
+}
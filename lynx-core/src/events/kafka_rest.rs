@@ -0,0 +1,73 @@
+use super::{Event, EventError, EventPublisher};
+use async_trait::async_trait;
+use serde_json::json;
+
+const CONTENT_TYPE: &str = "application/vnd.kafka.json.v2+json";
+
+/*
+ * KafkaRestPublisher
+ * Produces to Kafka through a REST proxy (Confluent REST Proxy v2 API or compatible), so the hub
+ * needs no native Kafka client: POST {url}/topics/{topic} with the batch as JSON records keyed by
+ * system id. Credentials can be embedded in the URL, they are sent as basic auth.
+ */
+pub struct KafkaRestPublisher {
+    client: reqwest::Client,
+    base_url: String,
+    username: String,
+    password: Option<String>,
+}
+
+impl KafkaRestPublisher {
+    pub fn new(url: &str) -> Result<Self, EventError> {
+        let mut parsed = url::Url::parse(url).map_err(|e| EventError::Config(e.to_string()))?;
+        let username = parsed.username().to_string();
+        let password = parsed.password().map(String::from);
+        let _ = parsed.set_username("");
+        let _ = parsed.set_password(None);
+        Ok(Self {
+            client: reqwest::Client::new(),
+            base_url: parsed.as_str().trim_end_matches('/').to_string(),
+            username,
+            password,
+        })
+    }
+}
+
+pub fn records_body(events: &[Event]) -> serde_json::Value {
+    json!({
+        "records": events
+            .iter()
+            .map(|e| json!({"key": e.key, "value": e.payload}))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[async_trait]
+impl EventPublisher for KafkaRestPublisher {
+    fn name(&self) -> &'static str {
+        "kafka_rest"
+    }
+
+    async fn publish(&self, topic: &str, events: &[Event]) -> Result<(), EventError> {
+        if events.is_empty() {
+            return Ok(());
+        }
+        let url = format!("{}/topics/{}", self.base_url, urlencoding::encode(topic));
+        let mut request = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, CONTENT_TYPE)
+            .body(records_body(events).to_string());
+        if !self.username.is_empty() {
+            request = request.basic_auth(&self.username, self.password.as_ref());
+        }
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            return Err(EventError::Rejected {
+                status: response.status().as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(())
+    }
+}
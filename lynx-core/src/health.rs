@@ -0,0 +1,120 @@
+use crate::proto::monitor::{
+    control_server, inventory_server, metrics_ingest_server, system_monitor_server,
+};
+use crate::tls::CertExpiry;
+use log::{info, warn};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use tonic_health::server::HealthReporter;
+use tonic_health::ServingStatus;
+
+pub const DB_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Longest /readyz waits for the database before reporting it unreachable.
+pub const READY_DB_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Services whose health follows the database.
+const SERVICES: [&str; 4] = [
+    metrics_ingest_server::SERVICE_NAME,
+    inventory_server::SERVICE_NAME,
+    control_server::SERVICE_NAME,
+    system_monitor_server::SERVICE_NAME,
+];
+
+/*
+ * watch_database
+ * Reports the hub through grpc.health.v1 as serving only while the database answers, so load
+ * balancers stop routing agents to a hub that can't store anything. Covers the overall ("")
+ * status as well as each agent facing service.
+ */
+pub async fn watch_database(pool: PgPool, reporter: HealthReporter) {
+    let mut ticker = tokio::time::interval(DB_CHECK_INTERVAL);
+    let mut serving = None;
+    loop {
+        ticker.tick().await;
+        let ok = sqlx::query("SELECT 1").execute(&pool).await.is_ok();
+        if serving == Some(ok) {
+            continue;
+        }
+        serving = Some(ok);
+        let status = if ok {
+            info!("[health] Database reachable, reporting SERVING");
+            ServingStatus::Serving
+        } else {
+            warn!("[health] Database unreachable, reporting NOT_SERVING");
+            ServingStatus::NotServing
+        };
+        reporter.set_service_status("", status).await;
+        for service in SERVICES {
+            reporter.set_service_status(service, status).await;
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReadyCheck {
+    pub ok: bool,
+    pub detail: String,
+}
+
+impl ReadyCheck {
+    fn new(ok: bool, detail: impl Into<String>) -> Self {
+        Self {
+            ok,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Body of `GET /readyz`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Readiness {
+    pub ready: bool,
+    pub database: ReadyCheck,
+    pub tls: ReadyCheck,
+    pub ingest_queue: ReadyCheck,
+}
+
+impl Readiness {
+    pub fn new(database: ReadyCheck, tls: ReadyCheck, ingest_queue: ReadyCheck) -> Self {
+        Self {
+            ready: database.ok && tls.ok && ingest_queue.ok,
+            database,
+            tls,
+            ingest_queue,
+        }
+    }
+}
+
+pub async fn check_database(pool: &PgPool) -> ReadyCheck {
+    match tokio::time::timeout(READY_DB_TIMEOUT, sqlx::query("SELECT 1").execute(pool)).await {
+        Ok(Ok(_)) => ReadyCheck::new(true, "reachable"),
+        Ok(Err(e)) => ReadyCheck::new(false, e.to_string()),
+        Err(_) => ReadyCheck::new(false, "timed out"),
+    }
+}
+
+/*
+ * check_tls
+ * The hub refuses to start without its certificates, so this catches the cert monitor not having
+ * read them yet and certificates that expired while the hub kept running. `--insecure` hubs
+ * have no TLS to check.
+ */
+pub fn check_tls(enabled: bool, certs: &[CertExpiry]) -> ReadyCheck {
+    if !enabled {
+        return ReadyCheck::new(true, "disabled");
+    }
+    if certs.is_empty() {
+        return ReadyCheck::new(false, "certificates not loaded");
+    }
+    match certs.iter().find(|c| c.days_remaining <= 0.0) {
+        Some(expired) => ReadyCheck::new(false, format!("{} expired", expired.file)),
+        None => ReadyCheck::new(true, format!("{} certificates loaded", certs.len())),
+    }
+}
+
+/// An instance whose ingest queue is close to full should get no new agents.
+pub fn check_queue(depth: usize, capacity: usize, max_percent: u8) -> ReadyCheck {
+    let limit = capacity * max_percent as usize / 100;
+    ReadyCheck::new(depth <= limit, format!("{depth}/{capacity} queued"))
+}
@@ -0,0 +1,969 @@
+use crate::auth_limit::AuthLimiter;
+use crate::cache::{Cache, CacheStats};
+use crate::cert_monitor::CertStatus;
+use crate::config::AgentRelease;
+use crate::health::{self, Readiness};
+use crate::notify::deliveries::{self, Delivery, DeliveryQuery};
+use crate::notify::flapping::{FlappingRule, FLAPPING};
+use crate::notify::{ParseError, RuleParser};
+use crate::proto::monitor::session_frame::Frame;
+use crate::proto::monitor::CommandAudit;
+use crate::retention::{self, RetentionOptions, RetentionReport};
+use crate::services::agent::{
+    generate_agent_install_script, InstallScriptError, InstallTarget, SignedScript,
+};
+use crate::services::authorization::{self, ActionToken, AuthorizationError, AuthorizationRequest};
+use crate::services::clock_skew::{self, SkewQuery, SkewedSystem};
+use crate::services::command_audit::{self, AuditEntry, AuditQuery, Transcript};
+use crate::services::custom_metrics::{self, CustomAuthError, CustomMetricsRequest};
+use crate::services::decommission::{self, Decommission, DecommissionError};
+use crate::services::feature_flags::{self, FeatureFlag, FeatureFlagError};
+use crate::services::gpu_history::{self, GpuHistory, GpuHistoryError, GpuMetricsQuery};
+use crate::services::ingest::{IngestError, IngestItem, IngestQueue};
+use crate::services::location::{self, GroupQuery, GroupSummary, Location, LocationError};
+use crate::services::overview::{self, SystemOverview};
+use crate::services::processes::{self, ProcessQuery, ProcessSnapshot};
+use crate::services::rule_pack::{self, ProvisionQuery, Provisioned, RulePackError};
+use crate::services::service_graph::{self, GraphQuery, ServiceGraph};
+use crate::services::service_list::{self, ServicePage, ServiceQuery};
+use crate::services::sessions::{
+    Session, SessionError, SessionRelay, Ticket, TunnelRequest, TunnelTicket,
+};
+use crate::shutdown::Shutdown;
+use crate::telemetry::TELEMETRY;
+use crate::tls::{CertExpiry, CertificateAuthority};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::time::Instant;
+
+#[derive(Clone)]
+pub struct HttpState {
+    pub cache: Cache,
+    pub certs: CertStatus,
+    pub pool: sqlx::PgPool,
+    /// Used by listing endpoints, may point at a replica
+    pub read_pool: sqlx::PgPool,
+    pub agent_release: AgentRelease,
+    /// ADMIN_TOKEN, required as a bearer token by endpoints that change systems
+    pub admin_token: Option<String>,
+    pub auth_limit: Arc<AuthLimiter>,
+    pub ingest: IngestQueue,
+    /// False for `--insecure` hubs, /readyz skips the certificate check then
+    pub tls_enabled: bool,
+    pub ready_queue_percent: u8,
+    pub sessions: SessionRelay,
+    /// Signs command authorizations, None without the CA key or in `--insecure` mode
+    pub ca: Option<Arc<CertificateAuthority>>,
+    /// Reported by GET /retention without pruning anything
+    pub retention: RetentionOptions,
+}
+
+#[derive(Deserialize)]
+struct InstallRequest {
+    hostname: String,
+    token: String,
+    /// `os`, `arch` and `init`, Linux x86_64 with systemd when left out
+    #[serde(flatten)]
+    target: InstallTarget,
+}
+
+#[derive(Deserialize)]
+struct ValidateRuleRequest {
+    expression: String,
+}
+
+#[derive(Serialize)]
+struct ParsedCondition {
+    component: String,
+    metric: String,
+    operator: &'static str,
+    value: f64,
+    /// AND / OR joining the next condition, None on the last one
+    next: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct ValidateRuleResponse {
+    valid: bool,
+    conditions: Vec<ParsedCondition>,
+    error: Option<ParseError>,
+}
+
+#[derive(Serialize)]
+struct CustomMetricsResponse {
+    accepted: usize,
+}
+
+pub fn router(state: HttpState) -> Router {
+    Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics))
+        .route("/cache/stats", get(cache_stats))
+        .route("/tls/certificates", get(tls_certificates))
+        .route("/systems/overview", get(systems_overview))
+        .route("/systems/groups", get(system_groups))
+        .route("/systems/clock-skew", get(clock_skewed_systems))
+        .route("/systems/{id}/location", put(set_system_location))
+        .route("/systems/{id}/services", get(system_services))
+        .route("/systems/{id}/services/graph", get(system_service_graph))
+        .route("/systems/{id}/processes", get(system_processes))
+        .route("/systems/{id}/gpus/metrics", get(query_gpu_metrics))
+        .route("/systems/{id}/decommission", post(decommission_system))
+        .route("/systems/{id}/deliveries", get(system_deliveries))
+        .route("/systems/{id}/audit", get(system_audit))
+        .route(
+            "/systems/{id}/audit/{audit_id}/transcript",
+            get(audit_transcript),
+        )
+        .route("/systems/{id}/sessions", post(open_session))
+        .route("/systems/{id}/ws", get(session_socket))
+        .route("/systems/{id}/authorizations", post(authorize_action))
+        .route("/systems/{id}/tunnels", post(open_tunnel))
+        .route("/systems/{id}/tunnel", get(tunnel_socket))
+        .route("/alerts/{id}/deliveries", get(alert_deliveries))
+        .route("/alerts/flapping", get(flapping_alerts))
+        .route("/rules/defaults", post(provision_default_rules))
+        .route("/rules/validate", post(validate_rule))
+        .route("/retention", get(retention_report))
+        .route("/features", get(list_feature_flags))
+        .route(
+            "/features/{name}",
+            put(set_feature_flag).delete(delete_feature_flag),
+        )
+        .route("/agents/install", post(agent_install_script))
+        .route("/metrics/custom", post(post_custom_metrics))
+        .with_state(state)
+}
+
+/// Liveness, answers as long as the process runs its HTTP server.
+async fn healthz() -> &'static str {
+    "ok"
+}
+
+/*
+ * readyz
+ * Readiness for Kubernetes and load balancers: the database answers, TLS certificates are loaded
+ * and valid, and the ingest queue has room. Returns 503 with the failing check otherwise.
+ */
+async fn readyz(State(state): State<HttpState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = Readiness::new(
+        health::check_database(&state.pool).await,
+        health::check_tls(state.tls_enabled, &state.certs.get().await),
+        health::check_queue(
+            state.ingest.depth(),
+            state.ingest.capacity(),
+            state.ready_queue_percent,
+        ),
+    );
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// Hub self-telemetry in the Prometheus text format, see telemetry.rs for the series.
+async fn metrics(State(state): State<HttpState>) -> impl IntoResponse {
+    let body = TELEMETRY.render(
+        state.ingest.depth(),
+        state.ingest.capacity(),
+        &state.cache.stats().await,
+    );
+    ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], body)
+}
+
+async fn cache_stats(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+) -> Result<Json<CacheStats>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    Ok(Json(state.cache.stats().await))
+}
+
+async fn tls_certificates(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<CertExpiry>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    Ok(Json(state.certs.get().await))
+}
+
+/*
+ * systems_overview
+ * Headline numbers, online state, active alert count and tags of every active system in one
+ * response, see services::overview.
+ */
+async fn systems_overview(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SystemOverview>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    overview::list(&state.read_pool)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to load the systems overview: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * system_groups
+ * The overview summed up per site, datacenter or rack (`?by=`), see services::location.
+ */
+async fn system_groups(
+    State(state): State<HttpState>,
+    Query(query): Query<GroupQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<GroupSummary>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    overview::list(&state.read_pool)
+        .await
+        .map(|systems| Json(location::summarize(&systems, query.by)))
+        .map_err(|e| {
+            error!("[http] Failed to load the system groups: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * clock_skewed_systems
+ * Systems whose clock was off by more than `?threshold_secs=` (default 30) at their last report,
+ * worst first, see services::clock_skew.
+ */
+async fn clock_skewed_systems(
+    State(state): State<HttpState>,
+    Query(query): Query<SkewQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<SkewedSystem>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    clock_skew::skewed(&state.read_pool, &query)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to list systems with clock skew: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * set_system_location
+ * Sets a system's site, datacenter and rack, levels left out are cleared. The agent's [tags]
+ * take precedence for the levels they carry.
+ */
+async fn set_system_location(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    headers: HeaderMap,
+    Json(request): Json<Location>,
+) -> Result<Json<Location>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    location::set(&state.pool, system_id, &request)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            LocationError::SystemNotFound(_) => (StatusCode::NOT_FOUND, e.to_string()),
+            LocationError::Db(_) => {
+                error!("[http] Failed to set the location of system {system_id}: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+            e => (StatusCode::BAD_REQUEST, e.to_string()),
+        })
+}
+
+/*
+ * system_services
+ * Pages through a system's services, see services::service_list for the query parameters.
+ */
+async fn system_services(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<ServiceQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ServicePage>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    service_list::list(&state.cache, &state.read_pool, system_id, &query)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to list services (system {system_id}): {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * alert_deliveries
+ * Delivery attempts of one alert, `id` being its alert_history row. An empty list means no
+ * notifier was attached to the rule when it triggered.
+ */
+async fn alert_deliveries(
+    State(state): State<HttpState>,
+    Path(alert_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Delivery>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    deliveries::for_alert(&state.read_pool, alert_id)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to list deliveries (alert {alert_id}): {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+#[derive(Deserialize)]
+struct FlappingQuery {
+    system_id: Option<i32>,
+}
+
+/// Rules this hub currently keeps quiet because they flap, `?system_id=` for one system.
+async fn flapping_alerts(
+    State(state): State<HttpState>,
+    Query(query): Query<FlappingQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<FlappingRule>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let mut rules = FLAPPING.flapping();
+    if let Some(system_id) = query.system_id {
+        rules.retain(|r| r.system_id == system_id);
+    }
+    Ok(Json(rules))
+}
+
+/// Latest delivery attempts for a system, `?failed=true` for the failed ones only.
+async fn system_deliveries(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<DeliveryQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Delivery>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    deliveries::for_system(&state.read_pool, system_id, &query)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to list deliveries (system {system_id}): {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * system_audit
+ * Actions taken on a system over the agent websocket, newest first, see
+ * services::command_audit. Admin only, command lines may carry secrets.
+ */
+async fn system_audit(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<AuditQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    command_audit::for_system(&state.read_pool, system_id, &query)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to list command audit (system {system_id}): {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/// What an audited command read and wrote, admin only like the audit itself.
+async fn audit_transcript(
+    State(state): State<HttpState>,
+    Path((system_id, audit_id)): Path<(i32, i32)>,
+    headers: HeaderMap,
+) -> Result<Json<Transcript>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    match command_audit::transcript(&state.read_pool, system_id, audit_id).await {
+        Ok(Some(transcript)) => Ok(Json(transcript)),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("No transcript for audit entry {audit_id} of system {system_id}"),
+        )),
+        Err(e) => {
+            error!("[http] Failed to get transcript {audit_id} (system {system_id}): {e}");
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    }
+}
+
+/// Checks the `Authorization: Bearer` header against ADMIN_TOKEN in constant time. Every endpoint
+/// but the probes, /metrics and the ones agents call with their own credentials requires it.
+fn require_admin(state: &HttpState, headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let Some(expected) = &state.admin_token else {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "ADMIN_TOKEN is not set on this hub".to_string(),
+        ));
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing admin token".to_string()))?;
+    if presented.len() != expected.len()
+        || !openssl::memcmp::eq(presented.as_bytes(), expected.as_bytes())
+    {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid admin token".to_string()));
+    }
+    Ok(())
+}
+
+/*
+ * system_service_graph
+ * Requires / Wants / After links between a system's units and what each failed unit impacts,
+ * see services::service_graph.
+ */
+async fn system_service_graph(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<GraphQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ServiceGraph>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    service_graph::graph(&state.cache, &state.read_pool, system_id, &query)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to load the service graph (system {system_id}): {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * system_processes
+ * The busiest processes of a system at a point in time, `?at=2024-05-02T03:12:00Z`, see
+ * services::processes.
+ */
+async fn system_processes(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<ProcessQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ProcessSnapshot>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    processes::at(&state.read_pool, system_id, &query)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to load processes (system {system_id}): {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * query_gpu_metrics
+ * A system's GPU utilization, memory, temperature and power over time, raw samples and hourly
+ * rollups alike, `?from=...&to=...&gpu=0&step=300`, see services::gpu_history.
+ */
+async fn query_gpu_metrics(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<GpuMetricsQuery>,
+    headers: HeaderMap,
+) -> Result<Json<GpuHistory>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    gpu_history::query(&state.read_pool, system_id, &query)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            GpuHistoryError::Db(_) => {
+                error!("[http] Failed to load GPU metrics (system {system_id}): {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+            e => (StatusCode::BAD_REQUEST, e.to_string()),
+        })
+}
+
+/*
+ * decommission_system
+ * Starts decommissioning a system, see services::decommission. Repeating the call is harmless
+ * and reports the archive once the key was revoked.
+ */
+async fn decommission_system(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Json<Decommission>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    decommission::request(&state.pool, system_id)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            DecommissionError::NotFound(_) => (StatusCode::NOT_FOUND, e.to_string()),
+            e => {
+                error!("[http] Failed to decommission system {system_id}: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+        })
+}
+
+/*
+ * open_session
+ * Issues a ticket for one websocket session to a system, see services::sessions. Browsers
+ * can't send the admin token on a websocket, the ticket stands in for it.
+ */
+async fn open_session(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Json<Ticket>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    state
+        .sessions
+        .issue_ticket(system_id)
+        .map(Json)
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct SessionQuery {
+    ticket: String,
+}
+
+async fn session_socket(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<SessionQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let sessions = &state.sessions;
+    sessions
+        .redeem(system_id, &query.ticket)
+        .map_err(|e| (StatusCode::UNAUTHORIZED, e.to_string()))?;
+    let session = sessions.open(system_id).await.map_err(|e| match e {
+        SessionError::NotConnected(_) => (StatusCode::NOT_FOUND, e.to_string()),
+        e => (StatusCode::BAD_REQUEST, e.to_string()),
+    })?;
+    info!(
+        "[http] Relaying session {} to system {system_id}",
+        session.id
+    );
+    Ok(ws.on_upgrade(move |socket| relay_session(socket, session, None)))
+}
+
+/*
+ * authorize_action
+ * Issues a short-lived token for a destructive websocket message, see
+ * services::authorization. Every token issued goes to the command audit.
+ */
+async fn authorize_action(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<AuthorizationRequest>,
+) -> Result<Json<ActionToken>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let Some(ca) = &state.ca else {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The CA key is not loaded on this hub".to_string(),
+        ));
+    };
+    let token = authorization::issue(&state.pool, ca, system_id, &request)
+        .await
+        .map_err(|e| match e {
+            AuthorizationError::NotFound(_) => (StatusCode::NOT_FOUND, e.to_string()),
+            AuthorizationError::InvalidAction(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+            e => {
+                error!(
+                    "[http] Failed to authorize {} on system {system_id}: {e}",
+                    request.action
+                );
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+        })?;
+    let entry = CommandAudit {
+        time: chrono::Utc::now().timestamp(),
+        actor: format!("admin@{}", remote.ip()),
+        action: "authorize".to_string(),
+        target: request.action.clone(),
+        args: request.target.iter().cloned().collect(),
+        exit_code: None,
+        error: String::new(),
+        transcript: Vec::new(),
+    };
+    if let Err(e) = command_audit::record(&state.pool, system_id, &entry).await {
+        error!("[http] Failed to audit the authorization for system {system_id}: {e}");
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+    info!(
+        "[http] Authorized {} on system {system_id} for {}",
+        request.action, entry.actor
+    );
+    Ok(Json(token))
+}
+
+/*
+ * open_tunnel
+ * Grants a TCP tunnel to a port on the system's loopback and issues the ticket for its
+ * websocket, see services::sessions. Grants go to the command audit with the admin's address,
+ * the agent records the tunnel itself once it closed.
+ */
+async fn open_tunnel(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<TunnelRequest>,
+) -> Result<Json<TunnelTicket>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let ticket = state
+        .sessions
+        .issue_tunnel_ticket(system_id, &request)
+        .map_err(|e| match e {
+            SessionError::NotConnected(_) => (StatusCode::NOT_FOUND, e.to_string()),
+            e => (StatusCode::BAD_REQUEST, e.to_string()),
+        })?;
+    let entry = CommandAudit {
+        time: chrono::Utc::now().timestamp(),
+        actor: format!("admin@{}", remote.ip()),
+        action: "grant_tunnel".to_string(),
+        target: ticket.port.to_string(),
+        args: vec![format!("ttl={}s", ticket.ttl_secs)],
+        exit_code: None,
+        error: String::new(),
+        transcript: Vec::new(),
+    };
+    if let Err(e) = command_audit::record(&state.pool, system_id, &entry).await {
+        error!("[http] Failed to audit the tunnel grant for system {system_id}: {e}");
+        return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+    }
+    info!(
+        "[http] Granted {} a tunnel to port {} of system {system_id} for {}s",
+        entry.actor, ticket.port, ticket.ttl_secs
+    );
+    Ok(Json(ticket))
+}
+
+async fn tunnel_socket(
+    State(state): State<HttpState>,
+    Path(system_id): Path<i32>,
+    Query(query): Query<SessionQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let sessions = &state.sessions;
+    let tunnel = sessions
+        .redeem_tunnel(system_id, &query.ticket)
+        .map_err(|e| (StatusCode::UNAUTHORIZED, e.to_string()))?;
+    let session = sessions
+        .open_tunnel(system_id, tunnel)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+    info!(
+        "[http] Tunneling session {} to port {} of system {system_id}",
+        session.id, tunnel.port
+    );
+    let deadline = Instant::now() + tunnel.ttl;
+    Ok(ws.on_upgrade(move |socket| relay_session(socket, session, Some(deadline))))
+}
+
+/*
+ * relay_session
+ * Copies messages between a dashboard's websocket and the agent until either side closes, or
+ * `deadline` passed for tunnels.
+ */
+async fn relay_session(mut socket: WebSocket, mut session: Session, deadline: Option<Instant>) {
+    let expired = async {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(expired);
+    loop {
+        tokio::select! {
+            _ = &mut expired => {
+                info!("[http] Session {} reached its TTL", session.id);
+                break;
+            }
+            message = socket.recv() => {
+                let frame = match message {
+                    Some(Ok(Message::Text(text))) => Frame::Text(text.as_str().to_string()),
+                    Some(Ok(Message::Binary(data))) => Frame::Binary(data.to_vec()),
+                    Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Err(e)) => {
+                        warn!("[http] Session {} failed: {e}", session.id);
+                        break;
+                    }
+                };
+                if session.send(frame).await.is_err() {
+                    break;
+                }
+            }
+            frame = session.from_agent.recv() => {
+                let message = match frame {
+                    Some(Frame::Text(text)) => Message::Text(text.into()),
+                    Some(Frame::Binary(data)) => Message::Binary(data.into()),
+                    Some(Frame::Open(_) | Frame::Tunnel(_)) => continue,
+                    Some(Frame::Close(_)) | None => break,
+                };
+                if socket.send(message).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    let _ = socket.send(Message::Close(None)).await;
+    info!(
+        "[http] Session {} to system {} closed",
+        session.id, session.system_id
+    );
+}
+
+/*
+ * provision_default_rules
+ * Adds the default rule pack for a user, the first admin without `user_id`, see
+ * services::rule_pack. Rules the user already has are reported as existing.
+ */
+async fn provision_default_rules(
+    State(state): State<HttpState>,
+    Query(query): Query<ProvisionQuery>,
+    headers: HeaderMap,
+) -> Result<Json<Provisioned>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    rule_pack::provision(&state.pool, query.user_id)
+        .await
+        .map(Json)
+        .map_err(|e| match e {
+            RulePackError::UserNotFound(_) | RulePackError::NoAdmin => {
+                (StatusCode::NOT_FOUND, e.to_string())
+            }
+            e => {
+                error!("[http] Failed to provision default rules: {e}");
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+        })
+}
+
+/*
+ * validate_rule
+ * Parses a rule expression the way the notification workers will, so the portal can point at
+ * the offending token before the rule is saved. Invalid expressions are a 200 with `error` set.
+ */
+async fn validate_rule(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+    Json(req): Json<ValidateRuleRequest>,
+) -> Result<Json<ValidateRuleResponse>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let response = match RuleParser::parse_expression(&req.expression) {
+        Ok(conditions) => ValidateRuleResponse {
+            valid: true,
+            conditions: conditions
+                .into_iter()
+                .map(|c| ParsedCondition {
+                    component: c.component,
+                    metric: c.metric,
+                    operator: c.operator.symbol(),
+                    value: c.value,
+                    next: c.next_logical.map(|l| l.keyword()),
+                })
+                .collect(),
+            error: None,
+        },
+        Err(e) => ValidateRuleResponse {
+            valid: false,
+            conditions: Vec::new(),
+            error: Some(e),
+        },
+    };
+    Ok(Json(response))
+}
+
+/*
+ * retention_report
+ * Dry run of the retention policy: per table, the rows past their retention and the hourly rows
+ * rolling them up would write. Counting scans the old rows, so it is for operators, not polling.
+ */
+async fn retention_report(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+) -> Result<Json<RetentionReport>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    retention::prune(&state.pool, &state.retention, true)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to count rows past retention: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+/*
+ * list_feature_flags
+ * Every hub feature flag with its rollout, see services::feature_flags.
+ */
+async fn list_feature_flags(
+    State(state): State<HttpState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<FeatureFlag>>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    feature_flags::list(&state.pool)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("[http] Failed to list feature flags: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+fn feature_flag_error(name: &str, e: FeatureFlagError) -> (StatusCode, String) {
+    match e {
+        FeatureFlagError::NotFound(_) => (StatusCode::NOT_FOUND, e.to_string()),
+        FeatureFlagError::Db(_) => {
+            error!("[http] Failed to update feature flag {name}: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        }
+        e => (StatusCode::BAD_REQUEST, e.to_string()),
+    }
+}
+
+/*
+ * set_feature_flag
+ * Creates or replaces a flag, `{"rollout_percent": 10, "canary_systems": [4, 7]}`. Agents get
+ * the change with their next config poll.
+ */
+async fn set_feature_flag(
+    State(state): State<HttpState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+    Json(request): Json<FeatureFlag>,
+) -> Result<Json<FeatureFlag>, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    let flag = FeatureFlag { name, ..request };
+    let flag = feature_flags::set(&state.pool, &flag)
+        .await
+        .map_err(|e| feature_flag_error(&flag.name, e))?;
+    info!(
+        "[http] Feature flag {} rolled out to {}% and {} canaries",
+        flag.name,
+        flag.rollout_percent,
+        flag.canary_systems.len()
+    );
+    Ok(Json(flag))
+}
+
+/// Removes a flag, agents go back to their built-in default for the feature.
+async fn delete_feature_flag(
+    State(state): State<HttpState>,
+    Path(name): Path<String>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    require_admin(&state, &headers)?;
+    feature_flags::delete(&state.pool, &name)
+        .await
+        .map_err(|e| feature_flag_error(&name, e))?;
+    info!("[http] Feature flag {name} removed");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/*
+ * agent_install_script
+ * Activates a pending agent and returns its install script with the release key's signature, so
+ * the script can be verified before it is run instead of piping it straight into bash.
+ */
+async fn agent_install_script(
+    State(state): State<HttpState>,
+    Json(req): Json<InstallRequest>,
+) -> Result<Json<SignedScript>, (StatusCode, String)> {
+    generate_agent_install_script(
+        &req.hostname,
+        &req.token,
+        &req.target,
+        &state.agent_release,
+        &state.pool,
+    )
+    .await
+    .map(Json)
+    .map_err(|e| match e {
+        InstallScriptError::InvalidToken => {
+            warn!(
+                "[http] Install script requested with an invalid token for {}",
+                req.hostname
+            );
+            (StatusCode::FORBIDDEN, e.to_string())
+        }
+        InstallScriptError::UnsupportedTarget(_) => (StatusCode::BAD_REQUEST, e.to_string()),
+        InstallScriptError::NoRelease { .. } => (StatusCode::NOT_FOUND, e.to_string()),
+        e => {
+            error!("[http] Failed to generate install script: {e}");
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        }
+    })
+}
+
+/*
+ * post_custom_metrics
+ * Accepts ad-hoc metrics from scripts and third-party tools, authenticated with the agent key of
+ * the system they belong to in the `x-agent-key` header like agents do over gRPC.
+ */
+async fn post_custom_metrics(
+    State(state): State<HttpState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<CustomMetricsRequest>,
+) -> Result<Json<CustomMetricsResponse>, (StatusCode, String)> {
+    let agent_key = headers
+        .get("x-agent-key")
+        .and_then(|v| v.to_str().ok())
+        .ok_or((StatusCode::UNAUTHORIZED, "Missing key".to_string()))?;
+
+    let system_id =
+        custom_metrics::authenticate(&state.pool, &state.auth_limit, Some(remote.ip()), agent_key)
+            .await
+            .map_err(|e| match e {
+                CustomAuthError::InvalidKey => (StatusCode::UNAUTHORIZED, e.to_string()),
+                CustomAuthError::Denied(_) => (StatusCode::TOO_MANY_REQUESTS, e.to_string()),
+                CustomAuthError::Database(_) => {
+                    error!("[http] Failed to authenticate custom metrics: {e}");
+                    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+                }
+            })?;
+
+    let items = custom_metrics::validate(system_id, req)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let accepted = items.len();
+    for item in items {
+        if let Err(e) = state.ingest.push(IngestItem::Custom(item)).await {
+            error!("[http] Failed to queue custom metric: {e}");
+            let status = match e {
+                IngestError::Full => StatusCode::TOO_MANY_REQUESTS,
+                IngestError::Closed => StatusCode::SERVICE_UNAVAILABLE,
+            };
+            return Err((status, e.to_string()));
+        }
+    }
+    Ok(Json(CustomMetricsResponse { accepted }))
+}
+
+/*
+ * serve
+ * Runs the operator facing HTTP API until the listener fails or the hub shuts down, in which
+ * case requests already in flight are completed.
+ */
+pub async fn serve(addr: SocketAddr, state: HttpState, shutdown: Shutdown) -> std::io::Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("[http] HTTP API listening on http://{addr}");
+    let app = router(state).into_make_service_with_connect_info::<SocketAddr>();
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await
+        .map_err(|e| {
+            error!("[http] HTTP API error: {e}");
+            e
+        })
+}
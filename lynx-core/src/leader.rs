@@ -0,0 +1,130 @@
+use crate::shutdown::Shutdown;
+use log::{info, warn};
+use sqlx::{Connection, PgConnection, PgPool};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::{interval_at, Instant};
+
+/*
+ * Leader election
+ * Hubs sharing one database elect a leader for the jobs that must run once per deployment:
+ * retention, certificate expiry notifications and the SNMP / Prometheus pollers, whose samples
+ * would otherwise be stored and alerted on by every hub. The leader holds a session level
+ * Postgres advisory lock on a dedicated connection. When that connection dies the server releases
+ * the lock and another hub takes over at its next attempt; a hub shutting down releases it
+ * right away.
+ * Agent reports are checked against the alert rules by the hub that received them when hubs
+ * claim their alerts in Redis (see REDIS_URL); without it only the leader's notification
+ * workers evaluate, since no hub could tell whether another one already notified.
+ */
+
+/// Advisory lock key, "lynx" in ASCII.
+pub const LEADER_LOCK_ID: i64 = 0x6c79_6e78;
+pub const ELECTION_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Whether this hub currently leads, handed to every leader-only job.
+#[derive(Clone, Debug)]
+pub struct Leadership {
+    rx: watch::Receiver<bool>,
+}
+
+impl Leadership {
+    /// A fixed answer, for tests and tools that run a single job without electing.
+    pub fn fixed(leader: bool) -> Self {
+        let (_tx, rx) = watch::channel(leader);
+        Self { rx }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        *self.rx.borrow()
+    }
+}
+
+/*
+ * elect
+ * Makes the first attempt before returning, so jobs starting right after see the outcome, then
+ * keeps competing (or checking the lock is still held) in the background.
+ */
+pub async fn elect(pool: PgPool, shutdown: Shutdown) -> Leadership {
+    let lock = try_acquire(&pool).await;
+    if lock.is_some() {
+        info!("[leader] This hub is now the leader");
+    } else {
+        info!("[leader] Another hub is the leader");
+    }
+    let (tx, rx) = watch::channel(lock.is_some());
+    tokio::spawn(run_election(pool, lock, tx, shutdown));
+    Leadership { rx }
+}
+
+async fn run_election(
+    pool: PgPool,
+    mut lock: Option<PgConnection>,
+    tx: watch::Sender<bool>,
+    shutdown: Shutdown,
+) {
+    let mut tick = interval_at(Instant::now() + ELECTION_INTERVAL, ELECTION_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = shutdown.wait() => break,
+        }
+        lock = match lock.take() {
+            // holding the lock, make sure the session that owns it is still alive
+            Some(mut conn) => match sqlx::query("SELECT 1").execute(&mut conn).await {
+                Ok(_) => Some(conn),
+                Err(e) => {
+                    warn!("[leader] Lost the connection holding the leader lock: {e}");
+                    None
+                }
+            },
+            None => try_acquire(&pool).await,
+        };
+
+        let leader = lock.is_some();
+        if *tx.borrow() != leader {
+            if leader {
+                info!("[leader] This hub is now the leader");
+            } else {
+                warn!("[leader] This hub is no longer the leader");
+            }
+            tx.send_replace(leader);
+        }
+    }
+
+    tx.send_replace(false);
+    if let Some(conn) = lock {
+        // ending the session releases the lock for the other hubs
+        if let Err(e) = conn.close().await {
+            warn!("[leader] Failed to release the leader lock: {e}");
+        }
+        info!("[leader] Leadership released");
+    }
+}
+
+/// A connection holding the lock, None when another hub has it.
+async fn try_acquire(pool: &PgPool) -> Option<PgConnection> {
+    let mut conn = match pool.acquire().await {
+        // the lock lives as long as this session, keep it out of the pool
+        Ok(conn) => conn.detach(),
+        Err(e) => {
+            warn!("[leader] Failed to get a connection for the leader lock: {e}");
+            return None;
+        }
+    };
+    let acquired = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_lock($1)")
+        .bind(LEADER_LOCK_ID)
+        .fetch_one(&mut conn)
+        .await;
+    match acquired {
+        Ok(true) => Some(conn),
+        Ok(false) => {
+            let _ = conn.close().await;
+            None
+        }
+        Err(e) => {
+            warn!("[leader] Leader lock query failed: {e}");
+            None
+        }
+    }
+}
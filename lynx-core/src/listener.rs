@@ -0,0 +1,42 @@
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::net::{UnixListener, UnixStream};
+use tonic::codegen::tokio_stream::Stream;
+
+/*
+ * Unix socket listener
+ * Lets the gRPC server accept on GRPC_SOCKET instead of TCP, e.g. behind a reverse proxy on the
+ * same host. mTLS still applies, the socket only replaces the transport.
+ */
+
+pub struct UnixIncoming {
+    listener: UnixListener,
+}
+
+impl Stream for UnixIncoming {
+    type Item = io::Result<UnixStream>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.listener
+            .poll_accept(cx)
+            .map(|accepted| Some(accepted.map(|(stream, _)| stream)))
+    }
+}
+
+/// Binds the socket, replacing one left behind by a previous run.
+pub fn bind_unix(path: &Path) -> io::Result<UnixIncoming> {
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    Ok(UnixIncoming { listener })
+}
@@ -0,0 +1,113 @@
+use crate::auth_limit;
+use log::info;
+use tonic::codegen::http;
+use tonic::metadata::MetadataMap;
+use tracing::field::Empty;
+use tracing::Span;
+use tracing_subscriber::EnvFilter;
+
+/*
+ * Logging
+ * The hub logs through tracing, one JSON object per line by default so Loki or Elastic can
+ * index it. Every gRPC call runs in an `rpc` span with the method path, a hash of the agent key,
+ * the request id and, once authenticated, the system id; the fields end up on each line logged
+ * during the call. The log macros used across the hub are forwarded into tracing.
+ *
+ * Agents send an `x-request-id` with every report. Metrics carry it past the call, through the
+ * ingest writers and the notification workers, so one sample can be followed from the agent's
+ * logs to the alert it fired by searching for a single id.
+ */
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Json,
+    Text,
+}
+
+impl LogFormat {
+    /// LOG_FORMAT, `json` (default) or `text` for reading logs in a terminal.
+    pub fn from_env() -> Self {
+        match std::env::var("LOG_FORMAT").as_deref() {
+            Ok("text") | Ok("pretty") => LogFormat::Text,
+            _ => LogFormat::Json,
+        }
+    }
+}
+
+/// MY_LOG_LEVEL takes EnvFilter directives, e.g. `info,sqlx=warn`.
+fn filter() -> EnvFilter {
+    EnvFilter::try_from_env("MY_LOG_LEVEL").unwrap_or_else(|_| EnvFilter::new("info"))
+}
+
+/// Colors for text output, MY_LOG_STYLE=never turns them off.
+fn ansi() -> bool {
+    !matches!(std::env::var("MY_LOG_STYLE").as_deref(), Ok("never"))
+}
+
+pub fn init() {
+    let builder = tracing_subscriber::fmt().with_env_filter(filter());
+    let format = LogFormat::from_env();
+    match format {
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+        LogFormat::Text => builder.with_ansi(ansi()).init(),
+    }
+    info!("[hub] Logging initialized ({format:?})");
+}
+
+/// Ids are logged verbatim, so only short ones made of characters safe in any log format count.
+pub fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/*
+ * rpc_span
+ * Span for one gRPC call, created before any interceptor runs. The agent key itself never
+ * leaves the request, only the short hash also used in auth_events.
+ */
+pub fn rpc_span(request: &http::Request<()>) -> Span {
+    let header = |name: &str| request.headers().get(name).and_then(|v| v.to_str().ok());
+    let key_id = header("x-agent-key").map(auth_limit::key_id);
+    let request_id = header(REQUEST_ID_HEADER).filter(|id| is_valid_request_id(id));
+    tracing::info_span!(
+        "rpc",
+        method = request.uri().path(),
+        key_id = key_id.as_deref(),
+        request_id,
+        system_id = Empty,
+    )
+}
+
+/*
+ * request_id
+ * The id the caller sent, or a new one recorded on the current rpc span for callers that sent
+ * none (or an invalid one), so every report has an id to be traced by.
+ */
+pub fn request_id(metadata: &MetadataMap) -> String {
+    if let Some(id) = metadata
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+    {
+        return id.to_string();
+    }
+    let id = uuid::Uuid::new_v4().to_string();
+    Span::current().record("request_id", id.as_str());
+    id
+}
+
+/// Adds the authenticated system to the current rpc span.
+pub fn record_system_id(system_id: i32) {
+    Span::current().record("system_id", system_id);
+}
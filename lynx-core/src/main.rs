@@ -1,37 +1,206 @@
+mod auth_limit;
+mod backup;
 mod cache;
+mod cert_monitor;
 mod config;
+mod counters;
 mod db;
+mod discovery;
+mod events;
+mod health;
+mod http;
+mod leader;
+mod listener;
+mod logging;
 mod notify;
+mod partitions;
+mod prometheus;
 mod proto;
 mod services;
+mod shared;
+mod shutdown;
+mod signing;
+mod sinks;
+mod snmp;
+mod telemetry;
 mod tls; // added cache module
+mod units;
 
 mod retention;
+mod revocation;
 
 mod queries;
 
 use crate::cache::Cache;
+use crate::config::GrpcBind;
+use crate::notify::worker::run_notification_worker;
+use crate::notify::{AlertState, EvaluationQueue};
+use crate::proto::monitor::control_server::ControlServer;
+use crate::proto::monitor::enrollment_server::EnrollmentServer;
+use crate::proto::monitor::inventory_server::InventoryServer;
+use crate::proto::monitor::metrics_ingest_server::MetricsIngestServer;
 use crate::proto::monitor::system_monitor_server::SystemMonitorServer;
-use crate::services::ingest::{run_metric_worker, IngestItem};
+use crate::services::decommission;
+use crate::services::enroll::EnrollmentService;
+use crate::services::ingest::{run_metric_worker, IngestQueue};
+use crate::services::maintenance;
 use crate::services::monitor::MyMonitor;
-use log::{error, info};
-use std::net::SocketAddr;
+use crate::services::prometheus_poller;
+use crate::services::releases;
+use crate::services::rule_pack;
+use crate::services::service_list;
+use crate::services::sessions::SessionRelay;
+use crate::services::snmp_poller;
+use log::{error, info, warn};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc::channel;
-use tokio::time::interval;
+use tokio::time::{interval, timeout, timeout_at, Instant};
+
+/// Closing the pools waits for checked out connections, this bounds it on top of the drain.
+const POOL_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type RpcServer = Pin<Box<dyn Future<Output = Result<(), tonic::transport::Error>> + Send>>;
+
+/// `backup` and `restore` work on the hub database and exit without starting the hub.
+async fn run_command(
+    cfg: &config::Config,
+    command: backup::Command,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::setup_db(&cfg.db).await?;
+    let (verb, path, summary) = match command {
+        backup::Command::Backup { path, metrics_days } => {
+            let summary = backup::backup(&pool, &path, metrics_days).await?;
+            ("Wrote", path, summary)
+        }
+        backup::Command::Restore { path } => {
+            let summary = backup::restore(&pool, &path).await?;
+            ("Restored", path, summary)
+        }
+    };
+    let rows: u64 = summary.iter().map(|(_, count)| count).sum();
+    info!("[backup] {verb} {rows} rows ({})", path.display());
+    pool.close().await;
+    Ok(())
+}
+
+/// `maintenance start|end|status` works on the database shared by all hubs and exits.
+async fn run_maintenance(
+    cfg: &config::Config,
+    command: maintenance::MaintenanceCommand,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::setup_db(&cfg.db).await?;
+    let running = match command {
+        maintenance::MaintenanceCommand::Start {
+            duration,
+            reason,
+            scope,
+        } => vec![maintenance::start(&pool, duration, &reason, scope.as_ref()).await?],
+        maintenance::MaintenanceCommand::End { scope } => {
+            maintenance::end(&pool, scope.as_ref()).await?;
+            maintenance::list(&pool).await?
+        }
+        maintenance::MaintenanceCommand::Status => maintenance::list(&pool).await?,
+    };
+    if running.is_empty() {
+        println!("No maintenance, notifications are sent");
+    }
+    for m in running {
+        let scope = if m.scope.is_empty() {
+            "the hub"
+        } else {
+            m.scope.as_str()
+        };
+        println!(
+            "Maintenance of {} since {}, notifications paused until {} {}",
+            scope, m.started, m.until, m.reason
+        );
+    }
+    pool.close().await;
+    Ok(())
+}
+
+/// `migrate` converts samples stored before sizes were in bytes and exits.
+async fn run_migrate(cfg: &config::Config) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::setup_db(&cfg.db).await?;
+    if units::needs_migration(&pool).await? {
+        units::migrate(&pool).await?;
+    } else {
+        println!("Stored samples are already in bytes, nothing to convert");
+    }
+    pool.close().await;
+    Ok(())
+}
+
+/// `rules install-defaults` provisions the default rule pack and exits.
+async fn run_rule_pack(
+    cfg: &config::Config,
+    query: rule_pack::ProvisionQuery,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let pool = db::setup_db(&cfg.db).await?;
+    let provisioned = rule_pack::provision(&pool, query.user_id).await?;
+    println!(
+        "Created {} default rules for user {} ({} notifiers each), already present: {}",
+        provisioned.created.len(),
+        provisioned.user_id,
+        provisioned.notifiers,
+        provisioned.existing.join(", ")
+    );
+    pool.close().await;
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load env and initialize logging
     config::load_env();
-    config::init_logging();
-    let cfg = config::Config::from_env()?;
+    logging::init();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = backup::Command::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    let maintenance_command =
+        maintenance::MaintenanceCommand::from_args(&args).unwrap_or_else(|e| {
+            eprintln!("{e}");
+            std::process::exit(2);
+        });
+    let rule_pack_command = rule_pack::ProvisionQuery::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    let migrate_command = units::MigrateCommand::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("{e}");
+        std::process::exit(2);
+    });
+    let cfg = config::Config::from_env().await?;
+    if let Some(command) = command {
+        return run_command(&cfg, command).await;
+    }
+    if let Some(command) = maintenance_command {
+        return run_maintenance(&cfg, command).await;
+    }
+    if let Some(query) = rule_pack_command {
+        return run_rule_pack(&cfg, query).await;
+    }
+    if migrate_command.is_some() {
+        return run_migrate(&cfg).await;
+    }
     info!("[hub] Starting Lynx Hub...");
+    if let Some(url) = &cfg.portal_url {
+        notify::alert::set_portal_url(url);
+    }
+    notify::FLAPPING.configure(cfg.flapping.clone());
+    if !cfg.insecure {
+        crate::tls::install_crypto_policy(&cfg.tls).unwrap_or_else(|e| {
+            error!("[hub] Invalid TLS policy: {e}");
+            std::process::exit(1);
+        });
+    }
 
-    // Validate database URL
-    let database_url = cfg.database_url;
     // Setup DB
-    let db_pool = match db::setup_db(&database_url).await {
+    let db_pool = match db::setup_db(&cfg.db).await {
         Ok(pool) => {
             info!("[hub] Connected to database");
             pool
@@ -41,19 +210,125 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             std::process::exit(1);
         }
     };
+    // sizes are stored in bytes, databases from before are converted by `lynx-core migrate`
+    match units::needs_migration(&db_pool).await {
+        Ok(false) => {}
+        Ok(true) => {
+            error!(
+                "[hub] Stored samples predate sizes in bytes, stop the hubs and run \
+                 `lynx-core migrate` first"
+            );
+            std::process::exit(1);
+        }
+        Err(e) => {
+            error!("[hub] Failed to check the stored sample units: {e}");
+            std::process::exit(1);
+        }
+    }
+    // systemctl upserts need unique service names, databases from before get them once
+    if let Err(e) = service_list::migrate(&db_pool).await {
+        error!("[hub] Failed to make service names unique: {e}");
+        std::process::exit(1);
+    }
+    // built-in rules are recorded in the alert history, databases from before get the column once
+    if let Err(e) = notify::history::migrate(&db_pool).await {
+        error!("[hub] Failed to add built-in rules to the alert history: {e}");
+        std::process::exit(1);
+    }
+    // release manifests are signed when published, databases from before get the columns once
+    if let Err(e) = releases::migrate(&db_pool).await {
+        error!("[hub] Failed to add signed manifests to agent releases: {e}");
+        std::process::exit(1);
+    }
+    let read_pool = match db::setup_read_db(&cfg.db, &db_pool).await {
+        Ok(pool) => {
+            if cfg.db.read_database_url.is_some() {
+                info!("[hub] Connected to read replica");
+            }
+            pool
+        }
+        Err(e) => {
+            error!("[hub] Failed to connect to read replica: {e}");
+            std::process::exit(1);
+        }
+    };
 
     // TLS configuration
     let current_dir = std::env::current_dir()?;
     let certs_dir = current_dir.join("certs");
-    let server_tls_config = match crate::tls::build_tls_config(&certs_dir) {
-        Ok(cfg) => cfg,
+    let server_tls_config = if cfg.insecure {
+        log::warn!("[hub] Running with --insecure: mTLS disabled, do not use in production");
+        None
+    } else {
+        match crate::tls::build_tls_config(&certs_dir) {
+            Ok(cfg) => Some(cfg),
+            Err(e) => {
+                error!("[hub] TLS configuration failed: {e}");
+                std::process::exit(1);
+            }
+        }
+    };
+
+    // Enrollment and command authorizations are only offered when the CA key is available
+    let ca = match crate::tls::CertificateAuthority::load(&certs_dir) {
+        // tokens and keys must never be handed out over plaintext
+        _ if cfg.insecure => None,
+        Ok(ca) => Some(Arc::new(ca)),
         Err(e) => {
-            error!("[hub] TLS configuration failed: {e}");
-            std::process::exit(1);
+            log::warn!(
+                "[hub] Agent enrollment and command authorizations disabled, CA not loaded: {e}"
+            );
+            None
+        }
+    };
+    let enrollment = ca.clone().map(|ca| {
+        info!("[hub] Agent enrollment enabled");
+        EnrollmentServer::new(EnrollmentService {
+            pool: db_pool.clone(),
+            ca,
+            cert_days: cfg.enroll_cert_days,
+        })
+    });
+
+    let revocation = if cfg.insecure {
+        None
+    } else {
+        match revocation::RevocationChecker::from_options(&certs_dir, &cfg.tls).await {
+            Ok(checker) => checker.map(Arc::new),
+            Err(e) => {
+                error!("[hub] Failed to load revocation data: {e}");
+                std::process::exit(1);
+            }
         }
     };
+    if let Some(checker) = &revocation {
+        info!("[hub] Client certificate revocation checks enabled");
+        tokio::spawn(revocation::run_crl_reload(checker.clone()));
+    }
+
+    let (shutdown_trigger, shutdown) = shutdown::channel();
+
+    let auth_limit = Arc::new(auth_limit::AuthLimiter::new(cfg.auth_limit.clone()));
+    tokio::spawn(auth_limit::run_prune(auth_limit.clone()));
 
     let cache = Cache::new(10_000, 1_000);
+    let shared = match &cfg.redis_url {
+        Some(url) => match shared::SharedState::connect(url).await {
+            Ok(shared) => {
+                info!("[hub] Sharing system state and alert cooldowns through Redis");
+                Some(shared)
+            }
+            Err(e) => {
+                error!("[hub] Failed to connect to Redis: {e}");
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+    let cache = match &shared {
+        Some(shared) => cache.with_shared(shared.clone()),
+        None => cache,
+    };
     let snapshot_path = current_dir.join("cache.snapshot");
     if let Err(e) = cache.load_from_file(&snapshot_path).await {
         error!("[hub] Failed to load cache snapshot: {e}");
@@ -61,14 +336,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         info!("[hub] Cache snapshot loaded");
     }
 
-    // periodic snapshot task
+    // periodic snapshot task, stops on shutdown so it can't race the final snapshot
     {
         let cache_clone = cache.clone();
         let snapshot_path_clone = snapshot_path.clone();
+        let shutdown = shutdown.clone();
         tokio::spawn(async move {
             let mut tick = interval(Duration::from_secs(60));
             loop {
-                tick.tick().await;
+                tokio::select! {
+                    _ = tick.tick() => {}
+                    _ = shutdown.wait() => break,
+                }
                 if let Err(e) = cache_clone.snapshot_to_file(&snapshot_path_clone).await {
                     log::warn!("[hub] Cache snapshot failed: {e}");
                 }
@@ -88,57 +367,312 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
-    // ingest worker
-    let (metric_tx, metric_rx) = channel::<IngestItem>(10_000);
-    {
-        let pool_clone = db_pool.clone();
-        tokio::spawn(async move {
-            run_metric_worker(metric_rx, pool_clone).await;
+    let events = cfg
+        .events
+        .as_ref()
+        .and_then(|events| match events::Events::new(events) {
+            Ok(events) => {
+                info!(
+                    "[hub] Publishing alert events to {}",
+                    events.publisher_name()
+                );
+                Some(events)
+            }
+            Err(e) => {
+                error!("[hub] Failed to set up event publishing: {e}");
+                None
+            }
         });
-    }
 
-    // retention policy task
-    {
+    // leader-only jobs below, and alerting without shared state, run on one hub at a time
+    let leadership = leader::elect(db_pool.clone(), shutdown.clone()).await;
+
+    // ingest writers, each drains its own share of the queue
+    let (ingest, receivers) = IngestQueue::new(&cfg.ingest);
+    let ingest_worker = {
         let pool_clone = db_pool.clone();
-        let retention_days = cfg.retention_days;
+        let mut metric_sinks: Vec<Arc<dyn sinks::MetricSink>> = Vec::new();
+        if let Some(influx) = &cfg.influx {
+            match sinks::InfluxSink::new(influx) {
+                Ok(sink) => {
+                    info!("[hub] Writing metrics to InfluxDB at {}", influx.url);
+                    metric_sinks.push(Arc::new(sink));
+                }
+                Err(e) => error!("[hub] Failed to set up InfluxDB sink: {e}"),
+            }
+        }
+        let events = events.clone();
+        if let Some(sink) = events.as_ref().and_then(|e| e.metrics_sink()) {
+            metric_sinks.push(Arc::new(sink));
+        }
+        // notification workers evaluate what the writers stored, they stop after the writers
+        let (evaluations, evaluation_receivers) = EvaluationQueue::new(&cfg.evaluation);
+        let alerts = AlertState::new(shared);
+        let notifiers: Vec<_> = evaluation_receivers
+            .into_iter()
+            .map(|rx| {
+                tokio::spawn(run_notification_worker(
+                    rx,
+                    pool_clone.clone(),
+                    cfg.secrets.clone(),
+                    events.clone(),
+                    alerts.clone(),
+                    leadership.clone(),
+                ))
+            })
+            .collect();
+        let workers: Vec<_> = receivers
+            .into_iter()
+            .map(|rx| {
+                tokio::spawn(run_metric_worker(
+                    rx,
+                    pool_clone.clone(),
+                    metric_sinks.clone(),
+                    evaluations.clone(),
+                    shutdown.clone(),
+                ))
+            })
+            .collect();
+        drop(evaluations);
         tokio::spawn(async move {
-            if retention_days <= 0 {
-                log::warn!("[retention] Retention policy is disabled");
-                return;
+            for worker in workers.into_iter().chain(notifiers) {
+                let _ = worker.await;
             }
-            log::warn!(
-                "[retention] Retention policy active: {} days",
-                retention_days
-            );
-            let mut tick = interval(Duration::from_secs(3600));
-            loop {
-                tick.tick().await;
-                if let Err(e) = retention::prune_old_metrics(&pool_clone, retention_days).await {
-                    log::warn!("[retention] Prune failed: {e}");
-                }
+        })
+    };
+
+    // agentless SNMP devices and Prometheus exporters feed the same ingest queue
+    tokio::spawn(snmp_poller::run_snmp_poller(
+        db_pool.clone(),
+        cfg.secrets.clone(),
+        ingest.clone(),
+        leadership.clone(),
+    ));
+    tokio::spawn(prometheus_poller::run_prometheus_poller(
+        db_pool.clone(),
+        cfg.secrets.clone(),
+        ingest.clone(),
+        leadership.clone(),
+    ));
+
+    // chunks metrics and disks by time, once this hub leads
+    tokio::spawn(partitions::run_setup(
+        db_pool.clone(),
+        cfg.partitions.clone(),
+        leadership.clone(),
+    ));
+
+    // prunes and rolls up old rows, once this hub leads
+    tokio::spawn(retention::run_retention(
+        db_pool.clone(),
+        cfg.retention.clone(),
+        leadership.clone(),
+    ));
+
+    // revokes keys and archives samples of decommissioned systems
+    tokio::spawn(decommission::run_finalizer(
+        db_pool.clone(),
+        cfg.decommission.clone(),
+        leadership.clone(),
+    ));
+
+    // alert rules stay cached until a change is announced on lynx_rules
+    notify::RULES.set_ttl(cfg.rule_cache_ttl);
+    tokio::spawn(notify::rule_cache::run_rule_listener(
+        db_pool.clone(),
+        shutdown.clone(),
+    ));
+
+    // alerts on systems that stopped reporting
+    tokio::spawn(notify::offline::run_offline_monitor(
+        db_pool.clone(),
+        cfg.secrets.clone(),
+        events,
+        leadership.clone(),
+    ));
+
+    // certificate expiry monitor
+    let cert_status = cert_monitor::CertStatus::default();
+    tokio::spawn(cert_monitor::run_cert_monitor(
+        certs_dir.clone(),
+        cert_status.clone(),
+        db_pool.clone(),
+        cfg.secrets.clone(),
+        leadership,
+    ));
+
+    // dashboard websocket sessions relayed to agents, see services::sessions
+    let sessions = SessionRelay::default();
+
+    // operator HTTP API
+    let http_server = {
+        let state = http::HttpState {
+            cache: cache.clone(),
+            certs: cert_status.clone(),
+            pool: db_pool.clone(),
+            read_pool: read_pool.clone(),
+            agent_release: cfg.agent_release.clone(),
+            admin_token: cfg.admin_token.clone(),
+            auth_limit: auth_limit.clone(),
+            ingest: ingest.clone(),
+            tls_enabled: !cfg.insecure,
+            ready_queue_percent: cfg.ready_queue_percent,
+            sessions: sessions.clone(),
+            ca: ca.clone(),
+            retention: cfg.retention.clone(),
+        };
+        let http_addr = cfg.http_addr;
+        if !http_addr.ip().is_loopback() {
+            warn!("[hub] HTTP API on {http_addr} is plain HTTP, put a TLS proxy in front of it");
+        }
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move {
+            if let Err(e) = http::serve(http_addr, state, shutdown).await {
+                error!("[hub] HTTP API failed: {e}");
             }
-        });
-    }
+        })
+    };
 
     let monitor = MyMonitor {
         pool: db_pool.clone(),
+        read_pool: read_pool.clone(),
         cache: cache.clone(),
-        metric_tx,
+        ingest,
+        pin_client_certs: cfg.pin_client_certs && !cfg.insecure,
+        revocation,
+        auth_limit,
+        shutdown: shutdown.clone(),
+        admin_token: cfg.admin_token.clone(),
+        sessions,
     };
-    let addr = SocketAddr::from(([0, 0, 0, 0], 50051));
-    info!("[hub] gRPC server starting on https://{addr}");
+    if cfg.pin_client_certs && !cfg.insecure {
+        info!("[hub] Agents are pinned to their client certificates");
+    }
 
-    if let Err(e) = tonic::transport::Server::builder()
+    let mut server = tonic::transport::Server::builder()
+        .trace_fn(logging::rpc_span)
+        .layer(telemetry::RpcMetricsLayer)
         .tcp_keepalive(Some(Duration::from_secs(30)))
         .http2_keepalive_interval(Some(Duration::from_secs(15)))
-        .http2_keepalive_timeout(Some(Duration::from_secs(5)))
-        .tls_config(server_tls_config)?
-        .add_service(SystemMonitorServer::new(monitor))
-        .serve(addr)
-        .await
-    {
-        error!("[hub] RPC server error: {e}");
+        .http2_keepalive_timeout(Some(Duration::from_secs(5)));
+    let client_cert_check: crate::tls::AuthInterceptor = match server_tls_config {
+        Some(tls) => {
+            server = server.tls_config(tls)?;
+            info!("[hub] gRPC server starting on https://{}", cfg.grpc_bind);
+            crate::tls::require_client_cert
+        }
+        None => {
+            log::warn!(
+                "[hub] gRPC server starting on http://{} (--insecure)",
+                cfg.grpc_bind
+            );
+            Ok
+        }
+    };
+
+    // Health and reflection answer without a client certificate or agent key
+    let (health_reporter, health_service) = tonic_health::server::health_reporter();
+    tokio::spawn(health::watch_database(db_pool.clone(), health_reporter));
+    let reflection = if cfg.grpc_reflection {
+        let builder = || {
+            tonic_reflection::server::Builder::configure()
+                .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+                .register_encoded_file_descriptor_set(tonic_health::pb::FILE_DESCRIPTOR_SET)
+        };
+        // grpcurl and older clients still ask for v1alpha
+        Some((builder().build_v1()?, builder().build_v1alpha()?))
+    } else {
+        None
+    };
+    let (reflection_v1, reflection_v1alpha) = reflection.unzip();
+
+    // Each area is its own service so interceptors can differ, SystemMonitor serves older agents
+    let rpc_shutdown = shutdown.clone();
+    let router = server
+        .add_service(MetricsIngestServer::with_interceptor(
+            monitor.clone(),
+            client_cert_check,
+        ))
+        .add_service(InventoryServer::with_interceptor(
+            monitor.clone(),
+            client_cert_check,
+        ))
+        .add_service(ControlServer::with_interceptor(
+            monitor.clone(),
+            client_cert_check,
+        ))
+        .add_service(SystemMonitorServer::with_interceptor(
+            monitor,
+            client_cert_check,
+        ))
+        .add_optional_service(enrollment)
+        .add_service(health_service)
+        .add_optional_service(reflection_v1)
+        .add_optional_service(reflection_v1alpha);
+    let stopped = async move { rpc_shutdown.wait().await };
+    let mut rpc_server: RpcServer = match &cfg.grpc_bind {
+        GrpcBind::Tcp(addr) => Box::pin(router.serve_with_shutdown(*addr, stopped)),
+        GrpcBind::Unix(path) => {
+            let incoming = listener::bind_unix(path).map_err(|e| {
+                error!("[hub] Failed to bind {}: {e}", path.display());
+                e
+            })?;
+            Box::pin(router.serve_with_incoming_shutdown(incoming, stopped))
+        }
+    };
+    // answers mDNS queries until shutdown, a hub that can't advertise still serves
+    let advertisement = cfg.discovery.as_ref().and_then(|options| {
+        discovery::advertise(options, &cfg.grpc_bind)
+            .map_err(|e| error!("[discovery] Failed to advertise the hub: {e}"))
+            .ok()
+            .flatten()
+    });
+
+    let rpc_result = tokio::select! {
+        _ = shutdown::signal() => None,
+        result = &mut rpc_server => Some(result),
+    };
+    shutdown_trigger.trigger();
+    if let Some(daemon) = advertisement {
+        let _ = daemon.shutdown();
+    }
+
+    /*
+     * Shutdown sequence
+     * New RPCs and HTTP requests are refused from here on. Once in-flight calls are done the
+     * ingest worker has everything that was accepted and drains it, then the cache is persisted
+     * and the pools are closed.
+     */
+    let deadline = Instant::now() + cfg.shutdown_timeout;
+    let rpc_result = match rpc_result {
+        Some(result) => Some(result),
+        None => timeout_at(deadline, &mut rpc_server).await.ok(),
+    };
+    match rpc_result {
+        Some(Ok(())) => info!("[hub] RPC server stopped"),
+        Some(Err(e)) => error!("[hub] RPC server error: {e}"),
+        None => warn!("[hub] RPC server did not stop before the shutdown deadline"),
+    }
+    if timeout_at(deadline, http_server).await.is_err() {
+        warn!("[hub] HTTP API did not stop before the shutdown deadline");
+    }
+    match timeout_at(deadline, ingest_worker).await {
+        Ok(_) => info!("[hub] Ingest and alert queues drained"),
+        Err(_) => warn!("[hub] Ingest and alert queues not drained before the shutdown deadline"),
     }
 
+    match cache.snapshot_to_file(&snapshot_path).await {
+        Ok(()) => info!("[hub] Final cache snapshot written"),
+        Err(e) => warn!("[hub] Final cache snapshot failed: {e}"),
+    }
+
+    let close = async {
+        read_pool.close().await;
+        db_pool.close().await;
+    };
+    if timeout(POOL_CLOSE_TIMEOUT, close).await.is_err() {
+        warn!("[hub] Database pools did not close in time");
+    }
+    info!("[hub] Shutdown complete");
     Ok(())
 }
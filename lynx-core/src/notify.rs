@@ -1,18 +1,34 @@
 use async_trait::async_trait;
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 use tokio::sync::RwLock;
 
+pub mod alert;
+pub mod chart;
 pub mod components;
+pub mod deliveries;
+pub mod flapping;
+pub mod history;
+pub mod offline;
+pub mod pending;
 pub mod processor;
+pub mod rule_cache;
 pub mod rules;
 pub mod services;
+pub mod worker;
 
+pub use alert::{AlertMessage, TriggerValue};
+pub use chart::Chart;
 pub use components::*;
+pub use flapping::{Flap, FlapOptions, FLAPPING};
+pub use pending::PENDING;
 pub use processor::*;
+pub use rule_cache::RULES;
 pub use rules::*;
 pub use services::*;
+pub use worker::{AlertState, Evaluation, EvaluationQueue};
 
 /*
  * Notification System
@@ -21,10 +37,11 @@ pub use services::*;
  * network, etc. Each component implements the MetricComponent trait, which binds the "metrics"
  * of each component to that component. For example, the CPU component may have metrics like
  * "usage", "temperature", etc. The Memory component may have "used", "total", "usage", etc. Once
- * a new metric request is received, the registry is populated with the available components.
- * Then the alert rules are retrieved for the given system. Each rule is evaluated using the
- * registry to fetch the necessary metric values. If a rule triggers, the associated notifier
- * for that rule gets executed.
+ * a metric request is stored it is queued for the notification workers (see worker), whose
+ * processor populates the system's registry with the available components. Then the alert
+ * rules are retrieved for the given system. Each rule is evaluated using the registry to fetch
+ * the necessary metric values. If a rule triggers, the associated notifier for that rule gets
+ * executed.
  */
 
 #[derive(Error, Debug)]
@@ -46,22 +63,48 @@ pub trait MetricComponent: Send + Sync {
 #[async_trait]
 pub trait NotificationService: Send + Sync + Clone {
     async fn send(&self, message: &str) -> Result<(), NotificationError>;
+
+    /// Sends an alert with the chart of its metric, services that can't attach one drop it.
+    async fn send_alert(
+        &self,
+        alert: &AlertMessage,
+        _chart: Option<&Chart>,
+    ) -> Result<(), NotificationError> {
+        self.send(&alert.text()).await
+    }
 }
 
+/// A component with the time its sample was registered.
+struct Registered {
+    component: Box<dyn MetricComponent>,
+    at: Instant,
+}
+
+/*
+ * MetricRegistry
+ * The components of one system. A registry outlives the report that filled it, so a rule can
+ * combine components that arrive separately (e.g. `custom.*` with `cpu.*`); components not
+ * registered again within `max_age` are dropped by `expire`.
+ */
+#[derive(Default)]
 pub struct MetricRegistry {
-    components: Arc<RwLock<HashMap<String, Box<dyn MetricComponent>>>>,
+    components: Arc<RwLock<HashMap<String, Registered>>>,
 }
 
 impl MetricRegistry {
     pub fn new() -> Self {
-        Self {
-            components: Arc::new(RwLock::new(HashMap::new())),
-        }
+        Self::default()
     }
 
     pub async fn register_component(&self, name: String, component: Box<dyn MetricComponent>) {
         let mut components = self.components.write().await;
-        components.insert(name, component);
+        components.insert(
+            name,
+            Registered {
+                component,
+                at: Instant::now(),
+            },
+        );
     }
 
     pub async fn get_metric_value(
@@ -70,27 +113,21 @@ impl MetricRegistry {
         metric: &str,
     ) -> Result<f64, MetricError> {
         let components = self.components.read().await;
-        if let Some(comp) = components.get(component) {
-            comp.get_metric(metric).await
+        if let Some(registered) = components.get(component) {
+            registered.component.get_metric(metric).await
         } else {
             Err(MetricError::ComponentNotFound(component.to_string()))
         }
     }
-}
 
-use crate::proto::monitor::MetricsRequest;
-use sqlx::PgPool;
+    pub async fn has_component(&self, component: &str) -> bool {
+        self.components.read().await.contains_key(component)
+    }
 
-/*
- * process_notification
- * Main entry point to process notifications for a given MetricsRequest
- */
-pub async fn process_notification(
-    metrics: &MetricsRequest,
-    system_id: i32,
-    pool: &PgPool,
-    triggered_rules: &HashSet<String>,
-) -> Result<Vec<String>, Box<dyn std::error::Error + Send>> {
-    let mut processor = NotificationProcessor::new(pool.clone());
-    processor.process(metrics, system_id, triggered_rules).await
+    /// Drops components older than `max_age`, returns whether any are left.
+    pub async fn expire(&self, max_age: Duration) -> bool {
+        let mut components = self.components.write().await;
+        components.retain(|_, registered| registered.at.elapsed() < max_age);
+        !components.is_empty()
+    }
 }
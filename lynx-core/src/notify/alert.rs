@@ -0,0 +1,258 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+/*
+ * Alert messages
+ * What notifiers are told about a triggered rule: the rule and its annotations, the values
+ * that triggered it, the recent samples of its charted metric and, with PORTAL_URL set, links
+ * to the system in the portal. Discord gets the plain text; emails get an HTML rendering with the plain text as
+ * fallback for clients that don't show HTML.
+ */
+
+/// Rows of the recent history table, the newest samples of the charted series.
+pub const HISTORY_ROWS: usize = 10;
+
+static PORTAL_URL: OnceLock<String> = OnceLock::new();
+
+/// Base url of the portal used for links in notifications, set once on startup.
+pub fn set_portal_url(url: &str) {
+    let _ = PORTAL_URL.set(url.trim_end_matches('/').to_string());
+}
+
+#[derive(Debug, Clone)]
+pub struct TriggerValue {
+    /// `component.metric`
+    pub metric: String,
+    /// None when the report didn't carry the metric (an `or` branch that didn't match)
+    pub value: Option<f64>,
+    pub operator: Operator,
+    pub threshold: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertMessage {
+    pub rule: String,
+    pub description: String,
+    pub severity: String,
+    pub system_id: i32,
+    pub hostname: Option<String>,
+    pub values: Vec<TriggerValue>,
+    /// The rule's annotations, e.g. runbook_url or owner
+    pub annotations: BTreeMap<String, String>,
+    /// `component.metric` of the history, see chart::Series
+    pub history_metric: Option<String>,
+    /// Oldest first, at most HISTORY_ROWS
+    pub history: Vec<(DateTime<Utc>, f64)>,
+}
+
+/// Hex color of a severity, matching the portal's badges.
+pub fn severity_color(severity: &str) -> &'static str {
+    match severity.to_ascii_lowercase().as_str() {
+        "critical" => "#ef4444",
+        "high" => "#f97316",
+        "medium" => "#eab308",
+        "low" => "#22c55e",
+        _ => "#737373",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn is_link(value: &str) -> bool {
+    value.starts_with("https://") || value.starts_with("http://")
+}
+
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{value:.0}")
+    } else {
+        format!("{value:.2}")
+    }
+}
+
+impl AlertMessage {
+    pub fn new(rule: &Rule, system_id: i32) -> Self {
+        Self {
+            rule: rule.name.clone(),
+            description: rule.description.clone(),
+            severity: rule.severity.clone(),
+            system_id,
+            hostname: None,
+            values: Vec::new(),
+            annotations: rule.annotations.clone(),
+            history_metric: None,
+            history: Vec::new(),
+        }
+    }
+
+    /// Keeps the newest HISTORY_ROWS samples of the series.
+    pub fn with_history(mut self, metric: &str, samples: &[(DateTime<Utc>, f64)]) -> Self {
+        self.history_metric = Some(metric.to_string());
+        self.history = samples[samples.len().saturating_sub(HISTORY_ROWS)..].to_vec();
+        self
+    }
+
+    fn system(&self) -> String {
+        match &self.hostname {
+            Some(hostname) => format!("{hostname} (ID {})", self.system_id),
+            None => format!("ID {}", self.system_id),
+        }
+    }
+
+    pub fn system_link(&self) -> Option<String> {
+        PORTAL_URL
+            .get()
+            .map(|url| format!("{url}/systems/{}", self.system_id))
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "Alert: {}\nDescription: {}\nSeverity: {}\nSystem: {}",
+            self.rule,
+            self.description,
+            self.severity,
+            self.system()
+        );
+        for v in &self.values {
+            let value = v.value.map(format_value).unwrap_or_else(|| "-".to_string());
+            let _ = write!(
+                text,
+                "\n{} = {} ({} {})",
+                v.metric,
+                value,
+                v.operator.symbol(),
+                format_value(v.threshold)
+            );
+        }
+        for (key, value) in &self.annotations {
+            let _ = write!(text, "\n{key}: {value}");
+        }
+        if let Some(link) = self.system_link() {
+            let _ = write!(text, "\n{link}");
+        }
+        text
+    }
+
+    /*
+     * html
+     * A single table based layout with inline styles, what mail clients render reliably.
+     * `chart_cid` is the content id of the inline sparkline, if one is attached.
+     */
+    pub fn html(&self, chart_cid: Option<&str>) -> String {
+        let color = severity_color(&self.severity);
+        let mut html = format!(
+            "<!DOCTYPE html><html><body style=\"margin:0;padding:16px;background:#f5f5f5;\
+             font-family:Arial,Helvetica,sans-serif;color:#171717;\">\
+             <table role=\"presentation\" width=\"100%\" style=\"max-width:600px;margin:0 auto;\
+             background:#ffffff;border-top:6px solid {color};border-collapse:collapse;\">\
+             <tr><td style=\"padding:20px;\">\
+             <span style=\"display:inline-block;padding:2px 8px;border-radius:4px;\
+             background:{color};color:#ffffff;font-size:12px;text-transform:uppercase;\">{}</span>\
+             <h2 style=\"margin:12px 0 4px;\">{}</h2>\
+             <p style=\"margin:0 0 12px;color:#525252;\">{}</p>\
+             <p style=\"margin:0 0 16px;\">System: <strong>{}</strong></p>",
+            escape(&self.severity),
+            escape(&self.rule),
+            escape(&self.description),
+            escape(&self.system())
+        );
+
+        if !self.values.is_empty() {
+            html.push_str(
+                "<table width=\"100%\" style=\"border-collapse:collapse;margin-bottom:16px;\">\
+                 <tr><th align=\"left\" style=\"border-bottom:1px solid #e5e5e5;\">Metric</th>\
+                 <th align=\"right\" style=\"border-bottom:1px solid #e5e5e5;\">Value</th>\
+                 <th align=\"right\" style=\"border-bottom:1px solid #e5e5e5;\">Condition</th>\
+                 </tr>",
+            );
+            for v in &self.values {
+                let value = v.value.map(format_value).unwrap_or_else(|| "-".to_string());
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td align=\"right\"><strong>{}</strong></td>\
+                     <td align=\"right\">{} {}</td></tr>",
+                    escape(&v.metric),
+                    value,
+                    escape(v.operator.symbol()),
+                    format_value(v.threshold)
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        if !self.annotations.is_empty() {
+            html.push_str(
+                "<table width=\"100%\" style=\"border-collapse:collapse;margin-bottom:16px;\
+                 font-size:13px;\">",
+            );
+            for (key, value) in &self.annotations {
+                let value = if is_link(value) {
+                    format!(
+                        "<a href=\"{0}\" style=\"color:{1};\">{0}</a>",
+                        escape(value),
+                        color
+                    )
+                } else {
+                    escape(value)
+                };
+                let _ = write!(
+                    html,
+                    "<tr><td style=\"color:#525252;padding-right:12px;\">{}</td><td>{}</td></tr>",
+                    escape(key),
+                    value
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        if let Some(cid) = chart_cid {
+            let _ = write!(
+                html,
+                "<img src=\"cid:{}\" width=\"400\" height=\"100\" alt=\"Last hour\" \
+                 style=\"display:block;margin-bottom:16px;\">",
+                escape(cid)
+            );
+        }
+
+        if let (Some(metric), false) = (&self.history_metric, self.history.is_empty()) {
+            let _ = write!(
+                html,
+                "<table width=\"100%\" style=\"border-collapse:collapse;margin-bottom:16px;\
+                 font-size:13px;\"><tr><th align=\"left\" style=\"border-bottom:1px solid \
+                 #e5e5e5;\">Time (UTC)</th><th align=\"right\" style=\"border-bottom:1px solid \
+                 #e5e5e5;\">{}</th></tr>",
+                escape(metric)
+            );
+            for (time, value) in self.history.iter().rev() {
+                let _ = write!(
+                    html,
+                    "<tr><td>{}</td><td align=\"right\">{}</td></tr>",
+                    time.format("%Y-%m-%d %H:%M:%S"),
+                    format_value(*value)
+                );
+            }
+            html.push_str("</table>");
+        }
+
+        if let Some(link) = self.system_link() {
+            let _ = write!(
+                html,
+                "<p style=\"margin:0;\"><a href=\"{0}\" style=\"color:{1};\">Open system</a>\
+                 &nbsp;&middot;&nbsp;<a href=\"{0}/alerts/history\" style=\"color:{1};\">\
+                 Alert history</a></p>",
+                escape(&link),
+                color
+            );
+        }
+        html.push_str("</td></tr></table></body></html>");
+        html
+    }
+}
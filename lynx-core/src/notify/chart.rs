@@ -0,0 +1,229 @@
+use super::*;
+use chrono::{DateTime, Utc};
+use log::warn;
+use sqlx::PgPool;
+
+/*
+ * Alert charts
+ * A triggered rule's notification carries a sparkline of the metric its first chartable
+ * condition tests, over the last hour, so responders see the trend without opening a
+ * dashboard. The PNG is drawn here without a plotting library: a white canvas, the threshold
+ * as a red line and the samples as a blue one. Conditions on metrics that aren't stored per
+ * sample (disks, probes, the certificate expiry) have no chart.
+ */
+
+pub const CHART_WIDTH: u32 = 400;
+pub const CHART_HEIGHT: u32 = 100;
+/// How far back the chart goes.
+pub const CHART_WINDOW_SECS: i64 = 3600;
+const PADDING: f64 = 6.0;
+/// Series spreading less than this share of their magnitude are drawn flat, byte counts in the
+/// billions carry rounding noise that would otherwise fill the whole chart
+const FLAT_SPREAD: f64 = 1e-9;
+
+const BACKGROUND: [u8; 4] = [255, 255, 255, 255];
+const SERIES: [u8; 4] = [36, 99, 235, 255];
+const THRESHOLD: [u8; 4] = [220, 38, 38, 255];
+
+/// The last hour of the metric a rule's condition tests.
+#[derive(Debug, Clone)]
+pub struct Series {
+    /// `component.metric`
+    pub title: String,
+    pub threshold: f64,
+    /// Oldest first
+    pub samples: Vec<(DateTime<Utc>, f64)>,
+}
+
+impl Series {
+    pub fn chart(&self) -> Option<Chart> {
+        let values: Vec<f64> = self.samples.iter().map(|(_, v)| *v).collect();
+        Some(Chart {
+            title: self.title.clone(),
+            png: render_sparkline(&values, Some(self.threshold))?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chart {
+    /// `component.metric` the chart shows
+    pub title: String,
+    pub png: Vec<u8>,
+}
+
+/// SQL over the metrics table giving the value of `component.metric` in the unit rules use,
+/// None if it isn't stored.
+pub fn series_expression(component: &str, metric: &str) -> Option<&'static str> {
+    Some(match (component, metric) {
+        ("cpu", "usage") => "cpu_usage",
+        ("cpu", "user") => "cpu_user",
+        ("cpu", "system") => "cpu_system",
+        ("cpu", "iowait") => "cpu_iowait",
+        ("cpu", "irq") => "cpu_irq",
+        ("cpu", "steal") => "cpu_steal",
+        ("memory", "used") => "memory_used_bytes / 1024.0",
+        ("memory", "total") => "memory_total_bytes / 1024.0",
+        ("memory", "usage") => "memory_used_bytes * 100.0 / NULLIF(memory_total_bytes, 0)",
+        ("memory", "available") => "memory_available_bytes / 1024.0",
+        ("memory", "swap_used") => "swap_used_bytes / 1024.0",
+        ("load", "one") => "load_one",
+        ("load", "five") => "load_five",
+        ("load", "fifteen") => "load_fifteen",
+        ("network", "in") => "net_in_bytes_per_sec / 1048576.0",
+        ("network", "out") => "net_out_bytes_per_sec / 1048576.0",
+        ("processes", "total") => "processes",
+        ("processes", "threads") => "threads",
+        ("processes", "running") => "procs_running",
+        ("processes", "zombie") => "procs_zombie",
+        ("kernel", "context_switches") => "ctxt_per_sec",
+        ("kernel", "interrupts") => "intr_per_sec",
+        ("kernel", "procs_blocked") => "procs_blocked",
+        ("kernel", "entropy") => "entropy_avail",
+        ("agent", "clock_skew_seconds") => "abs(clock_skew_ms) / 1000.0",
+        _ => return None,
+    })
+}
+
+fn chartable(condition: &Condition) -> bool {
+    condition.component == "custom"
+        || series_expression(&condition.component, &condition.metric).is_some()
+}
+
+async fn load_series(
+    pool: &PgPool,
+    system_id: i32,
+    condition: &Condition,
+) -> Result<Vec<(DateTime<Utc>, f64)>, sqlx::Error> {
+    let samples: Vec<(DateTime<Utc>, Option<f64>)> = if condition.component == "custom" {
+        sqlx::query_as(
+            "SELECT time, value FROM custom_metrics \
+             WHERE system_id = $1 AND name = $2 AND time > NOW() - ($3 * INTERVAL '1 second') \
+             ORDER BY time",
+        )
+        .bind(system_id)
+        .bind(&condition.metric)
+        .bind(CHART_WINDOW_SECS)
+        .fetch_all(pool)
+        .await?
+    } else {
+        let Some(expression) = series_expression(&condition.component, &condition.metric) else {
+            return Ok(Vec::new());
+        };
+        let sql = format!(
+            "SELECT time, ({expression})::float8 FROM metrics \
+             WHERE system_id = $1 AND time > NOW() - ($2 * INTERVAL '1 second') ORDER BY time"
+        );
+        sqlx::query_as(&sql)
+            .bind(system_id)
+            .bind(CHART_WINDOW_SECS)
+            .fetch_all(pool)
+            .await?
+    };
+    Ok(samples
+        .into_iter()
+        .filter_map(|(time, value)| Some((time, value?)))
+        .collect())
+}
+
+/// The series behind a triggered rule's chart, None without one. Failures are only logged.
+pub async fn for_rule(pool: &PgPool, system_id: i32, rule: &Rule) -> Option<Series> {
+    let condition = rule.conditions.iter().find(|c| chartable(c))?;
+    let samples = match load_series(pool, system_id, condition).await {
+        Ok(samples) => samples,
+        Err(e) => {
+            warn!("Failed to load the chart of rule '{}': {}", rule.name, e);
+            return None;
+        }
+    };
+    Some(Series {
+        title: format!("{}.{}", condition.component, condition.metric),
+        threshold: condition.value,
+        samples,
+    })
+}
+
+/*
+ * render_sparkline
+ * Scales the samples to the canvas, keeping the threshold in view, and encodes the RGBA
+ * pixels as PNG. Needs at least two finite samples.
+ */
+pub fn render_sparkline(values: &[f64], threshold: Option<f64>) -> Option<Vec<u8>> {
+    let values: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    if values.len() < 2 {
+        return None;
+    }
+    let threshold = threshold.filter(|t| t.is_finite());
+    let mut min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let mut max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if let Some(t) = threshold {
+        min = min.min(t);
+        max = max.max(t);
+    }
+    let magnitude = min.abs().max(max.abs()).max(1.0);
+    if max - min < magnitude * FLAT_SPREAD {
+        min -= 1.0;
+        max += 1.0;
+    }
+
+    let (width, height) = (CHART_WIDTH as f64, CHART_HEIGHT as f64);
+    let x = |i: usize| PADDING + i as f64 * (width - 2.0 * PADDING) / (values.len() - 1) as f64;
+    let y = |v: f64| height - PADDING - (v - min) / (max - min) * (height - 2.0 * PADDING);
+
+    let mut canvas = Canvas::new(CHART_WIDTH, CHART_HEIGHT);
+    if let Some(t) = threshold {
+        canvas.line((PADDING, y(t)), (width - PADDING, y(t)), THRESHOLD);
+    }
+    for (i, pair) in values.windows(2).enumerate() {
+        canvas.line((x(i), y(pair[0])), (x(i + 1), y(pair[1])), SERIES);
+    }
+    canvas.encode()
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: BACKGROUND.repeat((width * height) as usize),
+        }
+    }
+
+    /// A 2px wide line, plotted by stepping along its longer axis.
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), color: [u8; 4]) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0);
+        for step in 0..=steps as u32 {
+            let t = step as f64 / steps;
+            let (px, py) = (from.0 + dx * t, from.1 + dy * t);
+            for (ox, oy) in [(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)] {
+                self.set((px + ox - 0.5).round(), (py + oy - 0.5).round(), color);
+            }
+        }
+    }
+
+    fn set(&mut self, x: f64, y: f64, color: [u8; 4]) {
+        if x < 0.0 || y < 0.0 || x >= self.width as f64 || y >= self.height as f64 {
+            return;
+        }
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[offset..offset + 4].copy_from_slice(&color);
+    }
+
+    fn encode(&self) -> Option<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().ok()?;
+        writer.write_image_data(&self.pixels).ok()?;
+        writer.finish().ok()?;
+        Some(png)
+    }
+}
@@ -1,5 +1,91 @@
 use super::*;
-use crate::proto::monitor::{CpuStats, DiskStats, LoadAverage, MemoryStats, NetworkStats};
+use crate::proto::monitor::{
+    CpuStats, DiskStats, KernelStats, LoadAverage, MemoryStats, NetworkStats, ProbeResult,
+    ProcessStats, SmartDevice,
+};
+use crate::units;
+
+const CPU_METRICS: &[&str] = &["usage", "user", "system", "iowait", "irq", "steal"];
+const MEMORY_METRICS: &[&str] = &[
+    "used",
+    "total",
+    "usage",
+    "available",
+    "cached",
+    "swap_used",
+    "swap_usage",
+    "swap_in",
+    "swap_out",
+];
+const DISK_METRICS: &[&str] = &[
+    "used",
+    "total",
+    "usage",
+    "read",
+    "write",
+    "read_iops",
+    "write_iops",
+    "inodes_used",
+    "inodes_usage",
+];
+const LOAD_METRICS: &[&str] = &["one", "five", "fifteen"];
+const NETWORK_METRICS: &[&str] = &["in", "out"];
+const TLS_METRICS: &[&str] = &["expiry_days"];
+const AGENT_METRICS: &[&str] = &["offline_minutes", "clock_skew_seconds"];
+const PROBE_METRICS: &[&str] = &["up", "down", "max_latency"];
+const PROCESS_METRICS: &[&str] = &["total", "threads", "running", "zombie"];
+const KERNEL_METRICS: &[&str] = &["context_switches", "interrupts", "procs_blocked", "entropy"];
+const SMART_METRICS: &[&str] = &[
+    "failed",
+    "reallocated_sectors",
+    "pending_sectors",
+    "uncorrectable_sectors",
+    "media_errors",
+    "percentage_used",
+    "temperature",
+    "reallocated_sectors.delta_24h",
+    "reallocated_sectors.delta_7d",
+    "pending_sectors.delta_24h",
+    "pending_sectors.delta_7d",
+    "uncorrectable_sectors.delta_24h",
+    "uncorrectable_sectors.delta_7d",
+    "media_errors.delta_24h",
+    "media_errors.delta_7d",
+];
+
+/// Windows SMART counters are compared over, `<counter>.<window>` in rules, with their hours.
+pub const SMART_WINDOWS: &[(&str, i64)] = &[("delta_24h", 24), ("delta_7d", 7 * 24)];
+
+/// Every built-in component under the name rules address it by, with its metrics.
+pub const COMPONENTS: &[(&str, &[&str])] = &[
+    ("cpu", CPU_METRICS),
+    ("memory", MEMORY_METRICS),
+    ("disk", DISK_METRICS),
+    ("load", LOAD_METRICS),
+    ("network", NETWORK_METRICS),
+    ("tls", TLS_METRICS),
+    ("agent", AGENT_METRICS),
+    ("probes", PROBE_METRICS),
+    ("processes", PROCESS_METRICS),
+    ("kernel", KERNEL_METRICS),
+    ("smart", SMART_METRICS),
+];
+
+/// Takes any metric name the custom metrics API accepts.
+pub const CUSTOM_COMPONENT: &str = "custom";
+
+/// The metrics of a built-in component, None for `custom` and unknown names.
+pub fn known_metrics(component: &str) -> Option<&'static [&'static str]> {
+    COMPONENTS
+        .iter()
+        .find(|(name, _)| *name == component)
+        .map(|(_, metrics)| *metrics)
+}
+
+/// Metrics older agents or pollers don't report are missing rather than zero.
+fn optional_metric(component: &str, metric: &str, value: Option<f64>) -> Result<f64, MetricError> {
+    value.ok_or_else(|| MetricError::MetricNotFound(format!("{component} {metric} not reported")))
+}
 
 // CPU Component Implementation
 pub struct CpuComponent {
@@ -20,6 +106,11 @@ impl MetricComponent for CpuComponent {
         let stats = self.stats.read().await;
         match metric_name {
             "usage" => Ok(stats.usage_percent as f64),
+            "user" => optional_metric("CPU", "user", stats.user_percent),
+            "system" => optional_metric("CPU", "system", stats.system_percent),
+            "iowait" => optional_metric("CPU", "iowait", stats.iowait_percent),
+            "irq" => optional_metric("CPU", "irq", stats.irq_percent),
+            "steal" => optional_metric("CPU", "steal", stats.steal_percent),
             _ => Err(MetricError::MetricNotFound(format!(
                 "CPU metric {} not found",
                 metric_name
@@ -28,7 +119,7 @@ impl MetricComponent for CpuComponent {
     }
 
     fn available_metrics(&self) -> Vec<&str> {
-        vec!["usage"]
+        CPU_METRICS.to_vec()
     }
 }
 
@@ -53,6 +144,25 @@ impl MetricComponent for MemoryComponent {
             "used" => Ok(stats.used_kb as f64),
             "total" => Ok(stats.total_kb as f64),
             "usage" => Ok((stats.used_kb as f64 / stats.total_kb as f64) * 100.0),
+            "available" => optional_metric(
+                "Memory",
+                "available",
+                stats.available_kb.map(|kb| kb as f64),
+            ),
+            "cached" => optional_metric("Memory", "cached", stats.cached_kb.map(|kb| kb as f64)),
+            "swap_used" => optional_metric(
+                "Memory",
+                "swap_used",
+                stats.swap_used_kb.map(|kb| kb as f64),
+            ),
+            "swap_usage" => match (stats.swap_used_kb, stats.swap_total_kb) {
+                (Some(used), Some(total)) if total > 0 => Ok(used as f64 / total as f64 * 100.0),
+                _ => Err(MetricError::MetricNotFound(
+                    "Memory swap_usage not reported".to_string(),
+                )),
+            },
+            "swap_in" => optional_metric("Memory", "swap_in", stats.swap_in_per_sec),
+            "swap_out" => optional_metric("Memory", "swap_out", stats.swap_out_per_sec),
             _ => Err(MetricError::MetricNotFound(format!(
                 "Memory metric {} not found",
                 metric_name
@@ -61,7 +171,7 @@ impl MetricComponent for MemoryComponent {
     }
 
     fn available_metrics(&self) -> Vec<&str> {
-        vec!["used", "total", "usage"]
+        MEMORY_METRICS.to_vec()
     }
 }
 
@@ -92,9 +202,32 @@ impl MetricComponent for DiskComponent {
             .ok_or_else(|| MetricError::ComponentNotFound("Main disk (/) not found".to_string()))?;
 
         match metric_name {
-            "used" => Ok(main_disk.used_space as f64),
-            "total" => Ok(main_disk.total_space as f64),
-            "usage" => Ok((main_disk.used_space as f64 / main_disk.total_space as f64) * 100.0),
+            "used" | "total" | "usage" => {
+                // GB as rules were always written in, from the exact sizes where reported
+                let (total, used) = units::disk_bytes(&main_disk);
+                Ok(match metric_name {
+                    "used" => used as f64 / units::GB,
+                    "total" => total as f64 / units::GB,
+                    _ => used as f64 / total as f64 * 100.0,
+                })
+            }
+            "read" => Ok(main_disk.read_bytes / 1024.0 / 1024.0),
+            "write" => Ok(main_disk.write_bytes / 1024.0 / 1024.0),
+            "read_iops" => optional_metric("Disk", "read_iops", main_disk.read_iops),
+            "write_iops" => optional_metric("Disk", "write_iops", main_disk.write_iops),
+            "inodes_used" => optional_metric(
+                "Disk",
+                "inodes_used",
+                main_disk.inodes_used.map(|n| n as f64),
+            ),
+            "inodes_usage" => optional_metric(
+                "Disk",
+                "inodes_usage",
+                main_disk
+                    .inodes_total
+                    .zip(main_disk.inodes_used)
+                    .map(|(total, used)| (used as f64 / total as f64) * 100.0),
+            ),
             _ => Err(MetricError::MetricNotFound(format!(
                 "Disk metric {} not found",
                 metric_name
@@ -103,7 +236,7 @@ impl MetricComponent for DiskComponent {
     }
 
     fn available_metrics(&self) -> Vec<&str> {
-        vec!["used", "total", "usage"]
+        DISK_METRICS.to_vec()
     }
 }
 
@@ -136,7 +269,7 @@ impl MetricComponent for LoadComponent {
     }
 
     fn available_metrics(&self) -> Vec<&str> {
-        vec!["one", "five", "fifteen"]
+        LOAD_METRICS.to_vec()
     }
 }
 
@@ -158,8 +291,9 @@ impl MetricComponent for NetworkComponent {
     async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
         let stats = self.stats.read().await;
         match metric_name {
-            "in" => Ok(stats.r#in as f64),
-            "out" => Ok(stats.out as f64),
+            // MB/s, with fractions for agents that report bytes/s
+            "in" => Ok(units::network_rates(&stats).0 / units::MB),
+            "out" => Ok(units::network_rates(&stats).1 / units::MB),
             _ => Err(MetricError::MetricNotFound(format!(
                 "Network metric {} not found",
                 metric_name
@@ -168,6 +302,299 @@ impl MetricComponent for NetworkComponent {
     }
 
     fn available_metrics(&self) -> Vec<&str> {
-        vec!["in", "out"]
+        NETWORK_METRICS.to_vec()
+    }
+}
+
+// TLS Component Implementation
+pub struct TlsComponent {
+    expiry_days: f64,
+}
+
+impl TlsComponent {
+    pub fn new(expiry_days: f64) -> Self {
+        Self { expiry_days }
+    }
+}
+
+#[async_trait]
+impl MetricComponent for TlsComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        match metric_name {
+            "expiry_days" => Ok(self.expiry_days),
+            _ => Err(MetricError::MetricNotFound(format!(
+                "TLS metric {} not found",
+                metric_name
+            ))),
+        }
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        TLS_METRICS.to_vec()
+    }
+}
+
+// Agent Component Implementation
+// Registered by the offline monitor for systems that stopped reporting, and by reports that
+// carried the agent's send time, with the clock skew measured on their arrival.
+pub struct AgentComponent {
+    offline_minutes: f64,
+    clock_skew_seconds: Option<f64>,
+}
+
+impl AgentComponent {
+    pub fn new(offline_minutes: f64) -> Self {
+        Self {
+            offline_minutes,
+            clock_skew_seconds: None,
+        }
+    }
+
+    /// A system that just reported, `clock_skew_seconds` is how far off its clock is either way.
+    pub fn reporting(clock_skew_ms: i64) -> Self {
+        Self {
+            offline_minutes: 0.0,
+            clock_skew_seconds: Some(clock_skew_ms.unsigned_abs() as f64 / 1000.0),
+        }
+    }
+}
+
+#[async_trait]
+impl MetricComponent for AgentComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        match metric_name {
+            "offline_minutes" => Ok(self.offline_minutes),
+            "clock_skew_seconds" => optional_metric("agent", metric_name, self.clock_skew_seconds),
+            _ => Err(MetricError::MetricNotFound(format!(
+                "Agent metric {} not found",
+                metric_name
+            ))),
+        }
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        AGENT_METRICS.to_vec()
+    }
+}
+
+// Probe Component Implementation
+pub struct ProbeComponent {
+    results: Vec<ProbeResult>,
+}
+
+impl ProbeComponent {
+    pub fn new(results: Vec<ProbeResult>) -> Self {
+        Self { results }
+    }
+}
+
+#[async_trait]
+impl MetricComponent for ProbeComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        match metric_name {
+            "up" => Ok(self.results.iter().filter(|p| p.up).count() as f64),
+            "down" => Ok(self.results.iter().filter(|p| !p.up).count() as f64),
+            "max_latency" => optional_metric(
+                "Probe",
+                "max_latency",
+                self.results
+                    .iter()
+                    .filter_map(|p| p.latency_ms)
+                    .max_by(|a, b| a.total_cmp(b)),
+            ),
+            _ => Err(MetricError::MetricNotFound(format!(
+                "Probe metric {} not found",
+                metric_name
+            ))),
+        }
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        PROBE_METRICS.to_vec()
+    }
+}
+
+// Process Component Implementation
+pub struct ProcessComponent {
+    stats: ProcessStats,
+}
+
+impl ProcessComponent {
+    pub fn new(stats: ProcessStats) -> Self {
+        Self { stats }
+    }
+}
+
+#[async_trait]
+impl MetricComponent for ProcessComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        match metric_name {
+            "total" => Ok(self.stats.total as f64),
+            "threads" => Ok(self.stats.threads as f64),
+            "running" => Ok(self.stats.running as f64),
+            "zombie" => Ok(self.stats.zombie as f64),
+            _ => Err(MetricError::MetricNotFound(format!(
+                "Process metric {} not found",
+                metric_name
+            ))),
+        }
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        PROCESS_METRICS.to_vec()
+    }
+}
+
+// Kernel Component Implementation
+pub struct KernelComponent {
+    stats: KernelStats,
+}
+
+impl KernelComponent {
+    pub fn new(stats: KernelStats) -> Self {
+        Self { stats }
+    }
+}
+
+#[async_trait]
+impl MetricComponent for KernelComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        match metric_name {
+            "context_switches" => Ok(self.stats.context_switches_per_sec),
+            "interrupts" => Ok(self.stats.interrupts_per_sec),
+            "procs_blocked" => Ok(self.stats.procs_blocked as f64),
+            "entropy" => optional_metric(
+                "Kernel",
+                "entropy",
+                self.stats.entropy_avail.map(|bits| bits as f64),
+            ),
+            _ => Err(MetricError::MetricNotFound(format!(
+                "Kernel metric {} not found",
+                metric_name
+            ))),
+        }
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        KERNEL_METRICS.to_vec()
+    }
+}
+
+// SMART Component Implementation
+// Drive health, the worst drive counts: the highest counter or temperature, the number of
+// drives failing their self-assessment. `<counter>.delta_<window>` is the counter's growth since
+// the drive's oldest reading within the window, 0 for drives without an older reading.
+pub struct SmartComponent {
+    devices: Vec<SmartDevice>,
+    /// Oldest reading of each drive per window, by drive (see `smart_drive`)
+    baselines: HashMap<&'static str, HashMap<String, SmartDevice>>,
+}
+
+/// Drives keep their serial when device names move around, the name is the fallback.
+pub fn smart_drive(device: &SmartDevice) -> &str {
+    if device.serial.is_empty() {
+        &device.device
+    } else {
+        &device.serial
+    }
+}
+
+fn smart_counter(device: &SmartDevice, counter: &str) -> Option<u64> {
+    match counter {
+        "reallocated_sectors" => device.reallocated_sectors,
+        "pending_sectors" => device.pending_sectors,
+        "uncorrectable_sectors" => device.uncorrectable_sectors,
+        "media_errors" => device.media_errors,
+        _ => None,
+    }
+}
+
+impl SmartComponent {
+    pub fn new(
+        devices: Vec<SmartDevice>,
+        baselines: HashMap<&'static str, Vec<SmartDevice>>,
+    ) -> Self {
+        let baselines = baselines
+            .into_iter()
+            .map(|(window, readings)| {
+                let by_drive = readings
+                    .into_iter()
+                    .map(|d| (smart_drive(&d).to_string(), d))
+                    .collect();
+                (window, by_drive)
+            })
+            .collect();
+        Self { devices, baselines }
+    }
+
+    fn worst(
+        &self,
+        metric: &str,
+        value: impl Fn(&SmartDevice) -> Option<f64>,
+    ) -> Result<f64, MetricError> {
+        let worst = self.devices.iter().filter_map(value).reduce(f64::max);
+        optional_metric("SMART", metric, worst)
+    }
+}
+
+#[async_trait]
+impl MetricComponent for SmartComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        match metric_name {
+            "failed" => Ok(self.devices.iter().filter(|d| !d.passed).count() as f64),
+            "percentage_used" => self.worst(metric_name, |d| d.percentage_used.map(f64::from)),
+            "temperature" => self.worst(metric_name, |d| d.temperature),
+            _ if SMART_METRICS.contains(&metric_name) => {
+                let (counter, window) = metric_name
+                    .split_once('.')
+                    .map_or((metric_name, None), |(c, w)| (c, Some(w)));
+                let Some(window) = window else {
+                    return self
+                        .worst(metric_name, |d| smart_counter(d, counter).map(|v| v as f64));
+                };
+                let baselines = self.baselines.get(window);
+                self.worst(metric_name, |d| {
+                    let current = smart_counter(d, counter)?;
+                    let baseline = baselines
+                        .and_then(|b| b.get(smart_drive(d)))
+                        .and_then(|b| smart_counter(b, counter))
+                        .unwrap_or(current);
+                    Some(current as f64 - baseline as f64)
+                })
+            }
+            _ => Err(MetricError::MetricNotFound(format!(
+                "SMART metric {} not found",
+                metric_name
+            ))),
+        }
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        SMART_METRICS.to_vec()
+    }
+}
+
+// Custom Component Implementation
+// Latest values of ad-hoc metrics posted over HTTP, addressed as `custom.<name>` in rules.
+pub struct CustomComponent {
+    values: HashMap<String, f64>,
+}
+
+impl CustomComponent {
+    pub fn new(values: HashMap<String, f64>) -> Self {
+        Self { values }
+    }
+}
+
+#[async_trait]
+impl MetricComponent for CustomComponent {
+    async fn get_metric(&self, metric_name: &str) -> Result<f64, MetricError> {
+        self.values.get(metric_name).copied().ok_or_else(|| {
+            MetricError::MetricNotFound(format!("Custom metric {} not found", metric_name))
+        })
+    }
+
+    fn available_metrics(&self) -> Vec<&str> {
+        self.values.keys().map(String::as_str).collect()
     }
 }
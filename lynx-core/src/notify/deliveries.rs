@@ -0,0 +1,111 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/*
+ * Notification deliveries
+ * Every attempt to hand a triggered rule to a notifier is kept in notification_deliveries,
+ * with the channel, the time and the error if it failed, so operators can tell whether and
+ * where an alert went out. Errors are stored with the notifier's URL cut out, webhook URLs
+ * carry their token.
+ */
+
+pub const DEFAULT_LIMIT: u32 = 50;
+pub const MAX_LIMIT: u32 = 500;
+/// Errors are cut to this many characters.
+pub const MAX_ERROR_CHARS: usize = 1024;
+
+const INSERT_DELIVERY: &str = "INSERT INTO notification_deliveries \
+     (alert, system, rule, notifier, channel, success, error) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7)";
+
+const GET_ALERT_DELIVERIES: &str =
+    "SELECT id, time, alert, system, rule, notifier, channel, success, error \
+     FROM notification_deliveries WHERE alert = $1 ORDER BY time, id";
+
+const GET_SYSTEM_DELIVERIES: &str =
+    "SELECT id, time, alert, system, rule, notifier, channel, success, error \
+     FROM notification_deliveries WHERE system = $1 AND (NOT $2 OR NOT success) \
+     ORDER BY time DESC, id DESC LIMIT $3";
+
+/// One attempt as the dispatcher records it.
+#[derive(Debug, Clone)]
+pub struct Attempt<'a> {
+    /// alert_history row of the alert, None for notices and when it couldn't be inserted
+    pub alert: Option<i32>,
+    pub system: i32,
+    pub rule: &'a str,
+    pub notifier: i32,
+    /// `discord`, `email`, `webhook` or `unknown` when the notifier could not be set up
+    pub channel: &'a str,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Delivery {
+    pub id: i32,
+    pub time: DateTime<Utc>,
+    pub alert: Option<i32>,
+    pub system: i32,
+    pub rule: String,
+    pub notifier: Option<i32>,
+    pub channel: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Query string of `GET /systems/{id}/deliveries`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct DeliveryQuery {
+    /// Only failed attempts
+    #[serde(default)]
+    pub failed: bool,
+    pub limit: Option<u32>,
+}
+
+impl DeliveryQuery {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+pub async fn record(pool: &PgPool, attempt: &Attempt<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query(INSERT_DELIVERY)
+        .bind(attempt.alert)
+        .bind(attempt.system)
+        .bind(attempt.rule)
+        .bind(attempt.notifier)
+        .bind(attempt.channel)
+        .bind(attempt.error.is_none())
+        .bind(
+            attempt
+                .error
+                .as_ref()
+                .map(|e| e.chars().take(MAX_ERROR_CHARS).collect::<String>()),
+        )
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Attempts for one alert_history row, in the order they were made.
+pub async fn for_alert(pool: &PgPool, alert_id: i32) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as::<_, Delivery>(GET_ALERT_DELIVERIES)
+        .bind(alert_id)
+        .fetch_all(pool)
+        .await
+}
+
+/// Latest attempts for a system's alerts, built-in rules included, newest first.
+pub async fn for_system(
+    pool: &PgPool,
+    system_id: i32,
+    query: &DeliveryQuery,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as::<_, Delivery>(GET_SYSTEM_DELIVERIES)
+        .bind(system_id)
+        .bind(query.failed)
+        .bind(query.limit() as i64)
+        .fetch_all(pool)
+        .await
+}
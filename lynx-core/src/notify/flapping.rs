@@ -0,0 +1,168 @@
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/*
+ * Flap detection
+ * Every evaluation of a rule for a system is an observation; a change between firing and not
+ * firing is a transition. A rule with more than `max_transitions` transitions within `window`
+ * is flapping: its notifiers get one notice saying so and no alerts until it held its state
+ * for a whole window. Alert history is still written meanwhile. The state lives in the hub's
+ * memory, hubs sharing state each track the systems they evaluate.
+ */
+
+#[derive(Clone, Debug)]
+pub struct FlapOptions {
+    /// Transitions within `window` a rule may make before it counts as flapping, 0 disables
+    pub max_transitions: usize,
+    pub window: Duration,
+}
+
+impl Default for FlapOptions {
+    fn default() -> Self {
+        Self {
+            max_transitions: 6,
+            window: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flap {
+    /// Notify as usual
+    Stable,
+    /// Crossed the limit with this observation, send the flapping notice
+    Started,
+    /// Suppress notifications
+    Flapping,
+    /// Held its state for a window, notifications resume
+    Settled,
+}
+
+struct Entry {
+    firing: bool,
+    transitions: VecDeque<Instant>,
+    flapping_since: Option<DateTime<Utc>>,
+}
+
+/// A flapping rule as `GET /alerts/flapping` lists it.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct FlappingRule {
+    pub system_id: i32,
+    pub rule: String,
+    /// Transitions within the window
+    pub transitions: usize,
+    pub firing: bool,
+    pub since: DateTime<Utc>,
+}
+
+pub struct FlapDetector {
+    options: RwLock<FlapOptions>,
+    entries: DashMap<(i32, String), Entry>,
+}
+
+lazy_static::lazy_static! {
+    pub static ref FLAPPING: FlapDetector = FlapDetector::new(FlapOptions::default());
+}
+
+impl FlapDetector {
+    pub fn new(options: FlapOptions) -> Self {
+        Self {
+            options: RwLock::new(options),
+            entries: DashMap::new(),
+        }
+    }
+
+    /// Replaces the limits, set once on startup.
+    pub fn configure(&self, options: FlapOptions) {
+        *self.options.write().unwrap_or_else(|e| e.into_inner()) = options;
+    }
+
+    pub fn options(&self) -> FlapOptions {
+        self.options
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn observe(&self, system_id: i32, rule: &str, firing: bool) -> Flap {
+        self.observe_at(system_id, rule, firing, Instant::now())
+    }
+
+    pub fn observe_at(&self, system_id: i32, rule: &str, firing: bool, now: Instant) -> Flap {
+        let options = self.options();
+        if options.max_transitions == 0 {
+            return Flap::Stable;
+        }
+        let mut entry = self
+            .entries
+            .entry((system_id, rule.to_string()))
+            .or_insert_with(|| Entry {
+                firing: false,
+                transitions: VecDeque::new(),
+                flapping_since: None,
+            });
+        if entry.firing != firing {
+            entry.firing = firing;
+            entry.transitions.push_back(now);
+        }
+        while entry
+            .transitions
+            .front()
+            .is_some_and(|t| now.duration_since(*t) > options.window)
+        {
+            entry.transitions.pop_front();
+        }
+
+        match entry.flapping_since {
+            None if entry.transitions.len() > options.max_transitions => {
+                entry.flapping_since = Some(Utc::now());
+                Flap::Started
+            }
+            None => Flap::Stable,
+            Some(_) if entry.transitions.is_empty() => {
+                entry.flapping_since = None;
+                Flap::Settled
+            }
+            Some(_) => Flap::Flapping,
+        }
+    }
+
+    /*
+     * flapping
+     * Rules flapping right now, by system and rule name. Rules that were not evaluated since
+     * their last transition left the window (the system went quiet) are left out, they settle
+     * on their next evaluation.
+     */
+    pub fn flapping(&self) -> Vec<FlappingRule> {
+        let window = self.options().window;
+        let now = Instant::now();
+        let mut rules: Vec<FlappingRule> = self
+            .entries
+            .iter()
+            .filter_map(|e| {
+                let since = e.flapping_since?;
+                let transitions = e
+                    .transitions
+                    .iter()
+                    .filter(|t| now.duration_since(**t) <= window)
+                    .count();
+                if transitions == 0 {
+                    return None;
+                }
+                Some(FlappingRule {
+                    system_id: e.key().0,
+                    rule: e.key().1.clone(),
+                    transitions,
+                    firing: e.firing,
+                    since,
+                })
+            })
+            .collect();
+        rules.sort_by(|a, b| (a.system_id, &a.rule).cmp(&(b.system_id, &b.rule)));
+        rules
+    }
+}
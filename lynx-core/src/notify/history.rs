@@ -0,0 +1,61 @@
+use super::Rule;
+use crate::queries::alert_queries;
+use log::info;
+use sqlx::PgPool;
+
+/*
+ * Alert history
+ * Every triggered rule gets an alert_history row, which GetSystemStatus and the overview count
+ * as active for 30 minutes and delivery attempts point at. Rules from alert_rules are referenced
+ * by `alert`; built-in rules have no row there and are stored by name in `rule` instead.
+ */
+
+/// Held by the hub changing the table, hubs starting meanwhile wait for it.
+const BUILTIN_HISTORY_LOCK_ID: i64 = 0x6c79_6e78_0003;
+
+const HAS_RULE_COLUMN: &str = "SELECT EXISTS (SELECT 1 FROM information_schema.columns \
+     WHERE table_schema = current_schema() AND table_name = 'alert_history' \
+     AND column_name = 'rule')";
+
+const ADD_RULE_COLUMN: &str = "ALTER TABLE alert_history ALTER COLUMN alert DROP NOT NULL, \
+     ADD COLUMN IF NOT EXISTS rule text";
+
+/// Inserts the row of a triggered rule and returns its id.
+pub async fn record(pool: &PgPool, system_id: i32, rule: &Rule) -> Result<i32, sqlx::Error> {
+    if rule.builtin {
+        sqlx::query_scalar(alert_queries::INSERT_BUILTIN_ALERT_HISTORY)
+            .bind(system_id)
+            .bind(&rule.name)
+            .fetch_one(pool)
+            .await
+    } else {
+        sqlx::query_scalar(alert_queries::INSERT_ALERT_HISTORY)
+            .bind(system_id)
+            .bind(rule.id)
+            .fetch_one(pool)
+            .await
+    }
+}
+
+/*
+ * migrate
+ * Databases from before built-in rules were recorded get the nullable `alert` and the `rule`
+ * column on start. Runs in one transaction.
+ */
+pub async fn migrate(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(BUILTIN_HISTORY_LOCK_ID)
+        .execute(&mut *tx)
+        .await?;
+    let migrated: bool = sqlx::query_scalar(HAS_RULE_COLUMN)
+        .fetch_one(&mut *tx)
+        .await?;
+    if migrated {
+        return tx.commit().await;
+    }
+    sqlx::query(ADD_RULE_COLUMN).execute(&mut *tx).await?;
+    tx.commit().await?;
+    info!("[hub] Added built-in rules to the alert history");
+    Ok(())
+}
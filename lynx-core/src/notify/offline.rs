@@ -0,0 +1,87 @@
+use super::{AlertState, NotificationProcessor};
+use crate::config::Secrets;
+use crate::events::Events;
+use crate::leader::Leadership;
+use crate::services::status::ONLINE_THRESHOLD;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use sqlx::{PgPool, Row};
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::interval;
+
+/*
+ * Offline monitor
+ * Rules are evaluated as reports come in, so a system that stopped reporting never triggers
+ * anything. Once a minute the leader evaluates the rules on the `agent` component, e.g.
+ * `agent.offline_minutes > 5`, for every active system that missed its reports. Systems that
+ * never reported and decommissioned ones are left out.
+ */
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// A system that stays offline is notified about again after this long.
+const REMIND_AFTER: Duration = Duration::from_secs(3600);
+
+const GET_OFFLINE_SYSTEMS: &str = "SELECT id, last_seen FROM systems \
+     WHERE active = true AND decommissioned IS NULL \
+     AND last_seen < NOW() - ($1 * INTERVAL '1 second')";
+
+/// Minutes between `last_seen` and `now`.
+pub fn offline_minutes(last_seen: DateTime<Utc>, now: DateTime<Utc>) -> f64 {
+    (now - last_seen).num_seconds().max(0) as f64 / 60.0
+}
+
+pub async fn run_offline_monitor(
+    pool: PgPool,
+    secrets: Secrets,
+    events: Option<Events>,
+    leadership: Leadership,
+) {
+    let mut tick = interval(CHECK_INTERVAL);
+    // a system that stays offline is reminded once an hour
+    let alerts = AlertState::local(REMIND_AFTER);
+    let mut offline: HashSet<i32> = HashSet::new();
+    let mut processor = NotificationProcessor::new(pool.clone(), secrets, events);
+    loop {
+        tick.tick().await;
+        if !leadership.is_leader() {
+            continue;
+        }
+        processor.expire().await;
+        alerts.cleanup().await;
+        let rows = match sqlx::query(GET_OFFLINE_SYSTEMS)
+            .bind(ONLINE_THRESHOLD.as_secs() as i64)
+            .fetch_all(&pool)
+            .await
+        {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Failed to load offline systems: {e}");
+                continue;
+            }
+        };
+
+        let now = Utc::now();
+        let still_offline: HashSet<i32> = rows.iter().map(|row| row.get("id")).collect();
+        for system_id in offline.difference(&still_offline) {
+            alerts.release(*system_id).await;
+        }
+        offline = still_offline;
+        for row in rows {
+            let system_id: i32 = row.get("id");
+            let last_seen: DateTime<Utc> = row.get("last_seen");
+
+            match processor
+                .process_offline(offline_minutes(last_seen, now), system_id, &alerts)
+                .await
+            {
+                Ok(fired) => {
+                    for rule in fired {
+                        info!("System {} is offline, rule '{}' fired", system_id, rule);
+                    }
+                }
+                Err(e) => error!("Failed to evaluate offline rules for system {system_id}: {e}"),
+            }
+        }
+    }
+}
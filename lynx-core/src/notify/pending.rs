@@ -0,0 +1,47 @@
+use dashmap::DashMap;
+use std::time::{Duration, Instant};
+
+/*
+ * Pending rules
+ * A rule with a hold (`alert_rules.for_secs`) only fires once its conditions were met on every
+ * evaluation for that long, e.g. memory above 95% for 5 minutes instead of a single spike. One
+ * evaluation that doesn't meet them starts the wait over. Like flap detection the state lives
+ * in the hub's memory, a restart starts every wait over.
+ */
+
+#[derive(Default)]
+pub struct PendingRules {
+    /// First evaluation of the current run that met the conditions
+    since: DashMap<(i32, String), Instant>,
+}
+
+lazy_static::lazy_static! {
+    pub static ref PENDING: PendingRules = PendingRules::default();
+}
+
+impl PendingRules {
+    /// Whether the rule fires, `firing` being the result of this evaluation.
+    pub fn observe(&self, system_id: i32, rule: &str, firing: bool, hold: Duration) -> bool {
+        self.observe_at(system_id, rule, firing, hold, Instant::now())
+    }
+
+    pub fn observe_at(
+        &self,
+        system_id: i32,
+        rule: &str,
+        firing: bool,
+        hold: Duration,
+        now: Instant,
+    ) -> bool {
+        let key = (system_id, rule.to_string());
+        if !firing {
+            self.since.remove(&key);
+            return false;
+        }
+        if hold.is_zero() {
+            return true;
+        }
+        let since = *self.since.entry(key).or_insert(now);
+        now.duration_since(since) >= hold
+    }
+}
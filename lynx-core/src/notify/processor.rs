@@ -1,149 +1,377 @@
+use super::rule_cache::{SystemRules, RULES};
 use super::*;
-use crate::proto::monitor::MetricsRequest;
+use crate::config::Secrets;
+use crate::events::Events;
+use crate::proto::monitor::{MetricsRequest, SmartDevice};
+use crate::services::maintenance;
 use log::{error, info, warn};
 use sqlx::{PgPool, Row};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 
+/// A notifier of a rule, `url` may be a secret reference.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    pub id: i32,
+    pub url: String,
+}
+
+/// Resolved services are rebuilt after this, so rotated secrets are picked up.
+const SERVICE_TTL: Duration = Duration::from_secs(600);
+/// Components not reported again within this are dropped from a system's registry.
+pub const COMPONENT_MAX_AGE: Duration = Duration::from_secs(600);
+
+/*
+ * NotificationProcessor
+ * Lives as long as the notification worker owning it (see worker), so the registry of each of
+ * its systems keeps the components of earlier samples and notification services are set up
+ * once instead of for every report.
+ */
 pub struct NotificationProcessor {
-    registry: MetricRegistry,
-    services: Arc<Mutex<HashMap<String, NotificationServiceType>>>,
+    registries: HashMap<i32, Arc<MetricRegistry>>,
+    services: Arc<Mutex<HashMap<String, (NotificationServiceType, Instant)>>>,
     pool: PgPool,
+    secrets: Secrets,
+    /// Triggered rules are also published here when event publishing is configured
+    events: Option<Events>,
+}
+
+/// The components a MetricsRequest carries.
+fn report_components(metrics: &MetricsRequest) -> Vec<(&'static str, Box<dyn MetricComponent>)> {
+    let mut components: Vec<(&'static str, Box<dyn MetricComponent>)> = Vec::new();
+    if let Some(cpu_stats) = &metrics.cpu_stats {
+        components.push(("cpu", Box::new(CpuComponent::new(cpu_stats.clone()))));
+    }
+    if let Some(memory_stats) = &metrics.memory_stats {
+        components.push((
+            "memory",
+            Box::new(MemoryComponent::new(memory_stats.clone())),
+        ));
+    }
+    if let Some(load_avg) = &metrics.load_average {
+        components.push(("load", Box::new(LoadComponent::new(load_avg.clone()))));
+    }
+    if !metrics.disk_stats.is_empty() {
+        components.push((
+            "disk",
+            Box::new(DiskComponent::new(metrics.disk_stats.clone())),
+        ));
+    }
+    if let Some(expiry_days) = metrics.cert_expiry_days {
+        components.push(("tls", Box::new(TlsComponent::new(expiry_days))));
+    }
+    if let Some(process_stats) = metrics.process_stats {
+        components.push(("processes", Box::new(ProcessComponent::new(process_stats))));
+    }
+    if !metrics.probe_results.is_empty() {
+        components.push((
+            "probes",
+            Box::new(ProbeComponent::new(metrics.probe_results.clone())),
+        ));
+    }
+    if let Some(kernel_stats) = metrics.kernel_stats {
+        components.push(("kernel", Box::new(KernelComponent::new(kernel_stats))));
+    }
+    if let Some(network_stats) = &metrics.network_stats {
+        components.push((
+            "network",
+            Box::new(NetworkComponent::new(network_stats.clone())),
+        ));
+    }
+    components
 }
 
 impl NotificationProcessor {
-    pub fn new(pool: PgPool) -> Self {
+    pub fn new(pool: PgPool, secrets: Secrets, events: Option<Events>) -> Self {
         Self {
-            registry: MetricRegistry::new(),
+            registries: HashMap::new(),
             services: Arc::new(Mutex::new(HashMap::new())),
             pool,
+            secrets,
+            events,
         }
     }
 
+    fn registry(&mut self, system_id: i32) -> Arc<MetricRegistry> {
+        self.registries.entry(system_id).or_default().clone()
+    }
+
     /*
      * register_metrics
      * Registers available metric components used for alert logic based on the incoming
-     * MetricsRequest.
+     * MetricsRequest and the clock skew measured on its arrival, returns the names of the
+     * components it registered.
      */
-    pub async fn register_metrics(&self, metrics: &MetricsRequest) {
-        if let Some(cpu_stats) = &metrics.cpu_stats {
-            self.registry
-                .register_component(
-                    "cpu".to_string(),
-                    Box::new(CpuComponent::new(cpu_stats.clone())),
-                )
+    pub async fn register_metrics(
+        &mut self,
+        metrics: &MetricsRequest,
+        clock_skew_ms: Option<i64>,
+        system_id: i32,
+    ) -> Vec<&'static str> {
+        let registry = self.registry(system_id);
+        let mut registered = Vec::new();
+        for (name, component) in report_components(metrics) {
+            registry
+                .register_component(name.to_string(), component)
                 .await;
+            registered.push(name);
         }
-
-        if let Some(memory_stats) = &metrics.memory_stats {
-            self.registry
-                .register_component(
-                    "memory".to_string(),
-                    Box::new(MemoryComponent::new(memory_stats.clone())),
-                )
+        if !metrics.smart_devices.is_empty() {
+            let baselines = self
+                .load_smart_baselines(system_id)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("[notify] Failed to load SMART history for system {system_id}: {e}");
+                    HashMap::new()
+                });
+            let smart = SmartComponent::new(metrics.smart_devices.clone(), baselines);
+            registry
+                .register_component("smart".to_string(), Box::new(smart))
                 .await;
+            registered.push("smart");
         }
-
-        if let Some(load_avg) = &metrics.load_average {
-            self.registry
+        if let Some(skew_ms) = clock_skew_ms {
+            registry
                 .register_component(
-                    "load".to_string(),
-                    Box::new(LoadComponent::new(load_avg.clone())),
+                    "agent".to_string(),
+                    Box::new(AgentComponent::reporting(skew_ms)),
                 )
                 .await;
+            registered.push("agent");
         }
+        registered
+    }
 
-        if !metrics.disk_stats.is_empty() {
-            self.registry
-                .register_component(
-                    "disk".to_string(),
-                    Box::new(DiskComponent::new(metrics.disk_stats.clone())),
-                )
-                .await;
+    /// Each drive's oldest reading within every window of SMART_WINDOWS.
+    async fn load_smart_baselines(
+        &self,
+        system_id: i32,
+    ) -> Result<HashMap<&'static str, Vec<SmartDevice>>, sqlx::Error> {
+        let count = |v: Option<i64>| v.map(|v| v as u64);
+        let mut baselines = HashMap::new();
+        for &(window, hours) in SMART_WINDOWS {
+            let rows = sqlx::query(crate::queries::alert_queries::GET_SMART_BASELINES)
+                .bind(system_id)
+                .bind(hours as f64)
+                .fetch_all(&self.pool)
+                .await?;
+            let readings = rows
+                .iter()
+                .map(|row| SmartDevice {
+                    device: row.get("device"),
+                    serial: row.get("serial"),
+                    passed: row.get("passed"),
+                    reallocated_sectors: count(row.get("reallocated_sectors")),
+                    pending_sectors: count(row.get("pending_sectors")),
+                    uncorrectable_sectors: count(row.get("uncorrectable_sectors")),
+                    media_errors: count(row.get("media_errors")),
+                    ..Default::default()
+                })
+                .collect();
+            baselines.insert(window, readings);
         }
+        Ok(baselines)
+    }
 
-        if let Some(network_stats) = &metrics.network_stats {
-            self.registry
-                .register_component(
-                    "network".to_string(),
-                    Box::new(NetworkComponent::new(network_stats.clone())),
-                )
-                .await;
+    /*
+     * expire
+     * Drops components older than COMPONENT_MAX_AGE, the registries of systems left without
+     * any, and notification services older than SERVICE_TTL.
+     */
+    pub async fn expire(&mut self) {
+        let mut idle = Vec::new();
+        for (system_id, registry) in &self.registries {
+            if !registry.expire(COMPONENT_MAX_AGE).await {
+                idle.push(*system_id);
+            }
         }
+        for system_id in idle {
+            self.registries.remove(&system_id);
+        }
+        self.services
+            .lock()
+            .await
+            .retain(|_, (_, created)| created.elapsed() < SERVICE_TTL);
     }
 
     /*
-     * load_rules
-     * Combines alert rules with their associated notifiers from the database for a given system.
+     * load_rule_ids
+     * Rules assigned to the system in alert_systems plus the rules whose tag target matches the
+     * system's tags.
      */
-    async fn load_rules(&self, system_id: i32) -> Result<Vec<(Rule, Vec<String>)>, sqlx::Error> {
-        let alerts = sqlx::query(crate::queries::alert_queries::GET_ALERT_SYSTEMS)
+    async fn load_rule_ids(&self, system_id: i32) -> Result<Vec<i32>, sqlx::Error> {
+        let mut rule_ids: Vec<i32> = sqlx::query(crate::queries::alert_queries::GET_ALERT_SYSTEMS)
             .bind(system_id)
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(|row| row.get("rule_id"))
+            .collect();
+
+        let targeted = sqlx::query(crate::queries::alert_queries::GET_TARGETED_RULES)
             .fetch_all(&self.pool)
             .await?;
+        if targeted.is_empty() {
+            return Ok(rule_ids);
+        }
 
-        let mut rules_with_notifiers = Vec::new();
+        let tags: Option<serde_json::Value> =
+            sqlx::query(crate::queries::alert_queries::GET_SYSTEM_TAGS)
+                .bind(system_id)
+                .fetch_optional(&self.pool)
+                .await?
+                .map(|row| row.get("tags"));
+        let tags: HashMap<String, String> = tags
+            .and_then(|tags| serde_json::from_value(tags).ok())
+            .unwrap_or_default();
 
-        for alert in alerts {
-            let rule_id: i32 = alert.get("rule_id");
-            let row = sqlx::query(crate::queries::alert_queries::GET_ALERT_RULES)
-                .bind(rule_id)
-                .fetch_one(&self.pool)
-                .await?;
+        for row in targeted {
+            let rule_id: i32 = row.get("id");
+            let target: String = row.get("target");
+            match target.parse::<TagSelector>() {
+                Ok(selector) if selector.matches(&tags) && !rule_ids.contains(&rule_id) => {
+                    rule_ids.push(rule_id)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to parse target of rule {}: {}", rule_id, e),
+            }
+        }
+        Ok(rule_ids)
+    }
+
+    /*
+     * load_rules
+     * The system's rules with their notifiers, from the rule cache (see rule_cache) or else from
+     * the database: the rule ids, then the rules and all of their notifiers in one query each.
+     */
+    async fn load_rules(&self, system_id: i32) -> Result<Arc<SystemRules>, sqlx::Error> {
+        if let Some(rules) = RULES.rules(system_id) {
+            return Ok(rules);
+        }
+        let version = RULES.version();
+        let rule_ids = self.load_rule_ids(system_id).await?;
+        if rule_ids.is_empty() {
+            return Ok(RULES.put_rules(system_id, version, Vec::new()));
+        }
 
+        let mut notifiers: HashMap<i32, Vec<Notifier>> = HashMap::new();
+        for row in sqlx::query(crate::queries::alert_queries::GET_RULE_NOTIFIERS)
+            .bind(&rule_ids)
+            .fetch_all(&self.pool)
+            .await?
+        {
+            notifiers
+                .entry(row.get("rule_id"))
+                .or_default()
+                .push(Notifier {
+                    id: row.get("id"),
+                    url: row.get("value"),
+                });
+        }
+
+        let mut rules: HashMap<i32, Rule> = HashMap::new();
+        for row in sqlx::query(crate::queries::alert_queries::GET_ALERT_RULES)
+            .bind(&rule_ids)
+            .fetch_all(&self.pool)
+            .await?
+        {
+            let rule_id: i32 = row.get("id");
             let name: String = row.get("name");
             let enabled: bool = row.get("active");
             let expression: String = row.get("expression");
             let severity: String = row.get("severity");
             let description: String = row.get("description");
+            let for_secs: i32 = row.get("for_secs");
+            let annotations: serde_json::Value = row.get("annotations");
 
             // Parse the rule expression
             let conditions = match RuleParser::parse_expression(&expression) {
                 Ok(conditions) => conditions,
                 Err(e) => {
-                    warn!("Failed to parse rule {}: {}", name, e);
+                    warn!(
+                        "[notify] Rule {} ({}) skipped: {} in {:?}",
+                        rule_id, name, e, expression
+                    );
                     continue;
                 }
             };
 
-            let rule = Rule {
-                id: rule_id,
-                name,
-                enabled,
-                description,
-                severity,
-                conditions,
-            };
-
-            // Get notifiers for this rule
-            let notifiers = sqlx::query(crate::queries::alert_queries::GET_ALERT_NOTIFIERS)
-                .bind(rule_id)
-                .fetch_all(&self.pool)
-                .await?;
+            rules.insert(
+                rule_id,
+                Rule {
+                    id: rule_id,
+                    builtin: false,
+                    name,
+                    enabled,
+                    description,
+                    severity,
+                    conditions,
+                    hold: Duration::from_secs(for_secs.max(0) as u64),
+                    annotations: parse_annotations(&annotations),
+                },
+            );
+        }
 
-            let mut notifier_urls = Vec::new();
-            for notifier in notifiers {
-                let notifier_id: i32 = notifier.get("notifier_id");
-                let notifier_row = sqlx::query(crate::queries::alert_queries::GET_NOTIFIERS)
-                    .bind(notifier_id)
-                    .fetch_one(&self.pool)
-                    .await?;
-
-                let notifier_type: String = notifier_row.get("type");
-                let notifier_value: String = notifier_row.get("value");
-                notifier_urls.push(format!("{}", notifier_value));
-            }
+        // in the order of the ids, assigned rules first
+        let rules_with_notifiers = rule_ids
+            .iter()
+            .filter_map(|id| {
+                let rule = rules.remove(id)?;
+                Some((rule, notifiers.remove(id).unwrap_or_default()))
+            })
+            .collect();
+        Ok(RULES.put_rules(system_id, version, rules_with_notifiers))
+    }
 
-            rules_with_notifiers.push((rule, notifier_urls));
+    /*
+     * load_builtin_rules
+     * Built-in rules only apply when the agent reports the metrics they need, and notify the
+     * notifiers owned by the system's admin.
+     */
+    async fn load_builtin_rules(
+        &self,
+        metrics: &MetricsRequest,
+        system_id: i32,
+    ) -> Result<Vec<(Rule, Vec<Notifier>)>, sqlx::Error> {
+        let mut rules = Vec::new();
+        if metrics.cert_expiry_days.is_some() {
+            rules.push(Rule::builtin_cert_expiry());
+        }
+        if rules.is_empty() {
+            return Ok(Vec::new());
         }
 
-        Ok(rules_with_notifiers)
+        let notifiers = match RULES.owner_notifiers(system_id) {
+            Some(notifiers) => notifiers,
+            None => {
+                let version = RULES.version();
+                let notifiers: Vec<Notifier> =
+                    sqlx::query(crate::queries::alert_queries::GET_SYSTEM_OWNER_NOTIFIERS)
+                        .bind(system_id)
+                        .fetch_all(&self.pool)
+                        .await?
+                        .iter()
+                        .map(|row| Notifier {
+                            id: row.get("id"),
+                            url: row.get("value"),
+                        })
+                        .collect();
+                RULES.put_owner_notifiers(system_id, version, notifiers)
+            }
+        };
+
+        Ok(rules
+            .into_iter()
+            .map(|r| (r, notifiers.as_ref().clone()))
+            .collect())
     }
 
     /*
      * get_or_create_service
      * Retrieves an existing notification service or creates a new one based on the provided URL.
+     * Secret references in the URL are resolved here, the cache stays keyed by the reference.
      */
     async fn get_or_create_service(
         &self,
@@ -151,84 +379,345 @@ impl NotificationProcessor {
     ) -> Result<NotificationServiceType, NotificationError> {
         let mut services = self.services.lock().await;
 
-        if !services.contains_key(url) {
-            let service = NotificationServiceType::from_url(url)?;
-            services.insert(url.to_string(), service);
+        if let Some((service, _)) = services.get(url) {
+            return Ok(service.clone());
         }
-
-        Ok(services.get(url).unwrap().clone())
+        let resolved = self
+            .secrets
+            .resolve(url)
+            .await
+            .map_err(|e| NotificationError::ConfigError(e.to_string()))?;
+        let service = NotificationServiceType::from_url(&resolved)?;
+        services.insert(url.to_string(), (service.clone(), Instant::now()));
+        Ok(service)
     }
 
     /*
      * notify::processor::process
      * Processes metrics for a given system, evaluates rules, and sends notifications if rules
-     * are triggered. Called after metrics are ingested and inserted into the database.
+     * are triggered. Called by the notification workers after metrics are inserted into the
+     * database.
      */
     pub async fn process(
-        &self,
+        &mut self,
         metrics: &MetricsRequest,
+        clock_skew_ms: Option<i64>,
         system_id: i32,
-        triggered_rules: &HashSet<String>,
+        alerts: &AlertState,
     ) -> Result<Vec<String>, Box<dyn std::error::Error + Send>> {
         // Register metrics from the request
-        self.register_metrics(metrics).await;
+        let registered = self
+            .register_metrics(metrics, clock_skew_ms, system_id)
+            .await;
+
+        let mut rules = self
+            .load_rules(system_id)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?
+            .as_ref()
+            .clone();
+        rules.extend(
+            self.load_builtin_rules(metrics, system_id)
+                .await
+                .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?,
+        );
+        let registry = self.registry(system_id);
+        self.evaluate_and_notify(&registry, &registered, rules, system_id, alerts)
+            .await
+    }
+
+    /*
+     * process_custom
+     * Custom metrics are posted independently of agent reports. Rules on the `custom` component
+     * are evaluated with the latest posted values and whatever the system's last report left in
+     * its registry.
+     */
+    pub async fn process_custom(
+        &mut self,
+        values: &HashMap<String, f64>,
+        system_id: i32,
+        alerts: &AlertState,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send>> {
+        let registry = self.registry(system_id);
+        registry
+            .register_component(
+                "custom".to_string(),
+                Box::new(CustomComponent::new(values.clone())),
+            )
+            .await;
 
         let rules = self
             .load_rules(system_id)
             .await
-            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?;
-        let evaluator = RuleEvaluator::new(&self.registry);
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?
+            .as_ref()
+            .clone();
+        self.evaluate_and_notify(&registry, &["custom"], rules, system_id, alerts)
+            .await
+    }
+
+    /*
+     * process_offline
+     * Evaluates the rules on the `agent` component for a system that stopped reporting, called
+     * by the offline monitor since no report comes in to do it.
+     */
+    pub async fn process_offline(
+        &mut self,
+        offline_minutes: f64,
+        system_id: i32,
+        alerts: &AlertState,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send>> {
+        let registry = self.registry(system_id);
+        registry
+            .register_component(
+                "agent".to_string(),
+                Box::new(AgentComponent::new(offline_minutes)),
+            )
+            .await;
+
+        let rules = self
+            .load_rules(system_id)
+            .await
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send>)?
+            .as_ref()
+            .clone();
+        self.evaluate_and_notify(&registry, &["agent"], rules, system_id, alerts)
+            .await
+    }
+
+    /*
+     * alert_message
+     * What the notifiers get for a triggered rule: the current values of its conditions, the
+     * system's hostname and the last hour of its charted metric, as history and as chart.
+     */
+    async fn alert_message(
+        &self,
+        registry: &MetricRegistry,
+        rule: &Rule,
+        system_id: i32,
+    ) -> (AlertMessage, Option<Chart>) {
+        let mut alert = AlertMessage::new(rule, system_id);
+        for condition in &rule.conditions {
+            alert.values.push(TriggerValue {
+                metric: format!("{}.{}", condition.component, condition.metric),
+                value: registry
+                    .get_metric_value(&condition.component, &condition.metric)
+                    .await
+                    .ok(),
+                operator: condition.operator,
+                threshold: condition.value,
+            });
+        }
+        alert.hostname =
+            sqlx::query_scalar::<_, Option<String>>("SELECT hostname FROM systems WHERE id = $1")
+                .bind(system_id)
+                .fetch_optional(&self.pool)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("Failed to load the hostname of system {}: {}", system_id, e);
+                    None
+                })
+                .flatten();
+
+        let Some(series) = chart::for_rule(&self.pool, system_id, rule).await else {
+            return (alert, None);
+        };
+        let chart = series.chart();
+        (alert.with_history(&series.title, &series.samples), chart)
+    }
+
+    /*
+     * deliver
+     * Sends a message to one notifier and returns the channel it went to, `unknown` when the
+     * notifier could not be set up.
+     */
+    async fn deliver(
+        &self,
+        notifier: &Notifier,
+        outgoing: &Outgoing<'_>,
+    ) -> (&'static str, Result<(), NotificationError>) {
+        let service = match self.get_or_create_service(&notifier.url).await {
+            Ok(service) => service,
+            Err(e) => {
+                error!(
+                    "Failed to create notification service for {}: {}",
+                    notifier.url, e
+                );
+                return ("unknown", Err(e));
+            }
+        };
+        let result = match outgoing {
+            Outgoing::Alert(alert, chart) => service.send_alert(alert, *chart).await,
+            Outgoing::Notice(notice) => service.send(notice).await,
+        };
+        if let Err(e) = &result {
+            error!("Failed to send notification via {}: {}", notifier.url, e);
+        }
+        (service.kind(), result)
+    }
+
+    /// Sends to every notifier of a rule, each attempt is recorded in notification_deliveries.
+    async fn notify(
+        &self,
+        rule: &Rule,
+        system_id: i32,
+        alert_id: Option<i32>,
+        notifiers: &[Notifier],
+        outgoing: Outgoing<'_>,
+    ) {
+        for notifier in notifiers {
+            let (channel, result) = self.deliver(notifier, &outgoing).await;
+            let attempt = deliveries::Attempt {
+                alert: alert_id,
+                system: system_id,
+                rule: &rule.name,
+                notifier: notifier.id,
+                channel,
+                error: result.err().map(|e| e.redacted()),
+            };
+            if let Err(e) = deliveries::record(&self.pool, &attempt).await {
+                error!("Failed to record notification delivery: {}", e);
+            }
+        }
+    }
+
+    /// Failing to read the maintenance state notifies, a missed alert is worse than an extra one.
+    async fn in_maintenance(&self, system_id: i32) -> bool {
+        match maintenance::for_system(&self.pool, system_id).await {
+            Ok(current) => current.is_some(),
+            Err(e) => {
+                error!("Failed to load maintenance state: {}", e);
+                false
+            }
+        }
+    }
+
+    /*
+     * evaluate_and_notify
+     * Evaluates rules against the registered components and notifies for the ones that trigger.
+     * Rules referencing a component that isn't registered belong to another kind of report and
+     * are skipped, as are rules on none of the components `updated` by this sample: nothing they
+     * read changed since they were last evaluated. Every evaluation feeds the flap detector, flapping rules notify once and are
+     * then kept quiet until they settle.
+     */
+    async fn evaluate_and_notify(
+        &self,
+        registry: &MetricRegistry,
+        updated: &[&str],
+        rules: Vec<(Rule, Vec<Notifier>)>,
+        system_id: i32,
+        alerts: &AlertState,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error + Send>> {
+        let evaluator = RuleEvaluator::new(registry);
         let mut triggerd_rules = Vec::new();
-        for (rule, notifier_urls) in rules {
+        'rules: for (rule, notifiers) in rules {
             if !rule.enabled {
                 continue;
             }
 
-            // Skip already triggered rules in the current context
-            if triggered_rules.contains(&rule.name) {
+            for condition in &rule.conditions {
+                if !registry.has_component(&condition.component).await {
+                    continue 'rules;
+                }
+            }
+            if !rule
+                .conditions
+                .iter()
+                .any(|c| updated.contains(&c.component.as_str()))
+            {
                 continue;
             }
 
-            match evaluator.evaluate_rule(&rule).await {
-                Ok(true) => {
-                    info!("Rule '{}' triggered for system {}", rule.name, system_id);
-
-                    // Insert alert history
-                    if let Err(e) = sqlx::query(crate::queries::alert_queries::INSERT_ALERT_HISTORY)
-                        .bind(system_id)
-                        .bind(rule.id)
-                        .execute(&self.pool)
-                        .await
-                    {
-                        error!("Failed to insert alert history: {}", e);
-                    }
-
-                    // Send notifications
-                    let message = format!(
-                        "Alert: {}\nDescription: {}\nSeverity: {}\nSystem ID: {}",
-                        rule.name, rule.description, rule.severity, system_id
-                    );
+            let firing = match evaluator.evaluate_rule(&rule).await {
+                Ok(firing) => firing,
+                Err(e) => {
+                    warn!("Failed to evaluate rule '{}': {}", rule.name, e);
+                    continue;
+                }
+            };
 
-                    for url in notifier_urls {
-                        match self.get_or_create_service(&url).await {
-                            Ok(service) => {
-                                if let Err(e) = service.send(&message).await {
-                                    error!("Failed to send notification via {}: {}", url, e);
-                                }
-                            }
-                            Err(e) => {
-                                error!("Failed to create notification service for {}: {}", url, e);
-                            }
-                        }
-                    }
-                    triggerd_rules.push(rule.name.clone());
+            let flap = FLAPPING.observe(system_id, &rule.name, firing);
+            match flap {
+                Flap::Started if self.in_maintenance(system_id).await => {
+                    warn!("Rule '{}' is flapping on system {}", rule.name, system_id);
+                }
+                Flap::Started => {
+                    warn!("Rule '{}' is flapping on system {}", rule.name, system_id);
+                    let notice = flapping_notice(&rule, system_id, &FLAPPING.options());
+                    self.notify(
+                        &rule,
+                        system_id,
+                        None,
+                        &notifiers,
+                        Outgoing::Notice(&notice),
+                    )
+                    .await;
+                }
+                Flap::Settled => {
+                    info!("Rule '{}' settled on system {}", rule.name, system_id);
                 }
-                Ok(false) => {}
+                Flap::Stable | Flap::Flapping => {}
+            }
+
+            // Skip rules still waiting out their hold and ones in their cooldown
+            let fires = PENDING.observe(system_id, &rule.name, firing, rule.hold);
+            if !fires || !alerts.claim(system_id, &rule.name).await {
+                continue;
+            }
+
+            info!("Rule '{}' triggered for system {}", rule.name, system_id);
+
+            let alert_id = match history::record(&self.pool, system_id, &rule).await {
+                Ok(id) => Some(id),
                 Err(e) => {
-                    warn!("Failed to evaluate rule '{}': {}", rule.name, e);
+                    error!("Failed to insert alert history: {}", e);
+                    None
                 }
+            };
+
+            if let Some(events) = &self.events {
+                events.alert(&rule, system_id);
+            }
+
+            // Send notifications, unless the rule is flapping or the hub is in maintenance
+            if matches!(flap, Flap::Started | Flap::Flapping) {
+                info!(
+                    "Not notifying for rule '{}' on system {} while it flaps",
+                    rule.name, system_id
+                );
+            } else if !notifiers.is_empty() && self.in_maintenance(system_id).await {
+                info!(
+                    "Not notifying for rule '{}' on system {} during maintenance",
+                    rule.name, system_id
+                );
+            } else if !notifiers.is_empty() {
+                let (alert, chart) = self.alert_message(registry, &rule, system_id).await;
+                let outgoing = Outgoing::Alert(&alert, chart.as_ref());
+                self.notify(&rule, system_id, alert_id, &notifiers, outgoing)
+                    .await;
             }
+            triggerd_rules.push(rule.name.clone());
         }
         Ok(triggerd_rules)
     }
 }
+
+/// What a notifier is sent.
+enum Outgoing<'a> {
+    Alert(&'a AlertMessage, Option<&'a Chart>),
+    Notice(&'a str),
+}
+
+fn flapping_notice(rule: &Rule, system_id: i32, options: &FlapOptions) -> String {
+    format!(
+        "Alert flapping: {}\nSeverity: {}\nSystem ID: {}\nThe rule changed state more than {} \
+         times in {} minutes, its notifications are paused until it holds its state for {} \
+         minutes.",
+        rule.name,
+        rule.severity,
+        system_id,
+        options.max_transitions,
+        options.window.as_secs() / 60,
+        options.window.as_secs() / 60
+    )
+}
@@ -0,0 +1,167 @@
+use super::{Notifier, Rule};
+use crate::shutdown::Shutdown;
+use crate::telemetry::TELEMETRY;
+use dashmap::DashMap;
+use log::{info, warn};
+use sqlx::postgres::PgListener;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/*
+ * Rule cache
+ * Every report used to load the system's rules and their notifiers from Postgres. They are now
+ * kept per system until a rule, an assignment, a notifier or a system's tags change: triggers
+ * on those tables send NOTIFY lynx_rules and the hub drops everything it cached. Entries also
+ * expire after RULE_CACHE_SECS, which bounds how stale they get on databases without the
+ * triggers or while the listener reconnects. Like PENDING the cache lives in the hub's memory.
+ */
+
+pub const RULES_CHANNEL: &str = "lynx_rules";
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+pub type SystemRules = Vec<(Rule, Vec<Notifier>)>;
+
+struct Entry<T> {
+    value: Arc<T>,
+    loaded: Instant,
+}
+
+pub struct RuleCache {
+    rules: DashMap<i32, Entry<SystemRules>>,
+    /// Notifiers of the system's admin, for the built-in rules
+    owner_notifiers: DashMap<i32, Entry<Vec<Notifier>>>,
+    /// Bumped by every invalidation, loads that started before one aren't stored
+    version: AtomicU64,
+    ttl_secs: AtomicU64,
+}
+
+impl Default for RuleCache {
+    fn default() -> Self {
+        Self {
+            rules: DashMap::new(),
+            owner_notifiers: DashMap::new(),
+            version: AtomicU64::new(0),
+            ttl_secs: AtomicU64::new(60),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref RULES: RuleCache = RuleCache::default();
+}
+
+fn lookup<T>(map: &DashMap<i32, Entry<T>>, system_id: i32, ttl: Duration) -> Option<Arc<T>> {
+    let found = map
+        .get(&system_id)
+        .filter(|entry| entry.loaded.elapsed() < ttl)
+        .map(|entry| entry.value.clone());
+    TELEMETRY.record_rule_lookup(found.is_some());
+    found
+}
+
+impl RuleCache {
+    /// Zero turns the cache off, every report loads its rules again.
+    pub fn set_ttl(&self, ttl: Duration) {
+        self.ttl_secs.store(ttl.as_secs(), Ordering::Relaxed);
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs.load(Ordering::Relaxed))
+    }
+
+    /// Taken before loading from the database, see `put_rules`.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::Acquire)
+    }
+
+    pub fn rules(&self, system_id: i32) -> Option<Arc<SystemRules>> {
+        lookup(&self.rules, system_id, self.ttl())
+    }
+
+    pub fn owner_notifiers(&self, system_id: i32) -> Option<Arc<Vec<Notifier>>> {
+        lookup(&self.owner_notifiers, system_id, self.ttl())
+    }
+
+    /// Stores what was loaded at `version`, unless the cache was invalidated since.
+    pub fn put_rules(&self, system_id: i32, version: u64, rules: SystemRules) -> Arc<SystemRules> {
+        let rules = Arc::new(rules);
+        if version == self.version() && !self.ttl().is_zero() {
+            self.rules.insert(
+                system_id,
+                Entry {
+                    value: rules.clone(),
+                    loaded: Instant::now(),
+                },
+            );
+        }
+        rules
+    }
+
+    pub fn put_owner_notifiers(
+        &self,
+        system_id: i32,
+        version: u64,
+        notifiers: Vec<Notifier>,
+    ) -> Arc<Vec<Notifier>> {
+        let notifiers = Arc::new(notifiers);
+        if version == self.version() && !self.ttl().is_zero() {
+            self.owner_notifiers.insert(
+                system_id,
+                Entry {
+                    value: notifiers.clone(),
+                    loaded: Instant::now(),
+                },
+            );
+        }
+        notifiers
+    }
+
+    pub fn invalidate(&self) {
+        self.version.fetch_add(1, Ordering::AcqRel);
+        self.rules.clear();
+        self.owner_notifiers.clear();
+    }
+
+    /// Systems with cached rules, for the cache stats.
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/*
+ * run_rule_listener
+ * LISTENs on RULES_CHANNEL and drops the cache on every notification. Whatever changed while
+ * the connection was down is unknown, so the cache is dropped again after every reconnect.
+ */
+pub async fn run_rule_listener(pool: PgPool, shutdown: Shutdown) {
+    loop {
+        let listener = async {
+            let mut listener = PgListener::connect_with(&pool).await?;
+            listener.listen(RULES_CHANNEL).await?;
+            RULES.invalidate();
+            info!("[notify] Listening for rule changes");
+            loop {
+                listener.recv().await?;
+                RULES.invalidate();
+            }
+        };
+        let result: Result<(), sqlx::Error> = tokio::select! {
+            result = listener => result,
+            _ = shutdown.wait() => return,
+        };
+        if let Err(e) = result {
+            warn!("[notify] Rule change listener failed, retrying: {e}");
+        }
+        RULES.invalidate();
+        tokio::select! {
+            _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            _ = shutdown.wait() => return,
+        }
+    }
+}
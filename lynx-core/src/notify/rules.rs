@@ -1,14 +1,47 @@
 use super::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
 use std::str::FromStr;
+use std::time::Duration;
 
 #[derive(Debug, Clone)]
 pub struct Rule {
     pub id: i32,
+    /// Built-in rules live in code rather than the alert_rules table
+    pub builtin: bool,
     pub name: String,
     pub enabled: bool,
     pub description: String,
     pub severity: String,
     pub conditions: Vec<Condition>,
+    /// How long the conditions have to hold before the rule fires, zero fires right away
+    pub hold: Duration,
+    /// Free-form key/values such as runbook_url or owner, passed on with every notification
+    pub annotations: BTreeMap<String, String>,
+}
+
+/*
+ * parse_annotations
+ * Reads alert_rules.annotations, a JSON object. Numbers and booleans are kept as their text,
+ * nulls, nested values and empty keys are dropped; anything but an object yields none.
+ */
+pub fn parse_annotations(value: &serde_json::Value) -> BTreeMap<String, String> {
+    let Some(object) = value.as_object() else {
+        return BTreeMap::new();
+    };
+    object
+        .iter()
+        .filter(|(key, _)| !key.trim().is_empty())
+        .filter_map(|(key, value)| {
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                serde_json::Value::Number(n) => n.to_string(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                _ => return None,
+            };
+            Some((key.trim().to_string(), value))
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone)]
@@ -30,6 +63,19 @@ pub enum Operator {
     NotEqual,
 }
 
+impl Operator {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::GreaterThan => ">",
+            Operator::LessThan => "<",
+            Operator::GreaterThanOrEqual => ">=",
+            Operator::LessThanOrEqual => "<=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+        }
+    }
+}
+
 impl FromStr for Operator {
     type Err = MetricError;
 
@@ -55,6 +101,15 @@ pub enum LogicalOperator {
     Or,
 }
 
+impl LogicalOperator {
+    pub fn keyword(self) -> &'static str {
+        match self {
+            LogicalOperator::And => "AND",
+            LogicalOperator::Or => "OR",
+        }
+    }
+}
+
 impl FromStr for LogicalOperator {
     type Err = MetricError;
 
@@ -70,57 +125,346 @@ impl FromStr for LogicalOperator {
     }
 }
 
-// Rule parser that handles the expression syntax
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseErrorKind {
+    #[error("Empty expression")]
+    Empty,
+    #[error("Expected component.metric")]
+    ExpectedMetric,
+    #[error("Unknown component")]
+    UnknownComponent,
+    #[error("Unknown metric")]
+    UnknownMetric,
+    #[error("Expected a comparison operator")]
+    ExpectedOperator,
+    #[error("Expected a number")]
+    ExpectedNumber,
+    #[error("Expected AND or OR")]
+    ExpectedLogical,
+    #[error("Expected a condition after AND / OR")]
+    UnexpectedEnd,
+}
+
+/*
+ * ParseError
+ * Where an expression stops making sense: the byte offset of the offending token, the token
+ * itself (empty at the end of the expression) and, when there is an obvious fix, a suggestion
+ * such as the metric name closest to a misspelled one.
+ */
+#[derive(Error, Debug, Clone, PartialEq, Serialize)]
+#[error("{kind} at offset {offset}: {token:?}{}", .suggestion.as_ref().map(|s| format!(", {s}")).unwrap_or_default())]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub offset: usize,
+    pub token: String,
+    pub suggestion: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    /// Letters, digits, `_`, `.` and `-`: metric references, numbers and AND / OR
+    Word,
+    /// Runs of `<>!=&|`
+    Symbol,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Token<'a> {
+    kind: TokenKind,
+    text: &'a str,
+    offset: usize,
+}
+
+fn tokenize(expression: &str) -> Vec<Token<'_>> {
+    let class = |c: char| {
+        if c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-') {
+            Some(TokenKind::Word)
+        } else if matches!(c, '<' | '>' | '!' | '=' | '&' | '|') {
+            Some(TokenKind::Symbol)
+        } else if c.is_whitespace() {
+            None
+        } else {
+            Some(TokenKind::Other)
+        }
+    };
+    let mut tokens = Vec::new();
+    let mut chars = expression.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let Some(kind) = class(c) else { continue };
+        let mut end = offset + c.len_utf8();
+        if kind != TokenKind::Other {
+            while let Some(&(i, next)) = chars.peek() {
+                if class(next) != Some(kind) {
+                    break;
+                }
+                end = i + next.len_utf8();
+                chars.next();
+            }
+        }
+        tokens.push(Token {
+            kind,
+            text: &expression[offset..end],
+            offset,
+        });
+    }
+    tokens
+}
+
+/// Levenshtein distance, names are short enough for the quadratic version.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = previous + usize::from(ca != *cb);
+            previous = row[j + 1];
+            row[j + 1] = substituted.min(previous + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// "did you mean" for a close candidate, otherwise the full list.
+fn suggest<'a>(wrong: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    let candidates: Vec<&str> = candidates.into_iter().collect();
+    let closest = candidates
+        .iter()
+        .map(|c| (edit_distance(wrong, c), *c))
+        .min()
+        .filter(|(distance, c)| *distance <= (c.len() / 3).max(1));
+    match closest {
+        Some((_, c)) => format!("did you mean {c}?"),
+        None => format!("expected one of {}", candidates.join(", ")),
+    }
+}
+
+/*
+ * RuleParser
+ * Expressions are conditions joined by AND / OR, each `component.metric <op> number`, e.g.
+ * `cpu.usage > 80 AND memory.usage >= 90`. Components and metrics are checked against the
+ * built-in ones (see components::COMPONENTS), `custom.<name>` takes any custom metric name.
+ * Anything that doesn't fit fails the whole expression instead of being skipped.
+ */
 pub struct RuleParser;
 
 impl RuleParser {
-    pub fn parse_expression(expression: &str) -> Result<Vec<Condition>, MetricError> {
-        use regex::Regex;
+    pub fn parse_expression(expression: &str) -> Result<Vec<Condition>, ParseError> {
+        let tokens = tokenize(expression);
+        let end = expression.len();
+        let error = |kind, token: Option<&Token>, suggestion: Option<String>| ParseError {
+            kind,
+            offset: token.map_or(end, |t| t.offset),
+            token: token.map(|t| t.text.to_string()).unwrap_or_default(),
+            suggestion,
+        };
+        if tokens.is_empty() {
+            return Err(error(ParseErrorKind::Empty, None, None));
+        }
+
+        let mut conditions = Vec::new();
+        let mut tokens = tokens.iter().peekable();
+        loop {
+            let (component, metric) = Self::parse_metric(tokens.next(), end)?;
+
+            let operator = match tokens.next() {
+                Some(t) if t.kind == TokenKind::Symbol => {
+                    Operator::from_str(t.text).map_err(|_| {
+                        let suggestion = match t.text {
+                            "=" => Some("use == to compare".to_string()),
+                            "=>" => Some("did you mean >=?".to_string()),
+                            "=<" => Some("did you mean <=?".to_string()),
+                            _ => None,
+                        };
+                        error(ParseErrorKind::ExpectedOperator, Some(t), suggestion)
+                    })?
+                }
+                t => return Err(error(ParseErrorKind::ExpectedOperator, t, None)),
+            };
 
-        lazy_static::lazy_static! {
-            static ref COMPONENT_RE: Regex = Regex::new(
-                r"^([a-zA-Z0-9_]+)\.([a-zA-Z0-9_]+)\s*([<>!=]+)\s*([a-zA-Z0-9_.]+)"
-            ).unwrap();
-            static ref LOGICAL_RE: Regex = Regex::new(r"\s+(AND|OR)\s+").unwrap();
+            let value = match tokens.next() {
+                Some(t) if t.kind == TokenKind::Word => t
+                    .text
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.is_finite())
+                    .ok_or_else(|| error(ParseErrorKind::ExpectedNumber, Some(t), None))?,
+                t => return Err(error(ParseErrorKind::ExpectedNumber, t, None)),
+            };
+
+            let next_logical = match tokens.next() {
+                None => None,
+                Some(t) => match LogicalOperator::from_str(t.text) {
+                    Ok(logical) => Some(logical),
+                    Err(_) => {
+                        let suggestion = match t.text {
+                            "&&" | "&" => Some("use AND".to_string()),
+                            "||" | "|" => Some("use OR".to_string()),
+                            _ => None,
+                        };
+                        return Err(error(ParseErrorKind::ExpectedLogical, Some(t), suggestion));
+                    }
+                },
+            };
+
+            conditions.push(Condition {
+                component,
+                metric,
+                operator,
+                value,
+                next_logical,
+            });
+            if next_logical.is_none() {
+                return Ok(conditions);
+            }
+            if tokens.peek().is_none() {
+                return Err(error(ParseErrorKind::UnexpectedEnd, None, None));
+            }
         }
+    }
 
-        let segments: Vec<&str> = LOGICAL_RE.split(expression).collect();
-        let operators: Vec<&str> = LOGICAL_RE
-            .find_iter(expression)
-            .map(|m| m.as_str().trim())
-            .collect();
+    /// A `component.metric` reference, checked against the known components.
+    fn parse_metric(token: Option<&Token>, end: usize) -> Result<(String, String), ParseError> {
+        let expected = |token: Option<&Token>| ParseError {
+            kind: ParseErrorKind::ExpectedMetric,
+            offset: token.map_or(end, |t| t.offset),
+            token: token.map(|t| t.text.to_string()).unwrap_or_default(),
+            suggestion: None,
+        };
+        let Some(token) = token.filter(|t| t.kind == TokenKind::Word) else {
+            return Err(expected(token));
+        };
+        let valid =
+            |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        // built-in metrics can have a suffix of their own, e.g. smart.pending_sectors.delta_7d
+        let Some((component, metric)) = token
+            .text
+            .split_once('.')
+            .filter(|&(c, m)| valid(c) && m.split('.').all(valid))
+        else {
+            return Err(expected(Some(token)));
+        };
 
-        let mut conditions = Vec::new();
+        if component == CUSTOM_COMPONENT {
+            if !valid(metric) {
+                return Err(expected(Some(token)));
+            }
+            return Ok((component.to_string(), metric.to_string()));
+        }
+        let Some(metrics) = known_metrics(component) else {
+            let names = COMPONENTS.iter().map(|(name, _)| *name);
+            return Err(ParseError {
+                kind: ParseErrorKind::UnknownComponent,
+                offset: token.offset,
+                token: component.to_string(),
+                suggestion: Some(suggest(component, names.chain([CUSTOM_COMPONENT]))),
+            });
+        };
+        if !metrics.contains(&metric) {
+            return Err(ParseError {
+                kind: ParseErrorKind::UnknownMetric,
+                offset: token.offset + component.len() + 1,
+                token: metric.to_string(),
+                suggestion: Some(suggest(metric, metrics.iter().copied())),
+            });
+        }
+        Ok((component.to_string(), metric.to_string()))
+    }
+}
+
+/// One `key=value`, `key!=value` or bare `key` term of a rule's tag target.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TagMatch {
+    /// Tag is set to one of the `|` separated values
+    Equals(String, Vec<String>),
+    NotEquals(String, Vec<String>),
+    Present(String),
+}
+
+/*
+ * TagSelector
+ * Targets a rule at every system whose tags match, e.g. `role=db,dc=eu-1|eu-2`. Terms are
+ * comma separated and must all match, so systems enrolled later pick the rule up by their tags.
+ * `*` has no terms and targets every system.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagSelector {
+    pub terms: Vec<TagMatch>,
+}
+
+impl FromStr for TagSelector {
+    type Err = MetricError;
 
-        for (i, segment) in segments.iter().enumerate() {
-            if let Some(caps) = COMPONENT_RE.captures(segment) {
-                let component = caps.get(1).unwrap().as_str().to_string();
-                let metric = caps.get(2).unwrap().as_str().to_string();
-                let operator = Operator::from_str(caps.get(3).unwrap().as_str())?;
-                let value = caps.get(4).unwrap().as_str().parse::<f64>().map_err(|_| {
-                    MetricError::InvalidValue(format!(
-                        "Invalid numeric value: {}",
-                        caps.get(4).unwrap().as_str()
-                    ))
-                })?;
-
-                let next_logical = if i < operators.len() {
-                    Some(LogicalOperator::from_str(operators[i])?)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim() == "*" {
+            return Ok(Self { terms: Vec::new() });
+        }
+        let values = |v: &str| v.split('|').map(|v| v.trim().to_string()).collect();
+        let terms = s
+            .split(',')
+            .map(str::trim)
+            .filter(|term| !term.is_empty())
+            .map(|term| {
+                let term = if let Some((key, value)) = term.split_once("!=") {
+                    TagMatch::NotEquals(key.trim().to_string(), values(value))
+                } else if let Some((key, value)) = term.split_once('=') {
+                    TagMatch::Equals(key.trim().to_string(), values(value))
                 } else {
-                    None
+                    TagMatch::Present(term.to_string())
                 };
-
-                conditions.push(Condition {
-                    component,
-                    metric,
-                    operator,
-                    value,
-                    next_logical,
-                });
-            }
+                match &term {
+                    TagMatch::Equals(key, _)
+                    | TagMatch::NotEquals(key, _)
+                    | TagMatch::Present(key)
+                        if key.is_empty() =>
+                    {
+                        Err(MetricError::InvalidValue(format!(
+                            "Invalid tag target: {}",
+                            s
+                        )))
+                    }
+                    _ => Ok(term),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if terms.is_empty() {
+            return Err(MetricError::InvalidValue("Empty tag target".to_string()));
         }
+        Ok(Self { terms })
+    }
+}
+
+impl TagSelector {
+    pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        self.terms.iter().all(|term| match term {
+            TagMatch::Equals(key, values) => tags.get(key).is_some_and(|v| values.contains(v)),
+            TagMatch::NotEquals(key, values) => tags.get(key).is_none_or(|v| !values.contains(v)),
+            TagMatch::Present(key) => tags.contains_key(key),
+        })
+    }
+}
 
-        Ok(conditions)
+impl Rule {
+    /// Fires when the agent's client certificate is about to expire.
+    pub fn builtin_cert_expiry() -> Self {
+        let expression = format!("tls.expiry_days < {}", crate::tls::CERT_EXPIRY_WARN_DAYS);
+        Self {
+            id: 0,
+            builtin: true,
+            name: "builtin:cert_expiry".to_string(),
+            enabled: true,
+            description: format!(
+                "Agent client certificate expires within {} days",
+                crate::tls::CERT_EXPIRY_WARN_DAYS
+            ),
+            severity: "critical".to_string(),
+            conditions: RuleParser::parse_expression(&expression).unwrap_or_default(),
+            hold: Duration::ZERO,
+            annotations: BTreeMap::new(),
+        }
     }
 }
 
@@ -1,7 +1,12 @@
 use super::*;
+use crate::telemetry::TELEMETRY;
 use async_trait::async_trait;
 use log::info;
 use mail_send::{mail_builder::MessageBuilder, Credentials, SmtpClientBuilder};
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use reqwest::multipart::{Form, Part};
 use reqwest::Client;
 use serde_json::json;
 use url::Url;
@@ -16,26 +21,71 @@ pub enum NotificationError {
     ConfigError(String),
     #[error("URL parsing error: {0}")]
     UrlError(#[from] url::ParseError),
+    #[error("Signing error: {0}")]
+    SigningError(#[from] openssl::error::ErrorStack),
 }
 
+impl NotificationError {
+    /// The error without the URL of the failed request, webhook URLs carry their token.
+    pub fn redacted(&self) -> String {
+        match self {
+            NotificationError::RequestError(e) => match e.url() {
+                Some(url) => self.to_string().replace(url.as_str(), "<notifier>"),
+                None => self.to_string(),
+            },
+            _ => self.to_string(),
+        }
+    }
+}
+
+/// Name of the chart attachment in Discord messages and emails.
+const CHART_FILE_NAME: &str = "chart.png";
+
 // Enum to handle different notification service types
 #[derive(Clone)]
 pub enum NotificationServiceType {
     Discord(DiscordService),
     Email(EmailService),
+    Webhook(WebhookService),
 }
 
 #[async_trait]
 impl NotificationService for NotificationServiceType {
     async fn send(&self, message: &str) -> Result<(), NotificationError> {
-        match self {
+        let result = match self {
             NotificationServiceType::Discord(discord) => discord.send(message).await,
             NotificationServiceType::Email(email) => email.send(message).await,
-        }
+            NotificationServiceType::Webhook(webhook) => webhook.send(message).await,
+        };
+        TELEMETRY.record_notification(self.kind(), result.is_ok());
+        result
+    }
+
+    async fn send_alert(
+        &self,
+        alert: &AlertMessage,
+        chart: Option<&Chart>,
+    ) -> Result<(), NotificationError> {
+        let result = match self {
+            NotificationServiceType::Discord(discord) => discord.send_alert(alert, chart).await,
+            NotificationServiceType::Email(email) => email.send_alert(alert, chart).await,
+            NotificationServiceType::Webhook(webhook) => webhook.send_alert(alert, chart).await,
+        };
+        TELEMETRY.record_notification(self.kind(), result.is_ok());
+        result
     }
 }
 
 impl NotificationServiceType {
+    /// Label of the notifier in hub telemetry.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NotificationServiceType::Discord(_) => "discord",
+            NotificationServiceType::Email(_) => "email",
+            NotificationServiceType::Webhook(_) => "webhook",
+        }
+    }
+
     pub fn from_url(url: &str) -> Result<Self, NotificationError> {
         if url.starts_with("discord://") {
             Ok(NotificationServiceType::Discord(DiscordService::from_url(
@@ -43,10 +93,14 @@ impl NotificationServiceType {
             )?))
         } else if url.starts_with("smtp://") {
             Ok(NotificationServiceType::Email(EmailService::from_url(url)?))
+        } else if url.starts_with("webhook+https://") || url.starts_with("webhook+http://") {
+            Ok(NotificationServiceType::Webhook(WebhookService::from_url(
+                url,
+            )?))
         } else {
             Err(NotificationError::ConfigError(format!(
                 "Unsupported notification service: {}",
-                url
+                url.split("://").next().unwrap_or_default()
             )))
         }
     }
@@ -102,18 +156,55 @@ impl DiscordService {
 #[async_trait]
 impl NotificationService for DiscordService {
     async fn send(&self, message: &str) -> Result<(), NotificationError> {
+        self.send_message(message, None).await
+    }
+
+    async fn send_alert(
+        &self,
+        alert: &AlertMessage,
+        chart: Option<&Chart>,
+    ) -> Result<(), NotificationError> {
+        self.send_message(&alert.text(), chart).await
+    }
+}
+
+impl DiscordService {
+    /// The chart goes along as a multipart upload, shown as the embed's image.
+    async fn send_message(
+        &self,
+        message: &str,
+        chart: Option<&Chart>,
+    ) -> Result<(), NotificationError> {
         let client = Client::new();
+        let mut embed = json!({
+            "title": "Lynx Monitor Alert",
+            "description": message,
+            "color": 16711680
+        });
+        if let Some(chart) = chart {
+            embed["image"] = json!({ "url": format!("attachment://{}", CHART_FILE_NAME) });
+            embed["footer"] = json!({ "text": format!("{}, last hour", chart.title) });
+        }
         let payload = json!({
             "username": self.username,
-            "embeds": [{
-                "title": "Lynx Monitor Alert",
-                "description": message,
-                "color": 16711680
-            }]
+            "embeds": [embed]
         });
 
         info!("Sending Discord notification to {}", self.webhook_url);
-        client.post(&self.webhook_url).json(&payload).send().await?;
+        let request = client.post(&self.webhook_url);
+        let request = match chart {
+            Some(chart) => {
+                let image = Part::bytes(chart.png.clone())
+                    .file_name(CHART_FILE_NAME)
+                    .mime_str("image/png")?;
+                let form = Form::new()
+                    .text("payload_json", payload.to_string())
+                    .part("files[0]", image);
+                request.multipart(form)
+            }
+            None => request.json(&payload),
+        };
+        request.send().await?;
 
         Ok(())
     }
@@ -219,7 +310,31 @@ impl NotificationService for EmailService {
             .to(self.to_email.clone())
             .subject(self.subject.clone())
             .text_body(message.to_string());
+        self.deliver(message).await
+    }
 
+    /// HTML with the plain text as alternative, the chart is embedded inline.
+    async fn send_alert(
+        &self,
+        alert: &AlertMessage,
+        chart: Option<&Chart>,
+    ) -> Result<(), NotificationError> {
+        let cid = chart.map(|_| CHART_FILE_NAME);
+        let mut message = MessageBuilder::new()
+            .from(self.from_email.clone())
+            .to(self.to_email.clone())
+            .subject(self.subject.clone())
+            .text_body(alert.text())
+            .html_body(alert.html(cid));
+        if let Some(chart) = chart {
+            message = message.inline("image/png", CHART_FILE_NAME, chart.png.clone());
+        }
+        self.deliver(message).await
+    }
+}
+
+impl EmailService {
+    async fn deliver(&self, message: MessageBuilder<'_>) -> Result<(), NotificationError> {
         let credentials = Credentials::Plain {
             username: &self.username,
             secret: &self.password,
@@ -236,3 +351,118 @@ impl NotificationService for EmailService {
         Ok(())
     }
 }
+
+/*
+ * Generic webhook notification service
+ * `webhook+https://receiver.example.org/hooks/lynx?secret=...` POSTs alerts as JSON to the URL
+ * after the `webhook+` prefix. With `secret` set (it is not sent along) every request carries
+ * X-Lynx-Timestamp, the unix time in seconds, and X-Lynx-Signature, `sha256=` followed by the
+ * hex HMAC-SHA256 of `{timestamp}.{body}` under the secret. Receivers recompute it and reject
+ * stale timestamps to tell the hub's requests from forged or replayed ones.
+ */
+pub const SIGNATURE_HEADER: &str = "X-Lynx-Signature";
+pub const TIMESTAMP_HEADER: &str = "X-Lynx-Timestamp";
+
+#[derive(Clone)]
+pub struct WebhookService {
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookService {
+    pub fn new(url: String, secret: Option<String>) -> Self {
+        Self { url, secret }
+    }
+
+    pub fn from_url(url: &str) -> Result<Self, NotificationError> {
+        let target = url.strip_prefix("webhook+").ok_or_else(|| {
+            NotificationError::ConfigError("Invalid webhook URL scheme".to_string())
+        })?;
+        let mut url = Url::parse(target)?;
+        let mut secret = None;
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter_map(|(k, v)| {
+                if k == "secret" {
+                    secret = Some(v.to_string()).filter(|s| !s.is_empty());
+                    None
+                } else {
+                    Some((k.to_string(), v.to_string()))
+                }
+            })
+            .collect();
+        if query.is_empty() {
+            url.set_query(None);
+        } else {
+            url.query_pairs_mut().clear().extend_pairs(query);
+        }
+        Ok(Self::new(url.to_string(), secret))
+    }
+
+    async fn post(&self, payload: serde_json::Value) -> Result<(), NotificationError> {
+        let body = payload.to_string();
+        let mut request = Client::new()
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        if let Some(secret) = &self.secret {
+            let timestamp = chrono::Utc::now().timestamp();
+            request = request
+                .header(TIMESTAMP_HEADER, timestamp)
+                .header(SIGNATURE_HEADER, sign_payload(secret, timestamp, &body)?);
+        }
+        request.body(body).send().await?.error_for_status()?;
+        Ok(())
+    }
+}
+
+/// The X-Lynx-Signature value of a webhook body sent at `timestamp`.
+pub fn sign_payload(secret: &str, timestamp: i64, body: &str) -> Result<String, NotificationError> {
+    let key = PKey::hmac(secret.as_bytes())?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    signer.update(format!("{timestamp}.{body}").as_bytes())?;
+    let signature: String = signer
+        .sign_to_vec()?
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect();
+    Ok(format!("sha256={signature}"))
+}
+
+#[async_trait]
+impl NotificationService for WebhookService {
+    async fn send(&self, message: &str) -> Result<(), NotificationError> {
+        self.post(json!({ "text": message })).await
+    }
+
+    /// Charts are left out, receivers can query the metric themselves.
+    async fn send_alert(
+        &self,
+        alert: &AlertMessage,
+        _chart: Option<&Chart>,
+    ) -> Result<(), NotificationError> {
+        let values: Vec<serde_json::Value> = alert
+            .values
+            .iter()
+            .map(|v| {
+                json!({
+                    "metric": v.metric,
+                    "value": v.value,
+                    "operator": v.operator.symbol(),
+                    "threshold": v.threshold,
+                })
+            })
+            .collect();
+        self.post(json!({
+            "text": alert.text(),
+            "rule": alert.rule,
+            "description": alert.description,
+            "severity": alert.severity,
+            "system_id": alert.system_id,
+            "hostname": alert.hostname,
+            "values": values,
+            "annotations": alert.annotations,
+            "link": alert.system_link(),
+        }))
+        .await
+    }
+}
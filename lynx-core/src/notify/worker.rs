@@ -0,0 +1,268 @@
+use super::NotificationProcessor;
+use crate::config::Secrets;
+use crate::events::Events;
+use crate::leader::Leadership;
+use crate::proto::monitor::MetricsRequest;
+use crate::shared::SharedState;
+use crate::telemetry::TELEMETRY;
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::RwLock;
+use tokio::time::{interval, Duration, Instant, MissedTickBehavior};
+use tracing::Instrument;
+
+/*
+ * Notification workers
+ * The ingest writers queue every stored report and custom metric post for evaluation instead
+ * of building a processor per batch. ALERT_WORKERS workers each own one NotificationProcessor
+ * for as long as the hub runs; a system's evaluations always go to the same worker, so they are
+ * evaluated in order against a registry that still holds the components of earlier samples.
+ * Evaluations finding their worker's share of ALERT_QUEUE_SIZE full are dropped and counted in
+ * lynx_alert_evaluations_dropped_total, a slow notifier must not hold up the database writes.
+ */
+
+const ALERT_COOLDOWN: Duration = Duration::from_secs(600); // 10 minutes
+/// How often cooldowns, idle registries and old notification services are cleaned up
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug)]
+pub struct EvaluationOptions {
+    /// Evaluations queued across all workers
+    pub queue_size: usize,
+    /// Worker tasks, each with its share of the queue
+    pub workers: usize,
+}
+
+impl Default for EvaluationOptions {
+    fn default() -> Self {
+        Self {
+            queue_size: 10_000,
+            workers: 1,
+        }
+    }
+}
+
+/// A sample to evaluate the system's rules on.
+#[derive(Debug)]
+pub enum Evaluation {
+    Metrics {
+        system_id: i32,
+        metrics: Box<MetricsRequest>,
+        clock_skew_ms: Option<i64>,
+        /// Of the agent report the sample came in, logged with everything the evaluation does
+        request_id: Option<String>,
+    },
+    /// Latest value per custom metric name
+    Custom {
+        system_id: i32,
+        values: HashMap<String, f64>,
+    },
+}
+
+impl Evaluation {
+    pub fn system_id(&self) -> i32 {
+        match self {
+            Evaluation::Metrics { system_id, .. } | Evaluation::Custom { system_id, .. } => {
+                *system_id
+            }
+        }
+    }
+
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            Evaluation::Metrics { request_id, .. } => request_id.as_deref(),
+            Evaluation::Custom { .. } => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct EvaluationQueue {
+    senders: Vec<Sender<Evaluation>>,
+}
+
+impl EvaluationQueue {
+    /// The queue and one receiver per worker.
+    pub fn new(options: &EvaluationOptions) -> (Self, Vec<Receiver<Evaluation>>) {
+        let workers = options.workers.max(1);
+        let per_worker = (options.queue_size / workers).max(1);
+        let (senders, receivers) = (0..workers).map(|_| channel(per_worker)).unzip();
+        (Self { senders }, receivers)
+    }
+
+    /// Evaluations waiting across all workers.
+    pub fn depth(&self) -> usize {
+        self.senders
+            .iter()
+            .map(|tx| tx.max_capacity() - tx.capacity())
+            .sum()
+    }
+
+    /// Queues without waiting, returns whether the evaluation was taken.
+    pub fn push(&self, evaluation: Evaluation) -> bool {
+        let system_id = evaluation.system_id();
+        let worker = system_id.rem_euclid(self.senders.len() as i32) as usize;
+        match self.senders[worker].try_send(evaluation) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                TELEMETRY.record_evaluation_dropped();
+                warn!("[notify] Evaluation queue full, dropped a sample of system {system_id}");
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+}
+
+/*
+ * AlertState
+ * Alerts inside their cooldown, keyed by system and rule. A single hub keeps them in memory;
+ * hubs sharing state claim them in Redis, so of two instances evaluating a system at once only
+ * the one that claimed the alert notifies.
+ */
+#[derive(Clone)]
+pub enum AlertState {
+    Local {
+        alerts: Arc<RwLock<HashMap<String, Instant>>>,
+        cooldown: Duration,
+    },
+    /// Boxed, the connection manager is far larger than the local map's handle
+    Shared(Box<SharedState>),
+}
+
+impl AlertState {
+    pub fn new(shared: Option<SharedState>) -> Self {
+        match shared {
+            Some(shared) => AlertState::Shared(Box::new(shared)),
+            None => AlertState::local(ALERT_COOLDOWN),
+        }
+    }
+
+    pub fn local(cooldown: Duration) -> Self {
+        AlertState::Local {
+            alerts: Arc::new(RwLock::new(HashMap::new())),
+            cooldown,
+        }
+    }
+
+    /*
+     * claim
+     * Starts the cooldown of a rule on a system unless it is already running, returns whether
+     * the caller should notify. A Redis failure notifies, a missed alert is worse than an extra
+     * one.
+     */
+    pub async fn claim(&self, system_id: i32, rule_name: &str) -> bool {
+        let key = format!("{system_id}:{rule_name}");
+        match self {
+            AlertState::Local { alerts, cooldown } => {
+                let mut alerts = alerts.write().await;
+                match alerts.get(&key) {
+                    Some(at) if at.elapsed() < *cooldown => false,
+                    _ => {
+                        alerts.insert(key, Instant::now());
+                        true
+                    }
+                }
+            }
+            AlertState::Shared(shared) => match shared.claim_alert(&key, ALERT_COOLDOWN).await {
+                Ok(claimed) => claimed,
+                Err(e) => {
+                    error!("[notify] Failed to claim shared alert {key}: {e}");
+                    true
+                }
+            },
+        }
+    }
+
+    /// Ends the cooldowns of a system's rules, only kept in the local map.
+    pub async fn release(&self, system_id: i32) {
+        if let AlertState::Local { alerts, .. } = self {
+            let prefix = format!("{system_id}:");
+            alerts.write().await.retain(|key, _| !key.starts_with(&prefix));
+        }
+    }
+
+    /// Redis entries expire on their own, only the local map needs this.
+    pub async fn cleanup(&self) {
+        if let AlertState::Local { alerts, cooldown } = self {
+            let mut alerts = alerts.write().await;
+            alerts.retain(|_, claimed| claimed.elapsed() < *cooldown);
+        }
+    }
+}
+
+/*
+ * run_notification_worker
+ * Evaluates queued samples one at a time with a processor owned by this worker. Hubs sharing
+ * the database without Redis leave alerting to the leader, samples reaching the others are
+ * skipped; with Redis every hub evaluates and claims its alerts there. Stops once
+ * every sender is gone and the queue is empty, so evaluations queued by the draining ingest
+ * writers during shutdown still run.
+ */
+pub async fn run_notification_worker(
+    mut rx: Receiver<Evaluation>,
+    pool: PgPool,
+    secrets: Secrets,
+    events: Option<Events>,
+    alerts: AlertState,
+    leadership: Leadership,
+) {
+    let mut processor = NotificationProcessor::new(pool, secrets, events);
+    let mut expire = interval(EXPIRE_INTERVAL);
+    expire.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let evaluation = tokio::select! {
+            evaluation = rx.recv() => evaluation,
+            _ = expire.tick() => {
+                alerts.cleanup().await;
+                processor.expire().await;
+                continue;
+            }
+        };
+        let Some(evaluation) = evaluation else {
+            break;
+        };
+        // without shared alert state a hub can't tell whether another one already notified
+        if matches!(alerts, AlertState::Local { .. }) && !leadership.is_leader() {
+            continue;
+        }
+
+        let system_id = evaluation.system_id();
+        let span = tracing::info_span!(
+            "evaluation",
+            system_id,
+            request_id = evaluation.request_id()
+        );
+        async {
+            let result = match &evaluation {
+                Evaluation::Metrics {
+                    metrics,
+                    clock_skew_ms,
+                    ..
+                } => {
+                    processor
+                        .process(metrics, *clock_skew_ms, system_id, &alerts)
+                        .await
+                }
+                Evaluation::Custom { values, .. } => {
+                    processor.process_custom(values, system_id, &alerts).await
+                }
+            };
+            match result {
+                Ok(fired) => {
+                    if !fired.is_empty() {
+                        info!("[notify] System {}: Alerts Updated", system_id);
+                    }
+                }
+                Err(e) => error!("[notify] Failed for system {}: {e}", system_id),
+            }
+        }
+        .instrument(span)
+        .await;
+    }
+    info!("[notify] Notification worker stopped");
+}
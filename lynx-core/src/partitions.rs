@@ -0,0 +1,160 @@
+use crate::leader::{Leadership, ELECTION_INTERVAL};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use sqlx::PgPool;
+use thiserror::Error;
+use tokio::time::interval;
+
+/*
+ * Partitions
+ * metrics and disks are TimescaleDB hypertables, split into chunks of PARTITION_INTERVAL_DAYS
+ * (default 7) on their time column. A query bounded in time only reads the chunks covering its
+ * range, so a year of history costs the recent queries nothing; TimeRange gives every history
+ * query both bounds. The leader checks the tables once: installs whose tables were created
+ * without create_hypertable (e.g. by the portal's migrations) are converted, which rewrites the
+ * table and is only done for filled tables with PARTITION_MIGRATE=true. A changed interval
+ * applies to chunks created from then on.
+ */
+
+/// Partitioned time series, with the column naming the system.
+pub const PARTITIONED_SERIES: &[(&str, &str)] = &[("metrics", "system_id"), ("disks", "system")];
+
+/// Range a history query covers when the client gives no start.
+pub const DEFAULT_RANGE: chrono::Duration = chrono::Duration::hours(24);
+/// Longest range one history query may cover.
+pub const MAX_RANGE: chrono::Duration = chrono::Duration::days(400);
+
+const HAS_TIMESCALE: &str =
+    "SELECT EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'timescaledb')";
+
+const IS_HYPERTABLE: &str = "SELECT EXISTS (SELECT 1 FROM timescaledb_information.hypertables \
+     WHERE hypertable_schema = current_schema() AND hypertable_name = $1)";
+
+const CREATE_HYPERTABLE: &str = "SELECT create_hypertable($1::regclass, 'time', \
+     chunk_time_interval => $2 * INTERVAL '1 day', migrate_data => true, if_not_exists => true)";
+
+const SET_CHUNK_INTERVAL: &str =
+    "SELECT set_chunk_time_interval($1::regclass, $2 * INTERVAL '1 day')";
+
+#[derive(Clone, Debug)]
+pub struct PartitionOptions {
+    /// Days of samples per chunk, 7 for weekly and 30 for monthly chunks
+    pub interval_days: i32,
+    /// Convert tables that already hold samples, locks them while their rows are moved
+    pub migrate: bool,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum RangeError {
+    #[error("from must be before to")]
+    Inverted,
+    #[error("range is longer than {} days", MAX_RANGE.num_days())]
+    TooLong,
+}
+
+/// Both bounds of a history query, so only the chunks covering it are read.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeRange {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+}
+
+impl TimeRange {
+    /*
+     * resolve
+     * `to` defaults to (and is capped at) now, `from` to DEFAULT_RANGE before `to`. Ranges longer
+     * than MAX_RANGE are refused rather than cut, the client would otherwise get less than it
+     * asked for without noticing.
+     */
+    pub fn resolve(
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Result<Self, RangeError> {
+        let to = to.map_or(now, |to| to.min(now));
+        let from = from.unwrap_or(to - DEFAULT_RANGE);
+        if from >= to {
+            return Err(RangeError::Inverted);
+        }
+        if to - from > MAX_RANGE {
+            return Err(RangeError::TooLong);
+        }
+        Ok(Self { from, to })
+    }
+}
+
+async fn ensure_table(
+    pool: &PgPool,
+    table: &str,
+    options: &PartitionOptions,
+) -> Result<(), sqlx::Error> {
+    let partitioned: bool = sqlx::query_scalar(IS_HYPERTABLE)
+        .bind(table)
+        .fetch_one(pool)
+        .await?;
+    if partitioned {
+        sqlx::query(SET_CHUNK_INTERVAL)
+            .bind(table)
+            .bind(options.interval_days)
+            .execute(pool)
+            .await?;
+        return Ok(());
+    }
+
+    let filled: bool = sqlx::query_scalar(&format!("SELECT EXISTS (SELECT 1 FROM {table})"))
+        .fetch_one(pool)
+        .await?;
+    if filled && !options.migrate {
+        warn!(
+            "[partitions] {table} is not partitioned, set PARTITION_MIGRATE=true to convert it \
+             (locks the table while its rows are moved)"
+        );
+        return Ok(());
+    }
+    info!(
+        "[partitions] Partitioning {table} into {}-day chunks",
+        options.interval_days
+    );
+    sqlx::query(CREATE_HYPERTABLE)
+        .bind(table)
+        .bind(options.interval_days)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Partitions the time series, hubs without TimescaleDB keep plain tables.
+pub async fn ensure_partitions(
+    pool: &PgPool,
+    options: &PartitionOptions,
+) -> Result<(), sqlx::Error> {
+    let timescale: bool = sqlx::query_scalar(HAS_TIMESCALE).fetch_one(pool).await?;
+    if !timescale {
+        warn!("[partitions] TimescaleDB is not installed, metrics and disks are not partitioned");
+        return Ok(());
+    }
+    for (table, system_column) in PARTITIONED_SERIES {
+        ensure_table(pool, table, options).await?;
+        sqlx::query(&format!(
+            "CREATE INDEX IF NOT EXISTS {table}_system_time_idx ON {table} ({system_column}, time DESC)"
+        ))
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Waits for this hub to lead, then checks the partitions once.
+pub async fn run_setup(pool: PgPool, options: PartitionOptions, leadership: Leadership) {
+    let mut tick = interval(ELECTION_INTERVAL);
+    loop {
+        tick.tick().await;
+        if !leadership.is_leader() {
+            continue;
+        }
+        if let Err(e) = ensure_partitions(&pool, &options).await {
+            error!("[partitions] Setting up partitions failed: {e}");
+        }
+        return;
+    }
+}
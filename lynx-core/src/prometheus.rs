@@ -0,0 +1,310 @@
+use crate::counters::CounterRates;
+use crate::proto::monitor::{
+    Component, CpuStats, DiskStats, KernelStats, LoadAverage, MemoryStats, MetricsRequest,
+    NetworkStats, ProcessStats,
+};
+use crate::units;
+use std::collections::HashMap;
+use std::time::Instant;
+use thiserror::Error;
+
+/*
+ * Prometheus exporter compatibility
+ * Parses the text exposition format served by exporters and maps the well-known node_exporter
+ * series onto the fields of a MetricsRequest, so hosts that only run node_exporter are stored
+ * like agent hosts:
+ *   cpu        node_cpu_seconds_total (idle and iowait count as idle)
+ *   memory     node_memory_MemTotal_bytes, node_memory_MemAvailable_bytes
+ *   network    node_network_{receive,transmit}_bytes_total, loopback excluded
+ *   load       node_load1, node_load5, node_load15
+ *   disks      node_filesystem_{size,avail}_bytes, node_disk_{read,written}_bytes_total
+ *   components node_hwmon_temp_celsius, node_thermal_zone_temp
+ */
+
+/// Pseudo filesystems node_exporter reports that agents leave out
+const IGNORED_FSTYPES: &[&str] = &[
+    "tmpfs", "devtmpfs", "overlay", "squashfs", "proc", "sysfs", "nsfs", "ramfs",
+];
+
+#[derive(Error, Debug)]
+pub enum ParseError {
+    #[error("Line {line}: {reason}")]
+    Invalid { line: usize, reason: &'static str },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub name: String,
+    pub labels: HashMap<String, String>,
+    pub value: f64,
+}
+
+impl Sample {
+    pub fn label(&self, name: &str) -> &str {
+        self.labels.get(name).map(String::as_str).unwrap_or("")
+    }
+}
+
+fn parse_value(raw: &str) -> Option<f64> {
+    match raw {
+        "+Inf" => Some(f64::INFINITY),
+        "-Inf" => Some(f64::NEG_INFINITY),
+        "NaN" => Some(f64::NAN),
+        _ => raw.parse().ok(),
+    }
+}
+
+/// Parses `{a="x",b="y"}` starting after the opening brace, returns the labels and the rest.
+fn parse_labels(mut rest: &str) -> Result<(HashMap<String, String>, &str), &'static str> {
+    let mut labels = HashMap::new();
+    loop {
+        rest = rest.trim_start_matches([' ', ',']);
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((labels, after));
+        }
+        let (name, after) = rest.split_once('=').ok_or("expected label name")?;
+        let mut chars = after
+            .strip_prefix('"')
+            .ok_or("expected quoted label value")?
+            .char_indices();
+        let mut value = String::new();
+        let end = loop {
+            match chars.next().ok_or("unterminated label value")? {
+                (i, '"') => break i,
+                (_, '\\') => match chars.next().ok_or("unterminated label value")?.1 {
+                    'n' => value.push('\n'),
+                    c => value.push(c),
+                },
+                (_, c) => value.push(c),
+            }
+        };
+        labels.insert(name.trim().to_string(), value);
+        rest = &after[end + 2..];
+    }
+}
+
+/// Parses an exposition in the Prometheus text format, comments and timestamps are skipped.
+pub fn parse(text: &str) -> Result<Vec<Sample>, ParseError> {
+    let mut samples = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |reason| ParseError::Invalid {
+            line: i + 1,
+            reason,
+        };
+
+        let name_end = line
+            .find(['{', ' ', '\t'])
+            .ok_or(invalid("missing value"))?;
+        let (name, rest) = line.split_at(name_end);
+        let (labels, rest) = match rest.strip_prefix('{') {
+            Some(rest) => parse_labels(rest).map_err(invalid)?,
+            None => (HashMap::new(), rest),
+        };
+        let value = rest
+            .split_whitespace()
+            .next()
+            .and_then(parse_value)
+            .ok_or(invalid("invalid value"))?;
+
+        samples.push(Sample {
+            name: name.to_string(),
+            labels,
+            value,
+        });
+    }
+    Ok(samples)
+}
+
+/*
+ * node_exporter_metrics
+ * Builds the MetricsRequest an agent on the scraped host would have sent. CPU usage and network
+ * throughput, disk I/O rates included, come from counters, so the first scrape of a target reports them as zero.
+ */
+pub fn node_exporter_metrics(
+    samples: &[Sample],
+    counters: &mut CounterRates,
+    now: Instant,
+) -> MetricsRequest {
+    let series = |name: &'static str| samples.iter().filter(move |s| s.name == name);
+    let gauge = |name: &'static str| series(name).next().map(|s| s.value).unwrap_or(0.0);
+
+    let (mut idle, mut total) = (0.0, 0.0);
+    let mut modes: HashMap<&str, f64> = HashMap::new();
+    for s in series("node_cpu_seconds_total") {
+        total += s.value;
+        if matches!(s.label("mode"), "idle" | "iowait") {
+            idle += s.value;
+        }
+        *modes.entry(s.label("mode")).or_default() += s.value;
+    }
+    let cpu_total = counters.delta("cpu_total", total, now);
+    let cpu_idle = counters.delta("cpu_idle", idle, now);
+    let usage_percent = match (cpu_total, cpu_idle) {
+        (Some(t), Some(i)) if t > 0.0 => ((t - i) / t * 100.0).clamp(0.0, 100.0),
+        _ => 0.0,
+    };
+    // share of CPU time per mode group, same grouping as the agent's /proc/stat breakdown
+    let mut cpu_share = |group: &'static str, names: &[&str]| {
+        let seconds: f64 = names.iter().filter_map(|m| modes.get(m)).sum();
+        let delta = counters.delta(&format!("cpu_{group}"), seconds, now)?;
+        cpu_total
+            .filter(|t| *t > 0.0)
+            .map(|t| (delta / t * 100.0).clamp(0.0, 100.0))
+    };
+    let cpu_stats = CpuStats {
+        usage_percent,
+        user_percent: cpu_share("user", &["user", "nice"]),
+        system_percent: cpu_share("system", &["system"]),
+        iowait_percent: cpu_share("iowait", &["iowait"]),
+        irq_percent: cpu_share("irq", &["irq", "softirq"]),
+        steal_percent: cpu_share("steal", &["steal"]),
+    };
+
+    let mut network = |name: &'static str| {
+        let bytes: f64 = series(name)
+            .filter(|s| s.label("device") != "lo")
+            .map(|s| s.value)
+            .sum();
+        counters.rate(name, bytes, now).unwrap_or(0.0)
+    };
+    let rate_in = network("node_network_receive_bytes_total");
+    let rate_out = network("node_network_transmit_bytes_total");
+    let network_stats = NetworkStats {
+        r#in: (rate_in / units::MB) as u64,
+        out: (rate_out / units::MB) as u64,
+        in_bytes_per_sec: Some(rate_in),
+        out_bytes_per_sec: Some(rate_out),
+    };
+
+    let total_kb = (gauge("node_memory_MemTotal_bytes") / 1024.0) as u64;
+    let available_kb = (gauge("node_memory_MemAvailable_bytes") / 1024.0) as u64;
+    let kb = |name: &'static str| series(name).next().map(|s| (s.value / 1024.0) as u64);
+    let swap_total_kb = kb("node_memory_SwapTotal_bytes");
+    let swap_used_kb = swap_total_kb
+        .zip(kb("node_memory_SwapFree_bytes"))
+        .map(|(total, free)| total.saturating_sub(free));
+    let mut swap = |name: &'static str| {
+        let pages = series(name).next()?.value;
+        counters.rate(name, pages, now)
+    };
+    let swap_in_per_sec = swap("node_vmstat_pswpin");
+    let swap_out_per_sec = swap("node_vmstat_pswpout");
+
+    let mut disk_io = |name: &'static str, device: &str| {
+        let value = series(name)
+            .find(|s| s.label("device") == device.trim_start_matches("/dev/"))?
+            .value;
+        counters.rate(&format!("{name}:{device}"), value, now)
+    };
+    let to_gb = |bytes: f64| (bytes / units::GB) as i32;
+    let disk_stats = series("node_filesystem_size_bytes")
+        .filter(|s| !IGNORED_FSTYPES.contains(&s.label("fstype")))
+        .map(|size| {
+            let mount_point = size.label("mountpoint");
+            let available = series("node_filesystem_avail_bytes")
+                .find(|s| s.label("mountpoint") == mount_point)
+                .map(|s| s.value)
+                .unwrap_or(size.value);
+            let device = size.label("device");
+            let inodes_total = series("node_filesystem_files")
+                .find(|s| s.label("mountpoint") == mount_point && s.value > 0.0)
+                .map(|s| s.value);
+            let inodes_free = series("node_filesystem_files_free")
+                .find(|s| s.label("mountpoint") == mount_point)
+                .map(|s| s.value);
+            DiskStats {
+                name: device.to_string(),
+                total_space: to_gb(size.value),
+                used_space: to_gb(size.value - available),
+                unit: "gb".to_string(),
+                read_bytes: disk_io("node_disk_read_bytes_total", device).unwrap_or(0.0),
+                write_bytes: disk_io("node_disk_written_bytes_total", device).unwrap_or(0.0),
+                mount_point: mount_point.to_string(),
+                read_iops: disk_io("node_disk_reads_completed_total", device),
+                write_iops: disk_io("node_disk_writes_completed_total", device),
+                inodes_total: inodes_total.map(|n| n as u64),
+                inodes_used: inodes_total
+                    .zip(inodes_free)
+                    .map(|(total, free)| (total - free) as u64),
+                total_bytes: Some(size.value as u64),
+                used_bytes: Some((size.value - available).max(0.0) as u64),
+            }
+        })
+        .collect();
+
+    let hwmon = series("node_hwmon_temp_celsius").map(|s| Component {
+        label: format!("{} {}", s.label("chip"), s.label("sensor")),
+        temperature: s.value as f32,
+    });
+    let thermal = series("node_thermal_zone_temp").map(|s| Component {
+        label: s.label("type").to_string(),
+        temperature: s.value as f32,
+    });
+
+    let context_switches = gauge("node_context_switches_total");
+    let interrupts = gauge("node_intr_total");
+    let kernel_stats = KernelStats {
+        context_switches_per_sec: counters
+            .rate("node_context_switches_total", context_switches, now)
+            .unwrap_or(0.0),
+        interrupts_per_sec: counters
+            .rate("node_intr_total", interrupts, now)
+            .unwrap_or(0.0),
+        procs_blocked: gauge("node_procs_blocked") as u32,
+        entropy_avail: series("node_entropy_available_bits")
+            .next()
+            .map(|s| s.value as u32),
+    };
+
+    // only exported with node_exporter's opt-in processes collector
+    let process_stats = series("node_processes_threads").next().map(|threads| {
+        let states = || series("node_processes_state");
+        ProcessStats {
+            total: states().map(|s| s.value).sum::<f64>() as u32,
+            threads: threads.value as u32,
+            running: gauge("node_procs_running") as u32,
+            zombie: states()
+                .filter(|s| s.label("state") == "Z")
+                .map(|s| s.value)
+                .sum::<f64>() as u32,
+        }
+    });
+
+    MetricsRequest {
+        cpu_stats: Some(cpu_stats),
+        memory_stats: Some(MemoryStats {
+            total_kb,
+            used_kb: total_kb.saturating_sub(available_kb),
+            free_kb: (gauge("node_memory_MemFree_bytes") / 1024.0) as u64,
+            available_kb: Some(available_kb),
+            cached_kb: kb("node_memory_Cached_bytes"),
+            buffers_kb: kb("node_memory_Buffers_bytes"),
+            dirty_kb: kb("node_memory_Dirty_bytes"),
+            swap_total_kb,
+            swap_used_kb,
+            swap_in_per_sec,
+            swap_out_per_sec,
+        }),
+        disk_stats,
+        components: hwmon.chain(thermal).collect(),
+        network_stats: Some(network_stats),
+        load_average: Some(LoadAverage {
+            one_minute: gauge("node_load1"),
+            five_minutes: gauge("node_load5"),
+            fifteen_minutes: gauge("node_load15"),
+        }),
+        cert_expiry_days: None,
+        process_stats,
+        kernel_stats: Some(kernel_stats),
+        collected_at_ms: None,
+        probe_results: Vec::new(),
+        custom_metrics: Vec::new(),
+        top_processes: Vec::new(),
+        smart_devices: Vec::new(),
+        sent_at_ms: None,
+    }
+}
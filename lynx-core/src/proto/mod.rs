@@ -1 +1,4 @@
-pub mod monitor;
\ No newline at end of file
+pub mod monitor;
+
+/// Encoded descriptors of the lynx-proto files for gRPC reflection.
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("monitor_descriptor");
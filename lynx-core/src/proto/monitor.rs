@@ -1,5 +1,23 @@
 // This file is @generated by prost-build.
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EnrollRequest {
+    #[prost(string, tag = "1")]
+    pub hostname: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub token: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub csr_pem: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct EnrollResponse {
+    #[prost(string, tag = "1")]
+    pub certificate_pem: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub ca_certificate_pem: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub agent_key: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemInfoRequest {
     #[prost(string, tag = "1")]
     pub hostname: ::prost::alloc::string::String,
@@ -13,6 +31,19 @@ pub struct SystemInfoRequest {
     pub cpu_model: ::prost::alloc::string::String,
     #[prost(uint32, tag = "6")]
     pub cpu_count: u32,
+    #[prost(string, tag = "7")]
+    pub agent_version: ::prost::alloc::string::String,
+    #[prost(string, tag = "8")]
+    pub arch: ::prost::alloc::string::String,
+    /// systemd-detect-virt style name, "none" on bare metal
+    #[prost(string, tag = "9")]
+    pub virtualization: ::prost::alloc::string::String,
+    /// \[tags\] from the agent's config.toml
+    #[prost(map = "string, string", tag = "10")]
+    pub tags: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct MetricsRequest {
@@ -28,6 +59,137 @@ pub struct MetricsRequest {
     pub network_stats: ::core::option::Option<NetworkStats>,
     #[prost(message, optional, tag = "13")]
     pub load_average: ::core::option::Option<LoadAverage>,
+    #[prost(double, optional, tag = "14")]
+    pub cert_expiry_days: ::core::option::Option<f64>,
+    #[prost(message, optional, tag = "15")]
+    pub process_stats: ::core::option::Option<ProcessStats>,
+    #[prost(message, optional, tag = "16")]
+    pub kernel_stats: ::core::option::Option<KernelStats>,
+    /// unix millis on the agent, hub time is used when unset
+    #[prost(int64, optional, tag = "17")]
+    pub collected_at_ms: ::core::option::Option<i64>,
+    #[prost(message, repeated, tag = "18")]
+    pub probe_results: ::prost::alloc::vec::Vec<ProbeResult>,
+    /// derived by the agent's scripts
+    #[prost(message, repeated, tag = "19")]
+    pub custom_metrics: ::prost::alloc::vec::Vec<CustomMetric>,
+    /// busiest processes by CPU and by memory
+    #[prost(message, repeated, tag = "20")]
+    pub top_processes: ::prost::alloc::vec::Vec<TopProcess>,
+    /// read hourly, empty in the reports in between
+    #[prost(message, repeated, tag = "21")]
+    pub smart_devices: ::prost::alloc::vec::Vec<SmartDevice>,
+    /// unix millis on the agent when the report went out, for clock skew
+    #[prost(int64, optional, tag = "22")]
+    pub sent_at_ms: ::core::option::Option<i64>,
+}
+/// Same rules as POST /metrics/custom: names of up to 64 letters, digits and underscores
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CustomMetric {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(double, tag = "2")]
+    pub value: f64,
+    #[prost(map = "string, string", tag = "3")]
+    pub labels: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        ::prost::alloc::string::String,
+    >,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct WatchConfigRequest {
+    /// last applied version, 0 on startup
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+}
+/// Fleet configuration pushed by the hub, replaces the previous one entirely
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AgentConfig {
+    #[prost(uint64, tag = "1")]
+    pub version: u64,
+    /// collector name -> seconds
+    #[prost(map = "string, uint64", tag = "2")]
+    pub collector_intervals: ::std::collections::HashMap<
+        ::prost::alloc::string::String,
+        u64,
+    >,
+    #[prost(message, repeated, tag = "3")]
+    pub probe_targets: ::prost::alloc::vec::Vec<ProbeTarget>,
+    /// unset features stay enabled
+    #[prost(map = "string, bool", tag = "4")]
+    pub features: ::std::collections::HashMap<::prost::alloc::string::String, bool>,
+    /// the system was decommissioned, the agent uninstalls itself
+    #[prost(bool, tag = "5")]
+    pub decommission: bool,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProbeTarget {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    /// host:port, checked with a TCP connect
+    #[prost(string, tag = "2")]
+    pub address: ::prost::alloc::string::String,
+    #[prost(uint32, tag = "3")]
+    pub timeout_ms: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ProbeResult {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub address: ::prost::alloc::string::String,
+    #[prost(bool, tag = "3")]
+    pub up: bool,
+    #[prost(double, optional, tag = "4")]
+    pub latency_ms: ::core::option::Option<f64>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct SystemStatusRequest {
+    /// 0 for the system the agent key belongs to
+    #[prost(int32, tag = "1")]
+    pub system_id: i32,
+}
+/// Everything a dashboard shows for one system, served from the hub cache
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SystemStatusResponse {
+    #[prost(int32, tag = "1")]
+    pub system_id: i32,
+    #[prost(bool, tag = "2")]
+    pub online: bool,
+    /// unix millis of the last report
+    #[prost(int64, optional, tag = "3")]
+    pub last_seen_ms: ::core::option::Option<i64>,
+    /// unset until the hub received a sample since it started
+    #[prost(message, optional, tag = "4")]
+    pub latest_metrics: ::core::option::Option<MetricsRequest>,
+    #[prost(message, repeated, tag = "5")]
+    pub active_alerts: ::prost::alloc::vec::Vec<ActiveAlert>,
+    #[prost(message, optional, tag = "6")]
+    pub services: ::core::option::Option<ServiceSummary>,
+    #[prost(message, repeated, tag = "7")]
+    pub gpu_metrics: ::prost::alloc::vec::Vec<GpuMetrics>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ActiveAlert {
+    #[prost(int32, tag = "1")]
+    pub rule_id: i32,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub severity: ::prost::alloc::string::String,
+    #[prost(int64, tag = "4")]
+    pub last_fired_ms: i64,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServiceSummary {
+    #[prost(uint32, tag = "1")]
+    pub total: u32,
+    #[prost(uint32, tag = "2")]
+    pub running: u32,
+    #[prost(uint32, tag = "3")]
+    pub failed: u32,
+    #[prost(string, repeated, tag = "4")]
+    pub failed_names: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GpuRequest {
@@ -38,6 +200,9 @@ pub struct GpuRequest {
 pub struct GpuMetricsRequest {
     #[prost(message, repeated, tag = "1")]
     pub gpu_metrics: ::prost::alloc::vec::Vec<GpuMetrics>,
+    /// unix millis on the agent, hub time is used when unset
+    #[prost(int64, optional, tag = "2")]
+    pub collected_at_ms: ::core::option::Option<i64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ContainerRequest {
@@ -48,11 +213,20 @@ pub struct ContainerRequest {
 pub struct ContainerMetricsRequest {
     #[prost(message, repeated, tag = "1")]
     pub container_metrics: ::prost::alloc::vec::Vec<ContainerMetrics>,
+    /// unix millis on the agent, hub time is used when unset
+    #[prost(int64, optional, tag = "2")]
+    pub collected_at_ms: ::core::option::Option<i64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemctlRequest {
     #[prost(message, repeated, tag = "1")]
     pub services: ::prost::alloc::vec::Vec<SystemService>,
+    /// units gone since the previous report
+    #[prost(string, repeated, tag = "2")]
+    pub removed: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// services lists every unit, the hub drops the ones it doesn't (streamed: all chunks together)
+    #[prost(bool, tag = "3")]
+    pub full_sync: bool,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemService {
@@ -68,6 +242,13 @@ pub struct SystemService {
     pub cpu: ::prost::alloc::string::String,
     #[prost(string, tag = "6")]
     pub memory: ::prost::alloc::string::String,
+    /// units this one pulls in or is ordered after, from systemd's Requires, Wants and After
+    #[prost(string, repeated, tag = "7")]
+    pub requires: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "8")]
+    pub wants: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "9")]
+    pub after: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SystemctlResponse {
@@ -90,6 +271,109 @@ pub struct ContainerResponse {
     #[prost(string, tag = "2")]
     pub message: ::prost::alloc::string::String,
 }
+/// A collector run the agent's supervisor gave up on and restarted
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AgentHealthEvent {
+    #[prost(string, tag = "1")]
+    pub collector: ::prost::alloc::string::String,
+    /// panic or timeout
+    #[prost(string, tag = "2")]
+    pub kind: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub detail: ::prost::alloc::string::String,
+    /// restarts of this collector since the agent started
+    #[prost(uint32, tag = "4")]
+    pub restarts: u32,
+}
+/// Something that happened to the agent itself, sent on the next start
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AgentEvent {
+    /// panic
+    #[prost(string, tag = "1")]
+    pub kind: ::prost::alloc::string::String,
+    /// unix seconds when it happened
+    #[prost(int64, tag = "2")]
+    pub time: i64,
+    /// agent version that produced it
+    #[prost(string, tag = "3")]
+    pub version: ::prost::alloc::string::String,
+    #[prost(string, tag = "4")]
+    pub thread: ::prost::alloc::string::String,
+    /// file:line of the panic
+    #[prost(string, tag = "5")]
+    pub location: ::prost::alloc::string::String,
+    #[prost(string, tag = "6")]
+    pub message: ::prost::alloc::string::String,
+    #[prost(string, tag = "7")]
+    pub backtrace: ::prost::alloc::string::String,
+}
+/// An action taken on the host over the agent websocket, reported once it ended
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommandAudit {
+    /// unix seconds when it was requested
+    #[prost(int64, tag = "1")]
+    pub time: i64,
+    /// websocket peer address, hub:<session> for sessions relayed by the hub
+    #[prost(string, tag = "2")]
+    pub actor: ::prost::alloc::string::String,
+    /// execute, stop, update, delete, upload, tunnel, grant_tunnel, authorize, apply_updates, start_service, stop_service, restart_service
+    #[prost(string, tag = "3")]
+    pub action: ::prost::alloc::string::String,
+    /// command, service or container name, update url
+    #[prost(string, tag = "4")]
+    pub target: ::prost::alloc::string::String,
+    #[prost(string, repeated, tag = "5")]
+    pub args: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    /// execute only, unset when the command was killed or did not start
+    #[prost(int32, optional, tag = "6")]
+    pub exit_code: ::core::option::Option<i32>,
+    /// empty when it succeeded
+    #[prost(string, tag = "7")]
+    pub error: ::prost::alloc::string::String,
+    /// execute only, when the agent records transcripts
+    #[prost(message, repeated, tag = "8")]
+    pub transcript: ::prost::alloc::vec::Vec<TranscriptLine>,
+}
+/// One line of a command's transcript
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TranscriptLine {
+    /// unix milliseconds, 0 for input when the agent doesn't record input timestamps
+    #[prost(int64, tag = "1")]
+    pub time_ms: i64,
+    /// input, stdout or stderr
+    #[prost(string, tag = "2")]
+    pub stream: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub text: ::prost::alloc::string::String,
+}
+/// Starts (duration_minutes > 0) or ends hub-wide maintenance, neither only reads the status
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MaintenanceRequest {
+    #[prost(uint32, tag = "1")]
+    pub duration_minutes: u32,
+    #[prost(string, tag = "2")]
+    pub reason: ::prost::alloc::string::String,
+    #[prost(bool, tag = "3")]
+    pub end: bool,
+    /// location group such as site=eu-1, empty for the whole hub
+    #[prost(string, tag = "4")]
+    pub scope: ::prost::alloc::string::String,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MaintenanceStatus {
+    #[prost(bool, tag = "1")]
+    pub active: bool,
+    /// unix seconds, 0 when not active
+    #[prost(int64, tag = "2")]
+    pub started: i64,
+    /// unix seconds, 0 when not active
+    #[prost(int64, tag = "3")]
+    pub until: i64,
+    #[prost(string, tag = "4")]
+    pub reason: ::prost::alloc::string::String,
+    #[prost(string, tag = "5")]
+    pub scope: ::prost::alloc::string::String,
+}
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Response {
     #[prost(string, tag = "1")]
@@ -97,10 +381,39 @@ pub struct Response {
     #[prost(string, tag = "2")]
     pub message: ::prost::alloc::string::String,
 }
+/// The hub's answer to one sample on a MetricsChannel, in the order the samples arrived
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct MetricsAck {
+    /// position of the sample on the stream, from 0
+    #[prost(uint64, tag = "1")]
+    pub sequence: u64,
+    /// gRPC status code, 0 when the sample was queued
+    #[prost(int32, tag = "2")]
+    pub code: i32,
+    #[prost(string, tag = "3")]
+    pub message: ::prost::alloc::string::String,
+    /// hub -> agent, hold samples back this long, set while ingest is backed up
+    #[prost(uint32, tag = "4")]
+    pub retry_after_secs: u32,
+}
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct CpuStats {
     #[prost(double, tag = "1")]
     pub usage_percent: f64,
+    /// Share of CPU time since the previous sample, only where the OS exposes it (/proc/stat)
+    ///
+    /// user + nice
+    #[prost(double, optional, tag = "2")]
+    pub user_percent: ::core::option::Option<f64>,
+    #[prost(double, optional, tag = "3")]
+    pub system_percent: ::core::option::Option<f64>,
+    #[prost(double, optional, tag = "4")]
+    pub iowait_percent: ::core::option::Option<f64>,
+    /// irq + softirq
+    #[prost(double, optional, tag = "5")]
+    pub irq_percent: ::core::option::Option<f64>,
+    #[prost(double, optional, tag = "6")]
+    pub steal_percent: ::core::option::Option<f64>,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct MemoryStats {
@@ -110,6 +423,24 @@ pub struct MemoryStats {
     pub used_kb: u64,
     #[prost(uint64, tag = "3")]
     pub free_kb: u64,
+    /// what can be allocated without swapping, MemAvailable on Linux
+    #[prost(uint64, optional, tag = "4")]
+    pub available_kb: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "5")]
+    pub cached_kb: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    pub buffers_kb: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "7")]
+    pub dirty_kb: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "8")]
+    pub swap_total_kb: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "9")]
+    pub swap_used_kb: ::core::option::Option<u64>,
+    /// pages/sec since the previous sample
+    #[prost(double, optional, tag = "10")]
+    pub swap_in_per_sec: ::core::option::Option<f64>,
+    #[prost(double, optional, tag = "11")]
+    pub swap_out_per_sec: ::core::option::Option<f64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DiskStats {
@@ -121,12 +452,98 @@ pub struct DiskStats {
     pub used_space: i32,
     #[prost(string, tag = "4")]
     pub unit: ::prost::alloc::string::String,
+    /// bytes/sec since the previous sample
     #[prost(double, tag = "5")]
     pub read_bytes: f64,
+    /// bytes/sec since the previous sample
     #[prost(double, tag = "6")]
     pub write_bytes: f64,
     #[prost(string, tag = "7")]
     pub mount_point: ::prost::alloc::string::String,
+    /// only where the OS exposes operation counts
+    #[prost(double, optional, tag = "8")]
+    pub read_iops: ::core::option::Option<f64>,
+    #[prost(double, optional, tag = "9")]
+    pub write_iops: ::core::option::Option<f64>,
+    /// unset where the filesystem has no inode limit
+    #[prost(uint64, optional, tag = "10")]
+    pub inodes_total: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "11")]
+    pub inodes_used: ::core::option::Option<u64>,
+    /// exact sizes, total_space/used_space in `unit` are kept for hubs that predate them
+    #[prost(uint64, optional, tag = "12")]
+    pub total_bytes: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "13")]
+    pub used_bytes: ::core::option::Option<u64>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct ProcessStats {
+    /// processes, threads not included
+    #[prost(uint32, tag = "1")]
+    pub total: u32,
+    #[prost(uint32, tag = "2")]
+    pub threads: u32,
+    #[prost(uint32, tag = "3")]
+    pub running: u32,
+    #[prost(uint32, tag = "4")]
+    pub zombie: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TopProcess {
+    #[prost(uint32, tag = "1")]
+    pub pid: u32,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    /// percent of one core, above 100 for multithreaded processes
+    #[prost(float, tag = "3")]
+    pub cpu_usage: f32,
+    /// resident
+    #[prost(uint64, tag = "4")]
+    pub memory_kb: u64,
+}
+/// SMART health of one drive, attributes the drive doesn't report are unset
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SmartDevice {
+    /// e.g. /dev/sda
+    #[prost(string, tag = "1")]
+    pub device: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub model: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub serial: ::prost::alloc::string::String,
+    /// the drive's overall self-assessment
+    #[prost(bool, tag = "4")]
+    pub passed: bool,
+    #[prost(uint64, optional, tag = "5")]
+    pub reallocated_sectors: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    pub pending_sectors: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "7")]
+    pub uncorrectable_sectors: ::core::option::Option<u64>,
+    /// NVMe
+    #[prost(uint64, optional, tag = "8")]
+    pub media_errors: ::core::option::Option<u64>,
+    /// NVMe wear estimate, can exceed 100
+    #[prost(uint32, optional, tag = "9")]
+    pub percentage_used: ::core::option::Option<u32>,
+    /// celsius
+    #[prost(double, optional, tag = "10")]
+    pub temperature: ::core::option::Option<f64>,
+    #[prost(uint64, optional, tag = "11")]
+    pub power_on_hours: ::core::option::Option<u64>,
+}
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct KernelStats {
+    #[prost(double, tag = "1")]
+    pub context_switches_per_sec: f64,
+    #[prost(double, tag = "2")]
+    pub interrupts_per_sec: f64,
+    /// waiting on I/O
+    #[prost(uint32, tag = "3")]
+    pub procs_blocked: u32,
+    /// bits
+    #[prost(uint32, optional, tag = "4")]
+    pub entropy_avail: ::core::option::Option<u32>,
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct LoadAverage {
@@ -139,10 +556,15 @@ pub struct LoadAverage {
 }
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
 pub struct NetworkStats {
+    /// whole MB/s, kept for hubs that predate the fields below
     #[prost(uint64, tag = "1")]
     pub r#in: u64,
     #[prost(uint64, tag = "2")]
     pub out: u64,
+    #[prost(double, optional, tag = "3")]
+    pub in_bytes_per_sec: ::core::option::Option<f64>,
+    #[prost(double, optional, tag = "4")]
+    pub out_bytes_per_sec: ::core::option::Option<f64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Component {
@@ -211,8 +633,66 @@ pub struct ContainerInfo {
     #[prost(string, tag = "3")]
     pub state: ::prost::alloc::string::String,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReleaseRequest {
+    /// stable or beta
+    #[prost(string, tag = "1")]
+    pub channel: ::prost::alloc::string::String,
+    /// as in SystemInfoRequest, e.g. x86_64
+    #[prost(string, tag = "2")]
+    pub arch: ::prost::alloc::string::String,
+    /// linux or windows, empty means linux
+    #[prost(string, tag = "3")]
+    pub os: ::prost::alloc::string::String,
+}
+/// Newest agent build on a channel. `manifest` is JSON with channel, version, os, arch, url,
+/// sha256 and published, `signature` the raw Ed25519 signature of exactly those bytes by the
+/// release key.
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ReleaseManifest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub manifest: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+/// One frame of a relayed websocket session, both directions share the session id
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SessionFrame {
+    #[prost(string, tag = "1")]
+    pub session_id: ::prost::alloc::string::String,
+    #[prost(oneof = "session_frame::Frame", tags = "2, 3, 4, 5, 6")]
+    pub frame: ::core::option::Option<session_frame::Frame>,
+}
+/// Nested message and enum types in `SessionFrame`.
+pub mod session_frame {
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum Frame {
+        /// hub -> agent, a dashboard connected
+        #[prost(bool, tag = "2")]
+        Open(bool),
+        #[prost(string, tag = "3")]
+        Text(::prost::alloc::string::String),
+        #[prost(bytes, tag = "4")]
+        Binary(::prost::alloc::vec::Vec<u8>),
+        /// either side ended the session, with the reason
+        #[prost(string, tag = "5")]
+        Close(::prost::alloc::string::String),
+        /// hub -> agent, a TCP tunnel instead of a websocket, bytes go in binary frames
+        #[prost(message, tag = "6")]
+        Tunnel(super::TunnelOpen),
+    }
+}
+/// Opens a TCP connection to a port on the agent host's loopback for a tunnel granted on the hub
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct TunnelOpen {
+    #[prost(uint32, tag = "1")]
+    pub port: u32,
+    /// the agent closes the tunnel after this long at the latest
+    #[prost(uint32, tag = "2")]
+    pub ttl_secs: u32,
+}
 /// Generated client implementations.
-pub mod system_monitor_client {
+pub mod metrics_ingest_client {
     #![allow(
         unused_variables,
         dead_code,
@@ -222,11 +702,12 @@ pub mod system_monitor_client {
     )]
     use tonic::codegen::*;
     use tonic::codegen::http::Uri;
+    /// Samples agents send on every collection interval
     #[derive(Debug, Clone)]
-    pub struct SystemMonitorClient<T> {
+    pub struct MetricsIngestClient<T> {
         inner: tonic::client::Grpc<T>,
     }
-    impl SystemMonitorClient<tonic::transport::Channel> {
+    impl MetricsIngestClient<tonic::transport::Channel> {
         /// Attempt to create a new client by connecting to a given endpoint.
         pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
         where
@@ -237,7 +718,7 @@ pub mod system_monitor_client {
             Ok(Self::new(conn))
         }
     }
-    impl<T> SystemMonitorClient<T>
+    impl<T> MetricsIngestClient<T>
     where
         T: tonic::client::GrpcService<tonic::body::Body>,
         T::Error: Into<StdError>,
@@ -255,7 +736,7 @@ pub mod system_monitor_client {
         pub fn with_interceptor<F>(
             inner: T,
             interceptor: F,
-        ) -> SystemMonitorClient<InterceptedService<T, F>>
+        ) -> MetricsIngestClient<InterceptedService<T, F>>
         where
             F: tonic::service::Interceptor,
             T::ResponseBody: Default,
@@ -269,7 +750,7 @@ pub mod system_monitor_client {
                 http::Request<tonic::body::Body>,
             >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
         {
-            SystemMonitorClient::new(InterceptedService::new(inner, interceptor))
+            MetricsIngestClient::new(InterceptedService::new(inner, interceptor))
         }
         /// Compress requests with the given encoding.
         ///
@@ -302,27 +783,6 @@ pub mod system_monitor_client {
             self.inner = self.inner.max_encoding_message_size(limit);
             self
         }
-        pub async fn get_system_info(
-            &mut self,
-            request: impl tonic::IntoRequest<super::SystemInfoRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
-            self.inner
-                .ready()
-                .await
-                .map_err(|e| {
-                    tonic::Status::unknown(
-                        format!("Service was not ready: {}", e.into()),
-                    )
-                })?;
-            let codec = tonic::codec::ProstCodec::default();
-            let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/GetSystemInfo",
-            );
-            let mut req = request.into_request();
-            req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "GetSystemInfo"));
-            self.inner.unary(req, path, codec).await
-        }
         pub async fn report_metrics(
             &mut self,
             request: impl tonic::IntoRequest<super::MetricsRequest>,
@@ -337,11 +797,11 @@ pub mod system_monitor_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/ReportMetrics",
+                "/monitor.MetricsIngest/ReportMetrics",
             );
             let mut req = request.into_request();
             req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "ReportMetrics"));
+                .insert(GrpcMethod::new("monitor.MetricsIngest", "ReportMetrics"));
             self.inner.unary(req, path, codec).await
         }
         pub async fn stream_metrics(
@@ -358,38 +818,21 @@ pub mod system_monitor_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/StreamMetrics",
+                "/monitor.MetricsIngest/StreamMetrics",
             );
             let mut req = request.into_streaming_request();
             req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "StreamMetrics"));
+                .insert(GrpcMethod::new("monitor.MetricsIngest", "StreamMetrics"));
             self.inner.client_streaming(req, path, codec).await
         }
-        pub async fn report_systemctl(
-            &mut self,
-            request: impl tonic::IntoRequest<super::SystemctlRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
-            self.inner
-                .ready()
-                .await
-                .map_err(|e| {
-                    tonic::Status::unknown(
-                        format!("Service was not ready: {}", e.into()),
-                    )
-                })?;
-            let codec = tonic::codec::ProstCodec::default();
-            let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/ReportSystemctl",
-            );
-            let mut req = request.into_request();
-            req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "ReportSystemctl"));
-            self.inner.unary(req, path, codec).await
-        }
-        pub async fn register_gp_us(
+        /// Kept open by agents, every sample is acked in order and the hub can ask for a pause
+        pub async fn metrics_channel(
             &mut self,
-            request: impl tonic::IntoRequest<super::GpuRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            request: impl tonic::IntoStreamingRequest<Message = super::MetricsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::MetricsAck>>,
+            tonic::Status,
+        > {
             self.inner
                 .ready()
                 .await
@@ -400,12 +843,12 @@ pub mod system_monitor_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/RegisterGPUs",
+                "/monitor.MetricsIngest/MetricsChannel",
             );
-            let mut req = request.into_request();
+            let mut req = request.into_streaming_request();
             req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "RegisterGPUs"));
-            self.inner.unary(req, path, codec).await
+                .insert(GrpcMethod::new("monitor.MetricsIngest", "MetricsChannel"));
+            self.inner.streaming(req, path, codec).await
         }
         pub async fn report_gpu_metrics(
             &mut self,
@@ -421,32 +864,11 @@ pub mod system_monitor_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/ReportGPUMetrics",
-            );
-            let mut req = request.into_request();
-            req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "ReportGPUMetrics"));
-            self.inner.unary(req, path, codec).await
-        }
-        pub async fn register_containers(
-            &mut self,
-            request: impl tonic::IntoRequest<super::ContainerRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
-            self.inner
-                .ready()
-                .await
-                .map_err(|e| {
-                    tonic::Status::unknown(
-                        format!("Service was not ready: {}", e.into()),
-                    )
-                })?;
-            let codec = tonic::codec::ProstCodec::default();
-            let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/RegisterContainers",
+                "/monitor.MetricsIngest/ReportGPUMetrics",
             );
             let mut req = request.into_request();
             req.extensions_mut()
-                .insert(GrpcMethod::new("monitor.SystemMonitor", "RegisterContainers"));
+                .insert(GrpcMethod::new("monitor.MetricsIngest", "ReportGPUMetrics"));
             self.inner.unary(req, path, codec).await
         }
         pub async fn report_container_metrics(
@@ -463,19 +885,19 @@ pub mod system_monitor_client {
                 })?;
             let codec = tonic::codec::ProstCodec::default();
             let path = http::uri::PathAndQuery::from_static(
-                "/monitor.SystemMonitor/ReportContainerMetrics",
+                "/monitor.MetricsIngest/ReportContainerMetrics",
             );
             let mut req = request.into_request();
             req.extensions_mut()
                 .insert(
-                    GrpcMethod::new("monitor.SystemMonitor", "ReportContainerMetrics"),
+                    GrpcMethod::new("monitor.MetricsIngest", "ReportContainerMetrics"),
                 );
             self.inner.unary(req, path, codec).await
         }
     }
 }
 /// Generated server implementations.
-pub mod system_monitor_server {
+pub mod metrics_ingest_server {
     #![allow(
         unused_variables,
         dead_code,
@@ -484,13 +906,9 @@ pub mod system_monitor_server {
         clippy::let_unit_value,
     )]
     use tonic::codegen::*;
-    /// Generated trait containing gRPC methods that should be implemented for use with SystemMonitorServer.
+    /// Generated trait containing gRPC methods that should be implemented for use with MetricsIngestServer.
     #[async_trait]
-    pub trait SystemMonitor: std::marker::Send + std::marker::Sync + 'static {
-        async fn get_system_info(
-            &self,
-            request: tonic::Request<super::SystemInfoRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+    pub trait MetricsIngest: std::marker::Send + std::marker::Sync + 'static {
         async fn report_metrics(
             &self,
             request: tonic::Request<super::MetricsRequest>,
@@ -499,36 +917,39 @@ pub mod system_monitor_server {
             &self,
             request: tonic::Request<tonic::Streaming<super::MetricsRequest>>,
         ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
-        async fn report_systemctl(
-            &self,
-            request: tonic::Request<super::SystemctlRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
-        async fn register_gp_us(
+        /// Server streaming response type for the MetricsChannel method.
+        type MetricsChannelStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::MetricsAck, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// Kept open by agents, every sample is acked in order and the hub can ask for a pause
+        async fn metrics_channel(
             &self,
-            request: tonic::Request<super::GpuRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+            request: tonic::Request<tonic::Streaming<super::MetricsRequest>>,
+        ) -> std::result::Result<
+            tonic::Response<Self::MetricsChannelStream>,
+            tonic::Status,
+        >;
         async fn report_gpu_metrics(
             &self,
             request: tonic::Request<super::GpuMetricsRequest>,
         ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
-        async fn register_containers(
-            &self,
-            request: tonic::Request<super::ContainerRequest>,
-        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
         async fn report_container_metrics(
             &self,
             request: tonic::Request<super::ContainerMetricsRequest>,
         ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
     }
+    /// Samples agents send on every collection interval
     #[derive(Debug)]
-    pub struct SystemMonitorServer<T> {
+    pub struct MetricsIngestServer<T> {
         inner: Arc<T>,
         accept_compression_encodings: EnabledCompressionEncodings,
         send_compression_encodings: EnabledCompressionEncodings,
         max_decoding_message_size: Option<usize>,
         max_encoding_message_size: Option<usize>,
     }
-    impl<T> SystemMonitorServer<T> {
+    impl<T> MetricsIngestServer<T> {
         pub fn new(inner: T) -> Self {
             Self::from_arc(Arc::new(inner))
         }
@@ -579,9 +1000,9 @@ pub mod system_monitor_server {
             self
         }
     }
-    impl<T, B> tonic::codegen::Service<http::Request<B>> for SystemMonitorServer<T>
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for MetricsIngestServer<T>
     where
-        T: SystemMonitor,
+        T: MetricsIngest,
         B: Body + std::marker::Send + 'static,
         B::Error: Into<StdError> + std::marker::Send + 'static,
     {
@@ -596,13 +1017,13 @@ pub mod system_monitor_server {
         }
         fn call(&mut self, req: http::Request<B>) -> Self::Future {
             match req.uri().path() {
-                "/monitor.SystemMonitor/GetSystemInfo" => {
+                "/monitor.MetricsIngest/ReportMetrics" => {
                     #[allow(non_camel_case_types)]
-                    struct GetSystemInfoSvc<T: SystemMonitor>(pub Arc<T>);
+                    struct ReportMetricsSvc<T: MetricsIngest>(pub Arc<T>);
                     impl<
-                        T: SystemMonitor,
-                    > tonic::server::UnaryService<super::SystemInfoRequest>
-                    for GetSystemInfoSvc<T> {
+                        T: MetricsIngest,
+                    > tonic::server::UnaryService<super::MetricsRequest>
+                    for ReportMetricsSvc<T> {
                         type Response = super::Response;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
@@ -610,11 +1031,11 @@ pub mod system_monitor_server {
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::SystemInfoRequest>,
+                            request: tonic::Request<super::MetricsRequest>,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as SystemMonitor>::get_system_info(&inner, request).await
+                                <T as MetricsIngest>::report_metrics(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -625,7 +1046,7 @@ pub mod system_monitor_server {
                     let max_encoding_message_size = self.max_encoding_message_size;
                     let inner = self.inner.clone();
                     let fut = async move {
-                        let method = GetSystemInfoSvc(inner);
+                        let method = ReportMetricsSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -641,13 +1062,13 @@ pub mod system_monitor_server {
                     };
                     Box::pin(fut)
                 }
-                "/monitor.SystemMonitor/ReportMetrics" => {
+                "/monitor.MetricsIngest/StreamMetrics" => {
                     #[allow(non_camel_case_types)]
-                    struct ReportMetricsSvc<T: SystemMonitor>(pub Arc<T>);
+                    struct StreamMetricsSvc<T: MetricsIngest>(pub Arc<T>);
                     impl<
-                        T: SystemMonitor,
-                    > tonic::server::UnaryService<super::MetricsRequest>
-                    for ReportMetricsSvc<T> {
+                        T: MetricsIngest,
+                    > tonic::server::ClientStreamingService<super::MetricsRequest>
+                    for StreamMetricsSvc<T> {
                         type Response = super::Response;
                         type Future = BoxFuture<
                             tonic::Response<Self::Response>,
@@ -655,11 +1076,13 @@ pub mod system_monitor_server {
                         >;
                         fn call(
                             &mut self,
-                            request: tonic::Request<super::MetricsRequest>,
+                            request: tonic::Request<
+                                tonic::Streaming<super::MetricsRequest>,
+                            >,
                         ) -> Self::Future {
                             let inner = Arc::clone(&self.0);
                             let fut = async move {
-                                <T as SystemMonitor>::report_metrics(&inner, request).await
+                                <T as MetricsIngest>::stream_metrics(&inner, request).await
                             };
                             Box::pin(fut)
                         }
@@ -670,7 +1093,7 @@ pub mod system_monitor_server {
                     let max_encoding_message_size = self.max_encoding_message_size;
                     let inner = self.inner.clone();
                     let fut = async move {
-                        let method = ReportMetricsSvc(inner);
+                        let method = StreamMetricsSvc(inner);
                         let codec = tonic::codec::ProstCodec::default();
                         let mut grpc = tonic::server::Grpc::new(codec)
                             .apply_compression_config(
@@ -681,12 +1104,2254 @@ pub mod system_monitor_server {
                                 max_decoding_message_size,
                                 max_encoding_message_size,
                             );
-                        let res = grpc.unary(method, req).await;
+                        let res = grpc.client_streaming(method, req).await;
                         Ok(res)
                     };
                     Box::pin(fut)
                 }
-                "/monitor.SystemMonitor/StreamMetrics" => {
+                "/monitor.MetricsIngest/MetricsChannel" => {
+                    #[allow(non_camel_case_types)]
+                    struct MetricsChannelSvc<T: MetricsIngest>(pub Arc<T>);
+                    impl<
+                        T: MetricsIngest,
+                    > tonic::server::StreamingService<super::MetricsRequest>
+                    for MetricsChannelSvc<T> {
+                        type Response = super::MetricsAck;
+                        type ResponseStream = T::MetricsChannelStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::MetricsRequest>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MetricsIngest>::metrics_channel(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = MetricsChannelSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.MetricsIngest/ReportGPUMetrics" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportGPUMetricsSvc<T: MetricsIngest>(pub Arc<T>);
+                    impl<
+                        T: MetricsIngest,
+                    > tonic::server::UnaryService<super::GpuMetricsRequest>
+                    for ReportGPUMetricsSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GpuMetricsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MetricsIngest>::report_gpu_metrics(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportGPUMetricsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.MetricsIngest/ReportContainerMetrics" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportContainerMetricsSvc<T: MetricsIngest>(pub Arc<T>);
+                    impl<
+                        T: MetricsIngest,
+                    > tonic::server::UnaryService<super::ContainerMetricsRequest>
+                    for ReportContainerMetricsSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ContainerMetricsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MetricsIngest>::report_container_metrics(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportContainerMetricsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(
+                            tonic::body::Body::default(),
+                        );
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for MetricsIngestServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "monitor.MetricsIngest";
+    impl<T> tonic::server::NamedService for MetricsIngestServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
+/// Generated client implementations.
+pub mod inventory_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// What runs on a system: host details, GPUs, systemd units and containers
+    #[derive(Debug, Clone)]
+    pub struct InventoryClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl InventoryClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> InventoryClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::Body>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InventoryClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::Body>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            InventoryClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn get_system_info(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemInfoRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Inventory/GetSystemInfo",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Inventory", "GetSystemInfo"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn register_gp_us(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GpuRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Inventory/RegisterGPUs",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Inventory", "RegisterGPUs"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_systemctl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemctlRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Inventory/ReportSystemctl",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Inventory", "ReportSystemctl"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// ReportSystemctl in chunks, for hosts with thousands of units
+        pub async fn stream_systemctl(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::SystemctlRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Inventory/StreamSystemctl",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Inventory", "StreamSystemctl"));
+            self.inner.client_streaming(req, path, codec).await
+        }
+        pub async fn register_containers(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ContainerRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Inventory/RegisterContainers",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Inventory", "RegisterContainers"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod inventory_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with InventoryServer.
+    #[async_trait]
+    pub trait Inventory: std::marker::Send + std::marker::Sync + 'static {
+        async fn get_system_info(
+            &self,
+            request: tonic::Request<super::SystemInfoRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn register_gp_us(
+            &self,
+            request: tonic::Request<super::GpuRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_systemctl(
+            &self,
+            request: tonic::Request<super::SystemctlRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        /// ReportSystemctl in chunks, for hosts with thousands of units
+        async fn stream_systemctl(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::SystemctlRequest>>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn register_containers(
+            &self,
+            request: tonic::Request<super::ContainerRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+    }
+    /// What runs on a system: host details, GPUs, systemd units and containers
+    #[derive(Debug)]
+    pub struct InventoryServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> InventoryServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for InventoryServer<T>
+    where
+        T: Inventory,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::Body>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/monitor.Inventory/GetSystemInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSystemInfoSvc<T: Inventory>(pub Arc<T>);
+                    impl<
+                        T: Inventory,
+                    > tonic::server::UnaryService<super::SystemInfoRequest>
+                    for GetSystemInfoSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SystemInfoRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Inventory>::get_system_info(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetSystemInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Inventory/RegisterGPUs" => {
+                    #[allow(non_camel_case_types)]
+                    struct RegisterGPUsSvc<T: Inventory>(pub Arc<T>);
+                    impl<T: Inventory> tonic::server::UnaryService<super::GpuRequest>
+                    for RegisterGPUsSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GpuRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Inventory>::register_gp_us(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RegisterGPUsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Inventory/ReportSystemctl" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportSystemctlSvc<T: Inventory>(pub Arc<T>);
+                    impl<
+                        T: Inventory,
+                    > tonic::server::UnaryService<super::SystemctlRequest>
+                    for ReportSystemctlSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SystemctlRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Inventory>::report_systemctl(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportSystemctlSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Inventory/StreamSystemctl" => {
+                    #[allow(non_camel_case_types)]
+                    struct StreamSystemctlSvc<T: Inventory>(pub Arc<T>);
+                    impl<
+                        T: Inventory,
+                    > tonic::server::ClientStreamingService<super::SystemctlRequest>
+                    for StreamSystemctlSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::SystemctlRequest>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Inventory>::stream_systemctl(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = StreamSystemctlSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.client_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Inventory/RegisterContainers" => {
+                    #[allow(non_camel_case_types)]
+                    struct RegisterContainersSvc<T: Inventory>(pub Arc<T>);
+                    impl<
+                        T: Inventory,
+                    > tonic::server::UnaryService<super::ContainerRequest>
+                    for RegisterContainersSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ContainerRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Inventory>::register_containers(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = RegisterContainersSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(
+                            tonic::body::Body::default(),
+                        );
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for InventoryServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "monitor.Inventory";
+    impl<T> tonic::server::NamedService for InventoryServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
+/// Generated client implementations.
+pub mod control_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// Configuration pushed to agents and status read back by dashboards
+    #[derive(Debug, Clone)]
+    pub struct ControlClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl ControlClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> ControlClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::Body>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> ControlClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::Body>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            ControlClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn watch_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::AgentConfig>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/WatchConfig",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "WatchConfig"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        pub async fn get_system_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SystemStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/GetSystemStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "GetSystemStatus"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_release(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ReleaseRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ReleaseManifest>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/GetRelease",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "GetRelease"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_health(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AgentHealthEvent>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/ReportHealth",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "ReportHealth"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_agent_event(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AgentEvent>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/ReportAgentEvent",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "ReportAgentEvent"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_command(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CommandAudit>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/ReportCommand",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "ReportCommand"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Operator call, authorized with ADMIN_TOKEN as `authorization: Bearer` metadata
+        pub async fn set_maintenance(
+            &mut self,
+            request: impl tonic::IntoRequest<super::MaintenanceRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::MaintenanceStatus>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/SetMaintenance",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "SetMaintenance"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Opened by agents, carries dashboard websocket sessions the hub relays to them
+        pub async fn open_sessions(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::SessionFrame>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::SessionFrame>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Control/OpenSessions",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.Control", "OpenSessions"));
+            self.inner.streaming(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod control_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with ControlServer.
+    #[async_trait]
+    pub trait Control: std::marker::Send + std::marker::Sync + 'static {
+        /// Server streaming response type for the WatchConfig method.
+        type WatchConfigStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::AgentConfig, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        async fn watch_config(
+            &self,
+            request: tonic::Request<super::WatchConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::WatchConfigStream>,
+            tonic::Status,
+        >;
+        async fn get_system_status(
+            &self,
+            request: tonic::Request<super::SystemStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SystemStatusResponse>,
+            tonic::Status,
+        >;
+        async fn get_release(
+            &self,
+            request: tonic::Request<super::ReleaseRequest>,
+        ) -> std::result::Result<tonic::Response<super::ReleaseManifest>, tonic::Status>;
+        async fn report_health(
+            &self,
+            request: tonic::Request<super::AgentHealthEvent>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_agent_event(
+            &self,
+            request: tonic::Request<super::AgentEvent>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_command(
+            &self,
+            request: tonic::Request<super::CommandAudit>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        /// Operator call, authorized with ADMIN_TOKEN as `authorization: Bearer` metadata
+        async fn set_maintenance(
+            &self,
+            request: tonic::Request<super::MaintenanceRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::MaintenanceStatus>,
+            tonic::Status,
+        >;
+        /// Server streaming response type for the OpenSessions method.
+        type OpenSessionsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::SessionFrame, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// Opened by agents, carries dashboard websocket sessions the hub relays to them
+        async fn open_sessions(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::SessionFrame>>,
+        ) -> std::result::Result<
+            tonic::Response<Self::OpenSessionsStream>,
+            tonic::Status,
+        >;
+    }
+    /// Configuration pushed to agents and status read back by dashboards
+    #[derive(Debug)]
+    pub struct ControlServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> ControlServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for ControlServer<T>
+    where
+        T: Control,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::Body>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/monitor.Control/WatchConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchConfigSvc<T: Control>(pub Arc<T>);
+                    impl<
+                        T: Control,
+                    > tonic::server::ServerStreamingService<super::WatchConfigRequest>
+                    for WatchConfigSvc<T> {
+                        type Response = super::AgentConfig;
+                        type ResponseStream = T::WatchConfigStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::watch_config(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = WatchConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/GetSystemStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSystemStatusSvc<T: Control>(pub Arc<T>);
+                    impl<
+                        T: Control,
+                    > tonic::server::UnaryService<super::SystemStatusRequest>
+                    for GetSystemStatusSvc<T> {
+                        type Response = super::SystemStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SystemStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::get_system_status(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetSystemStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/GetRelease" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetReleaseSvc<T: Control>(pub Arc<T>);
+                    impl<T: Control> tonic::server::UnaryService<super::ReleaseRequest>
+                    for GetReleaseSvc<T> {
+                        type Response = super::ReleaseManifest;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ReleaseRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::get_release(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetReleaseSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/ReportHealth" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportHealthSvc<T: Control>(pub Arc<T>);
+                    impl<T: Control> tonic::server::UnaryService<super::AgentHealthEvent>
+                    for ReportHealthSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AgentHealthEvent>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::report_health(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportHealthSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/ReportAgentEvent" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportAgentEventSvc<T: Control>(pub Arc<T>);
+                    impl<T: Control> tonic::server::UnaryService<super::AgentEvent>
+                    for ReportAgentEventSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AgentEvent>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::report_agent_event(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportAgentEventSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/ReportCommand" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportCommandSvc<T: Control>(pub Arc<T>);
+                    impl<T: Control> tonic::server::UnaryService<super::CommandAudit>
+                    for ReportCommandSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CommandAudit>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::report_command(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportCommandSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/SetMaintenance" => {
+                    #[allow(non_camel_case_types)]
+                    struct SetMaintenanceSvc<T: Control>(pub Arc<T>);
+                    impl<
+                        T: Control,
+                    > tonic::server::UnaryService<super::MaintenanceRequest>
+                    for SetMaintenanceSvc<T> {
+                        type Response = super::MaintenanceStatus;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::MaintenanceRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::set_maintenance(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = SetMaintenanceSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.Control/OpenSessions" => {
+                    #[allow(non_camel_case_types)]
+                    struct OpenSessionsSvc<T: Control>(pub Arc<T>);
+                    impl<T: Control> tonic::server::StreamingService<super::SessionFrame>
+                    for OpenSessionsSvc<T> {
+                        type Response = super::SessionFrame;
+                        type ResponseStream = T::OpenSessionsStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::SessionFrame>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Control>::open_sessions(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = OpenSessionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(
+                            tonic::body::Body::default(),
+                        );
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for ControlServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "monitor.Control";
+    impl<T> tonic::server::NamedService for ControlServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
+/// Generated client implementations.
+pub mod system_monitor_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// Every RPC of MetricsIngest, Inventory and Control under its original name, kept so agents
+    /// built before the split keep working
+    #[derive(Debug, Clone)]
+    pub struct SystemMonitorClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl SystemMonitorClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> SystemMonitorClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::Body>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> SystemMonitorClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::Body>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            SystemMonitorClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn get_system_info(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemInfoRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/GetSystemInfo",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "GetSystemInfo"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_metrics(
+            &mut self,
+            request: impl tonic::IntoRequest<super::MetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/ReportMetrics",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "ReportMetrics"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn stream_metrics(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::MetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/StreamMetrics",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "StreamMetrics"));
+            self.inner.client_streaming(req, path, codec).await
+        }
+        pub async fn report_systemctl(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemctlRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/ReportSystemctl",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "ReportSystemctl"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn register_gp_us(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GpuRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/RegisterGPUs",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "RegisterGPUs"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_gpu_metrics(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GpuMetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/ReportGPUMetrics",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "ReportGPUMetrics"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn register_containers(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ContainerRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/RegisterContainers",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "RegisterContainers"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn report_container_metrics(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ContainerMetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/ReportContainerMetrics",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("monitor.SystemMonitor", "ReportContainerMetrics"),
+                );
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn watch_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::AgentConfig>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/WatchConfig",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "WatchConfig"));
+            self.inner.server_streaming(req, path, codec).await
+        }
+        pub async fn get_system_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::SystemStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SystemStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.SystemMonitor/GetSystemStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("monitor.SystemMonitor", "GetSystemStatus"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated client implementations.
+pub mod enrollment_client {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    #[derive(Debug, Clone)]
+    pub struct EnrollmentClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl EnrollmentClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> EnrollmentClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::Body>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + std::marker::Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + std::marker::Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> EnrollmentClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::Body>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::Body>,
+            >>::Error: Into<StdError> + std::marker::Send + std::marker::Sync,
+        {
+            EnrollmentClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn enroll(
+            &mut self,
+            request: impl tonic::IntoRequest<super::EnrollRequest>,
+        ) -> std::result::Result<tonic::Response<super::EnrollResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/monitor.Enrollment/Enroll",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("monitor.Enrollment", "Enroll"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod system_monitor_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with SystemMonitorServer.
+    #[async_trait]
+    pub trait SystemMonitor: std::marker::Send + std::marker::Sync + 'static {
+        async fn get_system_info(
+            &self,
+            request: tonic::Request<super::SystemInfoRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_metrics(
+            &self,
+            request: tonic::Request<super::MetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn stream_metrics(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::MetricsRequest>>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_systemctl(
+            &self,
+            request: tonic::Request<super::SystemctlRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn register_gp_us(
+            &self,
+            request: tonic::Request<super::GpuRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_gpu_metrics(
+            &self,
+            request: tonic::Request<super::GpuMetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn register_containers(
+            &self,
+            request: tonic::Request<super::ContainerRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        async fn report_container_metrics(
+            &self,
+            request: tonic::Request<super::ContainerMetricsRequest>,
+        ) -> std::result::Result<tonic::Response<super::Response>, tonic::Status>;
+        /// Server streaming response type for the WatchConfig method.
+        type WatchConfigStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::AgentConfig, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        async fn watch_config(
+            &self,
+            request: tonic::Request<super::WatchConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::WatchConfigStream>,
+            tonic::Status,
+        >;
+        async fn get_system_status(
+            &self,
+            request: tonic::Request<super::SystemStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::SystemStatusResponse>,
+            tonic::Status,
+        >;
+    }
+    /// Every RPC of MetricsIngest, Inventory and Control under its original name, kept so agents
+    /// built before the split keep working
+    #[derive(Debug)]
+    pub struct SystemMonitorServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> SystemMonitorServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for SystemMonitorServer<T>
+    where
+        T: SystemMonitor,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::Body>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/monitor.SystemMonitor/GetSystemInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSystemInfoSvc<T: SystemMonitor>(pub Arc<T>);
+                    impl<
+                        T: SystemMonitor,
+                    > tonic::server::UnaryService<super::SystemInfoRequest>
+                    for GetSystemInfoSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SystemInfoRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as SystemMonitor>::get_system_info(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetSystemInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.SystemMonitor/ReportMetrics" => {
+                    #[allow(non_camel_case_types)]
+                    struct ReportMetricsSvc<T: SystemMonitor>(pub Arc<T>);
+                    impl<
+                        T: SystemMonitor,
+                    > tonic::server::UnaryService<super::MetricsRequest>
+                    for ReportMetricsSvc<T> {
+                        type Response = super::Response;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::MetricsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as SystemMonitor>::report_metrics(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ReportMetricsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.SystemMonitor/StreamMetrics" => {
                     #[allow(non_camel_case_types)]
                     struct StreamMetricsSvc<T: SystemMonitor>(pub Arc<T>);
                     impl<
@@ -963,6 +3628,98 @@ pub mod system_monitor_server {
                     };
                     Box::pin(fut)
                 }
+                "/monitor.SystemMonitor/WatchConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchConfigSvc<T: SystemMonitor>(pub Arc<T>);
+                    impl<
+                        T: SystemMonitor,
+                    > tonic::server::ServerStreamingService<super::WatchConfigRequest>
+                    for WatchConfigSvc<T> {
+                        type Response = super::AgentConfig;
+                        type ResponseStream = T::WatchConfigStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as SystemMonitor>::watch_config(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = WatchConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/monitor.SystemMonitor/GetSystemStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetSystemStatusSvc<T: SystemMonitor>(pub Arc<T>);
+                    impl<
+                        T: SystemMonitor,
+                    > tonic::server::UnaryService<super::SystemStatusRequest>
+                    for GetSystemStatusSvc<T> {
+                        type Response = super::SystemStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::SystemStatusRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as SystemMonitor>::get_system_status(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = GetSystemStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(
@@ -1003,3 +3760,180 @@ pub mod system_monitor_server {
         const NAME: &'static str = SERVICE_NAME;
     }
 }
+/// Generated server implementations.
+pub mod enrollment_server {
+    #![allow(
+        unused_variables,
+        dead_code,
+        missing_docs,
+        clippy::wildcard_imports,
+        clippy::let_unit_value,
+    )]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with EnrollmentServer.
+    #[async_trait]
+    pub trait Enrollment: std::marker::Send + std::marker::Sync + 'static {
+        async fn enroll(
+            &self,
+            request: tonic::Request<super::EnrollRequest>,
+        ) -> std::result::Result<tonic::Response<super::EnrollResponse>, tonic::Status>;
+    }
+    #[derive(Debug)]
+    pub struct EnrollmentServer<T> {
+        inner: Arc<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    impl<T> EnrollmentServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for EnrollmentServer<T>
+    where
+        T: Enrollment,
+        B: Body + std::marker::Send + 'static,
+        B::Error: Into<StdError> + std::marker::Send + 'static,
+    {
+        type Response = http::Response<tonic::body::Body>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            match req.uri().path() {
+                "/monitor.Enrollment/Enroll" => {
+                    #[allow(non_camel_case_types)]
+                    struct EnrollSvc<T: Enrollment>(pub Arc<T>);
+                    impl<T: Enrollment> tonic::server::UnaryService<super::EnrollRequest>
+                    for EnrollSvc<T> {
+                        type Response = super::EnrollResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::EnrollRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Enrollment>::enroll(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = EnrollSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        let mut response = http::Response::new(
+                            tonic::body::Body::default(),
+                        );
+                        let headers = response.headers_mut();
+                        headers
+                            .insert(
+                                tonic::Status::GRPC_STATUS,
+                                (tonic::Code::Unimplemented as i32).into(),
+                            );
+                        headers
+                            .insert(
+                                http::header::CONTENT_TYPE,
+                                tonic::metadata::GRPC_CONTENT_TYPE,
+                            );
+                        Ok(response)
+                    })
+                }
+            }
+        }
+    }
+    impl<T> Clone for EnrollmentServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    /// Generated gRPC service name
+    pub const SERVICE_NAME: &str = "monitor.Enrollment";
+    impl<T> tonic::server::NamedService for EnrollmentServer<T> {
+        const NAME: &'static str = SERVICE_NAME;
+    }
+}
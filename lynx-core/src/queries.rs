@@ -1,17 +1,32 @@
 pub mod alert_queries {
     pub const GET_ALERT_SYSTEMS: &str = "SELECT rule_id FROM alert_systems WHERE system_id = $1";
 
-    pub const GET_ALERT_RULES: &str = "SELECT id, name, description, active, expression, severity FROM alert_rules WHERE id = $1 AND active = true";
+    pub const GET_TARGETED_RULES: &str =
+        "SELECT id, target FROM alert_rules WHERE target IS NOT NULL AND active = true";
 
-    pub const GET_ALERT_NOTIFIERS: &str =
-        "SELECT rule_id, notifier_id FROM alert_notifiers WHERE rule_id = $1";
+    pub const GET_SYSTEM_TAGS: &str = "SELECT tags FROM systems WHERE id = $1";
 
-    pub const GET_NOTIFIERS: &str = "SELECT id, type, value FROM notifiers WHERE id = $1";
+    pub const GET_ALERT_RULES: &str = "SELECT id, name, description, active, expression, severity, for_secs, annotations FROM alert_rules WHERE id = ANY($1) AND active = true";
+
+    pub const GET_RULE_NOTIFIERS: &str = "SELECT an.rule_id, n.id, n.value FROM alert_notifiers an JOIN notifiers n ON n.id = an.notifier_id WHERE an.rule_id = ANY($1)";
 
     pub const GET_EXISTING_ALERT: &str = "SELECT id FROM alert_history WHERE system = $1 AND alert = $2 AND date >= NOW() - INTERVAL '30 minutes'";
 
+    /// Built-in rules have no alert_rules row, they are listed with rule_id 0 and are critical.
+    pub const GET_ACTIVE_ALERTS: &str = "SELECT DISTINCT ON (COALESCE(r.id, 0), h.rule) COALESCE(r.id, 0) AS id, COALESCE(r.name, h.rule) AS name, COALESCE(r.severity, 'critical') AS severity, h.date FROM alert_history h LEFT JOIN alert_rules r ON r.id = h.alert WHERE h.system = $1 AND h.date >= NOW() - INTERVAL '30 minutes' AND (r.id IS NOT NULL OR h.rule IS NOT NULL) ORDER BY COALESCE(r.id, 0), h.rule, h.date DESC";
+
     pub const UPDATE_ALERT_HISTORY: &str = "UPDATE alert_history SET date = NOW() WHERE id = $1";
 
+    pub const GET_SYSTEM_OWNER_NOTIFIERS: &str = "SELECT n.id, n.value FROM notifiers n JOIN systems s ON s.admin = n.\"user\" WHERE s.id = $1";
+
+    pub const GET_ADMIN_NOTIFIERS: &str =
+        "SELECT n.value FROM notifiers n JOIN users u ON u.id = n.\"user\" WHERE u.admin = true";
+
+    pub const GET_SMART_BASELINES: &str = "SELECT DISTINCT ON (COALESCE(NULLIF(serial, ''), device)) device, serial, passed, reallocated_sectors, pending_sectors, uncorrectable_sectors, media_errors FROM smart_attributes WHERE system_id = $1 AND time >= NOW() - $2 * INTERVAL '1 hour' ORDER BY COALESCE(NULLIF(serial, ''), device), time";
+
     pub const INSERT_ALERT_HISTORY: &str =
-        "INSERT INTO alert_history (system, alert, date) VALUES ($1, $2, NOW())";
+        "INSERT INTO alert_history (system, alert, date) VALUES ($1, $2, NOW()) RETURNING id";
+
+    pub const INSERT_BUILTIN_ALERT_HISTORY: &str =
+        "INSERT INTO alert_history (system, rule, date) VALUES ($1, $2, NOW()) RETURNING id";
 }
@@ -1,71 +1,378 @@
+use crate::leader::Leadership;
 use log::{info, warn};
-use sqlx::PgPool;
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use std::time::Duration;
+use tokio::time::interval;
 
-pub async fn prune_old_metrics(pool: &PgPool, older_than_days: i64) -> Result<(), sqlx::Error> {
-    if older_than_days <= 0 {
-        return Ok(());
+/*
+ * Retention
+ * The leader prunes rows older than each table's retention every RETENTION_INTERVAL.
+ * RETENTION_DAYS (default 30) applies to every table, RETENTION_<TABLE>_DAYS overrides it for
+ * one, 0 keeps a table's rows forever. alert_history is kept unless it gets its own setting,
+ * process_samples at most 7 days.
+ * With RETENTION_ROLLUP_DAYS metrics, disks and gpu_metrics rows are first rolled up into hourly
+ * averages in metrics_hourly / disks_hourly / gpu_metrics_hourly, which are kept that many days. RETENTION_DRY_RUN only logs
+ * what would be removed, GET /retention on the HTTP API reports the same at any time.
+ */
+
+const RETENTION_INTERVAL: Duration = Duration::from_secs(3600);
+const BATCH_LIMIT: i64 = 10_000;
+
+/// Pruned tables with their time column, rolled up tables before their rollups.
+pub const RETAINED: &[(&str, &str)] = &[
+    ("metrics", "time"),
+    ("disks", "time"),
+    ("gpu_metrics", "time"),
+    ("container_metrics", "time"),
+    ("auth_events", "time"),
+    ("probe_results", "time"),
+    ("rejected_reports", "time"),
+    ("agent_health_events", "time"),
+    ("agent_events", "received"),
+    ("notification_deliveries", "time"),
+    ("alert_history", "date"),
+    ("metrics_hourly", "time"),
+    ("disks_hourly", "time"),
+    ("gpu_metrics_hourly", "time"),
+    ("process_samples", "time"),
+    ("smart_attributes", "time"),
+];
+
+/// A raw table averaged per hour into its `_hourly` table before it is pruned.
+struct Rollup {
+    table: &'static str,
+    /// Columns identifying a series, one rollup row per series and hour
+    series: &'static str,
+    /// Fills the rollup from the raw rows in [$1, $2)
+    insert: &'static str,
+}
+
+const ROLLUPS: &[Rollup] = &[
+    Rollup {
+        table: "metrics",
+        series: "system_id",
+        insert: "INSERT INTO metrics_hourly (time, system_id, samples, cpu_usage, cpu_usage_max, \
+             cpu_user, cpu_system, cpu_iowait, cpu_irq, cpu_steal, memory_used_bytes, \
+             memory_total_bytes, memory_available_bytes, swap_used_bytes, processes, threads, \
+             procs_running, procs_zombie, procs_blocked, ctxt_per_sec, intr_per_sec, \
+             net_in_bytes_per_sec, net_out_bytes_per_sec, load_one, load_five, load_fifteen) \
+             SELECT date_trunc('hour', time), system_id, count(*), avg(cpu_usage), \
+             max(cpu_usage), avg(cpu_user), avg(cpu_system), avg(cpu_iowait), avg(cpu_irq), \
+             avg(cpu_steal), avg(memory_used_bytes), max(memory_total_bytes), \
+             avg(memory_available_bytes), avg(swap_used_bytes), avg(processes), avg(threads), \
+             avg(procs_running), avg(procs_zombie), avg(procs_blocked), avg(ctxt_per_sec), \
+             avg(intr_per_sec), avg(net_in_bytes_per_sec), avg(net_out_bytes_per_sec), \
+             avg(load_one), avg(load_five), avg(load_fifteen) \
+             FROM metrics WHERE time >= $1 AND time < $2 \
+             GROUP BY 1, 2 \
+             ON CONFLICT (system_id, time) DO NOTHING",
+    },
+    Rollup {
+        table: "disks",
+        series: "system, name",
+        insert: "INSERT INTO disks_hourly (time, system, name, mount_point, samples, \
+             space_bytes, used_bytes, read, write, read_iops, write_iops, inodes_total, \
+             inodes_used) \
+             SELECT date_trunc('hour', time), system, name, max(mount_point), count(*), \
+             avg(space_bytes), avg(used_bytes), avg(read), avg(write), avg(read_iops), \
+             avg(write_iops), \
+             max(inodes_total), avg(inodes_used) \
+             FROM disks WHERE time >= $1 AND time < $2 \
+             GROUP BY 1, 2, 3 \
+             ON CONFLICT (system, name, time) DO NOTHING",
+    },
+    Rollup {
+        table: "gpu_metrics",
+        series: "gpu_id",
+        insert: "INSERT INTO gpu_metrics_hourly (time, gpu_id, samples, utilization, \
+             utilization_max, memory_used_mb, temperature, temperature_max, power) \
+             SELECT date_trunc('hour', time), gpu_id, count(*), avg(utilization), \
+             max(utilization), avg(memory_used_mb), avg(temperature), max(temperature), \
+             avg(power) \
+             FROM gpu_metrics WHERE time >= $1 AND time < $2 \
+             GROUP BY 1, 2 \
+             ON CONFLICT (gpu_id, time) DO NOTHING",
+    },
+];
+
+#[derive(Clone, Debug)]
+pub struct RetentionOptions {
+    /// Days kept per table of RETAINED, 0 or less keeps its rows forever
+    pub days: Vec<(&'static str, i64)>,
+    /// Days hourly rollups are kept, 0 prunes the rolled up tables without rolling them up
+    pub rollup_days: i64,
+    /// Only count the rows that would be removed
+    pub dry_run: bool,
+}
+
+impl RetentionOptions {
+    /// Reads RETENTION_DAYS, RETENTION_<TABLE>_DAYS, RETENTION_ROLLUP_DAYS and RETENTION_DRY_RUN.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let parse = |key: &str| lookup(key).and_then(|v| v.trim().parse::<i64>().ok());
+        let default_days = parse("RETENTION_DAYS").unwrap_or(30);
+        let rollup_days = parse("RETENTION_ROLLUP_DAYS").unwrap_or(0).max(0);
+        let days = RETAINED
+            .iter()
+            .map(|(table, _)| {
+                let default = match *table {
+                    "alert_history" => 0,
+                    "metrics_hourly" | "disks_hourly" | "gpu_metrics_hourly" => rollup_days,
+                    // a row per process and report, only useful for recent incidents
+                    "process_samples" => default_days.min(7),
+                    _ => default_days,
+                };
+                let key = format!("RETENTION_{}_DAYS", table.to_uppercase());
+                (*table, parse(&key).unwrap_or(default))
+            })
+            .collect();
+        let dry_run = lookup("RETENTION_DRY_RUN").and_then(|v| v.trim().parse::<bool>().ok());
+        Self {
+            days,
+            rollup_days,
+            dry_run: dry_run.unwrap_or(false),
+        }
     }
 
-    let tables: &[(&str, &str)] = &[
-        ("gpu_metrics", "time"),
-        ("container_metrics", "time"),
-        ("metrics", "time"),
-    ];
-
-    const BATCH_LIMIT: i64 = 10_000;
-    let mut total_deleted: i64 = 0;
-
-    for (table, col) in tables {
-        let mut table_deleted: i64 = 0;
-
-        loop {
-            let sql = format!(
-                "WITH c AS (
-                    SELECT ctid FROM {table}
-                    WHERE {col} < NOW() - ($1 * INTERVAL '1 day')
-                    LIMIT {batch}
-                 )
-                 DELETE FROM {table} t
-                 USING c
-                 WHERE t.ctid = c.ctid",
-                table = table,
-                col = col,
-                batch = BATCH_LIMIT
-            );
+    /// Days `table` keeps its rows, 0 when it is kept forever.
+    pub fn days_for(&self, table: &str) -> i64 {
+        self.days
+            .iter()
+            .find(|(t, _)| *t == table)
+            .map_or(0, |(_, days)| (*days).max(0))
+    }
 
-            let res = sqlx::query(&sql)
-                .bind(older_than_days)
-                .execute(pool)
-                .await?;
-            let affected = res.rows_affected() as i64;
+    /// Whether any table is pruned at all.
+    pub fn enabled(&self) -> bool {
+        self.days.iter().any(|(_, days)| *days > 0)
+    }
+}
 
-            if affected == 0 {
-                break;
-            }
-            table_deleted += affected;
-            total_deleted += affected;
+#[derive(Clone, Debug, Serialize)]
+pub struct TableReport {
+    pub table: &'static str,
+    pub older_than_days: i64,
+    /// Rows removed, or that would be removed on a dry run
+    pub rows: u64,
+    /// Hourly rows written to the table's rollup
+    pub rolled_up: u64,
+}
 
-            if affected < BATCH_LIMIT {
-                break;
-            }
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RetentionReport {
+    pub dry_run: bool,
+    /// Tables with rows past their retention, kept tables are left out
+    pub tables: Vec<TableReport>,
+}
+
+impl RetentionReport {
+    pub fn total_rows(&self) -> u64 {
+        self.tables.iter().map(|t| t.rows).sum()
+    }
+}
+
+fn rollup_of(table: &str) -> Option<&'static Rollup> {
+    ROLLUPS.iter().find(|r| r.table == table)
+}
+
+/// Counts what pruning `table` would do, without touching it.
+async fn count(
+    pool: &PgPool,
+    table: &'static str,
+    col: &str,
+    days: i64,
+    rollup: Option<&Rollup>,
+) -> Result<TableReport, sqlx::Error> {
+    let hours = match rollup {
+        Some(r) => format!("count(DISTINCT (date_trunc('hour', time), {}))", r.series),
+        None => "0".to_string(),
+    };
+    let row = sqlx::query(&format!(
+        "SELECT count(*) AS rows, {hours}::bigint AS hours FROM {table} \
+         WHERE {col} < NOW() - ($1 * INTERVAL '1 day')"
+    ))
+    .bind(days)
+    .fetch_one(pool)
+    .await?;
+    let rows: i64 = row.get("rows");
+    let hours: i64 = row.get("hours");
+    Ok(TableReport {
+        table,
+        older_than_days: days,
+        rows: rows as u64,
+        rolled_up: hours as u64,
+    })
+}
+
+/// Deletes `table`'s rows older than `days` in batches, so no statement holds locks for long.
+async fn delete(pool: &PgPool, table: &str, col: &str, days: i64) -> Result<u64, sqlx::Error> {
+    let sql = format!(
+        "WITH c AS (
+            SELECT ctid FROM {table}
+            WHERE {col} < NOW() - ($1 * INTERVAL '1 day')
+            LIMIT {BATCH_LIMIT}
+         )
+         DELETE FROM {table} t
+         USING c
+         WHERE t.ctid = c.ctid"
+    );
+    let mut deleted = 0;
+    loop {
+        let affected = sqlx::query(&sql)
+            .bind(days)
+            .execute(pool)
+            .await?
+            .rows_affected();
+        deleted += affected;
+        if affected < BATCH_LIMIT as u64 {
+            return Ok(deleted);
         }
+    }
+}
+
+/*
+ * roll_up
+ * Moves the rows of `table` older than `days` into hourly averages, one hour per transaction:
+ * the averages are written and the raw rows deleted together, so a failing run leaves every
+ * sample in one of the two. The cutoff is a full hour so no hour is averaged twice.
+ */
+async fn roll_up(
+    pool: &PgPool,
+    table: &str,
+    insert: &str,
+    days: i64,
+) -> Result<(u64, u64), sqlx::Error> {
+    let cutoff: chrono::DateTime<chrono::Utc> =
+        sqlx::query_scalar("SELECT date_trunc('hour', NOW() - ($1 * INTERVAL '1 day'))")
+            .bind(days)
+            .fetch_one(pool)
+            .await?;
+    let (mut deleted, mut rolled_up) = (0, 0);
+    loop {
+        let start: Option<chrono::DateTime<chrono::Utc>> = sqlx::query_scalar(&format!(
+            "SELECT date_trunc('hour', min(time))::timestamptz FROM {table} WHERE time < $1"
+        ))
+        .bind(cutoff)
+        .fetch_one(pool)
+        .await?;
+        let Some(start) = start else {
+            return Ok((deleted, rolled_up));
+        };
+        let end = start + chrono::Duration::hours(1);
 
-        if table_deleted > 0 {
-            info!("[retention] Pruned {table_deleted} rows from {table}");
+        let mut tx = pool.begin().await?;
+        rolled_up += sqlx::query(insert)
+            .bind(start)
+            .bind(end)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        deleted += sqlx::query(&format!(
+            "DELETE FROM {table} WHERE time >= $1 AND time < $2"
+        ))
+        .bind(start)
+        .bind(end)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+    }
+}
+
+/*
+ * prune
+ * Applies the retention of every table, or with `dry_run` only counts the rows it would remove
+ * and the hourly rows it would write. Tables without rows past their retention are left out of
+ * the report.
+ */
+pub async fn prune(
+    pool: &PgPool,
+    options: &RetentionOptions,
+    dry_run: bool,
+) -> Result<RetentionReport, sqlx::Error> {
+    let mut report = RetentionReport {
+        dry_run,
+        tables: Vec::new(),
+    };
+    for &(table, col) in RETAINED {
+        let days = options.days_for(table);
+        if days == 0 {
+            continue;
+        }
+        let rollup = rollup_of(table).filter(|_| options.rollup_days > 0);
+        let table_report = if dry_run {
+            count(pool, table, col, days, rollup).await?
+        } else {
+            let (rows, rolled_up) = match rollup {
+                Some(r) => roll_up(pool, table, r.insert, days).await?,
+                None => (delete(pool, table, col, days).await?, 0),
+            };
+            TableReport {
+                table,
+                older_than_days: days,
+                rows,
+                rolled_up,
+            }
+        };
+        if table_report.rows > 0 {
+            report.tables.push(table_report);
         }
     }
+    Ok(report)
+}
 
-    if total_deleted == 0 {
-        warn!(
-            "[retention] No metric rows to prune (>{} days)",
-            older_than_days
-        );
+fn log_report(report: &RetentionReport) {
+    let verb = if report.dry_run {
+        "Would prune"
+    } else {
+        "Pruned"
+    };
+    for t in &report.tables {
+        if t.rolled_up > 0 {
+            info!(
+                "[retention] {verb} {} rows from {} (older than {} days) into {} hourly rows",
+                t.rows, t.table, t.older_than_days, t.rolled_up
+            );
+        } else {
+            info!(
+                "[retention] {verb} {} rows from {} (older than {} days)",
+                t.rows, t.table, t.older_than_days
+            );
+        }
+    }
+    if report.tables.is_empty() {
+        info!("[retention] No rows past their retention");
     } else {
-        info!(
-            "[retention] Total pruned rows: {} (older than {} days)",
-            total_deleted, older_than_days
-        );
+        info!("[retention] {verb} {} rows in total", report.total_rows());
     }
+}
 
-    Ok(())
+/// Prunes every RETENTION_INTERVAL while this hub leads.
+pub async fn run_retention(pool: PgPool, options: RetentionOptions, leadership: Leadership) {
+    if !options.enabled() {
+        warn!("[retention] Retention policy is disabled");
+        return;
+    }
+    info!(
+        "[retention] Retention policy active: {}{}",
+        options
+            .days
+            .iter()
+            .filter(|(_, days)| *days > 0)
+            .map(|(table, days)| format!("{table} {days}d"))
+            .collect::<Vec<_>>()
+            .join(", "),
+        if options.dry_run { " (dry run)" } else { "" }
+    );
+    let mut tick = interval(RETENTION_INTERVAL);
+    loop {
+        tick.tick().await;
+        if !leadership.is_leader() {
+            continue;
+        }
+        match prune(&pool, &options, options.dry_run).await {
+            Ok(report) => log_report(&report),
+            Err(e) => warn!("[retention] Prune failed: {e}"),
+        }
+    }
 }
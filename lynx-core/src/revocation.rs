@@ -0,0 +1,211 @@
+use crate::config::TlsOptions;
+use dashmap::DashMap;
+use log::{info, warn};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::ocsp::{
+    OcspCertId, OcspCertStatus, OcspFlag, OcspRequest, OcspResponse, OcspResponseStatus,
+};
+use openssl::stack::Stack;
+use openssl::x509::store::X509StoreBuilder;
+use openssl::x509::{CrlStatus, X509Crl, X509};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+const CRL_RELOAD_INTERVAL: Duration = Duration::from_secs(3600);
+const OCSP_CACHE_TTL: Duration = Duration::from_secs(3600);
+const OCSP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Error, Debug)]
+pub enum RevocationError {
+    #[error("Certificate {0} has been revoked")]
+    Revoked(String),
+    #[error("OCSP error: {0}")]
+    Ocsp(String),
+    #[error("OpenSSL error: {0}")]
+    Ssl(#[from] ErrorStack),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/*
+ * RevocationChecker
+ * Checks agent client certificates against CRLs issued by our CA and, optionally, the OCSP
+ * responder named in the certificate. tonic's TLS stack has no revocation support, so this runs
+ * per request in MyMonitor once the handshake already succeeded.
+ */
+pub struct RevocationChecker {
+    ca: X509,
+    crl_files: Vec<PathBuf>,
+    crls: RwLock<Vec<X509Crl>>,
+    ocsp: bool,
+    /// fingerprint -> time the last GOOD answer was received
+    ocsp_cache: DashMap<String, Instant>,
+    client: reqwest::Client,
+}
+
+impl RevocationChecker {
+    /// Returns None when neither CRLs nor OCSP are configured.
+    pub async fn from_options(
+        certs_dir: &Path,
+        opts: &TlsOptions,
+    ) -> Result<Option<Self>, RevocationError> {
+        if opts.crl_files.is_empty() && !opts.ocsp {
+            return Ok(None);
+        }
+        let ca = X509::from_pem(&std::fs::read(certs_dir.join("ca.crt"))?)?;
+        let checker = Self {
+            ca,
+            crl_files: opts.crl_files.clone(),
+            crls: RwLock::new(Vec::new()),
+            ocsp: opts.ocsp,
+            ocsp_cache: DashMap::new(),
+            client: reqwest::Client::builder()
+                .timeout(OCSP_TIMEOUT)
+                .build()
+                .map_err(|e| RevocationError::Ocsp(e.to_string()))?,
+        };
+        checker.reload_crls().await?;
+        Ok(Some(checker))
+    }
+
+    /*
+     * reload_crls
+     * Re-reads the configured CRL files. CRLs that aren't signed by our CA are skipped so a
+     * misplaced file can't revoke (or un-revoke) anything.
+     */
+    pub async fn reload_crls(&self) -> Result<usize, RevocationError> {
+        let ca_key = self.ca.public_key()?;
+        let mut crls = Vec::new();
+        for path in &self.crl_files {
+            let crl = X509Crl::from_pem(&std::fs::read(path)?)?;
+            if !crl.verify(&ca_key)? {
+                warn!("[tls] CRL {:?} is not signed by the CA, ignoring", path);
+                continue;
+            }
+            crls.push(crl);
+        }
+        let count = crls.len();
+        *self.crls.write().await = crls;
+        Ok(count)
+    }
+
+    /// Fails when the DER encoded client certificate is revoked.
+    pub async fn check(&self, der: &[u8]) -> Result<(), RevocationError> {
+        let cert = X509::from_der(der)?;
+        let serial = cert.serial_number().to_bn()?.to_hex_str()?.to_string();
+
+        for crl in self.crls.read().await.iter() {
+            if let CrlStatus::Revoked(_) = crl.get_by_cert(&cert) {
+                return Err(RevocationError::Revoked(serial));
+            }
+        }
+
+        if self.ocsp {
+            self.check_ocsp(&cert, der, serial).await?;
+        }
+        Ok(())
+    }
+
+    /*
+     * check_ocsp
+     * Soft-fails: certificates without a responder, or an unreachable responder, are let
+     * through with a warning so an OCSP outage doesn't lock out every agent. Only an explicit
+     * REVOKED answer rejects the certificate.
+     */
+    async fn check_ocsp(
+        &self,
+        cert: &X509,
+        der: &[u8],
+        serial: String,
+    ) -> Result<(), RevocationError> {
+        let fingerprint = crate::tls::cert_fingerprint(der);
+        let cached = self.ocsp_cache.get(&fingerprint).map(|t| t.elapsed());
+        if cached.is_some_and(|age| age < OCSP_CACHE_TTL) {
+            return Ok(());
+        }
+
+        let Some(url) = cert.ocsp_responders()?.iter().next().map(|u| u.to_string()) else {
+            return Ok(());
+        };
+        let body = {
+            let mut request = OcspRequest::new()?;
+            request.add_id(OcspCertId::from_cert(
+                MessageDigest::sha1(),
+                cert,
+                &self.ca,
+            )?)?;
+            request.to_der()?
+        };
+
+        let response = match self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/ocsp-request")
+            .body(body)
+            .send()
+            .await
+        {
+            Ok(resp) => resp.bytes().await,
+            Err(e) => Err(e),
+        };
+        let response = match response {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                warn!("[tls] OCSP responder {url} unreachable, allowing {serial}: {e}");
+                return Ok(());
+            }
+        };
+
+        match self.ocsp_status(cert, &response)? {
+            OcspCertStatus::GOOD => {
+                self.ocsp_cache.insert(fingerprint, Instant::now());
+                Ok(())
+            }
+            OcspCertStatus::REVOKED => Err(RevocationError::Revoked(serial)),
+            _ => Err(RevocationError::Ocsp(format!(
+                "unknown status for {serial}"
+            ))),
+        }
+    }
+
+    /// Verifies an OCSP response against our CA and returns the status it reports for `cert`.
+    fn ocsp_status(&self, cert: &X509, der: &[u8]) -> Result<OcspCertStatus, RevocationError> {
+        let response = OcspResponse::from_der(der)?;
+        if response.status() != OcspResponseStatus::SUCCESSFUL {
+            return Err(RevocationError::Ocsp(
+                "responder did not answer".to_string(),
+            ));
+        }
+        let basic = response.basic()?;
+
+        let mut store = X509StoreBuilder::new()?;
+        store.add_cert(self.ca.clone())?;
+        let store = store.build();
+        let responder_certs: Stack<X509> = Stack::new()?;
+        basic.verify(&responder_certs, &store, OcspFlag::empty())?;
+
+        let id = OcspCertId::from_cert(MessageDigest::sha1(), cert, &self.ca)?;
+        let status = basic.find_status(&id).ok_or_else(|| {
+            RevocationError::Ocsp("certificate missing from response".to_string())
+        })?;
+        // allow 5 minutes of clock skew between us and the responder
+        status.check_validity(300, None)?;
+        Ok(status.status)
+    }
+}
+
+pub async fn run_crl_reload(checker: Arc<RevocationChecker>) {
+    let mut tick = tokio::time::interval(CRL_RELOAD_INTERVAL);
+    tick.tick().await;
+    loop {
+        tick.tick().await;
+        match checker.reload_crls().await {
+            Ok(count) => info!("[tls] Reloaded {count} CRL(s)"),
+            Err(e) => warn!("[tls] CRL reload failed, keeping previous CRLs: {e}"),
+        }
+    }
+}
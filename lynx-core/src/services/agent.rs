@@ -1,47 +1,279 @@
+use crate::config::AgentRelease;
+use crate::services::releases;
+use crate::signing::{ReleaseSigner, SigningError};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 use uuid::Uuid;
 
-/// Generate an installation script for an inactive (pending) agent.
-/// Activates the agent (sets key + active=true) if hostname + token match.
-pub async fn generate_agent_install_script(
-    hostname: &str,
-    token: &str,
+#[derive(Error, Debug)]
+pub enum InstallScriptError {
+    #[error("Invalid hostname or token")]
+    InvalidToken,
+    #[error("{0} is not configured, refusing to generate an unpinned install script")]
+    NotConfigured(&'static str),
+    #[error("{0} contains characters that are not safe to embed in a shell script")]
+    UnsafeValue(&'static str),
+    #[error("The agent binary checksum must be a hex encoded sha256")]
+    InvalidSha256,
+    #[error("No install script for {0}")]
+    UnsupportedTarget(String),
+    #[error("No stable agent release published for {os} {arch}")]
+    NoRelease { os: String, arch: String },
+    #[error("Signing error: {0}")]
+    Signing(#[from] SigningError),
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+}
+
+/// An install script together with its base64 Ed25519 signature.
+#[derive(Serialize, Debug)]
+pub struct SignedScript {
+    pub script: String,
+    pub signature: String,
+    /// `bash`, `sh` or `powershell`, how the script is meant to be run
+    pub shell: &'static str,
+}
+
+/// Values end up inside double quotes in the script, anything that could break out is refused.
+fn shell_safe(name: &'static str, value: &str) -> Result<(), InstallScriptError> {
+    if value.contains(['"', '$', '`', '\\', '\n']) {
+        return Err(InstallScriptError::UnsafeValue(name));
+    }
+    Ok(())
+}
+
+pub const INSTALL_ARCHES: &[&str] = &["x86_64", "aarch64"];
+
+/// How the installed agent is kept running.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum InitSystem {
+    Systemd,
+    OpenRc,
+    /// Windows service wrapped by NSSM, installed through PowerShell
+    Nssm,
+    /// FreeBSD rc.d script supervising the agent with daemon(8)
+    #[serde(rename = "rc.d")]
+    RcD,
+}
+
+/// What an install script is generated for, Linux x86_64 with systemd unless asked otherwise.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct InstallTarget {
+    /// `linux`, `windows` or `freebsd`, as in Rust's std::env::consts::OS
+    pub os: String,
+    /// `x86_64` or `aarch64`
+    pub arch: String,
+    /// systemd on Linux, NSSM on Windows and rc.d on FreeBSD when unset
+    pub init: Option<InitSystem>,
+    /// Run the agent as the `lynx-agent` user, service control goes through polkit (systemd only)
+    pub unprivileged: bool,
+}
+
+impl Default for InstallTarget {
+    fn default() -> Self {
+        Self {
+            os: "linux".to_string(),
+            arch: "x86_64".to_string(),
+            init: None,
+            unprivileged: false,
+        }
+    }
+}
+
+impl InstallTarget {
+    /// The init system to generate for, refusing combinations there is no script for.
+    pub fn init_system(&self) -> Result<InitSystem, InstallScriptError> {
+        if !INSTALL_ARCHES.contains(&self.arch.as_str()) {
+            return Err(InstallScriptError::UnsupportedTarget(format!(
+                "architecture {}",
+                self.arch
+            )));
+        }
+        let init = match (self.os.as_str(), self.init) {
+            ("linux", None) => InitSystem::Systemd,
+            ("linux", Some(init @ (InitSystem::Systemd | InitSystem::OpenRc))) => init,
+            ("windows", None | Some(InitSystem::Nssm)) => InitSystem::Nssm,
+            ("freebsd", None | Some(InitSystem::RcD)) => InitSystem::RcD,
+            (os, Some(init)) => {
+                return Err(InstallScriptError::UnsupportedTarget(format!(
+                    "{init:?} on {os}"
+                )))
+            }
+            (os, None) => return Err(InstallScriptError::UnsupportedTarget(format!("os {os}"))),
+        };
+        // polkit rules are what let an unprivileged agent manage units, they need systemd
+        if self.unprivileged && init != InitSystem::Systemd {
+            return Err(InstallScriptError::UnsupportedTarget(format!(
+                "unprivileged agent with {init:?}"
+            )));
+        }
+        Ok(init)
+    }
+}
+
+/// The build an install script downloads.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentBinary {
+    pub url: String,
+    pub sha256: String,
+    /// None for AGENT_BIN_URL, which carries no version
+    pub version: Option<String>,
+}
+
+impl AgentBinary {
+    /// AGENT_BIN_URL and AGENT_BIN_SHA256, the Linux x86_64 build used without published releases.
+    pub fn from_config(release: &AgentRelease) -> Result<Self, InstallScriptError> {
+        let url = release
+            .bin_url
+            .clone()
+            .ok_or(InstallScriptError::NotConfigured("AGENT_BIN_URL"))?;
+        let sha256 = release
+            .bin_sha256
+            .clone()
+            .ok_or(InstallScriptError::NotConfigured("AGENT_BIN_SHA256"))?;
+        Ok(Self {
+            url,
+            sha256,
+            version: None,
+        })
+    }
+}
+
+/*
+ * resolve_binary
+ * The newest stable build published in agent_releases for the target. Linux x86_64 falls back
+ * to AGENT_BIN_URL so hubs set up before release channels keep working.
+ */
+pub async fn resolve_binary(
     pool: &sqlx::PgPool,
-) -> Result<String, Box<dyn std::error::Error>> {
-    let agent = sqlx::query!(
-        r"SELECT id FROM systems WHERE hostname = $1 AND token = $2 AND active = false",
-        hostname,
-        token
-    )
-    .fetch_optional(pool)
-    .await?;
+    release: &AgentRelease,
+    target: &InstallTarget,
+) -> Result<AgentBinary, InstallScriptError> {
+    if let Some(latest) = releases::load_latest(pool, "stable", &target.os, &target.arch).await? {
+        return Ok(AgentBinary {
+            url: latest.url,
+            sha256: latest.sha256,
+            version: Some(latest.version),
+        });
+    }
+    if target.os == "linux" && target.arch == "x86_64" {
+        return AgentBinary::from_config(release);
+    }
+    Err(InstallScriptError::NoRelease {
+        os: target.os.clone(),
+        arch: target.arch.clone(),
+    })
+}
 
-    if agent.is_none() {
-        return Err("Invalid hostname or token".into());
+/*
+ * render_install_script
+ * Builds the install script for an agent: bash for systemd and OpenRC, PowerShell for Windows,
+ * sh for FreeBSD.
+ * The binary is only installed when it matches the pinned sha256, and the release public key is
+ * installed so the agent can verify later updates.
+ */
+pub fn render_install_script(
+    binary: &AgentBinary,
+    target: &InstallTarget,
+    server_url: &str,
+    agent_key: &str,
+    release_public_key: &str,
+) -> Result<String, InstallScriptError> {
+    let init = target.init_system()?;
+    if binary.sha256.len() != 64 || !binary.sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(InstallScriptError::InvalidSha256);
     }
+    shell_safe("The agent download URL", &binary.url)?;
+    shell_safe("AGENT_SERVER_URL", server_url)?;
+    let bin_sha256 = binary.sha256.to_lowercase();
+    let script = InstallScript {
+        bin_url: &binary.url,
+        bin_sha256: &bin_sha256,
+        version: binary.version.as_deref().unwrap_or("(unversioned)"),
+        arch: &target.arch,
+        server_url,
+        agent_key,
+        release_public_key,
+    };
+    Ok(match init {
+        InitSystem::Systemd if target.unprivileged => script.linux(&format!(
+            "{UNPRIVILEGED_SETUP}{UNPRIVILEGED_SYSTEMD_SERVICE}"
+        )),
+        InitSystem::Systemd => script.linux(SYSTEMD_SERVICE),
+        InitSystem::OpenRc => script.linux(OPENRC_SERVICE),
+        InitSystem::Nssm => script.windows(),
+        InitSystem::RcD => script.freebsd(),
+    })
+}
 
-    let agent_key = Uuid::new_v4().to_string();
+/// How to run the script generated for an init system.
+pub fn script_shell(init: InitSystem) -> &'static str {
+    match init {
+        InitSystem::Systemd | InitSystem::OpenRc => "bash",
+        InitSystem::Nssm => "powershell",
+        InitSystem::RcD => "sh",
+    }
+}
 
-    let script = format!(
-        r##"#!/bin/bash
-# Auto-generated install script for Lynx Agent
+struct InstallScript<'a> {
+    bin_url: &'a str,
+    bin_sha256: &'a str,
+    version: &'a str,
+    arch: &'a str,
+    server_url: &'a str,
+    agent_key: &'a str,
+    release_public_key: &'a str,
+}
 
-set -euo pipefail
+const SYSTEMD_SERVICE: &str = r##"SERVICE_FILE="/etc/systemd/system/lynx-view-agent.service"
+cat > "$SERVICE_FILE" <<EOF
+[Unit]
+Description=Lynx Agent
+After=network-online.target
 
-BIN_URL="https://example.com/agent/lynx-agent"
-INSTALL_PATH="/usr/local/bin/lynx-view-agent"
-CONFIG_DIR="/etc/lynx-view"
-SERVICE_FILE="/etc/systemd/system/lynx-view-agent.service"
+[Service]
+ExecStart=$INSTALL_PATH
+WorkingDirectory=$CONFIG_DIR
+Restart=always
+RestartSec=5
 
-curl -fsSL "$BIN_URL" -o "$INSTALL_PATH"
-chmod +x "$INSTALL_PATH"
+[Install]
+WantedBy=multi-user.target
+EOF
 
-mkdir -p "$CONFIG_DIR"
-cat > "$CONFIG_DIR/config.toml" <<EOF
-[core]
-server_url = "grpc://localhost:50051"
-agent_key = "{agent_key}"
+systemctl daemon-reload
+systemctl enable --now lynx-view-agent
+"##;
+
+/*
+ * Unprivileged agents
+ * Run as the lynx-agent system user owning the config directory. systemd start/stop/restart
+ * requests go over D-Bus and are granted by the polkit rule below, for every unit; narrow it
+ * down with action.lookup("unit") where the agent should only manage some services.
+ */
+const UNPRIVILEGED_SETUP: &str = r##"AGENT_USER="lynx-agent"
+if ! id "$AGENT_USER" >/dev/null 2>&1; then
+    useradd --system --no-create-home --home-dir "$CONFIG_DIR" --shell /usr/sbin/nologin "$AGENT_USER"
+fi
+chown -R "$AGENT_USER:$AGENT_USER" "$CONFIG_DIR"
+
+mkdir -p /etc/polkit-1/rules.d
+cat > /etc/polkit-1/rules.d/50-lynx-agent.rules <<'EOF'
+polkit.addRule(function(action, subject) {
+    if (action.id == "org.freedesktop.systemd1.manage-units" && subject.user == "lynx-agent") {
+        var verb = action.lookup("verb");
+        if (verb == "start" || verb == "stop" || verb == "restart") {
+            return polkit.Result.YES;
+        }
+    }
+});
 EOF
 
+"##;
+
+const UNPRIVILEGED_SYSTEMD_SERVICE: &str = r##"SERVICE_FILE="/etc/systemd/system/lynx-view-agent.service"
 cat > "$SERVICE_FILE" <<EOF
 [Unit]
 Description=Lynx Agent
@@ -49,6 +281,10 @@ After=network-online.target
 
 [Service]
 ExecStart=$INSTALL_PATH
+WorkingDirectory=$CONFIG_DIR
+User=$AGENT_USER
+Group=$AGENT_USER
+NoNewPrivileges=yes
 Restart=always
 RestartSec=5
 
@@ -58,16 +294,304 @@ EOF
 
 systemctl daemon-reload
 systemctl enable --now lynx-view-agent
+"##;
+
+// supervise-daemon brings the agent back after it exits for an update, like Restart=always
+const OPENRC_SERVICE: &str = r##"SERVICE_FILE="/etc/init.d/lynx-view-agent"
+cat > "$SERVICE_FILE" <<EOF
+#!/sbin/openrc-run
+description="Lynx Agent"
+supervisor=supervise-daemon
+command="$INSTALL_PATH"
+directory="$CONFIG_DIR"
+respawn_delay=5
+respawn_max=0
+
+depend() {
+    need net
+}
+EOF
+chmod 0755 "$SERVICE_FILE"
+
+rc-update add lynx-view-agent default
+rc-service lynx-view-agent restart
+"##;
+
+/*
+ * FreeBSD
+ * daemon(8) restarts the agent after it exits for an update, like Restart=always. rc.d names
+ * can't contain dashes, so the service is lynx_view_agent. The rc.subr variables are escaped
+ * in the heredoc, INSTALL_PATH and CONFIG_DIR are filled in.
+ */
+const RC_D_SERVICE: &str = r##"SERVICE_FILE="/usr/local/etc/rc.d/lynx_view_agent"
+cat > "$SERVICE_FILE" <<EOF
+#!/bin/sh
+
+# PROVIDE: lynx_view_agent
+# REQUIRE: NETWORKING
+# KEYWORD: shutdown
+
+. /etc/rc.subr
+
+name="lynx_view_agent"
+rcvar="lynx_view_agent_enable"
+desc="Lynx Agent"
+
+load_rc_config \$name
+: \${lynx_view_agent_enable:="NO"}
+
+lynx_view_agent_chdir="$CONFIG_DIR"
+pidfile="/var/run/\${name}.pid"
+command="/usr/sbin/daemon"
+command_args="-f -R 5 -P \${pidfile} $INSTALL_PATH"
+
+run_rc_command "\$1"
+EOF
+chmod 0755 "$SERVICE_FILE"
+
+sysrc lynx_view_agent_enable=YES
+service lynx_view_agent restart
+"##;
+
+impl InstallScript<'_> {
+    /// Download, checksum and config shared by systemd and OpenRC, followed by `service`.
+    fn linux(&self, service: &str) -> String {
+        let InstallScript {
+            bin_url,
+            bin_sha256,
+            version,
+            arch,
+            server_url,
+            agent_key,
+            release_public_key,
+        } = self;
+        format!(
+            r##"#!/bin/bash
+# Auto-generated install script for Lynx Agent {version} (linux {arch})
+
+set -euo pipefail
+
+BIN_URL="{bin_url}"
+BIN_SHA256="{bin_sha256}"
+INSTALL_PATH="/usr/local/bin/lynx-view-agent"
+CONFIG_DIR="/etc/lynx-view"
+
+if [ "$(uname -m)" != "{arch}" ]; then
+    echo "This script installs the {arch} agent, this machine is $(uname -m)" >&2
+    exit 1
+fi
+
+TMP_BIN="$(mktemp)"
+trap 'rm -f "$TMP_BIN"' EXIT
+curl -fsSL "$BIN_URL" -o "$TMP_BIN"
+if ! echo "$BIN_SHA256  $TMP_BIN" | sha256sum -c --quiet -; then
+    echo "Checksum mismatch for $BIN_URL, aborting" >&2
+    exit 1
+fi
+install -m 0755 "$TMP_BIN" "$INSTALL_PATH"
+
+mkdir -p "$CONFIG_DIR/certs"
+cat > "$CONFIG_DIR/certs/release.pub" <<'EOF'
+{release_public_key}EOF
+
+cat > "$CONFIG_DIR/config.toml" <<EOF
+[core]
+server_url = "{server_url}"
+agent_key = "{agent_key}"
+EOF
+chmod 600 "$CONFIG_DIR/config.toml"
+
+{service}"##
+        )
+    }
+
+    /// POSIX sh using only the base system: fetch, sha256 and an rc.d script.
+    fn freebsd(&self) -> String {
+        let InstallScript {
+            bin_url,
+            bin_sha256,
+            version,
+            arch,
+            server_url,
+            agent_key,
+            release_public_key,
+        } = self;
+        // uname -m names the architectures as the FreeBSD ports do
+        let machine = match *arch {
+            "aarch64" => "arm64",
+            _ => "amd64",
+        };
+        format!(
+            r##"#!/bin/sh
+# Auto-generated install script for Lynx Agent {version} (freebsd {arch})
+
+set -eu
+
+BIN_URL="{bin_url}"
+BIN_SHA256="{bin_sha256}"
+INSTALL_PATH="/usr/local/bin/lynx-view-agent"
+CONFIG_DIR="/usr/local/etc/lynx-view"
+
+if [ "$(uname -m)" != "{machine}" ]; then
+    echo "This script installs the {machine} agent, this machine is $(uname -m)" >&2
+    exit 1
+fi
+
+TMP_BIN="$(mktemp)"
+trap 'rm -f "$TMP_BIN"' EXIT
+fetch -q -o "$TMP_BIN" "$BIN_URL"
+if [ "$(sha256 -q "$TMP_BIN")" != "$BIN_SHA256" ]; then
+    echo "Checksum mismatch for $BIN_URL, aborting" >&2
+    exit 1
+fi
+install -m 0755 "$TMP_BIN" "$INSTALL_PATH"
+
+mkdir -p "$CONFIG_DIR/certs"
+cat > "$CONFIG_DIR/certs/release.pub" <<'EOF'
+{release_public_key}EOF
+
+cat > "$CONFIG_DIR/config.toml" <<EOF
+[core]
+server_url = "{server_url}"
+agent_key = "{agent_key}"
+EOF
+chmod 600 "$CONFIG_DIR/config.toml"
+
+{RC_D_SERVICE}"##
+        )
+    }
+
+    /// PowerShell for an elevated prompt, the service is wrapped by NSSM.
+    fn windows(&self) -> String {
+        let InstallScript {
+            bin_url,
+            bin_sha256,
+            version,
+            arch,
+            server_url,
+            agent_key,
+            release_public_key,
+        } = self;
+        let processor = match *arch {
+            "aarch64" => "ARM64",
+            _ => "AMD64",
+        };
+        format!(
+            r##"# Auto-generated install script for Lynx Agent {version} (windows {arch})
+# Run it from an elevated PowerShell, NSSM (https://nssm.cc) must be on the PATH
+
+$ErrorActionPreference = "Stop"
+
+$BinUrl = "{bin_url}"
+$BinSha256 = "{bin_sha256}"
+$InstallDir = Join-Path $env:ProgramFiles "Lynx"
+$ConfigDir = Join-Path $env:ProgramData "Lynx"
+$InstallPath = Join-Path $InstallDir "lynx-view-agent.exe"
+$ServiceName = "lynx-view-agent"
+
+if ($env:PROCESSOR_ARCHITECTURE -ne "{processor}") {{
+    Write-Error "This script installs the {arch} agent, this machine is $env:PROCESSOR_ARCHITECTURE"
+    exit 1
+}}
+if (-not (Get-Command nssm -ErrorAction SilentlyContinue)) {{
+    Write-Error "nssm was not found on the PATH"
+    exit 1
+}}
+
+New-Item -ItemType Directory -Force -Path $InstallDir, (Join-Path $ConfigDir "certs") | Out-Null
+$TmpBin = Join-Path ([System.IO.Path]::GetTempPath()) ("lynx-view-agent-" + [guid]::NewGuid() + ".exe")
+try {{
+    Invoke-WebRequest -UseBasicParsing -Uri $BinUrl -OutFile $TmpBin
+    $Actual = (Get-FileHash -Algorithm SHA256 -Path $TmpBin).Hash.ToLower()
+    if ($Actual -ne $BinSha256) {{
+        Write-Error "Checksum mismatch for $BinUrl, aborting"
+        exit 1
+    }}
+    # the running binary can't be replaced on Windows
+    $Existing = Get-Service -Name $ServiceName -ErrorAction SilentlyContinue
+    if ($Existing) {{
+        nssm stop $ServiceName | Out-Null
+    }}
+    Move-Item -Force -Path $TmpBin -Destination $InstallPath
+}} finally {{
+    Remove-Item -Force -ErrorAction SilentlyContinue -Path $TmpBin
+}}
+
+Set-Content -Path (Join-Path $ConfigDir "certs\release.pub") -Value @'
+{release_public_key}'@
+
+$ConfigFile = Join-Path $ConfigDir "config.toml"
+Set-Content -Path $ConfigFile -Value @"
+[core]
+server_url = "{server_url}"
+agent_key = "{agent_key}"
+"@
+icacls $ConfigFile /inheritance:r /grant:r "SYSTEM:F" "Administrators:F" | Out-Null
+
+if (-not $Existing) {{
+    nssm install $ServiceName $InstallPath | Out-Null
+}}
+nssm set $ServiceName AppDirectory $ConfigDir | Out-Null
+nssm set $ServiceName AppExit Default Restart | Out-Null
+nssm set $ServiceName AppRestartDelay 5000 | Out-Null
+nssm set $ServiceName Start SERVICE_AUTO_START | Out-Null
+nssm start $ServiceName | Out-Null
 "##
-    );
+        )
+    }
+}
 
-    sqlx::query!(
-        r"UPDATE systems SET active = true, key = $1 WHERE id = $2",
+/// Generate a signed installation script for an inactive (pending) agent.
+/// Activates the agent (sets key + active=true) if hostname + token match.
+pub async fn generate_agent_install_script(
+    hostname: &str,
+    token: &str,
+    target: &InstallTarget,
+    release: &AgentRelease,
+    pool: &sqlx::PgPool,
+) -> Result<SignedScript, InstallScriptError> {
+    let signing_key = release
+        .signing_key
+        .as_deref()
+        .ok_or(InstallScriptError::NotConfigured("AGENT_SIGNING_KEY"))?;
+    let signer = ReleaseSigner::load(signing_key)?;
+    let shell = script_shell(target.init_system()?);
+
+    let agent = sqlx::query!(
+        r"SELECT id FROM systems WHERE hostname = $1 AND token = $2 AND active = false",
+        hostname,
+        token
+    )
+    .fetch_optional(pool)
+    .await?
+    .ok_or(InstallScriptError::InvalidToken)?;
+
+    let binary = resolve_binary(pool, release, target).await?;
+    let agent_key = Uuid::new_v4().to_string();
+    let script = render_install_script(
+        &binary,
+        target,
+        &release.server_url,
+        &agent_key,
+        &signer.public_key_pem()?,
+    )?;
+    let signature = signer.sign(script.as_bytes())?;
+
+    // active = false guards against two concurrent requests with the same token
+    let updated = sqlx::query!(
+        r"UPDATE systems SET active = true, key = $1 WHERE id = $2 AND active = false",
         agent_key,
-        agent.unwrap().id
+        agent.id
     )
     .execute(pool)
     .await?;
+    if updated.rows_affected() == 0 {
+        return Err(InstallScriptError::InvalidToken);
+    }
 
-    Ok(script)
+    Ok(SignedScript {
+        script,
+        signature,
+        shell,
+    })
 }
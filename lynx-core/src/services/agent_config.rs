@@ -0,0 +1,116 @@
+use crate::proto::monitor::{AgentConfig, ProbeTarget};
+use crate::services::feature_flags;
+use serde::Deserialize;
+use sqlx::{PgPool, Row};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+/*
+ * Agent configuration push
+ * Collector intervals, probe targets and feature toggles live in the agent_config table: the
+ * row without a system_id applies to the whole fleet, a row for a system overrides it key by
+ * key (probe targets by name). Hub feature flags resolved for the system sit below both, see
+ * feature_flags. Agents keep a WatchConfig stream open and get the merged config whenever it
+ * changes.
+ */
+
+/// How often open WatchConfig streams look for changes.
+pub const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(30);
+/// Probes without a timeout of their own give up after this long.
+pub const DEFAULT_PROBE_TIMEOUT_MS: u32 = 2000;
+
+const GET_AGENT_CONFIG: &str = "SELECT system_id, config FROM agent_config \
+     WHERE system_id IS NULL OR system_id = $1 ORDER BY system_id NULLS FIRST";
+
+/// The jsonb document stored in agent_config.config, every section is optional.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+#[serde(default)]
+pub struct AgentConfigDoc {
+    pub collector_intervals: BTreeMap<String, u64>,
+    pub features: BTreeMap<String, bool>,
+    pub probe_targets: Vec<ProbeTargetDoc>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct ProbeTargetDoc {
+    pub name: String,
+    pub address: String,
+    pub timeout_ms: Option<u32>,
+}
+
+impl AgentConfigDoc {
+    /// Layers `other` over self, its keys and probe names win.
+    pub fn merge(mut self, other: AgentConfigDoc) -> Self {
+        self.collector_intervals.extend(other.collector_intervals);
+        self.features.extend(other.features);
+        for probe in other.probe_targets {
+            self.probe_targets.retain(|p| p.name != probe.name);
+            self.probe_targets.push(probe);
+        }
+        self
+    }
+
+    /*
+     * to_proto
+     * Converts the merged document into what is sent to agents. The version is a hash of the
+     * contents so streams only push when something actually changed.
+     */
+    pub fn to_proto(&self) -> AgentConfig {
+        let mut hasher = DefaultHasher::new();
+        self.collector_intervals.hash(&mut hasher);
+        self.features.hash(&mut hasher);
+        for probe in &self.probe_targets {
+            (&probe.name, &probe.address, probe.timeout_ms).hash(&mut hasher);
+        }
+        AgentConfig {
+            // 0 is what agents send before they received anything
+            version: hasher.finish().max(1),
+            collector_intervals: self
+                .collector_intervals
+                .iter()
+                .filter(|(_, secs)| **secs > 0)
+                .map(|(name, secs)| (name.clone(), *secs))
+                .collect::<HashMap<_, _>>(),
+            probe_targets: self
+                .probe_targets
+                .iter()
+                .map(|p| ProbeTarget {
+                    name: p.name.clone(),
+                    address: p.address.clone(),
+                    timeout_ms: p.timeout_ms.unwrap_or(DEFAULT_PROBE_TIMEOUT_MS),
+                })
+                .collect(),
+            features: self.features.clone().into_iter().collect(),
+            decommission: false,
+        }
+    }
+}
+
+/// Loads the system's feature flags and the fleet config merged with its overrides.
+pub async fn load(pool: &PgPool, system_id: i32) -> Result<AgentConfig, sqlx::Error> {
+    let flags = AgentConfigDoc {
+        features: feature_flags::resolve(&feature_flags::list(pool).await?, system_id),
+        ..AgentConfigDoc::default()
+    };
+    let rows = sqlx::query(GET_AGENT_CONFIG)
+        .bind(system_id)
+        .fetch_all(pool)
+        .await?;
+    let merged = rows
+        .iter()
+        .filter_map(|row| {
+            let config: serde_json::Value = row.get("config");
+            match serde_json::from_value::<AgentConfigDoc>(config) {
+                Ok(doc) => Some(doc),
+                Err(e) => {
+                    let scope: Option<i32> = row.get("system_id");
+                    log::warn!("[config] Ignoring invalid agent_config row {scope:?}: {e}");
+                    None
+                }
+            }
+        })
+        .fold(flags, AgentConfigDoc::merge);
+    Ok(merged.to_proto())
+}
@@ -0,0 +1,38 @@
+use crate::proto::monitor::AgentEvent;
+use chrono::DateTime;
+use sqlx::PgPool;
+
+/*
+ * Agent events
+ * Agents install a panic hook that writes each panic, with its backtrace, next to their config
+ * and report those files over ReportAgentEvent once they are running again. This keeps crashes
+ * across the fleet in agent_events instead of scattered over the hosts' journals.
+ */
+
+/// Panic messages and locations are cut to this many characters.
+pub const MAX_MESSAGE_CHARS: usize = 4096;
+/// Backtraces are cut to this many characters.
+pub const MAX_BACKTRACE_CHARS: usize = 32 * 1024;
+
+pub fn truncate(text: &str, max_chars: usize) -> String {
+    text.chars().take(max_chars).collect()
+}
+
+pub async fn record(pool: &PgPool, system_id: i32, event: &AgentEvent) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO agent_events \
+         (time, system, kind, version, thread, location, message, backtrace) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(DateTime::from_timestamp(event.time, 0))
+    .bind(system_id)
+    .bind(&event.kind)
+    .bind(truncate(&event.version, MAX_MESSAGE_CHARS))
+    .bind(truncate(&event.thread, MAX_MESSAGE_CHARS))
+    .bind(truncate(&event.location, MAX_MESSAGE_CHARS))
+    .bind(truncate(&event.message, MAX_MESSAGE_CHARS))
+    .bind(truncate(&event.backtrace, MAX_BACKTRACE_CHARS))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use crate::proto::monitor::AgentHealthEvent;
+use sqlx::PgPool;
+
+/*
+ * Agent health events
+ * Agents run every collector under a supervisor that gives up on runs which panic or exceed
+ * their timeout and starts the collector again on its next tick. Each of those restarts is
+ * reported over ReportHealth and kept in agent_health_events, pruned with the metrics.
+ */
+
+/// Longer details (panic messages) are cut to this many characters.
+pub const MAX_DETAIL_CHARS: usize = 1024;
+
+pub fn truncate_detail(detail: &str) -> String {
+    detail.chars().take(MAX_DETAIL_CHARS).collect()
+}
+
+pub async fn record(
+    pool: &PgPool,
+    system_id: i32,
+    event: &AgentHealthEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO agent_health_events (time, system, collector, kind, detail, restarts) \
+         VALUES (NOW(), $1, $2, $3, $4, $5)",
+    )
+    .bind(system_id)
+    .bind(&event.collector)
+    .bind(&event.kind)
+    .bind(truncate_detail(&event.detail))
+    .bind(event.restarts as i32)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
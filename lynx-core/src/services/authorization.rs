@@ -0,0 +1,136 @@
+use crate::auth_limit;
+use crate::tls::{CaError, CertificateAuthority};
+use openssl::base64;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::time::Duration;
+use thiserror::Error;
+
+/*
+ * Command authorizations
+ * Websocket messages that change the host (by default `delete`, `execute`, `applyupdates`,
+ * `upload`, `startservice`, `stopservice`, `restartservice` and `update`, agents choose in their
+ * `[authorization]` section) are only carried out with a token from the hub, so access to an
+ * agent's websocket port alone can't destroy the host. An admin asks for one with
+ * `POST /systems/{id}/authorizations`; the token binds the action, optionally its target, the
+ * system's agent key and an expiry, and is signed with the CA key the agent checks it against.
+ * A random nonce makes every token's claims unique, agents remember the claims they took rather
+ * than the signature, which ECDSA lets anyone rewrite.
+ *
+ * Token: base64(claims JSON) "." base64(signature over the claims JSON)
+ */
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(60);
+pub const MAX_TTL: Duration = Duration::from_secs(5 * 60);
+
+const GET_SYSTEM_KEY: &str = "SELECT key FROM systems WHERE id = $1 AND active = true";
+
+#[derive(Error, Debug)]
+pub enum AuthorizationError {
+    #[error("System {0} not found or has no agent key")]
+    NotFound(i32),
+    #[error("Invalid action {0:?}, use a websocket message type such as \"delete\"")]
+    InvalidAction(String),
+    #[error("Failed to sign the token: {0}")]
+    Signing(#[from] CaError),
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+}
+
+/// Body of `POST /systems/{id}/authorizations`.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct AuthorizationRequest {
+    /// Websocket message type, e.g. `delete`
+    pub action: String,
+    /// Binds the token to one service, command, path or url of the message too
+    pub target: Option<String>,
+    pub ttl_secs: Option<u64>,
+}
+
+impl AuthorizationRequest {
+    pub fn ttl(&self) -> Duration {
+        self.ttl_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TTL)
+            .clamp(Duration::from_secs(1), MAX_TTL)
+    }
+}
+
+/// What a token allows, agents check every field.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Claims {
+    pub action: String,
+    pub target: Option<String>,
+    /// The system's id, for people reading the token
+    pub system: i32,
+    /// auth_limit::key_id of the system's agent key
+    pub agent: String,
+    /// Unix seconds
+    pub expires: i64,
+    /// Random, tells apart tokens issued for the same action in the same second
+    pub nonce: String,
+}
+
+/// Returned by `POST /systems/{id}/authorizations`.
+#[derive(Serialize, Debug, Clone)]
+pub struct ActionToken {
+    /// Sent as `token` in the websocket message
+    pub token: String,
+    pub expires: i64,
+}
+
+/// Websocket message types are lowercase words.
+pub fn valid_action(action: &str) -> bool {
+    !action.is_empty() && action.len() <= 64 && action.chars().all(|c| c.is_ascii_lowercase())
+}
+
+pub fn claims(
+    system_id: i32,
+    agent_key: &str,
+    request: &AuthorizationRequest,
+    now: i64,
+) -> Result<Claims, AuthorizationError> {
+    if !valid_action(&request.action) {
+        return Err(AuthorizationError::InvalidAction(request.action.clone()));
+    }
+    Ok(Claims {
+        action: request.action.clone(),
+        target: request.target.clone(),
+        system: system_id,
+        agent: auth_limit::key_id(agent_key),
+        expires: now + request.ttl().as_secs() as i64,
+        nonce: uuid::Uuid::new_v4().simple().to_string(),
+    })
+}
+
+/// The token for `payload` (the claims JSON) and its signature.
+pub fn token(payload: &[u8], signature: &[u8]) -> String {
+    format!(
+        "{}.{}",
+        base64::encode_block(payload),
+        base64::encode_block(signature)
+    )
+}
+
+pub async fn issue(
+    pool: &PgPool,
+    ca: &CertificateAuthority,
+    system_id: i32,
+    request: &AuthorizationRequest,
+) -> Result<ActionToken, AuthorizationError> {
+    let key: Option<Option<String>> = sqlx::query_scalar(GET_SYSTEM_KEY)
+        .bind(system_id)
+        .fetch_optional(pool)
+        .await?;
+    let key = key
+        .flatten()
+        .ok_or(AuthorizationError::NotFound(system_id))?;
+    let claims = claims(system_id, &key, request, chrono::Utc::now().timestamp())?;
+    // serializing a struct of strings and numbers can't fail
+    let payload = serde_json::to_vec(&claims).unwrap_or_default();
+    let signature = ca.sign(&payload)?;
+    Ok(ActionToken {
+        token: token(&payload, &signature),
+        expires: claims.expires,
+    })
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+
+/*
+ * Clock skew
+ * Agents stamp every metrics report with their clock right before it goes out (sent_at_ms), the
+ * hub compares that with its own clock when the report arrives. Positive skew means the agent
+ * runs ahead. The measurement includes the network delay, milliseconds on a healthy link, far
+ * below anything worth flagging. Each sample keeps its skew in metrics.clock_skew_ms, the
+ * systems row the latest one, and rules see it as `agent.clock_skew_seconds`.
+ */
+
+/// Systems further off than this are flagged unless the caller asks for another threshold.
+pub const DEFAULT_THRESHOLD_SECS: u64 = 30;
+
+const GET_SKEWED: &str = "SELECT id, hostname, label, clock_skew_ms, last_seen FROM systems \
+     WHERE active = true AND decommissioned IS NULL AND abs(clock_skew_ms) > $1 \
+     ORDER BY abs(clock_skew_ms) DESC, id";
+
+/// How far the agent's clock is ahead of ours, None for reports without sent_at_ms.
+pub fn measure(sent_at_ms: Option<i64>, received: DateTime<Utc>) -> Option<i64> {
+    sent_at_ms.map(|sent| sent.saturating_sub(received.timestamp_millis()))
+}
+
+/// Query string of `GET /systems/clock-skew`.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SkewQuery {
+    #[serde(default = "default_threshold")]
+    pub threshold_secs: u64,
+}
+
+fn default_threshold() -> u64 {
+    DEFAULT_THRESHOLD_SECS
+}
+
+impl Default for SkewQuery {
+    fn default() -> Self {
+        Self {
+            threshold_secs: DEFAULT_THRESHOLD_SECS,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SkewedSystem {
+    pub id: i32,
+    pub hostname: Option<String>,
+    pub label: String,
+    /// Positive when the agent's clock is ahead
+    pub clock_skew_ms: i64,
+    /// When the skew was last measured
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// Systems whose last report was off by more than the threshold, worst first.
+pub async fn skewed(pool: &PgPool, query: &SkewQuery) -> Result<Vec<SkewedSystem>, sqlx::Error> {
+    let threshold_ms = i64::try_from(query.threshold_secs.saturating_mul(1000)).unwrap_or(i64::MAX);
+    let rows = sqlx::query(GET_SKEWED)
+        .bind(threshold_ms)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| SkewedSystem {
+            id: row.get("id"),
+            hostname: row.get("hostname"),
+            label: row.get("label"),
+            clock_skew_ms: row.get("clock_skew_ms"),
+            last_seen: row.get("last_seen"),
+        })
+        .collect())
+}
@@ -0,0 +1,180 @@
+use crate::proto::monitor::{CommandAudit, TranscriptLine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+/*
+ * Command audit
+ * Agents report every action taken on their host over the websocket (commands, service
+ * control, updates and uninstalls) over ReportCommand once it ended: who asked for it, what,
+ * when and how it ended. Entries are kept in command_audit and listed per system with
+ * `GET /systems/{id}/audit`. Commands can come with a transcript of their input and output,
+ * kept in command_transcripts and fetched one entry at a time.
+ */
+
+pub const DEFAULT_LIMIT: u32 = 50;
+pub const MAX_LIMIT: u32 = 500;
+/// Every text field is cut to this many characters.
+pub const MAX_FIELD_CHARS: usize = 1024;
+/// Arguments beyond this many are dropped.
+pub const MAX_ARGS: usize = 64;
+/// Transcript lines beyond this many are dropped, the transcript is marked truncated.
+pub const MAX_TRANSCRIPT_LINES: usize = 20_000;
+
+const INSERT_ENTRY: &str = "INSERT INTO command_audit \
+     (time, system, actor, action, target, args, exit_code, error) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id";
+
+const INSERT_TRANSCRIPT: &str =
+    "INSERT INTO command_transcripts (audit_id, lines, truncated) VALUES ($1, $2, $3)";
+
+const GET_TRANSCRIPT: &str = "SELECT t.lines, t.truncated FROM command_transcripts t \
+     JOIN command_audit a ON a.id = t.audit_id WHERE a.system = $1 AND t.audit_id = $2";
+
+const GET_SYSTEM_ENTRIES: &str =
+    "SELECT id, time, received, system, actor, action, target, args, exit_code, error, \
+     EXISTS (SELECT 1 FROM command_transcripts t WHERE t.audit_id = command_audit.id) \
+     AS has_transcript \
+     FROM command_audit WHERE system = $1 AND ($2::text IS NULL OR action = $2) \
+     AND (NOT $3 OR error IS NOT NULL OR exit_code <> 0) \
+     ORDER BY time DESC, id DESC LIMIT $4";
+
+#[derive(Serialize, Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: i32,
+    pub time: DateTime<Utc>,
+    pub received: DateTime<Utc>,
+    pub system: i32,
+    pub actor: String,
+    pub action: String,
+    pub target: String,
+    pub args: Vec<String>,
+    pub exit_code: Option<i32>,
+    pub error: Option<String>,
+    /// Fetch it with `GET /systems/{id}/audit/{audit_id}/transcript`
+    pub has_transcript: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TranscriptEntry {
+    /// None for input recorded without timestamps
+    pub time: Option<DateTime<Utc>>,
+    /// `input`, `stdout` or `stderr`
+    pub stream: String,
+    pub text: String,
+}
+
+/// Returned by `GET /systems/{id}/audit/{audit_id}/transcript`.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Transcript {
+    pub audit_id: i32,
+    pub lines: Vec<TranscriptEntry>,
+    /// Lines past MAX_TRANSCRIPT_LINES were dropped
+    pub truncated: bool,
+}
+
+/// Query string of `GET /systems/{id}/audit`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct AuditQuery {
+    /// Only this action, e.g. `execute`
+    pub action: Option<String>,
+    /// Only entries with an error or a non-zero exit code
+    #[serde(default)]
+    pub failed: bool,
+    pub limit: Option<u32>,
+}
+
+impl AuditQuery {
+    pub fn limit(&self) -> u32 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+fn truncate(text: &str) -> String {
+    text.chars().take(MAX_FIELD_CHARS).collect()
+}
+
+/// The transcript as stored, and whether lines were dropped.
+pub fn transcript_entries(lines: &[TranscriptLine]) -> (Vec<TranscriptEntry>, bool) {
+    let entries = lines
+        .iter()
+        .take(MAX_TRANSCRIPT_LINES)
+        .map(|line| TranscriptEntry {
+            time: (line.time_ms > 0)
+                .then(|| DateTime::from_timestamp_millis(line.time_ms))
+                .flatten(),
+            stream: truncate(&line.stream),
+            text: truncate(&line.text),
+        })
+        .collect();
+    (entries, lines.len() > MAX_TRANSCRIPT_LINES)
+}
+
+pub async fn record(
+    pool: &PgPool,
+    system_id: i32,
+    entry: &CommandAudit,
+) -> Result<(), sqlx::Error> {
+    let args: Vec<String> = entry
+        .args
+        .iter()
+        .take(MAX_ARGS)
+        .map(|a| truncate(a))
+        .collect();
+    let mut tx = pool.begin().await?;
+    let id: i32 = sqlx::query_scalar(INSERT_ENTRY)
+        .bind(DateTime::from_timestamp(entry.time, 0))
+        .bind(system_id)
+        .bind(truncate(&entry.actor))
+        .bind(truncate(&entry.action))
+        .bind(truncate(&entry.target))
+        .bind(args)
+        .bind(entry.exit_code)
+        .bind((!entry.error.is_empty()).then(|| truncate(&entry.error)))
+        .fetch_one(&mut *tx)
+        .await?;
+    if !entry.transcript.is_empty() {
+        let (lines, truncated) = transcript_entries(&entry.transcript);
+        sqlx::query(INSERT_TRANSCRIPT)
+            .bind(id)
+            .bind(sqlx::types::Json(lines))
+            .bind(truncated)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// Latest entries for a system, newest first.
+pub async fn for_system(
+    pool: &PgPool,
+    system_id: i32,
+    query: &AuditQuery,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    sqlx::query_as::<_, AuditEntry>(GET_SYSTEM_ENTRIES)
+        .bind(system_id)
+        .bind(query.action.as_deref())
+        .bind(query.failed)
+        .bind(query.limit() as i64)
+        .fetch_all(pool)
+        .await
+}
+
+/// The transcript of one of a system's entries, None when there is none.
+pub async fn transcript(
+    pool: &PgPool,
+    system_id: i32,
+    audit_id: i32,
+) -> Result<Option<Transcript>, sqlx::Error> {
+    let row: Option<(sqlx::types::Json<Vec<TranscriptEntry>>, bool)> =
+        sqlx::query_as(GET_TRANSCRIPT)
+            .bind(system_id)
+            .bind(audit_id)
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.map(|(lines, truncated)| Transcript {
+        audit_id,
+        lines: lines.0,
+        truncated,
+    }))
+}
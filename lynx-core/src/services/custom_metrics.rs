@@ -0,0 +1,166 @@
+use crate::auth_limit::{self, AuthDenied, AuthLimiter};
+use crate::services::ingest::CustomMetricItem;
+use chrono::Utc;
+use log::warn;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use thiserror::Error;
+
+/*
+ * Custom metrics
+ * Ad-hoc metrics posted by scripts and third-party tools, authenticated with the agent key of the
+ * system they belong to. They are stored in custom_metrics and exposed to alert rules as
+ * `custom.<name>`, so names are limited to what the rule syntax accepts. Agents running scripts
+ * send theirs in MetricsRequest.custom_metrics instead, checked the same way.
+ */
+
+pub const MAX_METRICS_PER_REQUEST: usize = 500;
+const MAX_LABELS: usize = 16;
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum CustomMetricError {
+    #[error("No metrics in request")]
+    Empty,
+    #[error("At most {MAX_METRICS_PER_REQUEST} metrics per request")]
+    TooMany,
+    #[error("Invalid metric name {0:?}, use up to 64 letters, digits and underscores")]
+    InvalidName(String),
+    #[error("Metric {0} has a value that is not a finite number")]
+    InvalidValue(String),
+    #[error("Metric {0} has more than {MAX_LABELS} labels")]
+    TooManyLabels(String),
+}
+
+#[derive(Error, Debug)]
+pub enum CustomAuthError {
+    #[error("Missing or invalid agent key")]
+    InvalidKey,
+    #[error("{0}")]
+    Denied(String),
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CustomMetric {
+    pub name: String,
+    pub value: f64,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CustomMetricsRequest {
+    pub metrics: Vec<CustomMetric>,
+}
+
+/// Metrics the agent's scripts derived, sent along with its regular metrics report.
+impl From<crate::proto::monitor::CustomMetric> for CustomMetric {
+    fn from(metric: crate::proto::monitor::CustomMetric) -> Self {
+        Self {
+            name: metric.name,
+            value: metric.value,
+            labels: metric.labels.into_iter().collect(),
+        }
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Checks a posted batch and stamps it for ingest, rejecting the whole batch on the first error.
+pub fn validate(
+    system_id: i32,
+    req: CustomMetricsRequest,
+) -> Result<Vec<CustomMetricItem>, CustomMetricError> {
+    if req.metrics.is_empty() {
+        return Err(CustomMetricError::Empty);
+    }
+    if req.metrics.len() > MAX_METRICS_PER_REQUEST {
+        return Err(CustomMetricError::TooMany);
+    }
+
+    let time = Utc::now();
+    req.metrics
+        .into_iter()
+        .map(|m| {
+            if !valid_name(&m.name) {
+                return Err(CustomMetricError::InvalidName(m.name));
+            }
+            if !m.value.is_finite() {
+                return Err(CustomMetricError::InvalidValue(m.name));
+            }
+            if m.labels.len() > MAX_LABELS {
+                return Err(CustomMetricError::TooManyLabels(m.name));
+            }
+            Ok(CustomMetricItem {
+                system_id,
+                time,
+                labels: serde_json::to_value(&m.labels).unwrap_or_default(),
+                name: m.name,
+                value: m.value,
+            })
+        })
+        .collect()
+}
+
+/*
+ * authenticate
+ * Resolves the system an agent key belongs to, going through the same rate limits, lockouts and
+ * auth_events as agent connections.
+ */
+pub async fn authenticate(
+    pool: &PgPool,
+    limiter: &AuthLimiter,
+    remote_ip: Option<IpAddr>,
+    agent_key: &str,
+) -> Result<i32, CustomAuthError> {
+    let key_id = auth_limit::key_id(agent_key);
+    if let Err(denied) = limiter.check(remote_ip, &key_id) {
+        return Err(CustomAuthError::Denied(match denied {
+            AuthDenied::RateLimited { subject, first } => {
+                if first {
+                    warn!("[http] Authentication rate limit exceeded by {subject}");
+                    auth_limit::record_auth_event(pool, "rate_limited", remote_ip, &key_id, None)
+                        .await;
+                }
+                "Too many authentication attempts, retry later".to_string()
+            }
+            AuthDenied::LockedOut {
+                subject,
+                retry_after,
+            } => format!(
+                "{subject} is locked out for {}s after repeated failed authentications",
+                retry_after.as_secs()
+            ),
+        }));
+    }
+
+    let system = sqlx::query_scalar!(
+        r#"SELECT id FROM systems WHERE key = $1 AND active = true"#,
+        agent_key
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    match system {
+        Some(id) => {
+            limiter.record_success(&key_id);
+            Ok(id)
+        }
+        None => {
+            auth_limit::record_auth_event(pool, "invalid_key", remote_ip, &key_id, None).await;
+            for subject in limiter.record_failure(remote_ip, &key_id) {
+                warn!("[http] Too many failed authentications, locking out {subject}");
+                auth_limit::record_auth_event(pool, "locked_out", remote_ip, &key_id, None).await;
+            }
+            Err(CustomAuthError::InvalidKey)
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use crate::leader::Leadership;
+use chrono::{DateTime, Utc};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::interval;
+use tonic::codegen::tokio_stream::StreamExt;
+
+/*
+ * Decommissioning
+ * The end of the lifecycle enrollment starts. POST /systems/{id}/decommission stamps
+ * systems.decommissioned; from then on the agent's WatchConfig stream carries `decommission`
+ * and the agent uninstalls itself. Once the grace period passed, the leader revokes the key
+ * (and pinned certificate), writes the system's samples to an archive in ARCHIVE_DIR and
+ * deletes them from the metrics tables. The systems row stays, with the archive's path in
+ * decommission_archive, so alert history keeps pointing at it.
+ * An agent offline for the whole grace period never hears about it and has to be removed by
+ * hand, its key no longer works either way.
+ */
+
+pub const ARCHIVE_FORMAT: &str = "lynx-system-archive";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+const FINALIZE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Time series holding the system's samples, with the condition picking the system's rows.
+/// GPU and container samples name the system through their gpus/containers rows, which stay.
+pub const ARCHIVED_SERIES: &[(&str, &str)] = &[
+    ("metrics", "system_id = $1"),
+    ("disks", "system = $1"),
+    ("metrics_hourly", "system_id = $1"),
+    ("disks_hourly", "system = $1"),
+    (
+        "gpu_metrics",
+        "gpu_id IN (SELECT id FROM gpus WHERE system_id = $1)",
+    ),
+    (
+        "gpu_metrics_hourly",
+        "gpu_id IN (SELECT id FROM gpus WHERE system_id = $1)",
+    ),
+    (
+        "container_metrics",
+        "container_id IN (SELECT id FROM containers WHERE system_id = $1)",
+    ),
+    ("custom_metrics", "system_id = $1"),
+    ("probe_results", "system_id = $1"),
+    ("process_samples", "system_id = $1"),
+    ("smart_attributes", "system_id = $1"),
+];
+
+const MARK_DECOMMISSIONED: &str = "UPDATE systems \
+     SET decommissioned = COALESCE(decommissioned, NOW()) WHERE id = $1 \
+     RETURNING decommissioned, decommission_archive";
+
+const IS_DECOMMISSIONED: &str = "SELECT decommissioned IS NOT NULL FROM systems WHERE id = $1";
+
+const DUE_SYSTEMS: &str = "SELECT id FROM systems \
+     WHERE decommissioned <= NOW() - ($1 * INTERVAL '1 second') \
+     AND decommission_archive IS NULL ORDER BY id";
+
+const REVOKE_KEY: &str = "UPDATE systems \
+     SET key = NULL, token = NULL, cert_fingerprint = NULL, active = false WHERE id = $1";
+
+#[derive(Clone, Debug)]
+pub struct DecommissionOptions {
+    /// How long the agent has to pick up the uninstall before its key is revoked
+    pub grace: Duration,
+    /// Where the archives of decommissioned systems are written
+    pub archive_dir: PathBuf,
+}
+
+#[derive(Error, Debug)]
+pub enum DecommissionError {
+    #[error("System {0} not found")]
+    NotFound(i32),
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Where a system is in its decommissioning, returned by the HTTP API.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Decommission {
+    pub system_id: i32,
+    pub decommissioned: DateTime<Utc>,
+    /// Set once the key is revoked and the samples are archived
+    pub archive: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct ArchiveHeader {
+    pub format: String,
+    pub version: u32,
+    pub created: DateTime<Utc>,
+    pub system_id: i32,
+}
+
+/// Marks the system decommissioned, asking again keeps the original time.
+pub async fn request(pool: &PgPool, system_id: i32) -> Result<Decommission, DecommissionError> {
+    let row = sqlx::query(MARK_DECOMMISSIONED)
+        .bind(system_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or(DecommissionError::NotFound(system_id))?;
+    let decommission = Decommission {
+        system_id,
+        decommissioned: row.get("decommissioned"),
+        archive: row.get("decommission_archive"),
+    };
+    info!(
+        "[decommission] System {system_id} decommissioned at {}",
+        decommission.decommissioned
+    );
+    Ok(decommission)
+}
+
+pub async fn is_decommissioned(pool: &PgPool, system_id: i32) -> Result<bool, sqlx::Error> {
+    let decommissioned = sqlx::query_scalar::<_, bool>(IS_DECOMMISSIONED)
+        .bind(system_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(decommissioned.unwrap_or(false))
+}
+
+pub fn archive_path(dir: &Path, system_id: i32, created: DateTime<Utc>) -> PathBuf {
+    dir.join(format!(
+        "system-{system_id}-{}.jsonl",
+        created.format("%Y%m%dT%H%M%SZ")
+    ))
+}
+
+/*
+ * finish
+ * Revokes the key first so nothing new comes in while the samples are archived. The archive
+ * uses the {"table", "row"} lines of `lynx-core backup` after its own header, the systems row
+ * first. Rows are only deleted once the archive is on disk; a failed attempt is retried by the
+ * next pass and writes a fresh archive.
+ */
+pub async fn finish(
+    pool: &PgPool,
+    system_id: i32,
+    archive_dir: &Path,
+) -> Result<PathBuf, DecommissionError> {
+    sqlx::query(REVOKE_KEY)
+        .bind(system_id)
+        .execute(pool)
+        .await?;
+
+    tokio::fs::create_dir_all(archive_dir).await?;
+    let created = Utc::now();
+    let path = archive_path(archive_dir, system_id, created);
+    let mut out = BufWriter::new(File::create(&path).await?);
+    let header = ArchiveHeader {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        created,
+        system_id,
+    };
+    let header = serde_json::to_string(&header).expect("header serializes");
+    write_line(&mut out, &header).await?;
+    let mut total = 0;
+    for (table, filter) in [("systems", "id = $1")].iter().chain(ARCHIVED_SERIES) {
+        let sql = format!("SELECT row_to_json(t)::text FROM {table} t WHERE {filter}");
+        let mut rows = sqlx::query_scalar::<_, String>(&sql)
+            .bind(system_id)
+            .fetch(pool);
+        while let Some(row) = rows.next().await {
+            let line = format!("{{\"table\":\"{table}\",\"row\":{}}}", row?);
+            write_line(&mut out, &line).await?;
+            total += 1;
+        }
+    }
+    out.flush().await?;
+
+    let mut tx = pool.begin().await?;
+    for (table, filter) in ARCHIVED_SERIES {
+        sqlx::query(&format!("DELETE FROM {table} WHERE {filter}"))
+            .bind(system_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("UPDATE systems SET decommission_archive = $2 WHERE id = $1")
+        .bind(system_id)
+        .bind(path.display().to_string())
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    info!(
+        "[decommission] System {system_id} key revoked, {total} rows archived to {}",
+        path.display()
+    );
+    Ok(path)
+}
+
+async fn write_line(out: &mut BufWriter<File>, line: &str) -> Result<(), std::io::Error> {
+    out.write_all(line.as_bytes()).await?;
+    out.write_all(b"\n").await
+}
+
+/// Finishes every system whose grace period is over, on the leader only.
+pub async fn run_finalizer(pool: PgPool, options: DecommissionOptions, leadership: Leadership) {
+    let mut tick = interval(FINALIZE_INTERVAL);
+    loop {
+        tick.tick().await;
+        if !leadership.is_leader() {
+            continue;
+        }
+        let due = match sqlx::query_scalar::<_, i32>(DUE_SYSTEMS)
+            .bind(options.grace.as_secs() as i64)
+            .fetch_all(&pool)
+            .await
+        {
+            Ok(due) => due,
+            Err(e) => {
+                error!("[decommission] Failed to load decommissioned systems: {e}");
+                continue;
+            }
+        };
+        for system_id in due {
+            if let Err(e) = finish(&pool, system_id, &options.archive_dir).await {
+                error!("[decommission] Failed to finish system {system_id}: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::proto::monitor::enrollment_server::Enrollment;
+use crate::proto::monitor::{EnrollRequest, EnrollResponse};
+use crate::tls::{pem_fingerprint, CaError, CertificateAuthority};
+use log::{error, info, warn};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+#[derive(Clone)]
+pub struct EnrollmentService {
+    pub pool: sqlx::PgPool,
+    pub ca: Arc<CertificateAuthority>,
+    pub cert_days: u32,
+}
+
+#[tonic::async_trait]
+impl Enrollment for EnrollmentService {
+    async fn enroll(
+        &self,
+        request: Request<EnrollRequest>,
+    ) -> Result<Response<EnrollResponse>, Status> {
+        let req = request.into_inner();
+        if req.hostname.is_empty() || req.token.is_empty() || req.csr_pem.is_empty() {
+            return Err(Status::invalid_argument(
+                "hostname, token and csr_pem are required",
+            ));
+        }
+
+        let system = sqlx::query!(
+            r#"SELECT id FROM systems
+               WHERE hostname = $1 AND token = $2 AND active = false
+                 AND (expires IS NULL OR expires > NOW())"#,
+            req.hostname,
+            req.token
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("[enroll] DB lookup error: {e}");
+            Status::internal("Database error")
+        })?
+        .ok_or_else(|| {
+            warn!("[enroll] Rejected enrollment for {}", req.hostname);
+            Status::permission_denied("Invalid hostname or enrollment token")
+        })?;
+
+        let certificate_pem = self
+            .ca
+            .sign_csr(&req.csr_pem, &req.hostname, self.cert_days)
+            .map_err(|e| match e {
+                CaError::InvalidCsr(msg) => Status::invalid_argument(format!("Invalid CSR: {msg}")),
+                e => {
+                    error!("[enroll] Failed to sign CSR: {e}");
+                    Status::internal("Failed to sign certificate")
+                }
+            })?;
+
+        let fingerprint = pem_fingerprint(&certificate_pem).map_err(|e| {
+            error!("[enroll] Failed to fingerprint certificate: {e}");
+            Status::internal("Failed to sign certificate")
+        })?;
+
+        let agent_key = Uuid::new_v4().to_string();
+        // active = false guards against two concurrent enrollments with the same token
+        let updated = sqlx::query!(
+            r"UPDATE systems SET active = true, key = $1, cert_fingerprint = $2
+              WHERE id = $3 AND active = false",
+            agent_key,
+            fingerprint,
+            system.id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("[enroll] Failed to activate system: {e}");
+            Status::internal("Database error")
+        })?;
+        if updated.rows_affected() == 0 {
+            return Err(Status::already_exists("System already enrolled"));
+        }
+
+        info!("[enroll] Enrolled {} (system {})", req.hostname, system.id);
+        Ok(Response::new(EnrollResponse {
+            certificate_pem,
+            ca_certificate_pem: self.ca.cert_pem().to_string(),
+            agent_key,
+        }))
+    }
+}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+/*
+ * Feature flags
+ * Hub-managed switches for agent features, mostly collectors that are still experimental. A
+ * flag is on for the systems in its canary list and for rollout_percent of the rest: every
+ * system falls in a stable bucket 0-99 per flag, so raising the percentage only ever adds
+ * systems and a flag at 10% keeps the same tenth. The resolved flags go out with the agent
+ * config (see agent_config), below the features set in agent_config rows, which still win.
+ */
+
+pub const MAX_NAME_LENGTH: usize = 64;
+
+const GET_FLAGS: &str =
+    "SELECT name, rollout_percent, canary_systems FROM feature_flags ORDER BY name";
+
+const SET_FLAG: &str = "INSERT INTO feature_flags (name, rollout_percent, canary_systems) \
+     VALUES ($1, $2, $3) \
+     ON CONFLICT (name) DO UPDATE SET rollout_percent = EXCLUDED.rollout_percent, \
+         canary_systems = EXCLUDED.canary_systems, updated = now()";
+
+const DELETE_FLAG: &str = "DELETE FROM feature_flags WHERE name = $1";
+
+#[derive(Error, Debug)]
+pub enum FeatureFlagError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Invalid flag name '{0}', use up to {MAX_NAME_LENGTH} letters, digits, '_' or '-'")]
+    InvalidName(String),
+    #[error("rollout_percent must be between 0 and 100, got {0}")]
+    InvalidRollout(u8),
+    #[error("Flag '{0}' not found")]
+    NotFound(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct FeatureFlag {
+    #[serde(default)]
+    pub name: String,
+    /// Share of the systems outside the canary list that get the flag
+    #[serde(default)]
+    pub rollout_percent: u8,
+    /// Systems that get the flag regardless of rollout_percent
+    #[serde(default)]
+    pub canary_systems: Vec<i32>,
+}
+
+/// The same names agents use for their features: collector names, `smart`, `gpu`, ...
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/*
+ * bucket
+ * The system's place 0-99 in a flag's rollout. FNV-1a over the flag name and system id rather
+ * than std's hasher, whose output may change between Rust releases and would reshuffle every
+ * rollout on a hub upgrade. Hashing the name too spreads the canaries of different flags.
+ */
+pub fn bucket(flag: &str, system_id: i32) -> u8 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in flag.bytes().chain(system_id.to_le_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % 100) as u8
+}
+
+impl FeatureFlag {
+    pub fn enabled_for(&self, system_id: i32) -> bool {
+        self.canary_systems.contains(&system_id)
+            || bucket(&self.name, system_id) < self.rollout_percent
+    }
+
+    pub fn validate(&self) -> Result<(), FeatureFlagError> {
+        if !is_valid_name(&self.name) {
+            return Err(FeatureFlagError::InvalidName(self.name.clone()));
+        }
+        if self.rollout_percent > 100 {
+            return Err(FeatureFlagError::InvalidRollout(self.rollout_percent));
+        }
+        Ok(())
+    }
+}
+
+/// Every flag resolved for one system, the `features` it is sent.
+pub fn resolve(flags: &[FeatureFlag], system_id: i32) -> BTreeMap<String, bool> {
+    flags
+        .iter()
+        .map(|flag| (flag.name.clone(), flag.enabled_for(system_id)))
+        .collect()
+}
+
+pub async fn list(pool: &PgPool) -> Result<Vec<FeatureFlag>, sqlx::Error> {
+    let rows = sqlx::query(GET_FLAGS).fetch_all(pool).await?;
+    Ok(rows
+        .iter()
+        .map(|row| FeatureFlag {
+            name: row.get("name"),
+            rollout_percent: row.get::<i16, _>("rollout_percent").clamp(0, 100) as u8,
+            canary_systems: row.get("canary_systems"),
+        })
+        .collect())
+}
+
+/// Creates or replaces a flag, open config streams pick it up with their next poll.
+pub async fn set(pool: &PgPool, flag: &FeatureFlag) -> Result<FeatureFlag, FeatureFlagError> {
+    flag.validate()?;
+    let mut flag = flag.clone();
+    flag.canary_systems.sort_unstable();
+    flag.canary_systems.dedup();
+    sqlx::query(SET_FLAG)
+        .bind(&flag.name)
+        .bind(i16::from(flag.rollout_percent))
+        .bind(&flag.canary_systems)
+        .execute(pool)
+        .await?;
+    Ok(flag)
+}
+
+/// Removes a flag, agents fall back to their own default for the feature.
+pub async fn delete(pool: &PgPool, name: &str) -> Result<(), FeatureFlagError> {
+    let deleted = sqlx::query(DELETE_FLAG)
+        .bind(name)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(FeatureFlagError::NotFound(name.to_string()));
+    }
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use thiserror::Error;
+
+/*
+ * GPU history
+ * GPU samples are kept in gpu_metrics like the metrics table, pruned after RETENTION_DAYS and,
+ * with RETENTION_ROLLUP_DAYS, averaged per GPU and hour into gpu_metrics_hourly first (see
+ * retention). `query` reads both, so a graph spans the raw samples and the rollups before them,
+ * and averages them into buckets of `step`: each bucket weighs an hourly row by its samples and
+ * keeps the peak utilization and temperature.
+ */
+
+/// Range graphed when the query leaves it out.
+pub const DEFAULT_RANGE_HOURS: i64 = 24;
+/// Smallest bucket, agents report GPUs about once a minute.
+pub const MIN_STEP_SECS: i64 = 60;
+/// Buckets per GPU, the step grows with the range to stay below this.
+pub const MAX_POINTS: i64 = 1_000;
+/// Longest range one query may cover.
+pub const MAX_RANGE_DAYS: i64 = 400;
+
+const GET_GPU_HISTORY: &str = "WITH gpu AS ( \
+         SELECT id, gpu_index, name FROM gpus \
+         WHERE system_id = $1 AND ($4::integer IS NULL OR gpu_index = $4) \
+     ), samples AS ( \
+         SELECT gpu_id, time, 1 AS samples, utilization, utilization AS utilization_max, \
+             memory_used_mb::double precision AS memory_used_mb, temperature, \
+             temperature AS temperature_max, power \
+         FROM gpu_metrics WHERE gpu_id IN (SELECT id FROM gpu) AND time >= $2 AND time < $3 \
+         UNION ALL \
+         SELECT gpu_id, time, samples, utilization, utilization_max, memory_used_mb, \
+             temperature, temperature_max, power \
+         FROM gpu_metrics_hourly \
+         WHERE gpu_id IN (SELECT id FROM gpu) AND time >= $2 AND time < $3 \
+     ) \
+     SELECT gpu.gpu_index, gpu.name, \
+         time_bucket($5 * INTERVAL '1 second', s.time)::timestamptz AS time, \
+         sum(s.samples)::bigint AS samples, \
+         sum(s.utilization * s.samples) \
+             / nullif(sum(s.samples) FILTER (WHERE s.utilization IS NOT NULL), 0) AS utilization, \
+         max(s.utilization_max) AS utilization_max, \
+         sum(s.memory_used_mb * s.samples) \
+             / nullif(sum(s.samples) FILTER (WHERE s.memory_used_mb IS NOT NULL), 0) \
+             AS memory_used_mb, \
+         sum(s.temperature * s.samples) \
+             / nullif(sum(s.samples) FILTER (WHERE s.temperature IS NOT NULL), 0) AS temperature, \
+         max(s.temperature_max) AS temperature_max, \
+         sum(s.power * s.samples) \
+             / nullif(sum(s.samples) FILTER (WHERE s.power IS NOT NULL), 0) AS power \
+     FROM samples s JOIN gpu ON gpu.id = s.gpu_id \
+     GROUP BY 1, 2, 3 ORDER BY 1, 3";
+
+#[derive(Error, Debug)]
+pub enum GpuHistoryError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("from must be before to")]
+    EmptyRange,
+    #[error("Range is longer than {MAX_RANGE_DAYS} days")]
+    RangeTooLong,
+}
+
+/// Query string of `GET /systems/{id}/gpus/metrics`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GpuMetricsQuery {
+    /// RFC 3339, DEFAULT_RANGE_HOURS before `to` when unset
+    pub from: Option<DateTime<Utc>>,
+    /// RFC 3339, now when unset
+    pub to: Option<DateTime<Utc>>,
+    /// Only the GPU with this index, every GPU of the system when unset
+    pub gpu: Option<i32>,
+    /// Seconds per point, raised to fit the range in MAX_POINTS
+    pub step: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GpuPoint {
+    /// Start of the bucket
+    pub time: DateTime<Utc>,
+    /// Samples averaged into the point, rolled up hours count with their own samples
+    pub samples: i64,
+    /// Percent
+    pub utilization: Option<f64>,
+    pub utilization_max: Option<f64>,
+    pub memory_used_mb: Option<f64>,
+    /// Celsius
+    pub temperature: Option<f64>,
+    pub temperature_max: Option<f64>,
+    /// Watts
+    pub power: Option<f64>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GpuSeries {
+    pub gpu_index: i32,
+    pub name: Option<String>,
+    pub points: Vec<GpuPoint>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GpuHistory {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Seconds per point
+    pub step: i64,
+    /// GPUs without samples in the range are left out
+    pub gpus: Vec<GpuSeries>,
+}
+
+/// The range a query covers, `to` exclusive.
+pub fn range(
+    query: &GpuMetricsQuery,
+    now: DateTime<Utc>,
+) -> Result<(DateTime<Utc>, DateTime<Utc>), GpuHistoryError> {
+    let to = query.to.unwrap_or(now);
+    let from = query
+        .from
+        .unwrap_or(to - Duration::hours(DEFAULT_RANGE_HOURS));
+    if from >= to {
+        return Err(GpuHistoryError::EmptyRange);
+    }
+    if to - from > Duration::days(MAX_RANGE_DAYS) {
+        return Err(GpuHistoryError::RangeTooLong);
+    }
+    Ok((from, to))
+}
+
+/// Seconds per point: the requested step, at least MIN_STEP_SECS and coarse enough for MAX_POINTS.
+pub fn step(requested: Option<i64>, from: DateTime<Utc>, to: DateTime<Utc>) -> i64 {
+    let span = (to - from).num_seconds().max(0);
+    let fitting = (span + MAX_POINTS - 1) / MAX_POINTS;
+    requested.unwrap_or(0).max(fitting).max(MIN_STEP_SECS)
+}
+
+/// A system's GPU samples in the queried range, one series per GPU.
+pub async fn query(
+    pool: &PgPool,
+    system_id: i32,
+    query: &GpuMetricsQuery,
+) -> Result<GpuHistory, GpuHistoryError> {
+    let (from, to) = range(query, Utc::now())?;
+    let step = step(query.step, from, to);
+    let rows = sqlx::query(GET_GPU_HISTORY)
+        .bind(system_id)
+        .bind(from)
+        .bind(to)
+        .bind(query.gpu)
+        .bind(step)
+        .fetch_all(pool)
+        .await?;
+
+    let mut gpus: Vec<GpuSeries> = Vec::new();
+    for row in &rows {
+        let gpu_index: i32 = row.get("gpu_index");
+        let point = GpuPoint {
+            time: row.get("time"),
+            samples: row.get("samples"),
+            utilization: row.get("utilization"),
+            utilization_max: row.get("utilization_max"),
+            memory_used_mb: row.get("memory_used_mb"),
+            temperature: row.get("temperature"),
+            temperature_max: row.get("temperature_max"),
+            power: row.get("power"),
+        };
+        match gpus.last_mut() {
+            Some(series) if series.gpu_index == gpu_index => series.points.push(point),
+            _ => gpus.push(GpuSeries {
+                gpu_index,
+                name: row.get("name"),
+                points: vec![point],
+            }),
+        }
+    }
+    Ok(GpuHistory {
+        from,
+        to,
+        step,
+        gpus,
+    })
+}
@@ -1,23 +1,72 @@
+use crate::notify::{Evaluation, EvaluationQueue};
 use crate::proto::monitor::{ContainerMetrics, ContainerMetricsRequest, MetricsRequest};
+use crate::services::clock_skew;
+use crate::shutdown::Shutdown;
+use crate::sinks::{self, MetricSink};
+use crate::telemetry::TELEMETRY;
+use crate::units;
 use chrono::{DateTime, Utc};
 use log::{error, info};
+use serde::Serialize;
 use sqlx::{PgPool, QueryBuilder};
 use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::sync::mpsc::Receiver;
+use thiserror::Error;
+use tokio::sync::mpsc::error::SendTimeoutError;
+use tokio::sync::mpsc::{channel, Receiver, Sender};
 use tokio::sync::RwLock;
 use tokio::time::{Duration, Instant};
 use tonic::Status;
 
+/// Samples stamped further ahead of the hub's clock than this are stored with the hub's time.
+pub const MAX_FUTURE_SKEW: chrono::Duration = chrono::Duration::minutes(2);
+/// Older samples are rejected, nothing buffers metrics for longer than a day.
+pub const MAX_SAMPLE_AGE: chrono::Duration = chrono::Duration::hours(24);
+
+/*
+ * sample_time
+ * Picks the time a metric sample is stored with. The agent's collection time is used when it
+ * is set and plausible, so buffered or late samples keep their time. Clocks running ahead fall
+ * back to the hub's time, samples older than MAX_SAMPLE_AGE are rejected.
+ */
+#[allow(clippy::result_large_err)]
+pub fn sample_time(
+    collected_at_ms: Option<i64>,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>, Status> {
+    let Some(ms) = collected_at_ms else {
+        return Ok(now);
+    };
+    let Some(collected_at) = DateTime::from_timestamp_millis(ms) else {
+        return Err(Status::invalid_argument("collected_at_ms out of range"));
+    };
+    if collected_at - now > MAX_FUTURE_SKEW {
+        log::warn!(
+            "[ingest] sample stamped {}s in the future, using hub time",
+            (collected_at - now).num_seconds()
+        );
+        return Ok(now);
+    }
+    if now - collected_at > MAX_SAMPLE_AGE {
+        return Err(Status::invalid_argument("collected_at_ms is too old"));
+    }
+    Ok(collected_at)
+}
+
 #[derive(Debug)]
 pub struct DiskEntry {
     pub name: String,
-    pub total_space: i64,
-    pub used_space: i64,
+    pub total_bytes: i64,
+    pub used_bytes: i64,
+    /// Bytes/sec since the agent's previous sample
     pub read_bytes: f64,
     pub write_bytes: f64,
-    pub unit: String,
     pub mount_point: String,
+    pub read_iops: Option<f64>,
+    pub write_iops: Option<f64>,
+    pub inodes_total: Option<i64>,
+    pub inodes_used: Option<i64>,
 }
 
 #[derive(Debug)]
@@ -25,18 +74,135 @@ pub struct MetricIngestItem {
     pub system_id: i32,
     pub time: DateTime<Utc>,
     pub cpu_usage: f64,
-    pub memory_used_kb: i64,
-    pub memory_total_kb: i64,
+    pub cpu_user: Option<f64>,
+    pub cpu_system: Option<f64>,
+    pub cpu_iowait: Option<f64>,
+    pub cpu_irq: Option<f64>,
+    pub cpu_steal: Option<f64>,
+    pub memory_used_bytes: i64,
+    pub memory_total_bytes: i64,
+    pub memory_available_bytes: Option<i64>,
+    pub swap_used_bytes: Option<i64>,
     pub components_json: String,
-    pub net_in: i64,
-    pub net_out: i64,
+    pub processes: Option<i32>,
+    pub threads: Option<i32>,
+    pub procs_running: Option<i32>,
+    pub procs_zombie: Option<i32>,
+    pub procs_blocked: Option<i32>,
+    pub ctxt_per_sec: Option<f64>,
+    pub intr_per_sec: Option<f64>,
+    pub entropy_avail: Option<i32>,
+    /// Bytes/sec across all interfaces
+    pub net_in_bytes_per_sec: f64,
+    pub net_out_bytes_per_sec: f64,
     pub load_one: f64,
     pub load_five: f64,
     pub load_fifteen: f64,
+    /// Agent clock minus hub clock when the report arrived, see services::clock_skew
+    pub clock_skew_ms: Option<i64>,
+    /// The agent's x-request-id, None for samples from the pollers
+    pub request_id: Option<String>,
     pub disks: Vec<DiskEntry>,
     pub original: MetricsRequest, // for notifications
 }
 
+#[derive(Debug, Serialize)]
+struct ComponentJSON {
+    label: String,
+    temperature: f32,
+}
+
+impl MetricIngestItem {
+    /// Validates a MetricsRequest and turns it into an ingest item stamped with its collection time.
+    #[allow(clippy::result_large_err)] // callers are gRPC handlers returning Status anyway
+    pub fn from_request(system_id: i32, metrics: MetricsRequest) -> Result<Self, Status> {
+        let cpu = metrics
+            .cpu_stats
+            .ok_or(Status::invalid_argument("missing cpu_stats"))?;
+        let mem = metrics
+            .memory_stats
+            .ok_or(Status::invalid_argument("missing memory_stats"))?;
+        let net = metrics
+            .network_stats
+            .ok_or(Status::invalid_argument("missing network_stats"))?;
+        let load = metrics
+            .load_average
+            .ok_or(Status::invalid_argument("missing load_average"))?;
+
+        let components_json = serde_json::to_string(
+            &metrics
+                .components
+                .iter()
+                .map(|c| ComponentJSON {
+                    label: c.label.clone(),
+                    temperature: c.temperature,
+                })
+                .collect::<Vec<_>>(),
+        )
+        .unwrap_or("[]".to_string());
+
+        let (net_in_bytes_per_sec, net_out_bytes_per_sec) = units::network_rates(&net);
+        let received = Utc::now();
+        let time = sample_time(metrics.collected_at_ms, received)?;
+        let clock_skew_ms = clock_skew::measure(metrics.sent_at_ms, received);
+        let disks = metrics
+            .disk_stats
+            .iter()
+            .map(|d| {
+                let (total_bytes, used_bytes) = units::disk_bytes(d);
+                DiskEntry {
+                    name: d.name.clone(),
+                    total_bytes,
+                    used_bytes,
+                    read_bytes: d.read_bytes,
+                    write_bytes: d.write_bytes,
+                    mount_point: d.mount_point.clone(),
+                    read_iops: d.read_iops,
+                    write_iops: d.write_iops,
+                    inodes_total: d.inodes_total.map(|n| n as i64),
+                    inodes_used: d.inodes_used.map(|n| n as i64),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(MetricIngestItem {
+            system_id,
+            time,
+            cpu_usage: cpu.usage_percent,
+            cpu_user: cpu.user_percent,
+            cpu_system: cpu.system_percent,
+            cpu_iowait: cpu.iowait_percent,
+            cpu_irq: cpu.irq_percent,
+            cpu_steal: cpu.steal_percent,
+            memory_used_bytes: units::kb_to_bytes(mem.used_kb),
+            memory_total_bytes: units::kb_to_bytes(mem.total_kb),
+            memory_available_bytes: mem.available_kb.map(units::kb_to_bytes),
+            swap_used_bytes: mem.swap_used_kb.map(units::kb_to_bytes),
+            components_json,
+            processes: metrics.process_stats.map(|p| p.total as i32),
+            threads: metrics.process_stats.map(|p| p.threads as i32),
+            procs_running: metrics.process_stats.map(|p| p.running as i32),
+            procs_zombie: metrics.process_stats.map(|p| p.zombie as i32),
+            procs_blocked: metrics.kernel_stats.map(|k| k.procs_blocked as i32),
+            ctxt_per_sec: metrics.kernel_stats.map(|k| k.context_switches_per_sec),
+            intr_per_sec: metrics.kernel_stats.map(|k| k.interrupts_per_sec),
+            entropy_avail: metrics
+                .kernel_stats
+                .and_then(|k| k.entropy_avail)
+                .map(|bits| bits as i32),
+            net_in_bytes_per_sec,
+            net_out_bytes_per_sec,
+            load_one: load.one_minute,
+            load_five: load.five_minutes,
+            load_fifteen: load.fifteen_minutes,
+            clock_skew_ms,
+            request_id: None,
+            disks,
+            original: metrics,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct ContainerIngestItem {
     pub system_id: i32,
@@ -47,10 +213,146 @@ pub struct ContainerIngestItem {
     pub original: ContainerMetrics, // for notifications
 }
 
+/// An ad-hoc metric posted over HTTP, see services::custom_metrics.
+#[derive(Debug)]
+pub struct CustomMetricItem {
+    pub system_id: i32,
+    pub time: DateTime<Utc>,
+    pub name: String,
+    pub value: f64,
+    pub labels: serde_json::Value,
+}
+
 #[derive(Debug)]
 pub enum IngestItem {
     Metric(MetricIngestItem),
     Container(ContainerIngestItem),
+    Custom(CustomMetricItem),
+}
+
+impl IngestItem {
+    pub fn system_id(&self) -> i32 {
+        match self {
+            IngestItem::Metric(item) => item.system_id,
+            IngestItem::Container(item) => item.system_id,
+            IngestItem::Custom(item) => item.system_id,
+        }
+    }
+}
+
+/*
+ * Ingest queue
+ * RPC handlers, pollers and the HTTP API only queue samples, INGEST_WORKERS writer tasks batch
+ * them into the database. A system's items always go to the same writer, so they are written in
+ * order. When that writer's queue stays full for INGEST_WAIT_MS the overflow policy decides:
+ * `reject` tells the sender the sample was not taken (RESOURCE_EXHAUSTED over gRPC), `drop`
+ * acknowledges and discards it. Either way it is counted in lynx_ingest_overflows_total.
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    Reject,
+    Drop,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(OverflowPolicy::Reject),
+            "drop" => Ok(OverflowPolicy::Drop),
+            other => Err(format!("unknown overflow policy {other:?}")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IngestOptions {
+    /// Items queued across all writers
+    pub queue_size: usize,
+    /// Writer tasks, each with its share of the queue
+    pub workers: usize,
+    /// How long a sender waits for room before the overflow policy applies
+    pub wait: Duration,
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for IngestOptions {
+    fn default() -> Self {
+        Self {
+            queue_size: 10_000,
+            workers: 1,
+            wait: Duration::from_secs(1),
+            overflow: OverflowPolicy::Reject,
+        }
+    }
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum IngestError {
+    #[error("Ingest queue is full")]
+    Full,
+    #[error("Ingest queue closed")]
+    Closed,
+}
+
+impl From<IngestError> for Status {
+    fn from(e: IngestError) -> Self {
+        match e {
+            IngestError::Full => Status::resource_exhausted("ingest queue is full, retry later"),
+            IngestError::Closed => Status::unavailable("ingest pipeline unavailable"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct IngestQueue {
+    senders: Vec<Sender<IngestItem>>,
+    wait: Duration,
+    overflow: OverflowPolicy,
+}
+
+impl IngestQueue {
+    /// The queue and one receiver per writer.
+    pub fn new(options: &IngestOptions) -> (Self, Vec<Receiver<IngestItem>>) {
+        let workers = options.workers.max(1);
+        let per_worker = (options.queue_size / workers).max(1);
+        let (senders, receivers) = (0..workers).map(|_| channel(per_worker)).unzip();
+        let queue = Self {
+            senders,
+            wait: options.wait,
+            overflow: options.overflow,
+        };
+        (queue, receivers)
+    }
+
+    /// Items waiting across all writers.
+    pub fn depth(&self) -> usize {
+        self.senders
+            .iter()
+            .map(|tx| tx.max_capacity() - tx.capacity())
+            .sum()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.senders.iter().map(Sender::max_capacity).sum()
+    }
+
+    pub async fn push(&self, item: IngestItem) -> Result<(), IngestError> {
+        let writer = item.system_id().rem_euclid(self.senders.len() as i32) as usize;
+        match self.senders[writer].send_timeout(item, self.wait).await {
+            Ok(()) => Ok(()),
+            Err(SendTimeoutError::Closed(_)) => Err(IngestError::Closed),
+            Err(SendTimeoutError::Timeout(_)) => {
+                TELEMETRY.record_ingest_overflow();
+                match self.overflow {
+                    OverflowPolicy::Reject => Err(IngestError::Full),
+                    OverflowPolicy::Drop => Ok(()),
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -60,20 +362,41 @@ pub struct MetricWorkerState {
 }
 
 const METRIC_BATCH_MAX: usize = 200;
+/// Six parameters per row, Postgres allows 65535 per statement
+const PROCESS_ROWS_PER_INSERT: usize = 10_000;
 const METRIC_FLUSH_MS: u64 = 3000;
 
-const ALERT_COOLDOWN: Duration = Duration::from_secs(600); // 10 minutes
-
-pub async fn run_metric_worker(mut rx: Receiver<IngestItem>, pool: PgPool) {
+/*
+ * run_metric_worker
+ * Writes batches of queued items, then hands the stored samples to the notification workers
+ * (see notify::worker) and the metric sinks.
+ */
+pub async fn run_metric_worker(
+    mut rx: Receiver<IngestItem>,
+    pool: PgPool,
+    sinks: Vec<Arc<dyn MetricSink>>,
+    evaluations: EvaluationQueue,
+    shutdown: Shutdown,
+) {
     use tokio::time::{timeout, Duration};
 
     let mut batch: Vec<IngestItem> = Vec::with_capacity(METRIC_BATCH_MAX);
     let mut last_flush = Instant::now();
-    let alert_history = Arc::new(RwLock::new(HashMap::<String, Instant>::new()));
+    let mut draining = false;
     loop {
         // Ensure at least one item (or exit if channel is closed)
         if batch.is_empty() {
-            match rx.recv().await {
+            let next = tokio::select! {
+                item = rx.recv() => item,
+                _ = shutdown.wait(), if !draining => {
+                    // queued items are still received, new sends fail
+                    info!("[ingest] Shutting down, draining {} queued items", rx.len());
+                    rx.close();
+                    draining = true;
+                    continue;
+                }
+            };
+            match next {
                 Some(item) => {
                     batch.push(item);
                     last_flush = Instant::now();
@@ -105,41 +428,52 @@ pub async fn run_metric_worker(mut rx: Receiver<IngestItem>, pool: PgPool) {
         }
 
         if !batch.is_empty() {
-            if let Err(e) = flush_batch(&pool, &batch).await {
-                error!("[ingest] Batch flush failed: {e}");
+            let started = Instant::now();
+            let flushed = flush_batch(&pool, &batch).await;
+            TELEMETRY.record_flush(batch.len(), started.elapsed(), flushed.is_ok());
+            if let Err(e) = flushed {
+                error!(
+                    "[ingest] Batch flush failed: {e} (requests {})",
+                    request_ids(&batch).join(", ")
+                );
             } else {
-                let pool_clone = pool.clone();
-                let state_clone = alert_history.clone();
+                for id in request_ids(&batch) {
+                    tracing::debug!(request_id = id, "[ingest] Metrics written");
+                }
+                // Custom metrics are evaluated on their own, latest value per name and system
+                for (system_id, values) in latest_custom_values(&batch) {
+                    evaluations.push(Evaluation::Custom { system_id, values });
+                }
 
                 // Currently only processing notifications for MetricIngestItem
                 // todo: Add support for container metrics notifications
-                if let IngestItem::Container(_) = batch[0] {
-                    batch.clear();
-                    continue;
+                for item in &batch {
+                    if let IngestItem::Metric(m) = item {
+                        evaluations.push(Evaluation::Metrics {
+                            system_id: m.system_id,
+                            metrics: Box::new(m.original.clone()),
+                            clock_skew_ms: m.clock_skew_ms,
+                            request_id: m.request_id.clone(),
+                        });
+                    }
                 }
 
-                let batch_clone: Vec<_> = batch
-                    .iter()
-                    .filter_map(|item| {
-                        if let IngestItem::Metric(m) = item {
-                            Some((m.system_id, m.original.clone()))
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-
-                cleanup_expired_alerts(&alert_history, ALERT_COOLDOWN).await;
-
-                tokio::spawn(async move {
-                    process_batch_notifications(&pool_clone, &batch_clone, &state_clone).await;
-                });
+                if !sinks.is_empty() {
+                    let metrics: Vec<MetricIngestItem> = batch
+                        .drain(..)
+                        .filter_map(|item| match item {
+                            IngestItem::Metric(m) => Some(m),
+                            IngestItem::Container(_) | IngestItem::Custom(_) => None,
+                        })
+                        .collect();
+                    sinks::dispatch(&sinks, Arc::new(metrics));
+                }
             }
             batch.clear();
         }
 
-        // Exit if channel closed and nothing pending
-        if rx.is_closed() && batch.is_empty() {
+        // Exit if channel closed and nothing pending, a closed channel may still hold items
+        if rx.is_closed() && rx.is_empty() && batch.is_empty() {
             break;
         }
     }
@@ -153,6 +487,31 @@ async fn flush_batch(pool: &PgPool, batch: &[IngestItem]) -> Result<(), sqlx::Er
     }
 
     let mut tx = pool.begin().await?;
+
+    // Custom metrics can arrive between any other items, insert them regardless of batch kind
+    let custom: Vec<&CustomMetricItem> = batch
+        .iter()
+        .filter_map(|item| {
+            if let IngestItem::Custom(c) = item {
+                Some(c)
+            } else {
+                None
+            }
+        })
+        .collect();
+    if !custom.is_empty() {
+        let mut qb =
+            QueryBuilder::new("INSERT INTO custom_metrics (time, system_id, name, value, labels) ");
+        qb.push_values(custom.iter(), |mut b, c| {
+            b.push_bind(c.time)
+                .push_bind(c.system_id)
+                .push_bind(&c.name)
+                .push_bind(c.value)
+                .push_bind(&c.labels);
+        });
+        qb.build().execute(&mut *tx).await?;
+    }
+
     match batch {
         [IngestItem::Metric(_), ..] => {
             let metrics: Vec<&MetricIngestItem> = batch
@@ -167,64 +526,157 @@ async fn flush_batch(pool: &PgPool, batch: &[IngestItem]) -> Result<(), sqlx::Er
                 .collect();
             {
                 let mut qb = QueryBuilder::new(
-                    "INSERT INTO metrics (time, system_id, cpu_usage, memory_used_kb, memory_total_kb, components, net_in, net_out, load_one, load_five, load_fifteen) ",
+                    "INSERT INTO metrics (time, system_id, cpu_usage, cpu_user, cpu_system, cpu_iowait, cpu_irq, cpu_steal, memory_used_bytes, memory_total_bytes, memory_available_bytes, swap_used_bytes, components, processes, threads, procs_running, procs_zombie, procs_blocked, ctxt_per_sec, intr_per_sec, entropy_avail, net_in_bytes_per_sec, net_out_bytes_per_sec, load_one, load_five, load_fifteen, clock_skew_ms) ",
                 );
                 qb.push_values(metrics.iter(), |mut b, m| {
                     b.push_bind(m.time)
                         .push_bind(m.system_id)
                         .push_bind(m.cpu_usage)
-                        .push_bind(m.memory_used_kb)
-                        .push_bind(m.memory_total_kb)
+                        .push_bind(m.cpu_user)
+                        .push_bind(m.cpu_system)
+                        .push_bind(m.cpu_iowait)
+                        .push_bind(m.cpu_irq)
+                        .push_bind(m.cpu_steal)
+                        .push_bind(m.memory_used_bytes)
+                        .push_bind(m.memory_total_bytes)
+                        .push_bind(m.memory_available_bytes)
+                        .push_bind(m.swap_used_bytes)
                         .push_bind(&m.components_json)
-                        .push_bind(m.net_in)
-                        .push_bind(m.net_out)
+                        .push_bind(m.processes)
+                        .push_bind(m.threads)
+                        .push_bind(m.procs_running)
+                        .push_bind(m.procs_zombie)
+                        .push_bind(m.procs_blocked)
+                        .push_bind(m.ctxt_per_sec)
+                        .push_bind(m.intr_per_sec)
+                        .push_bind(m.entropy_avail)
+                        .push_bind(m.net_in_bytes_per_sec)
+                        .push_bind(m.net_out_bytes_per_sec)
                         .push_bind(m.load_one)
                         .push_bind(m.load_five)
-                        .push_bind(m.load_fifteen);
+                        .push_bind(m.load_fifteen)
+                        .push_bind(m.clock_skew_ms);
                 });
                 qb.build().execute(&mut *tx).await?;
             }
 
             // Gather all disks
-            let mut latest_disks: HashMap<(i32, &str), (&DiskEntry, i32)> = HashMap::new();
-            for m in metrics {
+            let mut latest_disks: HashMap<(i32, &str), (&DiskEntry, DateTime<Utc>)> =
+                HashMap::new();
+            for m in &metrics {
                 for d in &m.disks {
-                    latest_disks.insert((m.system_id, d.name.as_str()), (d, m.system_id));
+                    latest_disks.insert((m.system_id, d.name.as_str()), (d, m.time));
                 }
             }
 
             if !latest_disks.is_empty() {
                 let mut qb = QueryBuilder::new(
                     "INSERT INTO disks \
-     (system, name, unit, mount_point, space, used, read, write, time) ",
+     (system, name, mount_point, space_bytes, used_bytes, read, write, read_iops, write_iops, inodes_total, inodes_used, time) ",
                 );
 
-                let disks: Vec<&DiskEntry> = latest_disks.values().map(|(d, _)| *d).collect();
-                let system_id = latest_disks.values().next().unwrap().1; // all have
-                let now = chrono::Utc::now();
-                qb.push_values(disks.iter(), |mut b, disk| {
-                    b.push_bind(system_id) // i64
-                        .push_bind(&disk.name) // String
-                        .push_bind(&disk.unit)
-                        .push_bind(&disk.mount_point)
-                        .push_bind(disk.total_space) // i64
-                        .push_bind(disk.used_space) // i64
-                        .push_bind(disk.read_bytes) // f64
-                        .push_bind(disk.write_bytes) // f64
-                        .push_bind(now); // Timestamp
-                });
+                qb.push_values(
+                    latest_disks.iter(),
+                    |mut b, ((system_id, _), (disk, time))| {
+                        b.push_bind(*system_id) // i32
+                            .push_bind(&disk.name) // String
+                            .push_bind(&disk.mount_point)
+                            .push_bind(disk.total_bytes) // i64
+                            .push_bind(disk.used_bytes) // i64
+                            .push_bind(disk.read_bytes) // f64
+                            .push_bind(disk.write_bytes) // f64
+                            .push_bind(disk.read_iops) // Option<f64>
+                            .push_bind(disk.write_iops) // Option<f64>
+                            .push_bind(disk.inodes_total) // Option<i64>
+                            .push_bind(disk.inodes_used) // Option<i64>
+                            .push_bind(*time); // Timestamp
+                    },
+                );
 
                 qb.push(
                     " ON CONFLICT (system, name, time) DO UPDATE SET \
-              unit = EXCLUDED.unit, \
               mount_point = EXCLUDED.mount_point, \
-              space = EXCLUDED.space, \
-              used = EXCLUDED.used, \
+              space_bytes = EXCLUDED.space_bytes, \
+              used_bytes = EXCLUDED.used_bytes, \
               read = EXCLUDED.read, \
               write = EXCLUDED.write, \
-              time = NOW()",
+              read_iops = EXCLUDED.read_iops, \
+              write_iops = EXCLUDED.write_iops, \
+              inodes_total = EXCLUDED.inodes_total, \
+              inodes_used = EXCLUDED.inodes_used",
+                );
+
+                qb.build().execute(&mut *tx).await?;
+            }
+
+            // Results of the probe targets pushed to the agents
+            let probes: Vec<_> = metrics
+                .iter()
+                .flat_map(|m| m.original.probe_results.iter().map(move |p| (*m, p)))
+                .collect();
+            if !probes.is_empty() {
+                let mut qb = QueryBuilder::new(
+                    "INSERT INTO probe_results (time, system_id, name, address, up, latency_ms) ",
                 );
+                qb.push_values(probes, |mut b, (m, probe)| {
+                    b.push_bind(m.time)
+                        .push_bind(m.system_id)
+                        .push_bind(&probe.name)
+                        .push_bind(&probe.address)
+                        .push_bind(probe.up)
+                        .push_bind(probe.latency_ms);
+                });
+                qb.build().execute(&mut *tx).await?;
+            }
+
+            // Busiest processes, see services::processes
+            let processes: Vec<_> = metrics
+                .iter()
+                .flat_map(|m| m.original.top_processes.iter().map(move |p| (*m, p)))
+                .collect();
+            // a full batch can hold more rows than one statement has bind parameters for
+            for chunk in processes.chunks(PROCESS_ROWS_PER_INSERT) {
+                let mut qb = QueryBuilder::new(
+                    "INSERT INTO process_samples (time, system_id, pid, name, cpu_usage, memory_kb) ",
+                );
+                qb.push_values(chunk, |mut b, (m, process)| {
+                    b.push_bind(m.time)
+                        .push_bind(m.system_id)
+                        .push_bind(process.pid as i32)
+                        .push_bind(&process.name)
+                        .push_bind(process.cpu_usage)
+                        .push_bind(process.memory_kb as i64);
+                });
+                qb.build().execute(&mut *tx).await?;
+            }
 
+            // Hourly SMART readings, see notify::smart
+            let drives: Vec<_> = metrics
+                .iter()
+                .flat_map(|m| m.original.smart_devices.iter().map(move |d| (*m, d)))
+                .collect();
+            if !drives.is_empty() {
+                let mut qb = QueryBuilder::new(
+                    "INSERT INTO smart_attributes (time, system_id, device, model, serial, passed, \
+                     reallocated_sectors, pending_sectors, uncorrectable_sectors, media_errors, \
+                     percentage_used, temperature, power_on_hours) ",
+                );
+                let count = |v: Option<u64>| v.map(|v| v as i64);
+                qb.push_values(drives, |mut b, (m, drive)| {
+                    b.push_bind(m.time)
+                        .push_bind(m.system_id)
+                        .push_bind(&drive.device)
+                        .push_bind(&drive.model)
+                        .push_bind(&drive.serial)
+                        .push_bind(drive.passed)
+                        .push_bind(count(drive.reallocated_sectors))
+                        .push_bind(count(drive.pending_sectors))
+                        .push_bind(count(drive.uncorrectable_sectors))
+                        .push_bind(count(drive.media_errors))
+                        .push_bind(drive.percentage_used.map(|v| v as i32))
+                        .push_bind(drive.temperature)
+                        .push_bind(count(drive.power_on_hours));
+                });
                 qb.build().execute(&mut *tx).await?;
             }
         }
@@ -273,10 +725,9 @@ async fn flush_batch(pool: &PgPool, batch: &[IngestItem]) -> Result<(), sqlx::Er
                             .push_bind(m.memory_usage);
                     },
                 );
-                if !any {
-                    return Ok(());
+                if any {
+                    qb.build().execute(&mut *tx).await?;
                 }
-                qb.build().execute(&mut *tx).await?;
             }
         }
         _ => {}
@@ -286,35 +737,26 @@ async fn flush_batch(pool: &PgPool, batch: &[IngestItem]) -> Result<(), sqlx::Er
     Ok(())
 }
 
-async fn cleanup_expired_alerts(state: &Arc<RwLock<HashMap<String, Instant>>>, cooldown: Duration) {
-    let mut alerts = state.write().await;
-    let now = Instant::now();
-    alerts.retain(|_, &mut last_triggered| now.duration_since(last_triggered) < cooldown);
+/// The request ids of the agent reports in a batch, for tracing them through the writers.
+fn request_ids(batch: &[IngestItem]) -> Vec<&str> {
+    batch
+        .iter()
+        .filter_map(|item| match item {
+            IngestItem::Metric(m) => m.request_id.as_deref(),
+            IngestItem::Container(_) | IngestItem::Custom(_) => None,
+        })
+        .collect()
 }
 
-async fn process_batch_notifications(
-    pool: &PgPool,
-    batch: &[(i32, MetricsRequest)],
-    triggered_alerts: &Arc<RwLock<HashMap<String, Instant>>>,
-) {
-    for (system_id, metrics) in batch {
-        let active_alerts = {
-            let alerts = triggered_alerts.read().await;
-            alerts.keys().cloned().collect::<HashSet<String>>()
-        };
-
-        match crate::notify::process_notification(metrics, *system_id, pool, &active_alerts).await {
-            Ok(new_triggered) => {
-                if !new_triggered.is_empty() {
-                    let mut alerts = triggered_alerts.write().await;
-                    let now = Instant::now();
-                    for rule_name in new_triggered {
-                        alerts.insert(rule_name, now);
-                    }
-                    info!("[notify] System {}: Alerts Updated", system_id);
-                }
-            }
-            Err(e) => error!("[notify] Failed for system {}: {e}", system_id),
+fn latest_custom_values(batch: &[IngestItem]) -> HashMap<i32, HashMap<String, f64>> {
+    let mut latest: HashMap<i32, HashMap<String, f64>> = HashMap::new();
+    for item in batch {
+        if let IngestItem::Custom(c) = item {
+            latest
+                .entry(c.system_id)
+                .or_default()
+                .insert(c.name.clone(), c.value);
         }
     }
+    latest
 }
@@ -0,0 +1,214 @@
+use crate::services::overview::SystemOverview;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/*
+ * Locations
+ * Where a system stands: site, datacenter and rack. Agents set them with the same keys in their
+ * [tags], which win over values set through the HTTP API, so the API is for systems whose config
+ * leaves them out. Systems are summarized per group of one level and maintenance can be scoped
+ * to a group (`site=eu-1`) to silence one location instead of the whole fleet.
+ */
+
+pub const MAX_LENGTH: usize = 64;
+
+const SET_LOCATION: &str = "UPDATE systems SET site = $2, datacenter = $3, rack = $4 \
+     WHERE id = $1 AND decommissioned IS NULL";
+
+#[derive(Error, Debug)]
+pub enum LocationError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("{0} is longer than {MAX_LENGTH} characters")]
+    TooLong(&'static str),
+    #[error("Invalid group '{0}', use site=<name>, datacenter=<name> or rack=<name>")]
+    InvalidGroup(String),
+    #[error("System {0} not found")]
+    SystemNotFound(i32),
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupLevel {
+    #[default]
+    Site,
+    Datacenter,
+    Rack,
+}
+
+impl GroupLevel {
+    pub const ALL: [GroupLevel; 3] = [GroupLevel::Site, GroupLevel::Datacenter, GroupLevel::Rack];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GroupLevel::Site => "site",
+            GroupLevel::Datacenter => "datacenter",
+            GroupLevel::Rack => "rack",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub site: Option<String>,
+    pub datacenter: Option<String>,
+    pub rack: Option<String>,
+}
+
+/// Trimmed, None when empty.
+fn normalize(value: Option<&str>) -> Option<String> {
+    value
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+impl Location {
+    /// The location keys of an agent's tags, values over MAX_LENGTH are left out.
+    pub fn from_tags(tags: &HashMap<String, String>) -> Self {
+        let tag = |level: GroupLevel| {
+            normalize(tags.get(level.name()).map(String::as_str)).filter(|v| v.len() <= MAX_LENGTH)
+        };
+        Self {
+            site: tag(GroupLevel::Site),
+            datacenter: tag(GroupLevel::Datacenter),
+            rack: tag(GroupLevel::Rack),
+        }
+    }
+
+    /// Trims every level, rejecting values over MAX_LENGTH.
+    pub fn normalized(&self) -> Result<Self, LocationError> {
+        let mut location = Self::default();
+        for level in GroupLevel::ALL {
+            let value = normalize(self.get(level));
+            if value.as_ref().is_some_and(|v| v.len() > MAX_LENGTH) {
+                return Err(LocationError::TooLong(level.name()));
+            }
+            *location.level_mut(level) = value;
+        }
+        Ok(location)
+    }
+
+    pub fn get(&self, level: GroupLevel) -> Option<&str> {
+        match level {
+            GroupLevel::Site => self.site.as_deref(),
+            GroupLevel::Datacenter => self.datacenter.as_deref(),
+            GroupLevel::Rack => self.rack.as_deref(),
+        }
+    }
+
+    fn level_mut(&mut self, level: GroupLevel) -> &mut Option<String> {
+        match level {
+            GroupLevel::Site => &mut self.site,
+            GroupLevel::Datacenter => &mut self.datacenter,
+            GroupLevel::Rack => &mut self.rack,
+        }
+    }
+}
+
+/// One location group, `site=eu-1`, e.g. as the scope of a maintenance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub level: GroupLevel,
+    pub name: String,
+}
+
+impl FromStr for Group {
+    type Err = LocationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || LocationError::InvalidGroup(s.to_string());
+        let (level, name) = s.split_once('=').ok_or_else(invalid)?;
+        let level = GroupLevel::ALL
+            .into_iter()
+            .find(|l| l.name() == level.trim())
+            .ok_or_else(invalid)?;
+        let name = normalize(Some(name))
+            .filter(|n| n.len() <= MAX_LENGTH)
+            .ok_or_else(invalid)?;
+        Ok(Self { level, name })
+    }
+}
+
+impl fmt::Display for Group {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.level.name(), self.name)
+    }
+}
+
+/// Query string of `GET /systems/groups`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GroupQuery {
+    #[serde(default)]
+    pub by: GroupLevel,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GroupSummary {
+    /// None for the systems without a value at this level
+    pub name: Option<String>,
+    pub systems: usize,
+    pub online: usize,
+    /// Systems with rules fired within the last 30 minutes
+    pub alerting: usize,
+    pub active_alerts: i64,
+    /// Averages over the systems that reported, in percent
+    pub cpu_usage: Option<f64>,
+    pub memory_usage: Option<f64>,
+}
+
+fn average(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    let (sum, count) = values
+        .flatten()
+        .fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    (count > 0).then(|| sum / count as f64)
+}
+
+/// The systems of the fleet overview per group of `level`, by name with unassigned ones last.
+pub fn summarize(systems: &[SystemOverview], level: GroupLevel) -> Vec<GroupSummary> {
+    let mut groups: BTreeMap<(bool, Option<&str>), Vec<&SystemOverview>> = BTreeMap::new();
+    for system in systems {
+        let name = system.location.get(level);
+        groups
+            .entry((name.is_none(), name))
+            .or_default()
+            .push(system);
+    }
+    groups
+        .into_iter()
+        .map(|((_, name), members)| GroupSummary {
+            name: name.map(str::to_string),
+            systems: members.len(),
+            online: members.iter().filter(|s| s.online).count(),
+            alerting: members.iter().filter(|s| s.active_alerts > 0).count(),
+            active_alerts: members.iter().map(|s| s.active_alerts).sum(),
+            cpu_usage: average(members.iter().map(|s| s.cpu_usage)),
+            memory_usage: average(members.iter().map(|s| s.memory_usage)),
+        })
+        .collect()
+}
+
+/// Sets a system's location, the levels the agent's tags carry come back with its next report.
+pub async fn set(
+    pool: &PgPool,
+    system_id: i32,
+    location: &Location,
+) -> Result<Location, LocationError> {
+    let location = location.normalized()?;
+    let updated = sqlx::query(SET_LOCATION)
+        .bind(system_id)
+        .bind(&location.site)
+        .bind(&location.datacenter)
+        .bind(&location.rack)
+        .execute(pool)
+        .await?
+        .rows_affected();
+    if updated == 0 {
+        return Err(LocationError::SystemNotFound(system_id));
+    }
+    Ok(location)
+}
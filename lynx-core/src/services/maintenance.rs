@@ -0,0 +1,220 @@
+use crate::services::location::{Group, LocationError};
+use chrono::{DateTime, Utc};
+use log::info;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use thiserror::Error;
+
+/*
+ * Maintenance mode
+ * Pauses every notification the hub sends, for all systems and on every hub sharing the
+ * database, during planned maintenance. Rules are still evaluated and alert history is still
+ * written, only the notifiers stay quiet. It always ends by itself at `until` so a forgotten
+ * maintenance can't silence alerting for good; it is started and ended with
+ * `lynx-core maintenance` or the SetMaintenance RPC. A maintenance can also be scoped to a
+ * location group (`site=eu-1`, see services::location), it then only pauses the notifications
+ * for that group's systems and runs alongside the hub-wide one and those of other groups.
+ */
+
+/// Longest maintenance that can be started at once, longer ones have to be extended.
+pub const MAX_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+const START_MAINTENANCE: &str = "INSERT INTO hub_maintenance (scope, started, until, reason) \
+     VALUES ($3, NOW(), NOW() + ($1 * INTERVAL '1 second'), $2) \
+     ON CONFLICT (scope) DO UPDATE SET \
+     started = CASE WHEN hub_maintenance.until > NOW() THEN hub_maintenance.started ELSE NOW() END, \
+     until = EXCLUDED.until, reason = EXCLUDED.reason \
+     RETURNING scope, started, until, reason";
+
+const END_MAINTENANCE: &str = "DELETE FROM hub_maintenance WHERE scope = $1 AND until > NOW()";
+
+const GET_MAINTENANCE: &str = "SELECT scope, started, until, reason FROM hub_maintenance \
+     WHERE scope = $1 AND until > NOW()";
+
+const LIST_MAINTENANCE: &str = "SELECT scope, started, until, reason FROM hub_maintenance \
+     WHERE until > NOW() ORDER BY scope";
+
+const GET_SYSTEM_MAINTENANCE: &str = "SELECT m.scope, m.started, m.until, m.reason \
+     FROM hub_maintenance m LEFT JOIN systems s ON s.id = $1 \
+     WHERE m.until > NOW() AND (m.scope = '' OR m.scope = 'site=' || s.site \
+         OR m.scope = 'datacenter=' || s.datacenter OR m.scope = 'rack=' || s.rack) \
+     ORDER BY m.scope LIMIT 1";
+
+#[derive(Serialize, Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Maintenance {
+    /// Empty for the whole hub, otherwise a location group such as `site=eu-1`
+    pub scope: String,
+    pub started: DateTime<Utc>,
+    pub until: DateTime<Utc>,
+    pub reason: String,
+}
+
+#[derive(Error, Debug)]
+pub enum MaintenanceError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("Invalid duration '{0}', use e.g. 90m, 4h or 2d")]
+    InvalidDuration(String),
+    #[error("Maintenance can last at most {} days", MAX_DURATION.as_secs() / 86400)]
+    TooLong,
+    #[error("{0}")]
+    Scope(#[from] LocationError),
+    #[error("{0}")]
+    Usage(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MaintenanceCommand {
+    Start {
+        duration: Duration,
+        reason: String,
+        scope: Option<Group>,
+    },
+    End {
+        scope: Option<Group>,
+    },
+    Status,
+}
+
+/// Splits a leading `--scope <group>` off the arguments.
+fn split_scope(args: &[String]) -> Result<(Option<Group>, &[String]), MaintenanceError> {
+    match args {
+        [flag, group, rest @ ..] if flag == "--scope" => Ok((Some(group.parse()?), rest)),
+        _ => Ok((None, args)),
+    }
+}
+
+/// The key a maintenance is stored under, empty for the whole hub.
+fn scope_key(scope: Option<&Group>) -> String {
+    scope.map(Group::to_string).unwrap_or_default()
+}
+
+impl MaintenanceCommand {
+    /// `maintenance ...` in the hub's arguments (without the program name), None otherwise.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, MaintenanceError> {
+        let usage = || {
+            MaintenanceError::Usage(
+                "usage: lynx-core maintenance start <duration> [--scope <group>] [reason] \
+                 | end [--scope <group>] | status"
+                    .to_string(),
+            )
+        };
+        match args {
+            [command, rest @ ..] if command == "maintenance" => match rest {
+                [action, duration, rest @ ..] if action == "start" => {
+                    let (scope, reason) = split_scope(rest)?;
+                    Ok(Some(Self::Start {
+                        duration: parse_duration(duration)?,
+                        reason: reason.join(" "),
+                        scope,
+                    }))
+                }
+                [action, rest @ ..] if action == "end" => match split_scope(rest)? {
+                    (scope, []) => Ok(Some(Self::End { scope })),
+                    _ => Err(usage()),
+                },
+                [action] if action == "status" => Ok(Some(Self::Status)),
+                _ => Err(usage()),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+/// `90m`, `4h`, `2d` or plain minutes.
+pub fn parse_duration(text: &str) -> Result<Duration, MaintenanceError> {
+    let invalid = || MaintenanceError::InvalidDuration(text.to_string());
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(at) => text.split_at(at),
+        None => (text, "m"),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(invalid()),
+    };
+    let duration = Duration::from_secs(number.checked_mul(secs).ok_or_else(invalid)?);
+    if duration.is_zero() {
+        return Err(invalid());
+    }
+    if duration > MAX_DURATION {
+        return Err(MaintenanceError::TooLong);
+    }
+    Ok(duration)
+}
+
+/// Starts maintenance of the hub or of one group, or moves the end of the running one to
+/// `duration` from now.
+pub async fn start(
+    pool: &PgPool,
+    duration: Duration,
+    reason: &str,
+    scope: Option<&Group>,
+) -> Result<Maintenance, MaintenanceError> {
+    if duration.is_zero() {
+        return Err(MaintenanceError::InvalidDuration("0".to_string()));
+    }
+    if duration > MAX_DURATION {
+        return Err(MaintenanceError::TooLong);
+    }
+    let maintenance = sqlx::query_as::<_, Maintenance>(START_MAINTENANCE)
+        .bind(duration.as_secs() as i64)
+        .bind(reason)
+        .bind(scope_key(scope))
+        .fetch_one(pool)
+        .await?;
+    match scope {
+        Some(group) => info!(
+            "[maintenance] Notifications for {} paused until {} ({})",
+            group, maintenance.until, maintenance.reason
+        ),
+        None => info!(
+            "[maintenance] Notifications paused until {} ({})",
+            maintenance.until, maintenance.reason
+        ),
+    }
+    Ok(maintenance)
+}
+
+/// Ends the running maintenance of the hub or of one group, false if there was none.
+pub async fn end(pool: &PgPool, scope: Option<&Group>) -> Result<bool, sqlx::Error> {
+    let ended = sqlx::query(END_MAINTENANCE)
+        .bind(scope_key(scope))
+        .execute(pool)
+        .await?
+        .rows_affected()
+        > 0;
+    if ended {
+        info!("[maintenance] Ended, notifications resume");
+    }
+    Ok(ended)
+}
+
+/// The running maintenance of the hub, or of one group.
+pub async fn current(
+    pool: &PgPool,
+    scope: Option<&Group>,
+) -> Result<Option<Maintenance>, sqlx::Error> {
+    sqlx::query_as::<_, Maintenance>(GET_MAINTENANCE)
+        .bind(scope_key(scope))
+        .fetch_optional(pool)
+        .await
+}
+
+/// Every running maintenance, the hub-wide one first.
+pub async fn list(pool: &PgPool) -> Result<Vec<Maintenance>, sqlx::Error> {
+    sqlx::query_as::<_, Maintenance>(LIST_MAINTENANCE)
+        .fetch_all(pool)
+        .await
+}
+
+/// The maintenance pausing a system's notifications: the hub-wide one or its groups'.
+pub async fn for_system(pool: &PgPool, system_id: i32) -> Result<Option<Maintenance>, sqlx::Error> {
+    sqlx::query_as::<_, Maintenance>(GET_SYSTEM_MAINTENANCE)
+        .bind(system_id)
+        .fetch_optional(pool)
+        .await
+}
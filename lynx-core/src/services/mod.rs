@@ -1,3 +1,27 @@
 pub mod agent;
+pub mod agent_config;
+pub mod agent_events;
+pub mod agent_health;
+pub mod authorization;
+pub mod clock_skew;
+pub mod command_audit;
+pub mod custom_metrics;
+pub mod decommission;
+pub mod enroll;
+pub mod feature_flags;
+pub mod gpu_history;
 pub mod ingest;
+pub mod location;
+pub mod maintenance;
 pub mod monitor;
+pub mod overview;
+pub mod processes;
+pub mod prometheus_poller;
+pub mod releases;
+pub mod rule_pack;
+pub mod service_graph;
+pub mod service_list;
+pub mod sessions;
+pub mod snmp_poller;
+pub mod status;
+pub mod validation;
@@ -1,127 +1,384 @@
+use crate::auth_limit::{self, AuthDenied, AuthLimiter};
 use crate::cache::Cache;
+use crate::logging;
+use crate::proto::monitor::control_server::Control;
+use crate::proto::monitor::inventory_server::Inventory;
+use crate::proto::monitor::metrics_ingest_server::MetricsIngest;
 use crate::proto::monitor::system_monitor_server::SystemMonitor;
 use crate::proto::monitor::{
-    ContainerInfo, ContainerMetrics, ContainerMetricsRequest, ContainerRequest, ContainerResponse,
-    GpuInfo, GpuMetrics, GpuMetricsRequest, GpuRequest, GpuResponse, MetricsRequest,
-    MetricsResponse, Response as ProtoResponse, SystemInfoRequest, SystemInfoResponse,
-    SystemctlRequest, SystemctlResponse,
+    AgentConfig, AgentEvent, AgentHealthEvent, CommandAudit, ContainerInfo, ContainerMetrics,
+    ContainerMetricsRequest, ContainerRequest, ContainerResponse, GpuInfo, GpuMetrics,
+    GpuMetricsRequest, GpuRequest, GpuResponse, MaintenanceRequest, MaintenanceStatus, MetricsAck,
+    MetricsRequest, MetricsResponse, ReleaseManifest, ReleaseRequest, Response as ProtoResponse,
+    SessionFrame, SystemInfoRequest, SystemInfoResponse, SystemService, SystemStatusRequest,
+    SystemStatusResponse, SystemctlRequest, SystemctlResponse, WatchConfigRequest,
 };
-use crate::services::ingest::{ContainerIngestItem, DiskEntry, IngestItem, MetricIngestItem};
-use chrono::Utc;
-use log::{error, info};
-use serde::{Deserialize, Serialize};
+use crate::revocation::RevocationChecker;
+use crate::services::custom_metrics::{self, CustomMetricsRequest};
+use crate::services::ingest::{
+    sample_time, ContainerIngestItem, IngestItem, IngestQueue, MetricIngestItem,
+};
+use crate::services::location::{Group, Location};
+use crate::services::maintenance::{self, Maintenance, MaintenanceError};
+use crate::services::sessions::{FrameStream, SessionRelay};
+use crate::services::validation::{self, ValidationError};
+use crate::services::{
+    agent_config, agent_events, agent_health, command_audit, decommission, releases, status,
+};
+use crate::shutdown::Shutdown;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
 use sqlx::QueryBuilder;
-use tokio::sync::mpsc::Sender;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
 use tonic::codegen::tokio_stream::StreamExt;
-use tonic::metadata::MetadataMap;
-use tonic::{Request, Response, Status, Streaming};
+use tonic::{Code, Request, Response, Status, Streaming};
+use tracing::{Instrument, Span};
+
+/// Seconds an agent is asked to hold samples back for while the ingest queue is full.
+pub const METRICS_RETRY_AFTER_SECS: u32 = 5;
+/// Acks waiting for a slow agent, the channel stops reading samples while they are queued.
+const METRICS_ACK_QUEUE: usize = 16;
+/// Rows per services upsert, 10 binds a row keeps a report under Postgres' 65535 bind limit.
+const SERVICE_UPSERT_CHUNK: usize = 1000;
 
 #[derive(Clone)]
 pub struct MyMonitor {
     pub pool: sqlx::PgPool,
+    /// Used by read-only query endpoints, may point at a replica
+    pub read_pool: sqlx::PgPool,
     pub cache: Cache,
-    pub metric_tx: Sender<IngestItem>,
+    pub ingest: IngestQueue,
+    /// When set, an agent key is only accepted together with the client cert pinned to it
+    pub pin_client_certs: bool,
+    /// CRL / OCSP checks on agent client certificates, None when not configured
+    pub revocation: Option<Arc<RevocationChecker>>,
+    pub auth_limit: Arc<AuthLimiter>,
+    /// Ends open WatchConfig and StreamMetrics streams when the hub shuts down
+    pub shutdown: Shutdown,
+    /// ADMIN_TOKEN, required by the operator RPCs
+    pub admin_token: Option<String>,
+    /// Agents' OpenSessions streams, shared with the HTTP server
+    pub sessions: SessionRelay,
+}
+
+/// What an agent presented with a request.
+struct AgentCredentials {
+    agent_key: String,
+    /// DER of the leaf client certificate, only extracted when something needs it
+    client_cert: Option<Vec<u8>>,
+    remote_ip: Option<IpAddr>,
+}
+
+/// Why an agent failed to authenticate, everything but `Internal` counts towards a lockout.
+enum AuthError {
+    InvalidKey,
+    CertRejected,
+    CertMismatch(i32),
+    Internal(Status),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ComponentJSON {
-    label: String,
-    temperature: f32,
+impl AuthError {
+    /// The auth_events entry for a failed attempt, None for internal errors.
+    fn event(&self) -> Option<(&'static str, Option<i32>)> {
+        match self {
+            AuthError::InvalidKey => Some(("invalid_key", None)),
+            AuthError::CertRejected => Some(("cert_rejected", None)),
+            AuthError::CertMismatch(id) => Some(("cert_mismatch", Some(*id))),
+            AuthError::Internal(_) => None,
+        }
+    }
+}
+
+impl From<AuthError> for Status {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::InvalidKey => Status::unauthenticated("Invalid or inactive agent key"),
+            AuthError::CertRejected => Status::permission_denied("Client certificate rejected"),
+            AuthError::CertMismatch(_) => {
+                Status::permission_denied("Client certificate does not match agent key")
+            }
+            AuthError::Internal(status) => status,
+        }
+    }
+}
+
+/// What agent_credentials finds missing, far smaller than the Status it becomes.
+#[derive(Debug)]
+enum CredentialsError {
+    MissingKey,
+    InvalidKey,
+    MissingCert,
+}
+
+impl From<CredentialsError> for Status {
+    fn from(e: CredentialsError) -> Self {
+        match e {
+            CredentialsError::MissingKey => Status::unauthenticated("Missing key"),
+            CredentialsError::InvalidKey => Status::invalid_argument("Invalid key"),
+            CredentialsError::MissingCert => Status::unauthenticated("Client certificate required"),
+        }
+    }
 }
 
 impl MyMonitor {
-    async fn get_system_id_from_md(&self, md: &MetadataMap) -> Result<i32, Status> {
-        let agent_key = md
+    /// Checks `authorization: Bearer` metadata against ADMIN_TOKEN in constant time.
+    #[allow(clippy::result_large_err)]
+    fn require_admin<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let Some(expected) = &self.admin_token else {
+            return Err(Status::permission_denied(
+                "ADMIN_TOKEN is not set on this hub",
+            ));
+        };
+        let presented = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(Status::unauthenticated("Missing admin token"))?;
+        if presented.len() != expected.len()
+            || !openssl::memcmp::eq(presented.as_bytes(), expected.as_bytes())
+        {
+            return Err(Status::unauthenticated("Invalid admin token"));
+        }
+        Ok(())
+    }
+
+    /*
+     * agent_credentials
+     * Extracts the agent key and, when pinning or revocation checks need it, the client cert.
+     * Kept synchronous so streaming requests aren't held across an await.
+     */
+    fn agent_credentials<T>(
+        &self,
+        request: &Request<T>,
+    ) -> Result<AgentCredentials, CredentialsError> {
+        let agent_key = request
+            .metadata()
             .get("x-agent-key")
-            .ok_or(Status::unauthenticated("Missing key"))?
+            .ok_or(CredentialsError::MissingKey)?
             .to_str()
-            .map_err(|_| Status::invalid_argument("Invalid key"))?;
+            .map_err(|_| CredentialsError::InvalidKey)?
+            .to_string();
+
+        let client_cert = if self.pin_client_certs || self.revocation.is_some() {
+            let certs = request.peer_certs().ok_or(CredentialsError::MissingCert)?;
+            let leaf = certs.first().ok_or(CredentialsError::MissingCert)?;
+            Some(leaf.to_vec())
+        } else {
+            None
+        };
+        Ok(AgentCredentials {
+            agent_key,
+            client_cert,
+            remote_ip: request.remote_addr().map(|addr| addr.ip()),
+        })
+    }
+
+    /*
+     * get_system_id
+     * Authenticates the agent behind the rate limiter. Failed attempts count towards a lockout of
+     * the source IP and key and are recorded in auth_events.
+     */
+    async fn get_system_id(&self, creds: AgentCredentials) -> Result<i32, Status> {
+        let remote_ip = creds.remote_ip;
+        let key_id = auth_limit::key_id(&creds.agent_key);
+
+        if let Err(denied) = self.auth_limit.check(remote_ip, &key_id) {
+            return Err(self.auth_denied(remote_ip, &key_id, denied).await);
+        }
+
+        match self.authenticate(creds).await {
+            Ok(id) => {
+                self.auth_limit.record_success(&key_id);
+                logging::record_system_id(id);
+                Ok(id)
+            }
+            Err(e) => {
+                if let Some((event, system)) = e.event() {
+                    auth_limit::record_auth_event(&self.pool, event, remote_ip, &key_id, system)
+                        .await;
+                    for subject in self.auth_limit.record_failure(remote_ip, &key_id) {
+                        warn!("[hub] Too many failed authentications, locking out {subject}");
+                        auth_limit::record_auth_event(
+                            &self.pool,
+                            "locked_out",
+                            remote_ip,
+                            &key_id,
+                            system,
+                        )
+                        .await;
+                    }
+                }
+                Err(e.into())
+            }
+        }
+    }
+
+    async fn auth_denied(
+        &self,
+        remote_ip: Option<IpAddr>,
+        key_id: &str,
+        denied: AuthDenied,
+    ) -> Status {
+        match denied {
+            AuthDenied::RateLimited { subject, first } => {
+                if first {
+                    warn!("[hub] Authentication rate limit exceeded by {subject}");
+                    auth_limit::record_auth_event(
+                        &self.pool,
+                        "rate_limited",
+                        remote_ip,
+                        key_id,
+                        None,
+                    )
+                    .await;
+                }
+                Status::resource_exhausted("Too many authentication attempts, retry later")
+            }
+            AuthDenied::LockedOut {
+                subject,
+                retry_after,
+            } => Status::resource_exhausted(format!(
+                "{subject} is locked out for {}s after repeated failed authentications",
+                retry_after.as_secs()
+            )),
+        }
+    }
+
+    async fn authenticate(&self, creds: AgentCredentials) -> Result<i32, AuthError> {
+        let AgentCredentials {
+            agent_key,
+            client_cert,
+            ..
+        } = creds;
+
+        if let (Some(revocation), Some(der)) = (&self.revocation, &client_cert) {
+            revocation.check(der).await.map_err(|e| {
+                warn!("[hub] Rejected client certificate: {e}");
+                AuthError::CertRejected
+            })?;
+        }
+
+        let fingerprint = client_cert
+            .as_deref()
+            .filter(|_| self.pin_client_certs)
+            .map(crate::tls::cert_fingerprint);
 
-        if let Some(id) = self.cache.get_system_id(agent_key) {
+        // a key presented with a different certificate must miss the cache
+        let cache_key = match &fingerprint {
+            Some(fp) => format!("{agent_key}:{fp}"),
+            None => agent_key.to_string(),
+        };
+        if let Some(id) = self.cache.get_system_id(&cache_key) {
             return Ok(id);
         }
 
         let rec = sqlx::query!(
-            r#"SELECT id FROM systems WHERE key = $1 AND active = true"#,
+            r#"SELECT id, cert_fingerprint FROM systems WHERE key = $1 AND active = true"#,
             agent_key
         )
         .fetch_optional(&self.pool)
         .await
         .map_err(|e| {
             error!("[hub] DB system lookup error: {e}");
-            Status::internal("Database error")
+            AuthError::Internal(Status::internal("Database error"))
         })?
-        .ok_or(Status::unauthenticated("Invalid or inactive agent key"))?;
+        .ok_or(AuthError::InvalidKey)?;
+
+        if let Some(fp) = &fingerprint {
+            match rec.cert_fingerprint.as_deref() {
+                Some(pinned) if pinned == fp => {}
+                Some(_) => {
+                    warn!(
+                        "[hub] Client certificate mismatch for system {}, rejecting",
+                        rec.id
+                    );
+                    return Err(AuthError::CertMismatch(rec.id));
+                }
+                None => self.pin_certificate(rec.id, fp).await?,
+            }
+        }
 
-        self.cache.put_system_id(agent_key.to_string(), rec.id);
+        self.cache.put_system_id(cache_key, rec.id);
         Ok(rec.id)
     }
 
+    /*
+     * pin_certificate
+     * Trust on first use for systems enrolled before pinning existed (or whose pin was cleared
+     * after re-issuing their cert): the first certificate seen with a valid key gets pinned.
+     */
+    async fn pin_certificate(&self, system_id: i32, fingerprint: &str) -> Result<(), AuthError> {
+        let updated = sqlx::query!(
+            r#"UPDATE systems SET cert_fingerprint = $1 WHERE id = $2 AND cert_fingerprint IS NULL"#,
+            fingerprint,
+            system_id
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("[hub] Failed to pin client certificate: {e}");
+            AuthError::Internal(Status::internal("Database error"))
+        })?;
+
+        // another connection pinned a (possibly different) cert first
+        if updated.rows_affected() == 0 {
+            return Err(AuthError::CertMismatch(system_id));
+        }
+        info!("[hub] Pinned client certificate for system {system_id}");
+        Ok(())
+    }
+
+    /// Logs and records a report that failed validation, returns what the agent is told.
+    async fn reject(&self, system_id: i32, report: &str, e: ValidationError) -> Status {
+        warn!("[hub] Rejected {report} report from system {system_id}: {e}");
+        validation::record_rejection(&self.pool, system_id, report, &e).await;
+        e.into()
+    }
+
     async fn handle_metrics_message(
         &self,
         system_id: i32,
-        metrics: crate::proto::monitor::MetricsRequest,
+        mut metrics: crate::proto::monitor::MetricsRequest,
+        request_id: String,
     ) -> Result<(), Status> {
-        let cpu = metrics
-            .cpu_stats
-            .ok_or(Status::invalid_argument("missing cpu_stats"))?;
-        let mem = metrics
-            .memory_stats
-            .ok_or(Status::invalid_argument("missing memory_stats"))?;
-        let net = metrics
-            .network_stats
-            .ok_or(Status::invalid_argument("missing network_stats"))?;
-        let load = metrics
-            .load_average
-            .ok_or(Status::invalid_argument("missing load_average"))?;
-
-        let components_json = serde_json::to_string(
-            &metrics
-                .components
-                .iter()
-                .map(|c| ComponentJSON {
-                    label: c.label.clone(),
-                    temperature: c.temperature,
-                })
-                .collect::<Vec<_>>(),
-        )
-        .unwrap_or("[]".to_string());
-
-        let now = chrono::Utc::now();
-        let disks = metrics
-            .disk_stats
-            .iter()
-            .map(|d| DiskEntry {
-                name: d.name.clone(),
-                total_space: d.total_space as i64,
-                used_space: d.used_space as i64,
-                read_bytes: d.read_bytes,
-                write_bytes: d.write_bytes,
-                unit: d.unit.clone(),
-                mount_point: d.mount_point.clone(),
-            })
-            .collect::<Vec<_>>();
-
-        let item = IngestItem::Metric(MetricIngestItem {
-            system_id,
-            time: now,
-            cpu_usage: cpu.usage_percent,
-            memory_used_kb: mem.used_kb as i64,
-            memory_total_kb: mem.total_kb as i64,
-            components_json,
-            net_in: net.r#in as i64,
-            net_out: net.out as i64,
-            load_one: load.one_minute,
-            load_five: load.five_minutes,
-            load_fifteen: load.fifteen_minutes,
-            disks,
-            original: metrics,
-        });
+        if let Err(e) = validation::metrics(&metrics) {
+            return Err(self.reject(system_id, "metrics", e).await);
+        }
+        let custom = std::mem::take(&mut metrics.custom_metrics);
+        let mut item = MetricIngestItem::from_request(system_id, metrics.clone())?;
+        item.request_id = Some(request_id);
+        let time = item.time;
+        self.cache.record_metrics(system_id, &metrics);
 
-        // await send for smoothing bursts
-        if let Err(e) = self.metric_tx.send(item).await {
-            log::error!("[hub] metric queue closed: {e}");
-            return Err(Status::unavailable("ingest pipeline unavailable"));
+        // acknowledged once queued, the writers persist it
+        if let Err(e) = self.ingest.push(IngestItem::Metric(item)).await {
+            log::error!("[hub] Failed to queue metrics of system {system_id}: {e}");
+            return Err(e.into());
+        }
+        if custom.is_empty() {
+            return Ok(());
+        }
+        // a broken script must not cost the regular metrics, only its own values are dropped
+        let request = CustomMetricsRequest {
+            metrics: custom.into_iter().map(Into::into).collect(),
+        };
+        match custom_metrics::validate(system_id, request) {
+            Ok(items) => {
+                for mut item in items {
+                    item.time = time;
+                    if let Err(e) = self.ingest.push(IngestItem::Custom(item)).await {
+                        log::error!(
+                            "[hub] Failed to queue custom metrics of system {system_id}: {e}"
+                        );
+                        return Err(e.into());
+                    }
+                }
+            }
+            Err(e) => warn!("[hub] Dropping custom metrics of system {system_id}: {e}"),
         }
         Ok(())
     }
@@ -160,6 +417,7 @@ impl MyMonitor {
         &self,
         system_id: i32,
         metrics: Vec<GpuMetrics>,
+        time: DateTime<Utc>,
     ) -> Result<(), Status> {
         if metrics.is_empty() {
             return Ok(());
@@ -185,7 +443,6 @@ impl MyMonitor {
         let mut qb = QueryBuilder::new(
             "INSERT INTO gpu_metrics (gpu_id, time, utilization, memory_used_mb, temperature, power) ",
         );
-        let now = Utc::now();
         let mut any = false;
         qb.push_values(
             metrics
@@ -194,7 +451,7 @@ impl MyMonitor {
             |mut b, (gpu_id, m)| {
                 any = true;
                 b.push_bind(*gpu_id)
-                    .push_bind(now)
+                    .push_bind(time)
                     .push_bind(m.utilization)
                     .push_bind(m.memory_used_mb as i64)
                     .push_bind(m.temperature)
@@ -211,6 +468,115 @@ impl MyMonitor {
         Ok(())
     }
 
+    async fn upsert_services(
+        &self,
+        system_id: i32,
+        services: Vec<SystemService>,
+    ) -> Result<(), Status> {
+        if services.is_empty() {
+            return Ok(());
+        }
+
+        // update in-memory cache first for fast reads, and drop duplicate names since a single
+        // ON CONFLICT statement can't touch the same row twice (the last one wins)
+        self.cache.record_services(system_id, &services);
+        let mut unique: HashMap<&str, &SystemService> = HashMap::with_capacity(services.len());
+        for service in &services {
+            unique.insert(&service.service_name, service);
+        }
+        let unique: Vec<&SystemService> = unique.into_values().collect();
+
+        let upsert_error = |e: sqlx::Error| {
+            error!("[hub] Service upsert error: {e}");
+            Status::internal("service upsert failed")
+        };
+        // one transaction, so a report is stored whole or not at all
+        let mut tx = self.pool.begin().await.map_err(upsert_error)?;
+        for chunk in unique.chunks(SERVICE_UPSERT_CHUNK) {
+            let mut qb = QueryBuilder::new(
+                "INSERT INTO services \
+                 (system, name, description, state, pid, cpu, memory, requires, wants, after) ",
+            );
+            qb.push_values(chunk, |mut b, s| {
+                b.push_bind(system_id)
+                    .push_bind(&s.service_name)
+                    .push_bind(&s.description)
+                    .push_bind(&s.state)
+                    .push_bind(s.pid as i32)
+                    .push_bind(&s.cpu)
+                    .push_bind(&s.memory)
+                    .push_bind(&s.requires)
+                    .push_bind(&s.wants)
+                    .push_bind(&s.after);
+            });
+            qb.push(
+                " ON CONFLICT (system, name) DO UPDATE SET \
+                  description = EXCLUDED.description, state = EXCLUDED.state, pid = EXCLUDED.pid, \
+                  cpu = EXCLUDED.cpu, memory = EXCLUDED.memory, requires = EXCLUDED.requires, \
+                  wants = EXCLUDED.wants, after = EXCLUDED.after",
+            );
+            qb.build().execute(&mut *tx).await.map_err(upsert_error)?;
+        }
+        tx.commit().await.map_err(upsert_error)
+    }
+
+    /// Units the agent reported as gone, dropped from the table and the cache.
+    async fn remove_services(&self, system_id: i32, names: Vec<String>) -> Result<(), Status> {
+        if names.is_empty() {
+            return Ok(());
+        }
+        sqlx::query("DELETE FROM services WHERE system = $1 AND name = ANY($2)")
+            .bind(system_id)
+            .bind(&names)
+            .execute(&self.pool)
+            .await
+            .map_err(|e| {
+                error!("[hub] Service removal error: {e}");
+                Status::internal("service removal failed")
+            })?;
+        self.cache.remove_services(system_id, &names);
+        Ok(())
+    }
+
+    /*
+     * sync_services
+     * A full sync lists every unit on the system, so units stored for it but not listed were
+     * uninstalled while a removal notice got lost (or before agents sent them). An empty list
+     * is taken at its word, the agent never marks a failed listing as a full sync.
+     */
+    async fn sync_services(
+        &self,
+        system_id: i32,
+        services: Vec<SystemService>,
+    ) -> Result<(), Status> {
+        let names: Vec<String> = services.iter().map(|s| s.service_name.clone()).collect();
+        self.upsert_services(system_id, services).await?;
+        self.prune_services(system_id, &names).await
+    }
+
+    /// Drops the system's units missing from `listed`, the end of a full sync.
+    async fn prune_services(&self, system_id: i32, listed: &[String]) -> Result<(), Status> {
+        let stale: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM services WHERE system = $1 AND NOT (name = ANY($2)) RETURNING name",
+        )
+        .bind(system_id)
+        .bind(listed)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|e| {
+            error!("[hub] Service sync error: {e}");
+            Status::internal("service sync failed")
+        })?;
+        if !stale.is_empty() {
+            info!(
+                "[hub] Pruned {} services no longer on system {system_id}",
+                stale.len()
+            );
+        }
+        self.cache.remove_services(system_id, &stale);
+        Ok(())
+    }
+
     async fn upsert_containers(
         &self,
         system_id: i32,
@@ -243,6 +609,7 @@ impl MyMonitor {
         &self,
         system_id: i32,
         metrics: Vec<ContainerMetrics>,
+        time: DateTime<Utc>,
     ) -> Result<(), Status> {
         if metrics.is_empty() {
             return Ok(());
@@ -252,14 +619,14 @@ impl MyMonitor {
             let item = IngestItem::Container(ContainerIngestItem {
                 system_id,
                 docker_id: m.docker_id.clone(),
-                time: Utc::now(),
+                time,
                 cpu_usage: m.cpu_usage,
                 memory_usage: m.memory_usage,
                 original: m,
             });
-            if let Err(e) = self.metric_tx.send(item).await {
-                log::error!("[hub] container metric queue closed: {e}");
-                return Err(Status::unavailable("ingest pipeline unavailable"));
+            if let Err(e) = self.ingest.push(item).await {
+                log::error!("[hub] Failed to queue container metrics of system {system_id}: {e}");
+                return Err(e.into());
             }
         }
 
@@ -313,14 +680,20 @@ impl MyMonitor {
 }
 
 #[tonic::async_trait]
-impl SystemMonitor for MyMonitor {
+impl MetricsIngest for MyMonitor {
+    type MetricsChannelStream = ReceiverStream<Result<MetricsAck, Status>>;
+
     async fn report_metrics(
         &self,
         request: Request<MetricsRequest>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let request_id = logging::request_id(request.metadata());
         let metrics = request.into_inner();
-        self.handle_metrics_message(system_id, metrics).await?;
+        self.handle_metrics_message(system_id, metrics, request_id)
+            .await?;
         // record lightweight log in cache
         let cache = self.cache.clone();
         tokio::spawn(async move {
@@ -336,14 +709,29 @@ impl SystemMonitor for MyMonitor {
         &self,
         request: Request<Streaming<MetricsRequest>>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        // messages of one stream are told apart by their position
+        let request_id = logging::request_id(request.metadata());
         let mut inbound = request.into_inner();
         let mut count: u64 = 0;
 
-        while let Some(msg) = inbound.next().await {
+        loop {
+            let msg = tokio::select! {
+                msg = inbound.next() => msg,
+                _ = self.shutdown.wait() => {
+                    info!(
+                        "[hub] stream_metrics ended by shutdown (system {system_id}, messages={count})"
+                    );
+                    return Err(Status::unavailable("hub is shutting down"));
+                }
+            };
+            let Some(msg) = msg else { break };
             match msg {
                 Ok(m) => {
-                    if let Err(e) = self.handle_metrics_message(system_id, m).await {
+                    let message_id = format!("{request_id}.{count}");
+                    if let Err(e) = self.handle_metrics_message(system_id, m, message_id).await {
                         return Err(e);
                     }
                     count += 1;
@@ -367,40 +755,127 @@ impl SystemMonitor for MyMonitor {
         }))
     }
 
-    async fn register_gp_us(
+    /*
+     * metrics_channel
+     * Like stream_metrics, but every sample is answered with a MetricsAck once it is queued or
+     * rejected, and a rejected sample doesn't end the stream. A full ingest queue asks the agent
+     * to hold samples back for METRICS_RETRY_AFTER_SECS. The stream only ends when the agent
+     * closes it, the ingest pipeline is gone or the hub shuts down.
+     */
+    async fn metrics_channel(
         &self,
-        request: Request<GpuRequest>,
+        request: Request<Streaming<MetricsRequest>>,
+    ) -> Result<Response<Self::MetricsChannelStream>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        // messages of one stream are told apart by their position, as on stream_metrics
+        let request_id = logging::request_id(request.metadata());
+        let mut inbound = request.into_inner();
+        let monitor = self.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(METRICS_ACK_QUEUE);
+
+        let channel = async move {
+            let mut sequence: u64 = 0;
+            loop {
+                let msg = tokio::select! {
+                    msg = inbound.next() => msg,
+                    _ = tx.closed() => break,
+                    _ = monitor.shutdown.wait() => {
+                        let _ = tx.send(Err(Status::unavailable("hub is shutting down"))).await;
+                        break;
+                    }
+                };
+                let metrics = match msg {
+                    Some(Ok(metrics)) => metrics,
+                    Some(Err(status)) => {
+                        warn!("[hub] metrics_channel error (system {system_id}): {status}");
+                        break;
+                    }
+                    None => break,
+                };
+                let message_id = format!("{request_id}.{sequence}");
+                let result = match monitor
+                    .handle_metrics_message(system_id, metrics, message_id)
+                    .await
+                {
+                    // the ingest pipeline is gone, the agent reconnects to a working hub
+                    Err(status) if status.code() == Code::Unavailable => {
+                        let _ = tx.send(Err(status)).await;
+                        break;
+                    }
+                    result => result,
+                };
+                if tx.send(Ok(metrics_ack(sequence, result))).await.is_err() {
+                    break;
+                }
+                sequence += 1;
+            }
+            info!("[hub] metrics_channel closed (system {system_id}, messages={sequence})");
+        };
+        tokio::spawn(channel.instrument(Span::current()));
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn report_gpu_metrics(
+        &self,
+        request: Request<GpuMetricsRequest>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
         let request = request.into_inner();
-        self.upsert_gpus(system_id.into(), request.gpus).await?;
-        info!("[hub] GPU list updated successfully");
+        if let Err(e) = validation::gpu_metrics(&request.gpu_metrics) {
+            return Err(self.reject(system_id, "gpu_metrics", e).await);
+        }
+        let time = sample_time(request.collected_at_ms, Utc::now())?;
+        self.cache
+            .record_gpu_metrics(system_id, &request.gpu_metrics);
+        self.insert_gpu_metrics(system_id.into(), request.gpu_metrics, time)
+            .await?;
         Ok(Response::new(ProtoResponse {
             status: "200".to_string(),
-            message: "GPUs reported successfully".to_string(),
+            message: "GPU metrics reported successfully".to_string(),
         }))
     }
 
-    async fn report_gpu_metrics(
+    async fn report_container_metrics(
         &self,
-        request: Request<GpuMetricsRequest>,
+        request: Request<ContainerMetricsRequest>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
-        let request = request.into_inner();
-        self.insert_gpu_metrics(system_id.into(), request.gpu_metrics)
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let body = request.into_inner();
+        if let Err(e) = validation::container_metrics(&body.container_metrics) {
+            return Err(self.reject(system_id, "container_metrics", e).await);
+        }
+        let time = sample_time(body.collected_at_ms, Utc::now())?;
+        self.insert_container_metrics(system_id.into(), body.container_metrics, time)
             .await?;
         Ok(Response::new(ProtoResponse {
             status: "200".to_string(),
-            message: "GPU metrics reported successfully".to_string(),
+            message: "Container metrics successfully".to_string(),
         }))
     }
+}
 
+#[tonic::async_trait]
+impl Inventory for MyMonitor {
     async fn get_system_info(
         &self,
         request: Request<SystemInfoRequest>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
         let system_request = request.into_inner();
+        if let Err(e) = validation::system_info(&system_request) {
+            return Err(self.reject(system_id, "system_info", e).await);
+        }
+        // levels the tags leave out keep what was set through the location API
+        let location = Location::from_tags(&system_request.tags);
 
         sqlx::query!(
             r#"
@@ -410,8 +885,15 @@ impl SystemMonitor for MyMonitor {
                 uptime = $3,
                 kernal = $4,
                 cpu = $5,
-                cpu_count = $6
-            WHERE id = $7
+                cpu_count = $6,
+                agent_version = $7,
+                arch = $8,
+                virtualization = $9,
+                tags = $10,
+                site = COALESCE($11, site),
+                datacenter = COALESCE($12, datacenter),
+                rack = COALESCE($13, rack)
+            WHERE id = $14
             "#,
             system_request.hostname,
             system_request.os,
@@ -419,6 +901,13 @@ impl SystemMonitor for MyMonitor {
             system_request.kernel_version,
             system_request.cpu_model,
             system_request.cpu_count as i32,
+            system_request.agent_version,
+            system_request.arch,
+            system_request.virtualization,
+            serde_json::to_value(&system_request.tags).unwrap_or_default(),
+            location.site,
+            location.datacenter,
+            location.rack,
             system_id as i32
         )
         .execute(&self.pool)
@@ -436,82 +925,112 @@ impl SystemMonitor for MyMonitor {
         }))
     }
 
+    async fn register_gp_us(
+        &self,
+        request: Request<GpuRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let request = request.into_inner();
+        if let Err(e) = validation::gpus(&request.gpus) {
+            return Err(self.reject(system_id, "gpus", e).await);
+        }
+        self.upsert_gpus(system_id.into(), request.gpus).await?;
+        info!("[hub] GPU list updated successfully");
+        Ok(Response::new(ProtoResponse {
+            status: "200".to_string(),
+            message: "GPUs reported successfully".to_string(),
+        }))
+    }
+
     async fn report_systemctl(
         &self,
         request: Request<SystemctlRequest>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
         let request = request.into_inner();
-        let services = request.services;
-        for service in services {
-            // update in-memory cache first for fast reads
-            self.cache.upsert_service(service.clone());
+        if let Err(e) = validation::services(&request.services) {
+            return Err(self.reject(system_id, "services", e).await);
+        }
+        if let Err(e) = validation::removed_services(&request.removed) {
+            return Err(self.reject(system_id, "services", e).await);
+        }
+        if request.full_sync {
+            self.sync_services(system_id, request.services).await?;
+        } else {
+            self.upsert_services(system_id, request.services).await?;
+            self.remove_services(system_id, request.removed).await?;
+        }
 
-            let existing = sqlx::query!(
-                r#"SELECT id FROM services WHERE system = $1 AND name = $2"#,
-                system_id,
-                service.service_name
-            )
-            .fetch_optional(&self.pool)
-            .await
-            .map_err(|e| {
-                error!("[hub] Failed to query existing service: {e:?}");
-                Status::internal("Database error")
+        info!("[hub] Systemctl services updated successfully");
+        // log cache size
+        let svc_count = self.cache.service_count();
+        info!("[hub] Cache now tracking {svc_count} services");
+        Ok(Response::new(ProtoResponse {
+            status: "200".to_string(),
+            message: "Services reported successfully".to_string(),
+        }))
+    }
+
+    /*
+     * stream_systemctl
+     * A systemctl report in chunks, sent by agents with more units than fit one chunk. Each
+     * chunk is validated and written on its own, so neither side holds the whole report; a full
+     * sync (decided by the first chunk) only keeps the names until the stream ends and then
+     * prunes the units not listed. A stream that breaks off keeps the chunks written so far and
+     * prunes nothing, the next full sync catches up.
+     */
+    async fn stream_systemctl(
+        &self,
+        request: Request<Streaming<SystemctlRequest>>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let mut inbound = request.into_inner();
+        let mut full_sync = None;
+        let mut listed: Vec<String> = Vec::new();
+        let mut total = 0;
+        let mut chunks: u64 = 0;
+
+        loop {
+            let chunk = tokio::select! {
+                chunk = inbound.next() => chunk,
+                _ = self.shutdown.wait() => {
+                    return Err(Status::unavailable("hub is shutting down"));
+                }
+            };
+            let Some(chunk) = chunk else { break };
+            let chunk = chunk.map_err(|status| {
+                log::warn!("[hub] stream_systemctl error (system {system_id}): {status}");
+                Status::aborted("stream receive error")
             })?;
+            total += chunk.services.len();
+            let valid = validation::services(&chunk.services)
+                .and_then(|()| validation::removed_services(&chunk.removed))
+                .and_then(|()| validation::streamed_services(total));
+            if let Err(e) = valid {
+                return Err(self.reject(system_id, "services", e).await);
+            }
 
-            if let Some(existing_service) = existing {
-                // update existing service
-                sqlx::query!(
-                    r#"
-                    UPDATE services
-                    SET description = $1,
-                        state = $2,
-                        pid = $3,
-                        cpu = $4,
-                        memory = $5
-                    WHERE id = $6
-                    "#,
-                    service.description,
-                    service.state,
-                    service.pid as i32,
-                    service.cpu,
-                    service.memory,
-                    existing_service.id
-                )
-                .execute(&self.pool)
-                .await
-                .map_err(|e| {
-                    error!("[hub] Failed to update service: {e:?}");
-                    Status::internal("Database error")
-                })?;
-                continue;
-            } else {
-                sqlx::query!(
-                    r#"
-                    INSERT INTO services (system, name, description, state, pid, cpu, memory)
-                    VALUES ($1, $2, $3, $4, $5, $6, $7)
-                    "#,
-                    system_id,
-                    service.service_name,
-                    service.description,
-                    service.state,
-                    service.pid as i32,
-                    service.cpu,
-                    service.memory
-                )
-                .execute(&self.pool)
-                .await
-                .map_err(|e| {
-                    error!("[hub] Failed to insert service: {e:?}");
-                    Status::internal("Database error")
-                })?;
+            let full = *full_sync.get_or_insert(chunk.full_sync);
+            if full {
+                listed.extend(chunk.services.iter().map(|s| s.service_name.clone()));
+            }
+            self.upsert_services(system_id, chunk.services).await?;
+            if !full {
+                self.remove_services(system_id, chunk.removed).await?;
             }
+            chunks += 1;
+        }
+        if full_sync == Some(true) {
+            self.prune_services(system_id, &listed).await?;
         }
 
-        info!("[hub] Systemctl services updated successfully");
-        // log cache size
-        let svc_count = self.cache.list_services().len();
-        info!("[hub] Cache now tracking {svc_count} services");
+        info!("[hub] Systemctl services updated from {chunks} chunks ({total} services)");
         Ok(Response::new(ProtoResponse {
             status: "200".to_string(),
             message: "Services reported successfully".to_string(),
@@ -522,8 +1041,13 @@ impl SystemMonitor for MyMonitor {
         &self,
         request: Request<ContainerRequest>,
     ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
         let body = request.into_inner();
+        if let Err(e) = validation::containers(&body.containers) {
+            return Err(self.reject(system_id, "containers", e).await);
+        }
         self.upsert_containers(system_id.into(), body.containers)
             .await?;
         Ok(Response::new(ProtoResponse {
@@ -531,18 +1055,452 @@ impl SystemMonitor for MyMonitor {
             message: "Containers reported successfully".to_string(),
         }))
     }
+}
 
-    async fn report_container_metrics(
+#[tonic::async_trait]
+impl Control for MyMonitor {
+    type WatchConfigStream = ReceiverStream<Result<AgentConfig, Status>>;
+    type OpenSessionsStream = FrameStream;
+
+    /*
+     * watch_config
+     * Sends the agent its merged config right away unless it already runs that version, then
+     * polls for changes until the agent disconnects. A decommissioned system gets one last
+     * config with `decommission` set and the stream ends.
+     */
+    async fn watch_config(
         &self,
-        request: Request<ContainerMetricsRequest>,
-    ) -> Result<Response<ProtoResponse>, Status> {
-        let system_id = self.get_system_id_from_md(request.metadata()).await?;
+        request: Request<WatchConfigRequest>,
+    ) -> Result<Response<Self::WatchConfigStream>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let mut version = request.into_inner().version;
+        let pool = self.pool.clone();
+        let shutdown = self.shutdown.clone();
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+
+        let watch = async move {
+            let mut poll = tokio::time::interval(agent_config::CONFIG_POLL_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = poll.tick() => {}
+                    _ = tx.closed() => break,
+                    _ = shutdown.wait() => break,
+                }
+                let mut config = match agent_config::load(&pool, system_id).await {
+                    Ok(config) => config,
+                    Err(e) => {
+                        error!("[hub] Failed to load agent config (system {system_id}): {e}");
+                        continue;
+                    }
+                };
+                match decommission::is_decommissioned(&pool, system_id).await {
+                    Ok(true) => {
+                        info!("[hub] Telling decommissioned system {system_id} to uninstall");
+                        config.decommission = true;
+                        let _ = tx.send(Ok(config)).await;
+                        break;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        error!("[hub] Failed to check decommissioning (system {system_id}): {e}");
+                        continue;
+                    }
+                }
+                if config.version == version {
+                    continue;
+                }
+                version = config.version;
+                info!("[hub] Pushing config version {version} to system {system_id}");
+                if tx.send(Ok(config)).await.is_err() {
+                    break;
+                }
+            }
+            info!("[hub] watch_config closed (system {system_id})");
+        };
+        // keeps the rpc span so pushes are logged with the system they went to
+        tokio::spawn(watch.instrument(Span::current()));
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    /*
+     * get_system_status
+     * Composite view of one system for dashboards. Agents may only read their own system;
+     * system_id 0 stands for the caller.
+     */
+    async fn get_system_status(
+        &self,
+        request: Request<SystemStatusRequest>,
+    ) -> Result<Response<SystemStatusResponse>, Status> {
+        let caller = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let system_id = match request.into_inner().system_id {
+            0 => caller,
+            id if id == caller => id,
+            _ => {
+                return Err(Status::permission_denied(
+                    "Key does not belong to this system",
+                ))
+            }
+        };
+
+        let snapshot = self.cache.load_system(system_id).await.unwrap_or_default();
+        let last_seen = match snapshot.last_seen {
+            Some(seen) => Some(seen),
+            None => status::load_last_seen(&self.read_pool, system_id)
+                .await
+                .map_err(|e| {
+                    error!("[hub] Failed to load last_seen (system {system_id}): {e}");
+                    Status::internal("status lookup failed")
+                })?,
+        };
+        let active_alerts = status::load_active_alerts(&self.read_pool, system_id)
+            .await
+            .map_err(|e| {
+                error!("[hub] Failed to load active alerts (system {system_id}): {e}");
+                Status::internal("status lookup failed")
+            })?;
+
+        Ok(Response::new(SystemStatusResponse {
+            system_id,
+            online: status::is_online(last_seen, Utc::now()),
+            last_seen_ms: last_seen.map(|t| t.timestamp_millis()),
+            latest_metrics: snapshot.metrics,
+            active_alerts,
+            services: Some(status::summarize_services(&snapshot.services)),
+            gpu_metrics: snapshot.gpu_metrics,
+        }))
+    }
+
+    /*
+     * get_release
+     * The newest build on the agent's channel for its OS and architecture, with the signature
+     * made when it was published, so the agent can check it against its pinned release key
+     * before downloading anything. The hub can't sign manifests itself.
+     */
+    async fn get_release(
+        &self,
+        request: Request<ReleaseRequest>,
+    ) -> Result<Response<ReleaseManifest>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
         let body = request.into_inner();
-        self.insert_container_metrics(system_id.into(), body.container_metrics)
+        if !releases::is_channel(&body.channel) {
+            return Err(Status::invalid_argument(format!(
+                "Unknown release channel {:?}",
+                body.channel
+            )));
+        }
+
+        // agents from before the os field only ran on Linux
+        let os = if body.os.is_empty() {
+            "linux"
+        } else {
+            &body.os
+        };
+        let signed = releases::load_latest_manifest(&self.read_pool, &body.channel, os, &body.arch)
+            .await
+            .map_err(|e| {
+                error!("[hub] Failed to load agent release (system {system_id}): {e}");
+                Status::internal("release lookup failed")
+            })?
+            .ok_or_else(|| {
+                Status::not_found(format!(
+                    "No {} release for {os} {}",
+                    body.channel, body.arch
+                ))
+            })?;
+        let manifest = releases::relay_manifest(signed).ok_or_else(|| {
+            error!(
+                "[hub] Signature of the {} release for {os} {} is not base64",
+                body.channel, body.arch
+            );
+            Status::internal("release signature unreadable")
+        })?;
+        Ok(Response::new(manifest))
+    }
+
+    /*
+     * report_health
+     * A collector the agent's supervisor restarted after a panic or timeout, logged and kept
+     * in agent_health_events.
+     */
+    async fn report_health(
+        &self,
+        request: Request<AgentHealthEvent>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
             .await?;
+        let event = request.into_inner();
+        if let Err(e) = validation::health_event(&event) {
+            return Err(self.reject(system_id, "health", e).await);
+        }
+        warn!(
+            "[hub] System {system_id} restarted its {} collector after a {} ({} restarts): {}",
+            event.collector,
+            event.kind,
+            event.restarts,
+            agent_health::truncate_detail(&event.detail)
+        );
+        agent_health::record(&self.pool, system_id, &event)
+            .await
+            .map_err(|e| {
+                error!("[hub] Failed to record health event (system {system_id}): {e}");
+                Status::internal("Database error")
+            })?;
         Ok(Response::new(ProtoResponse {
             status: "200".to_string(),
-            message: "Container metrics successfully".to_string(),
+            message: "Health event recorded".to_string(),
         }))
     }
+
+    /*
+     * report_agent_event
+     * A panic the agent persisted before it went down, sent once it runs again. The time is
+     * when it happened, not when it arrived.
+     */
+    async fn report_agent_event(
+        &self,
+        request: Request<AgentEvent>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let event = request.into_inner();
+        if let Err(e) = validation::agent_event(&event) {
+            return Err(self.reject(system_id, "agent_event", e).await);
+        }
+        warn!(
+            "[hub] System {system_id} reported a {} of agent {} at {}: {}",
+            event.kind,
+            event.version,
+            event.location,
+            agent_events::truncate(&event.message, agent_events::MAX_MESSAGE_CHARS)
+        );
+        agent_events::record(&self.pool, system_id, &event)
+            .await
+            .map_err(|e| {
+                error!("[hub] Failed to record agent event (system {system_id}): {e}");
+                Status::internal("Database error")
+            })?;
+        Ok(Response::new(ProtoResponse {
+            status: "200".to_string(),
+            message: "Agent event recorded".to_string(),
+        }))
+    }
+
+    /*
+     * report_command
+     * An action the agent took on its host over the websocket, see services::command_audit.
+     */
+    async fn report_command(
+        &self,
+        request: Request<CommandAudit>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let entry = request.into_inner();
+        if let Err(e) = validation::command_audit(&entry) {
+            return Err(self.reject(system_id, "command_audit", e).await);
+        }
+        info!(
+            "[hub] System {system_id} ran {} {} for {}",
+            entry.action, entry.target, entry.actor
+        );
+        command_audit::record(&self.pool, system_id, &entry)
+            .await
+            .map_err(|e| {
+                error!("[hub] Failed to record command audit (system {system_id}): {e}");
+                Status::internal("Database error")
+            })?;
+        Ok(Response::new(ProtoResponse {
+            status: "200".to_string(),
+            message: "Command recorded".to_string(),
+        }))
+    }
+
+    /*
+     * set_maintenance
+     * Starts, extends or ends the maintenance of the hub or, with `scope`, of one location
+     * group, see services::maintenance. A request with neither a duration nor `end` returns
+     * the current status.
+     */
+    async fn set_maintenance(
+        &self,
+        request: Request<MaintenanceRequest>,
+    ) -> Result<Response<MaintenanceStatus>, Status> {
+        self.require_admin(&request)?;
+        let request = request.into_inner();
+        let internal = |e: sqlx::Error| {
+            error!("[hub] Failed to update maintenance: {e}");
+            Status::internal("Database error")
+        };
+        let scope = match request.scope.trim() {
+            "" => None,
+            scope => Some(
+                scope
+                    .parse::<Group>()
+                    .map_err(|e| Status::invalid_argument(e.to_string()))?,
+            ),
+        };
+        let current = if request.end {
+            maintenance::end(&self.pool, scope.as_ref())
+                .await
+                .map_err(internal)?;
+            None
+        } else if request.duration_minutes > 0 {
+            let duration = std::time::Duration::from_secs(request.duration_minutes as u64 * 60);
+            match maintenance::start(&self.pool, duration, &request.reason, scope.as_ref()).await {
+                Ok(maintenance) => Some(maintenance),
+                Err(MaintenanceError::Db(e)) => return Err(internal(e)),
+                Err(e) => return Err(Status::invalid_argument(e.to_string())),
+            }
+        } else {
+            maintenance::current(&self.pool, scope.as_ref())
+                .await
+                .map_err(internal)?
+        };
+        Ok(Response::new(maintenance_status(current)))
+    }
+
+    /*
+     * open_sessions
+     * Kept open by agents so dashboards can reach their websocket through this hub, see
+     * services::sessions. A second stream from the same system replaces the first.
+     */
+    async fn open_sessions(
+        &self,
+        request: Request<Streaming<SessionFrame>>,
+    ) -> Result<Response<Self::OpenSessionsStream>, Status> {
+        let system_id = self
+            .get_system_id(self.agent_credentials(&request)?)
+            .await?;
+        let inbound = request.into_inner();
+        Ok(Response::new(self.sessions.attach(
+            system_id,
+            inbound,
+            self.shutdown.clone(),
+        )))
+    }
+}
+
+/// The ack for the sample at `sequence` of a MetricsChannel, asking for a pause when the ingest
+/// queue was full.
+pub fn metrics_ack(sequence: u64, result: Result<(), Status>) -> MetricsAck {
+    match result {
+        Ok(()) => MetricsAck {
+            sequence,
+            code: Code::Ok as i32,
+            message: "Metrics reported successfully".to_string(),
+            retry_after_secs: 0,
+        },
+        Err(status) => MetricsAck {
+            sequence,
+            code: status.code() as i32,
+            message: status.message().to_string(),
+            retry_after_secs: match status.code() {
+                Code::ResourceExhausted => METRICS_RETRY_AFTER_SECS,
+                _ => 0,
+            },
+        },
+    }
+}
+
+fn maintenance_status(maintenance: Option<Maintenance>) -> MaintenanceStatus {
+    match maintenance {
+        Some(m) => MaintenanceStatus {
+            active: true,
+            started: m.started.timestamp(),
+            until: m.until.timestamp(),
+            reason: m.reason,
+            scope: m.scope,
+        },
+        None => MaintenanceStatus::default(),
+    }
+}
+
+/*
+ * SystemMonitor
+ * The service agents used before the proto was split, every call is answered by the
+ * MetricsIngest, Inventory or Control implementation.
+ */
+#[tonic::async_trait]
+impl SystemMonitor for MyMonitor {
+    type WatchConfigStream = <Self as Control>::WatchConfigStream;
+
+    async fn get_system_info(
+        &self,
+        request: Request<SystemInfoRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        Inventory::get_system_info(self, request).await
+    }
+
+    async fn report_metrics(
+        &self,
+        request: Request<MetricsRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        MetricsIngest::report_metrics(self, request).await
+    }
+
+    async fn stream_metrics(
+        &self,
+        request: Request<Streaming<MetricsRequest>>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        MetricsIngest::stream_metrics(self, request).await
+    }
+
+    async fn report_systemctl(
+        &self,
+        request: Request<SystemctlRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        Inventory::report_systemctl(self, request).await
+    }
+
+    async fn register_gp_us(
+        &self,
+        request: Request<GpuRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        Inventory::register_gp_us(self, request).await
+    }
+
+    async fn report_gpu_metrics(
+        &self,
+        request: Request<GpuMetricsRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        MetricsIngest::report_gpu_metrics(self, request).await
+    }
+
+    async fn register_containers(
+        &self,
+        request: Request<ContainerRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        Inventory::register_containers(self, request).await
+    }
+
+    async fn report_container_metrics(
+        &self,
+        request: Request<ContainerMetricsRequest>,
+    ) -> Result<Response<ProtoResponse>, Status> {
+        MetricsIngest::report_container_metrics(self, request).await
+    }
+
+    async fn watch_config(
+        &self,
+        request: Request<WatchConfigRequest>,
+    ) -> Result<Response<Self::WatchConfigStream>, Status> {
+        Control::watch_config(self, request).await
+    }
+
+    async fn get_system_status(
+        &self,
+        request: Request<SystemStatusRequest>,
+    ) -> Result<Response<SystemStatusResponse>, Status> {
+        Control::get_system_status(self, request).await
+    }
 }
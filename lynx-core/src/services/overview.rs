@@ -0,0 +1,97 @@
+use crate::services::location::Location;
+use crate::services::status::is_online;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Row};
+use std::collections::BTreeMap;
+
+/*
+ * Fleet overview
+ * One row per system for the portal's overview page, from a single query instead of a status
+ * call per system: the systems row already holds the latest CPU and memory figures (kept up to
+ * date by the metrics triggers), the root disk is its latest sample and active alerts are
+ * counted like GetSystemStatus lists them, rules fired within the last 30 minutes.
+ */
+
+const GET_OVERVIEW: &str = "SELECT s.id, s.hostname, s.label, s.last_seen, s.cpu_usage, \
+     s.memory_used_bytes, s.memory_total_bytes, s.tags, s.site, s.datacenter, s.rack, s.clock_skew_ms, d.used_bytes AS disk_used, d.space_bytes AS disk_total, \
+     COALESCE(a.alerts, 0) AS active_alerts \
+     FROM systems s \
+     LEFT JOIN LATERAL ( \
+         SELECT used_bytes, space_bytes FROM disks \
+         WHERE system = s.id AND mount_point = '/' \
+         ORDER BY time DESC LIMIT 1 \
+     ) d ON true \
+     LEFT JOIN ( \
+         SELECT system, count(DISTINCT COALESCE(alert::text, rule)) AS alerts FROM alert_history \
+         WHERE date >= NOW() - INTERVAL '30 minutes' GROUP BY system \
+     ) a ON a.system = s.id \
+     WHERE s.active = true AND s.decommissioned IS NULL \
+     ORDER BY s.label, s.id";
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SystemOverview {
+    pub id: i32,
+    pub hostname: Option<String>,
+    pub label: String,
+    pub online: bool,
+    pub last_seen: Option<DateTime<Utc>>,
+    /// Percent, None until the system reported
+    pub cpu_usage: Option<f64>,
+    pub memory_usage: Option<f64>,
+    /// Of the disk mounted at `/`
+    pub disk_usage: Option<f64>,
+    /// Rules that fired for the system within the last 30 minutes
+    pub active_alerts: i64,
+    pub tags: BTreeMap<String, String>,
+    /// Agent clock minus hub clock at the last report, see services::clock_skew
+    pub clock_skew_ms: Option<i64>,
+    #[serde(flatten)]
+    pub location: Location,
+}
+
+/// `used` in percent of `total`, None without both or for an empty total.
+pub fn percent(used: Option<f64>, total: Option<f64>) -> Option<f64> {
+    match (used, total) {
+        (Some(used), Some(total)) if total > 0.0 => Some((used / total * 100.0).clamp(0.0, 100.0)),
+        _ => None,
+    }
+}
+
+/// Every active system, by label.
+pub async fn list(pool: &PgPool) -> Result<Vec<SystemOverview>, sqlx::Error> {
+    let rows = sqlx::query(GET_OVERVIEW).fetch_all(pool).await?;
+    let now = Utc::now();
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let last_seen: Option<DateTime<Utc>> = row.get("last_seen");
+            let memory_used: Option<i64> = row.get("memory_used_bytes");
+            let memory_total: Option<i64> = row.get("memory_total_bytes");
+            let disk_used: Option<i64> = row.get("disk_used");
+            let disk_total: Option<i64> = row.get("disk_total");
+            let tags: serde_json::Value = row.get("tags");
+            SystemOverview {
+                id: row.get("id"),
+                hostname: row.get("hostname"),
+                label: row.get("label"),
+                online: is_online(last_seen, now),
+                last_seen,
+                cpu_usage: row.get("cpu_usage"),
+                memory_usage: percent(
+                    memory_used.map(|v| v as f64),
+                    memory_total.map(|v| v as f64),
+                ),
+                disk_usage: percent(disk_used.map(|v| v as f64), disk_total.map(|v| v as f64)),
+                active_alerts: row.get("active_alerts"),
+                tags: serde_json::from_value(tags).unwrap_or_default(),
+                clock_skew_ms: row.get("clock_skew_ms"),
+                location: Location {
+                    site: row.get("site"),
+                    datacenter: row.get("datacenter"),
+                    rack: row.get("rack"),
+                },
+            }
+        })
+        .collect())
+}
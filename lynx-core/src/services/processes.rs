@@ -0,0 +1,97 @@
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+
+/*
+ * Process history
+ * Agents send their busiest processes, by CPU and by memory, with every metrics report and the
+ * hub keeps them in process_samples (7 days by default, see retention). `at` answers "what was
+ * running at 03:12" with the last report at or before that time, as long as it is at most
+ * MAX_GAP_MINUTES older: a system that was down then has no answer rather than a stale one.
+ */
+
+pub const MAX_GAP_MINUTES: i64 = 10;
+
+const GET_PROCESSES: &str = "SELECT time, pid, name, cpu_usage, memory_kb FROM process_samples \
+     WHERE system_id = $1 AND time > $2 AND time = ( \
+         SELECT max(time) FROM process_samples \
+         WHERE system_id = $1 AND time > $2 AND time <= $3 \
+     )";
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessSort {
+    #[default]
+    Cpu,
+    Memory,
+}
+
+/// Query string of `GET /systems/{id}/processes`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ProcessQuery {
+    /// RFC 3339, now when unset
+    pub at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub sort: ProcessSort,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProcessSample {
+    pub pid: i32,
+    pub name: String,
+    /// Percent of one core
+    pub cpu_usage: f32,
+    pub memory_kb: i64,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ProcessSnapshot {
+    pub at: DateTime<Utc>,
+    /// When the processes were reported, None without a report in the MAX_GAP_MINUTES before `at`
+    pub time: Option<DateTime<Utc>>,
+    pub processes: Vec<ProcessSample>,
+}
+
+/// Busiest first, ties broken by the other figure and then by pid.
+pub fn sort(processes: &mut [ProcessSample], by: ProcessSort) {
+    processes.sort_by(|a, b| {
+        let cpu = b.cpu_usage.total_cmp(&a.cpu_usage);
+        let memory = b.memory_kb.cmp(&a.memory_kb);
+        match by {
+            ProcessSort::Cpu => cpu.then(memory),
+            ProcessSort::Memory => memory.then(cpu),
+        }
+        .then(a.pid.cmp(&b.pid))
+    });
+}
+
+/// The processes a system reported last at or before `query.at`.
+pub async fn at(
+    pool: &PgPool,
+    system_id: i32,
+    query: &ProcessQuery,
+) -> Result<ProcessSnapshot, sqlx::Error> {
+    let at = query.at.unwrap_or_else(Utc::now);
+    let rows = sqlx::query(GET_PROCESSES)
+        .bind(system_id)
+        .bind(at - Duration::minutes(MAX_GAP_MINUTES))
+        .bind(at)
+        .fetch_all(pool)
+        .await?;
+    let time = rows.first().map(|r| r.get("time"));
+    let mut processes: Vec<ProcessSample> = rows
+        .iter()
+        .map(|r| ProcessSample {
+            pid: r.get("pid"),
+            name: r.get("name"),
+            cpu_usage: r.get("cpu_usage"),
+            memory_kb: r.get("memory_kb"),
+        })
+        .collect();
+    sort(&mut processes, query.sort);
+    Ok(ProcessSnapshot {
+        at,
+        time,
+        processes,
+    })
+}
@@ -0,0 +1,162 @@
+use crate::config::Secrets;
+use crate::counters::CounterRates;
+use crate::leader::Leadership;
+use crate::prometheus;
+use crate::services::ingest::{IngestError, IngestItem, IngestQueue, MetricIngestItem};
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+const POLL_TICK: Duration = Duration::from_secs(5);
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*
+ * Prometheus poller
+ * Scrapes the exporters listed in prometheus_targets (node_exporter and compatible) for hosts
+ * where the agent can't be installed, and feeds them into the ingest pipeline like the SNMP
+ * poller does. `bearer_token` is optional and may be a secret reference.
+ */
+
+struct ScrapeTarget {
+    id: i32,
+    system_id: i32,
+    url: String,
+    bearer_token: Option<String>,
+    interval_secs: i32,
+}
+
+#[derive(Default)]
+struct TargetState {
+    last_scrape: Option<Instant>,
+    counters: CounterRates,
+}
+
+async fn load_targets(pool: &PgPool) -> Result<Vec<ScrapeTarget>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT t.id, t.system_id, t.url, t.bearer_token, t.interval_secs
+           FROM prometheus_targets t JOIN systems s ON s.id = t.system_id
+           WHERE t.active = true"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| ScrapeTarget {
+            id: r.id,
+            system_id: r.system_id,
+            url: r.url,
+            bearer_token: r.bearer_token,
+            interval_secs: r.interval_secs,
+        })
+        .collect())
+}
+
+async fn scrape(
+    client: &reqwest::Client,
+    target: &ScrapeTarget,
+    secrets: &Secrets,
+) -> Result<Vec<prometheus::Sample>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut request = client
+        .get(&target.url)
+        .header("Accept", "text/plain;version=0.0.4");
+    if let Some(token) = &target.bearer_token {
+        request = request.bearer_auth(secrets.resolve(token).await?);
+    }
+    let body = request.send().await?.error_for_status()?.text().await?;
+    Ok(prometheus::parse(&body)?)
+}
+
+pub async fn run_prometheus_poller(
+    pool: PgPool,
+    secrets: Secrets,
+    ingest: IngestQueue,
+    leadership: Leadership,
+) {
+    let client = match reqwest::Client::builder().timeout(SCRAPE_TIMEOUT).build() {
+        Ok(client) => client,
+        Err(e) => {
+            error!("[scrape] Failed to build HTTP client: {e}");
+            return;
+        }
+    };
+    let mut state: HashMap<i32, TargetState> = HashMap::new();
+    let mut tick = tokio::time::interval(POLL_TICK);
+    let mut announced = false;
+
+    loop {
+        tick.tick().await;
+        // another hub scrapes, two would store every sample twice
+        if !leadership.is_leader() {
+            continue;
+        }
+        let targets = match load_targets(&pool).await {
+            Ok(targets) => targets,
+            Err(e) => {
+                error!("[scrape] Failed to load targets: {e}");
+                continue;
+            }
+        };
+        if !targets.is_empty() && !announced {
+            info!("[scrape] Scraping {} exporter(s)", targets.len());
+            announced = true;
+        }
+        state.retain(|id, _| targets.iter().any(|t| t.id == *id));
+
+        let now = Instant::now();
+        let mut scrapes = JoinSet::new();
+        for target in targets {
+            let entry = state.entry(target.id).or_default();
+            let interval = Duration::from_secs(target.interval_secs.max(1) as u64);
+            if entry
+                .last_scrape
+                .is_some_and(|t| now.duration_since(t) < interval)
+            {
+                continue;
+            }
+            entry.last_scrape = Some(now);
+            let (client, secrets) = (client.clone(), secrets.clone());
+            scrapes.spawn(async move {
+                let result = scrape(&client, &target, &secrets).await;
+                (target, result, std::time::Instant::now())
+            });
+        }
+
+        while let Some(joined) = scrapes.join_next().await {
+            let Ok((target, result, scraped_at)) = joined else {
+                continue;
+            };
+            let samples = match result {
+                Ok(samples) => samples,
+                Err(e) => {
+                    warn!("[scrape] Scraping {} failed: {e}", target.url);
+                    continue;
+                }
+            };
+            let counters = &mut state.entry(target.id).or_default().counters;
+            let request = prometheus::node_exporter_metrics(&samples, counters, scraped_at);
+            let item = match MetricIngestItem::from_request(target.system_id, request) {
+                Ok(item) => item,
+                Err(e) => {
+                    warn!(
+                        "[scrape] Dropping scrape of {}: {}",
+                        target.url,
+                        e.message()
+                    );
+                    continue;
+                }
+            };
+            match ingest.push(IngestItem::Metric(item)).await {
+                Ok(()) => {}
+                Err(IngestError::Full) => warn!("[scrape] Ingest queue is full, dropping a sample"),
+                Err(IngestError::Closed) => {
+                    error!("[scrape] Metric queue closed, stopping poller");
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use crate::proto::monitor::ReleaseManifest;
+use chrono::{DateTime, Utc};
+use log::info;
+use openssl::base64;
+use serde::Serialize;
+use sqlx::PgPool;
+
+/*
+ * Agent release channels
+ * Builds are published as rows of agent_releases. Agents call GetRelease for their channel, OS
+ * and architecture and get the newest row's JSON manifest with its signature. Both are made
+ * where the release key lives when the build is published (lynx-scripts/sign-release.sh), the
+ * hub only relays them: whoever can write to agent_releases still can't sign a build. The
+ * manifest names the channel, OS and architecture and carries the binary's sha256, so an agent
+ * that verified the signature only needs to compare the download against it.
+ */
+
+pub const CHANNELS: &[&str] = &["stable", "beta"];
+
+/// Held by the hub changing the table, hubs starting meanwhile wait for it.
+const SIGNED_MANIFEST_LOCK_ID: i64 = 0x6c79_6e78_0004;
+
+const HAS_MANIFEST_COLUMN: &str = "SELECT EXISTS (SELECT 1 FROM information_schema.columns \
+     WHERE table_schema = current_schema() AND table_name = 'agent_releases' \
+     AND column_name = 'signature')";
+
+const ADD_MANIFEST_COLUMNS: &str = "ALTER TABLE agent_releases \
+     ADD COLUMN IF NOT EXISTS manifest text, ADD COLUMN IF NOT EXISTS signature text";
+
+const GET_LATEST_MANIFEST: &str = "SELECT manifest, signature FROM agent_releases \
+     WHERE channel = $1 AND os = $2 AND arch = $3 \
+     AND manifest IS NOT NULL AND signature IS NOT NULL \
+     ORDER BY published DESC, id DESC LIMIT 1";
+
+const GET_LATEST_RELEASE: &str = "SELECT channel, version, arch, url, sha256, published \
+     FROM agent_releases WHERE channel = $1 AND os = $2 AND arch = $3 \
+     ORDER BY published DESC, id DESC LIMIT 1";
+
+/// What the manifest says about a build, serialized in this field order.
+#[derive(Serialize, Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Release {
+    pub channel: String,
+    pub version: String,
+    pub arch: String,
+    pub url: String,
+    pub sha256: String,
+    pub published: DateTime<Utc>,
+}
+
+pub fn is_channel(channel: &str) -> bool {
+    CHANNELS.contains(&channel)
+}
+
+pub async fn load_latest(
+    pool: &PgPool,
+    channel: &str,
+    os: &str,
+    arch: &str,
+) -> Result<Option<Release>, sqlx::Error> {
+    sqlx::query_as::<_, Release>(GET_LATEST_RELEASE)
+        .bind(channel)
+        .bind(os)
+        .bind(arch)
+        .fetch_optional(pool)
+        .await
+}
+
+/// A manifest as published: its JSON and the base64 Ed25519 signature over exactly those bytes.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct SignedManifest {
+    pub manifest: String,
+    pub signature: String,
+}
+
+/// The newest signed manifest for the target, rows published without one are skipped.
+pub async fn load_latest_manifest(
+    pool: &PgPool,
+    channel: &str,
+    os: &str,
+    arch: &str,
+) -> Result<Option<SignedManifest>, sqlx::Error> {
+    sqlx::query_as::<_, SignedManifest>(GET_LATEST_MANIFEST)
+        .bind(channel)
+        .bind(os)
+        .bind(arch)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Passes the manifest on byte for byte, None when the stored signature isn't base64.
+pub fn relay_manifest(signed: SignedManifest) -> Option<ReleaseManifest> {
+    let signature = base64::decode_block(signed.signature.trim()).ok()?;
+    Some(ReleaseManifest {
+        manifest: signed.manifest.into_bytes(),
+        signature,
+    })
+}
+
+/*
+ * migrate
+ * Databases from before manifests were signed at release time get the `manifest` and
+ * `signature` columns on start. Rows without them aren't offered to agents until they are
+ * published again with sign-release.sh.
+ */
+pub async fn migrate(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(SIGNED_MANIFEST_LOCK_ID)
+        .execute(&mut *tx)
+        .await?;
+    let migrated: bool = sqlx::query_scalar(HAS_MANIFEST_COLUMN)
+        .fetch_one(&mut *tx)
+        .await?;
+    if migrated {
+        return tx.commit().await;
+    }
+    sqlx::query(ADD_MANIFEST_COLUMNS).execute(&mut *tx).await?;
+    tx.commit().await?;
+    info!("[hub] Added signed manifests to agent releases");
+    Ok(())
+}
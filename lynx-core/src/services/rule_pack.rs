@@ -0,0 +1,197 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::time::Duration;
+use thiserror::Error;
+
+/*
+ * Default rule pack
+ * A starting set of rules so a new install alerts on the obvious failures without anyone
+ * writing expressions: disk or memory running full, agents going offline, agent certificates
+ * about to expire and drives whose SMART data predicts a failure. The rules target `*`, every system enrolled later gets them without an
+ * alert_systems row, and notify every notifier of the user they are provisioned for.
+ * Provisioning is idempotent, rules the user already has by name are left alone so edited
+ * thresholds survive running it again.
+ */
+
+pub struct DefaultRule {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub expression: &'static str,
+    pub severity: &'static str,
+    pub hold: Duration,
+}
+
+pub const DEFAULT_RULES: &[DefaultRule] = &[
+    DefaultRule {
+        name: "Disk almost full",
+        description: "The root filesystem is more than 90% full",
+        expression: "disk.usage > 90",
+        severity: "high",
+        hold: Duration::ZERO,
+    },
+    DefaultRule {
+        name: "Memory exhausted",
+        description: "Memory usage stayed above 95% for 5 minutes",
+        expression: "memory.usage > 95",
+        severity: "high",
+        hold: Duration::from_secs(300),
+    },
+    DefaultRule {
+        name: "Agent offline",
+        description: "The agent has not reported for 5 minutes",
+        expression: "agent.offline_minutes > 5",
+        severity: "critical",
+        hold: Duration::ZERO,
+    },
+    DefaultRule {
+        name: "Clock skewed",
+        description: "The agent's clock stayed more than 30 seconds off the hub's for 10 minutes",
+        expression: "agent.clock_skew_seconds > 30",
+        severity: "medium",
+        hold: Duration::from_secs(600),
+    },
+    DefaultRule {
+        name: "Agent certificate expiring",
+        description: "The agent's client certificate expires within 7 days",
+        expression: "tls.expiry_days < 7",
+        severity: "critical",
+        hold: Duration::ZERO,
+    },
+    DefaultRule {
+        name: "Drive failure predicted",
+        description: "A drive failed its SMART self-assessment or gained reallocated or pending \
+                      sectors within 7 days",
+        expression: "smart.failed > 0 OR smart.reallocated_sectors.delta_7d > 0 \
+                     OR smart.pending_sectors.delta_7d > 0",
+        severity: "critical",
+        hold: Duration::ZERO,
+    },
+];
+
+/// Tag target of the default rules, every system.
+pub const TARGET: &str = "*";
+
+const GET_FIRST_ADMIN: &str = "SELECT id FROM users WHERE admin = true ORDER BY id LIMIT 1";
+
+const GET_USER: &str = "SELECT id FROM users WHERE id = $1";
+
+const GET_RULE_BY_NAME: &str = "SELECT id FROM alert_rules WHERE user_id = $1 AND name = $2";
+
+const INSERT_RULE: &str = "INSERT INTO alert_rules \
+     (name, description, user_id, expression, severity, target, for_secs, active) \
+     VALUES ($1, $2, $3, $4, $5, $6, $7, true) RETURNING id";
+
+const INSERT_RULE_NOTIFIERS: &str = "INSERT INTO alert_notifiers (rule_id, notifier_id) \
+     SELECT $1, id FROM notifiers WHERE \"user\" = $2";
+
+#[derive(Error, Debug)]
+pub enum RulePackError {
+    #[error("Database error: {0}")]
+    Db(#[from] sqlx::Error),
+    #[error("User {0} not found")]
+    UserNotFound(i32),
+    #[error("No admin user to provision the default rules for")]
+    NoAdmin,
+    #[error("{0}")]
+    Usage(String),
+}
+
+/// Query string of `POST /rules/defaults`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ProvisionQuery {
+    /// Owner of the rules, the first admin when left out
+    pub user_id: Option<i32>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Provisioned {
+    pub user_id: i32,
+    pub created: Vec<String>,
+    /// Rules the user already had, left as they were
+    pub existing: Vec<String>,
+    /// Notifiers attached to each created rule
+    pub notifiers: u64,
+}
+
+impl ProvisionQuery {
+    /// `rules install-defaults [user_id]` in the hub's arguments, None otherwise.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, RulePackError> {
+        let usage = || {
+            RulePackError::Usage("usage: lynx-core rules install-defaults [user_id]".to_string())
+        };
+        match args {
+            [command, rest @ ..] if command == "rules" => match rest {
+                [action] if action == "install-defaults" => Ok(Some(Self::default())),
+                [action, user_id] if action == "install-defaults" => Ok(Some(Self {
+                    user_id: Some(user_id.parse().map_err(|_| usage())?),
+                })),
+                _ => Err(usage()),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+pub async fn provision(pool: &PgPool, user_id: Option<i32>) -> Result<Provisioned, RulePackError> {
+    let user_id: i32 = match user_id {
+        Some(id) => sqlx::query(GET_USER)
+            .bind(id)
+            .fetch_optional(pool)
+            .await?
+            .map(|row| row.get("id"))
+            .ok_or(RulePackError::UserNotFound(id))?,
+        None => sqlx::query(GET_FIRST_ADMIN)
+            .fetch_optional(pool)
+            .await?
+            .map(|row| row.get("id"))
+            .ok_or(RulePackError::NoAdmin)?,
+    };
+
+    let mut provisioned = Provisioned {
+        user_id,
+        created: Vec::new(),
+        existing: Vec::new(),
+        notifiers: 0,
+    };
+    let mut tx = pool.begin().await?;
+    for rule in DEFAULT_RULES {
+        let existing = sqlx::query(GET_RULE_BY_NAME)
+            .bind(user_id)
+            .bind(rule.name)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if existing.is_some() {
+            provisioned.existing.push(rule.name.to_string());
+            continue;
+        }
+        let rule_id: i32 = sqlx::query_scalar(INSERT_RULE)
+            .bind(rule.name)
+            .bind(rule.description)
+            .bind(user_id)
+            .bind(rule.expression)
+            .bind(rule.severity)
+            .bind(TARGET)
+            .bind(rule.hold.as_secs() as i32)
+            .fetch_one(&mut *tx)
+            .await?;
+        provisioned.notifiers = sqlx::query(INSERT_RULE_NOTIFIERS)
+            .bind(rule_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        provisioned.created.push(rule.name.to_string());
+    }
+    tx.commit().await?;
+    // the triggers tell every hub, this one doesn't wait for them
+    crate::notify::RULES.invalidate();
+
+    info!(
+        "[rules] Provisioned {} default rules for user {} ({} already present)",
+        provisioned.created.len(),
+        user_id,
+        provisioned.existing.len()
+    );
+    Ok(provisioned)
+}
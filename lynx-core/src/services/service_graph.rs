@@ -0,0 +1,179 @@
+use crate::cache::Cache;
+use crate::proto::monitor::SystemService;
+use crate::services::status::normalize_state;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/*
+ * Service dependency graph
+ * Agents report each unit's Requires, Wants and After, the graph links them so the portal can
+ * show what a failed unit takes down with it. Requires and Wants pull a unit in, so whatever
+ * requires or wants a failed unit (directly or through other units) is listed as impacted;
+ * After only orders start-up and impacts nothing. Dependencies on units the agent doesn't
+ * report, such as targets and sockets, are nodes without a state.
+ */
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyKind {
+    Requires,
+    Wants,
+    After,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct GraphNode {
+    pub name: String,
+    /// None for units the agent does not report
+    pub state: Option<String>,
+    pub failed: bool,
+}
+
+/// `from` requires, wants or starts after `to`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct GraphEdge {
+    pub from: String,
+    pub to: String,
+    pub kind: DependencyKind,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ServiceGraph {
+    /// "cache" or "database"
+    pub source: &'static str,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// Units pulling in each failed unit (or the queried one), directly or transitively
+    pub impact: BTreeMap<String, Vec<String>>,
+}
+
+/// Query string of `GET /systems/{id}/services/graph`.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct GraphQuery {
+    /// Report the impact of this unit, whatever its state, instead of the failed units'
+    pub unit: Option<String>,
+}
+
+/// Every unit that requires or wants `unit`, directly or through other units, sorted.
+pub fn impacted_by(edges: &[GraphEdge], unit: &str) -> Vec<String> {
+    let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+    for edge in edges.iter().filter(|e| e.kind != DependencyKind::After) {
+        dependents.entry(&edge.to).or_default().push(&edge.from);
+    }
+    let mut impacted = BTreeSet::new();
+    let mut queue = VecDeque::from([unit]);
+    while let Some(next) = queue.pop_front() {
+        for &dependent in dependents.get(next).into_iter().flatten() {
+            if dependent != unit && impacted.insert(dependent) {
+                queue.push_back(dependent);
+            }
+        }
+    }
+    impacted.into_iter().map(str::to_string).collect()
+}
+
+/// Links the reported services, with the impact of the failed ones or of `query.unit`.
+pub fn build<'a>(
+    services: impl IntoIterator<Item = &'a SystemService>,
+    query: &GraphQuery,
+) -> ServiceGraph {
+    let mut nodes: BTreeMap<String, GraphNode> = BTreeMap::new();
+    let mut edges = BTreeSet::new();
+    for service in services {
+        let state = normalize_state(&service.state);
+        nodes.insert(
+            service.service_name.clone(),
+            GraphNode {
+                name: service.service_name.clone(),
+                failed: state == "failed",
+                state: Some(state),
+            },
+        );
+        let kinds = [
+            (DependencyKind::Requires, &service.requires),
+            (DependencyKind::Wants, &service.wants),
+            (DependencyKind::After, &service.after),
+        ];
+        for (kind, names) in kinds {
+            for name in names {
+                edges.insert(GraphEdge {
+                    from: service.service_name.clone(),
+                    to: name.clone(),
+                    kind,
+                });
+            }
+        }
+    }
+    for edge in &edges {
+        nodes.entry(edge.to.clone()).or_insert_with(|| GraphNode {
+            name: edge.to.clone(),
+            state: None,
+            failed: false,
+        });
+    }
+
+    let edges: Vec<GraphEdge> = edges.into_iter().collect();
+    let roots: Vec<&str> = match query.unit.as_deref() {
+        Some(unit) => vec![unit],
+        None => nodes
+            .values()
+            .filter(|n| n.failed)
+            .map(|n| n.name.as_str())
+            .collect(),
+    };
+    let impact = roots
+        .into_iter()
+        .map(|unit| (unit.to_string(), impacted_by(&edges, unit)))
+        .collect();
+
+    ServiceGraph {
+        source: "cache",
+        nodes: nodes.into_values().collect(),
+        edges,
+        impact,
+    }
+}
+
+/// Same as `build` but from the services table.
+pub async fn load_graph(
+    pool: &PgPool,
+    system_id: i32,
+    query: &GraphQuery,
+) -> Result<ServiceGraph, sqlx::Error> {
+    let rows =
+        sqlx::query("SELECT name, state, requires, wants, after FROM services WHERE system = $1")
+            .bind(system_id)
+            .fetch_all(pool)
+            .await?;
+    let services: Vec<SystemService> = rows
+        .iter()
+        .map(|r| SystemService {
+            service_name: r.get("name"),
+            state: r.get::<Option<String>, _>("state").unwrap_or_default(),
+            requires: r.get("requires"),
+            wants: r.get("wants"),
+            after: r.get("after"),
+            ..Default::default()
+        })
+        .collect();
+    Ok(ServiceGraph {
+        source: "database",
+        ..build(&services, query)
+    })
+}
+
+/// A system's dependency graph from the (possibly shared) cache, falling back to the database.
+pub async fn graph(
+    cache: &Cache,
+    pool: &PgPool,
+    system_id: i32,
+    query: &GraphQuery,
+) -> Result<ServiceGraph, sqlx::Error> {
+    match cache.load_system(system_id).await {
+        Some(snapshot) if !snapshot.services.is_empty() => {
+            Ok(build(snapshot.services.values(), query))
+        }
+        _ => load_graph(pool, system_id, query).await,
+    }
+}
@@ -0,0 +1,255 @@
+use crate::cache::Cache;
+use crate::proto::monitor::SystemService;
+use crate::services::status::normalize_state;
+use log::info;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, QueryBuilder, Row};
+
+/*
+ * Service listing
+ * Hosts can run hundreds of units, so the HTTP API pages through a system's services with
+ * optional state filters and a name search. The per-system cache answers once the agent
+ * reported since the hub started, the services table is used until then.
+ */
+
+pub const DEFAULT_PER_PAGE: u32 = 50;
+pub const MAX_PER_PAGE: u32 = 500;
+
+/// Query string of `GET /systems/{id}/services`, pages start at 1.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ServiceQuery {
+    /// Comma separated states, e.g. `failed,activating`
+    pub state: Option<String>,
+    /// Case-insensitive substring of the name or description
+    pub q: Option<String>,
+    pub page: Option<u32>,
+    pub per_page: Option<u32>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ServiceEntry {
+    pub name: String,
+    pub description: String,
+    pub state: String,
+    pub pid: u64,
+    pub cpu: String,
+    pub memory: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ServicePage {
+    /// Matching services across all pages
+    pub total: u64,
+    pub page: u32,
+    pub per_page: u32,
+    /// "cache" or "database"
+    pub source: &'static str,
+    pub services: Vec<ServiceEntry>,
+}
+
+impl From<&SystemService> for ServiceEntry {
+    fn from(s: &SystemService) -> Self {
+        Self {
+            name: s.service_name.clone(),
+            description: s.description.clone(),
+            state: s.state.clone(),
+            pid: s.pid,
+            cpu: s.cpu.clone(),
+            memory: s.memory.clone(),
+        }
+    }
+}
+
+impl ServiceQuery {
+    fn page(&self) -> u32 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn per_page(&self) -> u32 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+
+    fn offset(&self) -> usize {
+        (self.page() as usize - 1) * self.per_page() as usize
+    }
+
+    fn states(&self) -> Vec<String> {
+        self.state
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| normalize_state(s.trim()))
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    fn search(&self) -> Option<String> {
+        self.q
+            .as_deref()
+            .map(str::trim)
+            .filter(|q| !q.is_empty())
+            .map(str::to_lowercase)
+    }
+}
+
+/*
+ * filter_page
+ * Applies the query to services held in memory, sorted by name so pages are stable between
+ * requests.
+ */
+pub fn filter_page<'a>(
+    services: impl IntoIterator<Item = &'a SystemService>,
+    query: &ServiceQuery,
+) -> ServicePage {
+    let states = query.states();
+    let search = query.search();
+    let mut matching: Vec<&SystemService> = services
+        .into_iter()
+        .filter(|s| states.is_empty() || states.contains(&normalize_state(&s.state)))
+        .filter(|s| {
+            search.as_ref().is_none_or(|q| {
+                s.service_name.to_lowercase().contains(q)
+                    || s.description.to_lowercase().contains(q)
+            })
+        })
+        .collect();
+    matching.sort_by(|a, b| a.service_name.cmp(&b.service_name));
+
+    ServicePage {
+        total: matching.len() as u64,
+        page: query.page(),
+        per_page: query.per_page(),
+        source: "cache",
+        services: matching
+            .into_iter()
+            .skip(query.offset())
+            .take(query.per_page() as usize)
+            .map(ServiceEntry::from)
+            .collect(),
+    }
+}
+
+/// Escapes LIKE wildcards so a search for `foo_bar` matches literally.
+fn like_pattern(q: &str) -> String {
+    let escaped = q
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// Same as `filter_page` but against the services table.
+pub async fn load_page(
+    pool: &PgPool,
+    system_id: i32,
+    query: &ServiceQuery,
+) -> Result<ServicePage, sqlx::Error> {
+    let mut qb = QueryBuilder::new(
+        "SELECT name, description, state, pid, cpu, memory, COUNT(*) OVER () AS total \
+         FROM services WHERE system = ",
+    );
+    qb.push_bind(system_id);
+    let states = query.states();
+    if !states.is_empty() {
+        qb.push(" AND lower(trim(both '\"' from state)) = ANY(")
+            .push_bind(states)
+            .push(")");
+    }
+    if let Some(q) = query.search() {
+        let pattern = like_pattern(&q);
+        qb.push(" AND (name ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR description ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    qb.push(" ORDER BY name LIMIT ")
+        .push_bind(query.per_page() as i64)
+        .push(" OFFSET ")
+        .push_bind(query.offset() as i64);
+
+    let rows = qb.build().fetch_all(pool).await?;
+    let total = rows.first().map_or(0, |r| r.get::<i64, _>("total") as u64);
+    let services = rows
+        .iter()
+        .map(|r| ServiceEntry {
+            name: r.get("name"),
+            description: r
+                .get::<Option<String>, _>("description")
+                .unwrap_or_default(),
+            state: r.get::<Option<String>, _>("state").unwrap_or_default(),
+            pid: r.get::<Option<i32>, _>("pid").unwrap_or(0).max(0) as u64,
+            cpu: r.get::<Option<String>, _>("cpu").unwrap_or_default(),
+            memory: r.get::<Option<String>, _>("memory").unwrap_or_default(),
+        })
+        .collect();
+
+    Ok(ServicePage {
+        total,
+        page: query.page(),
+        per_page: query.per_page(),
+        source: "database",
+        services,
+    })
+}
+
+/// Lists a system's services from the (possibly shared) cache, falling back to the database.
+pub async fn list(
+    cache: &Cache,
+    pool: &PgPool,
+    system_id: i32,
+    query: &ServiceQuery,
+) -> Result<ServicePage, sqlx::Error> {
+    match cache.load_system(system_id).await {
+        Some(snapshot) if !snapshot.services.is_empty() => {
+            Ok(filter_page(snapshot.services.values(), query))
+        }
+        _ => load_page(pool, system_id, query).await,
+    }
+}
+
+/// Held by the hub adding the constraint, hubs starting meanwhile wait for it.
+const UNIQUE_NAMES_LOCK_ID: i64 = 0x6c79_6e78_0002;
+
+const HAS_UNIQUE_NAMES: &str = "SELECT EXISTS (SELECT 1 FROM pg_constraint \
+     WHERE conrelid = 'services'::regclass AND conname = 'services_system_name_key')";
+
+/// Keeps the newest row of each (system, name), the one the last report wrote.
+const DELETE_DUPLICATES: &str = "DELETE FROM services a USING services b \
+     WHERE a.system = b.system AND a.name = b.name AND a.id < b.id";
+
+const ADD_UNIQUE_NAMES: &str =
+    "ALTER TABLE services ADD CONSTRAINT services_system_name_key UNIQUE (system, name)";
+
+/*
+ * migrate
+ * Systemctl reports are upserted ON CONFLICT (system, name), which needs the unique constraint
+ * fresh installs get from the schema. Databases from before it are given it on start, after
+ * dropping the duplicate rows the old inserts left behind. Runs in one transaction.
+ */
+pub async fn migrate(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(UNIQUE_NAMES_LOCK_ID)
+        .execute(&mut *tx)
+        .await?;
+    let unique: bool = sqlx::query_scalar(HAS_UNIQUE_NAMES)
+        .fetch_one(&mut *tx)
+        .await?;
+    if unique {
+        return tx.commit().await;
+    }
+    let duplicates = sqlx::query(DELETE_DUPLICATES)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    sqlx::query(ADD_UNIQUE_NAMES).execute(&mut *tx).await?;
+    tx.commit().await?;
+    info!(
+        "[hub] Made service names unique per system, dropped {} duplicate rows",
+        duplicates
+    );
+    Ok(())
+}
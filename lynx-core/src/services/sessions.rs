@@ -0,0 +1,382 @@
+use crate::proto::monitor::session_frame::Frame;
+use crate::proto::monitor::{SessionFrame, TunnelOpen};
+use crate::shutdown::Shutdown;
+use dashmap::DashMap;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::tokio_stream::{Stream, StreamExt};
+use tonic::Status;
+use uuid::Uuid;
+
+/*
+ * Relayed agent sessions
+ * Agents keep an OpenSessions stream to the hub so dashboards can reach their command and live
+ * metrics websocket without a direct connection to the agent's host. A dashboard asks for a
+ * ticket with `POST /systems/{id}/sessions` and opens `GET /systems/{id}/ws?ticket=`; the hub
+ * then opens a session on the agent's stream and copies frames both ways until either side
+ * closes. Streams live in the memory of the hub the agent is connected to, behind a load
+ * balancer the websocket has to land on that hub.
+ *
+ * Tunnels ride the same stream: an admin grants a TCP tunnel to one port of the agent host
+ * with `POST /systems/{id}/tunnels`, the websocket opened with its ticket carries the
+ * connection's bytes in binary messages until the tunnel's TTL runs out. The agent only
+ * connects to ports its config allows, on its loopback.
+ */
+
+/// How long a ticket can be redeemed, it is good for one connection.
+pub const TICKET_TTL: Duration = Duration::from_secs(30);
+
+pub const DEFAULT_TUNNEL_TTL: Duration = Duration::from_secs(15 * 60);
+pub const MAX_TUNNEL_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Frames buffered per direction before a slow side loses them.
+const SESSION_QUEUE: usize = 64;
+
+pub type FrameStream = ReceiverStream<Result<SessionFrame, Status>>;
+type Sessions = Arc<DashMap<String, Route>>;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SessionError {
+    #[error("System {0} has no session stream to this hub")]
+    NotConnected(i32),
+    #[error("Invalid or expired session ticket")]
+    InvalidTicket,
+    #[error("Invalid port {0}")]
+    InvalidPort(u16),
+}
+
+/// Returned by `POST /systems/{id}/sessions`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Ticket {
+    pub ticket: String,
+    pub expires_in_secs: u64,
+    /// Websocket path to open with the ticket
+    pub path: String,
+}
+
+/// Body of `POST /systems/{id}/tunnels`.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TunnelRequest {
+    /// Port on the agent host's loopback
+    pub port: u16,
+    pub ttl_secs: Option<u64>,
+}
+
+impl TunnelRequest {
+    pub fn ttl(&self) -> Duration {
+        self.ttl_secs
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TUNNEL_TTL)
+            .clamp(Duration::from_secs(1), MAX_TUNNEL_TTL)
+    }
+}
+
+/// Returned by `POST /systems/{id}/tunnels`.
+#[derive(Serialize, Debug, Clone)]
+pub struct TunnelTicket {
+    pub ticket: String,
+    pub expires_in_secs: u64,
+    /// Websocket path to open with the ticket
+    pub path: String,
+    pub port: u16,
+    /// How long the tunnel stays open once connected
+    pub ttl_secs: u64,
+}
+
+/// A redeemed tunnel ticket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tunnel {
+    pub port: u16,
+    pub ttl: Duration,
+}
+
+struct Grant {
+    system_id: i32,
+    expires: Instant,
+    /// None for websocket sessions
+    tunnel: Option<Tunnel>,
+}
+
+struct Route {
+    tx: mpsc::Sender<Frame>,
+    /// Tunnels are closed rather than losing bytes
+    tunnel: bool,
+}
+
+struct AgentLink {
+    /// Tells a replaced stream apart from the current one
+    link: u64,
+    to_agent: mpsc::Sender<Result<SessionFrame, Status>>,
+    sessions: Sessions,
+}
+
+#[derive(Default)]
+struct Inner {
+    agents: DashMap<i32, AgentLink>,
+    tickets: DashMap<String, Grant>,
+    next_link: AtomicU64,
+}
+
+#[derive(Clone, Default)]
+pub struct SessionRelay {
+    inner: Arc<Inner>,
+}
+
+/// One dashboard connection relayed to an agent, closed on the agent when dropped.
+pub struct Session {
+    pub id: String,
+    pub system_id: i32,
+    /// Frames the agent sent for this session, ends when the agent's stream goes away
+    pub from_agent: mpsc::Receiver<Frame>,
+    to_agent: mpsc::Sender<Result<SessionFrame, Status>>,
+    sessions: Sessions,
+}
+
+impl SessionRelay {
+    pub fn is_connected(&self, system_id: i32) -> bool {
+        self.inner.agents.contains_key(&system_id)
+    }
+
+    /// Systems with a session stream to this hub.
+    pub fn connected(&self) -> usize {
+        self.inner.agents.len()
+    }
+
+    fn grant(&self, system_id: i32, tunnel: Option<Tunnel>) -> Result<String, SessionError> {
+        if !self.is_connected(system_id) {
+            return Err(SessionError::NotConnected(system_id));
+        }
+        let now = Instant::now();
+        self.inner.tickets.retain(|_, grant| grant.expires > now);
+        let ticket = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        self.inner.tickets.insert(
+            ticket.clone(),
+            Grant {
+                system_id,
+                expires: now + TICKET_TTL,
+                tunnel,
+            },
+        );
+        Ok(ticket)
+    }
+
+    pub fn issue_ticket(&self, system_id: i32) -> Result<Ticket, SessionError> {
+        let ticket = self.grant(system_id, None)?;
+        Ok(Ticket {
+            path: format!("/systems/{system_id}/ws?ticket={ticket}"),
+            ticket,
+            expires_in_secs: TICKET_TTL.as_secs(),
+        })
+    }
+
+    pub fn issue_tunnel_ticket(
+        &self,
+        system_id: i32,
+        request: &TunnelRequest,
+    ) -> Result<TunnelTicket, SessionError> {
+        if request.port == 0 {
+            return Err(SessionError::InvalidPort(request.port));
+        }
+        let tunnel = Tunnel {
+            port: request.port,
+            ttl: request.ttl(),
+        };
+        let ticket = self.grant(system_id, Some(tunnel))?;
+        Ok(TunnelTicket {
+            path: format!("/systems/{system_id}/tunnel?ticket={ticket}"),
+            ticket,
+            expires_in_secs: TICKET_TTL.as_secs(),
+            port: tunnel.port,
+            ttl_secs: tunnel.ttl.as_secs(),
+        })
+    }
+
+    /// Uses up a ticket, it has to be for `system_id` and not expired.
+    fn take(&self, system_id: i32, ticket: &str) -> Result<Option<Tunnel>, SessionError> {
+        match self.inner.tickets.remove(ticket) {
+            Some((_, grant)) if grant.system_id == system_id && grant.expires > Instant::now() => {
+                Ok(grant.tunnel)
+            }
+            _ => Err(SessionError::InvalidTicket),
+        }
+    }
+
+    /// Uses up a websocket session ticket.
+    pub fn redeem(&self, system_id: i32, ticket: &str) -> Result<(), SessionError> {
+        match self.take(system_id, ticket)? {
+            None => Ok(()),
+            Some(_) => Err(SessionError::InvalidTicket),
+        }
+    }
+
+    /// Uses up a tunnel ticket, returning what it grants.
+    pub fn redeem_tunnel(&self, system_id: i32, ticket: &str) -> Result<Tunnel, SessionError> {
+        self.take(system_id, ticket)?
+            .ok_or(SessionError::InvalidTicket)
+    }
+
+    /*
+     * attach
+     * Takes an agent's OpenSessions stream and returns the stream the hub answers on. Frames
+     * from the agent are routed to their session until the agent hangs up or the hub shuts
+     * down. A reconnecting agent replaces its previous stream, sessions on that one end.
+     */
+    pub fn attach<S>(&self, system_id: i32, mut inbound: S, shutdown: Shutdown) -> FrameStream
+    where
+        S: Stream<Item = Result<SessionFrame, Status>> + Send + Unpin + 'static,
+    {
+        let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+        let link = self.inner.next_link.fetch_add(1, Ordering::Relaxed);
+        let sessions: Sessions = Arc::new(DashMap::new());
+        self.inner.agents.insert(
+            system_id,
+            AgentLink {
+                link,
+                to_agent: tx.clone(),
+                sessions: sessions.clone(),
+            },
+        );
+        info!("[hub] System {system_id} opened its session stream");
+
+        let relay = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let frame = tokio::select! {
+                    frame = inbound.next() => frame,
+                    _ = tx.closed() => break,
+                    _ = shutdown.wait() => break,
+                };
+                match frame {
+                    Some(Ok(frame)) => route(&sessions, &tx, frame),
+                    Some(Err(status)) => {
+                        warn!("[hub] Session stream of system {system_id} failed: {status}");
+                        break;
+                    }
+                    None => break,
+                }
+            }
+            relay
+                .inner
+                .agents
+                .remove_if(&system_id, |_, agent| agent.link == link);
+            // dropping the senders ends the dashboards' sessions
+            sessions.clear();
+            info!("[hub] System {system_id} closed its session stream");
+        });
+        ReceiverStream::new(rx)
+    }
+
+    /// Opens a session on the agent's stream.
+    pub async fn open(&self, system_id: i32) -> Result<Session, SessionError> {
+        self.open_with(system_id, Frame::Open(true)).await
+    }
+
+    /// Opens a tunnel on the agent's stream, its bytes travel in binary frames.
+    pub async fn open_tunnel(
+        &self,
+        system_id: i32,
+        tunnel: Tunnel,
+    ) -> Result<Session, SessionError> {
+        let open = TunnelOpen {
+            port: tunnel.port as u32,
+            ttl_secs: tunnel.ttl.as_secs() as u32,
+        };
+        self.open_with(system_id, Frame::Tunnel(open)).await
+    }
+
+    async fn open_with(&self, system_id: i32, open: Frame) -> Result<Session, SessionError> {
+        let (to_agent, sessions) = {
+            let agent = self
+                .inner
+                .agents
+                .get(&system_id)
+                .ok_or(SessionError::NotConnected(system_id))?;
+            (agent.to_agent.clone(), agent.sessions.clone())
+        };
+        let id = Uuid::new_v4().to_string();
+        let (tx, rx) = mpsc::channel(SESSION_QUEUE);
+        let tunnel = matches!(open, Frame::Tunnel(_));
+        sessions.insert(id.clone(), Route { tx, tunnel });
+        let open = SessionFrame {
+            session_id: id.clone(),
+            frame: Some(open),
+        };
+        if to_agent.send(Ok(open)).await.is_err() {
+            sessions.remove(&id);
+            return Err(SessionError::NotConnected(system_id));
+        }
+        Ok(Session {
+            id,
+            system_id,
+            from_agent: rx,
+            to_agent,
+            sessions,
+        })
+    }
+}
+
+/*
+ * route
+ * Hands a frame from the agent to its session, a Close frame ends the session. A tunnel whose
+ * dashboard is not keeping up is closed on both ends, its connection can't skip bytes.
+ */
+fn route(
+    sessions: &DashMap<String, Route>,
+    to_agent: &mpsc::Sender<Result<SessionFrame, Status>>,
+    frame: SessionFrame,
+) {
+    let Some(kind) = frame.frame else {
+        return;
+    };
+    let mut closing = matches!(kind, Frame::Close(_));
+    if let Some(session) = sessions.get(&frame.session_id)
+        && session.tx.try_send(kind).is_err()
+    {
+        warn!(
+            "[hub] Dropped a frame for session {}, the dashboard is not keeping up",
+            frame.session_id
+        );
+        if session.tunnel {
+            let _ = to_agent.try_send(Ok(SessionFrame {
+                session_id: frame.session_id.clone(),
+                frame: Some(Frame::Close("tunnel fell behind".to_string())),
+            }));
+            closing = true;
+        }
+    }
+    if closing {
+        sessions.remove(&frame.session_id);
+    }
+}
+
+impl Session {
+    /// Forwards a dashboard message to the agent, fails once the agent's stream is gone.
+    pub async fn send(&self, frame: Frame) -> Result<(), SessionError> {
+        let frame = SessionFrame {
+            session_id: self.id.clone(),
+            frame: Some(frame),
+        };
+        self.to_agent
+            .send(Ok(frame))
+            .await
+            .map_err(|_| SessionError::NotConnected(self.system_id))
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        // still registered means the agent did not close it, tell it to
+        if self.sessions.remove(&self.id).is_some() {
+            let _ = self.to_agent.try_send(Ok(SessionFrame {
+                session_id: self.id.clone(),
+                frame: Some(Frame::Close("dashboard disconnected".to_string())),
+            }));
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::config::Secrets;
+use crate::counters::CounterRates;
+use crate::leader::Leadership;
+use crate::proto::monitor::{
+    Component, CpuStats, LoadAverage, MemoryStats, MetricsRequest, NetworkStats,
+};
+use crate::services::ingest::{IngestError, IngestItem, IngestQueue, MetricIngestItem};
+use crate::snmp::{self, SnmpValue};
+use crate::units;
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+
+const POLL_TICK: Duration = Duration::from_secs(5);
+const SNMP_TIMEOUT: Duration = Duration::from_secs(2);
+/// Component temperatures are configured as `temperature.<label>` in `snmp_devices.oids`
+const TEMPERATURE_PREFIX: &str = "temperature.";
+
+/*
+ * SNMP poller
+ * Scrapes agentless devices (switches, printers, UPSes) listed in snmp_devices and feeds the
+ * readings into the regular ingest pipeline as MetricsRequests, so they are stored, alerted on
+ * and shown like any agent. `oids` maps metric names to the OID holding the value:
+ *   {"cpu_usage": "1.3.6.1.2.1.25.3.3.1.2.196608",
+ *    "net_in": "1.3.6.1.2.1.31.1.1.1.6.1", "net_out": "1.3.6.1.2.1.31.1.1.1.10.1",
+ *    "temperature.battery": "1.3.6.1.2.1.33.1.2.7.0"}
+ * net_in / net_out are octet counters, converted to bytes/s between polls like agents report.
+ */
+
+struct SnmpDevice {
+    id: i32,
+    system_id: i32,
+    host: String,
+    port: i32,
+    community: String,
+    interval_secs: i32,
+    oids: HashMap<String, String>,
+}
+
+#[derive(Default)]
+struct DeviceState {
+    last_poll: Option<Instant>,
+    counters: CounterRates,
+}
+
+async fn load_devices(pool: &PgPool) -> Result<Vec<SnmpDevice>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT d.id, d.system_id, d.host, d.port, d.community, d.interval_secs, d.oids
+           FROM snmp_devices d JOIN systems s ON s.id = d.system_id
+           WHERE d.active = true"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter_map(|r| {
+            let oids = match serde_json::from_value(r.oids) {
+                Ok(oids) => oids,
+                Err(e) => {
+                    warn!("[snmp] Ignoring device {}, invalid oids: {e}", r.id);
+                    return None;
+                }
+            };
+            Some(SnmpDevice {
+                id: r.id,
+                system_id: r.system_id,
+                host: r.host,
+                port: r.port,
+                community: r.community,
+                interval_secs: r.interval_secs,
+                oids,
+            })
+        })
+        .collect())
+}
+
+async fn poll_device(
+    device: &SnmpDevice,
+    secrets: &Secrets,
+) -> Result<HashMap<String, SnmpValue>, Box<dyn std::error::Error + Send + Sync>> {
+    let community = secrets.resolve(&device.community).await?;
+    let target = tokio::net::lookup_host((device.host.as_str(), device.port as u16))
+        .await?
+        .next()
+        .ok_or_else(|| format!("{} did not resolve", device.host))?;
+
+    let oids: Vec<String> = device.oids.values().cloned().collect();
+    let values = snmp::get(target, &community, &oids, SNMP_TIMEOUT).await?;
+
+    // key the readings by metric name instead of OID
+    Ok(device
+        .oids
+        .iter()
+        .filter_map(|(name, oid)| {
+            let value = values.get(oid.trim_start_matches('.'))?;
+            Some((name.clone(), value.clone()))
+        })
+        .collect())
+}
+
+/// Turns a device's readings into the MetricsRequest an agent would have sent.
+fn to_metrics_request(
+    values: &HashMap<String, SnmpValue>,
+    counters: &mut CounterRates,
+) -> MetricsRequest {
+    let value = |name: &str| values.get(name).and_then(SnmpValue::as_f64);
+    let now = std::time::Instant::now();
+    let mut rate = |name: &str| counters.rate(name, value(name)?, now);
+    let (rate_in, rate_out) = (rate("net_in"), rate("net_out"));
+
+    let total_kb = value("memory_total_kb").unwrap_or(0.0) as u64;
+    let used_kb = value("memory_used_kb").unwrap_or(0.0) as u64;
+    let network = NetworkStats {
+        r#in: (rate_in.unwrap_or(0.0) / units::MB) as u64,
+        out: (rate_out.unwrap_or(0.0) / units::MB) as u64,
+        in_bytes_per_sec: Some(rate_in.unwrap_or(0.0)),
+        out_bytes_per_sec: Some(rate_out.unwrap_or(0.0)),
+    };
+
+    MetricsRequest {
+        cpu_stats: Some(CpuStats {
+            usage_percent: value("cpu_usage").unwrap_or(0.0),
+            ..Default::default()
+        }),
+        memory_stats: Some(MemoryStats {
+            total_kb,
+            used_kb,
+            free_kb: total_kb.saturating_sub(used_kb),
+            ..Default::default()
+        }),
+        disk_stats: Vec::new(),
+        components: values
+            .iter()
+            .filter_map(|(name, v)| {
+                Some(Component {
+                    label: name.strip_prefix(TEMPERATURE_PREFIX)?.to_string(),
+                    temperature: v.as_f64()? as f32,
+                })
+            })
+            .collect(),
+        network_stats: Some(network),
+        load_average: Some(LoadAverage {
+            one_minute: value("load_one").unwrap_or(0.0),
+            five_minutes: value("load_five").unwrap_or(0.0),
+            fifteen_minutes: value("load_fifteen").unwrap_or(0.0),
+        }),
+        cert_expiry_days: None,
+        process_stats: None,
+        kernel_stats: None,
+        collected_at_ms: None,
+        probe_results: Vec::new(),
+        custom_metrics: Vec::new(),
+        top_processes: Vec::new(),
+        smart_devices: Vec::new(),
+        sent_at_ms: None,
+    }
+}
+
+pub async fn run_snmp_poller(
+    pool: PgPool,
+    secrets: Secrets,
+    ingest: IngestQueue,
+    leadership: Leadership,
+) {
+    let mut state: HashMap<i32, DeviceState> = HashMap::new();
+    let mut tick = tokio::time::interval(POLL_TICK);
+    let mut announced = false;
+
+    loop {
+        tick.tick().await;
+        // another hub polls, two would store every sample twice
+        if !leadership.is_leader() {
+            continue;
+        }
+        let devices = match load_devices(&pool).await {
+            Ok(devices) => devices,
+            Err(e) => {
+                error!("[snmp] Failed to load devices: {e}");
+                continue;
+            }
+        };
+        if !devices.is_empty() && !announced {
+            info!("[snmp] Polling {} device(s)", devices.len());
+            announced = true;
+        }
+        state.retain(|id, _| devices.iter().any(|d| d.id == *id));
+
+        let now = Instant::now();
+        let mut polls = JoinSet::new();
+        for device in devices {
+            let entry = state.entry(device.id).or_default();
+            let interval = Duration::from_secs(device.interval_secs.max(1) as u64);
+            if entry
+                .last_poll
+                .is_some_and(|t| now.duration_since(t) < interval)
+            {
+                continue;
+            }
+            entry.last_poll = Some(now);
+            let secrets = secrets.clone();
+            polls.spawn(async move {
+                let result = poll_device(&device, &secrets).await;
+                (device, result)
+            });
+        }
+
+        while let Some(joined) = polls.join_next().await {
+            let Ok((device, result)) = joined else {
+                continue;
+            };
+            let values = match result {
+                Ok(values) => values,
+                Err(e) => {
+                    warn!("[snmp] Polling {} failed: {e}", device.host);
+                    continue;
+                }
+            };
+            let counters = &mut state.entry(device.id).or_default().counters;
+            let request = to_metrics_request(&values, counters);
+            let item = match MetricIngestItem::from_request(device.system_id, request) {
+                Ok(item) => item,
+                Err(e) => {
+                    warn!(
+                        "[snmp] Dropping reading from {}: {}",
+                        device.host,
+                        e.message()
+                    );
+                    continue;
+                }
+            };
+            match ingest.push(IngestItem::Metric(item)).await {
+                Ok(()) => {}
+                Err(IngestError::Full) => warn!("[snmp] Ingest queue is full, dropping a sample"),
+                Err(IngestError::Closed) => {
+                    error!("[snmp] Metric queue closed, stopping poller");
+                    return;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::proto::monitor::{ActiveAlert, ServiceSummary, SystemService};
+use crate::queries::alert_queries;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/*
+ * System status for dashboards
+ * GetSystemStatus answers from the hub cache where it can: the latest metrics sample, GPU
+ * snapshot and service states are kept per system as reports come in. Only the active alerts
+ * and, right after a restart, the last seen time are read from the database.
+ */
+
+/// A system is shown offline once it missed three reports at the default 60s interval.
+pub const ONLINE_THRESHOLD: Duration = Duration::from_secs(180);
+
+/// Whether a system that last reported at `last_seen` still counts as online at `now`.
+pub fn is_online(last_seen: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let threshold = chrono::Duration::from_std(ONLINE_THRESHOLD).unwrap_or_default();
+    last_seen.is_some_and(|seen| now - seen <= threshold)
+}
+
+/*
+ * summarize_services
+ * Counts the reported service states. Agents send systemd's active state, "Active" counts as
+ * running and "Failed" as failed; failed names are sorted so responses are stable.
+ */
+pub fn summarize_services(services: &HashMap<String, SystemService>) -> ServiceSummary {
+    let mut summary = ServiceSummary {
+        total: services.len() as u32,
+        ..Default::default()
+    };
+    for (name, svc) in services {
+        match normalize_state(&svc.state).as_str() {
+            "active" | "running" => summary.running += 1,
+            "failed" => {
+                summary.failed += 1;
+                summary.failed_names.push(name.clone());
+            }
+            _ => {}
+        }
+    }
+    summary.failed_names.sort();
+    summary
+}
+
+/// Service state as compared by filters, agents send e.g. `Active` or `"active"`.
+pub fn normalize_state(state: &str) -> String {
+    state.trim_matches('"').to_ascii_lowercase()
+}
+
+/// Alerts that fired for the system within the alert dedup window.
+pub async fn load_active_alerts(
+    pool: &PgPool,
+    system_id: i32,
+) -> Result<Vec<ActiveAlert>, sqlx::Error> {
+    let rows = sqlx::query(alert_queries::GET_ACTIVE_ALERTS)
+        .bind(system_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            let last_fired: DateTime<Utc> = row.get("date");
+            ActiveAlert {
+                rule_id: row.get("id"),
+                name: row.get("name"),
+                severity: row.get("severity"),
+                last_fired_ms: last_fired.timestamp_millis(),
+            }
+        })
+        .collect())
+}
+
+/// systems.last_seen, used when the cache has not seen the system since the hub started.
+pub async fn load_last_seen(
+    pool: &PgPool,
+    system_id: i32,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let row = sqlx::query("SELECT last_seen FROM systems WHERE id = $1")
+        .bind(system_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.and_then(|r| r.get("last_seen")))
+}
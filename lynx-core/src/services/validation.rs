@@ -0,0 +1,354 @@
+use crate::proto::monitor::{
+    AgentEvent, AgentHealthEvent, CommandAudit, ContainerInfo, ContainerMetrics, GpuInfo,
+    GpuMetrics, MetricsRequest, SystemInfoRequest, SystemService,
+};
+use log::error;
+use sqlx::PgPool;
+use thiserror::Error;
+use tonic::Status;
+
+/*
+ * Report validation
+ * Checks what agents send before anything is queued or written: required sections, numbers
+ * that are finite and within range, and list sizes. Rejected reports are answered with
+ * invalid_argument and recorded in rejected_reports together with the system that sent them.
+ */
+
+pub const MAX_DISKS: usize = 256;
+pub const MAX_COMPONENTS: usize = 256;
+pub const MAX_PROBES: usize = 256;
+pub const MAX_TOP_PROCESSES: usize = 64;
+pub const MAX_SMART_DEVICES: usize = 256;
+pub const MAX_GPUS: usize = 64;
+pub const MAX_SERVICES: usize = 10_000;
+/// Requires, Wants and After entries of one service, each
+pub const MAX_DEPENDENCIES: usize = 1_024;
+pub const MAX_CONTAINERS: usize = 4_096;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ValidationError {
+    #[error("Missing {0}")]
+    Missing(&'static str),
+    #[error("{field} is out of range: {value}")]
+    OutOfRange { field: &'static str, value: f64 },
+    #[error("{0} exceeds its total")]
+    ExceedsTotal(&'static str),
+    #[error("{field} has {len} entries, at most {max} are accepted")]
+    TooMany {
+        field: &'static str,
+        len: usize,
+        max: usize,
+    },
+    #[error("{0} must not be empty")]
+    Empty(&'static str),
+}
+
+impl ValidationError {
+    /// The offending field, stored with the rejection.
+    pub fn field(&self) -> &'static str {
+        match self {
+            ValidationError::Missing(field)
+            | ValidationError::OutOfRange { field, .. }
+            | ValidationError::ExceedsTotal(field)
+            | ValidationError::TooMany { field, .. }
+            | ValidationError::Empty(field) => field,
+        }
+    }
+}
+
+impl From<ValidationError> for Status {
+    fn from(e: ValidationError) -> Self {
+        Status::invalid_argument(e.to_string())
+    }
+}
+
+fn finite(field: &'static str, value: f64) -> Result<(), ValidationError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(ValidationError::OutOfRange { field, value })
+    }
+}
+
+fn non_negative(field: &'static str, value: f64) -> Result<(), ValidationError> {
+    if value.is_finite() && value >= 0.0 {
+        Ok(())
+    } else {
+        Err(ValidationError::OutOfRange { field, value })
+    }
+}
+
+fn percent(field: &'static str, value: f64) -> Result<(), ValidationError> {
+    if (0.0..=100.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(ValidationError::OutOfRange { field, value })
+    }
+}
+
+fn at_most(field: &'static str, len: usize, max: usize) -> Result<(), ValidationError> {
+    if len > max {
+        return Err(ValidationError::TooMany { field, len, max });
+    }
+    Ok(())
+}
+
+fn within(field: &'static str, used: u64, total: u64) -> Result<(), ValidationError> {
+    if used > total {
+        return Err(ValidationError::ExceedsTotal(field));
+    }
+    Ok(())
+}
+
+/// Checks a metrics report from an agent.
+pub fn metrics(m: &MetricsRequest) -> Result<(), ValidationError> {
+    let cpu = m
+        .cpu_stats
+        .as_ref()
+        .ok_or(ValidationError::Missing("cpu_stats"))?;
+    let mem = m
+        .memory_stats
+        .as_ref()
+        .ok_or(ValidationError::Missing("memory_stats"))?;
+    let load = m
+        .load_average
+        .as_ref()
+        .ok_or(ValidationError::Missing("load_average"))?;
+    let net = m
+        .network_stats
+        .as_ref()
+        .ok_or(ValidationError::Missing("network_stats"))?;
+
+    percent("cpu_stats.usage_percent", cpu.usage_percent)?;
+    for (field, value) in [
+        ("cpu_stats.user_percent", cpu.user_percent),
+        ("cpu_stats.system_percent", cpu.system_percent),
+        ("cpu_stats.iowait_percent", cpu.iowait_percent),
+        ("cpu_stats.irq_percent", cpu.irq_percent),
+        ("cpu_stats.steal_percent", cpu.steal_percent),
+    ] {
+        if let Some(value) = value {
+            percent(field, value)?;
+        }
+    }
+
+    within("memory_stats.used_kb", mem.used_kb, mem.total_kb)?;
+    if let Some(available) = mem.available_kb {
+        within("memory_stats.available_kb", available, mem.total_kb)?;
+    }
+    if let (Some(used), Some(total)) = (mem.swap_used_kb, mem.swap_total_kb) {
+        within("memory_stats.swap_used_kb", used, total)?;
+    }
+    for (field, value) in [
+        ("memory_stats.swap_in_per_sec", mem.swap_in_per_sec),
+        ("memory_stats.swap_out_per_sec", mem.swap_out_per_sec),
+    ] {
+        if let Some(value) = value {
+            non_negative(field, value)?;
+        }
+    }
+
+    for (field, value) in [
+        ("network_stats.in_bytes_per_sec", net.in_bytes_per_sec),
+        ("network_stats.out_bytes_per_sec", net.out_bytes_per_sec),
+    ] {
+        if let Some(value) = value {
+            non_negative(field, value)?;
+        }
+    }
+
+    non_negative("load_average.one_minute", load.one_minute)?;
+    non_negative("load_average.five_minutes", load.five_minutes)?;
+    non_negative("load_average.fifteen_minutes", load.fifteen_minutes)?;
+
+    if let Some(days) = m.cert_expiry_days {
+        finite("cert_expiry_days", days)?;
+    }
+    if let Some(kernel) = &m.kernel_stats {
+        non_negative(
+            "kernel_stats.context_switches_per_sec",
+            kernel.context_switches_per_sec,
+        )?;
+        non_negative("kernel_stats.interrupts_per_sec", kernel.interrupts_per_sec)?;
+    }
+
+    at_most("disk_stats", m.disk_stats.len(), MAX_DISKS)?;
+    for disk in &m.disk_stats {
+        if disk.total_space < 0 || disk.used_space < 0 {
+            return Err(ValidationError::OutOfRange {
+                field: "disk_stats.used_space",
+                value: disk.used_space.min(disk.total_space) as f64,
+            });
+        }
+        within(
+            "disk_stats.used_space",
+            disk.used_space as u64,
+            disk.total_space as u64,
+        )?;
+        if let (Some(used), Some(total)) = (disk.used_bytes, disk.total_bytes) {
+            within("disk_stats.used_bytes", used, total)?;
+        }
+        non_negative("disk_stats.read_bytes", disk.read_bytes)?;
+        non_negative("disk_stats.write_bytes", disk.write_bytes)?;
+        for (field, value) in [
+            ("disk_stats.read_iops", disk.read_iops),
+            ("disk_stats.write_iops", disk.write_iops),
+        ] {
+            if let Some(value) = value {
+                non_negative(field, value)?;
+            }
+        }
+        if let (Some(used), Some(total)) = (disk.inodes_used, disk.inodes_total) {
+            within("disk_stats.inodes_used", used, total)?;
+        }
+    }
+
+    at_most("components", m.components.len(), MAX_COMPONENTS)?;
+    for component in &m.components {
+        finite("components.temperature", component.temperature as f64)?;
+    }
+
+    at_most("probe_results", m.probe_results.len(), MAX_PROBES)?;
+    for probe in &m.probe_results {
+        if let Some(latency) = probe.latency_ms {
+            non_negative("probe_results.latency_ms", latency)?;
+        }
+    }
+
+    at_most("top_processes", m.top_processes.len(), MAX_TOP_PROCESSES)?;
+    for process in &m.top_processes {
+        non_negative("top_processes.cpu_usage", process.cpu_usage as f64)?;
+    }
+
+    at_most("smart_devices", m.smart_devices.len(), MAX_SMART_DEVICES)?;
+    for device in &m.smart_devices {
+        if device.device.trim().is_empty() {
+            return Err(ValidationError::Empty("smart_devices.device"));
+        }
+        if let Some(temperature) = device.temperature {
+            finite("smart_devices.temperature", temperature)?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the host details an agent sends on startup.
+pub fn system_info(info: &SystemInfoRequest) -> Result<(), ValidationError> {
+    if info.hostname.trim().is_empty() {
+        return Err(ValidationError::Empty("hostname"));
+    }
+    Ok(())
+}
+
+pub fn gpus(gpus: &[GpuInfo]) -> Result<(), ValidationError> {
+    at_most("gpus", gpus.len(), MAX_GPUS)
+}
+
+pub fn gpu_metrics(metrics: &[GpuMetrics]) -> Result<(), ValidationError> {
+    at_most("gpu_metrics", metrics.len(), MAX_GPUS)?;
+    for gpu in metrics {
+        percent("gpu_metrics.utilization", gpu.utilization)?;
+        finite("gpu_metrics.temperature", gpu.temperature)?;
+        non_negative("gpu_metrics.power", gpu.power)?;
+    }
+    Ok(())
+}
+
+pub fn services(services: &[SystemService]) -> Result<(), ValidationError> {
+    at_most("services", services.len(), MAX_SERVICES)?;
+    if services.iter().any(|s| s.service_name.is_empty()) {
+        return Err(ValidationError::Empty("services.service_name"));
+    }
+    for s in services {
+        at_most("services.requires", s.requires.len(), MAX_DEPENDENCIES)?;
+        at_most("services.wants", s.wants.len(), MAX_DEPENDENCIES)?;
+        at_most("services.after", s.after.len(), MAX_DEPENDENCIES)?;
+    }
+    Ok(())
+}
+
+/// Services received so far on one StreamSystemctl, the chunks together get the same limit.
+pub fn streamed_services(total: usize) -> Result<(), ValidationError> {
+    at_most("services", total, MAX_SERVICES)
+}
+
+pub fn removed_services(names: &[String]) -> Result<(), ValidationError> {
+    at_most("removed", names.len(), MAX_SERVICES)?;
+    if names.iter().any(|name| name.is_empty()) {
+        return Err(ValidationError::Empty("removed"));
+    }
+    Ok(())
+}
+
+pub fn containers(containers: &[ContainerInfo]) -> Result<(), ValidationError> {
+    at_most("containers", containers.len(), MAX_CONTAINERS)?;
+    if containers.iter().any(|c| c.docker_id.is_empty()) {
+        return Err(ValidationError::Empty("containers.docker_id"));
+    }
+    Ok(())
+}
+
+pub fn container_metrics(metrics: &[ContainerMetrics]) -> Result<(), ValidationError> {
+    at_most("container_metrics", metrics.len(), MAX_CONTAINERS)?;
+    for c in metrics {
+        non_negative("container_metrics.cpu_usage", c.cpu_usage)?;
+        non_negative("container_metrics.memory_usage", c.memory_usage)?;
+    }
+    Ok(())
+}
+
+pub fn health_event(event: &AgentHealthEvent) -> Result<(), ValidationError> {
+    if event.collector.trim().is_empty() {
+        return Err(ValidationError::Empty("collector"));
+    }
+    if event.kind.trim().is_empty() {
+        return Err(ValidationError::Empty("kind"));
+    }
+    Ok(())
+}
+
+pub fn agent_event(event: &AgentEvent) -> Result<(), ValidationError> {
+    if event.kind.trim().is_empty() {
+        return Err(ValidationError::Empty("kind"));
+    }
+    if event.time <= 0 {
+        return Err(ValidationError::OutOfRange {
+            field: "time",
+            value: event.time as f64,
+        });
+    }
+    Ok(())
+}
+
+pub fn command_audit(entry: &CommandAudit) -> Result<(), ValidationError> {
+    if entry.action.trim().is_empty() {
+        return Err(ValidationError::Empty("action"));
+    }
+    if entry.actor.trim().is_empty() {
+        return Err(ValidationError::Empty("actor"));
+    }
+    if entry.time <= 0 {
+        return Err(ValidationError::OutOfRange {
+            field: "time",
+            value: entry.time as f64,
+        });
+    }
+    Ok(())
+}
+
+/// Best effort, a failed insert must not change what the agent is told.
+pub async fn record_rejection(pool: &PgPool, system_id: i32, report: &str, e: &ValidationError) {
+    let result = sqlx::query(
+        "INSERT INTO rejected_reports (time, system, report, field, reason) \
+         VALUES (NOW(), $1, $2, $3, $4)",
+    )
+    .bind(system_id)
+    .bind(report)
+    .bind(e.field())
+    .bind(e.to_string())
+    .execute(pool)
+    .await;
+    if let Err(e) = result {
+        error!("[hub] Failed to record rejected {report} report: {e}");
+    }
+}
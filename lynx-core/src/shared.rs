@@ -0,0 +1,204 @@
+use crate::cache::SystemSnapshot;
+use crate::proto::monitor::{GpuMetrics, GpuMetricsRequest, MetricsRequest, SystemService};
+use chrono::{DateTime, Utc};
+use prost::Message;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::time::Duration;
+use thiserror::Error;
+
+/*
+ * Shared hub state
+ * With REDIS_URL set, hubs behind a load balancer keep what agents last reported and which
+ * alerts are cooling down in Redis, so an agent can reconnect to any instance and an alert is
+ * not sent again by another hub during its cooldown. Every instance still has its in-memory
+ * cache; reports are written through to Redis and the per-system views read from it.
+ *   lynx:system:{id}:metrics    latest MetricsRequest (protobuf)
+ *   lynx:system:{id}:gpu        latest GPU samples (GpuMetricsRequest protobuf)
+ *   lynx:system:{id}:last_seen  unix millis of the last report
+ *   lynx:system:{id}:services   hash of service name to SystemService (protobuf)
+ *   lynx:alert:{key}            set by the hub that claimed the alert, expires with its cooldown
+ */
+
+/// Per-system keys expire once a system stopped reporting for this long.
+pub const SYSTEM_TTL: Duration = Duration::from_secs(7 * 24 * 3600);
+
+#[derive(Error, Debug)]
+pub enum SharedError {
+    #[error("Redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+    #[error("Invalid data in Redis: {0}")]
+    Decode(#[from] prost::DecodeError),
+}
+
+/// metrics, gpu, last_seen and services of a system as stored.
+type StoredSystem = (
+    Option<Vec<u8>>,
+    Option<Vec<u8>>,
+    Option<i64>,
+    HashMap<String, Vec<u8>>,
+);
+
+#[derive(Clone)]
+pub struct SharedState {
+    /// Reconnects on its own, cloning it is cheap
+    conn: ConnectionManager,
+}
+
+pub fn system_key(system_id: i32, field: &str) -> String {
+    format!("lynx:system:{system_id}:{field}")
+}
+
+impl SharedState {
+    pub async fn connect(url: &str) -> Result<Self, SharedError> {
+        let client = redis::Client::open(url)?;
+        let conn = client.get_connection_manager().await?;
+        Ok(Self { conn })
+    }
+
+    pub async fn put_metrics(
+        &self,
+        system_id: i32,
+        metrics: &MetricsRequest,
+        seen: DateTime<Utc>,
+    ) -> Result<(), SharedError> {
+        let ttl = SYSTEM_TTL.as_secs();
+        redis::pipe()
+            .set_ex(
+                system_key(system_id, "metrics"),
+                metrics.encode_to_vec(),
+                ttl,
+            )
+            .ignore()
+            .set_ex(
+                system_key(system_id, "last_seen"),
+                seen.timestamp_millis(),
+                ttl,
+            )
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn put_gpu_metrics(
+        &self,
+        system_id: i32,
+        gpu_metrics: Vec<GpuMetrics>,
+        seen: DateTime<Utc>,
+    ) -> Result<(), SharedError> {
+        let ttl = SYSTEM_TTL.as_secs();
+        let encoded = GpuMetricsRequest {
+            gpu_metrics,
+            collected_at_ms: None,
+        }
+        .encode_to_vec();
+        redis::pipe()
+            .set_ex(system_key(system_id, "gpu"), encoded, ttl)
+            .ignore()
+            .set_ex(
+                system_key(system_id, "last_seen"),
+                seen.timestamp_millis(),
+                ttl,
+            )
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// Merges into the stored services, agents only send the ones that changed.
+    pub async fn put_services(
+        &self,
+        system_id: i32,
+        services: &[SystemService],
+    ) -> Result<(), SharedError> {
+        if services.is_empty() {
+            return Ok(());
+        }
+        let key = system_key(system_id, "services");
+        let fields: Vec<(&str, Vec<u8>)> = services
+            .iter()
+            .map(|s| (s.service_name.as_str(), s.encode_to_vec()))
+            .collect();
+        redis::pipe()
+            .hset_multiple(&key, &fields)
+            .ignore()
+            .expire(&key, SYSTEM_TTL.as_secs() as i64)
+            .ignore()
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// Drops units the agent reported as gone.
+    pub async fn remove_services(
+        &self,
+        system_id: i32,
+        names: &[String],
+    ) -> Result<(), SharedError> {
+        if names.is_empty() {
+            return Ok(());
+        }
+        redis::cmd("HDEL")
+            .arg(system_key(system_id, "services"))
+            .arg(names)
+            .query_async::<()>(&mut self.conn.clone())
+            .await?;
+        Ok(())
+    }
+
+    /// What any hub last stored for the system, None when nothing is known about it.
+    pub async fn system_snapshot(
+        &self,
+        system_id: i32,
+    ) -> Result<Option<SystemSnapshot>, SharedError> {
+        let (metrics, gpu, last_seen, services): StoredSystem = redis::pipe()
+            .get(system_key(system_id, "metrics"))
+            .get(system_key(system_id, "gpu"))
+            .get(system_key(system_id, "last_seen"))
+            .hgetall(system_key(system_id, "services"))
+            .query_async(&mut self.conn.clone())
+            .await?;
+        if metrics.is_none() && gpu.is_none() && last_seen.is_none() && services.is_empty() {
+            return Ok(None);
+        }
+
+        let metrics = match metrics {
+            Some(bytes) => Some(MetricsRequest::decode(bytes.as_slice())?),
+            None => None,
+        };
+        let gpu_metrics = match gpu {
+            Some(bytes) => GpuMetricsRequest::decode(bytes.as_slice())?.gpu_metrics,
+            None => Vec::new(),
+        };
+        let services = services
+            .into_iter()
+            .map(|(name, bytes)| Ok((name, SystemService::decode(bytes.as_slice())?)))
+            .collect::<Result<HashMap<_, _>, SharedError>>()?;
+        Ok(Some(SystemSnapshot {
+            metrics,
+            last_seen: last_seen.and_then(DateTime::from_timestamp_millis),
+            gpu_metrics,
+            services,
+        }))
+    }
+
+    /*
+     * claim_alert
+     * Starts the cooldown of an alert unless another hub already did, with a single SET NX so
+     * two hubs evaluating the same system can't both see it as free. Returns whether this hub
+     * claimed it and should notify.
+     */
+    pub async fn claim_alert(&self, key: &str, cooldown: Duration) -> Result<bool, SharedError> {
+        let claimed: Option<String> = redis::cmd("SET")
+            .arg(format!("lynx:alert:{key}"))
+            .arg(Utc::now().timestamp_millis())
+            .arg("NX")
+            .arg("PX")
+            .arg(cooldown.as_millis() as u64)
+            .query_async(&mut self.conn.clone())
+            .await?;
+        Ok(claimed.is_some())
+    }
+}
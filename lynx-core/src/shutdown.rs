@@ -0,0 +1,66 @@
+use log::{error, info};
+use tokio::sync::watch;
+
+/*
+ * Graceful shutdown
+ * On SIGTERM or Ctrl-C the hub stops accepting RPCs and HTTP requests, ends open agent streams,
+ * lets the ingest worker write what is still queued, takes a last cache snapshot and closes the
+ * database pools. main bounds all of it with SHUTDOWN_TIMEOUT_SECS.
+ */
+
+/// Handed to everything that has to stop when the hub shuts down.
+#[derive(Clone, Debug)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+pub struct ShutdownTrigger {
+    tx: watch::Sender<bool>,
+}
+
+pub fn channel() -> (ShutdownTrigger, Shutdown) {
+    let (tx, rx) = watch::channel(false);
+    (ShutdownTrigger { tx }, Shutdown { rx })
+}
+
+impl ShutdownTrigger {
+    pub fn trigger(&self) {
+        self.tx.send_replace(true);
+    }
+}
+
+impl Shutdown {
+    pub fn is_triggered(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Resolves once shutdown was triggered, right away if it already was.
+    pub async fn wait(&self) {
+        let mut rx = self.rx.clone();
+        // an error means the trigger is gone, which only happens when main is unwinding
+        let _ = rx.wait_for(|triggered| *triggered).await;
+    }
+}
+
+/// Waits for SIGTERM (what docker and systemd send) or Ctrl-C.
+pub async fn signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = term.recv() => info!("[hub] SIGTERM received, shutting down"),
+                    _ = tokio::signal::ctrl_c() => info!("[hub] Ctrl-C received, shutting down"),
+                }
+                return;
+            }
+            Err(e) => error!("[hub] Failed to listen for SIGTERM: {e}"),
+        }
+    }
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("[hub] Failed to listen for Ctrl-C: {e}");
+        std::future::pending::<()>().await;
+    }
+    info!("[hub] Ctrl-C received, shutting down");
+}
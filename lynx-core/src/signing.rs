@@ -0,0 +1,54 @@
+use openssl::base64;
+use openssl::error::ErrorStack;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+use std::fs;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SigningError {
+    #[error("Release signing key must be Ed25519")]
+    UnsupportedKey,
+    #[error("OpenSSL error: {0}")]
+    Ssl(#[from] ErrorStack),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/*
+ * ReleaseSigner
+ * Ed25519 key signing install scripts and agent binaries. Agents pin the matching public key
+ * (certs/release.pub) and refuse updates that don't verify against it.
+ *   openssl genpkey -algorithm ed25519 -out release.key
+ *   openssl pkey -in release.key -pubout -out release.pub
+ */
+pub struct ReleaseSigner {
+    key: PKey<Private>,
+}
+
+impl ReleaseSigner {
+    pub fn load(path: &Path) -> Result<Self, SigningError> {
+        let key = PKey::private_key_from_pem(&fs::read(path)?)?;
+        if key.id() != Id::ED25519 {
+            return Err(SigningError::UnsupportedKey);
+        }
+        Ok(Self { key })
+    }
+
+    pub fn public_key_pem(&self) -> Result<String, SigningError> {
+        let pem = self.key.public_key_to_pem()?;
+        Ok(String::from_utf8_lossy(&pem).into_owned())
+    }
+
+    /// Base64 encoded signature, verifiable with `openssl pkeyutl -verify -rawin`.
+    pub fn sign(&self, data: &[u8]) -> Result<String, SigningError> {
+        Ok(base64::encode_block(&self.sign_raw(data)?))
+    }
+
+    /// The 64 byte Ed25519 signature itself.
+    pub fn sign_raw(&self, data: &[u8]) -> Result<Vec<u8>, SigningError> {
+        let mut signer = Signer::new_without_digest(&self.key)?;
+        Ok(signer.sign_oneshot_to_vec(data)?)
+    }
+}
@@ -0,0 +1,201 @@
+use crate::proto::monitor::{
+    Component, CpuStats, DiskStats, KernelStats, LoadAverage, MemoryStats, MetricsRequest,
+    NetworkStats, ProcessStats,
+};
+
+/*
+ * Simulator
+ * Metric streams for the fake systems of `lynx-simulator`. Every value follows a bounded random
+ * walk around a per-system baseline so charts look like a real host, with occasional incidents
+ * that push CPU, memory and temperatures up for a few samples so alert rules fire and resolve.
+ * Seeded, the same seed gives the same stream.
+ */
+
+const MEMORY_TOTAL_KB: u64 = 16 * 1024 * 1024;
+const DISK_TOTAL_GB: i32 = 500;
+/// Chance per sample that an incident starts on a calm system
+const INCIDENT_CHANCE: f64 = 0.01;
+const INCIDENT_SAMPLES: u32 = 6;
+
+/// xorshift64*, good enough for load and without a dependency.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // a zero state would only ever produce zeros
+        Self((seed ^ 0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub fn range(&mut self, low: f64, high: f64) -> f64 {
+        low + (high - low) * self.next_f64()
+    }
+}
+
+/// A value drifting around `base` within [`min`, `max`].
+#[derive(Clone, Debug)]
+struct Walk {
+    value: f64,
+    base: f64,
+    step: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Walk {
+    fn new(base: f64, step: f64, min: f64, max: f64) -> Self {
+        Self {
+            value: base,
+            base,
+            step,
+            min,
+            max,
+        }
+    }
+
+    /// Moves one step, pulled back towards the baseline, or quickly towards `target` during an
+    /// incident.
+    fn next(&mut self, rng: &mut Rng, target: Option<f64>) -> f64 {
+        let pull = match target {
+            Some(target) => (target - self.value) * 0.5,
+            None => (self.base - self.value) * 0.2,
+        };
+        self.value =
+            (self.value + pull + rng.range(-self.step, self.step)).clamp(self.min, self.max);
+        self.value
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulatedSystem {
+    rng: Rng,
+    cpu: Walk,
+    memory: Walk,
+    load: Walk,
+    temperature: Walk,
+    network: Walk,
+    /// Used GB, grows slowly and is cleaned up once nearly full
+    disk_used: f64,
+    incident: u32,
+}
+
+impl SimulatedSystem {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let cpu = rng.range(5.0, 40.0);
+        let memory = rng.range(20.0, 60.0);
+        let disk_used = rng.range(0.2, 0.7) * DISK_TOTAL_GB as f64;
+        let network = rng.range(10_000.0, 5_000_000.0);
+        Self {
+            cpu: Walk::new(cpu, 4.0, 0.0, 100.0),
+            memory: Walk::new(memory, 1.5, 1.0, 99.0),
+            load: Walk::new(cpu / 25.0, 0.15, 0.0, 16.0),
+            temperature: Walk::new(40.0 + cpu / 4.0, 1.0, 25.0, 105.0),
+            network: Walk::new(network, network / 10.0, 0.0, network * 20.0),
+            disk_used,
+            incident: 0,
+            rng,
+        }
+    }
+
+    /// Whether the last sample was part of an incident.
+    pub fn in_incident(&self) -> bool {
+        self.incident > 0
+    }
+
+    /// The next report, stamped with `collected_at_ms`.
+    pub fn sample(&mut self, collected_at_ms: i64) -> MetricsRequest {
+        if self.incident > 0 {
+            self.incident -= 1;
+        } else if self.rng.next_f64() < INCIDENT_CHANCE {
+            self.incident = INCIDENT_SAMPLES;
+        }
+        let busy = self.incident > 0;
+        let rng = &mut self.rng;
+
+        let cpu = self.cpu.next(rng, busy.then_some(97.0));
+        let memory = self.memory.next(rng, busy.then_some(92.0));
+        let load = self.load.next(rng, busy.then_some(8.0));
+        let temperature = self.temperature.next(rng, busy.then_some(88.0));
+        let network = self.network.next(rng, None);
+
+        self.disk_used += rng.range(0.0, 0.01);
+        if self.disk_used > DISK_TOTAL_GB as f64 * 0.95 {
+            self.disk_used = DISK_TOTAL_GB as f64 * 0.4;
+        }
+
+        let used_kb = (MEMORY_TOTAL_KB as f64 * memory / 100.0) as u64;
+        let cached_kb = (MEMORY_TOTAL_KB - used_kb) / 2;
+        MetricsRequest {
+            cpu_stats: Some(CpuStats {
+                usage_percent: cpu,
+                user_percent: Some(cpu * 0.7),
+                system_percent: Some(cpu * 0.25),
+                iowait_percent: Some(cpu * 0.05),
+                ..Default::default()
+            }),
+            memory_stats: Some(MemoryStats {
+                total_kb: MEMORY_TOTAL_KB,
+                used_kb,
+                free_kb: MEMORY_TOTAL_KB - used_kb - cached_kb,
+                available_kb: Some(MEMORY_TOTAL_KB - used_kb),
+                cached_kb: Some(cached_kb),
+                swap_total_kb: Some(2 * 1024 * 1024),
+                swap_used_kb: Some(0),
+                ..Default::default()
+            }),
+            disk_stats: vec![DiskStats {
+                name: "/dev/sda1".to_string(),
+                total_space: DISK_TOTAL_GB,
+                used_space: self.disk_used as i32,
+                unit: "GB".to_string(),
+                read_bytes: rng.range(0.0, 2_000_000.0),
+                write_bytes: rng.range(0.0, 4_000_000.0),
+                mount_point: "/".to_string(),
+                read_iops: Some(rng.range(0.0, 200.0)),
+                write_iops: Some(rng.range(0.0, 400.0)),
+                ..Default::default()
+            }],
+            components: vec![Component {
+                label: "coretemp Package id 0".to_string(),
+                temperature: temperature as f32,
+            }],
+            network_stats: Some(NetworkStats {
+                r#in: network as u64,
+                out: (network * rng.range(0.3, 0.8)) as u64,
+                ..Default::default()
+            }),
+            load_average: Some(LoadAverage {
+                one_minute: load,
+                five_minutes: load * 0.9,
+                fifteen_minutes: load * 0.8,
+            }),
+            process_stats: Some(ProcessStats {
+                total: 180 + (cpu as u32),
+                threads: 600 + 4 * (cpu as u32),
+                running: 1 + (load as u32),
+                zombie: 0,
+            }),
+            kernel_stats: Some(KernelStats {
+                context_switches_per_sec: 2_000.0 + cpu * 150.0,
+                interrupts_per_sec: 1_000.0 + cpu * 60.0,
+                procs_blocked: u32::from(busy),
+                entropy_avail: Some(256),
+            }),
+            collected_at_ms: Some(collected_at_ms),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::services::ingest::MetricIngestItem;
+use async_trait::async_trait;
+use log::error;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+pub mod influx;
+
+pub use influx::InfluxSink;
+
+/*
+ * Metric sinks
+ * Secondary destinations for ingested metrics. Postgres stays the source of truth: sinks are fed
+ * after a batch was stored and are best effort, a failing sink only logs and drops its copy.
+ */
+
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("HTTP request error: {0}")]
+    Request(#[from] reqwest::Error),
+    #[error("Sink rejected the write ({status}): {body}")]
+    Rejected { status: u16, body: String },
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Event publish failed: {0}")]
+    Events(#[from] crate::events::EventError),
+}
+
+#[async_trait]
+pub trait MetricSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn write(&self, metrics: &[MetricIngestItem]) -> Result<(), SinkError>;
+}
+
+/// Hands a stored batch to every sink without holding up the ingest worker.
+pub fn dispatch(sinks: &[Arc<dyn MetricSink>], metrics: Arc<Vec<MetricIngestItem>>) {
+    if metrics.is_empty() {
+        return;
+    }
+    for sink in sinks {
+        let sink = sink.clone();
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let result = match tokio::time::timeout(SINK_TIMEOUT, sink.write(&metrics)).await {
+                Ok(result) => result,
+                Err(_) => Err(SinkError::Timeout(SINK_TIMEOUT)),
+            };
+            if let Err(e) = result {
+                error!(
+                    "[sink] {} dropped {} samples: {e}",
+                    sink.name(),
+                    metrics.len()
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,134 @@
+use super::{MetricSink, SinkError};
+use crate::services::ingest::MetricIngestItem;
+use async_trait::async_trait;
+use std::fmt::Write;
+
+#[derive(Clone, Debug)]
+pub struct InfluxConfig {
+    /// Base URL of the InfluxDB v2 server, e.g. http://influx:8086
+    pub url: String,
+    pub org: String,
+    pub bucket: String,
+    pub token: String,
+}
+
+/*
+ * InfluxSink
+ * Writes every MetricsRequest as line protocol through the InfluxDB v2 write API:
+ *   lynx_system     cpu, memory, network and load per system, sizes in bytes
+ *   lynx_disk       one point per disk, tagged with name and mount point
+ *   lynx_component  temperature per component label
+ */
+pub struct InfluxSink {
+    client: reqwest::Client,
+    write_url: String,
+    token: String,
+}
+
+/// Escapes tag keys/values and measurement names (commas, equal signs and spaces).
+fn escape_tag(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, ',' | '=' | ' ') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Renders a batch as line protocol with nanosecond timestamps.
+pub fn to_line_protocol(metrics: &[MetricIngestItem]) -> String {
+    let mut out = String::new();
+    for m in metrics {
+        let ts = m.time.timestamp_nanos_opt().unwrap_or_default();
+        let system = m.system_id;
+        let _ = writeln!(
+            out,
+            "lynx_system,system_id={system} cpu_usage={},memory_used_bytes={}i,memory_total_bytes={}i,net_in_bytes_per_sec={},net_out_bytes_per_sec={},load_one={},load_five={},load_fifteen={} {ts}",
+            m.cpu_usage,
+            m.memory_used_bytes,
+            m.memory_total_bytes,
+            m.net_in_bytes_per_sec,
+            m.net_out_bytes_per_sec,
+            m.load_one,
+            m.load_five,
+            m.load_fifteen,
+        );
+        for d in &m.disks {
+            let _ = write!(
+                out,
+                "lynx_disk,system_id={system},name={},mount_point={} total_bytes={}i,used_bytes={}i,read_bytes={},write_bytes={}",
+                escape_tag(&d.name),
+                escape_tag(&d.mount_point),
+                d.total_bytes,
+                d.used_bytes,
+                d.read_bytes,
+                d.write_bytes,
+            );
+            if let Some(iops) = d.read_iops {
+                let _ = write!(out, ",read_iops={iops}");
+            }
+            if let Some(iops) = d.write_iops {
+                let _ = write!(out, ",write_iops={iops}");
+            }
+            if let (Some(total), Some(used)) = (d.inodes_total, d.inodes_used) {
+                let _ = write!(out, ",inodes_total={total}i,inodes_used={used}i");
+            }
+            let _ = writeln!(out, " {ts}");
+        }
+        for c in &m.original.components {
+            if c.label.is_empty() || !c.temperature.is_finite() {
+                continue;
+            }
+            let _ = writeln!(
+                out,
+                "lynx_component,system_id={system},label={} temperature={} {ts}",
+                escape_tag(&c.label),
+                c.temperature,
+            );
+        }
+    }
+    out
+}
+
+impl InfluxSink {
+    pub fn new(config: &InfluxConfig) -> Result<Self, SinkError> {
+        let write_url = format!(
+            "{}/api/v2/write?org={}&bucket={}&precision=ns",
+            config.url.trim_end_matches('/'),
+            urlencoding::encode(&config.org),
+            urlencoding::encode(&config.bucket)
+        );
+        Ok(Self {
+            client: reqwest::Client::builder().build()?,
+            write_url,
+            token: config.token.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl MetricSink for InfluxSink {
+    fn name(&self) -> &'static str {
+        "influxdb"
+    }
+
+    async fn write(&self, metrics: &[MetricIngestItem]) -> Result<(), SinkError> {
+        let resp = self
+            .client
+            .post(&self.write_url)
+            .header("Authorization", format!("Token {}", self.token))
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(to_line_protocol(metrics))
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(SinkError::Rejected {
+                status: resp.status().as_u16(),
+                body: resp.text().await.unwrap_or_default(),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+use tokio::net::UdpSocket;
+
+/*
+ * Minimal SNMPv2c client
+ * Only what the hub's poller needs: GetRequest for a list of OIDs and decoding the GetResponse.
+ * Messages are BER encoded by hand:
+ *   SEQUENCE { version, community, GetRequest-PDU { request-id, error-status, error-index,
+ *              SEQUENCE OF SEQUENCE { OID, value } } }
+ */
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_IP_ADDRESS: u8 = 0x40;
+const TAG_COUNTER32: u8 = 0x41;
+const TAG_GAUGE32: u8 = 0x42;
+const TAG_TIMETICKS: u8 = 0x43;
+const TAG_COUNTER64: u8 = 0x46;
+const TAG_GET_REQUEST: u8 = 0xa0;
+const TAG_GET_RESPONSE: u8 = 0xa2;
+const SNMP_V2C: i64 = 1;
+const RETRIES: usize = 2;
+
+static REQUEST_ID: AtomicI32 = AtomicI32::new(1);
+
+#[derive(Error, Debug)]
+pub enum SnmpError {
+    #[error("Invalid OID {0}")]
+    InvalidOid(String),
+    #[error("Malformed response: {0}")]
+    Malformed(&'static str),
+    #[error("Agent returned error-status {status} at index {index}")]
+    Agent { status: i64, index: i64 },
+    #[error("No response after {0} attempts")]
+    Timeout(usize),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SnmpValue {
+    Integer(i64),
+    /// Counter32/64, Gauge32 and TimeTicks
+    Unsigned(u64),
+    Bytes(Vec<u8>),
+    /// noSuchObject, noSuchInstance, endOfMibView or NULL
+    Missing,
+}
+
+impl SnmpValue {
+    /// Numeric value, octet strings are parsed since some devices report numbers as text.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            SnmpValue::Integer(v) => Some(*v as f64),
+            SnmpValue::Unsigned(v) => Some(*v as f64),
+            SnmpValue::Bytes(b) => std::str::from_utf8(b).ok()?.trim().parse().ok(),
+            SnmpValue::Missing => None,
+        }
+    }
+}
+
+fn push_len(out: &mut Vec<u8>, len: usize) {
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|b| **b == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len() + 4);
+    out.push(tag);
+    push_len(&mut out, content.len());
+    out.extend_from_slice(content);
+    out
+}
+
+fn encode_integer(value: i64) -> Vec<u8> {
+    let bytes = value.to_be_bytes();
+    // drop leading bytes that only repeat the sign
+    let mut start = 0;
+    while start < 7 {
+        let (b, next) = (bytes[start], bytes[start + 1]);
+        if (b == 0x00 && next & 0x80 == 0) || (b == 0xff && next & 0x80 != 0) {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    tlv(TAG_INTEGER, &bytes[start..])
+}
+
+pub fn encode_oid(oid: &str) -> Result<Vec<u8>, SnmpError> {
+    let invalid = || SnmpError::InvalidOid(oid.to_string());
+    let arcs = oid
+        .trim_start_matches('.')
+        .split('.')
+        .map(|a| a.parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| invalid())?;
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) {
+        return Err(invalid());
+    }
+
+    let mut content = Vec::new();
+    for arc in std::iter::once(arcs[0] * 40 + arcs[1]).chain(arcs[2..].iter().copied()) {
+        let mut chunk = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            chunk.push(0x80 | (rest & 0x7f) as u8);
+            rest >>= 7;
+        }
+        content.extend(chunk.iter().rev());
+    }
+    Ok(tlv(TAG_OID, &content))
+}
+
+fn decode_oid(content: &[u8]) -> Result<String, SnmpError> {
+    let mut arcs = Vec::new();
+    let mut value: u64 = 0;
+    for b in content {
+        value = (value << 7) | u64::from(b & 0x7f);
+        if b & 0x80 == 0 {
+            arcs.push(value);
+            value = 0;
+        }
+    }
+    let first = *arcs.first().ok_or(SnmpError::Malformed("empty OID"))?;
+    let (a, b) = match first {
+        0..=39 => (0, first),
+        40..=79 => (1, first - 40),
+        _ => (2, first - 80),
+    };
+    let mut out = format!("{a}.{b}");
+    for arc in &arcs[1..] {
+        out.push_str(&format!(".{arc}"));
+    }
+    Ok(out)
+}
+
+/// Builds a GetRequest for `oids`.
+pub fn encode_get(request_id: i32, community: &str, oids: &[String]) -> Result<Vec<u8>, SnmpError> {
+    let mut varbinds = Vec::new();
+    for oid in oids {
+        let mut bind = encode_oid(oid)?;
+        bind.extend(tlv(TAG_NULL, &[]));
+        varbinds.extend(tlv(TAG_SEQUENCE, &bind));
+    }
+
+    let mut pdu = encode_integer(i64::from(request_id));
+    pdu.extend(encode_integer(0));
+    pdu.extend(encode_integer(0));
+    pdu.extend(tlv(TAG_SEQUENCE, &varbinds));
+
+    let mut message = encode_integer(SNMP_V2C);
+    message.extend(tlv(TAG_OCTET_STRING, community.as_bytes()));
+    message.extend(tlv(TAG_GET_REQUEST, &pdu));
+    Ok(tlv(TAG_SEQUENCE, &message))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self) -> Result<(u8, &'a [u8]), SnmpError> {
+        let truncated = || SnmpError::Malformed("truncated");
+        let (&tag, rest) = self.data.split_first().ok_or_else(truncated)?;
+        let (&first, mut rest) = rest.split_first().ok_or_else(truncated)?;
+        let len = if first & 0x80 == 0 {
+            usize::from(first)
+        } else {
+            let n = usize::from(first & 0x7f);
+            if n == 0 || n > 4 || rest.len() < n {
+                return Err(SnmpError::Malformed("bad length"));
+            }
+            let len = rest[..n]
+                .iter()
+                .fold(0usize, |acc, b| (acc << 8) | usize::from(*b));
+            rest = &rest[n..];
+            len
+        };
+        if rest.len() < len {
+            return Err(truncated());
+        }
+        self.data = &rest[len..];
+        Ok((tag, &rest[..len]))
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<&'a [u8], SnmpError> {
+        match self.read()? {
+            (t, content) if t == tag => Ok(content),
+            _ => Err(SnmpError::Malformed("unexpected tag")),
+        }
+    }
+
+    fn integer(&mut self) -> Result<i64, SnmpError> {
+        Ok(decode_signed(self.expect(TAG_INTEGER)?))
+    }
+}
+
+fn decode_signed(content: &[u8]) -> i64 {
+    let init = match content.first() {
+        Some(b) if b & 0x80 != 0 => -1i64,
+        _ => 0,
+    };
+    content
+        .iter()
+        .take(8)
+        .fold(init, |acc, b| (acc << 8) | i64::from(*b))
+}
+
+fn decode_unsigned(content: &[u8]) -> u64 {
+    content
+        .iter()
+        .fold(0u64, |acc, b| (acc << 8) | u64::from(*b))
+}
+
+/// Parses a GetResponse into OID -> value, checking it answers `request_id`.
+pub fn decode_response(
+    data: &[u8],
+    request_id: i32,
+) -> Result<HashMap<String, SnmpValue>, SnmpError> {
+    let mut message = Reader {
+        data: Reader { data }.expect(TAG_SEQUENCE)?,
+    };
+    message.integer()?;
+    message.expect(TAG_OCTET_STRING)?;
+    let mut pdu = Reader {
+        data: message.expect(TAG_GET_RESPONSE)?,
+    };
+    if pdu.integer()? != i64::from(request_id) {
+        return Err(SnmpError::Malformed("request-id mismatch"));
+    }
+    let status = pdu.integer()?;
+    let index = pdu.integer()?;
+    if status != 0 {
+        return Err(SnmpError::Agent { status, index });
+    }
+
+    let mut varbinds = Reader {
+        data: pdu.expect(TAG_SEQUENCE)?,
+    };
+    let mut values = HashMap::new();
+    while !varbinds.data.is_empty() {
+        let mut bind = Reader {
+            data: varbinds.expect(TAG_SEQUENCE)?,
+        };
+        let oid = decode_oid(bind.expect(TAG_OID)?)?;
+        let value = match bind.read()? {
+            (TAG_INTEGER, c) => SnmpValue::Integer(decode_signed(c)),
+            (TAG_COUNTER32 | TAG_GAUGE32 | TAG_TIMETICKS | TAG_COUNTER64, c) => {
+                SnmpValue::Unsigned(decode_unsigned(c))
+            }
+            (TAG_OCTET_STRING | TAG_IP_ADDRESS, c) => SnmpValue::Bytes(c.to_vec()),
+            _ => SnmpValue::Missing,
+        };
+        values.insert(oid, value);
+    }
+    Ok(values)
+}
+
+/*
+ * get
+ * Sends a GetRequest for `oids` and waits for the matching response, retrying on timeouts since
+ * SNMP runs over UDP.
+ */
+pub async fn get(
+    target: SocketAddr,
+    community: &str,
+    oids: &[String],
+    timeout: Duration,
+) -> Result<HashMap<String, SnmpValue>, SnmpError> {
+    let request_id = REQUEST_ID.fetch_add(1, Ordering::Relaxed) & i32::MAX;
+    let request = encode_get(request_id, community, oids)?;
+    let bind: SocketAddr = match target {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().expect("valid address"),
+        SocketAddr::V6(_) => "[::]:0".parse().expect("valid address"),
+    };
+    let socket = UdpSocket::bind(bind).await?;
+    socket.connect(target).await?;
+
+    let mut buf = vec![0u8; 65_535];
+    for _ in 0..RETRIES {
+        socket.send(&request).await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        // ignore stray datagrams (e.g. late answers to a previous attempt) until the deadline
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await {
+            match decode_response(&buf[..received?], request_id) {
+                Err(SnmpError::Malformed("request-id mismatch")) => continue,
+                result => return result,
+            }
+        }
+    }
+    Err(SnmpError::Timeout(RETRIES))
+}
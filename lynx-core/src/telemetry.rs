@@ -0,0 +1,355 @@
+use crate::cache::CacheStats;
+use dashmap::DashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tonic::codegen::{http, BoxFuture};
+use tower::{Layer, Service};
+
+/*
+ * Hub self-telemetry
+ * Counters and latency histograms for the hub itself, served in the Prometheus text format on
+ * `GET /metrics` of the HTTP API:
+ *   lynx_rpc_duration_seconds           per gRPC method, _count gives RPCs/sec
+ *   lynx_ingest_flush_duration_seconds  batch inserts of the ingest worker
+ *   lynx_ingest_items_total, lynx_ingest_flush_failures_total
+ *   lynx_ingest_queue_depth, lynx_ingest_queue_capacity, lynx_ingest_overflows_total
+ *   lynx_alert_evaluations_dropped_total  samples not evaluated, the alert queue was full
+ *   lynx_notifications_total, lynx_notification_failures_total  per notifier kind
+ *   lynx_cache_hits_total, lynx_cache_misses_total
+ *   lynx_rule_cache_hits_total, lynx_rule_cache_misses_total  rule loads skipped and done
+ * Everything is recorded into TELEMETRY; gauges are read when the endpoint is scraped.
+ */
+
+/// Upper bounds in seconds, shared by every latency histogram.
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+/// Distinct method labels kept, anything past it is counted as "other".
+pub const MAX_RPC_METHODS: usize = 64;
+
+lazy_static::lazy_static! {
+    pub static ref TELEMETRY: Telemetry = Telemetry::default();
+}
+
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// Observations per bucket, not cumulative, the last slot is +Inf
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let mut cumulative = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let le = LATENCY_BUCKETS
+                .get(i)
+                .map_or("+Inf".to_string(), |bound| bound.to_string());
+            let labels = join_labels(labels, &format!("le=\"{le}\""));
+            let _ = writeln!(out, "{name}_bucket{{{labels}}} {cumulative}");
+        }
+        let braces = if labels.is_empty() {
+            String::new()
+        } else {
+            format!("{{{labels}}}")
+        };
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{name}_sum{braces} {sum}");
+        let _ = writeln!(out, "{name}_count{braces} {}", self.count());
+    }
+}
+
+#[derive(Debug, Default)]
+struct NotifyCounts {
+    sent: AtomicU64,
+    failed: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    rpc: DashMap<String, Histogram>,
+    flush: Histogram,
+    flush_failures: AtomicU64,
+    ingested_items: AtomicU64,
+    ingest_overflows: AtomicU64,
+    dropped_evaluations: AtomicU64,
+    rule_cache_hits: AtomicU64,
+    rule_cache_misses: AtomicU64,
+    notifications: DashMap<&'static str, NotifyCounts>,
+}
+
+impl Telemetry {
+    pub fn record_rpc(&self, method: &str, elapsed: Duration) {
+        if let Some(histogram) = self.rpc.get(method) {
+            histogram.observe(elapsed);
+            return;
+        }
+        // unknown paths come from clients, don't let them grow the label set without bound
+        let method = if self.rpc.len() < MAX_RPC_METHODS {
+            method
+        } else {
+            "other"
+        };
+        self.rpc
+            .entry(method.to_string())
+            .or_default()
+            .observe(elapsed);
+    }
+
+    /// One flush of the ingest worker with the number of items it wrote.
+    pub fn record_flush(&self, items: usize, elapsed: Duration, ok: bool) {
+        self.flush.observe(elapsed);
+        if ok {
+            self.ingested_items
+                .fetch_add(items as u64, Ordering::Relaxed);
+        } else {
+            self.flush_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// An item that found the ingest queue full, see services::ingest::OverflowPolicy.
+    pub fn record_ingest_overflow(&self) {
+        self.ingest_overflows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A sample dropped because the evaluation queue was full, see notify::worker.
+    pub fn record_evaluation_dropped(&self) {
+        self.dropped_evaluations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A rule lookup of the notification path, see notify::rule_cache.
+    pub fn record_rule_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.rule_cache_hits
+        } else {
+            &self.rule_cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_notification(&self, kind: &'static str, ok: bool) {
+        let counts = self.notifications.entry(kind).or_default();
+        counts.sent.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            counts.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /*
+     * render
+     * Prometheus text exposition of everything recorded plus the gauges passed in by the
+     * caller. Series are sorted so consecutive scrapes are easy to diff.
+     */
+    pub fn render(&self, queue_depth: usize, queue_capacity: usize, cache: &CacheStats) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "lynx_rpc_duration_seconds",
+            "histogram",
+            "gRPC call latency until the response starts",
+        );
+        let mut methods: Vec<String> = self.rpc.iter().map(|r| r.key().clone()).collect();
+        methods.sort();
+        for method in methods {
+            if let Some(histogram) = self.rpc.get(&method) {
+                let labels = format!("method=\"{}\"", escape(&method));
+                histogram.render(&mut out, "lynx_rpc_duration_seconds", &labels);
+            }
+        }
+
+        header(
+            &mut out,
+            "lynx_ingest_flush_duration_seconds",
+            "histogram",
+            "Batch insert latency of the ingest worker",
+        );
+        self.flush
+            .render(&mut out, "lynx_ingest_flush_duration_seconds", "");
+        counter(
+            &mut out,
+            "lynx_ingest_items_total",
+            "Items written by the ingest worker",
+            self.ingested_items.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "lynx_ingest_flush_failures_total",
+            "Ingest batches that failed to insert",
+            self.flush_failures.load(Ordering::Relaxed),
+        );
+        gauge(
+            &mut out,
+            "lynx_ingest_queue_depth",
+            "Items waiting in the ingest queue",
+            queue_depth,
+        );
+        gauge(
+            &mut out,
+            "lynx_ingest_queue_capacity",
+            "Size of the ingest queue",
+            queue_capacity,
+        );
+        counter(
+            &mut out,
+            "lynx_ingest_overflows_total",
+            "Items rejected or dropped because the ingest queue was full",
+            self.ingest_overflows.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "lynx_alert_evaluations_dropped_total",
+            "Samples not evaluated for alerts because the evaluation queue was full",
+            self.dropped_evaluations.load(Ordering::Relaxed),
+        );
+
+        let mut kinds: Vec<&'static str> = self.notifications.iter().map(|r| *r.key()).collect();
+        kinds.sort();
+        header(
+            &mut out,
+            "lynx_notifications_total",
+            "counter",
+            "Notifications sent, including failed ones",
+        );
+        for kind in &kinds {
+            if let Some(counts) = self.notifications.get(kind) {
+                let sent = counts.sent.load(Ordering::Relaxed);
+                let _ = writeln!(out, "lynx_notifications_total{{kind=\"{kind}\"}} {sent}");
+            }
+        }
+        header(
+            &mut out,
+            "lynx_notification_failures_total",
+            "counter",
+            "Notifications that could not be delivered",
+        );
+        for kind in &kinds {
+            if let Some(counts) = self.notifications.get(kind) {
+                let failed = counts.failed.load(Ordering::Relaxed);
+                let _ = writeln!(
+                    out,
+                    "lynx_notification_failures_total{{kind=\"{kind}\"}} {failed}"
+                );
+            }
+        }
+
+        counter(
+            &mut out,
+            "lynx_cache_hits_total",
+            "Agent key lookups answered by the cache",
+            cache.hits,
+        );
+        counter(
+            &mut out,
+            "lynx_cache_misses_total",
+            "Agent key lookups that went to the database",
+            cache.misses,
+        );
+        counter(
+            &mut out,
+            "lynx_rule_cache_hits_total",
+            "Alert rule lookups answered by the rule cache",
+            self.rule_cache_hits.load(Ordering::Relaxed),
+        );
+        counter(
+            &mut out,
+            "lynx_rule_cache_misses_total",
+            "Alert rule lookups that went to the database",
+            self.rule_cache_misses.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    header(out, name, "counter", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: usize) {
+    header(out, name, "gauge", help);
+    let _ = writeln!(out, "{name} {value}");
+}
+
+fn join_labels(labels: &str, extra: &str) -> String {
+    if labels.is_empty() {
+        extra.to_string()
+    } else {
+        format!("{labels},{extra}")
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Tower layer timing every gRPC call into lynx_rpc_duration_seconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RpcMetricsLayer;
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcMetrics { inner }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RpcMetrics<S> {
+    inner: S,
+}
+
+impl<S, B, R> Service<http::Request<B>> for RpcMetrics<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<R>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let method = request.uri().path().to_string();
+        let started = Instant::now();
+        let response = self.inner.call(request);
+        Box::pin(async move {
+            let response = response.await;
+            TELEMETRY.record_rpc(&method, started.elapsed());
+            response
+        })
+    }
+}
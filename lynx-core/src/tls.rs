@@ -1,7 +1,19 @@
+use openssl::asn1::Asn1Time;
+use openssl::bn::{BigNum, MsbOption};
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Private};
+use openssl::sign::Signer;
+use openssl::x509::extension::{
+    BasicConstraints, ExtendedKeyUsage, KeyUsage, SubjectAlternativeName,
+};
+use openssl::x509::{X509NameBuilder, X509Req, X509};
+use serde::Serialize;
 use std::error::Error;
 use std::fs;
 use std::path::Path;
+use thiserror::Error;
 use tonic::transport::{Certificate, Identity, ServerTlsConfig};
+use tonic::{Request, Status};
 
 pub fn build_tls_config(certs_dir: &Path) -> Result<ServerTlsConfig, Box<dyn Error>> {
     if !certs_dir.exists() {
@@ -25,9 +37,259 @@ pub fn build_tls_config(certs_dir: &Path) -> Result<ServerTlsConfig, Box<dyn Err
         return Err("Server certificate or key is empty".into());
     }
     let ca_cert = fs::read_to_string(&ca_cert_path)?;
+    // Client certs are optional at the transport level so agents can reach the enrollment
+    // service before they own one. Every other service is wrapped in `require_client_cert`.
     let tls = ServerTlsConfig::new()
         .identity(Identity::from_pem(server_cert, server_key))
         .client_ca_root(Certificate::from_pem(ca_cert))
-        .client_auth_optional(false);
+        .client_auth_optional(true);
     Ok(tls)
 }
+
+#[derive(Error, Debug)]
+pub enum TlsPolicyError {
+    #[error("Invalid TLS_MIN_VERSION {0}, expected 1.2 or 1.3")]
+    InvalidVersion(String),
+    #[error("Unknown cipher suite {0}")]
+    UnknownSuite(String),
+    #[error("No cipher suite left that satisfies the TLS policy")]
+    NoSuites,
+    #[error("A TLS crypto provider was already installed")]
+    AlreadyInstalled,
+}
+
+/*
+ * install_crypto_policy
+ * tonic builds its rustls configs from the process wide crypto provider, so the minimum version
+ * and cipher suites are enforced by installing a provider that only offers the allowed suites.
+ * Without any TLS 1.2 suite a TLS 1.2 handshake can't be negotiated at all.
+ * Must run before any TLS config (or TLS database connection) is created.
+ */
+pub fn install_crypto_policy(opts: &crate::config::TlsOptions) -> Result<(), TlsPolicyError> {
+    use rustls::crypto::{ring, CryptoProvider};
+
+    let tls13_only = match opts.min_version.as_str() {
+        "1.2" => false,
+        "1.3" => true,
+        other => return Err(TlsPolicyError::InvalidVersion(other.to_string())),
+    };
+
+    let defaults = ring::default_provider().cipher_suites;
+    for name in &opts.cipher_suites {
+        if !defaults.iter().any(|s| format!("{:?}", s.suite()) == *name) {
+            return Err(TlsPolicyError::UnknownSuite(name.clone()));
+        }
+    }
+    let cipher_suites: Vec<_> = defaults
+        .into_iter()
+        .filter(|s| !tls13_only || s.version() == &rustls::version::TLS13)
+        .filter(|s| {
+            opts.cipher_suites.is_empty()
+                || opts
+                    .cipher_suites
+                    .iter()
+                    .any(|name| format!("{:?}", s.suite()) == *name)
+        })
+        .collect();
+    if cipher_suites.is_empty() {
+        return Err(TlsPolicyError::NoSuites);
+    }
+
+    log::info!(
+        "[tls] Minimum TLS {}, cipher suites: {}",
+        opts.min_version,
+        cipher_suites
+            .iter()
+            .map(|s| format!("{:?}", s.suite()))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    CryptoProvider {
+        cipher_suites,
+        ..ring::default_provider()
+    }
+    .install_default()
+    .map_err(|_| TlsPolicyError::AlreadyInstalled)
+}
+
+/// Signature shared by the interceptors guarding the agent facing services.
+pub type AuthInterceptor = fn(Request<()>) -> Result<Request<()>, Status>;
+
+/// Interceptor rejecting requests that did not present a client certificate signed by our CA.
+#[allow(clippy::result_large_err)] // signature is dictated by tonic's Interceptor
+pub fn require_client_cert(request: Request<()>) -> Result<Request<()>, Status> {
+    match request.peer_certs() {
+        Some(certs) if !certs.is_empty() => Ok(request),
+        _ => Err(Status::unauthenticated("Client certificate required")),
+    }
+}
+
+/// Lowercase hex SHA-256 of a DER encoded certificate, used to pin agents to their client cert.
+pub fn cert_fingerprint(der: &[u8]) -> String {
+    openssl::sha::sha256(der)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Same as [`cert_fingerprint`] for a PEM encoded certificate.
+pub fn pem_fingerprint(pem: &str) -> Result<String, CaError> {
+    let der = X509::from_pem(pem.as_bytes())?.to_der()?;
+    Ok(cert_fingerprint(&der))
+}
+
+/// Certificates expiring within this many days raise the built-in expiry alert.
+pub const CERT_EXPIRY_WARN_DAYS: f64 = 14.0;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct CertExpiry {
+    pub file: String,
+    pub subject: String,
+    pub not_after: String,
+    pub days_remaining: f64,
+}
+
+/*
+ * cert_expiry
+ * Parses every certificate in a PEM bundle and returns how many days each one has left.
+ * Negative values mean the certificate already expired.
+ */
+pub fn cert_expiry(file: &str, pem: &[u8]) -> Result<Vec<CertExpiry>, CaError> {
+    let now = Asn1Time::days_from_now(0)?;
+    X509::stack_from_pem(pem)?
+        .iter()
+        .map(|cert| {
+            let diff = now.diff(cert.not_after())?;
+            let subject = cert
+                .subject_name()
+                .entries()
+                .filter_map(|e| e.data().to_string().ok())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Ok(CertExpiry {
+                file: file.to_string(),
+                subject,
+                not_after: cert.not_after().to_string(),
+                days_remaining: diff.days as f64 + diff.secs as f64 / 86_400.0,
+            })
+        })
+        .collect()
+}
+
+/// Inspects the hub's own server certificate and CA, skipping files that don't exist.
+pub fn inspect_certs(certs_dir: &Path) -> Vec<CertExpiry> {
+    let mut out = Vec::new();
+    for file in ["docker.crt", "ca.crt"] {
+        let path = certs_dir.join(file);
+        let Ok(pem) = fs::read(&path) else {
+            continue;
+        };
+        match cert_expiry(file, &pem) {
+            Ok(certs) => out.extend(certs),
+            Err(e) => log::warn!("[tls] Failed to parse {:?}: {e}", path),
+        }
+    }
+    out
+}
+
+#[derive(Error, Debug)]
+pub enum CaError {
+    #[error("OpenSSL error: {0}")]
+    Ssl(#[from] openssl::error::ErrorStack),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid CSR: {0}")]
+    InvalidCsr(String),
+}
+
+/// The hub's certificate authority, used to sign agent CSRs during enrollment.
+pub struct CertificateAuthority {
+    cert: X509,
+    key: PKey<Private>,
+    cert_pem: String,
+}
+
+impl CertificateAuthority {
+    pub fn load(certs_dir: &Path) -> Result<Self, CaError> {
+        let cert_pem = fs::read_to_string(certs_dir.join("ca.crt"))?;
+        let key_pem = fs::read(certs_dir.join("ca.key"))?;
+        Ok(Self {
+            cert: X509::from_pem(cert_pem.as_bytes())?,
+            key: PKey::private_key_from_pem(&key_pem)?,
+            cert_pem,
+        })
+    }
+
+    pub fn cert_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /*
+     * sign_csr
+     * Issues a client/server certificate for `common_name` using the public key from the CSR.
+     * The subject requested in the CSR is ignored, the hub decides what identity is granted.
+     */
+    pub fn sign_csr(&self, csr_pem: &str, common_name: &str, days: u32) -> Result<String, CaError> {
+        let req = X509Req::from_pem(csr_pem.as_bytes())
+            .map_err(|e| CaError::InvalidCsr(e.to_string()))?;
+        let public_key = req.public_key()?;
+        if !req.verify(&public_key)? {
+            return Err(CaError::InvalidCsr("signature does not match".to_string()));
+        }
+
+        let mut serial = BigNum::new()?;
+        serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+        let serial = serial.to_asn1_integer()?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        let not_after = Asn1Time::days_from_now(days)?;
+
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", common_name)?;
+        let name = name.build();
+
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&name)?;
+        builder.set_issuer_name(self.cert.subject_name())?;
+        builder.set_pubkey(&public_key)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().critical().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .key_encipherment()
+                .build()?,
+        )?;
+        builder.append_extension(
+            ExtendedKeyUsage::new()
+                .client_auth()
+                .server_auth()
+                .build()?,
+        )?;
+        let san = SubjectAlternativeName::new()
+            .dns(common_name)
+            .build(&builder.x509v3_context(Some(&self.cert), None))?;
+        builder.append_extension(san)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+
+        let pem = builder.build().to_pem()?;
+        Ok(String::from_utf8_lossy(&pem).into_owned())
+    }
+
+    /*
+     * sign
+     * Signs `data` with the CA key so agents can check it against their ca.crt: SHA-256 for RSA
+     * (PKCS#1 v1.5) and P-256 keys, SHA-384 for P-384 keys, Ed25519 keys without a digest.
+     */
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, CaError> {
+        let mut signer = match self.key.id() {
+            Id::ED25519 => Signer::new_without_digest(&self.key)?,
+            Id::EC if self.key.bits() > 256 => Signer::new(MessageDigest::sha384(), &self.key)?,
+            _ => Signer::new(MessageDigest::sha256(), &self.key)?,
+        };
+        Ok(signer.sign_oneshot_to_vec(data)?)
+    }
+}
@@ -0,0 +1,233 @@
+use crate::proto::monitor::{DiskStats, NetworkStats};
+use log::info;
+use sqlx::PgPool;
+use std::collections::HashSet;
+
+/*
+ * Units
+ * Sizes are stored in bytes and throughput in bytes/s: memory, swap, disk space and network
+ * alike. Agents send memory in KB and, since total_bytes, used_bytes and in/out_bytes_per_sec,
+ * exact disk sizes and network rates; the whole GB and MB/s of older agents are scaled up on
+ * ingest. Values are converted back only where they are shown: rule components and their
+ * charts keep the units rules were written in (memory in KB, disk space in GB, network in
+ * MB/s), now with fractions instead of rounded-down integers.
+ */
+
+pub const KB: f64 = 1024.0;
+pub const MB: f64 = KB * 1024.0;
+pub const GB: f64 = MB * 1024.0;
+
+/// Held while the tables are converted, a second `lynx-core migrate` waits for it.
+const MIGRATION_LOCK_ID: i64 = 0x6c79_6e78_0001;
+
+const HAS_LEGACY_COLUMNS: &str = "SELECT EXISTS (SELECT 1 FROM information_schema.columns \
+     WHERE table_schema = current_schema() AND table_name = 'metrics' \
+     AND column_name = 'memory_used_kb')";
+
+/// Bytes per unit of the disks.unit column being dropped, `gb` when unset.
+const DISK_UNIT_BYTES: &str = "CASE lower(coalesce(unit, 'gb')) WHEN 'b' THEN 1 \
+     WHEN 'kb' THEN 1024 WHEN 'mb' THEN 1048576 WHEN 'tb' THEN 1099511627776 \
+     ELSE 1073741824 END";
+
+const LEGACY_TABLE_COLUMNS: &str = "SELECT table_name::text, column_name::text \
+     FROM information_schema.columns WHERE table_schema = current_schema() \
+     AND table_name IN ('metrics', 'metrics_hourly', 'disks', 'disks_hourly', 'systems')";
+
+const MEMORY_TRIGGER: &str = "CREATE TRIGGER metrics_memory_trigger AFTER INSERT ON metrics \
+     FOR EACH ROW \
+     WHEN ((new.memory_used_bytes IS NOT NULL) AND (new.memory_total_bytes IS NOT NULL)) \
+     EXECUTE FUNCTION public.update_memory()";
+
+const UPDATE_MEMORY_FUNCTION: &str = "CREATE OR REPLACE FUNCTION public.update_memory() \
+     RETURNS trigger LANGUAGE plpgsql AS $$ \
+     BEGIN \
+         UPDATE systems SET memory_used_bytes = NEW.memory_used_bytes, \
+             memory_total_bytes = NEW.memory_total_bytes \
+         WHERE id = NEW.system_id; \
+         RETURN NEW; \
+     END; $$";
+
+/// Renamed in metrics and metrics_hourly, with the factor their values are scaled by.
+pub const METRIC_COLUMNS: &[(&str, &str, u64)] = &[
+    ("memory_used_kb", "memory_used_bytes", 1 << 10),
+    ("memory_total_kb", "memory_total_bytes", 1 << 10),
+    ("memory_available_kb", "memory_available_bytes", 1 << 10),
+    ("swap_used_kb", "swap_used_bytes", 1 << 10),
+    ("net_in", "net_in_bytes_per_sec", 1 << 20),
+    ("net_out", "net_out_bytes_per_sec", 1 << 20),
+];
+
+/// Bytes per unit of the legacy DiskStats.unit, agents always sent `gb`.
+pub fn disk_unit_bytes(unit: &str) -> u64 {
+    match unit.trim().to_ascii_lowercase().as_str() {
+        "b" => 1,
+        "kb" => 1 << 10,
+        "mb" => 1 << 20,
+        "tb" => 1 << 40,
+        _ => 1 << 30,
+    }
+}
+
+pub fn kb_to_bytes(kb: u64) -> i64 {
+    i64::try_from(kb.saturating_mul(1024)).unwrap_or(i64::MAX)
+}
+
+/// Total and used space of a disk in bytes, exact when the agent sent them.
+pub fn disk_bytes(disk: &DiskStats) -> (i64, i64) {
+    let unit = disk_unit_bytes(&disk.unit);
+    let bytes = |exact: Option<u64>, legacy: i32| {
+        let bytes =
+            exact.unwrap_or_else(|| u64::try_from(legacy).unwrap_or(0).saturating_mul(unit));
+        i64::try_from(bytes).unwrap_or(i64::MAX)
+    };
+    (
+        bytes(disk.total_bytes, disk.total_space),
+        bytes(disk.used_bytes, disk.used_space),
+    )
+}
+
+/// Received and transmitted bytes/s, from whole MB/s for agents without the exact rates.
+pub fn network_rates(net: &NetworkStats) -> (f64, f64) {
+    (
+        net.in_bytes_per_sec.unwrap_or(net.r#in as f64 * MB),
+        net.out_bytes_per_sec.unwrap_or(net.out as f64 * MB),
+    )
+}
+
+/// The statements converting a database from before sizes were stored in bytes, in order.
+/// Only the `(table, column)`s in `existing` are converted: a database created before swap or
+/// the hourly rollups has neither, and its conversion must not fail over them.
+pub fn migration(existing: &HashSet<(String, String)>) -> Vec<String> {
+    let has =
+        |table: &str, column: &str| existing.contains(&(table.to_string(), column.to_string()));
+    let mut statements = vec![
+        "SET LOCAL statement_timeout = 0".to_string(),
+        // its WHEN clause names the memory columns, which can't change type while it exists
+        "DROP TRIGGER IF EXISTS metrics_memory_trigger ON metrics".to_string(),
+    ];
+    for (table, averaged) in [("metrics", false), ("metrics_hourly", true)] {
+        let present: Vec<_> = METRIC_COLUMNS
+            .iter()
+            .filter(|(column, _, _)| has(table, column))
+            .collect();
+        if present.is_empty() {
+            continue;
+        }
+        // hourly rows hold averages, except for the total taken as max
+        let scaled = present
+            .iter()
+            .map(|(column, _, factor)| {
+                let kind = match *column {
+                    "net_in" | "net_out" => "double precision",
+                    "memory_total_kb" => "bigint",
+                    _ if averaged => "double precision",
+                    _ => "bigint",
+                };
+                format!("ALTER COLUMN {column} TYPE {kind} USING {column}::numeric * {factor}")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        statements.push(format!("ALTER TABLE {table} {scaled}"));
+        for (from, to, _) in present {
+            statements.push(format!("ALTER TABLE {table} RENAME COLUMN {from} TO {to}"));
+        }
+    }
+    for (table, kind) in [("disks", "bigint"), ("disks_hourly", "double precision")] {
+        let present: Vec<_> = [("space", "space_bytes"), ("used", "used_bytes")]
+            .into_iter()
+            .filter(|(column, _)| has(table, column))
+            .collect();
+        // sizes without a unit were always gb
+        let unit_bytes = if has(table, "unit") {
+            DISK_UNIT_BYTES
+        } else {
+            "1073741824"
+        };
+        if !present.is_empty() {
+            let scaled = present
+                .iter()
+                .map(|(column, _)| {
+                    format!(
+                        "ALTER COLUMN {column} TYPE {kind} USING {column}::numeric * {unit_bytes}"
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            statements.push(format!("ALTER TABLE {table} {scaled}"));
+        }
+        if has(table, "unit") {
+            statements.push(format!("ALTER TABLE {table} DROP COLUMN IF EXISTS unit"));
+        }
+        for (from, to) in present {
+            statements.push(format!("ALTER TABLE {table} RENAME COLUMN {from} TO {to}"));
+        }
+    }
+    for (from, to) in [
+        ("memory_used", "memory_used_bytes"),
+        ("memory_total", "memory_total_bytes"),
+    ] {
+        if has("systems", from) {
+            statements.push(format!("UPDATE systems SET {from} = {from} * 1024"));
+            statements.push(format!("ALTER TABLE systems RENAME COLUMN {from} TO {to}"));
+        }
+    }
+    statements.push(UPDATE_MEMORY_FUNCTION.to_string());
+    if has("metrics", "memory_used_kb") && has("metrics", "memory_total_kb") {
+        statements.push(MEMORY_TRIGGER.to_string());
+    }
+    statements
+}
+
+/// `lynx-core migrate` converts the stored samples and exits.
+#[derive(Debug, PartialEq)]
+pub struct MigrateCommand;
+
+impl MigrateCommand {
+    /// `migrate` in the hub's arguments, None otherwise.
+    pub fn from_args(args: &[String]) -> Result<Option<Self>, String> {
+        match args {
+            [command] if command == "migrate" => Ok(Some(Self)),
+            [command, ..] if command == "migrate" => Err("usage: lynx-core migrate".to_string()),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Whether the database still stores sizes in the old units, checked by the hub on start.
+pub async fn needs_migration(pool: &PgPool) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(HAS_LEGACY_COLUMNS).fetch_one(pool).await
+}
+
+/*
+ * migrate
+ * Converts a database written before sizes were stored in bytes, run by `lynx-core migrate`
+ * with the hubs stopped: it rewrites metrics and disks, which takes a while on a long history,
+ * so it isn't done on start. Everything runs in one transaction, so a failed conversion leaves
+ * the old columns in place; converted databases are left alone. Precision lost to the old units
+ * can't be recovered, rows written from here on are exact.
+ */
+pub async fn migrate(pool: &PgPool) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(MIGRATION_LOCK_ID)
+        .execute(&mut *tx)
+        .await?;
+    let legacy: bool = sqlx::query_scalar(HAS_LEGACY_COLUMNS)
+        .fetch_one(&mut *tx)
+        .await?;
+    if !legacy {
+        return tx.commit().await;
+    }
+    info!("[units] Converting stored memory, disk and network samples to bytes");
+    let existing: HashSet<(String, String)> = sqlx::query_as(LEGACY_TABLE_COLUMNS)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+    for statement in migration(&existing) {
+        sqlx::query(&statement).execute(&mut *tx).await?;
+    }
+    tx.commit().await?;
+    info!("[units] Stored samples converted to bytes");
+    Ok(())
+}
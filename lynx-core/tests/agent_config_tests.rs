@@ -0,0 +1,56 @@
+use lynx_core::services::agent_config::{AgentConfigDoc, DEFAULT_PROBE_TIMEOUT_MS};
+
+fn doc(json: serde_json::Value) -> AgentConfigDoc {
+    serde_json::from_value(json).unwrap()
+}
+
+#[test]
+fn system_overrides_fleet_defaults() {
+    let fleet = doc(serde_json::json!({
+        "collector_intervals": {"MetricsCollector": 60, "SystemctlCollector": 300},
+        "features": {"gpu": false},
+        "probe_targets": [
+            {"name": "db", "address": "10.0.0.5:5432"},
+            {"name": "cache", "address": "10.0.0.6:6379"}
+        ]
+    }));
+    let system = doc(serde_json::json!({
+        "collector_intervals": {"MetricsCollector": 15},
+        "features": {"gpu": true},
+        "probe_targets": [{"name": "db", "address": "10.1.0.5:5432", "timeout_ms": 500}]
+    }));
+
+    let config = fleet.merge(system).to_proto();
+    assert_eq!(config.collector_intervals["MetricsCollector"], 15);
+    assert_eq!(config.collector_intervals["SystemctlCollector"], 300);
+    assert!(config.features["gpu"]);
+    assert_eq!(config.probe_targets.len(), 2);
+    let db = config
+        .probe_targets
+        .iter()
+        .find(|p| p.name == "db")
+        .unwrap();
+    assert_eq!((db.address.as_str(), db.timeout_ms), ("10.1.0.5:5432", 500));
+    let cache = config
+        .probe_targets
+        .iter()
+        .find(|p| p.name == "cache")
+        .unwrap();
+    assert_eq!(cache.timeout_ms, DEFAULT_PROBE_TIMEOUT_MS);
+}
+
+#[test]
+fn version_follows_contents() {
+    let a = doc(serde_json::json!({"features": {"containers": false}}));
+    let b = doc(serde_json::json!({"features": {"containers": true}}));
+    assert_eq!(a.to_proto().version, a.clone().to_proto().version);
+    assert_ne!(a.to_proto().version, b.to_proto().version);
+    assert_ne!(AgentConfigDoc::default().to_proto().version, 0);
+}
+
+#[test]
+fn drops_zero_intervals() {
+    let config =
+        doc(serde_json::json!({"collector_intervals": {"MetricsCollector": 0}})).to_proto();
+    assert!(config.collector_intervals.is_empty());
+}
@@ -0,0 +1,40 @@
+use lynx_core::proto::monitor::AgentEvent;
+use lynx_core::services::agent_events::{truncate, MAX_BACKTRACE_CHARS};
+use lynx_core::services::validation::{self, ValidationError};
+
+fn panic_event(time: i64) -> AgentEvent {
+    AgentEvent {
+        kind: "panic".to_string(),
+        time,
+        version: "0.1.0".to_string(),
+        thread: "tokio-runtime-worker".to_string(),
+        location: "src/lib/collectors.rs:120".to_string(),
+        message: "called `Option::unwrap()` on a `None` value".to_string(),
+        backtrace: String::new(),
+    }
+}
+
+#[test]
+fn agent_events_need_kind_and_time() {
+    assert!(validation::agent_event(&panic_event(1_760_000_000)).is_ok());
+    assert_eq!(
+        validation::agent_event(&AgentEvent {
+            kind: String::new(),
+            ..panic_event(1_760_000_000)
+        }),
+        Err(ValidationError::Empty("kind"))
+    );
+    assert!(matches!(
+        validation::agent_event(&panic_event(0)),
+        Err(ValidationError::OutOfRange { field: "time", .. })
+    ));
+}
+
+#[test]
+fn backtraces_are_truncated() {
+    let backtrace = "frame\n".repeat(MAX_BACKTRACE_CHARS);
+    assert_eq!(
+        truncate(&backtrace, MAX_BACKTRACE_CHARS).chars().count(),
+        MAX_BACKTRACE_CHARS
+    );
+}
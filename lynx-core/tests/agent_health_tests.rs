@@ -0,0 +1,33 @@
+use lynx_core::proto::monitor::AgentHealthEvent;
+use lynx_core::services::agent_health::{truncate_detail, MAX_DETAIL_CHARS};
+use lynx_core::services::validation::{self, ValidationError};
+
+fn event(collector: &str, kind: &str) -> AgentHealthEvent {
+    AgentHealthEvent {
+        collector: collector.to_string(),
+        kind: kind.to_string(),
+        detail: "no result after 120s".to_string(),
+        restarts: 1,
+    }
+}
+
+#[test]
+fn health_events_need_collector_and_kind() {
+    assert!(validation::health_event(&event("SystemctlCollector", "timeout")).is_ok());
+    assert_eq!(
+        validation::health_event(&event(" ", "timeout")),
+        Err(ValidationError::Empty("collector"))
+    );
+    assert_eq!(
+        validation::health_event(&event("MetricsCollector", "")),
+        Err(ValidationError::Empty("kind"))
+    );
+}
+
+#[test]
+fn long_details_are_truncated_on_char_boundaries() {
+    let detail = "é".repeat(MAX_DETAIL_CHARS + 10);
+    let truncated = truncate_detail(&detail);
+    assert_eq!(truncated.chars().count(), MAX_DETAIL_CHARS);
+    assert_eq!(truncate_detail("short"), "short");
+}
@@ -0,0 +1,143 @@
+use chrono::{TimeZone, Utc};
+use lynx_core::notify::alert::{set_portal_url, severity_color, HISTORY_ROWS};
+use lynx_core::notify::{parse_annotations, AlertMessage, Operator, Rule, TriggerValue};
+use serde_json::json;
+use std::time::Duration;
+
+fn rule() -> Rule {
+    Rule {
+        id: 7,
+        builtin: false,
+        name: "High <CPU>".to_string(),
+        enabled: true,
+        description: "CPU & load are high".to_string(),
+        severity: "Critical".to_string(),
+        conditions: Vec::new(),
+        hold: Duration::ZERO,
+        annotations: [
+            (
+                "runbook_url".to_string(),
+                "https://wiki.example.org/cpu?a=1&b=2".to_string(),
+            ),
+            ("owner".to_string(), "<platform>".to_string()),
+        ]
+        .into(),
+    }
+}
+
+fn alert() -> AlertMessage {
+    let mut alert = AlertMessage::new(&rule(), 42);
+    alert.hostname = Some("web-1".to_string());
+    alert.values = vec![
+        TriggerValue {
+            metric: "cpu.usage".to_string(),
+            value: Some(97.25),
+            operator: Operator::GreaterThan,
+            threshold: 90.0,
+        },
+        TriggerValue {
+            metric: "load.one".to_string(),
+            value: None,
+            operator: Operator::GreaterThanOrEqual,
+            threshold: 4.0,
+        },
+    ];
+    alert
+}
+
+#[test]
+fn text_lists_the_triggering_values() {
+    let text = alert().text();
+    assert!(text.contains("Alert: High <CPU>"));
+    assert!(text.contains("Severity: Critical"));
+    assert!(text.contains("System: web-1 (ID 42)"));
+    assert!(text.contains("cpu.usage = 97.25 (> 90)"));
+    assert!(text.contains("load.one = - (>= 4)"));
+}
+
+#[test]
+fn html_escapes_and_colors_by_severity() {
+    let html = alert().html(Some("chart"));
+    assert!(html.contains("High &lt;CPU&gt;"));
+    assert!(html.contains("CPU &amp; load are high"));
+    assert!(!html.contains("<CPU>"));
+    assert!(html.contains(severity_color("critical")));
+    assert!(html.contains("src=\"cid:chart\""));
+    assert!(html.contains("&gt; 90"));
+
+    assert!(!alert().html(None).contains("cid:"));
+}
+
+#[test]
+fn severity_colors() {
+    assert_eq!(severity_color("critical"), "#ef4444");
+    assert_eq!(severity_color("HIGH"), "#f97316");
+    assert_eq!(severity_color("low"), "#22c55e");
+    assert_eq!(severity_color("whatever"), "#737373");
+}
+
+#[test]
+fn history_keeps_the_newest_samples_newest_first() {
+    let samples: Vec<_> = (0..15)
+        .map(|i| {
+            (
+                Utc.timestamp_opt(1_700_000_000 + i * 60, 0).unwrap(),
+                i as f64,
+            )
+        })
+        .collect();
+    let alert = alert().with_history("cpu.usage", &samples);
+    assert_eq!(alert.history.len(), HISTORY_ROWS);
+    assert_eq!(alert.history.first().map(|s| s.1), Some(5.0));
+
+    let html = alert.html(None);
+    let newest = html.find(">14<").expect("newest sample");
+    let oldest = html.find(">5<").expect("oldest kept sample");
+    assert!(newest < oldest);
+    assert!(!html.contains(">4<"));
+}
+
+#[test]
+fn links_to_the_portal() {
+    set_portal_url("https://lynx.example.org/");
+    let alert = alert();
+    assert_eq!(
+        alert.system_link().as_deref(),
+        Some("https://lynx.example.org/systems/42")
+    );
+    assert!(alert.text().contains("https://lynx.example.org/systems/42"));
+    assert!(alert
+        .html(None)
+        .contains("https://lynx.example.org/systems/42/alerts/history"));
+}
+
+#[test]
+fn annotations_are_passed_on() {
+    let alert = alert();
+    let text = alert.text();
+    assert!(text.contains("owner: <platform>"));
+    assert!(text.contains("runbook_url: https://wiki.example.org/cpu?a=1&b=2"));
+
+    let html = alert.html(None);
+    assert!(html.contains("&lt;platform&gt;"));
+    assert!(html.contains("<a href=\"https://wiki.example.org/cpu?a=1&amp;b=2\""));
+}
+
+#[test]
+fn parses_annotations() {
+    let annotations = parse_annotations(&json!({
+        "runbook_url": "https://wiki.example.org/cpu",
+        "priority": 2,
+        "paging": true,
+        " ": "blank",
+        "nested": { "a": 1 },
+        "empty": null,
+    }));
+    assert_eq!(annotations.len(), 3);
+    assert_eq!(annotations["priority"], "2");
+    assert_eq!(annotations["paging"], "true");
+    assert_eq!(annotations["runbook_url"], "https://wiki.example.org/cpu");
+
+    assert!(parse_annotations(&json!(["runbook_url"])).is_empty());
+    assert!(parse_annotations(&json!(null)).is_empty());
+}
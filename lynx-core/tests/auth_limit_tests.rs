@@ -0,0 +1,83 @@
+use lynx_core::auth_limit::{key_id, AuthDenied, AuthLimitOptions, AuthLimiter};
+use std::net::IpAddr;
+
+fn limiter(per_ip: u32, per_key: u32, max_failures: u32) -> AuthLimiter {
+    AuthLimiter::new(AuthLimitOptions {
+        per_ip_per_min: per_ip,
+        per_key_per_min: per_key,
+        max_failures,
+        lockout_secs: 900,
+    })
+}
+
+#[test]
+fn rate_limit_applies_per_ip_and_per_key() {
+    let limiter = limiter(3, 2, 0);
+    let ip: IpAddr = "10.0.0.1".parse().unwrap();
+    let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+    assert!(limiter.check(Some(ip), "a").is_ok());
+    assert!(limiter.check(Some(ip), "a").is_ok());
+    assert_eq!(
+        limiter.check(Some(ip), "a"),
+        Err(AuthDenied::RateLimited {
+            subject: "key a".to_string(),
+            first: true,
+        })
+    );
+    // the IP budget is shared between keys
+    assert_eq!(
+        limiter.check(Some(ip), "b"),
+        Err(AuthDenied::RateLimited {
+            subject: "ip 10.0.0.1".to_string(),
+            first: true,
+        })
+    );
+    assert!(matches!(
+        limiter.check(Some(ip), "c"),
+        Err(AuthDenied::RateLimited { first: false, .. })
+    ));
+    assert!(limiter.check(Some(other), "b").is_ok());
+}
+
+#[test]
+fn repeated_failures_lock_out_ip_and_key() {
+    let limiter = limiter(0, 0, 3);
+    let ip: IpAddr = "192.0.2.7".parse().unwrap();
+
+    assert!(limiter.record_failure(Some(ip), "guess1").is_empty());
+    assert!(limiter.record_failure(Some(ip), "guess2").is_empty());
+    assert_eq!(
+        limiter.record_failure(Some(ip), "guess3"),
+        vec!["ip 192.0.2.7".to_string()]
+    );
+
+    // a locked out IP is refused even with a key that never failed
+    assert!(matches!(
+        limiter.check(Some(ip), "valid"),
+        Err(AuthDenied::LockedOut { ref subject, .. }) if subject == "ip 192.0.2.7"
+    ));
+    assert!(limiter
+        .check(Some("192.0.2.8".parse().unwrap()), "valid")
+        .is_ok());
+}
+
+#[test]
+fn success_clears_key_failures() {
+    let limiter = limiter(0, 0, 2);
+
+    assert!(limiter.record_failure(None, "k").is_empty());
+    limiter.record_success("k");
+    assert!(limiter.record_failure(None, "k").is_empty());
+    assert_eq!(limiter.record_failure(None, "k"), vec!["key k".to_string()]);
+    assert!(limiter.check(None, "k").is_err());
+}
+
+#[test]
+fn key_id_does_not_leak_the_key() {
+    let id = key_id("super-secret-agent-key");
+    assert_eq!(id.len(), 16);
+    assert!(!id.contains("secret"));
+    assert_eq!(id, key_id("super-secret-agent-key"));
+    assert_ne!(id, key_id("super-secret-agent-key2"));
+}
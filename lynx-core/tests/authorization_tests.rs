@@ -0,0 +1,64 @@
+use lynx_core::auth_limit::key_id;
+use lynx_core::services::authorization::{
+    claims, token, valid_action, AuthorizationError, AuthorizationRequest, DEFAULT_TTL, MAX_TTL,
+};
+use std::time::Duration;
+
+fn request(action: &str) -> AuthorizationRequest {
+    AuthorizationRequest {
+        action: action.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn actions_are_message_types() {
+    assert!(valid_action("delete"));
+    assert!(valid_action("stopservice"));
+    assert!(!valid_action(""));
+    assert!(!valid_action("Delete"));
+    assert!(!valid_action("delete all"));
+}
+
+#[test]
+fn ttl_is_clamped() {
+    assert_eq!(request("delete").ttl(), DEFAULT_TTL);
+    let ttl = |secs| {
+        AuthorizationRequest {
+            ttl_secs: Some(secs),
+            ..request("delete")
+        }
+        .ttl()
+    };
+    assert_eq!(ttl(0), Duration::from_secs(1));
+    assert_eq!(ttl(30), Duration::from_secs(30));
+    assert_eq!(ttl(86_400), MAX_TTL);
+}
+
+#[test]
+fn claims_bind_action_target_agent_and_expiry() {
+    let scoped = AuthorizationRequest {
+        target: Some("nginx".to_string()),
+        ttl_secs: Some(120),
+        ..request("stopservice")
+    };
+    let granted = claims(42, "agent-key", &scoped, 1_760_000_000).unwrap();
+    assert_eq!(granted.action, "stopservice");
+    assert_eq!(granted.target.as_deref(), Some("nginx"));
+    assert_eq!(granted.system, 42);
+    assert_eq!(granted.agent, key_id("agent-key"));
+    assert_eq!(granted.expires, 1_760_000_120);
+    let again = claims(42, "agent-key", &scoped, 1_760_000_000).unwrap();
+    assert_ne!(granted.nonce, again.nonce);
+
+    assert!(matches!(
+        claims(42, "agent-key", &request("rm -rf"), 1_760_000_000),
+        Err(AuthorizationError::InvalidAction(_))
+    ));
+}
+
+#[test]
+fn tokens_carry_payload_and_signature() {
+    let token = token(b"{}", &[1, 2, 3]);
+    assert_eq!(token, "e30=.AQID");
+}
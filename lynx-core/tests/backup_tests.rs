@@ -0,0 +1,119 @@
+use lynx_core::backup::{self, BackupError, Command, DEFAULT_METRICS_DAYS, TABLES};
+use std::path::PathBuf;
+
+fn args(list: &[&str]) -> Vec<String> {
+    list.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn no_subcommand_runs_the_hub() {
+    assert_eq!(Command::from_args(&[]).unwrap(), None);
+    assert_eq!(Command::from_args(&args(&["--insecure"])).unwrap(), None);
+}
+
+#[test]
+fn backup_defaults_and_metrics_days() {
+    assert_eq!(
+        Command::from_args(&args(&["backup", "hub.backup"])).unwrap(),
+        Some(Command::Backup {
+            path: PathBuf::from("hub.backup"),
+            metrics_days: DEFAULT_METRICS_DAYS,
+        })
+    );
+    assert_eq!(
+        Command::from_args(&args(&["backup", "--metrics-days", "0", "hub.backup"])).unwrap(),
+        Some(Command::Backup {
+            path: PathBuf::from("hub.backup"),
+            metrics_days: 0,
+        })
+    );
+}
+
+#[test]
+fn invalid_usage_is_rejected() {
+    for bad in [
+        &["backup"][..],
+        &["backup", "a", "b"],
+        &["backup", "a", "--metrics-days", "-1"],
+        &["backup", "a", "--metrics-days"],
+        &["restore"],
+        &["restore", "a", "b"],
+    ] {
+        assert!(
+            matches!(Command::from_args(&args(bad)), Err(BackupError::Usage(_))),
+            "{bad:?}"
+        );
+    }
+}
+
+#[test]
+fn restore_takes_one_file() {
+    assert_eq!(
+        Command::from_args(&args(&["restore", "hub.backup"])).unwrap(),
+        Some(Command::Restore {
+            path: PathBuf::from("hub.backup"),
+        })
+    );
+}
+
+#[test]
+fn header_is_checked() {
+    let header =
+        r#"{"format":"lynx-backup","version":1,"created":"2025-01-01T00:00:00Z","metrics_days":7}"#;
+    assert_eq!(backup::parse_header(header).unwrap().metrics_days, 7);
+
+    let newer = header.replace("\"version\":1", "\"version\":99");
+    assert!(backup::parse_header(&newer).is_err());
+    let other = header.replace("lynx-backup", "pg_dump");
+    assert!(backup::parse_header(&other).is_err());
+    assert!(backup::parse_header("not json").is_err());
+}
+
+#[test]
+fn parents_come_before_their_rows() {
+    let position = |name: &str| TABLES.iter().position(|t| t.name == name).unwrap();
+    assert!(position("users") < position("systems"));
+    assert!(position("systems") < position("metrics"));
+    assert!(position("alert_rules") < position("alert_history"));
+    assert!(position("notifiers") < position("alert_notifiers"));
+    // windowed tables only come after every configuration table
+    let first_series = TABLES.iter().position(|t| t.time_column.is_some()).unwrap();
+    assert!(TABLES[first_series..]
+        .iter()
+        .all(|t| t.time_column.is_some()));
+}
+
+#[test]
+fn version_1_rows_are_upgraded_to_bytes() {
+    let mut metrics = serde_json::json!({
+        "system_id": 1,
+        "memory_used_kb": 1024,
+        "memory_total_kb": 4096,
+        "swap_used_kb": null,
+        "net_in": 2,
+        "cpu_usage": 12.5,
+    });
+    backup::upgrade_row(1, "metrics", &mut metrics);
+    assert_eq!(metrics["memory_used_bytes"], 1_048_576);
+    assert_eq!(metrics["memory_total_bytes"], 4_194_304);
+    assert!(metrics["swap_used_bytes"].is_null());
+    assert_eq!(metrics["net_in_bytes_per_sec"], 2_097_152);
+    assert_eq!(metrics["cpu_usage"], 12.5);
+    assert!(metrics.get("memory_used_kb").is_none());
+
+    let mut disk = serde_json::json!({"name": "sda1", "space": 100, "used": 40, "unit": "mb"});
+    backup::upgrade_row(1, "disks", &mut disk);
+    assert_eq!(
+        disk,
+        serde_json::json!({"name": "sda1", "space_bytes": 104_857_600, "used_bytes": 41_943_040})
+    );
+
+    let mut system = serde_json::json!({"id": 1, "memory_used": 10, "memory_total": 20});
+    backup::upgrade_row(1, "systems", &mut system);
+    assert_eq!(system["memory_total_bytes"], 20_480);
+
+    // current archives are restored as they are
+    let mut current = serde_json::json!({"memory_used_bytes": 10});
+    backup::upgrade_row(backup::ARCHIVE_VERSION, "metrics", &mut current);
+    assert_eq!(current, serde_json::json!({"memory_used_bytes": 10}));
+}
@@ -14,6 +14,7 @@ async fn cache_snapshot_persists_services_and_logs() {
             state: "running".into(),
             cpu: "0%".into(),
             memory: "0".into(),
+            ..Default::default()
         });
     }
     // Insert logs
@@ -44,3 +45,58 @@ async fn cache_log_trim_works() {
     }
     assert_eq!(cache.log_count().await, 5, "should retain only max_logs");
 }
+
+#[tokio::test]
+async fn cache_stats_track_hits_and_misses() {
+    let cache = Cache::new(10, 10);
+    cache.put_system_id("agent-key".into(), 7);
+
+    assert_eq!(cache.get_system_id("agent-key"), Some(7));
+    assert_eq!(cache.get_system_id("unknown"), None);
+    assert!(cache.get_service("missing").is_none());
+
+    let stats = cache.stats().await;
+    assert_eq!(stats.system_ids, 1);
+    assert_eq!(stats.hits, 1);
+    assert_eq!(stats.misses, 2);
+    assert!(stats.approx_memory_bytes > 0);
+
+    // clones share counters and maps
+    let clone = cache.clone();
+    clone.get_system_id("agent-key");
+    assert_eq!(cache.stats().await.hits, 2);
+}
+
+#[tokio::test]
+async fn load_system_without_shared_state_uses_local_view() {
+    let cache = Cache::new(10, 10);
+    assert!(cache.load_system(3).await.is_none());
+
+    cache.record_services(
+        3,
+        &[SystemService {
+            service_name: "nginx.service".into(),
+            state: "Active".into(),
+            ..Default::default()
+        }],
+    );
+    let snapshot = cache.load_system(3).await.expect("system cached");
+    assert!(snapshot.services.contains_key("nginx.service"));
+    assert!(snapshot.metrics.is_none());
+}
+
+#[tokio::test]
+async fn removed_services_leave_the_system_view() {
+    let cache = Cache::new(10, 10);
+    let service = |name: &str| SystemService {
+        service_name: name.into(),
+        state: "Active".into(),
+        ..Default::default()
+    };
+    cache.record_services(3, &[service("nginx.service"), service("old.service")]);
+    cache.remove_services(3, &["old.service".to_string()]);
+
+    let snapshot = cache.load_system(3).await.expect("system cached");
+    assert!(snapshot.services.contains_key("nginx.service"));
+    assert!(!snapshot.services.contains_key("old.service"));
+}
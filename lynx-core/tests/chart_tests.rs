@@ -0,0 +1,33 @@
+use lynx_core::notify::chart::{render_sparkline, series_expression};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[test]
+fn renders_a_png_with_the_threshold_in_view() {
+    let values = [12.0, 35.5, 80.0, 97.2, 91.0];
+    let png = render_sparkline(&values, Some(90.0)).expect("chart");
+    assert!(png.starts_with(PNG_SIGNATURE));
+
+    // flat series and thresholds far away still draw
+    assert!(render_sparkline(&[5.0, 5.0], Some(1000.0)).is_some());
+    assert!(render_sparkline(&[5.0, 5.0], None).is_some());
+    // rounding noise on large byte counts counts as flat too
+    let bytes = 8.0 * 1024.0 * 1024.0 * 1024.0;
+    assert!(render_sparkline(&[bytes, bytes + 1e-6], None).is_some());
+}
+
+#[test]
+fn needs_two_finite_samples() {
+    assert!(render_sparkline(&[], Some(1.0)).is_none());
+    assert!(render_sparkline(&[3.0], None).is_none());
+    assert!(render_sparkline(&[3.0, f64::NAN], None).is_none());
+}
+
+#[test]
+fn only_stored_metrics_have_a_series() {
+    assert_eq!(series_expression("cpu", "usage"), Some("cpu_usage"));
+    assert_eq!(series_expression("load", "five"), Some("load_five"));
+    assert!(series_expression("memory", "usage").is_some());
+    assert_eq!(series_expression("disk", "usage"), None);
+    assert_eq!(series_expression("tls", "expiry_days"), None);
+}
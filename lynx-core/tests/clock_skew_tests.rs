@@ -0,0 +1,51 @@
+use chrono::{DateTime, Duration, Utc};
+use lynx_core::notify::{AgentComponent, MetricComponent};
+use lynx_core::services::clock_skew::{self, SkewQuery, DEFAULT_THRESHOLD_SECS};
+
+fn at(ms: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(ms).unwrap()
+}
+
+#[test]
+fn measures_agent_clock_against_arrival() {
+    let received = at(1_700_000_000_000);
+    let ahead = received + Duration::seconds(42);
+    let behind = received - Duration::milliseconds(1500);
+    assert_eq!(
+        clock_skew::measure(Some(ahead.timestamp_millis()), received),
+        Some(42_000)
+    );
+    assert_eq!(
+        clock_skew::measure(Some(behind.timestamp_millis()), received),
+        Some(-1500)
+    );
+    // pollers and older agents don't stamp their reports
+    assert_eq!(clock_skew::measure(None, received), None);
+    // a garbage stamp saturates instead of overflowing
+    assert_eq!(
+        clock_skew::measure(Some(i64::MIN), received),
+        Some(i64::MIN)
+    );
+}
+
+#[tokio::test]
+async fn rules_see_skew_either_way() {
+    let ahead = AgentComponent::reporting(45_500);
+    assert_eq!(ahead.get_metric("clock_skew_seconds").await.unwrap(), 45.5);
+    assert_eq!(ahead.get_metric("offline_minutes").await.unwrap(), 0.0);
+    let behind = AgentComponent::reporting(-45_500);
+    assert_eq!(behind.get_metric("clock_skew_seconds").await.unwrap(), 45.5);
+
+    // the offline monitor has nothing to measure
+    let offline = AgentComponent::new(12.0);
+    assert!(offline.get_metric("clock_skew_seconds").await.is_err());
+    assert!(offline.available_metrics().contains(&"clock_skew_seconds"));
+}
+
+#[test]
+fn threshold_defaults() {
+    let query: SkewQuery = serde_json::from_str("{}").unwrap();
+    assert_eq!(query.threshold_secs, DEFAULT_THRESHOLD_SECS);
+    let query: SkewQuery = serde_json::from_str(r#"{"threshold_secs": 5}"#).unwrap();
+    assert_eq!(query.threshold_secs, 5);
+}
@@ -0,0 +1,87 @@
+use lynx_core::proto::monitor::{CommandAudit, TranscriptLine};
+use lynx_core::services::command_audit::{
+    transcript_entries, AuditQuery, DEFAULT_LIMIT, MAX_LIMIT, MAX_TRANSCRIPT_LINES,
+};
+use lynx_core::services::validation::{self, ValidationError};
+
+fn execute(time: i64) -> CommandAudit {
+    CommandAudit {
+        time,
+        actor: "10.0.0.5:51234".to_string(),
+        action: "execute".to_string(),
+        target: "uptime".to_string(),
+        args: vec!["-p".to_string()],
+        exit_code: Some(0),
+        error: String::new(),
+        transcript: Vec::new(),
+    }
+}
+
+#[test]
+fn entries_need_action_actor_and_time() {
+    assert!(validation::command_audit(&execute(1_760_000_000)).is_ok());
+    assert_eq!(
+        validation::command_audit(&CommandAudit {
+            action: " ".to_string(),
+            ..execute(1_760_000_000)
+        }),
+        Err(ValidationError::Empty("action"))
+    );
+    assert_eq!(
+        validation::command_audit(&CommandAudit {
+            actor: String::new(),
+            ..execute(1_760_000_000)
+        }),
+        Err(ValidationError::Empty("actor"))
+    );
+    assert!(matches!(
+        validation::command_audit(&execute(0)),
+        Err(ValidationError::OutOfRange { field: "time", .. })
+    ));
+}
+
+#[test]
+fn limit_is_clamped() {
+    assert_eq!(AuditQuery::default().limit(), DEFAULT_LIMIT);
+    let query = |limit| AuditQuery {
+        limit: Some(limit),
+        ..Default::default()
+    };
+    assert_eq!(query(0).limit(), 1);
+    assert_eq!(query(20).limit(), 20);
+    assert_eq!(query(100_000).limit(), MAX_LIMIT);
+}
+
+fn line(time_ms: i64, stream: &str, text: &str) -> TranscriptLine {
+    TranscriptLine {
+        time_ms,
+        stream: stream.to_string(),
+        text: text.to_string(),
+    }
+}
+
+#[test]
+fn transcripts_keep_times_and_streams() {
+    let (entries, truncated) = transcript_entries(&[
+        line(0, "input", "uptime -p"),
+        line(1_760_000_000_250, "stdout", "up 3 days"),
+    ]);
+    assert!(!truncated);
+    assert_eq!(entries.len(), 2);
+    // input without a timestamp
+    assert_eq!(entries[0].time, None);
+    assert_eq!(entries[0].stream, "input");
+    assert_eq!(
+        entries[1].time.map(|t| t.timestamp_millis()),
+        Some(1_760_000_000_250)
+    );
+    assert_eq!(entries[1].text, "up 3 days");
+}
+
+#[test]
+fn long_transcripts_are_truncated() {
+    let lines = vec![line(1_760_000_000_000, "stdout", "y"); MAX_TRANSCRIPT_LINES + 1];
+    let (entries, truncated) = transcript_entries(&lines);
+    assert!(truncated);
+    assert_eq!(entries.len(), MAX_TRANSCRIPT_LINES);
+}
@@ -0,0 +1,92 @@
+use lynx_core::services::custom_metrics::{
+    validate, CustomMetric, CustomMetricError, CustomMetricsRequest, MAX_METRICS_PER_REQUEST,
+};
+use std::collections::BTreeMap;
+
+fn metric(name: &str, value: f64) -> CustomMetric {
+    CustomMetric {
+        name: name.to_string(),
+        value,
+        labels: BTreeMap::new(),
+    }
+}
+
+#[test]
+fn stamps_valid_metrics_for_the_system() {
+    let mut queue = metric("queue_depth", 12.0);
+    queue.labels.insert("queue".to_string(), "mail".to_string());
+    let items = validate(
+        7,
+        CustomMetricsRequest {
+            metrics: vec![queue, metric("backup_age_hours", 3.5)],
+        },
+    )
+    .unwrap();
+
+    assert_eq!(items.len(), 2);
+    assert!(items.iter().all(|i| i.system_id == 7));
+    assert_eq!(items[0].time, items[1].time);
+    assert_eq!(items[0].labels, serde_json::json!({"queue": "mail"}));
+    assert_eq!(items[1].labels, serde_json::json!({}));
+}
+
+#[test]
+fn rejects_names_the_rule_syntax_cannot_address() {
+    for name in ["", "queue.depth", "queue depth", &"x".repeat(65)] {
+        let err = validate(
+            1,
+            CustomMetricsRequest {
+                metrics: vec![metric(name, 1.0)],
+            },
+        )
+        .unwrap_err();
+        assert!(matches!(err, CustomMetricError::InvalidName(_)), "{name}");
+    }
+}
+
+#[test]
+fn rejects_empty_oversized_and_non_finite_batches() {
+    assert!(matches!(
+        validate(1, CustomMetricsRequest { metrics: vec![] }),
+        Err(CustomMetricError::Empty)
+    ));
+    assert!(matches!(
+        validate(
+            1,
+            CustomMetricsRequest {
+                metrics: vec![metric("a", 1.0); MAX_METRICS_PER_REQUEST + 1],
+            }
+        ),
+        Err(CustomMetricError::TooMany)
+    ));
+    assert!(matches!(
+        validate(
+            1,
+            CustomMetricsRequest {
+                metrics: vec![metric("ok", 1.0), metric("bad", f64::NAN)],
+            }
+        ),
+        Err(CustomMetricError::InvalidValue(name)) if name == "bad"
+    ));
+}
+
+#[test]
+fn agent_script_metrics_are_checked_like_posted_ones() {
+    let derived = lynx_core::proto::monitor::CustomMetric {
+        name: "health_score".to_string(),
+        value: 0.75,
+        labels: [("tier".to_string(), "gold".to_string())].into(),
+    };
+    let metric = CustomMetric::from(derived);
+    assert_eq!(metric.labels.get("tier").map(String::as_str), Some("gold"));
+
+    let items = validate(
+        3,
+        CustomMetricsRequest {
+            metrics: vec![metric],
+        },
+    )
+    .unwrap();
+    assert_eq!(items[0].name, "health_score");
+    assert_eq!(items[0].value, 0.75);
+}
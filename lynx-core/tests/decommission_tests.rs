@@ -0,0 +1,105 @@
+use chrono::{TimeZone, Utc};
+use lynx_core::services::decommission::{
+    archive_path, ArchiveHeader, ARCHIVED_SERIES, ARCHIVE_FORMAT, ARCHIVE_VERSION,
+};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn archive_is_named_after_system_and_time() {
+    let created = Utc.with_ymd_and_hms(2025, 3, 4, 5, 6, 7).unwrap();
+    assert_eq!(
+        archive_path(Path::new("archive"), 42, created),
+        PathBuf::from("archive/system-42-20250304T050607Z.jsonl")
+    );
+}
+
+#[test]
+fn systems_row_is_kept() {
+    // alert history still references the system after its samples are archived
+    assert!(ARCHIVED_SERIES.iter().all(|(table, _)| *table != "systems"));
+    assert!(ARCHIVED_SERIES.contains(&("disks", "system = $1")));
+}
+
+/// Tables naming a system that aren't samples, they stay with the systems row.
+const KEPT_WITH_SYSTEM: &[&str] = &[
+    "services",
+    "gpus",
+    "containers",
+    "agent_config",
+    "auth_events",
+    "rejected_reports",
+    "agent_health_events",
+    "agent_events",
+    "snmp_devices",
+    "prometheus_targets",
+    "alert_systems",
+    "alert_history",
+    "notification_deliveries",
+    "command_audit",
+];
+
+/// Tables of the schema with a column naming a system, directly or through a GPU or container.
+fn tables_naming_a_system() -> Vec<String> {
+    let schema = std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../deploy/db-data/01_schema.sql"),
+    )
+    .unwrap();
+    let mut tables = Vec::new();
+    let mut current: Option<String> = None;
+    for line in schema.lines().map(str::trim) {
+        if let Some(name) = line.strip_prefix("CREATE TABLE ") {
+            current = Some(name.trim_matches('"').to_string());
+        } else if line.starts_with(");") {
+            current = None;
+        } else if let Some(table) = &current {
+            let column = line.split_whitespace().next().unwrap_or("");
+            let names_system = matches!(
+                column,
+                "\"system\"" | "\"system_id\"" | "\"gpu_id\"" | "\"container_id\""
+            );
+            if names_system && !tables.contains(table) {
+                tables.push(table.clone());
+            }
+        }
+    }
+    tables
+}
+
+#[test]
+fn every_system_table_is_archived_or_kept() {
+    let archived: HashSet<&str> = ARCHIVED_SERIES.iter().map(|(table, _)| *table).collect();
+    let tables = tables_naming_a_system();
+    assert!(tables.iter().any(|t| t == "gpu_metrics"));
+    for table in &tables {
+        assert!(
+            archived.contains(table.as_str()) || KEPT_WITH_SYSTEM.contains(&table.as_str()),
+            "{table} names a system but is neither archived nor kept on decommission"
+        );
+    }
+    for table in &archived {
+        assert!(
+            tables.iter().any(|t| t == table),
+            "{table} is not in the schema"
+        );
+    }
+}
+
+#[test]
+fn header_round_trips() {
+    let header = ArchiveHeader {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        created: Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+        system_id: 7,
+    };
+    let json = serde_json::to_string(&header).unwrap();
+    assert_eq!(
+        json,
+        r#"{"format":"lynx-system-archive","version":1,"created":"2025-01-01T00:00:00Z","system_id":7}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<ArchiveHeader>(&json).unwrap(),
+        header
+    );
+}
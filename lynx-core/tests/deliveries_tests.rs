@@ -0,0 +1,24 @@
+use lynx_core::notify::deliveries::{DeliveryQuery, DEFAULT_LIMIT, MAX_LIMIT};
+use lynx_core::notify::NotificationServiceType;
+
+#[test]
+fn limit_is_clamped() {
+    assert_eq!(DeliveryQuery::default().limit(), DEFAULT_LIMIT);
+    let query = |limit| DeliveryQuery {
+        failed: false,
+        limit: Some(limit),
+    };
+    assert_eq!(query(0).limit(), 1);
+    assert_eq!(query(20).limit(), 20);
+    assert_eq!(query(100_000).limit(), MAX_LIMIT);
+}
+
+#[test]
+fn unsupported_notifier_errors_leave_the_url_out() {
+    let Err(e) = NotificationServiceType::from_url("teams://hooks.example.org/secret-token") else {
+        panic!("teams is not a notifier");
+    };
+    let error = e.redacted();
+    assert!(error.contains("teams"));
+    assert!(!error.contains("secret-token"));
+}
@@ -0,0 +1,34 @@
+use lynx_core::config::GrpcBind;
+use lynx_core::discovery;
+use std::path::PathBuf;
+
+#[test]
+fn advertises_reachable_ports_only() {
+    let tcp = |addr: &str| GrpcBind::Tcp(addr.parse().unwrap());
+    assert_eq!(
+        discovery::advertised_port(&tcp("0.0.0.0:50051")),
+        Some(50051)
+    );
+    assert_eq!(
+        discovery::advertised_port(&tcp("192.168.1.10:6000")),
+        Some(6000)
+    );
+    assert_eq!(discovery::advertised_port(&tcp("127.0.0.1:50051")), None);
+    assert_eq!(
+        discovery::advertised_port(&GrpcBind::Unix(PathBuf::from("/run/lynx.sock"))),
+        None
+    );
+}
+
+#[test]
+fn tls_name_is_the_server_url_host() {
+    assert_eq!(
+        discovery::tls_name_from_url("https://hub.lan:50051").as_deref(),
+        Some("hub.lan")
+    );
+    assert_eq!(
+        discovery::tls_name_from_url("https://10.0.0.2").as_deref(),
+        Some("10.0.0.2")
+    );
+    assert_eq!(discovery::tls_name_from_url("not a url"), None);
+}
@@ -0,0 +1,97 @@
+use chrono::{TimeZone, Utc};
+use lynx_core::events::kafka_rest::records_body;
+use lynx_core::events::nats::pub_frame;
+use lynx_core::events::{alert_event, Event, EventPublisher, NatsPublisher};
+use lynx_core::notify::Rule;
+use serde_json::json;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+fn rule() -> Rule {
+    Rule {
+        id: 3,
+        builtin: false,
+        name: "High CPU".to_string(),
+        enabled: true,
+        description: "cpu.usage > 90".to_string(),
+        severity: "critical".to_string(),
+        conditions: vec![],
+        hold: Duration::ZERO,
+        annotations: [("owner".to_string(), "platform".to_string())].into(),
+    }
+}
+
+#[test]
+fn alert_event_is_keyed_by_system() {
+    let time = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+    let event = alert_event(&rule(), 7, time);
+    assert_eq!(event.key, "7");
+    assert_eq!(event.payload["rule_id"], json!(3));
+    assert_eq!(event.payload["severity"], json!("critical"));
+    assert_eq!(event.payload["time"], json!("2023-11-14T22:13:20+00:00"));
+    assert_eq!(event.payload["annotations"], json!({ "owner": "platform" }));
+
+    let builtin = Rule {
+        builtin: true,
+        ..rule()
+    };
+    assert!(alert_event(&builtin, 7, time).payload["rule_id"].is_null());
+}
+
+#[test]
+fn renders_broker_frames() {
+    assert_eq!(
+        pub_frame("lynx.alerts", b"{}").unwrap(),
+        b"PUB lynx.alerts 2\r\n{}\r\n".to_vec()
+    );
+    assert!(pub_frame("lynx alerts", b"{}").is_err());
+
+    let body = records_body(&[Event {
+        key: "7".to_string(),
+        payload: json!({"type": "alert"}),
+    }]);
+    assert_eq!(
+        body,
+        json!({"records": [{"key": "7", "value": {"type": "alert"}}]})
+    );
+}
+
+#[tokio::test]
+async fn nats_publisher_connects_and_publishes() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let server = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket
+            .write_all(b"INFO {\"server_id\":\"test\"}\r\n")
+            .await
+            .unwrap();
+        let mut reader = BufReader::new(socket);
+        let mut connect = String::new();
+        reader.read_line(&mut connect).await.unwrap();
+        let mut publish = String::new();
+        reader.read_line(&mut publish).await.unwrap();
+        let size: usize = publish.trim().rsplit(' ').next().unwrap().parse().unwrap();
+        let mut payload = vec![0; size];
+        reader.read_exact(&mut payload).await.unwrap();
+        (connect, publish, payload)
+    });
+
+    let publisher = NatsPublisher::new(&format!("nats://s3cret@127.0.0.1:{port}")).unwrap();
+    publisher
+        .publish(
+            "lynx.alerts",
+            &[Event {
+                key: "1".to_string(),
+                payload: json!({"a": 1}),
+            }],
+        )
+        .await
+        .unwrap();
+
+    let (connect, publish, payload) = server.await.unwrap();
+    assert!(connect.starts_with("CONNECT "));
+    assert!(connect.contains("\"auth_token\":\"s3cret\""));
+    assert_eq!(publish, "PUB lynx.alerts 7\r\n");
+    assert_eq!(payload, b"{\"a\":1}");
+}
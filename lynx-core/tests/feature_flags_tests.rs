@@ -0,0 +1,100 @@
+use lynx_core::services::agent_config::AgentConfigDoc;
+use lynx_core::services::feature_flags::{self, FeatureFlag, FeatureFlagError};
+
+fn flag(name: &str, rollout_percent: u8, canary_systems: Vec<i32>) -> FeatureFlag {
+    FeatureFlag {
+        name: name.to_string(),
+        rollout_percent,
+        canary_systems,
+    }
+}
+
+fn flag_at(rollout_percent: u8) -> FeatureFlag {
+    flag("smart", rollout_percent, Vec::new())
+}
+
+#[test]
+fn canaries_get_the_flag_before_the_rollout() {
+    let flag = flag("smart", 0, vec![4, 7]);
+    assert!(flag.enabled_for(4));
+    assert!(flag.enabled_for(7));
+    assert!(!(1..=1000)
+        .filter(|id| ![4, 7].contains(id))
+        .any(|id| flag.enabled_for(id)));
+    assert!((1..=1000).all(|id| flag_at(100).enabled_for(id)));
+}
+
+#[test]
+fn raising_the_rollout_only_adds_systems() {
+    let mut previous: Vec<i32> = Vec::new();
+    for percent in [0, 5, 10, 25, 50, 100] {
+        let enabled: Vec<i32> = (1..=2000)
+            .filter(|id| flag_at(percent).enabled_for(*id))
+            .collect();
+        assert!(previous.iter().all(|id| enabled.contains(id)));
+        // a stable bucket per system, roughly the share asked for
+        let expected = 2000 * usize::from(percent) / 100;
+        assert!(
+            enabled.len().abs_diff(expected) <= 100,
+            "{percent}%: {}",
+            enabled.len()
+        );
+        previous = enabled;
+    }
+}
+
+#[test]
+fn buckets_differ_between_flags() {
+    let cohort = |name: &str| -> Vec<i32> {
+        (1..=1000)
+            .filter(|id| flag(name, 10, Vec::new()).enabled_for(*id))
+            .collect()
+    };
+    assert_ne!(cohort("smart"), cohort("ebpf"));
+    assert_eq!(
+        feature_flags::bucket("smart", 42),
+        feature_flags::bucket("smart", 42)
+    );
+}
+
+#[test]
+fn resolves_every_flag_for_a_system() {
+    let flags = vec![flag("smart", 0, vec![3]), flag("gpu", 0, Vec::new())];
+    let features = feature_flags::resolve(&flags, 3);
+    assert_eq!(features.len(), 2);
+    assert!(features["smart"]);
+    assert!(!features["gpu"]);
+}
+
+#[test]
+fn config_rows_override_flags() {
+    let flags = AgentConfigDoc {
+        features: feature_flags::resolve(&[flag("smart", 100, Vec::new())], 3),
+        ..AgentConfigDoc::default()
+    };
+    let system: AgentConfigDoc =
+        serde_json::from_value(serde_json::json!({"features": {"smart": false}})).unwrap();
+    assert!(flags.clone().to_proto().features["smart"]);
+    assert!(!flags.merge(system).to_proto().features["smart"]);
+}
+
+#[test]
+fn validates_flags() {
+    assert!(flag("smart", 100, Vec::new()).validate().is_ok());
+    assert!(flag("Systemctl_Collector-2", 0, Vec::new())
+        .validate()
+        .is_ok());
+    assert!(matches!(
+        flag("smart", 101, Vec::new()).validate(),
+        Err(FeatureFlagError::InvalidRollout(101))
+    ));
+    for name in ["", "with space", "dotted.name", &"x".repeat(65)] {
+        assert!(
+            matches!(
+                flag(name, 10, Vec::new()).validate(),
+                Err(FeatureFlagError::InvalidName(_))
+            ),
+            "{name}"
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use lynx_core::notify::flapping::{Flap, FlapDetector, FlapOptions};
+use std::time::{Duration, Instant};
+
+fn detector() -> FlapDetector {
+    FlapDetector::new(FlapOptions {
+        max_transitions: 3,
+        window: Duration::from_secs(600),
+    })
+}
+
+#[test]
+fn steady_rules_are_stable() {
+    let flaps = detector();
+    let now = Instant::now();
+    for i in 0..10 {
+        assert_eq!(
+            flaps.observe_at(1, "cpu", true, now + Duration::from_secs(i)),
+            Flap::Stable
+        );
+    }
+    assert!(flaps.flapping().is_empty());
+}
+
+#[test]
+fn flapping_starts_once_and_settles_after_a_quiet_window() {
+    let flaps = detector();
+    let now = Instant::now();
+    let at = |secs| now + Duration::from_secs(secs);
+
+    assert_eq!(flaps.observe_at(1, "cpu", true, at(0)), Flap::Stable);
+    assert_eq!(flaps.observe_at(1, "cpu", false, at(10)), Flap::Stable);
+    assert_eq!(flaps.observe_at(1, "cpu", true, at(20)), Flap::Stable);
+    assert_eq!(flaps.observe_at(1, "cpu", false, at(30)), Flap::Started);
+    assert_eq!(flaps.observe_at(1, "cpu", true, at(40)), Flap::Flapping);
+    assert_eq!(flaps.observe_at(1, "cpu", true, at(300)), Flap::Flapping);
+
+    let flapping = flaps.flapping();
+    assert_eq!(flapping.len(), 1);
+    assert_eq!(
+        (flapping[0].system_id, flapping[0].rule.as_str()),
+        (1, "cpu")
+    );
+    assert!(flapping[0].firing);
+
+    // other systems and rules are tracked on their own
+    assert_eq!(flaps.observe_at(2, "cpu", true, at(40)), Flap::Stable);
+    assert_eq!(flaps.observe_at(1, "disk", true, at(40)), Flap::Stable);
+
+    assert_eq!(flaps.observe_at(1, "cpu", true, at(641)), Flap::Settled);
+    assert_eq!(flaps.observe_at(1, "cpu", true, at(650)), Flap::Stable);
+}
+
+#[test]
+fn zero_transitions_disables_detection() {
+    let flaps = FlapDetector::new(FlapOptions {
+        max_transitions: 0,
+        window: Duration::from_secs(600),
+    });
+    let now = Instant::now();
+    for i in 0..20 {
+        assert_eq!(
+            flaps.observe_at(1, "cpu", i % 2 == 0, now + Duration::from_secs(i)),
+            Flap::Stable
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use lynx_core::services::gpu_history::{
+    range, step, GpuHistoryError, GpuMetricsQuery, MAX_POINTS, MIN_STEP_SECS,
+};
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 5, 2, 12, 0, 0).unwrap()
+}
+
+#[test]
+fn range_defaults_to_the_last_day() {
+    let (from, to) = range(&GpuMetricsQuery::default(), now()).unwrap();
+    assert_eq!(to, now());
+    assert_eq!(from, now() - Duration::hours(24));
+
+    // only `to` set: the day before it
+    let query = GpuMetricsQuery {
+        to: Some(now() - Duration::days(3)),
+        ..Default::default()
+    };
+    let (from, to) = range(&query, now()).unwrap();
+    assert_eq!(to - from, Duration::hours(24));
+}
+
+#[test]
+fn invalid_ranges_are_refused() {
+    let query = GpuMetricsQuery {
+        from: Some(now()),
+        to: Some(now() - Duration::hours(1)),
+        ..Default::default()
+    };
+    assert!(matches!(
+        range(&query, now()),
+        Err(GpuHistoryError::EmptyRange)
+    ));
+
+    let query = GpuMetricsQuery {
+        from: Some(now() - Duration::days(401)),
+        ..Default::default()
+    };
+    assert!(matches!(
+        range(&query, now()),
+        Err(GpuHistoryError::RangeTooLong)
+    ));
+}
+
+#[test]
+fn step_fits_the_range() {
+    let to = now();
+    // an hour fits at the smallest step
+    assert_eq!(step(None, to - Duration::hours(1), to), MIN_STEP_SECS);
+    assert_eq!(step(Some(1), to - Duration::hours(1), to), MIN_STEP_SECS);
+    assert_eq!(step(Some(300), to - Duration::hours(1), to), 300);
+
+    // a month is coarsened to MAX_POINTS points, whatever was asked for
+    let from = to - Duration::days(30);
+    let fitted = step(Some(60), from, to);
+    assert!((to - from).num_seconds() / fitted <= MAX_POINTS);
+    assert_eq!(fitted, 2592);
+}
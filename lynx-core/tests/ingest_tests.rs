@@ -0,0 +1,122 @@
+use chrono::{Duration, TimeZone, Utc};
+use lynx_core::services::ingest::{
+    sample_time, CustomMetricItem, IngestError, IngestItem, IngestOptions, IngestQueue,
+    OverflowPolicy, MAX_SAMPLE_AGE,
+};
+
+#[test]
+fn uses_hub_time_without_agent_timestamp() {
+    let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+    assert_eq!(sample_time(None, now).unwrap(), now);
+}
+
+#[test]
+fn keeps_agent_time_for_late_samples() {
+    let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+    let collected = now - Duration::minutes(30);
+    assert_eq!(
+        sample_time(Some(collected.timestamp_millis()), now).unwrap(),
+        collected
+    );
+}
+
+#[test]
+fn tolerates_small_clock_skew() {
+    let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+    let collected = now + Duration::seconds(5);
+    assert_eq!(
+        sample_time(Some(collected.timestamp_millis()), now).unwrap(),
+        collected
+    );
+}
+
+#[test]
+fn falls_back_to_hub_time_for_future_samples() {
+    let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+    let collected = now + Duration::hours(1);
+    assert_eq!(
+        sample_time(Some(collected.timestamp_millis()), now).unwrap(),
+        now
+    );
+}
+
+#[test]
+fn rejects_samples_older_than_max_age() {
+    let now = Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).unwrap();
+    let collected = now - MAX_SAMPLE_AGE - Duration::seconds(1);
+    let err = sample_time(Some(collected.timestamp_millis()), now).unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+}
+
+fn custom_item(system_id: i32) -> IngestItem {
+    IngestItem::Custom(CustomMetricItem {
+        system_id,
+        time: Utc::now(),
+        name: "queue_test".to_string(),
+        value: 1.0,
+        labels: serde_json::Value::Null,
+    })
+}
+
+fn queue_options(overflow: OverflowPolicy) -> IngestOptions {
+    IngestOptions {
+        queue_size: 4,
+        workers: 2,
+        wait: std::time::Duration::ZERO,
+        overflow,
+    }
+}
+
+#[test]
+fn parses_overflow_policies() {
+    assert_eq!("reject".parse(), Ok(OverflowPolicy::Reject));
+    assert_eq!(" Drop ".parse(), Ok(OverflowPolicy::Drop));
+    assert!("block".parse::<OverflowPolicy>().is_err());
+}
+
+#[tokio::test]
+async fn routes_a_system_to_one_writer() {
+    let (queue, mut receivers) = IngestQueue::new(&queue_options(OverflowPolicy::Reject));
+    assert_eq!(receivers.len(), 2);
+    assert_eq!(queue.capacity(), 4);
+
+    queue.push(custom_item(3)).await.unwrap();
+    queue.push(custom_item(3)).await.unwrap();
+    queue.push(custom_item(4)).await.unwrap();
+    assert_eq!(queue.depth(), 3);
+    assert_eq!(receivers[1].len(), 2);
+    assert_eq!(receivers[0].len(), 1);
+    assert_eq!(receivers[1].recv().await.unwrap().system_id(), 3);
+}
+
+#[tokio::test]
+async fn rejects_samples_once_full() {
+    let (queue, _receivers) = IngestQueue::new(&queue_options(OverflowPolicy::Reject));
+    queue.push(custom_item(1)).await.unwrap();
+    queue.push(custom_item(1)).await.unwrap();
+    assert!(matches!(
+        queue.push(custom_item(1)).await,
+        Err(IngestError::Full)
+    ));
+    // the other writer still has room
+    queue.push(custom_item(2)).await.unwrap();
+}
+
+#[tokio::test]
+async fn drops_samples_once_full() {
+    let (queue, _receivers) = IngestQueue::new(&queue_options(OverflowPolicy::Drop));
+    queue.push(custom_item(1)).await.unwrap();
+    queue.push(custom_item(1)).await.unwrap();
+    queue.push(custom_item(1)).await.unwrap();
+    assert_eq!(queue.depth(), 2);
+}
+
+#[tokio::test]
+async fn reports_a_closed_queue() {
+    let (queue, receivers) = IngestQueue::new(&queue_options(OverflowPolicy::Reject));
+    drop(receivers);
+    assert!(matches!(
+        queue.push(custom_item(1)).await,
+        Err(IngestError::Closed)
+    ));
+}
@@ -0,0 +1,115 @@
+use lynx_core::services::location::{
+    summarize, Group, GroupLevel, GroupQuery, Location, LocationError, MAX_LENGTH,
+};
+use lynx_core::services::overview::SystemOverview;
+use std::collections::{BTreeMap, HashMap};
+
+fn system(
+    id: i32,
+    site: Option<&str>,
+    online: bool,
+    alerts: i64,
+    cpu: Option<f64>,
+) -> SystemOverview {
+    SystemOverview {
+        id,
+        hostname: None,
+        label: format!("system-{id}"),
+        online,
+        last_seen: None,
+        cpu_usage: cpu,
+        memory_usage: None,
+        disk_usage: None,
+        active_alerts: alerts,
+        tags: BTreeMap::new(),
+        clock_skew_ms: None,
+        location: Location {
+            site: site.map(str::to_string),
+            ..Default::default()
+        },
+    }
+}
+
+#[test]
+fn location_from_tags() {
+    let tags = HashMap::from([
+        ("site".to_string(), " eu-1 ".to_string()),
+        ("rack".to_string(), "".to_string()),
+        ("datacenter".to_string(), "x".repeat(MAX_LENGTH + 1)),
+        ("env".to_string(), "prod".to_string()),
+    ]);
+    assert_eq!(
+        Location::from_tags(&tags),
+        Location {
+            site: Some("eu-1".to_string()),
+            datacenter: None,
+            rack: None,
+        }
+    );
+}
+
+#[test]
+fn api_values_are_checked() {
+    let location = Location {
+        site: Some("  ".to_string()),
+        datacenter: Some(" fra1".to_string()),
+        rack: None,
+    };
+    assert_eq!(
+        location.normalized().unwrap(),
+        Location {
+            site: None,
+            datacenter: Some("fra1".to_string()),
+            rack: None,
+        }
+    );
+    let too_long = Location {
+        rack: Some("r".repeat(MAX_LENGTH + 1)),
+        ..Default::default()
+    };
+    assert!(matches!(
+        too_long.normalized(),
+        Err(LocationError::TooLong("rack"))
+    ));
+}
+
+#[test]
+fn groups() {
+    let group: Group = "datacenter= fra1".parse().unwrap();
+    assert_eq!(group.level, GroupLevel::Datacenter);
+    assert_eq!(group.to_string(), "datacenter=fra1");
+    for invalid in ["fra1", "room=3", "site=", "=eu-1"] {
+        assert!(invalid.parse::<Group>().is_err(), "{invalid}");
+    }
+
+    let query: GroupQuery = serde_json::from_str("{}").unwrap();
+    assert_eq!(query.by, GroupLevel::Site);
+    let query: GroupQuery = serde_json::from_str(r#"{"by": "rack"}"#).unwrap();
+    assert_eq!(query.by, GroupLevel::Rack);
+}
+
+#[test]
+fn summaries_per_group() {
+    let systems = [
+        system(1, Some("us-1"), true, 0, Some(10.0)),
+        system(2, None, false, 0, None),
+        system(3, Some("eu-1"), true, 2, Some(30.0)),
+        system(4, Some("eu-1"), false, 1, None),
+        system(5, Some("eu-1"), true, 0, Some(50.0)),
+    ];
+    let groups = summarize(&systems, GroupLevel::Site);
+    let names: Vec<Option<&str>> = groups.iter().map(|g| g.name.as_deref()).collect();
+    assert_eq!(names, [Some("eu-1"), Some("us-1"), None]);
+
+    let eu = &groups[0];
+    assert_eq!((eu.systems, eu.online, eu.alerting), (3, 2, 2));
+    assert_eq!(eu.active_alerts, 3);
+    assert_eq!(eu.cpu_usage, Some(40.0));
+    assert_eq!(eu.memory_usage, None);
+    assert_eq!(groups[2].systems, 1);
+
+    // nobody has a rack, everything lands in one unassigned group
+    let racks = summarize(&systems, GroupLevel::Rack);
+    assert_eq!(racks.len(), 1);
+    assert_eq!((racks[0].name.clone(), racks[0].systems), (None, 5));
+}
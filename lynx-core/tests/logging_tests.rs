@@ -0,0 +1,64 @@
+use lynx_core::logging;
+use tonic::codegen::http;
+use tonic::metadata::MetadataMap;
+
+fn request(key: Option<&str>) -> http::Request<()> {
+    let mut builder = http::Request::builder().uri("/monitor.MetricsIngest/ReportMetrics");
+    if let Some(key) = key {
+        builder = builder.header("x-agent-key", key);
+    }
+    builder.body(()).unwrap()
+}
+
+#[test]
+fn rpc_span_carries_call_fields() {
+    tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+        let span = logging::rpc_span(&request(Some("secret-key")));
+        let meta = span.metadata().expect("span disabled");
+        assert_eq!(meta.name(), "rpc");
+        for field in ["method", "key_id", "request_id", "system_id"] {
+            assert!(meta.fields().field(field).is_some(), "missing {field}");
+        }
+    });
+}
+
+#[test]
+fn rpc_span_without_key() {
+    tracing::subscriber::with_default(tracing_subscriber::registry(), || {
+        let span = logging::rpc_span(&request(None));
+        assert!(!span.is_disabled());
+        // recording before authentication happened must not panic
+        let _guard = span.enter();
+        logging::record_system_id(7);
+    });
+}
+
+#[test]
+fn request_id_validation() {
+    assert!(logging::is_valid_request_id(
+        "6f1c2a9e-3b7d-4c1e-9a0f-2d4b6c8e0a1b"
+    ));
+    assert!(logging::is_valid_request_id("batch_7.3"));
+    assert!(!logging::is_valid_request_id(""));
+    assert!(!logging::is_valid_request_id("a b"));
+    assert!(!logging::is_valid_request_id("id\"injected"));
+    assert!(!logging::is_valid_request_id(&"a".repeat(65)));
+}
+
+#[test]
+fn request_id_from_metadata() {
+    let mut metadata = MetadataMap::new();
+    metadata.insert(logging::REQUEST_ID_HEADER, "agent-42".parse().unwrap());
+    assert_eq!(logging::request_id(&metadata), "agent-42");
+}
+
+#[test]
+fn request_id_generated_when_missing_or_invalid() {
+    let generated = logging::request_id(&MetadataMap::new());
+    assert!(logging::is_valid_request_id(&generated));
+    assert_ne!(generated, logging::request_id(&MetadataMap::new()));
+
+    let mut metadata = MetadataMap::new();
+    metadata.insert(logging::REQUEST_ID_HEADER, "a;b".parse().unwrap());
+    assert_ne!(logging::request_id(&metadata), "a;b");
+}
@@ -0,0 +1,98 @@
+use lynx_core::services::location::{Group, GroupLevel};
+use lynx_core::services::maintenance::{
+    parse_duration, MaintenanceCommand, MaintenanceError, MAX_DURATION,
+};
+use std::time::Duration;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn durations() {
+    assert_eq!(parse_duration("90m").unwrap(), Duration::from_secs(90 * 60));
+    assert_eq!(parse_duration("4h").unwrap(), Duration::from_secs(4 * 3600));
+    assert_eq!(
+        parse_duration("2d").unwrap(),
+        Duration::from_secs(2 * 86400)
+    );
+    assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30 * 60));
+    assert_eq!(parse_duration("7d").unwrap(), MAX_DURATION);
+
+    assert!(matches!(
+        parse_duration("8d"),
+        Err(MaintenanceError::TooLong)
+    ));
+    for invalid in ["", "0", "0h", "h", "5w", "-1h", "1.5h"] {
+        assert!(
+            matches!(
+                parse_duration(invalid),
+                Err(MaintenanceError::InvalidDuration(_))
+            ),
+            "{invalid}"
+        );
+    }
+}
+
+#[test]
+fn commands() {
+    assert_eq!(MaintenanceCommand::from_args(&args(&[])).unwrap(), None);
+    assert_eq!(
+        MaintenanceCommand::from_args(&args(&["backup", "x"])).unwrap(),
+        None
+    );
+    assert_eq!(
+        MaintenanceCommand::from_args(&args(&["maintenance", "start", "2h", "DC", "move"]))
+            .unwrap(),
+        Some(MaintenanceCommand::Start {
+            duration: Duration::from_secs(7200),
+            reason: "DC move".to_string(),
+            scope: None,
+        })
+    );
+    assert_eq!(
+        MaintenanceCommand::from_args(&args(&["maintenance", "end"])).unwrap(),
+        Some(MaintenanceCommand::End { scope: None })
+    );
+    assert_eq!(
+        MaintenanceCommand::from_args(&args(&["maintenance", "status"])).unwrap(),
+        Some(MaintenanceCommand::Status)
+    );
+    assert!(MaintenanceCommand::from_args(&args(&["maintenance"])).is_err());
+    assert!(MaintenanceCommand::from_args(&args(&["maintenance", "start"])).is_err());
+}
+
+#[test]
+fn scoped_commands() {
+    let eu = Group {
+        level: GroupLevel::Site,
+        name: "eu-1".to_string(),
+    };
+    assert_eq!(
+        MaintenanceCommand::from_args(&args(&[
+            "maintenance",
+            "start",
+            "4h",
+            "--scope",
+            "site=eu-1",
+            "power",
+            "work"
+        ]))
+        .unwrap(),
+        Some(MaintenanceCommand::Start {
+            duration: Duration::from_secs(4 * 3600),
+            reason: "power work".to_string(),
+            scope: Some(eu.clone()),
+        })
+    );
+    assert_eq!(
+        MaintenanceCommand::from_args(&args(&["maintenance", "end", "--scope", "site=eu-1"]))
+            .unwrap(),
+        Some(MaintenanceCommand::End { scope: Some(eu) })
+    );
+    assert!(matches!(
+        MaintenanceCommand::from_args(&args(&["maintenance", "start", "1h", "--scope", "room=3"])),
+        Err(MaintenanceError::Scope(_))
+    ));
+    assert!(MaintenanceCommand::from_args(&args(&["maintenance", "end", "now"])).is_err());
+}
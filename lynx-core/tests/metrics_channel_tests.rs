@@ -0,0 +1,27 @@
+use lynx_core::services::monitor::{metrics_ack, METRICS_RETRY_AFTER_SECS};
+use tonic::{Code, Status};
+
+#[test]
+fn queued_samples_are_acked_with_ok() {
+    let ack = metrics_ack(7, Ok(()));
+    assert_eq!(ack.sequence, 7);
+    assert_eq!(ack.code, Code::Ok as i32);
+    assert_eq!(ack.retry_after_secs, 0);
+}
+
+#[test]
+fn rejected_samples_carry_the_status() {
+    let ack = metrics_ack(3, Err(Status::invalid_argument("cpu_stats.usage_percent")));
+    assert_eq!(ack.sequence, 3);
+    assert_eq!(ack.code, Code::InvalidArgument as i32);
+    assert_eq!(ack.message, "cpu_stats.usage_percent");
+    // a bad sample is the agent's problem, the hub has room for the next one
+    assert_eq!(ack.retry_after_secs, 0);
+}
+
+#[test]
+fn a_full_ingest_queue_asks_for_a_pause() {
+    let ack = metrics_ack(0, Err(Status::resource_exhausted("ingest queue is full")));
+    assert_eq!(ack.code, Code::ResourceExhausted as i32);
+    assert_eq!(ack.retry_after_secs, METRICS_RETRY_AFTER_SECS);
+}
@@ -1,5 +1,85 @@
+use lynx_core::notify::rule_cache::RuleCache;
+use lynx_core::notify::worker::EvaluationOptions;
+use lynx_core::notify::{CustomComponent, Evaluation, EvaluationQueue, MetricRegistry, Notifier};
+use std::collections::HashMap;
+use std::time::Duration;
+
 #[tokio::test]
 async fn test_process_notification() {
     let title = "Test Notification";
     let message = "This is a test notification message.";
 }
+
+#[test]
+fn rule_cache_drops_loads_that_raced_an_invalidation() {
+    let cache = RuleCache::default();
+    assert!(cache.rules(1).is_none());
+
+    let version = cache.version();
+    cache.put_rules(1, version, Vec::new());
+    assert!(cache.rules(1).is_some());
+
+    // a load that started before a change must not be stored
+    let stale = cache.version();
+    cache.invalidate();
+    assert!(cache.rules(1).is_none());
+    let notifiers = vec![Notifier {
+        id: 7,
+        url: "https://example.com/hook".to_string(),
+    }];
+    cache.put_owner_notifiers(1, stale, notifiers.clone());
+    assert!(cache.owner_notifiers(1).is_none());
+
+    cache.put_owner_notifiers(1, cache.version(), notifiers);
+    assert_eq!(cache.owner_notifiers(1).unwrap()[0].id, 7);
+}
+
+#[test]
+fn rule_cache_with_zero_ttl_stores_nothing() {
+    let cache = RuleCache::default();
+    cache.set_ttl(Duration::ZERO);
+    cache.put_rules(1, cache.version(), Vec::new());
+    assert!(cache.is_empty());
+}
+
+#[tokio::test]
+async fn registry_keeps_components_until_they_expire() {
+    let registry = MetricRegistry::new();
+    let values = HashMap::from([("queue_depth".to_string(), 12.0)]);
+    registry
+        .register_component("custom".to_string(), Box::new(CustomComponent::new(values)))
+        .await;
+
+    assert!(registry.expire(Duration::from_secs(600)).await);
+    assert_eq!(
+        registry
+            .get_metric_value("custom", "queue_depth")
+            .await
+            .unwrap(),
+        12.0
+    );
+    assert!(!registry.expire(Duration::ZERO).await);
+    assert!(!registry.has_component("custom").await);
+}
+
+#[tokio::test]
+async fn evaluation_queue_keeps_a_system_on_one_worker_and_drops_when_full() {
+    let options = EvaluationOptions {
+        queue_size: 2,
+        workers: 2,
+    };
+    let (queue, mut receivers) = EvaluationQueue::new(&options);
+    let custom = |system_id| Evaluation::Custom {
+        system_id,
+        values: HashMap::new(),
+    };
+
+    assert!(queue.push(custom(3)));
+    // system 5 shares the second worker with system 3, whose share is full
+    assert!(!queue.push(custom(5)));
+    assert!(queue.push(custom(4)));
+    assert_eq!(queue.depth(), 2);
+
+    assert!(receivers[0].try_recv().is_ok_and(|e| e.system_id() == 4));
+    assert!(receivers[1].try_recv().is_ok_and(|e| e.system_id() == 3));
+}
@@ -0,0 +1,43 @@
+use lynx_core::services::location::Location;
+use lynx_core::services::overview::{percent, SystemOverview};
+use std::collections::BTreeMap;
+
+#[test]
+fn headline_percentages() {
+    assert_eq!(percent(Some(4.0), Some(16.0)), Some(25.0));
+    assert_eq!(percent(Some(0.0), Some(500.0)), Some(0.0));
+    // reports racing a resize can briefly exceed the total
+    assert_eq!(percent(Some(510.0), Some(500.0)), Some(100.0));
+    assert_eq!(percent(Some(1.0), Some(0.0)), None);
+    assert_eq!(percent(None, Some(16.0)), None);
+    assert_eq!(percent(Some(4.0), None), None);
+}
+
+#[test]
+fn serializes_for_the_portal() {
+    let system = SystemOverview {
+        id: 3,
+        hostname: Some("web-1".to_string()),
+        label: "web-1".to_string(),
+        online: true,
+        last_seen: None,
+        cpu_usage: Some(12.5),
+        memory_usage: Some(40.0),
+        disk_usage: None,
+        active_alerts: 2,
+        tags: BTreeMap::from([("env".to_string(), "prod".to_string())]),
+        clock_skew_ms: Some(-1500),
+        location: Location {
+            site: Some("eu-1".to_string()),
+            ..Default::default()
+        },
+    };
+    let json = serde_json::to_value(&system).unwrap();
+    assert_eq!(json["online"], true);
+    assert_eq!(json["disk_usage"], serde_json::Value::Null);
+    assert_eq!(json["active_alerts"], 2);
+    assert_eq!(json["tags"]["env"], "prod");
+    assert_eq!(json["clock_skew_ms"], -1500);
+    assert_eq!(json["site"], "eu-1");
+    assert_eq!(json["rack"], serde_json::Value::Null);
+}
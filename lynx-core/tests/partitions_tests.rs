@@ -0,0 +1,32 @@
+use chrono::{Duration, TimeZone, Utc};
+use lynx_core::partitions::{RangeError, TimeRange, DEFAULT_RANGE, MAX_RANGE};
+
+#[test]
+fn defaults_to_the_last_day() {
+    let now = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let range = TimeRange::resolve(None, None, now).unwrap();
+    assert_eq!(range.to, now);
+    assert_eq!(range.from, now - DEFAULT_RANGE);
+}
+
+#[test]
+fn caps_the_end_at_now() {
+    let now = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let from = now - Duration::hours(2);
+    let range = TimeRange::resolve(Some(from), Some(now + Duration::days(1)), now).unwrap();
+    assert_eq!(range, TimeRange { from, to: now });
+}
+
+#[test]
+fn refuses_inverted_and_long_ranges() {
+    let now = Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap();
+    let to = now - Duration::days(1);
+    assert_eq!(
+        TimeRange::resolve(Some(now), Some(to), now),
+        Err(RangeError::Inverted)
+    );
+    assert_eq!(
+        TimeRange::resolve(Some(now - MAX_RANGE - Duration::days(1)), None, now),
+        Err(RangeError::TooLong)
+    );
+}
@@ -0,0 +1,59 @@
+use lynx_core::retention::RetentionOptions;
+use lynx_core::services::processes::{self, ProcessQuery, ProcessSample, ProcessSort};
+
+fn sample(pid: i32, cpu_usage: f32, memory_kb: i64) -> ProcessSample {
+    ProcessSample {
+        pid,
+        name: format!("proc-{pid}"),
+        cpu_usage,
+        memory_kb,
+    }
+}
+
+fn pids(processes: &[ProcessSample]) -> Vec<i32> {
+    processes.iter().map(|p| p.pid).collect()
+}
+
+#[test]
+fn sorts_busiest_first() {
+    let mut list = vec![
+        sample(3, 5.0, 900),
+        sample(1, 80.0, 100),
+        sample(4, 5.0, 2_000),
+        sample(2, 5.0, 900),
+    ];
+    processes::sort(&mut list, ProcessSort::Cpu);
+    assert_eq!(pids(&list), [1, 4, 2, 3]);
+    processes::sort(&mut list, ProcessSort::Memory);
+    assert_eq!(pids(&list), [4, 2, 3, 1]);
+}
+
+#[test]
+fn query_defaults_to_now_by_cpu() {
+    let query: ProcessQuery = serde_json::from_str("{}").unwrap();
+    assert!(query.at.is_none());
+    assert_eq!(query.sort, ProcessSort::Cpu);
+
+    let query: ProcessQuery =
+        serde_json::from_str(r#"{"at": "2024-05-02T03:12:00Z", "sort": "memory"}"#).unwrap();
+    assert_eq!(query.at.unwrap().to_rfc3339(), "2024-05-02T03:12:00+00:00");
+    assert_eq!(query.sort, ProcessSort::Memory);
+}
+
+#[test]
+fn kept_a_week_unless_configured() {
+    let options = RetentionOptions::from_lookup(|_| None);
+    assert_eq!(options.days_for("process_samples"), 7);
+
+    let options = RetentionOptions::from_lookup(|key| match key {
+        "RETENTION_DAYS" => Some("3".into()),
+        _ => None,
+    });
+    assert_eq!(options.days_for("process_samples"), 3);
+
+    let options = RetentionOptions::from_lookup(|key| match key {
+        "RETENTION_PROCESS_SAMPLES_DAYS" => Some("30".into()),
+        _ => None,
+    });
+    assert_eq!(options.days_for("process_samples"), 30);
+}
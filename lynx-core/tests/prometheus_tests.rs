@@ -0,0 +1,125 @@
+use lynx_core::counters::CounterRates;
+use lynx_core::prometheus::{node_exporter_metrics, parse};
+use std::time::{Duration, Instant};
+
+const SCRAPE: &str = r#"
+# HELP node_load1 1m load average.
+# TYPE node_load1 gauge
+node_load1 0.5
+node_load5 0.25
+node_load15 0.125
+node_procs_running 3
+node_procs_blocked 1
+node_context_switches_total 1000
+node_entropy_available_bits 256
+node_processes_threads 420
+node_processes_state{state="S"} 180
+node_processes_state{state="R"} 3
+node_processes_state{state="Z"} 2
+node_memory_MemTotal_bytes 8.589934592e+09
+node_memory_MemAvailable_bytes 2.147483648e+09
+node_memory_MemFree_bytes 1.073741824e+09
+node_memory_SwapTotal_bytes 2.097152e+06
+node_memory_SwapFree_bytes 1.048576e+06
+node_cpu_seconds_total{cpu="0",mode="idle"} 100
+node_cpu_seconds_total{cpu="0",mode="user"} 50
+node_network_receive_bytes_total{device="eth0"} 1048576
+node_network_receive_bytes_total{device="lo"} 999999999
+node_network_transmit_bytes_total{device="eth0"} 0
+node_filesystem_size_bytes{device="/dev/sda1",fstype="ext4",mountpoint="/"} 1.073741824e+11
+node_filesystem_avail_bytes{device="/dev/sda1",fstype="ext4",mountpoint="/"} 5.36870912e+10
+node_filesystem_files{device="/dev/sda1",fstype="ext4",mountpoint="/"} 6553600
+node_filesystem_files_free{device="/dev/sda1",fstype="ext4",mountpoint="/"} 6400000
+node_filesystem_size_bytes{device="tmpfs",fstype="tmpfs",mountpoint="/run"} 1e+08
+node_disk_read_bytes_total{device="sda1"} 4096
+node_disk_reads_completed_total{device="sda1"} 10
+node_hwmon_temp_celsius{chip="platform_coretemp_0",sensor="temp1"} 45 1700000000000
+"#;
+
+#[test]
+fn parses_labels_escapes_and_special_values() {
+    let samples =
+        parse("up 1\nweird{path=\"C:\\\\x\",msg=\"say \\\"hi\\\"\",} +Inf\nempty{} NaN 123\n")
+            .unwrap();
+    assert_eq!(samples.len(), 3);
+    assert_eq!(samples[0].name, "up");
+    assert_eq!(samples[0].value, 1.0);
+    assert_eq!(samples[1].label("path"), "C:\\x");
+    assert_eq!(samples[1].label("msg"), "say \"hi\"");
+    assert_eq!(samples[1].value, f64::INFINITY);
+    assert!(samples[2].value.is_nan());
+
+    assert!(parse("broken{a=\"x} 1").is_err());
+    assert!(parse("novalue").is_err());
+}
+
+#[test]
+fn maps_node_exporter_series() {
+    let samples = parse(SCRAPE).unwrap();
+    let mut counters = CounterRates::default();
+    let start = Instant::now();
+
+    let first = node_exporter_metrics(&samples, &mut counters, start);
+    assert_eq!(first.cpu_stats.unwrap().usage_percent, 0.0);
+    assert_eq!(first.cpu_stats.unwrap().user_percent, None);
+    let memory = first.memory_stats.unwrap();
+    assert_eq!(memory.total_kb, 8 * 1024 * 1024);
+    assert_eq!(memory.used_kb, 6 * 1024 * 1024);
+    assert_eq!(memory.free_kb, 1024 * 1024);
+    assert_eq!(memory.available_kb, Some(2 * 1024 * 1024));
+    assert_eq!(
+        (memory.swap_total_kb, memory.swap_used_kb),
+        (Some(2048), Some(1024))
+    );
+    assert_eq!(memory.cached_kb, None);
+    assert_eq!(first.load_average.unwrap().one_minute, 0.5);
+    let processes = first.process_stats.unwrap();
+    assert_eq!((processes.total, processes.threads), (185, 420));
+    assert_eq!((processes.running, processes.zombie), (3, 2));
+    let kernel = first.kernel_stats.unwrap();
+    assert_eq!(kernel.context_switches_per_sec, 0.0);
+    assert_eq!((kernel.procs_blocked, kernel.entropy_avail), (1, Some(256)));
+    assert_eq!(first.disk_stats.len(), 1);
+    let disk = &first.disk_stats[0];
+    assert_eq!((disk.total_space, disk.used_space), (100, 50));
+    assert_eq!(
+        (disk.total_bytes, disk.used_bytes),
+        (Some(100 << 30), Some(50 << 30))
+    );
+    assert_eq!(disk.mount_point, "/");
+    assert_eq!(disk.read_bytes, 0.0);
+    assert_eq!(disk.read_iops, None);
+    assert_eq!(
+        (disk.inodes_total, disk.inodes_used),
+        (Some(6553600), Some(153600))
+    );
+    assert_eq!(first.components[0].label, "platform_coretemp_0 temp1");
+
+    // one second later: 3 busy and 1 idle CPU second, 2 MB received on eth0, 8 KiB in 2 reads
+    let later = SCRAPE
+        .replace("mode=\"idle\"} 100", "mode=\"idle\"} 101")
+        .replace("mode=\"user\"} 50", "mode=\"user\"} 53")
+        .replace("{device=\"eth0\"} 1048576", "{device=\"eth0\"} 3145728")
+        .replace("{device=\"sda1\"} 4096", "{device=\"sda1\"} 12288")
+        .replace("{device=\"sda1\"} 10", "{device=\"sda1\"} 12")
+        .replace(
+            "node_context_switches_total 1000",
+            "node_context_switches_total 1500",
+        );
+    let second = node_exporter_metrics(
+        &parse(&later).unwrap(),
+        &mut counters,
+        start + Duration::from_secs(1),
+    );
+    let cpu = second.cpu_stats.unwrap();
+    assert_eq!(cpu.usage_percent, 75.0);
+    assert_eq!(cpu.user_percent, Some(75.0));
+    assert_eq!(cpu.steal_percent, Some(0.0));
+    let network = second.network_stats.unwrap();
+    assert_eq!(network.r#in, 2);
+    assert_eq!(network.in_bytes_per_sec, Some(2_097_152.0));
+    assert_eq!(second.disk_stats[0].read_bytes, 8192.0);
+    assert_eq!(second.disk_stats[0].read_iops, Some(2.0));
+    assert_eq!(second.disk_stats[0].write_iops, None);
+    assert_eq!(second.kernel_stats.unwrap().context_switches_per_sec, 500.0);
+}
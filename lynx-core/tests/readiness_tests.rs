@@ -0,0 +1,52 @@
+use lynx_core::health::{self, Readiness, ReadyCheck};
+use lynx_core::tls::CertExpiry;
+
+fn cert(file: &str, days_remaining: f64) -> CertExpiry {
+    CertExpiry {
+        file: file.to_string(),
+        subject: "CN=hub".to_string(),
+        not_after: String::new(),
+        days_remaining,
+    }
+}
+
+#[test]
+fn queue_below_threshold_is_ready() {
+    assert!(health::check_queue(0, 10_000, 90).ok);
+    assert!(health::check_queue(9_000, 10_000, 90).ok);
+    assert!(!health::check_queue(9_001, 10_000, 90).ok);
+}
+
+#[test]
+fn queue_threshold_of_100_only_fails_when_full() {
+    assert!(health::check_queue(10_000, 10_000, 100).ok);
+}
+
+#[test]
+fn tls_disabled_is_ready() {
+    assert!(health::check_tls(false, &[]).ok);
+}
+
+#[test]
+fn tls_requires_loaded_unexpired_certs() {
+    assert!(!health::check_tls(true, &[]).ok);
+    assert!(health::check_tls(true, &[cert("server.crt", 30.0), cert("ca.crt", 900.0)]).ok);
+
+    let check = health::check_tls(true, &[cert("server.crt", -1.0), cert("ca.crt", 900.0)]);
+    assert!(!check.ok);
+    assert_eq!(check.detail, "server.crt expired");
+}
+
+#[test]
+fn readiness_needs_every_check() {
+    let ok = ReadyCheck {
+        ok: true,
+        detail: String::new(),
+    };
+    let failed = ReadyCheck {
+        ok: false,
+        detail: "timed out".to_string(),
+    };
+    assert!(Readiness::new(ok.clone(), ok.clone(), ok.clone()).ready);
+    assert!(!Readiness::new(failed, ok.clone(), ok.clone()).ready);
+}
@@ -0,0 +1,65 @@
+use lynx_core::retention::{RetentionOptions, RETAINED};
+use std::collections::HashMap;
+
+fn from_vars(vars: &[(&str, &str)]) -> RetentionOptions {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    RetentionOptions::from_lookup(|key| vars.get(key).cloned())
+}
+
+#[test]
+fn defaults() {
+    let options = from_vars(&[]);
+    assert_eq!(options.days.len(), RETAINED.len());
+    assert_eq!(options.days_for("metrics"), 30);
+    assert_eq!(options.days_for("notification_deliveries"), 30);
+    // alert history and rollups are only pruned when asked for
+    assert_eq!(options.days_for("alert_history"), 0);
+    assert_eq!(options.days_for("metrics_hourly"), 0);
+    assert_eq!(options.rollup_days, 0);
+    assert!(!options.dry_run);
+    assert!(options.enabled());
+    assert_eq!(options.days_for("systems"), 0);
+}
+
+#[test]
+fn per_table_overrides() {
+    let options = from_vars(&[
+        ("RETENTION_DAYS", "14"),
+        ("RETENTION_METRICS_DAYS", "90"),
+        ("RETENTION_AUTH_EVENTS_DAYS", "0"),
+        ("RETENTION_ALERT_HISTORY_DAYS", "365"),
+        ("RETENTION_ROLLUP_DAYS", "400"),
+        ("RETENTION_DRY_RUN", "true"),
+    ]);
+    assert_eq!(options.days_for("metrics"), 90);
+    assert_eq!(options.days_for("disks"), 14);
+    assert_eq!(options.days_for("auth_events"), 0);
+    assert_eq!(options.days_for("alert_history"), 365);
+    assert_eq!(options.days_for("metrics_hourly"), 400);
+    assert_eq!(options.days_for("disks_hourly"), 400);
+    assert_eq!(options.days_for("gpu_metrics_hourly"), 400);
+    assert!(options.dry_run);
+}
+
+#[test]
+fn disabled_and_invalid_values() {
+    let options = from_vars(&[("RETENTION_DAYS", "0"), ("RETENTION_DISKS_DAYS", "-3")]);
+    assert!(!options.enabled());
+    assert_eq!(options.days_for("disks"), 0);
+
+    // unparsable values fall back to the defaults
+    let options = from_vars(&[("RETENTION_DAYS", "a month"), ("RETENTION_DRY_RUN", "yes")]);
+    assert_eq!(options.days_for("metrics"), 30);
+    assert!(!options.dry_run);
+}
+
+#[test]
+fn rollups_come_after_their_tables() {
+    let position = |name: &str| RETAINED.iter().position(|(t, _)| *t == name).unwrap();
+    assert!(position("metrics") < position("metrics_hourly"));
+    assert!(position("disks") < position("disks_hourly"));
+    assert!(position("gpu_metrics") < position("gpu_metrics_hourly"));
+}
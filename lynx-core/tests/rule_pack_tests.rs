@@ -0,0 +1,78 @@
+use chrono::{Duration as ChronoDuration, TimeZone, Utc};
+use lynx_core::notify::offline::offline_minutes;
+use lynx_core::notify::pending::PendingRules;
+use lynx_core::notify::{RuleParser, TagSelector};
+use lynx_core::services::rule_pack::{ProvisionQuery, DEFAULT_RULES, TARGET};
+use std::time::{Duration, Instant};
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn default_rules_parse() {
+    assert!(TARGET.parse::<TagSelector>().is_ok());
+    let mut components = Vec::new();
+    for rule in DEFAULT_RULES {
+        let conditions = RuleParser::parse_expression(rule.expression).unwrap();
+        // conditions of one rule come from the same report
+        assert!(
+            conditions
+                .iter()
+                .all(|c| c.component == conditions[0].component),
+            "{}",
+            rule.name
+        );
+        components.push(conditions[0].component.clone());
+    }
+    assert_eq!(
+        components,
+        ["disk", "memory", "agent", "agent", "tls", "smart"]
+    );
+}
+
+#[test]
+fn command_line() {
+    assert!(ProvisionQuery::from_args(&args(&["maintenance", "end"]))
+        .unwrap()
+        .is_none());
+    let query = ProvisionQuery::from_args(&args(&["rules", "install-defaults"]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(query.user_id, None);
+    let query = ProvisionQuery::from_args(&args(&["rules", "install-defaults", "3"]))
+        .unwrap()
+        .unwrap();
+    assert_eq!(query.user_id, Some(3));
+    assert!(ProvisionQuery::from_args(&args(&["rules"])).is_err());
+    assert!(ProvisionQuery::from_args(&args(&["rules", "install-defaults", "x"])).is_err());
+}
+
+#[test]
+fn held_rules_fire_after_their_hold() {
+    let pending = PendingRules::default();
+    let hold = Duration::from_secs(300);
+    let start = Instant::now();
+    assert!(!pending.observe_at(1, "mem", true, hold, start));
+    assert!(!pending.observe_at(1, "mem", true, hold, start + Duration::from_secs(240)));
+    assert!(pending.observe_at(1, "mem", true, hold, start + Duration::from_secs(300)));
+
+    // one evaluation below the threshold starts the wait over
+    assert!(!pending.observe_at(1, "mem", false, hold, start + Duration::from_secs(360)));
+    assert!(!pending.observe_at(1, "mem", true, hold, start + Duration::from_secs(420)));
+    assert!(!pending.observe_at(2, "mem", true, hold, start + Duration::from_secs(720)));
+    assert!(pending.observe_at(1, "mem", true, hold, start + Duration::from_secs(720)));
+
+    assert!(pending.observe_at(1, "disk", true, Duration::ZERO, start));
+    assert!(!pending.observe_at(1, "disk", false, Duration::ZERO, start));
+}
+
+#[test]
+fn minutes_offline() {
+    let now = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+    assert_eq!(
+        offline_minutes(now - ChronoDuration::seconds(390), now),
+        6.5
+    );
+    assert_eq!(offline_minutes(now + ChronoDuration::seconds(30), now), 0.0);
+}
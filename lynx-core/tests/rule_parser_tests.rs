@@ -0,0 +1,101 @@
+use lynx_core::notify::{known_metrics, ParseErrorKind, RuleParser, COMPONENTS};
+
+fn error(expression: &str) -> lynx_core::notify::ParseError {
+    RuleParser::parse_expression(expression).unwrap_err()
+}
+
+#[test]
+fn parses_conditions() {
+    let conditions = RuleParser::parse_expression(
+        "cpu.usage > 80 and memory.usage>=90.5 OR custom.queue_depth != -1",
+    )
+    .unwrap();
+    assert_eq!(conditions.len(), 3);
+    assert_eq!(conditions[0].component, "cpu");
+    assert_eq!(conditions[0].operator.symbol(), ">");
+    assert_eq!(conditions[0].next_logical.unwrap().keyword(), "AND");
+    assert_eq!(conditions[1].metric, "usage");
+    assert_eq!(conditions[1].value, 90.5);
+    assert_eq!(conditions[1].next_logical.unwrap().keyword(), "OR");
+    assert_eq!(conditions[2].metric, "queue_depth");
+    assert_eq!(conditions[2].value, -1.0);
+    assert!(conditions[2].next_logical.is_none());
+}
+
+#[test]
+fn catalogue_lists_every_component() {
+    assert_eq!(known_metrics("tls"), Some(&["expiry_days"][..]));
+    assert!(known_metrics("custom").is_none());
+    for (component, metrics) in COMPONENTS {
+        let expression = format!("{component}.{} > 1", metrics[0]);
+        assert!(
+            RuleParser::parse_expression(&expression).is_ok(),
+            "{expression}"
+        );
+    }
+}
+
+#[test]
+fn unknown_names_get_suggestions() {
+    let e = error("cpu.usage > 80 AND memory.usge > 90");
+    assert_eq!(e.kind, ParseErrorKind::UnknownMetric);
+    assert_eq!(e.offset, 26);
+    assert_eq!(e.token, "usge");
+    assert_eq!(e.suggestion.as_deref(), Some("did you mean usage?"));
+
+    let e = error("memroy.usage > 90");
+    assert_eq!(e.kind, ParseErrorKind::UnknownComponent);
+    assert_eq!(e.offset, 0);
+    assert_eq!(e.suggestion.as_deref(), Some("did you mean memory?"));
+
+    // nothing close, the available metrics are listed instead
+    let e = error("load.everything > 1");
+    assert_eq!(
+        e.suggestion.as_deref(),
+        Some("expected one of one, five, fifteen")
+    );
+}
+
+#[test]
+fn malformed_expressions_fail_at_the_offending_token() {
+    assert_eq!(error("").kind, ParseErrorKind::Empty);
+    assert_eq!(error("   ").kind, ParseErrorKind::Empty);
+
+    let e = error("cpu.usage > 80 && memory.usage > 90");
+    assert_eq!((e.kind, e.offset), (ParseErrorKind::ExpectedLogical, 15));
+    assert_eq!(e.token, "&&");
+    assert_eq!(e.suggestion.as_deref(), Some("use AND"));
+
+    let e = error("cpu.usage = 80");
+    assert_eq!((e.kind, e.offset), (ParseErrorKind::ExpectedOperator, 10));
+    assert_eq!(e.suggestion.as_deref(), Some("use == to compare"));
+
+    let e = error("cpu.usage > high");
+    assert_eq!((e.kind, e.offset), (ParseErrorKind::ExpectedNumber, 12));
+    assert_eq!(e.token, "high");
+
+    let e = error("cpu.usage > 80 AND");
+    assert_eq!((e.kind, e.offset), (ParseErrorKind::UnexpectedEnd, 18));
+    assert_eq!(e.token, "");
+
+    let e = error("cpu > 80");
+    assert_eq!(
+        (e.kind, e.token.as_str()),
+        (ParseErrorKind::ExpectedMetric, "cpu")
+    );
+
+    let e = error("cpu.usage >");
+    assert_eq!((e.kind, e.offset), (ParseErrorKind::ExpectedNumber, 11));
+}
+
+#[test]
+fn errors_read_well_in_logs() {
+    assert_eq!(
+        error("disk.usag > 90").to_string(),
+        "Unknown metric at offset 5: \"usag\", did you mean usage?"
+    );
+    assert_eq!(
+        error("cpu.usage > 80 AND").to_string(),
+        "Expected a condition after AND / OR at offset 18: \"\""
+    );
+}
@@ -0,0 +1,56 @@
+use lynx_core::notify::{TagMatch, TagSelector};
+use std::collections::HashMap;
+
+fn tags(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+#[test]
+fn parses_terms() {
+    let selector: TagSelector = "role=db, dc!=eu-1|eu-2, backup".parse().unwrap();
+    assert_eq!(
+        selector.terms,
+        vec![
+            TagMatch::Equals("role".to_string(), vec!["db".to_string()]),
+            TagMatch::NotEquals(
+                "dc".to_string(),
+                vec!["eu-1".to_string(), "eu-2".to_string()]
+            ),
+            TagMatch::Present("backup".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn rejects_empty_targets() {
+    assert!("".parse::<TagSelector>().is_err());
+    assert!("=db".parse::<TagSelector>().is_err());
+}
+
+#[test]
+fn all_terms_must_match() {
+    let selector: TagSelector = "role=db|cache,dc=eu-1".parse().unwrap();
+    assert!(selector.matches(&tags(&[("role", "db"), ("dc", "eu-1")])));
+    assert!(selector.matches(&tags(&[("role", "cache"), ("dc", "eu-1")])));
+    assert!(!selector.matches(&tags(&[("role", "db"), ("dc", "us-1")])));
+    assert!(!selector.matches(&tags(&[("role", "db")])));
+}
+
+#[test]
+fn not_equals_matches_missing_tags() {
+    let selector: TagSelector = "env!=staging".parse().unwrap();
+    assert!(selector.matches(&tags(&[])));
+    assert!(selector.matches(&tags(&[("env", "prod")])));
+    assert!(!selector.matches(&tags(&[("env", "staging")])));
+}
+
+#[test]
+fn star_targets_every_system() {
+    let selector: TagSelector = " * ".parse().unwrap();
+    assert!(selector.terms.is_empty());
+    assert!(selector.matches(&tags(&[])));
+    assert!(selector.matches(&tags(&[("role", "db")])));
+}
@@ -0,0 +1,99 @@
+use lynx_core::proto::monitor::SystemService;
+use lynx_core::services::service_graph::{build, impacted_by, DependencyKind, GraphQuery};
+use lynx_core::services::validation::{self, ValidationError, MAX_DEPENDENCIES};
+
+fn service(name: &str, state: &str, requires: &[&str], wants: &[&str]) -> SystemService {
+    let names = |units: &[&str]| units.iter().map(|u| u.to_string()).collect();
+    SystemService {
+        service_name: name.into(),
+        state: state.into(),
+        requires: names(requires),
+        wants: names(wants),
+        after: names(requires),
+        ..Default::default()
+    }
+}
+
+fn fleet() -> Vec<SystemService> {
+    vec![
+        service(
+            "postgresql.service",
+            "Failed",
+            &[],
+            &["network-online.target"],
+        ),
+        service("api.service", "Active", &["postgresql.service"], &[]),
+        service("worker.service", "Active", &[], &["api.service"]),
+        service("nginx.service", "Active", &[], &[]),
+    ]
+}
+
+#[test]
+fn failed_units_list_what_they_take_down() {
+    let graph = build(&fleet(), &GraphQuery::default());
+    assert_eq!(graph.source, "cache");
+    assert_eq!(graph.impact.len(), 1);
+    assert_eq!(
+        graph.impact["postgresql.service"],
+        ["api.service", "worker.service"]
+    );
+
+    // units that are only depended on are nodes without a state
+    let target = graph
+        .nodes
+        .iter()
+        .find(|n| n.name == "network-online.target")
+        .unwrap();
+    assert_eq!(target.state, None);
+    assert!(!target.failed);
+    assert_eq!(graph.nodes.len(), 5);
+
+    let api_edges: Vec<DependencyKind> = graph
+        .edges
+        .iter()
+        .filter(|e| e.from == "api.service")
+        .map(|e| e.kind)
+        .collect();
+    assert_eq!(api_edges, [DependencyKind::Requires, DependencyKind::After]);
+}
+
+#[test]
+fn impact_of_a_queried_unit() {
+    let query = GraphQuery {
+        unit: Some("api.service".to_string()),
+    };
+    let graph = build(&fleet(), &query);
+    assert_eq!(graph.impact.len(), 1);
+    assert_eq!(graph.impact["api.service"], ["worker.service"]);
+    assert!(impacted_by(&graph.edges, "nginx.service").is_empty());
+}
+
+#[test]
+fn ordering_alone_impacts_nothing_and_cycles_end() {
+    let mut units = vec![
+        service("a.service", "Failed", &["b.service"], &[]),
+        service("b.service", "Active", &["a.service"], &[]),
+    ];
+    units.push(SystemService {
+        service_name: "c.service".into(),
+        state: "Active".into(),
+        after: vec!["a.service".into()],
+        ..Default::default()
+    });
+    let graph = build(&units, &GraphQuery::default());
+    assert_eq!(graph.impact["a.service"], ["b.service"]);
+}
+
+#[test]
+fn dependency_lists_are_limited() {
+    let mut unit = service("big.service", "Active", &[], &[]);
+    unit.wants = vec!["x.service".to_string(); MAX_DEPENDENCIES + 1];
+    assert_eq!(
+        validation::services(&[unit]),
+        Err(ValidationError::TooMany {
+            field: "services.wants",
+            len: MAX_DEPENDENCIES + 1,
+            max: MAX_DEPENDENCIES,
+        })
+    );
+}
@@ -0,0 +1,273 @@
+use lynx_core::proto::monitor::session_frame::Frame;
+use lynx_core::proto::monitor::{SessionFrame, TunnelOpen};
+use lynx_core::services::sessions::{
+    SessionError, SessionRelay, Tunnel, TunnelRequest, DEFAULT_TUNNEL_TTL, MAX_TUNNEL_TTL,
+};
+use lynx_core::shutdown;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tonic::codegen::tokio_stream::wrappers::ReceiverStream;
+use tonic::codegen::tokio_stream::StreamExt;
+use tonic::Status;
+
+type AgentSide = (
+    mpsc::Sender<Result<SessionFrame, Status>>,
+    ReceiverStream<Result<SessionFrame, Status>>,
+);
+
+fn connect(relay: &SessionRelay, system_id: i32, shutdown: &shutdown::Shutdown) -> AgentSide {
+    let (tx, rx) = mpsc::channel(8);
+    let from_hub = relay.attach(system_id, ReceiverStream::new(rx), shutdown.clone());
+    (tx, from_hub)
+}
+
+async fn next_frame(from_hub: &mut ReceiverStream<Result<SessionFrame, Status>>) -> SessionFrame {
+    from_hub.next().await.expect("frame").expect("ok frame")
+}
+
+#[test]
+fn tickets_need_a_connected_system() {
+    let relay = SessionRelay::default();
+    assert_eq!(
+        relay.issue_ticket(3).unwrap_err(),
+        SessionError::NotConnected(3)
+    );
+}
+
+#[tokio::test]
+async fn tickets_are_single_use_and_bound_to_a_system() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let _agent = connect(&relay, 3, &shutdown);
+
+    let ticket = relay.issue_ticket(3).unwrap();
+    assert_eq!(
+        ticket.path,
+        format!("/systems/3/ws?ticket={}", ticket.ticket)
+    );
+    assert_eq!(
+        relay.redeem(4, &ticket.ticket),
+        Err(SessionError::InvalidTicket)
+    );
+
+    let ticket = relay.issue_ticket(3).unwrap();
+    assert_eq!(relay.redeem(3, &ticket.ticket), Ok(()));
+    assert_eq!(
+        relay.redeem(3, &ticket.ticket),
+        Err(SessionError::InvalidTicket)
+    );
+    assert_eq!(relay.redeem(3, "nope"), Err(SessionError::InvalidTicket));
+}
+
+#[tokio::test]
+async fn relays_frames_both_ways() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let (to_hub, mut from_hub) = connect(&relay, 3, &shutdown);
+
+    let mut session = relay.open(3).await.unwrap();
+    let open = next_frame(&mut from_hub).await;
+    assert_eq!(open.session_id, session.id);
+    assert_eq!(open.frame, Some(Frame::Open(true)));
+
+    session
+        .send(Frame::Text("{\"type\":\"live\"}".to_string()))
+        .await
+        .unwrap();
+    let text = next_frame(&mut from_hub).await;
+    assert_eq!(
+        text.frame,
+        Some(Frame::Text("{\"type\":\"live\"}".to_string()))
+    );
+
+    to_hub
+        .send(Ok(SessionFrame {
+            session_id: session.id.clone(),
+            frame: Some(Frame::Binary(vec![1, 2, 3])),
+        }))
+        .await
+        .unwrap();
+    assert_eq!(
+        session.from_agent.recv().await,
+        Some(Frame::Binary(vec![1, 2, 3]))
+    );
+
+    // frames for other sessions are not delivered
+    to_hub
+        .send(Ok(SessionFrame {
+            session_id: "other".to_string(),
+            frame: Some(Frame::Text("lost".to_string())),
+        }))
+        .await
+        .unwrap();
+    to_hub
+        .send(Ok(SessionFrame {
+            session_id: session.id.clone(),
+            frame: Some(Frame::Close("done".to_string())),
+        }))
+        .await
+        .unwrap();
+    assert_eq!(
+        session.from_agent.recv().await,
+        Some(Frame::Close("done".to_string()))
+    );
+    assert_eq!(session.from_agent.recv().await, None);
+}
+
+#[tokio::test]
+async fn dropping_a_session_closes_it_on_the_agent() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let (_to_hub, mut from_hub) = connect(&relay, 3, &shutdown);
+
+    let session = relay.open(3).await.unwrap();
+    let id = session.id.clone();
+    next_frame(&mut from_hub).await;
+    drop(session);
+
+    let close = next_frame(&mut from_hub).await;
+    assert_eq!(close.session_id, id);
+    assert!(matches!(close.frame, Some(Frame::Close(_))));
+}
+
+#[tokio::test]
+async fn agent_hanging_up_ends_its_sessions() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let (to_hub, _from_hub) = connect(&relay, 3, &shutdown);
+    let mut session = relay.open(3).await.unwrap();
+
+    drop(to_hub);
+    assert_eq!(session.from_agent.recv().await, None);
+    assert!(!relay.is_connected(3));
+    assert_eq!(
+        relay.open(3).await.err(),
+        Some(SessionError::NotConnected(3))
+    );
+}
+
+#[tokio::test]
+async fn a_reconnecting_agent_replaces_its_stream() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let (old_to_hub, _old_from_hub) = connect(&relay, 3, &shutdown);
+    let (_to_hub, mut from_hub) = connect(&relay, 3, &shutdown);
+
+    // the old stream ending must not unregister the new one
+    drop(old_to_hub);
+    tokio::task::yield_now().await;
+    assert!(relay.is_connected(3));
+    assert_eq!(relay.connected(), 1);
+
+    let session = relay.open(3).await.unwrap();
+    assert_eq!(next_frame(&mut from_hub).await.session_id, session.id);
+}
+
+#[test]
+fn tunnel_ttl_is_clamped() {
+    let request = |ttl_secs| TunnelRequest {
+        port: 8443,
+        ttl_secs,
+    };
+    assert_eq!(request(None).ttl(), DEFAULT_TUNNEL_TTL);
+    assert_eq!(request(Some(0)).ttl(), Duration::from_secs(1));
+    assert_eq!(request(Some(120)).ttl(), Duration::from_secs(120));
+    assert_eq!(request(Some(86_400)).ttl(), MAX_TUNNEL_TTL);
+}
+
+#[tokio::test]
+async fn tunnel_tickets_only_open_tunnels() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let _agent = connect(&relay, 3, &shutdown);
+
+    assert_eq!(
+        relay
+            .issue_tunnel_ticket(3, &TunnelRequest::default())
+            .unwrap_err(),
+        SessionError::InvalidPort(0)
+    );
+    let request = TunnelRequest {
+        port: 8443,
+        ttl_secs: Some(600),
+    };
+    let ticket = relay.issue_tunnel_ticket(3, &request).unwrap();
+    assert_eq!(
+        ticket.path,
+        format!("/systems/3/tunnel?ticket={}", ticket.ticket)
+    );
+    assert_eq!(ticket.ttl_secs, 600);
+    // a tunnel ticket is no websocket session ticket, and the other way round
+    assert_eq!(
+        relay.redeem(3, &ticket.ticket),
+        Err(SessionError::InvalidTicket)
+    );
+    let session = relay.issue_ticket(3).unwrap();
+    assert_eq!(
+        relay.redeem_tunnel(3, &session.ticket),
+        Err(SessionError::InvalidTicket)
+    );
+
+    let ticket = relay.issue_tunnel_ticket(3, &request).unwrap();
+    assert_eq!(
+        relay.redeem_tunnel(3, &ticket.ticket),
+        Ok(Tunnel {
+            port: 8443,
+            ttl: Duration::from_secs(600),
+        })
+    );
+    assert_eq!(
+        relay.redeem_tunnel(3, &ticket.ticket),
+        Err(SessionError::InvalidTicket)
+    );
+}
+
+#[tokio::test]
+async fn tunnels_open_with_their_port_and_ttl() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let (_to_hub, mut from_hub) = connect(&relay, 3, &shutdown);
+
+    let tunnel = Tunnel {
+        port: 8443,
+        ttl: Duration::from_secs(600),
+    };
+    let session = relay.open_tunnel(3, tunnel).await.unwrap();
+    let open = next_frame(&mut from_hub).await;
+    assert_eq!(open.session_id, session.id);
+    assert_eq!(
+        open.frame,
+        Some(Frame::Tunnel(TunnelOpen {
+            port: 8443,
+            ttl_secs: 600,
+        }))
+    );
+}
+
+#[tokio::test]
+async fn a_tunnel_falling_behind_is_closed() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let relay = SessionRelay::default();
+    let (to_hub, mut from_hub) = connect(&relay, 3, &shutdown);
+    let tunnel = Tunnel {
+        port: 8443,
+        ttl: Duration::from_secs(600),
+    };
+    let mut session = relay.open_tunnel(3, tunnel).await.unwrap();
+    next_frame(&mut from_hub).await;
+
+    // nobody reads the tunnel until the hub's queue overflowed
+    for _ in 0..100 {
+        to_hub
+            .send(Ok(SessionFrame {
+                session_id: session.id.clone(),
+                frame: Some(Frame::Binary(vec![0; 16])),
+            }))
+            .await
+            .unwrap();
+    }
+    let close = next_frame(&mut from_hub).await;
+    assert_eq!(close.session_id, session.id);
+    assert!(matches!(close.frame, Some(Frame::Close(_))));
+    while session.from_agent.recv().await.is_some() {}
+}
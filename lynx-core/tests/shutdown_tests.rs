@@ -0,0 +1,37 @@
+use lynx_core::shutdown;
+use std::time::Duration;
+use tokio::time::timeout;
+
+#[tokio::test]
+async fn wait_resolves_after_trigger() {
+    let (trigger, shutdown) = shutdown::channel();
+    assert!(!shutdown.is_triggered());
+
+    let waiter = {
+        let shutdown = shutdown.clone();
+        tokio::spawn(async move { shutdown.wait().await })
+    };
+    trigger.trigger();
+
+    timeout(Duration::from_secs(1), waiter)
+        .await
+        .expect("wait did not resolve")
+        .unwrap();
+    assert!(shutdown.is_triggered());
+}
+
+#[tokio::test]
+async fn wait_returns_immediately_once_triggered() {
+    let (trigger, shutdown) = shutdown::channel();
+    trigger.trigger();
+    timeout(Duration::from_millis(100), shutdown.wait())
+        .await
+        .expect("wait blocked after trigger");
+}
+
+#[tokio::test]
+async fn wait_blocks_until_triggered() {
+    let (_trigger, shutdown) = shutdown::channel();
+    let waited = timeout(Duration::from_millis(50), shutdown.wait()).await;
+    assert!(waited.is_err());
+}
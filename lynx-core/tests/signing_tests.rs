@@ -0,0 +1,293 @@
+use lynx_core::config::AgentRelease;
+use lynx_core::services::agent::{
+    render_install_script, script_shell, AgentBinary, InitSystem, InstallScriptError, InstallTarget,
+};
+use lynx_core::signing::{ReleaseSigner, SigningError};
+use openssl::pkey::PKey;
+
+fn verify(public_key_pem: &str, data: &[u8], signature: &str) -> bool {
+    let key = PKey::public_key_from_pem(public_key_pem.as_bytes()).unwrap();
+    let signature = openssl::base64::decode_block(signature).unwrap();
+    openssl::sign::Verifier::new_without_digest(&key)
+        .unwrap()
+        .verify_oneshot(&signature, data)
+        .unwrap()
+}
+
+fn write_key(dir: &std::path::Path, key: &PKey<openssl::pkey::Private>) -> std::path::PathBuf {
+    let path = dir.join("release.key");
+    std::fs::write(&path, key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+    path
+}
+
+#[test]
+fn release_signatures_verify_against_public_key() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_key(dir.path(), &PKey::generate_ed25519().unwrap());
+    let signer = ReleaseSigner::load(&path).expect("Ed25519 key should load");
+    let public_key = signer.public_key_pem().unwrap();
+
+    let signature = signer.sign(b"#!/bin/bash\necho hi\n").unwrap();
+    assert!(verify(&public_key, b"#!/bin/bash\necho hi\n", &signature));
+    assert!(!verify(
+        &public_key,
+        b"#!/bin/bash\necho pwned\n",
+        &signature
+    ));
+}
+
+#[test]
+fn release_signer_rejects_non_ed25519_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let rsa = PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+    let path = write_key(dir.path(), &rsa);
+    assert!(matches!(
+        ReleaseSigner::load(&path),
+        Err(SigningError::UnsupportedKey)
+    ));
+}
+
+fn binary(sha256: &str) -> AgentBinary {
+    AgentBinary {
+        url: "https://releases.lynx.local/lynx-agent-0.2.0".to_string(),
+        sha256: sha256.to_string(),
+        version: Some("0.2.0".to_string()),
+    }
+}
+
+const SERVER_URL: &str = "https://hub.lynx.local:50051";
+
+#[test]
+fn install_script_pins_binary_checksum() {
+    let sha = "ab".repeat(32);
+    let target = InstallTarget::default();
+
+    let script = render_install_script(
+        &binary(&sha),
+        &target,
+        SERVER_URL,
+        "agent-key",
+        "PUBLIC KEY\n",
+    )
+    .unwrap();
+    assert!(script.starts_with("#!/bin/bash"));
+    assert!(script.contains(&format!("BIN_SHA256=\"{sha}\"")));
+    assert!(script.contains("BIN_URL=\"https://releases.lynx.local/lynx-agent-0.2.0\""));
+    assert!(script.contains("Lynx Agent 0.2.0 (linux x86_64)"));
+    assert!(script.contains("sha256sum -c"));
+    assert!(script.contains("PUBLIC KEY\nEOF"));
+    assert!(script.contains("systemctl enable --now lynx-view-agent"));
+
+    assert!(matches!(
+        render_install_script(&binary("deadbeef"), &target, SERVER_URL, "agent-key", ""),
+        Err(InstallScriptError::InvalidSha256)
+    ));
+
+    let mut unsafe_url = binary(&sha);
+    unsafe_url.url = "https://x/$(id)".to_string();
+    assert!(matches!(
+        render_install_script(&unsafe_url, &target, SERVER_URL, "agent-key", ""),
+        Err(InstallScriptError::UnsafeValue(_))
+    ));
+}
+
+#[test]
+fn install_binary_from_config_needs_url_and_checksum() {
+    let mut release = AgentRelease {
+        bin_url: Some("https://releases.lynx.local/lynx-agent".to_string()),
+        bin_sha256: Some("ab".repeat(32)),
+        signing_key: None,
+        server_url: SERVER_URL.to_string(),
+    };
+    let binary = AgentBinary::from_config(&release).unwrap();
+    assert_eq!(binary.version, None);
+
+    release.bin_sha256 = None;
+    assert!(matches!(
+        AgentBinary::from_config(&release),
+        Err(InstallScriptError::NotConfigured("AGENT_BIN_SHA256"))
+    ));
+    release.bin_url = None;
+    assert!(matches!(
+        AgentBinary::from_config(&release),
+        Err(InstallScriptError::NotConfigured("AGENT_BIN_URL"))
+    ));
+}
+
+#[test]
+fn openrc_script_uses_supervise_daemon() {
+    let target = InstallTarget {
+        arch: "aarch64".to_string(),
+        init: Some(InitSystem::OpenRc),
+        ..Default::default()
+    };
+    let script =
+        render_install_script(&binary(&"ab".repeat(32)), &target, SERVER_URL, "key", "").unwrap();
+    assert!(script.contains("#!/sbin/openrc-run"));
+    assert!(script.contains("supervisor=supervise-daemon"));
+    assert!(script.contains("rc-update add lynx-view-agent default"));
+    assert!(script.contains("!= \"aarch64\""));
+    assert!(!script.contains("systemctl"));
+}
+
+#[test]
+fn windows_script_uses_powershell_and_nssm() {
+    let target = InstallTarget {
+        os: "windows".to_string(),
+        ..Default::default()
+    };
+    assert_eq!(target.init_system().unwrap(), InitSystem::Nssm);
+    assert_eq!(script_shell(InitSystem::Nssm), "powershell");
+
+    let sha = "AB".repeat(32);
+    let script =
+        render_install_script(&binary(&sha), &target, SERVER_URL, "key", "PUBLIC KEY\n").unwrap();
+    assert!(script.contains("Get-FileHash -Algorithm SHA256"));
+    // Get-FileHash output is lowercased before the comparison
+    assert!(script.contains(&format!("$BinSha256 = \"{}\"", "ab".repeat(32))));
+    assert!(script.contains("nssm install $ServiceName $InstallPath"));
+    assert!(script.contains("PUBLIC KEY\n'@"));
+    assert!(script.contains("-ne \"AMD64\""));
+}
+
+#[test]
+fn freebsd_script_installs_an_rc_d_service() {
+    let target: InstallTarget =
+        serde_json::from_str(r#"{"os": "freebsd", "arch": "aarch64"}"#).unwrap();
+    assert_eq!(target.init_system().unwrap(), InitSystem::RcD);
+    assert_eq!(script_shell(InitSystem::RcD), "sh");
+
+    let sha = "ab".repeat(32);
+    let script = render_install_script(&binary(&sha), &target, SERVER_URL, "key", "").unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains(&format!("BIN_SHA256=\"{sha}\"")));
+    assert!(script.contains("sha256 -q \"$TMP_BIN\""));
+    assert!(script.contains("!= \"arm64\""));
+    // rc.subr variables stay escaped for the script's heredoc
+    assert!(script.contains("pidfile=\"/var/run/\\${name}.pid\""));
+    assert!(script.contains("command_args=\"-f -R 5 -P \\${pidfile} $INSTALL_PATH\""));
+    assert!(script.contains("sysrc lynx_view_agent_enable=YES"));
+    assert!(!script.contains("bash"));
+    assert!(!script.contains("systemctl"));
+
+    let explicit: InstallTarget =
+        serde_json::from_str(r#"{"os": "freebsd", "init": "rc.d"}"#).unwrap();
+    assert_eq!(explicit.init_system().unwrap(), InitSystem::RcD);
+}
+
+#[test]
+fn unsupported_install_targets_are_refused() {
+    let cases = [
+        ("linux", "x86_64", Some(InitSystem::Nssm)),
+        ("windows", "x86_64", Some(InitSystem::Systemd)),
+        ("freebsd", "x86_64", Some(InitSystem::Systemd)),
+        ("openbsd", "x86_64", None),
+        ("linux", "riscv64", None),
+    ];
+    for (os, arch, init) in cases {
+        let target = InstallTarget {
+            os: os.to_string(),
+            arch: arch.to_string(),
+            init,
+            ..Default::default()
+        };
+        assert!(
+            matches!(
+                target.init_system(),
+                Err(InstallScriptError::UnsupportedTarget(_))
+            ),
+            "{target:?}"
+        );
+    }
+}
+
+#[test]
+fn unprivileged_script_runs_as_agent_user_with_polkit_rule() {
+    let target: InstallTarget = serde_json::from_str(r#"{"unprivileged": true}"#).unwrap();
+    let script =
+        render_install_script(&binary(&"ab".repeat(32)), &target, SERVER_URL, "key", "").unwrap();
+    assert!(script.contains("useradd --system"));
+    assert!(script.contains("User=$AGENT_USER"));
+    assert!(script.contains("org.freedesktop.systemd1.manage-units"));
+    assert!(script.contains("subject.user == \"lynx-agent\""));
+
+    let root = render_install_script(
+        &binary(&"ab".repeat(32)),
+        &InstallTarget::default(),
+        SERVER_URL,
+        "key",
+        "",
+    )
+    .unwrap();
+    assert!(!root.contains("polkit"));
+    assert!(!root.contains("User="));
+}
+
+#[test]
+fn unprivileged_agents_need_systemd() {
+    for (os, init) in [
+        ("linux", InitSystem::OpenRc),
+        ("windows", InitSystem::Nssm),
+        ("freebsd", InitSystem::RcD),
+    ] {
+        let target = InstallTarget {
+            os: os.to_string(),
+            init: Some(init),
+            unprivileged: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            target.init_system(),
+            Err(InstallScriptError::UnsupportedTarget(_))
+        ));
+    }
+}
+
+#[test]
+fn install_target_parses_from_the_request() {
+    let target: InstallTarget = serde_json::from_str(r#"{"init": "openrc"}"#).unwrap();
+    assert_eq!(target.os, "linux");
+    assert_eq!(target.arch, "x86_64");
+    assert_eq!(target.init_system().unwrap(), InitSystem::OpenRc);
+}
+
+#[test]
+fn release_manifest_is_relayed_as_signed() {
+    use lynx_core::services::releases::{self, SignedManifest};
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = write_key(dir.path(), &PKey::generate_ed25519().unwrap());
+    // what sign-release.sh does where the key lives
+    let signer = ReleaseSigner::load(&path).unwrap();
+    let manifest = format!(
+        r#"{{"channel":"beta","version":"0.2.0-beta.1","os":"linux","arch":"x86_64","url":"https://releases.lynx.local/lynx-agent","sha256":"{}","published":"2026-10-01T00:00:00Z"}}"#,
+        "ab".repeat(32)
+    );
+    let signature = signer.sign(manifest.as_bytes()).unwrap();
+
+    let relayed = releases::relay_manifest(SignedManifest {
+        manifest: manifest.clone(),
+        signature: format!("{signature}\n"),
+    })
+    .unwrap();
+    assert_eq!(relayed.manifest, manifest.as_bytes());
+    assert_eq!(relayed.signature.len(), 64);
+    let public_key = signer.public_key_pem().unwrap();
+    assert!(verify(&public_key, &relayed.manifest, &signature));
+
+    assert!(releases::relay_manifest(SignedManifest {
+        manifest,
+        signature: "not base64!".to_string(),
+    })
+    .is_none());
+}
+
+#[test]
+fn only_known_release_channels() {
+    use lynx_core::services::releases;
+
+    assert!(releases::is_channel("stable"));
+    assert!(releases::is_channel("beta"));
+    assert!(!releases::is_channel("nightly"));
+    assert!(!releases::is_channel(""));
+}
@@ -0,0 +1,50 @@
+use lynx_core::services::validation;
+use lynx_core::simulator::SimulatedSystem;
+
+#[test]
+fn same_seed_gives_same_stream() {
+    let mut a = SimulatedSystem::new(7);
+    let mut b = SimulatedSystem::new(7);
+    for i in 0..50 {
+        assert_eq!(a.sample(i), b.sample(i));
+    }
+}
+
+#[test]
+fn systems_differ_by_seed() {
+    let a = SimulatedSystem::new(1).sample(0);
+    let b = SimulatedSystem::new(2).sample(0);
+    assert_ne!(a, b);
+}
+
+#[test]
+fn samples_pass_report_validation() {
+    for seed in 0..20 {
+        let mut system = SimulatedSystem::new(seed);
+        for i in 0..500 {
+            let sample = system.sample(i * 10_000);
+            validation::metrics(&sample).unwrap();
+            let cpu = sample.cpu_stats.unwrap().usage_percent;
+            assert!((0.0..=100.0).contains(&cpu));
+            let memory = sample.memory_stats.unwrap();
+            assert!(memory.used_kb <= memory.total_kb);
+            assert_eq!(sample.collected_at_ms, Some(i * 10_000));
+        }
+    }
+}
+
+#[test]
+fn incidents_push_cpu_up() {
+    let mut system = SimulatedSystem::new(3);
+    let mut peak = 0.0_f64;
+    let mut incidents = 0;
+    for i in 0..2_000 {
+        let cpu = system.sample(i).cpu_stats.unwrap().usage_percent;
+        if system.in_incident() {
+            incidents += 1;
+            peak = peak.max(cpu);
+        }
+    }
+    assert!(incidents > 0);
+    assert!(peak > 80.0, "peak {peak}");
+}
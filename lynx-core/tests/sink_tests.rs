@@ -0,0 +1,73 @@
+use chrono::{TimeZone, Utc};
+use lynx_core::proto::monitor::{Component, MetricsRequest};
+use lynx_core::services::ingest::{DiskEntry, MetricIngestItem};
+use lynx_core::sinks::influx::to_line_protocol;
+
+#[test]
+fn influx_line_protocol_escapes_tags() {
+    let item = MetricIngestItem {
+        system_id: 7,
+        time: Utc.timestamp_opt(1_700_000_000, 0).unwrap(),
+        cpu_usage: 12.5,
+        cpu_user: Some(10.0),
+        cpu_system: Some(2.5),
+        cpu_iowait: None,
+        cpu_irq: None,
+        cpu_steal: None,
+        memory_used_bytes: 1_048_576,
+        memory_total_bytes: 4_194_304,
+        memory_available_bytes: Some(2_097_152),
+        swap_used_bytes: None,
+        components_json: "[]".to_string(),
+        processes: Some(120),
+        threads: Some(300),
+        procs_running: Some(2),
+        procs_zombie: Some(0),
+        procs_blocked: None,
+        ctxt_per_sec: None,
+        intr_per_sec: None,
+        entropy_avail: None,
+        net_in_bytes_per_sec: 1536.5,
+        net_out_bytes_per_sec: 20480.0,
+        load_one: 0.5,
+        load_five: 0.25,
+        load_fifteen: 0.125,
+        clock_skew_ms: None,
+        request_id: None,
+        disks: vec![DiskEntry {
+            name: "Data Disk".to_string(),
+            total_bytes: 100,
+            used_bytes: 40,
+            read_bytes: 1.5,
+            write_bytes: 2.5,
+            mount_point: "/mnt/a,b".to_string(),
+            read_iops: Some(3.0),
+            write_iops: None,
+            inodes_total: Some(1000),
+            inodes_used: Some(250),
+        }],
+        original: MetricsRequest {
+            components: vec![Component {
+                label: "cpu temp=1".to_string(),
+                temperature: 55.0,
+            }],
+            ..Default::default()
+        },
+    };
+
+    let lines = to_line_protocol(&[item]);
+    let lines: Vec<&str> = lines.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(
+        lines[0],
+        "lynx_system,system_id=7 cpu_usage=12.5,memory_used_bytes=1048576i,memory_total_bytes=4194304i,net_in_bytes_per_sec=1536.5,net_out_bytes_per_sec=20480,load_one=0.5,load_five=0.25,load_fifteen=0.125 1700000000000000000"
+    );
+    assert_eq!(
+        lines[1],
+        "lynx_disk,system_id=7,name=Data\\ Disk,mount_point=/mnt/a\\,b total_bytes=100i,used_bytes=40i,read_bytes=1.5,write_bytes=2.5,read_iops=3,inodes_total=1000i,inodes_used=250i 1700000000000000000"
+    );
+    assert_eq!(
+        lines[2],
+        "lynx_component,system_id=7,label=cpu\\ temp\\=1 temperature=55 1700000000000000000"
+    );
+}
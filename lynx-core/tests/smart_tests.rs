@@ -0,0 +1,110 @@
+use lynx_core::notify::{MetricComponent, RuleParser, SmartComponent, SMART_WINDOWS};
+use lynx_core::proto::monitor::SmartDevice;
+use std::collections::HashMap;
+
+fn drive(device: &str, serial: &str, reallocated: u64, pending: u64) -> SmartDevice {
+    SmartDevice {
+        device: device.into(),
+        serial: serial.into(),
+        passed: true,
+        reallocated_sectors: Some(reallocated),
+        pending_sectors: Some(pending),
+        temperature: Some(38.0),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn worst_drive_counts() {
+    let mut failing = drive("/dev/sdb", "B", 12, 0);
+    failing.passed = false;
+    failing.temperature = Some(51.0);
+    let smart = SmartComponent::new(vec![drive("/dev/sda", "A", 0, 3), failing], HashMap::new());
+
+    assert_eq!(smart.get_metric("failed").await.unwrap(), 1.0);
+    assert_eq!(smart.get_metric("reallocated_sectors").await.unwrap(), 12.0);
+    assert_eq!(smart.get_metric("pending_sectors").await.unwrap(), 3.0);
+    assert_eq!(smart.get_metric("temperature").await.unwrap(), 51.0);
+    // ATA drives have no NVMe health log
+    assert!(smart.get_metric("media_errors").await.is_err());
+    assert!(smart.get_metric("percentage_used").await.is_err());
+    assert!(smart.get_metric("sectors").await.is_err());
+}
+
+#[tokio::test]
+async fn deltas_compare_with_the_oldest_reading_in_the_window() {
+    let baselines = HashMap::from([
+        // matched by serial, the drive moved from sdc to sda since
+        (
+            "delta_7d",
+            vec![drive("/dev/sdc", "A", 2, 0), drive("/dev/sdb", "B", 7, 0)],
+        ),
+        ("delta_24h", vec![drive("/dev/sda", "A", 8, 0)]),
+    ]);
+    let smart = SmartComponent::new(
+        vec![
+            drive("/dev/sda", "A", 8, 0),
+            drive("/dev/sdb", "B", 7, 0),
+            // no history yet
+            drive("/dev/sdd", "D", 40, 40),
+        ],
+        baselines,
+    );
+
+    assert_eq!(
+        smart
+            .get_metric("reallocated_sectors.delta_7d")
+            .await
+            .unwrap(),
+        6.0
+    );
+    assert_eq!(
+        smart
+            .get_metric("reallocated_sectors.delta_24h")
+            .await
+            .unwrap(),
+        0.0
+    );
+    assert_eq!(
+        smart.get_metric("pending_sectors.delta_7d").await.unwrap(),
+        0.0
+    );
+    assert!(smart.get_metric("media_errors.delta_7d").await.is_err());
+}
+
+#[tokio::test]
+async fn drives_without_a_serial_are_matched_by_name() {
+    let baselines = HashMap::from([("delta_24h", vec![drive("/dev/sda", "", 1, 0)])]);
+    let smart = SmartComponent::new(vec![drive("/dev/sda", "", 4, 0)], baselines);
+    assert_eq!(
+        smart
+            .get_metric("reallocated_sectors.delta_24h")
+            .await
+            .unwrap(),
+        3.0
+    );
+}
+
+#[test]
+fn rules_address_windows() {
+    for (window, _) in SMART_WINDOWS {
+        let expression = format!("smart.pending_sectors.{window} > 0");
+        assert!(
+            RuleParser::parse_expression(&expression).is_ok(),
+            "{expression}"
+        );
+    }
+    let conditions =
+        RuleParser::parse_expression("smart.reallocated_sectors.delta_7d > 0").unwrap();
+    assert_eq!(conditions[0].component, "smart");
+    assert_eq!(conditions[0].metric, "reallocated_sectors.delta_7d");
+
+    let e = RuleParser::parse_expression("smart.reallocated_sectors.delta_3d > 0").unwrap_err();
+    assert_eq!(e.token, "reallocated_sectors.delta_3d");
+    assert_eq!(
+        e.suggestion.as_deref(),
+        Some("did you mean reallocated_sectors.delta_7d?")
+    );
+    // custom metric names have no suffixes
+    assert!(RuleParser::parse_expression("custom.queue.depth > 1").is_err());
+}
@@ -0,0 +1,84 @@
+use lynx_core::snmp::{decode_response, encode_get, encode_oid, SnmpValue};
+
+#[test]
+fn encodes_multi_byte_oid_arcs() {
+    // sysUpTime.0 and an arc above 127 that needs two base-128 bytes
+    assert_eq!(
+        encode_oid("1.3.6.1.2.1.1.3.0").unwrap(),
+        vec![0x06, 0x08, 0x2b, 0x06, 0x01, 0x02, 0x01, 0x01, 0x03, 0x00]
+    );
+    assert_eq!(
+        encode_oid(".1.3.6.1.4.1.2021").unwrap(),
+        vec![0x06, 0x07, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x8f, 0x65]
+    );
+    assert!(encode_oid("1.40.2").is_err());
+    assert!(encode_oid("1.3.x").is_err());
+}
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag, content.len() as u8];
+    out.extend_from_slice(content);
+    out
+}
+
+fn varbind(oid: &str, tag: u8, value: &[u8]) -> Vec<u8> {
+    let mut bind = encode_oid(oid).unwrap();
+    bind.extend(tlv(tag, value));
+    tlv(0x30, &bind)
+}
+
+fn get_response(request_id: u8, status: u8, binds: &[Vec<u8>]) -> Vec<u8> {
+    let mut pdu = tlv(0x02, &[request_id]);
+    pdu.extend(tlv(0x02, &[status]));
+    pdu.extend(tlv(0x02, &[0]));
+    pdu.extend(tlv(0x30, &binds.concat()));
+    let mut message = tlv(0x02, &[1]);
+    message.extend(tlv(0x04, b"public"));
+    message.extend(tlv(0xa2, &pdu));
+    tlv(0x30, &message)
+}
+
+#[test]
+fn get_request_carries_community_and_oids() {
+    let request = encode_get(7, "secret", &["1.3.6.1.2.1.1.3.0".to_string()]).unwrap();
+    assert_eq!(request[0], 0x30);
+    assert!(request.windows(6).any(|w| w == b"secret"));
+    assert!(request.ends_with(&[0x03, 0x00, 0x05, 0x00]));
+}
+
+#[test]
+fn decodes_get_response_values() {
+    let response = get_response(
+        42,
+        0,
+        &[
+            varbind("1.3.6.1.2.1.1.3.0", 0x43, &[0x01, 0x2c]),
+            varbind(
+                "1.3.6.1.2.1.31.1.1.1.6.1",
+                0x46,
+                &[0x00, 0xff, 0xff, 0xff, 0xff],
+            ),
+            varbind("1.3.6.1.4.1.1.1", 0x02, &[0xfe]),
+            varbind("1.3.6.1.4.1.1.2", 0x04, b" 41.5"),
+            varbind("1.3.6.1.4.1.1.3", 0x81, &[]),
+        ],
+    );
+
+    let values = decode_response(&response, 42).unwrap();
+    assert_eq!(values["1.3.6.1.2.1.1.3.0"], SnmpValue::Unsigned(300));
+    assert_eq!(
+        values["1.3.6.1.2.1.31.1.1.1.6.1"],
+        SnmpValue::Unsigned(0xffff_ffff)
+    );
+    assert_eq!(values["1.3.6.1.4.1.1.1"], SnmpValue::Integer(-2));
+    assert_eq!(values["1.3.6.1.4.1.1.2"].as_f64(), Some(41.5));
+    assert_eq!(values["1.3.6.1.4.1.1.3"], SnmpValue::Missing);
+}
+
+#[test]
+fn rejects_mismatched_or_failed_responses() {
+    let bind = varbind("1.3.6.1.2.1.1.3.0", 0x43, &[0x01]);
+    assert!(decode_response(&get_response(42, 0, std::slice::from_ref(&bind)), 43).is_err());
+    assert!(decode_response(&get_response(42, 2, &[bind]), 42).is_err());
+    assert!(decode_response(&[0x30, 0x10, 0x02], 42).is_err());
+}
@@ -0,0 +1,84 @@
+use lynx_core::cache::CacheStats;
+use lynx_core::telemetry::{Histogram, Telemetry, MAX_RPC_METHODS};
+use std::time::Duration;
+
+fn cache_stats(hits: u64, misses: u64) -> CacheStats {
+    CacheStats {
+        services: 0,
+        system_ids: 0,
+        systems: 0,
+        logs: 0,
+        config_changes: 0,
+        approx_memory_bytes: 0,
+        hits,
+        misses,
+        hit_ratio: 0.0,
+    }
+}
+
+#[test]
+fn histogram_buckets_are_cumulative() {
+    let telemetry = Telemetry::default();
+    telemetry.record_rpc(
+        "/monitor.MetricsIngest/ReportMetrics",
+        Duration::from_millis(3),
+    );
+    telemetry.record_rpc(
+        "/monitor.MetricsIngest/ReportMetrics",
+        Duration::from_secs(20),
+    );
+
+    let out = telemetry.render(0, 10, &cache_stats(0, 0));
+    let method = "method=\"/monitor.MetricsIngest/ReportMetrics\"";
+    assert!(out.contains(&format!(
+        "lynx_rpc_duration_seconds_bucket{{{method},le=\"0.001\"}} 0"
+    )));
+    assert!(out.contains(&format!(
+        "lynx_rpc_duration_seconds_bucket{{{method},le=\"0.005\"}} 1"
+    )));
+    assert!(out.contains(&format!(
+        "lynx_rpc_duration_seconds_bucket{{{method},le=\"10\"}} 1"
+    )));
+    assert!(out.contains(&format!(
+        "lynx_rpc_duration_seconds_bucket{{{method},le=\"+Inf\"}} 2"
+    )));
+    assert!(out.contains(&format!("lynx_rpc_duration_seconds_count{{{method}}} 2")));
+}
+
+#[test]
+fn histogram_counts_observations() {
+    let histogram = Histogram::default();
+    histogram.observe(Duration::from_millis(1));
+    histogram.observe(Duration::ZERO);
+    assert_eq!(histogram.count(), 2);
+}
+
+#[test]
+fn rpc_methods_are_capped() {
+    let telemetry = Telemetry::default();
+    for i in 0..MAX_RPC_METHODS + 10 {
+        telemetry.record_rpc(&format!("/junk/{i}"), Duration::from_millis(1));
+    }
+    let out = telemetry.render(0, 10, &cache_stats(0, 0));
+    assert!(out.contains("lynx_rpc_duration_seconds_count{method=\"other\"} 10"));
+}
+
+#[test]
+fn flushes_and_notifications_are_counted() {
+    let telemetry = Telemetry::default();
+    telemetry.record_flush(100, Duration::from_millis(20), true);
+    telemetry.record_flush(50, Duration::from_millis(20), false);
+    telemetry.record_notification("discord", true);
+    telemetry.record_notification("discord", false);
+
+    let out = telemetry.render(7, 10_000, &cache_stats(9, 1));
+    assert!(out.contains("lynx_ingest_items_total 100\n"));
+    assert!(out.contains("lynx_ingest_flush_failures_total 1\n"));
+    assert!(out.contains("lynx_ingest_flush_duration_seconds_count 2\n"));
+    assert!(out.contains("lynx_ingest_queue_depth 7\n"));
+    assert!(out.contains("lynx_ingest_queue_capacity 10000\n"));
+    assert!(out.contains("lynx_notifications_total{kind=\"discord\"} 2\n"));
+    assert!(out.contains("lynx_notification_failures_total{kind=\"discord\"} 1\n"));
+    assert!(out.contains("lynx_cache_hits_total 9\n"));
+    assert!(out.contains("lynx_cache_misses_total 1\n"));
+}
@@ -19,3 +19,150 @@ fn tls_config_errors_on_missing_dir() {
         .expect("Expected error for missing cert dir");
     assert!(err.to_string().contains("Certificates directory not found"));
 }
+
+fn generate_ca(dir: &std::path::Path) {
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::extension::BasicConstraints;
+    use openssl::x509::{X509NameBuilder, X509};
+
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "lynx-test-ca").unwrap();
+    let name = name.build();
+    let not_before = Asn1Time::days_from_now(0).unwrap();
+    let not_after = Asn1Time::days_from_now(30).unwrap();
+    let mut builder = X509::builder().unwrap();
+    builder.set_version(2).unwrap();
+    builder.set_subject_name(&name).unwrap();
+    builder.set_issuer_name(&name).unwrap();
+    builder.set_pubkey(&key).unwrap();
+    builder.set_not_before(&not_before).unwrap();
+    builder.set_not_after(&not_after).unwrap();
+    builder
+        .append_extension(BasicConstraints::new().critical().ca().build().unwrap())
+        .unwrap();
+    builder.sign(&key, MessageDigest::sha256()).unwrap();
+
+    std::fs::write(dir.join("ca.crt"), builder.build().to_pem().unwrap()).unwrap();
+    std::fs::write(dir.join("ca.key"), key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+}
+
+#[test]
+fn ca_signs_agent_csr() {
+    use lynx_core::tls::CertificateAuthority;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::x509::{X509Req, X509};
+
+    let dir = tempfile::tempdir().unwrap();
+    generate_ca(dir.path());
+    let ca = CertificateAuthority::load(dir.path()).expect("CA should load");
+
+    let agent_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+    let mut req = X509Req::builder().unwrap();
+    req.set_pubkey(&agent_key).unwrap();
+    req.sign(&agent_key, MessageDigest::sha256()).unwrap();
+    let csr_pem = String::from_utf8(req.build().to_pem().unwrap()).unwrap();
+
+    let cert_pem = ca
+        .sign_csr(&csr_pem, "agent-01", 30)
+        .expect("CSR should be signed");
+    let cert = X509::from_pem(cert_pem.as_bytes()).unwrap();
+    let ca_cert = X509::from_pem(ca.cert_pem().as_bytes()).unwrap();
+    assert!(cert.verify(&ca_cert.public_key().unwrap()).unwrap());
+    let cn = cert
+        .subject_name()
+        .entries_by_nid(openssl::nid::Nid::COMMONNAME)
+        .next()
+        .unwrap();
+    assert_eq!(cn.data().to_string().unwrap(), "agent-01");
+
+    assert!(ca.sign_csr("not a csr", "agent-01", 30).is_err());
+}
+
+#[test]
+fn ca_signatures_verify_against_its_certificate() {
+    use lynx_core::tls::CertificateAuthority;
+    use openssl::hash::MessageDigest;
+    use openssl::sign::Verifier;
+    use openssl::x509::X509;
+
+    let dir = tempfile::tempdir().unwrap();
+    generate_ca(dir.path());
+    let ca = CertificateAuthority::load(dir.path()).expect("CA should load");
+
+    let signature = ca.sign(b"{\"action\":\"delete\"}").unwrap();
+    let public_key = X509::from_pem(ca.cert_pem().as_bytes())
+        .unwrap()
+        .public_key()
+        .unwrap();
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+    assert!(verifier
+        .verify_oneshot(&signature, b"{\"action\":\"delete\"}")
+        .unwrap());
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &public_key).unwrap();
+    assert!(!verifier
+        .verify_oneshot(&signature, b"{\"action\":\"execute\"}")
+        .unwrap());
+}
+
+#[test]
+fn inspect_certs_reports_days_remaining() {
+    use lynx_core::tls::inspect_certs;
+
+    let dir = tempfile::tempdir().unwrap();
+    generate_ca(dir.path());
+    let certs = inspect_certs(dir.path());
+    assert_eq!(certs.len(), 1);
+    assert_eq!(certs[0].file, "ca.crt");
+    assert!(certs[0].subject.contains("lynx-test-ca"));
+    assert!(certs[0].days_remaining > 29.0 && certs[0].days_remaining <= 30.0);
+}
+
+#[test]
+fn pem_and_der_fingerprints_match() {
+    use lynx_core::tls::{cert_fingerprint, pem_fingerprint};
+
+    let dir = tempfile::tempdir().unwrap();
+    generate_ca(dir.path());
+    let pem = std::fs::read_to_string(dir.path().join("ca.crt")).unwrap();
+    let der = openssl::x509::X509::from_pem(pem.as_bytes())
+        .unwrap()
+        .to_der()
+        .unwrap();
+
+    let fp = pem_fingerprint(&pem).unwrap();
+    assert_eq!(fp, cert_fingerprint(&der));
+    assert_eq!(fp.len(), 64);
+}
+
+#[test]
+fn crypto_policy_rejects_invalid_options() {
+    use lynx_core::config::TlsOptions;
+    use lynx_core::tls::{install_crypto_policy, TlsPolicyError};
+
+    let opts = |min_version: &str, suites: &[&str]| TlsOptions {
+        min_version: min_version.to_string(),
+        cipher_suites: suites.iter().map(|s| s.to_string()).collect(),
+        crl_files: Vec::new(),
+        ocsp: false,
+    };
+
+    assert!(matches!(
+        install_crypto_policy(&opts("1.1", &[])),
+        Err(TlsPolicyError::InvalidVersion(_))
+    ));
+    assert!(matches!(
+        install_crypto_policy(&opts("1.3", &["TLS_RSA_WITH_RC4_128_MD5"])),
+        Err(TlsPolicyError::UnknownSuite(_))
+    ));
+    // a TLS 1.2 only suite can't satisfy a TLS 1.3 minimum
+    assert!(matches!(
+        install_crypto_policy(&opts("1.3", &["TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"])),
+        Err(TlsPolicyError::NoSuites)
+    ));
+}
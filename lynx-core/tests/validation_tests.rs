@@ -0,0 +1,198 @@
+use lynx_core::proto::monitor::{
+    CpuStats, DiskStats, GpuMetrics, LoadAverage, MemoryStats, MetricsRequest, NetworkStats,
+    SystemService, TopProcess,
+};
+use lynx_core::services::validation::{self, ValidationError, MAX_DISKS, MAX_TOP_PROCESSES};
+use tonic::Code;
+
+fn report() -> MetricsRequest {
+    MetricsRequest {
+        cpu_stats: Some(CpuStats {
+            usage_percent: 12.5,
+            iowait_percent: Some(1.0),
+            ..Default::default()
+        }),
+        memory_stats: Some(MemoryStats {
+            total_kb: 8_000_000,
+            used_kb: 2_000_000,
+            free_kb: 6_000_000,
+            available_kb: Some(5_500_000),
+            ..Default::default()
+        }),
+        network_stats: Some(NetworkStats {
+            r#in: 10,
+            out: 20,
+            in_bytes_per_sec: Some(10_485_760.0),
+            out_bytes_per_sec: Some(20_971_520.0),
+        }),
+        load_average: Some(LoadAverage {
+            one_minute: 0.5,
+            five_minutes: 0.4,
+            fifteen_minutes: 0.3,
+        }),
+        disk_stats: vec![DiskStats {
+            name: "sda1".into(),
+            total_space: 100,
+            used_space: 40,
+            unit: "GB".into(),
+            mount_point: "/".into(),
+            inodes_total: Some(1000),
+            inodes_used: Some(10),
+            total_bytes: Some(107_374_182_400),
+            used_bytes: Some(42_949_672_960),
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn accepts_a_well_formed_report() {
+    assert_eq!(validation::metrics(&report()), Ok(()));
+}
+
+#[test]
+fn rejects_missing_sections() {
+    let mut m = report();
+    m.load_average = None;
+    let err = validation::metrics(&m).unwrap_err();
+    assert_eq!(err, ValidationError::Missing("load_average"));
+
+    let status: tonic::Status = err.into();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
+
+#[test]
+fn rejects_out_of_range_values() {
+    let mut m = report();
+    m.cpu_stats.as_mut().unwrap().usage_percent = f64::NAN;
+    assert_eq!(
+        validation::metrics(&m).unwrap_err().field(),
+        "cpu_stats.usage_percent"
+    );
+
+    let mut m = report();
+    m.cpu_stats.as_mut().unwrap().steal_percent = Some(140.0);
+    assert_eq!(
+        validation::metrics(&m).unwrap_err().field(),
+        "cpu_stats.steal_percent"
+    );
+
+    let mut m = report();
+    m.memory_stats.as_mut().unwrap().used_kb = 9_000_000;
+    assert_eq!(
+        validation::metrics(&m).unwrap_err(),
+        ValidationError::ExceedsTotal("memory_stats.used_kb")
+    );
+
+    let mut m = report();
+    m.load_average.as_mut().unwrap().five_minutes = -1.0;
+    assert_eq!(
+        validation::metrics(&m).unwrap_err().field(),
+        "load_average.five_minutes"
+    );
+
+    let mut m = report();
+    m.disk_stats[0].inodes_used = Some(2000);
+    assert_eq!(
+        validation::metrics(&m).unwrap_err(),
+        ValidationError::ExceedsTotal("disk_stats.inodes_used")
+    );
+
+    let mut m = report();
+    m.disk_stats[0].used_bytes = Some(107_374_182_401);
+    assert_eq!(
+        validation::metrics(&m).unwrap_err(),
+        ValidationError::ExceedsTotal("disk_stats.used_bytes")
+    );
+
+    let mut m = report();
+    m.network_stats.as_mut().unwrap().out_bytes_per_sec = Some(f64::INFINITY);
+    assert_eq!(
+        validation::metrics(&m).unwrap_err().field(),
+        "network_stats.out_bytes_per_sec"
+    );
+}
+
+#[test]
+fn rejects_oversized_lists() {
+    let mut m = report();
+    m.disk_stats = vec![m.disk_stats[0].clone(); MAX_DISKS + 1];
+    assert!(matches!(
+        validation::metrics(&m).unwrap_err(),
+        ValidationError::TooMany {
+            field: "disk_stats",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn checks_top_processes() {
+    let process = TopProcess {
+        pid: 42,
+        name: "postgres".into(),
+        cpu_usage: 250.0,
+        memory_kb: 204_800,
+    };
+    let mut m = report();
+    m.top_processes = vec![process.clone(); MAX_TOP_PROCESSES];
+    assert_eq!(validation::metrics(&m), Ok(()));
+
+    m.top_processes.push(process.clone());
+    assert!(matches!(
+        validation::metrics(&m).unwrap_err(),
+        ValidationError::TooMany {
+            field: "top_processes",
+            ..
+        }
+    ));
+
+    m.top_processes = vec![TopProcess {
+        cpu_usage: f32::NAN,
+        ..process
+    }];
+    assert!(matches!(
+        validation::metrics(&m).unwrap_err(),
+        ValidationError::OutOfRange {
+            field: "top_processes.cpu_usage",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn checks_gpu_metrics_and_services() {
+    let gpu = GpuMetrics {
+        gpu_index: 0,
+        utilization: 250.0,
+        memory_used_mb: 0,
+        temperature: 60.0,
+        power: 100.0,
+    };
+    assert_eq!(
+        validation::gpu_metrics(&[gpu]).unwrap_err().field(),
+        "gpu_metrics.utilization"
+    );
+
+    let unnamed = SystemService {
+        service_name: String::new(),
+        ..Default::default()
+    };
+    assert_eq!(
+        validation::services(&[unnamed]),
+        Err(ValidationError::Empty("services.service_name"))
+    );
+    assert!(validation::removed_services(&["old.service".to_string()]).is_ok());
+    assert_eq!(
+        validation::removed_services(&[String::new()]),
+        Err(ValidationError::Empty("removed"))
+    );
+    assert!(validation::streamed_services(validation::MAX_SERVICES).is_ok());
+    assert_eq!(
+        validation::streamed_services(validation::MAX_SERVICES + 1)
+            .unwrap_err()
+            .field(),
+        "services"
+    );
+}
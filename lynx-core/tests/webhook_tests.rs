@@ -0,0 +1,24 @@
+use lynx_core::notify::{sign_payload, NotificationServiceType};
+
+#[test]
+fn signature_is_hmac_sha256_of_timestamp_and_body() {
+    let signature = sign_payload("topsecret", 1_700_000_000, r#"{"text":"hi"}"#).unwrap();
+    assert_eq!(
+        signature,
+        "sha256=50ca5eb6c82b9398df9cc00607201461e4477ab84600060e056de78073be92f7"
+    );
+    assert_ne!(
+        sign_payload("topsecret", 1_700_000_001, r#"{"text":"hi"}"#).unwrap(),
+        signature
+    );
+}
+
+#[test]
+fn webhook_urls_are_notifiers() {
+    let service =
+        NotificationServiceType::from_url("webhook+https://hooks.example.org/lynx?secret=abc&x=1")
+            .unwrap_or_else(|e| panic!("{e}"));
+    assert_eq!(service.kind(), "webhook");
+    assert!(NotificationServiceType::from_url("webhook+http://10.0.0.5:8080/hook").is_ok());
+    assert!(NotificationServiceType::from_url("webhook+ftp://example.org").is_err());
+}
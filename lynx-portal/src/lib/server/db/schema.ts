@@ -10,6 +10,8 @@ import {
 	doublePrecision,
 	bigint,
 	index,
+	uniqueIndex,
+	jsonb,
 	customType, date
 } from 'drizzle-orm/pg-core';
 import { sql } from "drizzle-orm"
@@ -52,6 +54,9 @@ export const alertRules = pgTable("alert_rules", {
 	userId: integer("user_id").notNull(),
 	expression: text().notNull(),
 	severity: text().notNull(),
+	target: text(),
+	forSecs: integer("for_secs").default(0).notNull(),
+	annotations: jsonb().default({}).notNull(),
 	active: boolean().default(false),
 	created: timestamp({ mode: 'string' }).defaultNow(),
 	updated: timestamp({ mode: 'string' }).defaultNow(),
@@ -168,6 +173,113 @@ export const containers = pgTable("containers", {
 	unique("containers_system_idx_unique").on(table.id, table.systemId),
 ]);
 
+export const probeResults = pgTable("probe_results", {
+	time: timestamp({ withTimezone: true, mode: 'string' }).notNull(),
+	systemId: integer("system_id").notNull(),
+	name: text().notNull(),
+	address: text().notNull(),
+	up: boolean().notNull(),
+	latencyMs: doublePrecision("latency_ms"),
+}, (table) => [
+	foreignKey({
+		columns: [table.systemId],
+		foreignColumns: [systems.id],
+		name: "probe_results_system_fk"
+	}).onDelete("cascade"),
+]);
+
+export const agentConfig = pgTable("agent_config", {
+	id: serial().primaryKey().notNull(),
+	systemId: integer("system_id"),
+	config: jsonb().default({}).notNull(),
+	updated: timestamp({ withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+}, (table) => [
+	uniqueIndex("agent_config_system_idx").using("btree", sql`COALESCE(${table.systemId}, 0)`),
+	foreignKey({
+		columns: [table.systemId],
+		foreignColumns: [systems.id],
+		name: "agent_config_system_fk"
+	}).onDelete("cascade"),
+]);
+
+export const customMetrics = pgTable("custom_metrics", {
+	time: timestamp({ withTimezone: true, mode: 'string' }).notNull(),
+	systemId: integer("system_id").notNull(),
+	name: text().notNull(),
+	value: doublePrecision().notNull(),
+	labels: jsonb().default({}).notNull(),
+}, (table) => [
+	index("custom_metrics_system_name_time_idx").using("btree", table.systemId.asc().nullsLast().op("int4_ops"), table.name.asc().nullsLast().op("text_ops"), table.time.desc().nullsFirst().op("timestamptz_ops")),
+	foreignKey({
+		columns: [table.systemId],
+		foreignColumns: [systems.id],
+		name: "custom_metrics_system_fk"
+	}).onDelete("cascade"),
+]);
+
+export const authEvents = pgTable("auth_events", {
+	id: integer().primaryKey().generatedAlwaysAsIdentity(),
+	time: timestamp({ withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+	event: text().notNull(),
+	sourceIp: text("source_ip"),
+	keyId: text("key_id").notNull(),
+	system: integer(),
+}, (table) => [
+	index("auth_events_time_idx").using("btree", table.time.asc().nullsLast().op("timestamptz_ops")),
+	foreignKey({
+		columns: [table.system],
+		foreignColumns: [systems.id],
+		name: "auth_events_system_fk"
+	}).onDelete("set null"),
+]);
+
+export const rejectedReports = pgTable("rejected_reports", {
+	id: integer().primaryKey().generatedAlwaysAsIdentity(),
+	time: timestamp({ withTimezone: true, mode: 'string' }).defaultNow().notNull(),
+	system: integer().notNull(),
+	report: text().notNull(),
+	field: text().notNull(),
+	reason: text().notNull(),
+}, (table) => [
+	index("rejected_reports_system_time_idx").using("btree", table.system.asc().nullsLast().op("int4_ops"), table.time.asc().nullsLast().op("timestamptz_ops")),
+	foreignKey({
+		columns: [table.system],
+		foreignColumns: [systems.id],
+		name: "rejected_reports_system_fk"
+	}).onDelete("cascade"),
+]);
+
+export const snmpDevices = pgTable("snmp_devices", {
+	id: integer().primaryKey().generatedAlwaysAsIdentity(),
+	systemId: integer("system_id").notNull(),
+	host: text().notNull(),
+	port: integer().default(161).notNull(),
+	community: text().default('public').notNull(),
+	intervalSecs: integer("interval_secs").default(60).notNull(),
+	oids: jsonb().default({}).notNull(),
+	active: boolean().default(true).notNull(),
+}, (table) => [
+	foreignKey({
+		columns: [table.systemId],
+		foreignColumns: [systems.id],
+		name: "snmp_devices_system_fk"
+	}).onDelete("cascade"),
+]);
+
+export const prometheusTargets = pgTable("prometheus_targets", {
+	id: integer().primaryKey().generatedAlwaysAsIdentity(),
+	systemId: integer("system_id").notNull(),
+	url: text().notNull(),
+	bearerToken: text("bearer_token"),
+	intervalSecs: integer("interval_secs").default(30).notNull(),
+	active: boolean().default(true).notNull(),
+}, (table) => [
+	foreignKey({
+		columns: [table.systemId],
+		foreignColumns: [systems.id],
+		name: "prometheus_targets_system_fk"
+	}).onDelete("cascade"),
+]);
 
 export const services = pgTable("services", {
 	id: serial().notNull(),
@@ -184,6 +296,7 @@ export const services = pgTable("services", {
 		foreignColumns: [systems.id],
 		name: "services_systems_id_fk"
 	}),
+	unique("services_system_name_key").on(table.system, table.name),
 ]);
 
 
@@ -237,10 +350,17 @@ export const systems = pgTable("systems", {
 	cpuUsage: doublePrecision("cpu_usage"),
 	uptime: integer(),
 	// You can use { mode: "bigint" } if numbers are exceeding js number limitations
-	memoryUsed: bigint("memory_used", { mode: "number" }),
+	memoryUsedBytes: bigint("memory_used_bytes", { mode: "number" }),
 	// You can use { mode: "bigint" } if numbers are exceeding js number limitations
-	memoryTotal: bigint("memory_total", { mode: "number" }),
+	memoryTotalBytes: bigint("memory_total_bytes", { mode: "number" }),
 	admin: integer(),
+	certFingerprint: text("cert_fingerprint"),
+	agentVersion: text("agent_version"),
+	arch: text(),
+	virtualization: text(),
+	tags: jsonb().default({}).notNull(),
+	decommissioned: timestamp({ withTimezone: true, mode: 'string' }),
+	decommissionArchive: text("decommission_archive"),
 }, (table) => [
 	foreignKey({
 		columns: [table.admin],
@@ -248,16 +368,20 @@ export const systems = pgTable("systems", {
 		name: "systems_users_id_fk"
 	}).onUpdate("cascade").onDelete("cascade"),
 	unique("systems_hostname_key").on(table.hostname),
+	index("systems_tags_idx").using("gin", table.tags),
 ]);
 
 export const disks = pgTable("disks", {
 	system: integer().notNull(),
 	name: text().notNull(),
-	space: integer(),
-	used: integer(),
+	spaceBytes: bigint("space_bytes", { mode: "number" }),
+	usedBytes: bigint("used_bytes", { mode: "number" }),
 	read: doublePrecision(),
 	write: doublePrecision(),
-	unit: text(),
+	readIops: doublePrecision("read_iops"),
+	writeIops: doublePrecision("write_iops"),
+	inodesTotal: bigint("inodes_total", { mode: "number" }),
+	inodesUsed: bigint("inodes_used", { mode: "number" }),
 	time: timestamp({ withTimezone: true, mode: 'string' }).primaryKey().notNull(),
 	mountPoint: text("mount_point"),
 }, (table) => [
@@ -272,15 +396,30 @@ export const metrics = pgTable("metrics", {
 	time: timestamp({ withTimezone: true, mode: 'string' }).notNull(),
 	systemId: integer("system_id").notNull(),
 	cpuUsage: doublePrecision("cpu_usage"),
+	cpuUser: doublePrecision("cpu_user"),
+	cpuSystem: doublePrecision("cpu_system"),
+	cpuIowait: doublePrecision("cpu_iowait"),
+	cpuIrq: doublePrecision("cpu_irq"),
+	cpuSteal: doublePrecision("cpu_steal"),
 	// You can use { mode: "bigint" } if numbers are exceeding js number limitations
-	memoryUsedKb: bigint("memory_used_kb", { mode: "number" }),
+	memoryUsedBytes: bigint("memory_used_bytes", { mode: "number" }),
 	// You can use { mode: "bigint" } if numbers are exceeding js number limitations
-	memoryTotalKb: bigint("memory_total_kb", { mode: "number" }),
+	memoryTotalBytes: bigint("memory_total_bytes", { mode: "number" }),
+	memoryAvailableBytes: bigint("memory_available_bytes", { mode: "number" }),
+	swapUsedBytes: bigint("swap_used_bytes", { mode: "number" }),
 	dockerContainersRunning: integer("docker_containers_running"),
 	components: text(),
+	processes: integer(),
+	threads: integer(),
+	procsRunning: integer("procs_running"),
+	procsZombie: integer("procs_zombie"),
+	procsBlocked: integer("procs_blocked"),
+	ctxtPerSec: doublePrecision("ctxt_per_sec"),
+	intrPerSec: doublePrecision("intr_per_sec"),
+	entropyAvail: integer("entropy_avail"),
 	uptime: integer(),
-	netIn: integer("net_in"),
-	netOut: integer("net_out"),
+	netInBytesPerSec: doublePrecision("net_in_bytes_per_sec"),
+	netOutBytesPerSec: doublePrecision("net_out_bytes_per_sec"),
 	loadOne: doublePrecision("load_one"),
 	loadFive: doublePrecision("load_five"),
 	loadFifteen: doublePrecision("load_fifteen"),
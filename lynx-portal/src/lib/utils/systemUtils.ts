@@ -1,5 +1,12 @@
 export type SystemStatus = 'online' | 'offline' | 'error' | 'warning';
 
+/** The hub stores sizes in bytes and rates in bytes/s, converted here for display */
+export const BYTES_PER_GB = 1024 * 1024 * 1024;
+
+export function bytesToGB(bytes?: number | string | null): number {
+	return bytes ? Number(bytes) / BYTES_PER_GB : 0;
+}
+
 export interface System {
 	id: number;
 	label: string;
@@ -7,15 +14,15 @@ export interface System {
 	active: boolean | null;
 	lastSeen?: string | null;
 	cpuUsage?: number | null;
-	memoryTotal?: number | null;
-	memoryUsed?: number | null;
+	memoryTotalBytes?: number | null;
+	memoryUsedBytes?: number | null;
 	disks?: Array<{
-		used?: number | null;
-		space?: number | null;
+		usedBytes?: number | null;
+		spaceBytes?: number | null;
 	}>;
 	metrics?: Array<{
-		memoryTotalKb?: number | null;
-		memoryUsedKb?: number | null;
+		memoryTotalBytes?: number | null;
+		memoryUsedBytes?: number | null;
 	}>;
 	alertHistories?: Array<{
 		date: string;
@@ -56,17 +63,17 @@ export function getMetricValue(system: System, metric: 'cpu' | 'memory' | 'disk'
 		case 'cpu':
 			return system.cpuUsage || 0;
 		case 'memory':
-			if (system.memoryTotal && system.memoryUsed) {
-				return (system.memoryUsed / system.memoryTotal) * 100;
+			if (system.memoryTotalBytes && system.memoryUsedBytes) {
+				return (system.memoryUsedBytes / system.memoryTotalBytes) * 100;
 			}
 			// Fallback to metrics data
-			if (system.metrics?.[0]?.memoryTotalKb && system.metrics?.[0]?.memoryUsedKb) {
-				return (system.metrics[0].memoryUsedKb / system.metrics[0].memoryTotalKb) * 100;
+			if (system.metrics?.[0]?.memoryTotalBytes && system.metrics?.[0]?.memoryUsedBytes) {
+				return (system.metrics[0].memoryUsedBytes / system.metrics[0].memoryTotalBytes) * 100;
 			}
 			return 0;
 		case 'disk':
-			if (system.disks?.[0]?.used && system.disks?.[0]?.space) {
-				return (system.disks[0].used / system.disks[0].space) * 100;
+			if (system.disks?.[0]?.usedBytes && system.disks?.[0]?.spaceBytes) {
+				return (system.disks[0].usedBytes / system.disks[0].spaceBytes) * 100;
 			}
 			return 0;
 	}
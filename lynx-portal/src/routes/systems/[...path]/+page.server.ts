@@ -119,13 +119,13 @@ export const load = async ({params, url, depends}) => {
         SELECT t.time_slot,
                MAX(t.original_time)               as latest_original_time,
                AVG(cpu_usage)                     as cpu_total,
-               AVG(memory_used_kb)                as used_total,
-               AVG(net_in)                        as in_total,
-               AVG(net_out)                       as out_total,
+               AVG(memory_used_bytes)             as used_total,
+               AVG(net_in_bytes_per_sec)          as in_total,
+               AVG(net_out_bytes_per_sec)         as out_total,
                AVG(load_one)                      as one_total,
                AVG(load_five)                     as five_total,
                AVG(load_fifteen)                  as fifteen_total,
-               AVG(memory_total_kb)               as memory_total_kb,
+               AVG(memory_total_bytes)            as memory_total_bytes,
                (SELECT jsonb_agg(jsonb_build_object(
                        'label', cs.component_name,
                        'avg_temperature', cs.avg_temp
@@ -180,7 +180,7 @@ export const load = async ({params, url, depends}) => {
         disks = await db.execute(sql`
             SELECT mount_point,
                    to_char(time, 'YYYY-MM-DD HH24:MI:SS') as time_slot,
-                   used                                   as used_total, space as total, read as read_total, write as write_total
+                   used_bytes                             as used_total, space_bytes as total, read as read_total, write as write_total
             FROM disks
             WHERE
                 system = ${path_number}
@@ -202,8 +202,8 @@ export const load = async ({params, url, depends}) => {
                            ),
                            'YYYY-MM-DD HH24:MI:SS'
                    )          as time_slot,
-                   AVG(used)  as used_total,
-                   AVG(space) as total,
+                   AVG(used_bytes)  as used_total,
+                   AVG(space_bytes) as total,
                    AVG(read)  as read_total,
                    AVG(write) as write_total
             FROM disks
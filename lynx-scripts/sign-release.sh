@@ -0,0 +1,36 @@
+#!/bin/bash
+# Signs an agent release manifest with the release key and prints the agent_releases row to
+# insert. Run it where release.key lives, the hub only relays the manifest and its signature.
+#
+#   sign-release.sh release.key stable 0.2.0 linux x86_64 https://downloads.example.org/lynx-agent-0.2.0 ./lynx-agent
+set -euo pipefail
+
+if [ $# -ne 7 ]; then
+    echo "usage: $0 <release.key> <channel> <version> <os> <arch> <url> <binary>" >&2
+    exit 2
+fi
+key=$1 channel=$2 version=$3 os=$4 arch=$5 url=$6 binary=$7
+
+# the values go into JSON and SQL literals unescaped
+for value in "$channel" "$version" "$os" "$arch" "$url"; do
+    case "$value" in
+        *[\"\'\\]* | *[[:space:]]*)
+            echo "Refusing $value: quotes, backslashes and whitespace are not allowed" >&2
+            exit 2
+            ;;
+    esac
+done
+
+sha256=$(sha256sum "$binary" | cut -d' ' -f1)
+published=$(date -u +%FT%TZ)
+manifest=$(printf '{"channel":"%s","version":"%s","os":"%s","arch":"%s","url":"%s","sha256":"%s","published":"%s"}' \
+    "$channel" "$version" "$os" "$arch" "$url" "$sha256" "$published")
+
+tmp=$(mktemp -d)
+trap 'rm -rf "$tmp"' EXIT
+printf '%s' "$manifest" > "$tmp/manifest.json"
+openssl pkeyutl -sign -rawin -inkey "$key" -in "$tmp/manifest.json" -out "$tmp/manifest.sig"
+signature=$(base64 -w0 < "$tmp/manifest.sig")
+
+echo "INSERT INTO agent_releases (channel, version, os, arch, url, sha256, published, manifest, signature)"
+echo "VALUES ('$channel', '$version', '$os', '$arch', '$url', '$sha256', '$published', '$manifest', '$signature');"